@@ -0,0 +1,54 @@
+//! Compare the default parsers with the trusted ones, which skip the limits
+//! and the validation of the numbers.
+//!
+//! Run with `cargo bench --bench parse`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use minecraft_server_query::{BasicStat, BasicStatRef, FullStat, FullStatRef};
+
+fn full_stat(players: usize) -> FullStat {
+    FullStat {
+        hostname: "§aA Minecraft Server §7- §eSurvival".into(),
+        gametype: "SMP".into(),
+        game_id: "MINECRAFT".into(),
+        version: "1.20.1".into(),
+        plugins: "Paper on 1.20.1: WorldEdit 7.2.15; EssentialsX 2.20.0".into(),
+        map: "world".into(),
+        numplayers: players as u32,
+        maxplayers: 100,
+        hostport: 25565,
+        hostip: "127.0.0.1".into(),
+        player_list: (0..players)
+            .map(|i| format!("Player{i}").as_str().into())
+            .collect(),
+    }
+}
+
+fn basic_stat(c: &mut Criterion) {
+    let payload = BasicStat::from(&full_stat(0)).to_payload();
+    let mut group = c.benchmark_group("basic_stat");
+    group.bench_function("checked", |b| {
+        b.iter(|| BasicStatRef::from_payload(black_box(&payload)))
+    });
+    group.bench_function("trusted", |b| {
+        b.iter(|| BasicStatRef::from_payload_trusted(black_box(&payload)))
+    });
+    group.finish();
+}
+
+fn full_stat_players(c: &mut Criterion) {
+    for players in [0, 20, 100] {
+        let payload = full_stat(players).to_payload();
+        let mut group = c.benchmark_group(format!("full_stat/{players}_players"));
+        group.bench_function("checked", |b| {
+            b.iter(|| FullStatRef::from_payload(black_box(&payload)))
+        });
+        group.bench_function("trusted", |b| {
+            b.iter(|| FullStatRef::from_payload_trusted(black_box(&payload)))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, basic_stat, full_stat_players);
+criterion_main!(benches);
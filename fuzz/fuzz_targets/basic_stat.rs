@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::{BasicStat, BasicStatRef};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(stat) = BasicStatRef::from_payload(data) {
+        let stat = stat.into_owned();
+        assert_eq!(BasicStat::from_payload(&stat.to_payload()).unwrap(), stat);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::{BasicStat, ParseOptions};
+
+fuzz_target!(|stat: BasicStat| {
+    assert_eq!(
+        BasicStat::from_payload_with(&stat.to_payload(), &ParseOptions::UNLIMITED).unwrap(),
+        stat
+    );
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::{FullStat, ParseOptions};
+
+fuzz_target!(|stat: FullStat| {
+    assert_eq!(
+        FullStat::from_payload_with(&stat.to_payload(), &ParseOptions::UNLIMITED).unwrap(),
+        stat
+    );
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::{FullStat, FullStatRef};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(stat) = FullStatRef::from_payload(data) {
+        let stat = stat.into_owned();
+        assert_eq!(FullStat::from_payload(&stat.to_payload()).unwrap(), stat);
+    }
+});
@@ -0,0 +1,39 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::packets::{self, Request};
+
+/// Session mask: the higher 4 bits of each byte are ignored by servers
+const SESSION_MASK: u32 = 0x0F0F0F0F;
+
+fuzz_target!(|data: &[u8]| {
+    let Some(request) = Request::parse(data) else {
+        return;
+    };
+    // The padding of full stat requests is not parsed, and written back as zeros
+    let mut packet = Vec::new();
+    let expected = match request {
+        Request::Handshake { session_id } => {
+            packets::write_handshake(&mut packet, session_id);
+            Request::Handshake {
+                session_id: session_id & SESSION_MASK,
+            }
+        }
+        Request::BasicStat { session_id, token } => {
+            packets::write_basic_stat(&mut packet, session_id, token);
+            Request::BasicStat {
+                session_id: session_id & SESSION_MASK,
+                token,
+            }
+        }
+        Request::FullStat { session_id, token } => {
+            packets::write_full_stat(&mut packet, session_id, token);
+            Request::FullStat {
+                session_id: session_id & SESSION_MASK,
+                token,
+            }
+        }
+    };
+    assert_eq!(packet.len(), data.len());
+    assert_eq!(Request::parse(&packet), Some(expected));
+});
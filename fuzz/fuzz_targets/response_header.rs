@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::packets::{write_response, ResponseHeader};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((header, payload)) = ResponseHeader::parse(data) {
+        assert_eq!(
+            write_response(header.packet_type, header.session_id, payload),
+            data
+        );
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use minecraft_server_query::Token;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(token) = Token::try_from_payload(data) {
+        assert_eq!(Token::from_payload(&token.to_payload()), token);
+    }
+    let token = Token::from_payload(data);
+    // Payloads made only of digits are encoded back identically, unless they overflow
+    if !data.is_empty() && data.len() < 10 && data.iter().all(u8::is_ascii_digit) && data[0] != b'0'
+    {
+        assert_eq!(token.to_payload(), [data, b"\0"].concat());
+    }
+});
@@ -0,0 +1,394 @@
+//! Python bindings of the blocking Query client.
+//!
+//! The GIL is released while waiting for the network, so other Python threads
+//! keep running during queries.
+
+use std::{io, net::Ipv4Addr, time::Duration};
+
+use minecraft_server_query::{blocking, ClientError, Token, DEFAULT_PORT, DEFAULT_TIMEOUT};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyKeyError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+create_exception!(
+    minecraft_server_query,
+    QueryError,
+    PyException,
+    "A query failed."
+);
+create_exception!(
+    minecraft_server_query,
+    QueryTimeout,
+    QueryError,
+    "The server did not answer in time."
+);
+create_exception!(
+    minecraft_server_query,
+    ParseError,
+    QueryError,
+    "The server answered with a malformed response."
+);
+
+/// Convert a client error to the matching Python exception.
+fn query_error(e: io::Error) -> PyErr {
+    let operation = e
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<ClientError>())
+        .map(ClientError::operation);
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => QueryTimeout::new_err(e.to_string()),
+        // Parsers fail with these kinds, which network errors do not use
+        io::ErrorKind::Other | io::ErrorKind::InvalidData
+            if matches!(operation, Some("handshake" | "basic_stat" | "full_stat")) =>
+        {
+            ParseError::new_err(e.to_string())
+        }
+        _ => QueryError::new_err(e.to_string()),
+    }
+}
+
+/// Convert a timeout in seconds.
+fn timeout(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| PyValueError::new_err("timeout must be a positive number of seconds"))
+}
+
+/// Value of a field of a status, for the dict-like accessors.
+fn get_item<'py>(dict: Bound<'py, PyDict>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+    dict.get_item(key)?
+        .ok_or_else(|| PyKeyError::new_err(key.to_string()))
+}
+
+/// Basic status of a server. Fields are available as attributes, or with
+/// `stat["field"]` like a dict.
+#[pyclass(
+    frozen,
+    eq,
+    get_all,
+    skip_from_py_object,
+    module = "minecraft_server_query"
+)]
+#[derive(Debug, Clone, PartialEq)]
+struct BasicStat {
+    motd: String,
+    gametype: String,
+    map: String,
+    numplayers: u32,
+    maxplayers: u32,
+    hostport: u16,
+    hostip: String,
+}
+
+impl From<minecraft_server_query::BasicStat> for BasicStat {
+    fn from(stat: minecraft_server_query::BasicStat) -> Self {
+        Self {
+            motd: stat.motd.to_string(),
+            gametype: stat.gametype.to_string(),
+            map: stat.map.to_string(),
+            numplayers: stat.numplayers,
+            maxplayers: stat.maxplayers,
+            hostport: stat.hostport,
+            hostip: stat.hostip.to_string(),
+        }
+    }
+}
+
+#[pymethods]
+impl BasicStat {
+    const KEYS: [&'static str; 7] = [
+        "motd",
+        "gametype",
+        "map",
+        "numplayers",
+        "maxplayers",
+        "hostport",
+        "hostip",
+    ];
+
+    /// The fields as a dict.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("motd", &self.motd)?;
+        dict.set_item("gametype", &self.gametype)?;
+        dict.set_item("map", &self.map)?;
+        dict.set_item("numplayers", self.numplayers)?;
+        dict.set_item("maxplayers", self.maxplayers)?;
+        dict.set_item("hostport", self.hostport)?;
+        dict.set_item("hostip", &self.hostip)?;
+        Ok(dict)
+    }
+
+    /// Names of the fields.
+    fn keys(&self) -> Vec<&'static str> {
+        Self::KEYS.to_vec()
+    }
+
+    fn __getitem__<'py>(&self, py: Python<'py>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+        get_item(self.to_dict(py)?, key)
+    }
+
+    fn __len__(&self) -> usize {
+        Self::KEYS.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "BasicStat(motd={:?}, numplayers={}, maxplayers={})",
+            self.motd, self.numplayers, self.maxplayers
+        )
+    }
+}
+
+/// Full status of a server. Fields are available as attributes, or with
+/// `stat["field"]` like a dict.
+#[pyclass(
+    frozen,
+    eq,
+    get_all,
+    skip_from_py_object,
+    module = "minecraft_server_query"
+)]
+#[derive(Debug, Clone, PartialEq)]
+struct FullStat {
+    hostname: String,
+    gametype: String,
+    game_id: String,
+    version: String,
+    plugins: String,
+    map: String,
+    numplayers: u32,
+    maxplayers: u32,
+    hostport: u16,
+    hostip: String,
+    player_list: Vec<String>,
+}
+
+impl From<minecraft_server_query::FullStat> for FullStat {
+    fn from(stat: minecraft_server_query::FullStat) -> Self {
+        Self {
+            hostname: stat.hostname.to_string(),
+            gametype: stat.gametype.to_string(),
+            game_id: stat.game_id.to_string(),
+            version: stat.version.to_string(),
+            plugins: stat.plugins.to_string(),
+            map: stat.map.to_string(),
+            numplayers: stat.numplayers,
+            maxplayers: stat.maxplayers,
+            hostport: stat.hostport,
+            hostip: stat.hostip.to_string(),
+            player_list: stat.player_list.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+}
+
+#[pymethods]
+impl FullStat {
+    const KEYS: [&'static str; 11] = [
+        "hostname",
+        "gametype",
+        "game_id",
+        "version",
+        "plugins",
+        "map",
+        "numplayers",
+        "maxplayers",
+        "hostport",
+        "hostip",
+        "player_list",
+    ];
+
+    /// The fields as a dict.
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("hostname", &self.hostname)?;
+        dict.set_item("gametype", &self.gametype)?;
+        dict.set_item("game_id", &self.game_id)?;
+        dict.set_item("version", &self.version)?;
+        dict.set_item("plugins", &self.plugins)?;
+        dict.set_item("map", &self.map)?;
+        dict.set_item("numplayers", self.numplayers)?;
+        dict.set_item("maxplayers", self.maxplayers)?;
+        dict.set_item("hostport", self.hostport)?;
+        dict.set_item("hostip", &self.hostip)?;
+        dict.set_item("player_list", &self.player_list)?;
+        Ok(dict)
+    }
+
+    /// Names of the fields.
+    fn keys(&self) -> Vec<&'static str> {
+        Self::KEYS.to_vec()
+    }
+
+    fn __getitem__<'py>(&self, py: Python<'py>, key: &str) -> PyResult<Bound<'py, PyAny>> {
+        get_item(self.to_dict(py)?, key)
+    }
+
+    fn __len__(&self) -> usize {
+        Self::KEYS.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "FullStat(hostname={:?}, version={:?}, numplayers={}, maxplayers={})",
+            self.hostname, self.version, self.numplayers, self.maxplayers
+        )
+    }
+}
+
+/// Get the full status of a server.
+#[pyfunction]
+#[pyo3(signature = (host, port = DEFAULT_PORT, timeout = DEFAULT_TIMEOUT.as_secs_f64()))]
+fn query(py: Python<'_>, host: &str, port: u16, timeout: f64) -> PyResult<FullStat> {
+    let query = blocking::Query::to(host)
+        .port(port)
+        .timeout(self::timeout(timeout)?);
+    py.detach(|| query.full())
+        .map(FullStat::from)
+        .map_err(query_error)
+}
+
+/// Get the basic status of a server.
+#[pyfunction]
+#[pyo3(signature = (host, port = DEFAULT_PORT, timeout = DEFAULT_TIMEOUT.as_secs_f64()))]
+fn basic_query(py: Python<'_>, host: &str, port: u16, timeout: f64) -> PyResult<BasicStat> {
+    let query = blocking::Query::to(host)
+        .port(port)
+        .timeout(self::timeout(timeout)?);
+    py.detach(|| query.basic())
+        .map(BasicStat::from)
+        .map_err(query_error)
+}
+
+/// A Query client, reusing its socket and tokens across requests.
+#[pyclass(frozen, module = "minecraft_server_query")]
+struct QueryClient(blocking::QueryClient);
+
+#[pymethods]
+impl QueryClient {
+    #[new]
+    #[pyo3(signature = (host, port = DEFAULT_PORT, timeout = DEFAULT_TIMEOUT.as_secs_f64()))]
+    fn new(py: Python<'_>, host: &str, port: u16, timeout: f64) -> PyResult<Self> {
+        let timeout = self::timeout(timeout)?;
+        py.detach(|| {
+            blocking::QueryClient::new_with_socket_address(
+                host,
+                port,
+                (Ipv4Addr::UNSPECIFIED, 0),
+                Some(timeout),
+            )
+        })
+        .map(Self)
+        .map_err(query_error)
+    }
+
+    /// Get a token, valid for 30 seconds.
+    fn handshake(&self, py: Python<'_>) -> PyResult<u32> {
+        py.detach(|| self.0.handshake())
+            .map(|token| token.0)
+            .map_err(query_error)
+    }
+
+    /// Get the basic status of the server with a token.
+    fn basic_stat(&self, py: Python<'_>, token: u32) -> PyResult<BasicStat> {
+        py.detach(|| self.0.basic_stat(Token(token)))
+            .map(BasicStat::from)
+            .map_err(query_error)
+    }
+
+    /// Get the full status of the server with a token.
+    fn full_stat(&self, py: Python<'_>, token: u32) -> PyResult<FullStat> {
+        py.detach(|| self.0.full_stat(Token(token)))
+            .map(FullStat::from)
+            .map_err(query_error)
+    }
+
+    /// Get the full status of the server, with a new token.
+    fn query(&self, py: Python<'_>) -> PyResult<FullStat> {
+        py.detach(|| self.0.handshake().and_then(|token| self.0.full_stat(token)))
+            .map(FullStat::from)
+            .map_err(query_error)
+    }
+}
+
+/// A Query server running on a background thread, bound to a loopback
+/// address, to test code using the bindings.
+#[cfg(feature = "testing")]
+#[pyclass(frozen, module = "minecraft_server_query")]
+struct MockQueryServer(minecraft_server_query::testing::MockQueryServer);
+
+#[cfg(feature = "testing")]
+#[pymethods]
+impl MockQueryServer {
+    /// Start a server with a sample status, with the given MOTD and players.
+    #[new]
+    #[pyo3(signature = (hostname = None, player_list = None))]
+    fn new(hostname: Option<String>, player_list: Option<Vec<String>>) -> PyResult<Self> {
+        let sample = minecraft_server_query::testing::sample_stat();
+        // `StatString` is only another type than `String` with the `compact_str` feature
+        #[allow(clippy::useless_conversion)]
+        let stat = minecraft_server_query::FullStat {
+            hostname: hostname.map_or(sample.hostname.clone(), Into::into),
+            numplayers: player_list
+                .as_ref()
+                .map_or(sample.numplayers, |players| players.len() as u32),
+            player_list: player_list.map_or(sample.player_list.clone(), |players| {
+                players.into_iter().map(Into::into).collect()
+            }),
+            ..sample
+        };
+        minecraft_server_query::testing::MockQueryServer::with_stat(stat)
+            .map(Self)
+            .map_err(query_error)
+    }
+
+    #[getter]
+    fn host(&self) -> String {
+        self.0.addr().ip().to_string()
+    }
+
+    #[getter]
+    fn port(&self) -> u16 {
+        self.0.addr().port()
+    }
+
+    /// Drop the next requests of every kind without answering them.
+    fn drop_next(&self, count: usize) {
+        use minecraft_server_query::{packets::PacketType, testing::Faults};
+        for kind in [PacketType::Handshake, PacketType::Stat] {
+            let faults = Faults {
+                drop_next: count,
+                ..Default::default()
+            };
+            self.0.set_faults(kind, faults);
+        }
+    }
+
+    /// Corrupt the payload of the status responses.
+    fn corrupt_stats(&self) {
+        use minecraft_server_query::{packets::PacketType, testing::Faults};
+        let faults = Faults {
+            corrupt: true,
+            ..Default::default()
+        };
+        self.0.set_faults(PacketType::Stat, faults);
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "minecraft_server_query")]
+fn init(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(query, m)?)?;
+    m.add_function(wrap_pyfunction!(basic_query, m)?)?;
+    m.add_class::<QueryClient>()?;
+    m.add_class::<BasicStat>()?;
+    m.add_class::<FullStat>()?;
+    m.add("QueryError", m.py().get_type::<QueryError>())?;
+    m.add("QueryTimeout", m.py().get_type::<QueryTimeout>())?;
+    m.add("ParseError", m.py().get_type::<ParseError>())?;
+    #[cfg(feature = "testing")]
+    m.add_class::<MockQueryServer>()?;
+    Ok(())
+}
@@ -0,0 +1,108 @@
+"""Tests of the Python bindings against the mock Query server.
+
+Build the module with the mock server first:
+
+    maturin develop --features testing
+"""
+
+import threading
+import time
+
+import pytest
+
+import minecraft_server_query as mcq
+
+
+@pytest.fixture
+def server():
+    return mcq.MockQueryServer()
+
+
+def test_query(server):
+    stat = mcq.query(server.host, server.port)
+    assert stat.hostname == "A Minecraft Server"
+    assert stat.version == "1.20.1"
+    assert stat.numplayers == 2
+    assert stat.maxplayers == 20
+    assert stat.player_list == ["AldanTanneo", "Dinnerbone"]
+
+
+def test_basic_query(server):
+    stat = mcq.basic_query(server.host, port=server.port, timeout=1.0)
+    assert stat.motd == "A Minecraft Server"
+    assert stat.gametype == "SMP"
+    assert stat.map == "world"
+    assert stat.numplayers == 2
+
+
+def test_stats_are_dict_like(server):
+    stat = mcq.query(server.host, server.port)
+    assert stat["hostname"] == stat.hostname
+    assert len(stat) == len(stat.keys())
+    assert stat.to_dict() == {key: stat[key] for key in stat.keys()}
+    with pytest.raises(KeyError):
+        stat["motd"]
+
+    basic = mcq.basic_query(server.host, server.port)
+    assert basic.to_dict()["motd"] == "A Minecraft Server"
+
+
+def test_strings_are_decoded():
+    server = mcq.MockQueryServer(hostname="§aSalut à tous", player_list=["Jörg"])
+    stat = mcq.query(server.host, server.port)
+    assert stat.hostname == "§aSalut à tous"
+    assert stat.player_list == ["Jörg"]
+    assert stat.numplayers == 1
+
+
+def test_client(server):
+    client = mcq.QueryClient(server.host, server.port)
+    token = client.handshake()
+    assert client.basic_stat(token).motd == "A Minecraft Server"
+    assert client.full_stat(token).player_list == ["AldanTanneo", "Dinnerbone"]
+    assert client.query().hostname == "A Minecraft Server"
+
+
+def test_timeout(server):
+    server.drop_next(1)
+    with pytest.raises(mcq.QueryTimeout):
+        mcq.query(server.host, server.port, timeout=0.1)
+
+
+def test_parse_error(server):
+    server.corrupt_stats()
+    with pytest.raises(mcq.ParseError):
+        mcq.query(server.host, server.port)
+
+
+def test_exception_hierarchy():
+    assert issubclass(mcq.QueryTimeout, mcq.QueryError)
+    assert issubclass(mcq.ParseError, mcq.QueryError)
+    assert not issubclass(mcq.QueryTimeout, mcq.ParseError)
+
+
+def test_invalid_timeout(server):
+    with pytest.raises(ValueError):
+        mcq.query(server.host, server.port, timeout=-1.0)
+
+
+def test_gil_is_released(server):
+    server.drop_next(1)
+    ticks = 0
+    done = threading.Event()
+
+    def count():
+        nonlocal ticks
+        while not done.is_set():
+            ticks += 1
+            time.sleep(0.01)
+
+    counter = threading.Thread(target=count)
+    counter.start()
+    try:
+        with pytest.raises(mcq.QueryTimeout):
+            mcq.query(server.host, server.port, timeout=0.5)
+    finally:
+        done.set()
+        counter.join()
+    assert ticks > 10
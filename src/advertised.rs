@@ -0,0 +1,410 @@
+//! Addresses advertised by servers in their full status.
+//!
+//! The `hostip` and `hostport` fields of a [`FullStat`] are the address the
+//! server believes it is reachable at. Behind proxies and NAT, or with
+//! misconfigured or deliberately misleading servers, it can differ from the
+//! address the status was actually received from:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{advertised::{verify_advertised_address, AddressCheck}, blocking::QueryClient};
+//! let client = QueryClient::new("play.example.com")?;
+//! let full_stat = client.full_stat(client.handshake()?)?;
+//! match verify_advertised_address(&full_stat, client.peer_addr()?) {
+//!     AddressCheck::Match => {}
+//!     check => println!("advertised {}:{}: {check:?}", full_stat.hostip, full_stat.hostport),
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Addresses are told apart by their routability: an address is
+//! [routable](is_routable) if it can be reached from the public Internet.
+//!
+//! The clients can also follow the advertisement, and connect to the
+//! advertised address with `QueryClient::from_stat`.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::{BasicStat, FullStat, DEFAULT_TIMEOUT};
+
+/// A status advertising the address of its server, a [`FullStat`] or a
+/// [`BasicStat`]
+pub trait Advertised {
+    /// The advertised IP address, as sent by the server
+    fn hostip(&self) -> &str;
+    /// The advertised port
+    fn hostport(&self) -> u16;
+}
+
+impl Advertised for FullStat {
+    fn hostip(&self) -> &str {
+        &self.hostip
+    }
+
+    fn hostport(&self) -> u16 {
+        self.hostport
+    }
+}
+
+impl Advertised for BasicStat {
+    fn hostip(&self) -> &str {
+        &self.hostip
+    }
+
+    fn hostport(&self) -> u16 {
+        self.hostport
+    }
+}
+
+impl FullStat {
+    /// The address advertised by the server, or `None` if its IP address
+    /// can't be parsed or is unspecified.
+    ///
+    /// See the [`advertised`](crate::advertised) module to compare it with
+    /// the address the server was queried at.
+    pub fn advertised_addr(&self) -> Option<SocketAddr> {
+        advertised_addr(self)
+    }
+}
+
+impl BasicStat {
+    /// The address advertised by the server, or `None` if its IP address
+    /// can't be parsed or is unspecified.
+    pub fn advertised_addr(&self) -> Option<SocketAddr> {
+        advertised_addr(self)
+    }
+}
+
+fn advertised_addr(stat: &impl Advertised) -> Option<SocketAddr> {
+    let ip = stat.hostip().trim().parse::<IpAddr>().ok()?;
+    (!ip.is_unspecified()).then(|| SocketAddr::new(ip, stat.hostport()))
+}
+
+/// Options of the clients connected to an advertised address, with
+/// `QueryClient::from_stat`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FollowOptions {
+    /// Address the status was received from. When set, the client connects
+    /// to the [effective address](effective_address) instead, falling back
+    /// to this address when the advertised one can't be used
+    pub peer: Option<SocketAddr>,
+    /// Timeout of the client
+    pub timeout: Option<Duration>,
+}
+
+impl Default for FollowOptions {
+    /// No fallback, with the [default timeout](DEFAULT_TIMEOUT).
+    fn default() -> Self {
+        Self {
+            peer: None,
+            timeout: Some(DEFAULT_TIMEOUT),
+        }
+    }
+}
+
+/// The address a client following an advertisement connects to.
+///
+/// Fails with an [`InvalidData`](io::ErrorKind::InvalidData) error if the
+/// advertised IP address can't be parsed or is unspecified, and no fallback
+/// is given.
+pub(crate) fn follow_target(
+    stat: &impl Advertised,
+    options: &FollowOptions,
+) -> io::Result<SocketAddr> {
+    match options.peer {
+        Some(peer) => Ok(effective_address(stat, peer)),
+        None => advertised_addr(stat).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The server advertises no usable address: `{}`.",
+                    stat.hostip()
+                ),
+            )
+        }),
+    }
+}
+
+/// How the address advertised by a server compares to the address it was
+/// queried at
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AddressCheck {
+    /// The server advertises the address it was queried at, or the
+    /// unspecified address with the queried port, as servers listening on
+    /// every interface do.
+    Match,
+    /// The server advertises the queried IP address, with another port, as
+    /// behind port forwarding.
+    PortMismatch,
+    /// The server advertises a non-routable address, while it was queried at
+    /// a routable one, as behind NAT or a proxy.
+    PrivateAdvertised,
+    /// The server advertises another address.
+    Mismatch,
+    /// The advertised IP address can't be parsed, for example if it is empty
+    /// or a host name.
+    Unparseable,
+}
+
+/// Compare the address advertised by a server in its full status with the
+/// address it was queried at.
+///
+/// ```rust
+/// # use minecraft_server_query::{advertised::{verify_advertised_address, AddressCheck}, FullStat};
+/// # fn stat(hostip: &str, hostport: u16) -> FullStat {
+/// #     FullStat {
+/// #         hostname: "".into(), gametype: "".into(), game_id: "".into(), version: "".into(),
+/// #         plugins: "".into(), map: "".into(), numplayers: 0, maxplayers: 20,
+/// #         hostport, hostip: hostip.into(), player_list: vec![],
+/// #     }
+/// # }
+/// let stat = stat("127.0.0.1", 25565);
+/// assert_eq!(verify_advertised_address(&stat, "127.0.0.1:25565".parse().unwrap()), AddressCheck::Match);
+/// assert_eq!(verify_advertised_address(&stat, "127.0.0.1:25566".parse().unwrap()), AddressCheck::PortMismatch);
+/// assert_eq!(verify_advertised_address(&stat, "198.51.99.7:25565".parse().unwrap()), AddressCheck::PrivateAdvertised);
+/// ```
+pub fn verify_advertised_address(stat: &impl Advertised, peer: SocketAddr) -> AddressCheck {
+    let Ok(advertised) = stat.hostip().trim().parse::<IpAddr>() else {
+        return AddressCheck::Unparseable;
+    };
+    let advertised = advertised.to_canonical();
+    let peer_ip = peer.ip().to_canonical();
+
+    if advertised == peer_ip || advertised.is_unspecified() {
+        if stat.hostport() == peer.port() {
+            AddressCheck::Match
+        } else {
+            AddressCheck::PortMismatch
+        }
+    } else if !is_routable(advertised) && is_routable(peer_ip) {
+        AddressCheck::PrivateAdvertised
+    } else {
+        AddressCheck::Mismatch
+    }
+}
+
+/// The address to reach a server at, from the address it advertises and the
+/// address it was queried at.
+///
+/// The advertised address is used, unless it can't be parsed, is unspecified,
+/// or is not routable while the queried address is: the IP address of the
+/// queried address is then used, with the advertised port.
+///
+/// ```rust
+/// # use minecraft_server_query::{advertised::effective_address, FullStat};
+/// # fn stat(hostip: &str, hostport: u16) -> FullStat {
+/// #     FullStat {
+/// #         hostname: "".into(), gametype: "".into(), game_id: "".into(), version: "".into(),
+/// #         plugins: "".into(), map: "".into(), numplayers: 0, maxplayers: 20,
+/// #         hostport, hostip: hostip.into(), player_list: vec![],
+/// #     }
+/// # }
+/// let peer = "198.51.99.7:25565".parse().unwrap();
+/// let advertised = effective_address(&stat("198.51.99.4", 25565), peer);
+/// assert_eq!(advertised.to_string(), "198.51.99.4:25565");
+/// let behind_nat = effective_address(&stat("10.0.0.2", 25565), peer);
+/// assert_eq!(behind_nat.to_string(), "198.51.99.7:25565");
+/// ```
+pub fn effective_address(stat: &impl Advertised, peer: SocketAddr) -> SocketAddr {
+    let ip = match stat.hostip().trim().parse::<IpAddr>() {
+        Ok(ip) if !ip.is_unspecified() && (is_routable(ip) || !is_routable(peer.ip())) => ip,
+        _ => peer.ip(),
+    };
+    SocketAddr::new(ip, stat.hostport())
+}
+
+/// Whether an IP address can be reached from the public Internet.
+///
+/// Unspecified, loopback, private, shared (carrier-grade NAT), link-local,
+/// documentation, benchmarking, reserved, broadcast and multicast addresses
+/// are not routable, nor unique local IPv6 addresses.
+///
+/// ```rust
+/// # use minecraft_server_query::advertised::is_routable;
+/// assert!(is_routable([198, 51, 99, 1].into()));
+/// assert!(!is_routable([192, 168, 1, 20].into()));
+/// assert!(!is_routable("fe80::1".parse().unwrap()));
+/// ```
+pub fn is_routable(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => is_routable_v4(ip),
+        IpAddr::V6(ip) => is_routable_v6(ip),
+    }
+}
+
+fn is_routable_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network", 0.0.0.0/8
+        || a == 0
+        // Shared address space, 100.64.0.0/10
+        || (a == 100 && b & 0xc0 == 64)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && b & 0xfe == 18)
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_routable_v6(ip: Ipv6Addr) -> bool {
+    let [a, b, ..] = ip.segments();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || a & 0xfe00 == 0xfc00
+        // Link-local, fe80::/10
+        || a & 0xffc0 == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (a == 0x2001 && b == 0xdb8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sample_stat, MockQueryServer};
+
+    fn check(hostip: &str, hostport: u16, peer: &str) -> AddressCheck {
+        let mut stat = sample_stat();
+        stat.hostip = hostip.into();
+        stat.hostport = hostport;
+        verify_advertised_address(&stat, peer.parse().unwrap())
+    }
+
+    #[test]
+    fn test_classification() {
+        use AddressCheck::*;
+
+        let cases = [
+            ("198.51.99.1", 25565, "198.51.99.1:25565", Match),
+            ("0.0.0.0", 25565, "198.51.99.1:25565", Match),
+            ("::", 25565, "[2a01:4f8::1]:25565", Match),
+            (" 198.51.99.1 ", 25565, "198.51.99.1:25565", Match),
+            ("198.51.99.1", 25565, "[::ffff:198.51.99.1]:25565", Match),
+            ("198.51.99.1", 25566, "198.51.99.1:25565", PortMismatch),
+            ("0.0.0.0", 25566, "198.51.99.1:25565", PortMismatch),
+            ("10.0.0.2", 25565, "198.51.99.1:25565", PrivateAdvertised),
+            (
+                "192.168.1.20",
+                25566,
+                "198.51.99.1:25565",
+                PrivateAdvertised,
+            ),
+            ("100.64.3.4", 25565, "198.51.99.1:25565", PrivateAdvertised),
+            ("fd00::2", 25565, "[2a01:4f8::1]:25565", PrivateAdvertised),
+            ("127.0.0.1", 25565, "198.51.99.1:25565", PrivateAdvertised),
+            ("198.51.99.2", 25565, "198.51.99.1:25565", Mismatch),
+            ("10.0.0.2", 25565, "192.168.1.20:25565", Mismatch),
+            ("198.51.99.1", 25565, "10.0.0.2:25565", Mismatch),
+            ("2a01:4f8::2", 25565, "[2a01:4f8::1]:25565", Mismatch),
+            ("", 25565, "198.51.99.1:25565", Unparseable),
+            ("play.example.com", 25565, "198.51.99.1:25565", Unparseable),
+            ("198.51.99.1:25565", 25565, "198.51.99.1:25565", Unparseable),
+        ];
+        for (hostip, hostport, peer, expected) in cases {
+            assert_eq!(
+                check(hostip, hostport, peer),
+                expected,
+                "{hostip}:{hostport} from {peer}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_advertised_addr() {
+        let mut stat = sample_stat();
+        assert_eq!(
+            stat.advertised_addr(),
+            Some("127.0.0.1:25565".parse().unwrap())
+        );
+        stat.hostip = "2a01:4f8::1".into();
+        stat.hostport = 19132;
+        assert_eq!(
+            stat.advertised_addr(),
+            Some("[2a01:4f8::1]:19132".parse().unwrap())
+        );
+        let basic = BasicStat::from(&stat);
+        assert_eq!(basic.advertised_addr(), stat.advertised_addr());
+        for hostip in ["", "::", "0.0.0.0", "localhost", "127.0.0.1:25565"] {
+            stat.hostip = hostip.into();
+            assert_eq!(stat.advertised_addr(), None, "{hostip}");
+        }
+    }
+
+    #[test]
+    fn test_is_routable() {
+        for ip in ["198.51.99.1", "8.8.8.8", "2a01:4f8::1", "::ffff:8.8.8.8"] {
+            assert!(is_routable(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "0.0.0.0",
+            "0.1.2.3",
+            "10.1.2.3",
+            "100.100.0.1",
+            "127.0.0.1",
+            "169.254.0.1",
+            "172.16.0.1",
+            "192.0.2.1",
+            "192.168.0.1",
+            "198.19.0.1",
+            "224.0.0.1",
+            "250.0.0.1",
+            "255.255.255.255",
+            "::",
+            "::1",
+            "::ffff:10.0.0.1",
+            "fc00::1",
+            "fe80::1",
+            "ff02::1",
+            "2001:db8::1",
+        ] {
+            assert!(!is_routable(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[test]
+    fn test_effective_address() {
+        let effective = |hostip: &str, peer: &str| {
+            let mut stat = sample_stat();
+            stat.hostip = hostip.into();
+            stat.hostport = 25566;
+            effective_address(&stat, peer.parse().unwrap()).to_string()
+        };
+        assert_eq!(effective("8.8.8.8", "198.51.99.1:1"), "8.8.8.8:25566");
+        assert_eq!(effective("10.0.0.2", "198.51.99.1:1"), "198.51.99.1:25566");
+        assert_eq!(effective("10.0.0.2", "192.168.1.20:1"), "10.0.0.2:25566");
+        assert_eq!(effective("0.0.0.0", "192.168.1.20:1"), "192.168.1.20:25566");
+        assert_eq!(effective("invalid", "198.51.99.1:1"), "198.51.99.1:25566");
+    }
+
+    #[test]
+    fn test_mock_server() {
+        let server = MockQueryServer::new().unwrap();
+        let client = crate::blocking::QueryClient::new_addr(server.addr()).unwrap();
+        assert_eq!(client.peer_addr().unwrap(), server.addr());
+
+        // The sample status advertises the default port
+        let stat = client.full_stat(client.handshake().unwrap()).unwrap();
+        assert_eq!(
+            verify_advertised_address(&stat, client.peer_addr().unwrap()),
+            AddressCheck::PortMismatch
+        );
+
+        let mut advertised = server.full_stat();
+        advertised.hostport = server.addr().port();
+        server.set_full_stat(advertised);
+        let stat = client.full_stat(client.handshake().unwrap()).unwrap();
+        assert_eq!(
+            verify_advertised_address(&stat, client.peer_addr().unwrap()),
+            AddressCheck::Match
+        );
+    }
+}
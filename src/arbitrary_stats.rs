@@ -0,0 +1,128 @@
+//! [`Arbitrary`] implementations for the stat types, behind the `arbitrary` feature.
+//!
+//! Generated stats can be encoded and parsed back: strings are Latin-1 without
+//! null bytes, and player names are not empty. Player counts are realistic:
+//! `numplayers` is at most `maxplayers`, except for a few overfull servers,
+//! where operators joined past the limit.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{BasicStat, FullStat, StatString};
+
+/// Max number of players above the limit of an overfull server
+const MAX_OVERFULL: u32 = 8;
+
+/// A Latin-1 string without null bytes, as sent by Query servers.
+fn stat_string(u: &mut Unstructured<'_>) -> Result<StatString> {
+    let bytes = <&[u8]>::arbitrary(u)?;
+    Ok(bytes
+        .iter()
+        .filter(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect())
+}
+
+/// Player counts, with at most [`MAX_OVERFULL`] players above the limit.
+fn player_counts(u: &mut Unstructured<'_>) -> Result<(u32, u32)> {
+    let maxplayers = u.int_in_range(0..=100_000)?;
+    let limit = if u.ratio(1, 16)? {
+        maxplayers + MAX_OVERFULL
+    } else {
+        maxplayers
+    };
+    Ok((u.int_in_range(0..=limit)?, maxplayers))
+}
+
+impl<'a> Arbitrary<'a> for BasicStat {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let (numplayers, maxplayers) = player_counts(u)?;
+        Ok(Self {
+            motd: stat_string(u)?,
+            gametype: stat_string(u)?,
+            map: stat_string(u)?,
+            numplayers,
+            maxplayers,
+            hostport: u16::arbitrary(u)?,
+            hostip: stat_string(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for FullStat {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let (numplayers, maxplayers) = player_counts(u)?;
+        let mut player_list = Vec::new();
+        u.arbitrary_loop(None, Some(numplayers.min(100)), |u| {
+            let player = stat_string(u)?;
+            // Empty player names are skipped by the encoder
+            if !player.is_empty() {
+                player_list.push(player);
+            }
+            Ok(std::ops::ControlFlow::Continue(()))
+        })?;
+
+        Ok(Self {
+            hostname: stat_string(u)?,
+            gametype: stat_string(u)?,
+            game_id: stat_string(u)?,
+            version: stat_string(u)?,
+            plugins: stat_string(u)?,
+            map: stat_string(u)?,
+            numplayers,
+            maxplayers,
+            hostport: u16::arbitrary(u)?,
+            hostip: stat_string(u)?,
+            player_list,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use super::MAX_OVERFULL;
+    use crate::{motd, BasicStat, FullStat, ParseOptions, Token};
+
+    /// Pseudo-random bytes, from a xorshift generator.
+    fn random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trips() {
+        for seed in 0..300 {
+            let data = random_bytes(seed, 4096);
+            let mut u = Unstructured::new(&data);
+
+            let token = Token::arbitrary(&mut u).unwrap();
+            assert_eq!(Token::from_payload(&token.to_payload()), token);
+
+            let basic = BasicStat::arbitrary(&mut u).unwrap();
+            assert!(basic.numplayers <= basic.maxplayers + MAX_OVERFULL);
+            assert_eq!(
+                BasicStat::from_payload_with(&basic.to_payload(), &ParseOptions::UNLIMITED)
+                    .unwrap(),
+                basic
+            );
+
+            let full = FullStat::arbitrary(&mut u).unwrap();
+            assert!(full.numplayers <= full.maxplayers + MAX_OVERFULL);
+            assert_eq!(
+                FullStat::from_payload_with(&full.to_payload(), &ParseOptions::UNLIMITED).unwrap(),
+                full
+            );
+
+            let spans = Vec::<motd::Span>::arbitrary(&mut u).unwrap();
+            motd::to_legacy(&spans);
+        }
+    }
+}
@@ -6,9 +6,19 @@ use ::async_std::{
     future::timeout,
     net::{ToSocketAddrs, UdpSocket},
 };
-use std::{io, net::Ipv4Addr, time::Duration};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use super::*;
+use crate::advertised::{self, Advertised, FollowOptions};
+use crate::connection_string::{ConnectionString, ConnectionStringError};
+use crate::gs4::BedrockFullStat;
+use crate::packets::QueryPacket;
+use crate::rate_limit::{self, RateLimiter};
+use crate::resolve::{self, Resolve, SystemResolver};
 
 /// An asynchronous Query client using the [`async-std`](https://docs.rs/async-std/*/async_std) networking primitives.
 #[derive(Debug)]
@@ -16,6 +26,9 @@ pub struct QueryClient {
     socket: UdpSocket,
     session_id: u32,
     timeout: Option<Duration>,
+    target: String,
+    #[cfg(feature = "histogram")]
+    latency: crate::histogram::ClientLatency,
 }
 
 impl QueryClient {
@@ -25,16 +38,7 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub async fn new(ip: &str) -> io::Result<Self> {
-        let (ip, port) = if let Some((ip, port)) = ip.split_once(':') {
-            (
-                ip,
-                port.parse::<u16>().map_err(|_| {
-                    io::Error::new(io::ErrorKind::Other, "Invalid port in IP address")
-                })?,
-            )
-        } else {
-            (ip, DEFAULT_PORT)
-        };
+        let (ip, port) = split_address(ip)?;
 
         Self::new_with_port(ip, port).await
     }
@@ -45,10 +49,41 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub async fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
-        Self::new_with_socket_address(ip, port, (Ipv4Addr::UNSPECIFIED, 0), Some(DEFAULT_TIMEOUT))
+        Self::new_with_socket_address(ip, port, unspecified_for_host(ip), Some(DEFAULT_TIMEOUT))
             .await
     }
 
+    /// Build a new QueryClient from the given socket address, for example from
+    /// [`query_target_from_properties`](crate::server_properties::query_target_from_properties).
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn new_addr(addr: SocketAddr) -> io::Result<Self> {
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            Some(DEFAULT_TIMEOUT),
+        )
+        .await
+    }
+
+    /// Build a new QueryClient connected to the address advertised by a
+    /// server in its status, see [`FollowOptions`].
+    ///
+    /// Fails with an [`InvalidData`](io::ErrorKind::InvalidData) error if the
+    /// advertised IP address can't be parsed or is unspecified, unless the
+    /// options fall back to the address the status was received from.
+    pub async fn from_stat(stat: &impl Advertised, options: FollowOptions) -> io::Result<Self> {
+        let addr = advertised::follow_target(stat, &options)?;
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            options.timeout,
+        )
+        .await
+    }
+
     /// Builds a new QueryClient from the given IP address, port, socket address and optional timeout.
     ///
     /// The IP adress must not contain a port.
@@ -58,15 +93,32 @@ impl QueryClient {
         addr: impl ToSocketAddrs,
         timeout: Option<Duration>,
     ) -> io::Result<Self> {
-        if ip.contains(':') {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Invalid IP address: must not contain a port.",
-            ));
+        check_no_port(ip)?;
+
+        let target = format_target(ip, port);
+        match zone::scoped_socket_addr(ip, port) {
+            Ok(Some(server)) => Self::connect(target, addr, server, timeout).await,
+            Ok(None) => Self::connect(target, addr, (ip, port), timeout).await,
+            Err(e) => Err(ClientError::wrap(e, "connect", &target, None)),
         }
+    }
 
-        let socket = UdpSocket::bind(addr).await?;
-        socket.connect((ip, port)).await?;
+    /// Build a new QueryClient bound to the given address and connected to
+    /// the server, described by the target in errors.
+    async fn connect(
+        target: String,
+        addr: impl ToSocketAddrs,
+        server: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let connect = async {
+            let socket = UdpSocket::bind(addr).await?;
+            socket.connect(server).await?;
+            Ok(socket)
+        };
+        let socket = connect
+            .await
+            .map_err(|e| ClientError::wrap(e, "connect", &target, None))?;
 
         let session_id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -77,9 +129,37 @@ impl QueryClient {
             socket,
             session_id,
             timeout,
+            target,
+            #[cfg(feature = "histogram")]
+            latency: Default::default(),
         })
     }
 
+    /// Address of the server this client is connected to, to compare with
+    /// the address it advertises, see [`verify_advertised_address`](crate::advertised::verify_advertised_address).
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Latency histograms of the successful requests of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn latency_snapshot(&self) -> crate::histogram::LatencySnapshot {
+        self.latency.snapshot()
+    }
+
+    /// Clear the latency histograms of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn reset_latency(&self) {
+        self.latency.reset();
+    }
+
+    /// Add a client operation and the server address to one of its errors.
+    fn context(&self, operation: &'static str, e: io::Error) -> io::Error {
+        ClientError::wrap(e, operation, &self.target, self.timeout)
+    }
+
     /// Receive a UDP packet from the client socket.
     pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
         let fut = self.socket.recv(buf);
@@ -92,52 +172,515 @@ impl QueryClient {
         }
     }
 
+    /// Send a request packet to the server.
+    async fn send(&self, packet: &impl QueryPacket) -> io::Result<usize> {
+        self.socket.send(packet.as_bytes()).await
+    }
+
     /// Send a UDP handshake packet to the client socket.
     ///
     /// Receive and parse the response into a Query token, valid up to 30 seconds.
     pub async fn handshake(&self) -> io::Result<Token> {
-        let handshake = packets::Handshake::new(self.session_id);
-        self.socket.send(&handshake).await?;
+        #[cfg(feature = "histogram")]
+        let start = std::time::Instant::now();
+        let res = async {
+            self.send(&packets::Handshake::new(self.session_id)).await?;
 
-        let mut buf = [0; Token::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
+            let mut buf = [0; Token::RESPONSE_SIZE];
+            let received = self.recv(&mut buf).await?;
 
-        Ok(Token::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        ))
+            Token::try_from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..received)
+                    .ok_or_else(not_enough_data)?,
+            )
+        }
+        .await;
+        #[cfg(feature = "histogram")]
+        self.latency.record("handshake", start, &res);
+        res.map_err(|e| self.context("handshake", e))
     }
 
     /// Request and wait for a basic status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub async fn basic_stat(&self, token: Token) -> std::io::Result<BasicStat> {
-        let request = packets::BasicStat::new(self.session_id, token.0);
-        self.socket.send(&request).await?;
+        self.basic_stat_into(token, &mut Vec::new()).await
+    }
 
-        let mut buf = vec![0; BasicStat::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
+    /// Like [`basic_stat`](Self::basic_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub async fn basic_stat_into(
+        &self,
+        token: Token,
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<BasicStat> {
+        #[cfg(feature = "histogram")]
+        let start = std::time::Instant::now();
+        let res = async {
+            self.send(&packets::BasicStat::new(self.session_id, token.0))
+                .await?;
 
-        BasicStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        )
+            buf.clear();
+            buf.resize(BasicStat::RESPONSE_SIZE, 0);
+            let received = self.recv(buf).await?;
+            buf.truncate(received);
+
+            BasicStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..)
+                    .ok_or_else(not_enough_data)?,
+            )
+        }
+        .await;
+        #[cfg(feature = "histogram")]
+        self.latency.record("basic_stat", start, &res);
+        res.map_err(|e| self.context("basic_stat", e))
     }
 
     /// Request and wait for a full status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub async fn full_stat(&self, token: Token) -> std::io::Result<FullStat> {
-        let request = packets::FullStat::new(self.session_id, token.0);
-        self.socket.send(&request).await?;
+        self.full_stat_into(token, &mut Vec::new()).await
+    }
 
-        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
+    /// Like [`full_stat`](Self::full_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub async fn full_stat_into(
+        &self,
+        token: Token,
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<FullStat> {
+        #[cfg(feature = "histogram")]
+        let start = std::time::Instant::now();
+        let res = async {
+            self.send(&packets::FullStat::new(self.session_id, token.0))
+                .await?;
 
-        FullStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        )
+            buf.clear();
+            buf.resize(FullStat::RESPONSE_SIZE, 0);
+            let received = self.recv(buf).await?;
+            buf.truncate(received);
+
+            FullStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..)
+                    .ok_or_else(not_enough_data)?,
+            )
+        }
+        .await;
+        #[cfg(feature = "histogram")]
+        self.latency.record("full_stat", start, &res);
+        res.map_err(|e| self.context("full_stat", e))
+    }
+
+    /// Request and wait for the full status of a Bedrock server, with its
+    /// Bedrock keys.
+    ///
+    /// If the token is no longer valid, no packet is received and an error is returned.
+    pub async fn bedrock_full_stat(&self, token: Token) -> io::Result<BedrockFullStat> {
+        #[cfg(feature = "histogram")]
+        let start = std::time::Instant::now();
+        let res = async {
+            self.send(&packets::FullStat::new(self.session_id, token.0))
+                .await?;
+
+            let mut buf = vec![0; FullStat::RESPONSE_SIZE];
+            let received = self.recv(&mut buf).await?;
+
+            BedrockFullStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..received)
+                    .ok_or_else(not_enough_data)?,
+            )
+        }
+        .await;
+        #[cfg(feature = "histogram")]
+        self.latency.record("full_stat", start, &res);
+        res.map_err(|e| self.context("full_stat", e))
+    }
+
+    /// Send a status request with arbitrary bytes after the token, built with
+    /// [`StatRequest::with_payload`](packets::StatRequest::with_payload), and
+    /// return the raw response, split into its header and its payload.
+    ///
+    /// This is a diagnostic API: the response is neither checked nor parsed,
+    /// and servers usually ignore non-standard requests, which then time out.
+    pub async fn send_custom_stat(
+        &self,
+        token: Token,
+        extra: &[u8],
+    ) -> io::Result<(packets::ResponseHeader, Vec<u8>)> {
+        async {
+            self.send(&packets::StatRequest::with_payload(
+                self.session_id,
+                token.0,
+                extra,
+            ))
+            .await?;
+
+            let mut buf = vec![0; FullStat::RESPONSE_SIZE];
+            let received = self.recv(&mut buf).await?;
+
+            let (header, payload) =
+                packets::ResponseHeader::parse(&buf[..received]).ok_or_else(not_enough_data)?;
+            Ok((header, payload.to_vec()))
+        }
+        .await
+        .map_err(|e| self.context("custom_stat", e))
+    }
+}
+
+/// One-shot query of a server, built with chained options.
+///
+/// A client is built for each call to [`full`](Self::full), [`basic`](Self::basic)
+/// or [`ping`](Self::ping), and dropped afterwards.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::async_std::Query;
+/// # use std::time::Duration;
+/// # async fn example() -> std::io::Result<()> {
+/// let full_stat = Query::to("play.example.com")
+///     .port(25565)
+///     .timeout(Duration::from_secs(2))
+///     .retries(2)
+///     .full()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    host: String,
+    port: Option<u16>,
+    bind: Option<SocketAddr>,
+    timeout: Duration,
+    retries: u32,
+    resolver: Arc<dyn Resolve>,
+    rate_limiter: Option<RateLimiter>,
+    #[cfg(feature = "opentelemetry")]
+    telemetry: Option<crate::otel::Telemetry>,
+}
+
+impl Query {
+    /// Start a query of the given server. If no port is specified in the
+    /// address or with [`port`](Self::port), the [default port](DEFAULT_PORT) is used.
+    pub fn to(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: None,
+            bind: None,
+            timeout: DEFAULT_TIMEOUT,
+            retries: 0,
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+
+    /// Port of the server. The address given to [`to`](Self::to) is then used
+    /// as is, so it can be an IPv6 address.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Local address to bind the client socket to. By default, an unspecified
+    /// address of the same IP version as the resolved server address.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = Some(addr);
+        self
+    }
+
+    /// Timeout of each response, [`DEFAULT_TIMEOUT`] by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of times to start over from the handshake if a response times
+    /// out, none by default. Other errors are returned immediately.
+    ///
+    /// Queries failing after a retry return an [`AttemptsExhausted`] error,
+    /// with the error of every attempt.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Resolver of the host name of the server, the [system
+    /// resolver](SystemResolver) by default. It is not consulted when the
+    /// host is an IP address.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Limiter every attempt of the query draws a permit from, the [global
+    /// limiter](crate::rate_limit::set_global) by default, if any.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Record the duration, failures and spans of the query with the given
+    /// [instruments](crate::otel).
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+    pub fn telemetry(mut self, telemetry: crate::otel::Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Get the full status of the server.
+    pub async fn full(self) -> io::Result<FullStat> {
+        self.full_timed().await.map(|(stat, _)| stat)
+    }
+
+    /// Get the basic status of the server.
+    pub async fn basic(self) -> io::Result<BasicStat> {
+        self.basic_timed().await.map(|(stat, _)| stat)
+    }
+
+    /// Like [`full`](Self::full), but also return the duration of every phase of the query.
+    pub async fn full_timed(self) -> io::Result<(FullStat, Timings)> {
+        let (res, timings) = self.full_recorded().await;
+        res.map(|(stat, _)| (stat, timings))
+    }
+
+    /// Like [`basic`](Self::basic), but also return the duration of every phase of the query.
+    pub async fn basic_timed(self) -> io::Result<(BasicStat, Timings)> {
+        let (res, timings) = self.basic_recorded().await;
+        res.map(|(stat, _)| (stat, timings))
+    }
+
+    /// Like [`full`](Self::full), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub async fn full_attempts(self) -> io::Result<(FullStat, Vec<AttemptError>)> {
+        self.full_recorded().await.0
+    }
+
+    /// Like [`basic`](Self::basic), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub async fn basic_attempts(self) -> io::Result<(BasicStat, Vec<AttemptError>)> {
+        self.basic_recorded().await.0
+    }
+
+    /// Get the full status of the server, or its basic status if the full
+    /// status request still times out on the last attempt. See
+    /// [`blocking::Query::full_or_basic`].
+    pub async fn full_or_basic(self) -> io::Result<AnyStat> {
+        self.full_or_basic_attempts().await.map(|(stat, _)| stat)
+    }
+
+    /// Like [`full_or_basic`](Self::full_or_basic), but also return the
+    /// attempts which timed out before the query succeeded. When the query
+    /// fell back to the basic status, the last one is the full status
+    /// request which timed out.
+    pub async fn full_or_basic_attempts(self) -> io::Result<(AnyStat, Vec<AttemptError>)> {
+        let operation = self.operation("full_or_basic");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, last| async move {
+                let start = Instant::now();
+                match client.full_stat(token).await {
+                    Ok(stat) => Ok((AnyStat::Full(stat), None)),
+                    Err(error) if last && is_timeout(&error) => {
+                        let stat = client.basic_stat(token).await?;
+                        let fallback = AttemptError::new(&client.target, error, start);
+                        Ok((AnyStat::Basic(stat), Some(fallback)))
+                    }
+                    Err(error) => Err(error),
+                }
+            })
+            .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        res.map(|((stat, fallback), mut attempts)| {
+            attempts.extend(fallback);
+            (stat, attempts)
+        })
+    }
+
+    /// Measure the round-trip time of a handshake with the server.
+    pub async fn ping(self) -> io::Result<Duration> {
+        let operation = self.operation("ping");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry(client, move || async move {
+                let start = Instant::now();
+                client.handshake().await?;
+                Ok(start.elapsed())
+            })
+            .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        res
+    }
+
+    /// Get the full status of the server, recording the operation. Returns
+    /// the attempts which failed before, and the duration of every phase.
+    async fn full_recorded(&self) -> (io::Result<(FullStat, Vec<AttemptError>)>, Timings) {
+        let operation = self.operation("full_stat");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, _| client.full_stat(token))
+                .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        (res, timings)
+    }
+
+    /// Like [`full_recorded`](Self::full_recorded), for the basic status.
+    async fn basic_recorded(&self) -> (io::Result<(BasicStat, Vec<AttemptError>)>, Timings) {
+        let operation = self.operation("basic_stat");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, _| client.basic_stat(token))
+                .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        (res, timings)
+    }
+
+    /// Start recording an operation of the query, if it has telemetry.
+    fn operation(&self, name: &'static str) -> Operation {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(telemetry) = &self.telemetry {
+            let (host, port) = match self.port {
+                Some(port) => (self.host.as_str(), port),
+                None => split_address(&self.host).unwrap_or((&self.host, DEFAULT_PORT)),
+            };
+            return Operation::start(telemetry, name, host, Some(port));
+        }
+        #[cfg(not(feature = "opentelemetry"))]
+        let _ = name;
+        Operation::none()
+    }
+
+    /// Resolve the server address and build the client of the query,
+    /// recording the duration of both phases.
+    async fn client(&self, timings: &mut Timings) -> io::Result<QueryClient> {
+        let (host, port) = match self.port {
+            Some(port) => (self.host.as_str(), port),
+            None => split_address(&self.host)?,
+        };
+        let target = format_target(host, port);
+
+        let start = Instant::now();
+        let server = resolve::first_address_async(&*self.resolver, host, port)
+            .await
+            .map_err(|e| ClientError::wrap(e, "resolve", &target, None))?;
+        timings.resolve = start.elapsed();
+
+        let start = Instant::now();
+        let bind = self.bind.unwrap_or_else(|| unspecified_for(&server));
+        let client = QueryClient::connect(target, bind, server, Some(self.timeout)).await?;
+        timings.connect = start.elapsed();
+        Ok(client)
+    }
+
+    /// Run a request of the client, retrying on timeouts.
+    async fn retry<T, F>(
+        &self,
+        client: &QueryClient,
+        mut request: impl FnMut() -> F,
+    ) -> io::Result<T>
+    where
+        F: std::future::Future<Output = io::Result<T>>,
+    {
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let mut attempts = Attempts::new(self.retries);
+        loop {
+            if let Some(limiter) = &limiter {
+                acquire(limiter).await;
+            }
+            let start = Instant::now();
+            if let Some(res) = attempts.check(&client.target, start, request().await) {
+                return res;
+            }
+        }
+    }
+
+    /// Run handshakes and status requests until one succeeds, retrying on
+    /// timeouts and recording every attempt. The status request is told
+    /// whether it is the last attempt. Returns the attempts which failed
+    /// before.
+    async fn retry_timed<T, F>(
+        &self,
+        client: &QueryClient,
+        timings: &mut Timings,
+        mut stat: impl FnMut(Token, bool) -> F,
+    ) -> io::Result<(T, Vec<AttemptError>)>
+    where
+        F: std::future::Future<Output = io::Result<T>>,
+    {
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let mut attempts = Attempts::new(self.retries);
+        loop {
+            if let Some(limiter) = &limiter {
+                acquire(limiter).await;
+            }
+            let start = Instant::now();
+            let token = client.handshake().await;
+            timings.handshakes.push(start.elapsed());
+
+            let res = match token {
+                Ok(token) => {
+                    let start = Instant::now();
+                    let res = stat(token, attempts.is_last()).await;
+                    timings.stats.push(start.elapsed());
+                    res
+                }
+                Err(e) => Err(e),
+            };
+            if let Some(res) = attempts.check(&client.target, start, res) {
+                return res.map(|stat| (stat, attempts.into_failed()));
+            }
+        }
+    }
+}
+
+impl From<ConnectionString> for Query {
+    /// Query the server of a connection string, with its options.
+    fn from(target: ConnectionString) -> Self {
+        Self {
+            port: Some(target.port_or_default()),
+            host: target.host,
+            bind: target.bind,
+            timeout: target.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            retries: target.retries.unwrap_or(0),
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = ConnectionStringError;
+
+    /// Parse a [connection string](crate::connection_string), or a plain
+    /// `host[:port]` target.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<ConnectionString>().map(Self::from)
+    }
+}
+
+/// Wait for a permit of the limiter, with the timer of `async-std`.
+async fn acquire(limiter: &RateLimiter) {
+    let wait = limiter.reserve();
+    if !wait.is_zero() {
+        ::async_std::task::sleep(wait).await;
     }
 }
 
@@ -146,25 +689,69 @@ impl QueryClient {
 /// Send a handshake first, and if a token is successfully received and parsed,
 /// request a full status packet.
 pub async fn query(ip: &str) -> io::Result<FullStat> {
+    if let Some(limiter) = &rate_limit::global() {
+        acquire(limiter).await;
+    }
     let client = QueryClient::new(ip).await?;
     let token = client.handshake().await?;
 
     client.full_stat(token).await
 }
 
+/// Convenience function to get a full status packet, with the duration of
+/// every phase of the query.
+///
+/// Like [`query`], built with [`Query`]: see [`Query::full_timed`].
+pub async fn query_timed(ip: &str) -> io::Result<(FullStat, Timings)> {
+    Query::to(ip).full_timed().await
+}
+
+/// Convenience function to get the most detailed status a server answers with.
+///
+/// Like [`query`], but if the full status request times out, request a basic
+/// status instead, with the same token. Some old modded servers answer
+/// handshakes and basic status requests, but ignore full status requests.
+///
+/// Built with [`Query`]: see [`Query::full_or_basic`].
+pub async fn query_lenient(ip: &str) -> io::Result<AnyStat> {
+    Query::to(ip).full_or_basic().await
+}
+
+/// Convenience function to get the full status of a Bedrock server with
+/// query enabled, with its Bedrock keys.
+///
+/// Like [`query`], but if no port is specified in the IP address, the
+/// [default Bedrock port](DEFAULT_BEDROCK_PORT) is used.
+pub async fn query_bedrock(ip: &str) -> io::Result<BedrockFullStat> {
+    if let Some(limiter) = &rate_limit::global() {
+        acquire(limiter).await;
+    }
+    let (ip, port) = split_address_or(ip, DEFAULT_BEDROCK_PORT)?;
+    let client = QueryClient::new_with_port(ip, port).await?;
+    let token = client.handshake().await?;
+
+    client.bedrock_full_stat(token).await
+}
+
 #[cfg(test)]
 mod tests {
-    const TEST_IP: &str = "lotr.g.akliz.net:25565";
+    use crate::testing::MockQueryServer;
 
     #[tokio::test]
     async fn test_handshake() {
-        let client = super::QueryClient::new(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
         client.handshake().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_basic_stat() {
-        let client = super::QueryClient::new(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
         let token = client.handshake().await.unwrap();
 
         let basic_stat = client.basic_stat(token).await.unwrap();
@@ -173,11 +760,45 @@ mod tests {
 
     #[tokio::test]
     async fn test_full_stat() {
-        let full_stat = super::query(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let full_stat = super::query(&server.addr().to_string()).await.unwrap();
 
         assert_eq!(full_stat.hostport, crate::DEFAULT_PORT);
         assert_eq!(full_stat.numplayers as usize, full_stat.player_list.len());
-        assert_eq!(full_stat.version, "1.7.10");
+        assert_eq!(full_stat.version, server.full_stat().version);
+        assert_eq!(full_stat.game_id, "MINECRAFT");
+    }
+
+    /// Query a live server, if one is given in the `LIVE_QUERY_SERVER` environment variable
+    #[tokio::test]
+    async fn test_live_server() {
+        let Ok(host) = std::env::var("LIVE_QUERY_SERVER") else {
+            return;
+        };
+        let client = super::QueryClient::new(&host).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        let basic_stat = client.basic_stat(token).await.unwrap();
+        let full_stat = client.full_stat(token).await.unwrap();
         assert_eq!(full_stat.game_id, "MINECRAFT");
+        assert_eq!(basic_stat.gametype, full_stat.gametype);
+        assert_eq!(basic_stat.map, full_stat.map);
+        assert_eq!(basic_stat.maxplayers, full_stat.maxplayers);
+    }
+
+    #[tokio::test]
+    async fn test_from_stat() {
+        let front = MockQueryServer::new().unwrap();
+        let back = MockQueryServer::new().unwrap();
+        let mut advertisement = front.full_stat();
+        advertisement.hostip = "127.0.0.1".into();
+        advertisement.hostport = back.addr().port();
+        front.set_full_stat(advertisement);
+
+        let front_stat = super::query(&front.addr().to_string()).await.unwrap();
+        let client = super::QueryClient::from_stat(&front_stat, Default::default())
+            .await
+            .unwrap();
+        let token = client.handshake().await.unwrap();
+        assert_eq!(client.full_stat(token).await.unwrap(), back.full_stat());
     }
 }
@@ -0,0 +1,204 @@
+//! Blocking implementation of the Bedrock ping.
+//!
+//! Uses [std::net::UdpSocket] for sending and receiving UDP data.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use super::*;
+use crate::DEFAULT_TIMEOUT;
+
+/// A blocking Bedrock ping client using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct BedrockClient {
+    socket: UdpSocket,
+    client_guid: u64,
+}
+
+impl BedrockClient {
+    /// Build a new BedrockClient from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default Bedrock port](DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new(ip: &str) -> io::Result<Self> {
+        let (ip, port) = if let Some((ip, port)) = ip.split_once(':') {
+            (
+                ip,
+                port.parse::<u16>()
+                    .map_err(|_| io::Error::other("Invalid port in IP address"))?,
+            )
+        } else {
+            (ip, DEFAULT_PORT)
+        };
+
+        Self::new_with_port(ip, port)
+    }
+
+    /// Build a new BedrockClient from the given IP address and port.
+    ///
+    /// If the IP address already contains a port, an error is returned.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
+        if ip.contains(':') {
+            return Err(io::Error::other(
+                "Invalid IP address: must not contain a port.",
+            ));
+        }
+
+        Self::new_with_socket_address(ip, port, (Ipv4Addr::UNSPECIFIED, 0), Some(DEFAULT_TIMEOUT))
+    }
+
+    /// Builds a new BedrockClient from the given IP address, port, socket address and optional timeout.
+    ///
+    /// The IP adress must not contain a port.
+    pub fn new_with_socket_address(
+        ip: &str,
+        port: u16,
+        addr: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(timeout)?;
+        socket.connect((ip, port))?;
+
+        Ok(Self {
+            socket,
+            client_guid: client_guid(),
+        })
+    }
+
+    /// Send an unconnected ping to the client socket.
+    ///
+    /// Receive and parse the pong, measuring the round trip time.
+    pub fn ping(&self) -> io::Result<BedrockPing> {
+        let request = UnconnectedPing::new(ping_time(), self.client_guid);
+        let start = Instant::now();
+        self.socket.send(&request)?;
+
+        let mut buf = vec![0; PONG_RESPONSE_SIZE];
+        let received = self.socket.recv(&mut buf)?;
+        let latency = start.elapsed();
+
+        let pong = Pong::from_payload(&buf[..received])?;
+
+        Ok(BedrockPing {
+            server_guid: pong.server_guid,
+            server_id: pong.server_id,
+            latency,
+        })
+    }
+}
+
+/// Convenience function to ping a Bedrock server.
+pub fn ping(ip: &str) -> io::Result<BedrockPing> {
+    BedrockClient::new(ip)?.ping()
+}
+
+/// Broadcast an unconnected ping to the [default Bedrock port](DEFAULT_PORT)
+/// on the local network, returning the status of every server answering
+/// within the wait.
+///
+/// The broadcast does not cross routers. See [`discover_lan_to`] to send
+/// directed broadcasts, or to reach servers on other ports.
+pub fn discover_lan(wait: Duration) -> io::Result<Vec<(SocketAddr, BedrockStat)>> {
+    discover_lan_to(
+        &[SocketAddr::from((Ipv4Addr::BROADCAST, DEFAULT_PORT))],
+        wait,
+    )
+}
+
+/// Send an unconnected ping to each of the given addresses, usually the
+/// directed broadcast addresses of the local interfaces, returning the status
+/// of every server answering within the wait.
+///
+/// Servers are deduplicated by address, and malformed pongs are ignored.
+pub fn discover_lan_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, BedrockStat)>> {
+    let deadline = Instant::now() + wait;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+
+    let mut discovery = Discovery::new();
+    for target in targets {
+        socket.send_to(&discovery.ping(), target)?;
+    }
+
+    let mut buf = vec![0; PONG_RESPONSE_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((received, source)) => discovery.receive(&buf[..received], source),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            // ICMP port unreachable errors from targets without a server are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(discovery.into_servers())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{spawn_stub, BDS_PONG};
+
+    #[test]
+    fn test_ping() {
+        let addr = spawn_stub(BDS_PONG);
+
+        let ping = super::ping(&addr.to_string()).unwrap();
+        assert_eq!(ping.server_guid, 0xb7f06c1a2d840231);
+        assert!(ping.server_id.starts_with("MCPE;Dedicated Server;"));
+    }
+
+    #[test]
+    fn test_ping_timeout() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let client = super::BedrockClient::new_with_socket_address(
+            "127.0.0.1",
+            addr.port(),
+            "127.0.0.1:0",
+            Some(std::time::Duration::from_millis(50)),
+        )
+        .unwrap();
+        assert!(client.ping().is_err());
+    }
+
+    #[test]
+    fn test_discover_lan_to() {
+        let a = spawn_stub(BDS_PONG);
+        let b = spawn_stub(BDS_PONG);
+        let servers =
+            super::discover_lan_to(&[a, b, a], std::time::Duration::from_millis(200)).unwrap();
+
+        let mut addrs = servers.iter().map(|(addr, _)| *addr).collect::<Vec<_>>();
+        addrs.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(addrs, expected);
+        assert!(servers
+            .iter()
+            .all(|(_, stat)| stat.motd == "Dedicated Server"));
+    }
+}
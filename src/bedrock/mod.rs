@@ -0,0 +1,617 @@
+//! Implementation of the Bedrock Edition [RakNet unconnected ping](https://wiki.vg/Raknet_Protocol#Unconnected_Ping)
+//!
+//! Bedrock servers do not answer the Query protocol by default, but they reply to
+//! RakNet unconnected pings with a pong containing a semicolon-separated server ID string.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::bedrock;
+//! let client = bedrock::blocking::BedrockClient::new("127.0.0.1:19132")?;
+//! let pong = client.ping()?;
+//!
+//! println!("{} ({:?})", pong.server_id, pong.latency);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Servers on the local network, including worlds hosted by game clients, can
+//! be found by broadcasting a ping:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::bedrock;
+//! # use std::time::Duration;
+//! for (addr, stat) in bedrock::blocking::discover_lan(Duration::from_secs(1))? {
+//!     println!("{} on {}", stat, addr);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{io, net::SocketAddr, ops::Deref, time::Duration};
+
+use bytes::{Buf, BufMut};
+
+use crate::{custom_io_error, not_enough_data};
+
+/// Default port for a Bedrock server.
+pub const DEFAULT_PORT: u16 = crate::DEFAULT_BEDROCK_PORT;
+
+/// Offline message magic, present in every unconnected RakNet packet
+pub const MAGIC: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// RakNet packet ID of an unconnected ping
+const UNCONNECTED_PING_ID: u8 = 0x01;
+/// RakNet packet ID of an unconnected pong
+const UNCONNECTED_PONG_ID: u8 = 0x1C;
+
+/// Pong response max size, in bytes
+const PONG_RESPONSE_SIZE: usize = 1500;
+
+/// Unconnected ping request packet, 33 bytes long
+///
+/// | Field name  | Field type | Notes                                 |
+/// |-------------|------------|---------------------------------------|
+/// | Packet ID   | [`u8`]     | Always `0x01`                         |
+/// | Time        | [`u64`]    | Echoed back by the server in the pong |
+/// | Magic       | 16 bytes   | See [`MAGIC`]                         |
+/// | Client GUID | [`u64`]    |                                       |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnconnectedPing([u8; 33]);
+
+impl UnconnectedPing {
+    /// Build a new unconnected ping packet from the given timestamp and client GUID
+    pub fn new(time: u64, client_guid: u64) -> Self {
+        let mut res = [0; 33];
+        {
+            let mut packet = &mut res[..];
+            packet.put_u8(UNCONNECTED_PING_ID);
+            packet.put_u64(time);
+            packet.put_slice(&MAGIC);
+            packet.put_u64(client_guid);
+        }
+
+        Self(res)
+    }
+}
+
+impl Deref for UnconnectedPing {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// An unconnected pong sent back by a Bedrock server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pong {
+    /// Timestamp of the ping this pong answers
+    pub time: u64,
+    /// Server GUID, randomly generated by the server on startup
+    pub server_guid: u64,
+    /// Semicolon-separated server ID string
+    pub server_id: String,
+}
+
+impl Pong {
+    /// Parse a pong from a UDP payload. Fails if the packet ID or the magic
+    /// are wrong, or if the pong is too short to hold them.
+    ///
+    /// A server ID string shorter than its announced length, like in pongs
+    /// truncated on the way, is kept as received. Any bytes after the server
+    /// ID string are ignored.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::bedrock::{Pong, MAGIC};
+    /// let mut payload = vec![0x1C];
+    /// payload.extend_from_slice(&42u64.to_be_bytes());
+    /// payload.extend_from_slice(&1234u64.to_be_bytes());
+    /// payload.extend_from_slice(&MAGIC);
+    /// payload.extend_from_slice(&[0, 4]);
+    /// payload.extend_from_slice(b"MCPE");
+    ///
+    /// assert_eq!(
+    ///     Pong::from_payload(&payload)?,
+    ///     Pong {
+    ///         time: 42,
+    ///         server_guid: 1234,
+    ///         server_id: "MCPE".to_string(),
+    ///     }
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload(mut payload: &[u8]) -> io::Result<Self> {
+        if payload.remaining() < 1 + 8 + 8 + MAGIC.len() + 2 {
+            return Err(not_enough_data());
+        }
+
+        if payload.get_u8() != UNCONNECTED_PONG_ID {
+            return Err(custom_io_error(
+                "Invalid packet ID for an unconnected pong.",
+            ));
+        }
+        let time = payload.get_u64();
+        let server_guid = payload.get_u64();
+        if payload[..MAGIC.len()] != MAGIC {
+            return Err(custom_io_error("Invalid RakNet magic in unconnected pong."));
+        }
+        payload.advance(MAGIC.len());
+
+        let len = payload.get_u16() as usize;
+        let server_id = &payload[..len.min(payload.len())];
+
+        Ok(Self {
+            time,
+            server_guid,
+            server_id: String::from_utf8_lossy(server_id).into_owned(),
+        })
+    }
+}
+
+/// Result of a Bedrock ping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BedrockPing {
+    /// Server GUID, randomly generated by the server on startup
+    pub server_guid: u64,
+    /// Semicolon-separated server ID string
+    pub server_id: String,
+    /// Round trip time between sending the ping and receiving the pong
+    pub latency: Duration,
+}
+
+impl BedrockPing {
+    /// Parse the server ID string of this ping into a [`BedrockStat`].
+    pub fn stat(&self) -> io::Result<BedrockStat> {
+        BedrockStat::from_id_string(&self.server_id)
+    }
+}
+
+/// Status information on a Bedrock server, parsed from a pong server ID string
+///
+/// Older servers send fewer segments: only the fields up to the maximum number
+/// of players are mandatory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BedrockStat {
+    /// Edition of the server, `"MCPE"` or `"MCEE"` for Education Edition
+    pub edition: String,
+    /// First line of the server MoTD
+    pub motd: String,
+    /// Protocol version (`594`...)
+    pub protocol: u32,
+    /// Game version (`"1.20.12"`...)
+    pub version: String,
+    /// How many players are currently online
+    pub numplayers: u32,
+    /// Maximum number of players this server supports
+    pub maxplayers: u32,
+    /// Server unique ID
+    pub server_guid: Option<u64>,
+    /// Second line of the server MoTD, usually the world name
+    pub sub_motd: Option<String>,
+    /// Default game mode (`"Survival"`...)
+    pub gamemode: Option<String>,
+    /// Default game mode, as a number
+    pub gamemode_num: Option<u8>,
+    /// IPv4 port the server is listening on
+    pub port_v4: Option<u16>,
+    /// IPv6 port the server is listening on
+    pub port_v6: Option<u16>,
+}
+
+impl BedrockStat {
+    /// Parse a Bedrock stat struct from a server ID string. Fails if the mandatory
+    /// fields are missing, or if a numeric field cannot be parsed.
+    ///
+    /// Escaped semicolons (`\;`) in the MoTDs are unescaped.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::bedrock::BedrockStat;
+    /// let stat = BedrockStat::from_id_string("MCPE;A Bedrock Server;594;1.20.12;2;10")?;
+    ///
+    /// assert_eq!(stat.motd, "A Bedrock Server");
+    /// assert_eq!(stat.numplayers, 2);
+    /// assert_eq!(stat.maxplayers, 10);
+    /// assert_eq!(stat.sub_motd, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_id_string(id: &str) -> io::Result<Self> {
+        Self::parse(id, true)
+    }
+
+    /// Parse a Bedrock stat struct from a server ID string, like
+    /// [`from_id_string`](Self::from_id_string), but without failing on
+    /// numeric fields that cannot be parsed.
+    ///
+    /// Invalid mandatory numbers are set to `0`, invalid optional numbers to `None`.
+    pub fn from_id_string_lenient(id: &str) -> io::Result<Self> {
+        Self::parse(id, false)
+    }
+
+    /// Parse a server ID string, failing on invalid numbers in strict mode.
+    fn parse(id: &str, strict: bool) -> io::Result<Self> {
+        let mut values = split_id_string(id).into_iter();
+
+        let mut string = || values.next().ok_or_else(not_enough_data);
+        let edition = string()?;
+        let motd = string()?;
+        let protocol = string()?;
+        let version = string()?;
+        let numplayers = string()?;
+        let maxplayers = string()?;
+
+        let mut optional = || values.next().filter(|value| !value.is_empty());
+        let server_guid = optional();
+        let sub_motd = optional();
+        let gamemode = optional();
+        let gamemode_num = optional();
+        let port_v4 = optional();
+        let port_v6 = optional();
+
+        Ok(Self {
+            edition,
+            motd,
+            protocol: parse_segment(&protocol, strict)?.unwrap_or(0),
+            version,
+            numplayers: parse_segment(&numplayers, strict)?.unwrap_or(0),
+            maxplayers: parse_segment(&maxplayers, strict)?.unwrap_or(0),
+            server_guid: parse_optional_segment(server_guid, strict)?,
+            sub_motd,
+            gamemode,
+            gamemode_num: parse_optional_segment(gamemode_num, strict)?,
+            port_v4: parse_optional_segment(port_v4, strict)?,
+            port_v6: parse_optional_segment(port_v6, strict)?,
+        })
+    }
+}
+
+impl std::fmt::Display for BedrockStat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.motd)?;
+        if let Some(sub_motd) = &self.sub_motd {
+            write!(f, " - {}", sub_motd)?;
+        }
+        write!(
+            f,
+            " ({} {}, {}/{} players)",
+            self.edition, self.version, self.numplayers, self.maxplayers
+        )
+    }
+}
+
+/// Servers answering a broadcast ping, deduplicated by source address
+#[derive(Debug)]
+struct Discovery {
+    client_guid: u64,
+    servers: Vec<(SocketAddr, BedrockStat)>,
+}
+
+impl Discovery {
+    fn new() -> Self {
+        Self {
+            client_guid: client_guid(),
+            servers: Vec::new(),
+        }
+    }
+
+    /// The unconnected ping to broadcast.
+    fn ping(&self) -> UnconnectedPing {
+        UnconnectedPing::new(ping_time(), self.client_guid)
+    }
+
+    /// Handle a datagram received by the broadcasting socket.
+    ///
+    /// The server ID string is parsed leniently. Malformed pongs, and pongs
+    /// from a source which already answered, are ignored.
+    fn receive(&mut self, datagram: &[u8], source: SocketAddr) {
+        if self.servers.iter().any(|(addr, _)| *addr == source) {
+            return;
+        }
+        let Ok(pong) = Pong::from_payload(datagram) else {
+            return;
+        };
+        if let Ok(stat) = BedrockStat::from_id_string_lenient(&pong.server_id) {
+            self.servers.push((source, stat));
+        }
+    }
+
+    /// The servers which answered, in the order of their first pong.
+    fn into_servers(self) -> Vec<(SocketAddr, BedrockStat)> {
+        self.servers
+    }
+}
+
+/// Split a server ID string on unescaped semicolons, unescaping `\;` sequences.
+fn split_id_string(id: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut current = String::new();
+    let mut chars = id.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&';') => current.push(chars.next().unwrap()),
+            ';' => values.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        values.push(current);
+    }
+
+    values
+}
+
+/// Parse a decimal number from a server ID string segment.
+///
+/// Some server implementations (Nukkit...) send their unique ID as a signed
+/// number: negative values are reinterpreted in two's complement.
+///
+/// In strict mode, invalid or out of range numbers are an error. Otherwise,
+/// they are discarded.
+fn parse_segment<T: TryFrom<u64>>(value: &str, strict: bool) -> io::Result<Option<T>> {
+    let number = match value.strip_prefix('-') {
+        Some(abs) => abs.parse::<u64>().ok().map(|n| n.wrapping_neg()),
+        None => value.parse::<u64>().ok(),
+    };
+
+    match number.and_then(|n| T::try_from(n).ok()) {
+        Some(n) => Ok(Some(n)),
+        None if strict => Err(custom_io_error(
+            "Failed to parse decimal integer in Bedrock server ID string.",
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Parse an optional server ID string segment, see [`parse_segment`].
+fn parse_optional_segment<T: TryFrom<u64>>(
+    value: Option<String>,
+    strict: bool,
+) -> io::Result<Option<T>> {
+    match value {
+        Some(value) => parse_segment(&value, strict),
+        None => Ok(None),
+    }
+}
+
+/// Generate a pseudo-random client GUID from the system time
+fn client_guid() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time cannot be before UNIX_EPOCH")
+        .as_nanos() as u64
+}
+
+/// Timestamp sent in unconnected pings, in milliseconds since the UNIX epoch
+fn ping_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("System time cannot be before UNIX_EPOCH")
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Pong captured from a vanilla Bedrock Dedicated Server
+    pub(crate) const BDS_PONG: &[u8] = b"\x1c\x00\x00\x00\x00\x00\x0e\x1d\x5e\
+        \xb7\xf0\x6c\x1a\x2d\x84\x02\x31\x00\xff\xff\x00\xfe\xfe\xfe\xfe\xfd\xfd\xfd\xfd\
+        \x12\x34\x56\x78\x00\x61MCPE;Dedicated Server;594;1.20.12;0;10;13253860892328930865;\
+        Bedrock level;Survival;1;19132;19133;";
+
+    /// Spawn an in-process stub replaying `pong` to every unconnected ping it receives,
+    /// until no ping is received for a second.
+    pub(crate) fn spawn_stub(pong: &'static [u8]) -> std::net::SocketAddr {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let mut buf = [0; 64];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                if len == 33 && buf[0] == UNCONNECTED_PING_ID && buf[9..25] == MAGIC {
+                    socket.send_to(pong, peer).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    /// Server ID string sent by a vanilla Bedrock Dedicated Server
+    const BDS_ID: &str = "MCPE;Dedicated Server;594;1.20.12;0;10;13253860892328930865;\
+        Bedrock level;Survival;1;19132;19133;";
+    /// Server ID string sent by a Nukkit server, with a signed server ID
+    const NUKKIT_ID: &str =
+        "MCPE;\u{a7}bNukkit \\;Server;419;1.16.100;3;50;-6431986324932476623;Nukkit;Survival";
+
+    #[test]
+    fn test_parse_bds_id_string() {
+        assert_eq!(
+            BedrockStat::from_id_string(BDS_ID).unwrap(),
+            BedrockStat {
+                edition: "MCPE".to_string(),
+                motd: "Dedicated Server".to_string(),
+                protocol: 594,
+                version: "1.20.12".to_string(),
+                numplayers: 0,
+                maxplayers: 10,
+                server_guid: Some(13253860892328930865),
+                sub_motd: Some("Bedrock level".to_string()),
+                gamemode: Some("Survival".to_string()),
+                gamemode_num: Some(1),
+                port_v4: Some(19132),
+                port_v6: Some(19133),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_nukkit_id_string() {
+        assert_eq!(
+            BedrockStat::from_id_string(NUKKIT_ID).unwrap(),
+            BedrockStat {
+                edition: "MCPE".to_string(),
+                motd: "\u{a7}bNukkit ;Server".to_string(),
+                protocol: 419,
+                version: "1.16.100".to_string(),
+                numplayers: 3,
+                maxplayers: 50,
+                server_guid: Some(-6431986324932476623i64 as u64),
+                sub_motd: Some("Nukkit".to_string()),
+                gamemode: Some("Survival".to_string()),
+                gamemode_num: None,
+                port_v4: None,
+                port_v6: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_short_id_string() {
+        let stat = BedrockStat::from_id_string("MCPE;Old Server;137;1.2.0;1;20").unwrap();
+        assert_eq!(stat.maxplayers, 20);
+        assert_eq!(stat.server_guid, None);
+        assert_eq!(stat.sub_motd, None);
+
+        assert!(BedrockStat::from_id_string("MCPE;Old Server;137;1.2.0;1").is_err());
+    }
+
+    #[test]
+    fn test_parse_extra_fields() {
+        // Fields added after the IPv6 port by newer servers and proxies are ignored
+        let id = format!("{BDS_ID}0;Geyser;extra;;");
+        assert_eq!(
+            BedrockStat::from_id_string(&id).unwrap(),
+            BedrockStat::from_id_string(BDS_ID).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_numbers() {
+        let id = "MCPE;Server;abc;1.20.12;2;10;123;World;Survival;1;99999;19133";
+        assert!(BedrockStat::from_id_string(id).is_err());
+
+        let stat = BedrockStat::from_id_string_lenient(id).unwrap();
+        assert_eq!(stat.protocol, 0);
+        assert_eq!(stat.numplayers, 2);
+        assert_eq!(stat.port_v4, None);
+        assert_eq!(stat.port_v6, Some(19133));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            BedrockStat::from_id_string(BDS_ID).unwrap().to_string(),
+            "Dedicated Server - Bedrock level (MCPE 1.20.12, 0/10 players)"
+        );
+    }
+
+    #[test]
+    fn test_ping_layout() {
+        let ping = UnconnectedPing::new(0x0102030405060708, 0xAABBCCDDEEFF0011);
+        assert_eq!(ping.len(), 33);
+        assert_eq!(ping[0], 0x01);
+        assert_eq!(ping[1..9], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(ping[9..25], MAGIC);
+        assert_eq!(ping[25..], [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x00, 0x11]);
+    }
+
+    #[test]
+    fn test_parse_captured_pong() {
+        let pong = Pong::from_payload(BDS_PONG).unwrap();
+        assert_eq!(pong.time, 0x0e1d5e);
+        assert_eq!(pong.server_guid, 0xb7f06c1a2d840231);
+        assert_eq!(
+            pong.server_id,
+            "MCPE;Dedicated Server;594;1.20.12;0;10;13253860892328930865;\
+            Bedrock level;Survival;1;19132;19133;"
+        );
+    }
+
+    #[test]
+    fn test_parse_pong_trailing_bytes() {
+        let mut payload = BDS_PONG.to_vec();
+        payload.extend_from_slice(b"\0\0garbage");
+        assert_eq!(
+            Pong::from_payload(&payload).unwrap(),
+            Pong::from_payload(BDS_PONG).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_discovery_responses() {
+        let a = SocketAddr::from(([192, 168, 1, 2], 19132));
+        let b = SocketAddr::from(([192, 168, 1, 3], 19132));
+        let c = SocketAddr::from(([192, 168, 1, 4], 19132));
+        let pong = |id: &str| {
+            let mut payload = vec![UNCONNECTED_PONG_ID];
+            payload.extend_from_slice(&0u64.to_be_bytes());
+            payload.extend_from_slice(&42u64.to_be_bytes());
+            payload.extend_from_slice(&MAGIC);
+            payload.extend_from_slice(&(id.len() as u16).to_be_bytes());
+            payload.extend_from_slice(id.as_bytes());
+            payload
+        };
+
+        let mut discovery = Discovery::new();
+        assert_eq!(discovery.ping()[25..], discovery.client_guid.to_be_bytes());
+
+        // Malformed pongs and server ID strings are ignored
+        discovery.receive(&BDS_PONG[..20], a);
+        discovery.receive(&pong("MCPE;Missing fields;594"), a);
+        discovery.receive(BDS_PONG, a);
+        // Only the first pong of a source is kept
+        discovery.receive(&pong("MCPE;Again;594;1.20.12;1;10"), a);
+        // Invalid numbers are tolerated
+        discovery.receive(&pong("MCPE;Friend's world;594;1.20.12;one;8"), b);
+        discovery.receive(b"garbage", c);
+
+        let servers = discovery.into_servers();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].0, a);
+        assert_eq!(servers[0].1.motd, "Dedicated Server");
+        assert_eq!(servers[1].0, b);
+        assert_eq!(servers[1].1.motd, "Friend's world");
+        assert_eq!(servers[1].1.numplayers, 0);
+    }
+
+    #[test]
+    fn test_parse_truncated_pong() {
+        let pong = Pong::from_payload(&BDS_PONG[..BDS_PONG.len() - 7]).unwrap();
+        assert_eq!(
+            pong.server_id,
+            "MCPE;Dedicated Server;594;1.20.12;0;10;13253860892328930865;\
+            Bedrock level;Survival;1;19132"
+        );
+        let stat = BedrockStat::from_id_string(&pong.server_id).unwrap();
+        assert_eq!(stat.port_v4, Some(19132));
+        assert_eq!(stat.port_v6, None);
+
+        let header = 1 + 8 + 8 + MAGIC.len() + 2;
+        assert_eq!(
+            Pong::from_payload(&BDS_PONG[..header]).unwrap().server_id,
+            ""
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_pong() {
+        assert!(Pong::from_payload(&BDS_PONG[..20]).is_err());
+        assert!(Pong::from_payload(&BDS_PONG[..1 + 8 + 8 + MAGIC.len() + 1]).is_err());
+
+        let mut payload = BDS_PONG.to_vec();
+        payload[0] = 0x1D;
+        assert!(Pong::from_payload(&payload).is_err());
+
+        let mut payload = BDS_PONG.to_vec();
+        payload[18] = 0;
+        assert!(Pong::from_payload(&payload).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the Bedrock ping.
+//!
+//! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
+
+use ::tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::{timeout, timeout_at},
+};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+use super::*;
+use crate::DEFAULT_TIMEOUT;
+
+/// An asynchronous Bedrock ping client using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct BedrockClient {
+    socket: UdpSocket,
+    client_guid: u64,
+    timeout: Option<Duration>,
+}
+
+impl BedrockClient {
+    /// Build a new BedrockClient from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default Bedrock port](DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn new(ip: &str) -> io::Result<Self> {
+        let (ip, port) = if let Some((ip, port)) = ip.split_once(':') {
+            (
+                ip,
+                port.parse::<u16>()
+                    .map_err(|_| io::Error::other("Invalid port in IP address"))?,
+            )
+        } else {
+            (ip, DEFAULT_PORT)
+        };
+
+        Self::new_with_port(ip, port).await
+    }
+
+    /// Build a new BedrockClient from the given IP address and port.
+    ///
+    /// If the IP address already contains a port, an error is returned.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
+        if ip.contains(':') {
+            return Err(io::Error::other(
+                "Invalid IP address: must not contain a port.",
+            ));
+        }
+
+        Self::new_with_socket_address(ip, port, (Ipv4Addr::UNSPECIFIED, 0), Some(DEFAULT_TIMEOUT))
+            .await
+    }
+
+    /// Builds a new BedrockClient from the given IP address, port, socket address and optional timeout.
+    ///
+    /// The IP adress must not contain a port.
+    pub async fn new_with_socket_address(
+        ip: &str,
+        port: u16,
+        addr: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr).await?;
+        socket.connect((ip, port)).await?;
+
+        Ok(Self {
+            socket,
+            client_guid: client_guid(),
+            timeout,
+        })
+    }
+
+    /// Receive a UDP packet from the client socket.
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let fut = self.socket.recv(buf);
+        if let Some(duration) = self.timeout {
+            timeout(duration, fut).await.map_err(|_| {
+                io::Error::new(io::ErrorKind::TimedOut, "UDP async recv call timed out.")
+            })?
+        } else {
+            fut.await
+        }
+    }
+
+    /// Send an unconnected ping to the client socket.
+    ///
+    /// Receive and parse the pong, measuring the round trip time.
+    pub async fn ping(&self) -> io::Result<BedrockPing> {
+        let request = UnconnectedPing::new(ping_time(), self.client_guid);
+        let start = Instant::now();
+        self.socket.send(&request).await?;
+
+        let mut buf = vec![0; PONG_RESPONSE_SIZE];
+        let received = self.recv(&mut buf).await?;
+        let latency = start.elapsed();
+
+        let pong = Pong::from_payload(&buf[..received])?;
+
+        Ok(BedrockPing {
+            server_guid: pong.server_guid,
+            server_id: pong.server_id,
+            latency,
+        })
+    }
+}
+
+/// Convenience function to ping a Bedrock server.
+pub async fn ping(ip: &str) -> io::Result<BedrockPing> {
+    BedrockClient::new(ip).await?.ping().await
+}
+
+/// Broadcast an unconnected ping to the [default Bedrock port](DEFAULT_PORT)
+/// on the local network, returning the status of every server answering
+/// within the wait.
+///
+/// The broadcast does not cross routers. See [`discover_lan_to`] to send
+/// directed broadcasts, or to reach servers on other ports.
+pub async fn discover_lan(wait: Duration) -> io::Result<Vec<(SocketAddr, BedrockStat)>> {
+    discover_lan_to(
+        &[SocketAddr::from((Ipv4Addr::BROADCAST, DEFAULT_PORT))],
+        wait,
+    )
+    .await
+}
+
+/// Send an unconnected ping to each of the given addresses, usually the
+/// directed broadcast addresses of the local interfaces, returning the status
+/// of every server answering within the wait.
+///
+/// Servers are deduplicated by address, and malformed pongs are ignored.
+pub async fn discover_lan_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, BedrockStat)>> {
+    let deadline = ::tokio::time::Instant::now() + wait;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+
+    let mut discovery = Discovery::new();
+    for target in targets {
+        socket.send_to(&discovery.ping(), target).await?;
+    }
+
+    let mut buf = vec![0; PONG_RESPONSE_SIZE];
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        match received {
+            Ok((received, source)) => discovery.receive(&buf[..received], source),
+            // ICMP port unreachable errors from targets without a server are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(discovery.into_servers())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{spawn_stub, BDS_PONG};
+
+    #[tokio::test]
+    async fn test_ping() {
+        let addr = spawn_stub(BDS_PONG);
+
+        let ping = super::ping(&addr.to_string()).await.unwrap();
+        assert_eq!(ping.server_guid, 0xb7f06c1a2d840231);
+        assert!(ping.server_id.starts_with("MCPE;Dedicated Server;"));
+    }
+
+    #[tokio::test]
+    async fn test_ping_timeout() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let client = super::BedrockClient::new_with_socket_address(
+            "127.0.0.1",
+            addr.port(),
+            "127.0.0.1:0",
+            Some(std::time::Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+        let err = client.ping().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_discover_lan_to() {
+        let addr = spawn_stub(BDS_PONG);
+        let servers = super::discover_lan_to(&[addr, addr], std::time::Duration::from_millis(200))
+            .await
+            .unwrap();
+
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].0, addr);
+        assert_eq!(servers[0].1.maxplayers, 10);
+    }
+}
@@ -0,0 +1,389 @@
+//! The `check` command, a monitoring probe following the conventions of
+//! Nagios plugins.
+//!
+//! The status is printed on a single line, followed by performance data, and
+//! the exit code gives the state of the server: 0 for OK, 1 for WARNING, 2 for
+//! CRITICAL and 3 for UNKNOWN. A server which does not answer is CRITICAL,
+//! while a probe which could not run, because of a DNS or usage error, is
+//! UNKNOWN.
+
+use std::{
+    fmt,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use minecraft_server_query::{blocking::QueryClient, FullStat, DEFAULT_PORT, DEFAULT_TIMEOUT};
+
+/// State of a checked server, ordered by severity
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum State {
+    Ok,
+    Warning,
+    Critical,
+    Unknown,
+}
+
+impl State {
+    /// Exit code of the state.
+    pub fn exit_code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "OK",
+            Self::Warning => "WARNING",
+            Self::Critical => "CRITICAL",
+            Self::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+/// Threshold on the number of players, reached at or above its value
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Threshold {
+    /// Number of players
+    Absolute(u32),
+    /// Percentage of the max number of players
+    Percent(u32),
+}
+
+impl Threshold {
+    /// Parse a threshold such as `45` or `90%`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid threshold `{s}`");
+        match s.strip_suffix('%') {
+            Some(percent) => percent.parse().map(Self::Percent).map_err(|_| invalid()),
+            None => s.parse().map(Self::Absolute).map_err(|_| invalid()),
+        }
+    }
+
+    /// The number of players of the threshold, rounding percentages up.
+    pub fn players(self, max_players: u32) -> u32 {
+        match self {
+            Self::Absolute(players) => players,
+            Self::Percent(percent) => {
+                (u64::from(max_players) * u64::from(percent)).div_ceil(100) as u32
+            }
+        }
+    }
+}
+
+/// Options of the `check` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckOptions {
+    pub warn_players: Option<Threshold>,
+    pub crit_players: Option<Threshold>,
+    pub warn_latency: Option<Duration>,
+    pub crit_latency: Option<Duration>,
+    pub timeout: Duration,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            warn_players: None,
+            crit_players: None,
+            warn_latency: None,
+            crit_latency: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// Result of a check, printed as a status line with performance data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub state: State,
+    pub message: String,
+    pub perfdata: Option<String>,
+}
+
+impl Report {
+    fn failed(state: State, message: impl fmt::Display) -> Self {
+        Self {
+            state,
+            message: message.to_string(),
+            perfdata: None,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QUERY {} - {}", self.state, self.message)?;
+        if let Some(perfdata) = &self.perfdata {
+            write!(f, " | {perfdata}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A performance data value, formatted as `label=value[uom];[warn];[crit];[min];[max]`
+/// without the trailing empty fields.
+fn perfdata(label: &str, value: String, thresholds: [Option<String>; 4]) -> String {
+    let mut fields = vec![value];
+    fields.extend(thresholds.map(Option::unwrap_or_default));
+    while fields.last().is_some_and(String::is_empty) {
+        fields.pop();
+    }
+    format!("{label}={}", fields.join(";"))
+}
+
+/// Evaluate the status of a server, received after `latency`.
+pub fn evaluate(stat: &FullStat, latency: Duration, options: &CheckOptions) -> Report {
+    let players = stat.numplayers;
+    let max = stat.maxplayers;
+    let warn_players = options.warn_players.map(|t| t.players(max));
+    let crit_players = options.crit_players.map(|t| t.players(max));
+    let latency_ms = latency.as_millis();
+    let warn_latency = options.warn_latency.map(|t| t.as_millis());
+    let crit_latency = options.crit_latency.map(|t| t.as_millis());
+
+    let mut state = State::Ok;
+    let mut reasons = Vec::new();
+    if crit_players.is_some_and(|t| players >= t) {
+        state = state.max(State::Critical);
+        reasons.push("too many players");
+    } else if warn_players.is_some_and(|t| players >= t) {
+        state = state.max(State::Warning);
+        reasons.push("many players");
+    }
+    if crit_latency.is_some_and(|t| latency_ms >= t) {
+        state = state.max(State::Critical);
+        reasons.push("latency too high");
+    } else if warn_latency.is_some_and(|t| latency_ms >= t) {
+        state = state.max(State::Warning);
+        reasons.push("high latency");
+    }
+
+    let mut message = format!("{players}/{max} players, {latency_ms}ms");
+    if !reasons.is_empty() {
+        message = format!("{message} ({})", reasons.join(", "));
+    }
+    let players = perfdata(
+        "players",
+        players.to_string(),
+        [
+            warn_players.map(|t| t.to_string()),
+            crit_players.map(|t| t.to_string()),
+            Some("0".into()),
+            Some(max.to_string()),
+        ],
+    );
+    let latency = perfdata(
+        "latency",
+        format!("{latency_ms}ms"),
+        [
+            warn_latency.map(|t| t.to_string()),
+            crit_latency.map(|t| t.to_string()),
+            None,
+            None,
+        ],
+    );
+
+    Report {
+        state,
+        message,
+        perfdata: Some(format!("{players} {latency}")),
+    }
+}
+
+/// Check the server at a `host[:port]` target.
+pub fn run(target: &str, options: &CheckOptions) -> Report {
+    let (host, port) = match target.split_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host, port),
+            Err(_) => return Report::failed(State::Unknown, format!("invalid port in `{target}`")),
+        },
+        None => (target, DEFAULT_PORT),
+    };
+    // Resolution errors happen when connecting
+    let client = match QueryClient::new_with_socket_address(
+        host,
+        port,
+        (Ipv4Addr::UNSPECIFIED, 0),
+        Some(options.timeout),
+    ) {
+        Ok(client) => client,
+        Err(e) => return Report::failed(State::Unknown, e),
+    };
+
+    let start = Instant::now();
+    match client.handshake().and_then(|token| client.full_stat(token)) {
+        Ok(stat) => evaluate(&stat, start.elapsed(), options),
+        Err(e) => Report::failed(State::Critical, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_threshold() {
+        assert_eq!(Threshold::parse("45"), Ok(Threshold::Absolute(45)));
+        assert_eq!(Threshold::parse("90%"), Ok(Threshold::Percent(90)));
+        assert_eq!(
+            Threshold::parse("90 %"),
+            Err("invalid threshold `90 %`".into())
+        );
+        assert!(Threshold::parse("-1").is_err());
+        assert!(Threshold::parse("%").is_err());
+
+        assert_eq!(Threshold::Percent(90).players(50), 45);
+        assert_eq!(Threshold::Percent(90).players(20), 18);
+        assert_eq!(Threshold::Percent(90).players(15), 14);
+        assert_eq!(Threshold::Percent(100).players(50), 50);
+        assert_eq!(Threshold::Absolute(12).players(50), 12);
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let stat = |numplayers| FullStat {
+            hostname: "A Minecraft Server".into(),
+            gametype: "SMP".into(),
+            game_id: "MINECRAFT".into(),
+            version: "1.20.1".into(),
+            plugins: "".into(),
+            map: "world".into(),
+            numplayers,
+            maxplayers: 50,
+            hostport: DEFAULT_PORT,
+            hostip: "127.0.0.1".into(),
+            player_list: Vec::new(),
+        };
+        let options = CheckOptions {
+            warn_players: Some(Threshold::Percent(90)),
+            crit_players: Some(Threshold::Percent(100)),
+            crit_latency: Some(Duration::from_millis(500)),
+            ..CheckOptions::default()
+        };
+        let ms = Duration::from_millis;
+
+        let report = evaluate(&stat(12), ms(23), &options);
+        assert_eq!(report.state, State::Ok);
+        assert_eq!(
+            report.to_string(),
+            "QUERY OK - 12/50 players, 23ms | players=12;45;50;0;50 latency=23ms;;500"
+        );
+
+        let report = evaluate(&stat(45), ms(23), &options);
+        assert_eq!(report.state, State::Warning);
+        assert_eq!(
+            report.to_string(),
+            "QUERY WARNING - 45/50 players, 23ms (many players) | players=45;45;50;0;50 latency=23ms;;500"
+        );
+
+        let report = evaluate(&stat(50), ms(23), &options);
+        assert_eq!(report.state, State::Critical);
+        assert_eq!(report.message, "50/50 players, 23ms (too many players)");
+
+        let report = evaluate(&stat(46), ms(612), &options);
+        assert_eq!(report.state, State::Critical);
+        assert_eq!(
+            report.to_string(),
+            "QUERY CRITICAL - 46/50 players, 612ms (many players, latency too high) | players=46;45;50;0;50 latency=612ms;;500"
+        );
+
+        // Without thresholds
+        let report = evaluate(&stat(3), ms(8), &CheckOptions::default());
+        assert_eq!(report.state, State::Ok);
+        assert_eq!(
+            report.perfdata.as_deref(),
+            Some("players=3;;;0;50 latency=8ms")
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        let report = run("does-not-exist.invalid", &CheckOptions::default());
+        assert_eq!(report.state, State::Unknown);
+        assert_eq!(report.perfdata, None);
+
+        let report = run("127.0.0.1:port", &CheckOptions::default());
+        assert_eq!(
+            report.to_string(),
+            "QUERY UNKNOWN - invalid port in `127.0.0.1:port`"
+        );
+        assert_eq!(report.state.exit_code(), 3);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_check_mock_server() {
+        use minecraft_server_query::{
+            packets::PacketType,
+            testing::{sample_stat, Faults, MockQueryServer},
+        };
+
+        let server = MockQueryServer::with_stat(FullStat {
+            numplayers: 18,
+            maxplayers: 20,
+            ..sample_stat()
+        })
+        .unwrap();
+        let target = server.addr().to_string();
+        let mut options = CheckOptions {
+            crit_latency: Some(Duration::from_secs(1)),
+            timeout: Duration::from_millis(300),
+            ..CheckOptions::default()
+        };
+
+        let report = run(&target, &options);
+        assert_eq!(report.state.exit_code(), 0);
+        let perfdata = report.perfdata.unwrap();
+        assert!(
+            perfdata.starts_with("players=18;;;0;20 latency="),
+            "{perfdata}"
+        );
+        assert!(perfdata.ends_with("ms;;1000"), "{perfdata}");
+
+        options.warn_players = Some(Threshold::Percent(90));
+        options.crit_players = Some(Threshold::Absolute(19));
+        let report = run(&target, &options);
+        assert_eq!(report.state.exit_code(), 1);
+        assert!(report
+            .perfdata
+            .unwrap()
+            .starts_with("players=18;18;19;0;20 "));
+
+        options.crit_players = Some(Threshold::Absolute(18));
+        assert_eq!(run(&target, &options).state.exit_code(), 2);
+
+        // Slow server
+        options.warn_players = None;
+        options.crit_players = None;
+        options.warn_latency = Some(Duration::from_millis(50));
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                delay: Some(Duration::from_millis(100)),
+                ..Faults::default()
+            },
+        );
+        let report = run(&target, &options);
+        assert_eq!(report.state.exit_code(), 1);
+        assert!(
+            report.message.ends_with("(high latency)"),
+            "{}",
+            report.message
+        );
+
+        // Down server
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let report = run(&target, &options);
+        assert_eq!(report.state.exit_code(), 2);
+        assert_eq!(report.perfdata, None);
+        assert!(report.message.contains("timed out"), "{}", report.message);
+    }
+}
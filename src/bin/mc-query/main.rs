@@ -0,0 +1,316 @@
+//! Command line client of the Query protocol.
+//!
+//! Built with the `cli` feature:
+//!
+//! ```text
+//! cargo install minecraft-server-query --features cli
+//! mc-query watch play.example.com 127.0.0.1:25566 --interval 10s
+//! ```
+
+mod check;
+mod scan;
+mod watch;
+
+use std::{process::ExitCode, sync::Arc, time::Duration};
+
+use check::{CheckOptions, State, Threshold};
+use scan::{Cidr, ScanOptions};
+use watch::{Output, Stop, WatchOptions};
+
+const USAGE: &str = "\
+Usage: mc-query <command> [options]
+
+Commands:
+  watch <host[:port]>...  Poll servers and show their status as it changes
+    --interval <duration>   Time between two polls of a server [default: 10s]
+    --timeout <duration>    Timeout of the requests [default: 500ms]
+    --count <n>             Stop after polling every server n times
+    --json-lines            Print a JSON object per poll instead of a table
+  scan <range>...         Query every port of IPv4 ranges, such as 10.0.0.0/24
+    --ports <ports>         Ports and port ranges, such as 25565,25566-25570 [default: 25565]
+    --rate <rate>           Handshakes sent per second, or per minute with /m [default: 100/s]
+    --timeout <duration>    Timeout of the requests [default: 500ms]
+    --all                   Also print the targets which did not respond
+    --json                  Print a JSON object per target instead of a table
+  check <host[:port]>     Check a server, exiting with 0, 1, 2 or 3 for OK, WARNING, CRITICAL or UNKNOWN
+    --warn-players <n|n%>   Warn from a number of players, or a percentage of the max
+    --crit-players <n|n%>   Critical from a number of players, or a percentage of the max
+    --warn-latency <duration>
+                            Warn from a response time
+    --crit-latency <duration>
+                            Critical from a response time
+    --timeout <duration>    Timeout of the requests [default: 500ms]
+
+Durations are numbers of seconds, or numbers followed by ms, s, m or h.";
+
+/// A parsed command line
+#[derive(Debug, PartialEq)]
+enum Command {
+    Help,
+    Watch {
+        targets: Vec<String>,
+        options: WatchOptions,
+    },
+    Scan(ScanOptions),
+    Check {
+        target: String,
+        options: CheckOptions,
+    },
+}
+
+/// Parse a duration such as `500ms`, `10s`, `2m` or `10`, in seconds.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{s}`"))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 3600)),
+        _ => Err(format!("invalid duration unit in `{s}`")),
+    }
+}
+
+/// The value of an option, which must be the next argument.
+fn value(option: &str, args: &mut impl Iterator<Item = String>) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("missing value for `{option}`"))
+}
+
+fn parse_watch(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut targets = Vec::new();
+    let mut options = WatchOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--interval" => options.interval = parse_duration(&value(&arg, &mut args)?)?,
+            "--timeout" => options.timeout = parse_duration(&value(&arg, &mut args)?)?,
+            "--count" => {
+                let count = value(&arg, &mut args)?;
+                options.count = Some(
+                    count
+                        .parse()
+                        .map_err(|_| format!("invalid count `{count}`"))?,
+                );
+            }
+            "--json-lines" => options.output = Output::JsonLines,
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option `{option}`")),
+            _ => targets.push(arg),
+        }
+    }
+    if targets.is_empty() {
+        return Err("no server to watch".into());
+    }
+    Ok(Command::Watch { targets, options })
+}
+
+fn parse_scan(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = ScanOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ports" => options.ports = scan::parse_ports(&value(&arg, &mut args)?)?,
+            "--rate" => options.interval = scan::parse_rate(&value(&arg, &mut args)?)?,
+            "--timeout" => options.timeout = parse_duration(&value(&arg, &mut args)?)?,
+            "--all" => options.all = true,
+            "--json" | "--json-lines" => options.output = Output::JsonLines,
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option `{option}`")),
+            _ => options.ranges.push(Cidr::parse(&arg)?),
+        }
+    }
+    if options.ranges.is_empty() {
+        return Err("no range to scan".into());
+    }
+    Ok(Command::Scan(options))
+}
+
+fn parse_check(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut target = None;
+    let mut options = CheckOptions::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--warn-players" => {
+                options.warn_players = Some(Threshold::parse(&value(&arg, &mut args)?)?)
+            }
+            "--crit-players" => {
+                options.crit_players = Some(Threshold::parse(&value(&arg, &mut args)?)?)
+            }
+            "--warn-latency" => {
+                options.warn_latency = Some(parse_duration(&value(&arg, &mut args)?)?)
+            }
+            "--crit-latency" => {
+                options.crit_latency = Some(parse_duration(&value(&arg, &mut args)?)?)
+            }
+            "--timeout" => options.timeout = parse_duration(&value(&arg, &mut args)?)?,
+            "-h" | "--help" => return Ok(Command::Help),
+            option if option.starts_with('-') => return Err(format!("unknown option `{option}`")),
+            _ if target.is_some() => return Err("only one server can be checked".into()),
+            _ => target = Some(arg),
+        }
+    }
+    let target = target.ok_or("no server to check")?;
+    Ok(Command::Check { target, options })
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    match args.next().as_deref() {
+        Some("watch") => parse_watch(args),
+        Some("scan") => parse_scan(args),
+        Some("check") => parse_check(args),
+        None | Some("-h" | "--help" | "help") => Ok(Command::Help),
+        Some(command) => Err(format!("unknown command `{command}`")),
+    }
+}
+
+/// Run a command until it is done, or stopped by Ctrl-C.
+fn with_stop(command: impl FnOnce(&Stop) -> std::io::Result<()>) -> std::io::Result<()> {
+    let stop = Arc::new(Stop::default());
+    let handler = Arc::clone(&stop);
+    ctrlc::set_handler(move || handler.stop()).map_err(std::io::Error::other)?;
+    command(&stop)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse_args(args.iter().cloned()) {
+        Ok(command) => command,
+        // Monitoring systems expect the UNKNOWN state for usage errors
+        Err(e) if args.first().is_some_and(|command| command == "check") => {
+            println!("QUERY {} - {e}", State::Unknown);
+            return ExitCode::from(State::Unknown.exit_code());
+        }
+        Err(e) => {
+            eprintln!("error: {e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    let res = match command {
+        Command::Help => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        Command::Watch { targets, options } => {
+            with_stop(|stop| watch::run(&targets, &options, stop, std::io::stdout().lock()))
+        }
+        Command::Scan(options) => with_stop(|stop| {
+            let totals = scan::run(&options, stop, std::io::stdout().lock())?;
+            // Keep the JSON output machine-readable
+            match options.output {
+                Output::Table => println!("{totals}"),
+                Output::JsonLines => eprintln!("{totals}"),
+            }
+            Ok(())
+        }),
+        Command::Check { target, options } => {
+            let report = check::run(&target, &options);
+            println!("{report}");
+            return ExitCode::from(report.state.exit_code());
+        }
+    };
+
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use minecraft_server_query::DEFAULT_TIMEOUT;
+
+    use super::*;
+
+    fn parse(args: &str) -> Result<Command, String> {
+        parse_args(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("10s"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert!(parse_duration("10 s").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("1.5s").is_err());
+    }
+
+    #[test]
+    fn test_parse_watch() {
+        assert_eq!(
+            parse("watch host1 host2:25566 --interval 2s --json-lines --count 3"),
+            Ok(Command::Watch {
+                targets: vec!["host1".into(), "host2:25566".into()],
+                options: WatchOptions {
+                    interval: Duration::from_secs(2),
+                    timeout: DEFAULT_TIMEOUT,
+                    count: Some(3),
+                    output: Output::JsonLines,
+                },
+            })
+        );
+        assert_eq!(parse(""), Ok(Command::Help));
+        assert_eq!(parse("watch host --help"), Ok(Command::Help));
+        assert!(parse("watch").is_err());
+        assert!(parse("watch host --interval").is_err());
+        assert!(parse("watch host --verbose").is_err());
+        assert!(parse("ping host").is_err());
+    }
+
+    #[test]
+    fn test_parse_scan() {
+        assert_eq!(
+            parse("scan 10.0.0.0/24 --ports 25565,25566-25570 --rate 500/s --timeout 800ms --json"),
+            Ok(Command::Scan(ScanOptions {
+                ranges: vec![Cidr::parse("10.0.0.0/24").unwrap()],
+                ports: vec![25565, 25566, 25567, 25568, 25569, 25570],
+                interval: Duration::from_millis(2),
+                timeout: Duration::from_millis(800),
+                all: false,
+                output: Output::JsonLines,
+            }))
+        );
+        assert_eq!(
+            parse("scan 10.0.0.0/24 --ports 25570-25566"),
+            Err("inverted port range `25570-25566`".into())
+        );
+        assert_eq!(
+            parse("scan 10.0.0.0/40"),
+            Err("invalid prefix length in range `10.0.0.0/40`, expected 0 to 32".into())
+        );
+        assert!(parse("scan --all").is_err());
+    }
+
+    #[test]
+    fn test_parse_check() {
+        assert_eq!(
+            parse("check play.example.com --warn-players 90% --crit-players 50 --crit-latency 500ms --timeout 2s"),
+            Ok(Command::Check {
+                target: "play.example.com".into(),
+                options: CheckOptions {
+                    warn_players: Some(Threshold::Percent(90)),
+                    crit_players: Some(Threshold::Absolute(50)),
+                    warn_latency: None,
+                    crit_latency: Some(Duration::from_millis(500)),
+                    timeout: Duration::from_secs(2),
+                },
+            })
+        );
+        assert_eq!(
+            parse("check host --warn-players 90%%"),
+            Err("invalid threshold `90%%`".into())
+        );
+        assert_eq!(
+            parse("check host1 host2"),
+            Err("only one server can be checked".into())
+        );
+        assert!(parse("check").is_err());
+    }
+}
@@ -0,0 +1,516 @@
+//! The `scan` command, querying every port of IPv4 ranges.
+//!
+//! Every request is sent from a single unconnected socket, and responses are
+//! matched to their target by their source address. Handshakes are sent at a
+//! fixed rate, and answered handshakes are followed by a full stat request.
+
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::{Duration, Instant, SystemTime},
+};
+
+use minecraft_server_query::{
+    blocking::CANCEL_LATENCY,
+    jsonl::StatLogger,
+    motd,
+    packets::{self, PacketType, QueryPacket, ResponseHeader},
+    FullStat, Token, DEFAULT_PORT, DEFAULT_TIMEOUT,
+};
+
+use crate::watch::{Output, Stop};
+
+/// Session ID of the scan requests
+const SESSION_ID: u32 = 0x0D0A_0C03;
+
+/// A range of IPv4 addresses, in CIDR notation
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cidr {
+    base: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Parse a range such as `10.0.0.0/24`, or a single address. Host bits
+    /// of the base address are ignored.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (base, prefix) = s.split_once('/').unwrap_or((s, "32"));
+        let base: Ipv4Addr = base.parse().map_err(|_| {
+            if base.contains(':') {
+                format!("only IPv4 ranges can be scanned, not `{s}`")
+            } else {
+                format!("invalid address in range `{s}`")
+            }
+        })?;
+        let prefix = prefix
+            .parse()
+            .ok()
+            .filter(|&prefix| prefix <= 32)
+            .ok_or_else(|| format!("invalid prefix length in range `{s}`, expected 0 to 32"))?;
+        Ok(Self { base, prefix })
+    }
+
+    /// Number of addresses in the range.
+    pub fn len(&self) -> u64 {
+        1 << (32 - self.prefix)
+    }
+
+    /// Every address of the range, in increasing order.
+    pub fn addresses(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first =
+            u32::from(self.base) & (u32::MAX.checked_shl(32 - self.prefix as u32)).unwrap_or(0);
+        (0..self.len()).map(move |i| Ipv4Addr::from(first + i as u32))
+    }
+}
+
+/// Parse a list of ports and port ranges, such as `25565,25566-25570`.
+pub fn parse_ports(s: &str) -> Result<Vec<u16>, String> {
+    let port = |p: &str| {
+        p.parse::<u16>()
+            .ok()
+            .filter(|&p| p != 0)
+            .ok_or_else(|| format!("invalid port `{p}`"))
+    };
+    let mut ports = Vec::new();
+    for spec in s.split(',') {
+        match spec.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (port(first)?, port(last)?);
+                if first > last {
+                    return Err(format!("inverted port range `{spec}`"));
+                }
+                ports.extend(first..=last);
+            }
+            None => ports.push(port(spec)?),
+        }
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+/// Parse a rate of requests such as `500/s`, `600/m` or `500`, per second.
+/// Returns the interval between two requests.
+pub fn parse_rate(s: &str) -> Result<Duration, String> {
+    let (count, per) = match s.split_once('/') {
+        Some((count, "s")) => (count, Duration::from_secs(1)),
+        Some((count, "m")) => (count, Duration::from_secs(60)),
+        Some(_) => return Err(format!("invalid rate unit in `{s}`, expected /s or /m")),
+        None => (s, Duration::from_secs(1)),
+    };
+    match count.parse::<u32>() {
+        Ok(count) if count > 0 => Ok(per / count),
+        _ => Err(format!("invalid rate `{s}`, expected a positive number")),
+    }
+}
+
+/// Options of the `scan` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub ranges: Vec<Cidr>,
+    pub ports: Vec<u16>,
+    /// Time between two handshakes
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Whether to print the targets which did not respond
+    pub all: bool,
+    pub output: Output,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            ranges: Vec::new(),
+            ports: vec![DEFAULT_PORT],
+            interval: Duration::from_millis(10),
+            timeout: DEFAULT_TIMEOUT,
+            all: false,
+            output: Output::Table,
+        }
+    }
+}
+
+/// Counts of a scan, printed at its end
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// Targets sent a handshake
+    pub scanned: u64,
+    /// Targets which answered with their full status
+    pub responded: u64,
+    pub elapsed: Duration,
+}
+
+impl std::fmt::Display for Totals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scanned {} targets in {:.1}s: {} responded, {} did not",
+            self.scanned,
+            self.elapsed.as_secs_f64(),
+            self.responded,
+            self.scanned - self.responded
+        )
+    }
+}
+
+/// Request stage of a target
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Handshake,
+    Stat,
+}
+
+/// A target waiting for a response
+#[derive(Debug)]
+struct Pending {
+    stage: Stage,
+    started: Instant,
+    deadline: Instant,
+}
+
+/// Result of the scan of a target
+#[derive(Debug)]
+struct Found {
+    addr: SocketAddr,
+    result: io::Result<FullStat>,
+    latency: Duration,
+}
+
+/// Handle a datagram received from a pending target. Returns the result of
+/// the target if it is done.
+fn receive(
+    socket: &UdpSocket,
+    pending: &mut HashMap<SocketAddr, Pending>,
+    from: SocketAddr,
+    datagram: &[u8],
+    options: &ScanOptions,
+) -> Option<Found> {
+    let (Some(target), Some((header, payload))) =
+        (pending.get_mut(&from), ResponseHeader::parse(datagram))
+    else {
+        return None;
+    };
+    if header.session_id != SESSION_ID {
+        return None;
+    }
+    let now = Instant::now();
+    let result = match (target.stage, header.packet_type) {
+        (Stage::Handshake, PacketType::Handshake) => {
+            let request = Token::try_from_payload(payload).and_then(|token| {
+                socket.send_to(packets::FullStat::new(SESSION_ID, token.0).as_bytes(), from)
+            });
+            match request {
+                Ok(_) => {
+                    target.stage = Stage::Stat;
+                    target.deadline = now + options.timeout;
+                    return None;
+                }
+                Err(e) => Err(e),
+            }
+        }
+        (Stage::Stat, PacketType::Stat) => FullStat::from_payload(payload),
+        _ => return None,
+    };
+    let started = pending.remove(&from).map_or(now, |target| target.started);
+    Some(Found {
+        addr: from,
+        result,
+        latency: now - started,
+    })
+}
+
+/// Scan every target, calling `found` with their results as they arrive,
+/// until every target is done or the scan is stopped.
+fn scan(
+    options: &ScanOptions,
+    stop: &Stop,
+    mut found: impl FnMut(Found) -> io::Result<()>,
+) -> io::Result<Totals> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    let mut targets = options.ranges.iter().flat_map(|range| {
+        range.addresses().flat_map(|ip| {
+            options
+                .ports
+                .iter()
+                .map(move |&port| SocketAddr::V4(SocketAddrV4::new(ip, port)))
+        })
+    });
+    let start = Instant::now();
+    let mut totals = Totals::default();
+    let mut pending: HashMap<SocketAddr, Pending> = HashMap::new();
+    let mut next_send = Some(start);
+    let mut buf = vec![0; u16::MAX as usize];
+
+    while !stop.is_stopped() {
+        let now = Instant::now();
+        while let Some(send) = next_send.filter(|&send| send <= now) {
+            let Some(addr) = targets.next() else {
+                next_send = None;
+                break;
+            };
+            totals.scanned += 1;
+            next_send = Some(send + options.interval);
+            match socket.send_to(packets::Handshake::new(SESSION_ID).as_bytes(), addr) {
+                Ok(_) => {
+                    pending.insert(
+                        addr,
+                        Pending {
+                            stage: Stage::Handshake,
+                            started: now,
+                            deadline: now + options.timeout,
+                        },
+                    );
+                }
+                Err(e) => found(Found {
+                    addr,
+                    result: Err(e),
+                    latency: Duration::ZERO,
+                })?,
+            }
+        }
+
+        let expired: Vec<SocketAddr> = pending
+            .iter()
+            .filter(|(_, target)| target.deadline <= now)
+            .map(|(&addr, _)| addr)
+            .collect();
+        for addr in expired {
+            pending.remove(&addr);
+            found(Found {
+                addr,
+                result: Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+                latency: options.timeout,
+            })?;
+        }
+        if next_send.is_none() && pending.is_empty() {
+            break;
+        }
+
+        let wakeup = pending
+            .values()
+            .map(|target| target.deadline)
+            .chain(next_send)
+            .min()
+            .unwrap_or(now);
+        // Wake up regularly to check whether the scan was stopped
+        let wait = wakeup.saturating_duration_since(now).min(CANCEL_LATENCY);
+        // A zero timeout is rejected by the socket
+        socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                if let Some(res) = receive(&socket, &mut pending, from, &buf[..len], options) {
+                    totals.responded += u64::from(res.result.is_ok());
+                    found(res)?;
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            // Errors of previous datagrams reported by the system, such as
+            // unreachable ports on some platforms
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+            Err(e) => return Err(e),
+        }
+    }
+    totals.elapsed = start.elapsed();
+    Ok(totals)
+}
+
+/// A line of the results table. Columns have fixed widths, so that lines are
+/// printed as soon as their target is done.
+fn table_line(cells: [&str; 7]) -> String {
+    let [addr, motd, players, max, version, latency, error] = cells;
+    let line = format!(
+        "{addr:<21}  {motd:<32}  {players:>7}  {max:>5}  {version:<16}  {latency:>7}  {error}"
+    );
+    line.trim_end().to_string()
+}
+
+/// Line of the results table of a target.
+fn found_line(found: &Found) -> String {
+    let addr = found.addr.to_string();
+    match &found.result {
+        Ok(stat) => {
+            let motd: String = motd::strip_codes(&stat.hostname)
+                .replace(['\n', '\r'], " ")
+                .chars()
+                .take(32)
+                .collect();
+            table_line([
+                &addr,
+                &motd,
+                &stat.numplayers.to_string(),
+                &stat.maxplayers.to_string(),
+                &stat.version,
+                &format!("{}ms", found.latency.as_millis()),
+                "",
+            ])
+        }
+        Err(e) => table_line([&addr, "-", "-", "-", "-", "-", &e.to_string()]),
+    }
+}
+
+/// Scan the ranges, printing the results to `out` as they arrive. Only the
+/// targets which responded are printed, unless `all` is set.
+pub fn run(options: &ScanOptions, stop: &Stop, mut out: impl Write) -> io::Result<Totals> {
+    match options.output {
+        Output::JsonLines => {
+            let mut logger = StatLogger::new(out);
+            scan(options, stop, |found| {
+                if options.all || found.result.is_ok() {
+                    let target = found.addr.to_string();
+                    logger.log_at(&target, &found.result, SystemTime::now())?;
+                }
+                Ok(())
+            })
+        }
+        Output::Table => {
+            writeln!(
+                out,
+                "{}",
+                table_line(["ADDRESS", "MOTD", "PLAYERS", "MAX", "VERSION", "LATENCY", "ERROR"])
+            )?;
+            scan(options, stop, |found| {
+                if options.all || found.result.is_ok() {
+                    writeln!(out, "{}", found_line(&found))?;
+                    out.flush()?;
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_specs() {
+        let range = Cidr::parse("10.0.0.7/30").unwrap();
+        assert_eq!(range.len(), 4);
+        assert_eq!(
+            range.addresses().collect::<Vec<_>>(),
+            [
+                Ipv4Addr::new(10, 0, 0, 4),
+                Ipv4Addr::new(10, 0, 0, 5),
+                Ipv4Addr::new(10, 0, 0, 6),
+                Ipv4Addr::new(10, 0, 0, 7),
+            ]
+        );
+        assert_eq!(Cidr::parse("127.0.0.1").unwrap().len(), 1);
+        assert_eq!(Cidr::parse("0.0.0.0/0").unwrap().len(), 1 << 32);
+        assert_eq!(
+            Cidr::parse("10.0.0.0/33"),
+            Err("invalid prefix length in range `10.0.0.0/33`, expected 0 to 32".into())
+        );
+        assert_eq!(
+            Cidr::parse("10.0.0/24"),
+            Err("invalid address in range `10.0.0/24`".into())
+        );
+        assert_eq!(
+            Cidr::parse("::1/128"),
+            Err("only IPv4 ranges can be scanned, not `::1/128`".into())
+        );
+
+        assert_eq!(
+            parse_ports("25570,25565-25567,25566"),
+            Ok(vec![25565, 25566, 25567, 25570])
+        );
+        assert_eq!(
+            parse_ports("25570-25566"),
+            Err("inverted port range `25570-25566`".into())
+        );
+        assert_eq!(parse_ports("25565,"), Err("invalid port ``".into()));
+        assert_eq!(parse_ports("0"), Err("invalid port `0`".into()));
+        assert_eq!(parse_ports("65536"), Err("invalid port `65536`".into()));
+
+        assert_eq!(parse_rate("500/s"), Ok(Duration::from_millis(2)));
+        assert_eq!(parse_rate("4"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_rate("600/m"), Ok(Duration::from_millis(100)));
+        assert!(parse_rate("0/s").is_err());
+        assert!(parse_rate("10/h").is_err());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_scan_loopback() {
+        use minecraft_server_query::{
+            jsonl::{Outcome, Record},
+            testing::{sample_stat, MockQueryServer},
+        };
+
+        let servers: Vec<MockQueryServer> = (0..3)
+            .map(|i| {
+                let stat = FullStat {
+                    numplayers: i,
+                    ..sample_stat()
+                };
+                MockQueryServer::with_stat(stat).unwrap()
+            })
+            .collect();
+        // Nothing answers on this port
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut ports: Vec<u16> = servers.iter().map(|s| s.addr().port()).collect();
+        ports.push(silent.local_addr().unwrap().port());
+
+        let mut options = ScanOptions {
+            ranges: vec![Cidr::parse("127.0.0.1/32").unwrap()],
+            ports: ports.clone(),
+            interval: Duration::from_millis(1),
+            timeout: Duration::from_millis(200),
+            all: false,
+            output: Output::JsonLines,
+        };
+        let mut out = Vec::new();
+        let totals = run(&options, &Stop::default(), &mut out).unwrap();
+        assert_eq!((totals.scanned, totals.responded), (4, 3));
+
+        let mut records: Vec<Record> = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        records.sort_by_key(|record| record.target.clone());
+        let mut expected: Vec<(String, Outcome)> = servers
+            .iter()
+            .enumerate()
+            .map(|(i, server)| {
+                let stat = FullStat {
+                    numplayers: i as u32,
+                    ..sample_stat()
+                };
+                (server.addr().to_string(), Outcome::Stat(stat))
+            })
+            .collect();
+        expected.sort_by_key(|(target, _)| target.clone());
+        assert_eq!(
+            records
+                .into_iter()
+                .map(|r| (r.target, r.outcome))
+                .collect::<Vec<_>>(),
+            expected
+        );
+
+        // With every target, in a table
+        options.all = true;
+        options.output = Output::Table;
+        let mut out = Vec::new();
+        let totals = run(&options, &Stop::default(), &mut out).unwrap();
+        assert_eq!((totals.scanned, totals.responded), (4, 3));
+        let table = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].starts_with("ADDRESS                MOTD"));
+        let silent_line = format!("127.0.0.1:{}", ports[3]);
+        assert!(lines[4].starts_with(&silent_line));
+        assert!(lines[4].ends_with("-  timed out"));
+        for line in &lines[1..4] {
+            assert!(line.contains("  A Minecraft Server  "), "{line}");
+        }
+        assert!(totals.to_string().ends_with(": 3 responded, 1 did not"));
+    }
+}
@@ -0,0 +1,469 @@
+//! The `watch` command, polling servers periodically.
+//!
+//! Every server is polled by its own thread, which sends the results to the
+//! main thread, printing them either as a table redrawn in place or as JSON
+//! Lines.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    net::Ipv4Addr,
+    sync::{mpsc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use minecraft_server_query::{
+    blocking::{CancelHandle, QueryClient},
+    jsonl::StatLogger,
+    motd, FullStat, DEFAULT_PORT, DEFAULT_TIMEOUT,
+};
+
+/// Maximum number of characters of the MOTD column
+const MOTD_WIDTH: usize = 32;
+
+/// How the polls are printed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Output {
+    /// A table of the last status of every server, redrawn after every poll
+    Table,
+    /// A JSON object per poll, see [`StatLogger`]
+    JsonLines,
+}
+
+/// Options of the `watch` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchOptions {
+    pub interval: Duration,
+    pub timeout: Duration,
+    /// Number of polls of every server, unlimited if `None`
+    pub count: Option<u32>,
+    pub output: Output,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: DEFAULT_TIMEOUT,
+            count: None,
+            output: Output::Table,
+        }
+    }
+}
+
+/// Stop signal of the watchers, cancelling their in-flight requests.
+#[derive(Debug, Default)]
+pub struct Stop {
+    stopped: Mutex<bool>,
+    wake: Condvar,
+    clients: Mutex<Vec<CancelHandle>>,
+}
+
+impl Stop {
+    /// Stop the watchers, waking them up if they are waiting for their next poll.
+    pub fn stop(&self) {
+        *self.stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.wake.notify_all();
+        let clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        clients.iter().for_each(CancelHandle::cancel);
+    }
+
+    /// Whether the watchers were stopped.
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Cancel the client when the watchers are stopped.
+    fn register(&self, client: &QueryClient) {
+        let handle = client.cancel_handle();
+        let mut clients = self.clients.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.is_stopped() {
+            handle.cancel();
+        }
+        clients.push(handle);
+    }
+
+    /// Wait until the deadline, returning `true` if the watchers were stopped.
+    fn sleep_until(&self, deadline: Instant) -> bool {
+        let mut stopped = self.stopped.lock().unwrap_or_else(PoisonError::into_inner);
+        while !*stopped {
+            let Some(timeout) = deadline.checked_duration_since(Instant::now()) else {
+                break;
+            };
+            stopped = self
+                .wake
+                .wait_timeout(stopped, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        *stopped
+    }
+}
+
+/// Result of a poll of a server
+#[derive(Debug)]
+struct Poll {
+    /// Index of the server in the targets
+    server: usize,
+    result: io::Result<FullStat>,
+    latency: Duration,
+    at: SystemTime,
+}
+
+/// Connect a client to a `host[:port]` target.
+fn connect(target: &str, timeout: Duration) -> io::Result<QueryClient> {
+    let (host, port) = match target.split_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .map_err(|_| io::Error::other("Invalid port in IP address"))?,
+        ),
+        None => (target, DEFAULT_PORT),
+    };
+    QueryClient::new_with_socket_address(host, port, (Ipv4Addr::UNSPECIFIED, 0), Some(timeout))
+}
+
+/// Get the full status of a server, connecting the client first if needed.
+fn poll(
+    client: &mut Option<QueryClient>,
+    target: &str,
+    options: &WatchOptions,
+    stop: &Stop,
+) -> io::Result<FullStat> {
+    let client = match client {
+        Some(client) => client,
+        None => {
+            let connected = connect(target, options.timeout)?;
+            stop.register(&connected);
+            client.insert(connected)
+        }
+    };
+    let token = client.handshake()?;
+    client.full_stat(token)
+}
+
+/// Poll a server at a fixed interval until the watchers are stopped, or
+/// until it was polled `count` times.
+fn watch_server(
+    server: usize,
+    target: &str,
+    options: &WatchOptions,
+    stop: &Stop,
+    polls: mpsc::Sender<Poll>,
+) {
+    let start = Instant::now();
+    let mut client = None;
+    for i in 1.. {
+        let at = SystemTime::now();
+        let poll_start = Instant::now();
+        let result = poll(&mut client, target, options, stop);
+        let latency = poll_start.elapsed();
+        if stop.is_stopped() {
+            break;
+        }
+        let poll = Poll {
+            server,
+            result,
+            latency,
+            at,
+        };
+        if polls.send(poll).is_err()
+            || options.count.is_some_and(|count| i >= count)
+            || stop.sleep_until(start + options.interval * i)
+        {
+            break;
+        }
+    }
+}
+
+/// Last known status of a server
+#[derive(Debug, Default)]
+struct Row {
+    stat: Option<FullStat>,
+    latency: Option<Duration>,
+    error: Option<String>,
+}
+
+/// Table of the servers, redrawn in place
+#[derive(Debug)]
+struct Table<'a> {
+    targets: &'a [String],
+    rows: Vec<Row>,
+    /// Number of lines drawn by the last redraw
+    drawn: usize,
+}
+
+impl<'a> Table<'a> {
+    fn new(targets: &'a [String]) -> Self {
+        Self {
+            targets,
+            rows: targets.iter().map(|_| Row::default()).collect(),
+            drawn: 0,
+        }
+    }
+
+    /// Update the row of the polled server. Failed polls keep the last status.
+    fn update(&mut self, poll: Poll) {
+        let row = &mut self.rows[poll.server];
+        match poll.result {
+            Ok(stat) => {
+                row.stat = Some(stat);
+                row.latency = Some(poll.latency);
+                row.error = None;
+            }
+            Err(e) => row.error = Some(e.to_string()),
+        }
+    }
+
+    /// Lines of the table, with aligned columns.
+    fn frame(&self) -> String {
+        let header = [
+            "SERVER",
+            "MOTD",
+            "PLAYERS",
+            "MAX",
+            "VERSION",
+            "LATENCY",
+            "LAST ERROR",
+        ]
+        .map(String::from);
+        let cells: Vec<[String; 7]> = std::iter::once(header)
+            .chain(self.targets.iter().zip(&self.rows).map(|(target, row)| {
+                let stat = row.stat.as_ref();
+                let motd: String = motd::strip_codes(stat.map_or("-", |s| &s.hostname))
+                    .replace(['\n', '\r'], " ")
+                    .chars()
+                    .take(MOTD_WIDTH)
+                    .collect();
+                [
+                    target.clone(),
+                    motd,
+                    stat.map_or("-".into(), |s| s.numplayers.to_string()),
+                    stat.map_or("-".into(), |s| s.maxplayers.to_string()),
+                    stat.map_or("-".into(), |s| s.version.to_string()),
+                    row.latency
+                        .map_or("-".into(), |l| format!("{}ms", l.as_millis())),
+                    row.error.clone().unwrap_or_default(),
+                ]
+            }))
+            .collect();
+
+        let mut widths = [0; 7];
+        for line in &cells {
+            for (width, cell) in widths.iter_mut().zip(line) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let mut res = String::new();
+        for line in &cells {
+            let mut text = String::new();
+            for (cell, width) in line.iter().zip(widths) {
+                let _ = write!(text, "{cell:width$}  ");
+            }
+            res.push_str(text.trim_end());
+            res.push('\n');
+        }
+        res
+    }
+
+    /// The frame, preceded by the escape codes moving the cursor up to the
+    /// start of the previous frame and clearing its lines.
+    fn redraw(&mut self) -> String {
+        let mut res = String::new();
+        if self.drawn > 0 {
+            let _ = write!(res, "\x1b[{}A", self.drawn);
+        }
+        let frame = self.frame();
+        for line in frame.lines() {
+            res.push_str("\x1b[2K");
+            res.push_str(line);
+            res.push('\n');
+        }
+        self.drawn = frame.lines().count();
+        res
+    }
+}
+
+/// Print the polls of the watchers until they are stopped or done.
+fn print(
+    targets: &[String],
+    options: &WatchOptions,
+    polls: mpsc::Receiver<Poll>,
+    mut out: impl Write,
+) -> io::Result<()> {
+    match options.output {
+        Output::JsonLines => {
+            let mut logger = StatLogger::new(out);
+            for poll in polls {
+                logger.log_at(&targets[poll.server], &poll.result, poll.at)?;
+            }
+        }
+        Output::Table => {
+            let mut table = Table::new(targets);
+            out.write_all(table.redraw().as_bytes())?;
+            out.flush()?;
+            for poll in polls {
+                table.update(poll);
+                out.write_all(table.redraw().as_bytes())?;
+                out.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Watch the servers, printing their polls to `out` until `stop` is called,
+/// or until every server was polled `count` times.
+pub fn run(
+    targets: &[String],
+    options: &WatchOptions,
+    stop: &Stop,
+    out: impl Write,
+) -> io::Result<()> {
+    let (send, polls) = mpsc::channel();
+    thread::scope(|scope| {
+        for (server, target) in targets.iter().enumerate() {
+            let send = send.clone();
+            scope.spawn(move || watch_server(server, target, options, stop, send));
+        }
+        drop(send);
+        let res = print(targets, options, polls, out);
+        // Stop the watchers if printing failed
+        stop.stop();
+        res
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    fn sample_stat() -> FullStat {
+        FullStat {
+            hostname: "§6A §lMinecraft§r Server".into(),
+            gametype: "SMP".into(),
+            game_id: "MINECRAFT".into(),
+            version: "1.20.1".into(),
+            plugins: "".into(),
+            map: "world".into(),
+            numplayers: 2,
+            maxplayers: 20,
+            hostport: 25565,
+            hostip: "127.0.0.1".into(),
+            player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+        }
+    }
+
+    #[test]
+    fn test_table_frame() {
+        let targets = ["play.example.com".into(), "127.0.0.1:25566".into()];
+        let mut table = Table::new(&targets);
+        let poll = |server, result| Poll {
+            server,
+            result,
+            latency: Duration::from_millis(23),
+            at: SystemTime::now(),
+        };
+        table.update(poll(0, Ok(sample_stat())));
+        table.update(poll(
+            1,
+            Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+        ));
+        assert_eq!(
+            table.frame(),
+            "\
+SERVER            MOTD                PLAYERS  MAX  VERSION  LATENCY  LAST ERROR
+play.example.com  A Minecraft Server  2        20   1.20.1   23ms
+127.0.0.1:25566   -                   -        -    -        -        timed out
+"
+        );
+
+        // Errors keep the last status
+        table.update(poll(
+            0,
+            Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+        ));
+        assert!(table
+            .frame()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("23ms     timed out"));
+
+        let first = table.redraw();
+        assert!(first.starts_with("\x1b[2KSERVER"));
+        let second = table.redraw();
+        assert!(second.starts_with("\x1b[3A\x1b[2KSERVER"));
+        assert_eq!(second.matches("\x1b[2K").count(), 3);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn test_json_lines() {
+        use minecraft_server_query::{
+            jsonl::{Outcome, Record},
+            testing::MockQueryServer,
+        };
+
+        let server = MockQueryServer::with_stat(sample_stat()).unwrap();
+        let targets = [server.addr().to_string(), "127.0.0.1:invalid".into()];
+        let options = WatchOptions {
+            interval: Duration::from_millis(10),
+            count: Some(2),
+            output: Output::JsonLines,
+            ..Default::default()
+        };
+        let mut out = Vec::new();
+        run(&targets, &options, &Stop::default(), &mut out).unwrap();
+
+        let records: Vec<Record> = std::str::from_utf8(&out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 4);
+        for target in &targets {
+            assert_eq!(records.iter().filter(|r| &r.target == target).count(), 2);
+        }
+        for record in records {
+            match record.outcome {
+                Outcome::Stat(stat) => {
+                    assert_eq!(record.target, targets[0]);
+                    assert_eq!(stat, sample_stat());
+                }
+                Outcome::Error(e) => {
+                    assert_eq!(record.target, targets[1]);
+                    assert_eq!(e, "Invalid port in IP address");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stop() {
+        let stop = Arc::new(Stop::default());
+        let stopper = Arc::clone(&stop);
+        // Nothing answers on this port
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let targets = [socket.local_addr().unwrap().to_string()];
+        let options = WatchOptions {
+            timeout: Duration::from_secs(60),
+            output: Output::JsonLines,
+            ..Default::default()
+        };
+
+        let start = Instant::now();
+        let watch = thread::spawn(move || {
+            let mut out = Vec::new();
+            run(&targets, &options, &stopper, &mut out).map(|()| out)
+        });
+        thread::sleep(Duration::from_millis(100));
+        stop.stop();
+        // The in-flight handshake is cancelled, and not printed
+        assert_eq!(watch.join().unwrap().unwrap(), b"");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
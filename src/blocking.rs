@@ -4,17 +4,106 @@
 
 use std::{
     io,
-    net::{Ipv4Addr, ToSocketAddrs, UdpSocket},
-    time::Duration,
+    net::{ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
 use super::*;
+use crate::advertised::{self, Advertised, FollowOptions};
+use crate::connection_string::{ConnectionString, ConnectionStringError};
+use crate::gs4::BedrockFullStat;
+use crate::packets::QueryPacket;
+use crate::quality::{ProbeOptions, Probes, QualityReport};
+use crate::rate_limit::{self, RateLimiter};
+use crate::resolve::{self, Resolve, SystemResolver};
+use crate::token_cache::TokenHandle;
+use crate::zone::HostPort;
+
+/// Max delay for an operation waiting for a response to return once its
+/// client is cancelled through a [`CancelHandle`].
+pub const CANCEL_LATENCY: Duration = Duration::from_millis(50);
+
+/// Handle cancelling the operations of a [`QueryClient`], from another thread.
+///
+/// Once cancelled, the operations of the client return an
+/// [`Interrupted`](io::ErrorKind::Interrupted) error: operations waiting for a
+/// response return within [`CANCEL_LATENCY`], and later operations right away.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::blocking::QueryClient;
+/// let client = QueryClient::new("play.example.com")?;
+/// let handle = client.cancel_handle();
+/// std::thread::spawn(move || {
+///     // On shutdown
+///     handle.cancel();
+/// });
+/// let token = client.handshake()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Cancel the in-flight and later operations of the client.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the client was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Return an error if the client was cancelled.
+    fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Operation cancelled.",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Guard restoring the read timeout of a socket, once the timeout slices of
+/// a cancellable receive are done
+struct RestoreReadTimeout<'a> {
+    socket: &'a UdpSocket,
+    timeout: Option<Duration>,
+}
+
+impl<'a> RestoreReadTimeout<'a> {
+    fn new(socket: &'a UdpSocket) -> io::Result<Self> {
+        Ok(Self {
+            timeout: socket.read_timeout()?,
+            socket,
+        })
+    }
+}
+
+impl Drop for RestoreReadTimeout<'_> {
+    fn drop(&mut self) {
+        // The timeout was accepted by the socket before
+        let _ = self.socket.set_read_timeout(self.timeout);
+    }
+}
 
 /// A blocking Query client using the [`std`] networking primitives.
 #[derive(Debug)]
 pub struct QueryClient {
     socket: UdpSocket,
     session_id: u32,
+    target: String,
+    timeout: Option<Duration>,
+    cancel: OnceLock<CancelHandle>,
+    #[cfg(feature = "histogram")]
+    latency: crate::histogram::ClientLatency,
 }
 
 impl QueryClient {
@@ -24,16 +113,7 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub fn new(ip: &str) -> io::Result<Self> {
-        let (ip, port) = if let Some((ip, port)) = ip.split_once(':') {
-            (
-                ip,
-                port.parse::<u16>().map_err(|_| {
-                    io::Error::new(io::ErrorKind::Other, "Invalid port in IP address")
-                })?,
-            )
-        } else {
-            (ip, DEFAULT_PORT)
-        };
+        let (ip, port) = split_address(ip)?;
 
         Self::new_with_port(ip, port)
     }
@@ -44,14 +124,38 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
-        if ip.contains(':') {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Invalid IP address: must not contain a port.",
-            ));
-        }
+        check_no_port(ip)?;
 
-        Self::new_with_socket_address(ip, port, (Ipv4Addr::UNSPECIFIED, 0), Some(DEFAULT_TIMEOUT))
+        Self::new_with_socket_address(ip, port, unspecified_for_host(ip), Some(DEFAULT_TIMEOUT))
+    }
+
+    /// Build a new QueryClient from the given socket address, for example from
+    /// [`query_target_from_properties`](crate::server_properties::query_target_from_properties).
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new_addr(addr: SocketAddr) -> io::Result<Self> {
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            Some(DEFAULT_TIMEOUT),
+        )
+    }
+
+    /// Build a new QueryClient connected to the address advertised by a
+    /// server in its status, see [`FollowOptions`].
+    ///
+    /// Fails with an [`InvalidData`](io::ErrorKind::InvalidData) error if the
+    /// advertised IP address can't be parsed or is unspecified, unless the
+    /// options fall back to the address the status was received from.
+    pub fn from_stat(stat: &impl Advertised, options: FollowOptions) -> io::Result<Self> {
+        let addr = advertised::follow_target(stat, &options)?;
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            options.timeout,
+        )
     }
 
     /// Builds a new QueryClient from the given IP address, port, socket address and optional timeout.
@@ -63,64 +167,600 @@ impl QueryClient {
         addr: impl ToSocketAddrs,
         timeout: Option<Duration>,
     ) -> io::Result<Self> {
-        let socket = UdpSocket::bind(addr)?;
-        socket.set_read_timeout(timeout)?;
-        socket.connect((ip, port))?;
+        Self::connect(format_target(ip, port), addr, HostPort(ip, port), timeout)
+    }
+
+    /// Build a new QueryClient bound to the given address and connected to
+    /// the server, described by the target in errors.
+    fn connect(
+        target: String,
+        addr: impl ToSocketAddrs,
+        server: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let connect = || {
+            let socket = UdpSocket::bind(addr)?;
+            socket.set_read_timeout(timeout)?;
+            socket.connect(server)?;
+            Ok(socket)
+        };
+        let socket = connect().map_err(|e| ClientError::wrap(e, "connect", &target, None))?;
 
         let session_id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .expect("System time cannot be before UNIX_EPOCH")
             .as_nanos() as u32;
 
-        Ok(Self { socket, session_id })
+        Ok(Self {
+            socket,
+            session_id,
+            target,
+            timeout,
+            cancel: OnceLock::new(),
+            #[cfg(feature = "histogram")]
+            latency: Default::default(),
+        })
+    }
+
+    /// A handle cancelling the operations of this client.
+    ///
+    /// Once a handle is taken, responses are waited for in slices of at most
+    /// [`CANCEL_LATENCY`], checking for cancellation in between.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.get_or_init(CancelHandle::default).clone()
+    }
+
+    /// Address of the server this client is connected to, to compare with
+    /// the address it advertises, see [`verify_advertised_address`](crate::advertised::verify_advertised_address).
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Latency histograms of the successful requests of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn latency_snapshot(&self) -> crate::histogram::LatencySnapshot {
+        self.latency.snapshot()
+    }
+
+    /// Clear the latency histograms of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn reset_latency(&self) {
+        self.latency.reset();
+    }
+
+    /// Run a client operation, adding it and the server address to its errors.
+    fn with_context<T>(
+        &self,
+        operation: &'static str,
+        f: impl FnOnce() -> io::Result<T>,
+    ) -> io::Result<T> {
+        #[cfg(feature = "histogram")]
+        let start = Instant::now();
+        let run = || {
+            if let Some(cancel) = self.cancel.get() {
+                cancel.check()?;
+            }
+            f()
+        };
+        let res = run().map_err(|e| ClientError::wrap(e, operation, &self.target, self.timeout));
+        #[cfg(feature = "histogram")]
+        self.latency.record(operation, start, &res);
+        res
+    }
+
+    /// Wait for a datagram with `recv`.
+    ///
+    /// If a cancel handle was taken, the client timeout is split in slices of
+    /// at most [`CANCEL_LATENCY`], checking for cancellation in between.
+    fn recv_with(&self, mut recv: impl FnMut() -> io::Result<usize>) -> io::Result<usize> {
+        let Some(cancel) = self.cancel.get() else {
+            return recv();
+        };
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let _restore = RestoreReadTimeout::new(&self.socket)?;
+        loop {
+            cancel.check()?;
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            let slice = remaining.map_or(CANCEL_LATENCY, |remaining| remaining.min(CANCEL_LATENCY));
+            // A zero timeout is rejected by the socket
+            self.socket
+                .set_read_timeout(Some(slice.max(Duration::from_millis(1))))?;
+
+            let last_slice = remaining.is_some_and(|remaining| remaining <= CANCEL_LATENCY);
+            match recv() {
+                Err(e) if is_timeout(&e) && !last_slice => {}
+                res => return res,
+            }
+        }
+    }
+
+    /// Receive a datagram of at most `max` bytes in a cleared buffer, without
+    /// initializing the buffer first.
+    fn recv_into(&self, buf: &mut Vec<u8>, max: usize) -> io::Result<usize> {
+        let socket = socket2::SockRef::from(&self.socket);
+        self.recv_with(|| recv_uninit(buf, max, |spare| socket.recv(spare)))
+    }
+
+    /// Send a request packet to the server.
+    fn send(&self, packet: &impl QueryPacket) -> io::Result<usize> {
+        self.socket.send(packet.as_bytes())
     }
 
     /// Send a UDP handshake packet to the client socket.
     ///
     /// Receive and parse the response into a Query token, valid up to 30 seconds.
     pub fn handshake(&self) -> io::Result<Token> {
-        let handshake = packets::Handshake::new(self.session_id);
-        self.socket.send(&handshake)?;
+        self.with_context("handshake", || {
+            self.send(&packets::Handshake::new(self.session_id))?;
 
-        let mut buf = [0; Token::RESPONSE_SIZE];
-        let received = self.socket.recv(&mut buf)?;
+            let mut buf = [0; Token::RESPONSE_SIZE];
+            let received = self.recv_with(|| self.socket.recv(&mut buf))?;
 
-        Ok(Token::from_payload(
-            &buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        ))
+            Token::try_from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..received)
+                    .ok_or_else(not_enough_data)?,
+            )
+        })
     }
 
     /// Request and wait for a basic status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub fn basic_stat(&self, token: Token) -> std::io::Result<BasicStat> {
-        let request = packets::BasicStat::new(self.session_id, token.0);
-        self.socket.send(&request)?;
+        self.basic_stat_into(token, &mut Vec::new())
+    }
 
-        let mut buf = vec![0; BasicStat::RESPONSE_SIZE];
-        let received = self.socket.recv(&mut buf)?;
+    /// Like [`basic_stat`](Self::basic_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub fn basic_stat_into(&self, token: Token, buf: &mut Vec<u8>) -> std::io::Result<BasicStat> {
+        self.with_context("basic_stat", || {
+            self.send(&packets::BasicStat::new(self.session_id, token.0))?;
 
-        BasicStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        )
+            self.recv_into(buf, BasicStat::RESPONSE_SIZE)?;
+
+            BasicStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..)
+                    .ok_or_else(not_enough_data)?,
+            )
+        })
     }
 
     /// Request and wait for a full status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub fn full_stat(&self, token: Token) -> std::io::Result<FullStat> {
-        let request = packets::FullStat::new(self.session_id, token.0);
-        self.socket.send(&request)?;
+        self.full_stat_into(token, &mut Vec::new())
+    }
 
-        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
-        let received = self.socket.recv(&mut buf)?;
+    /// Like [`full_stat`](Self::full_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub fn full_stat_into(&self, token: Token, buf: &mut Vec<u8>) -> std::io::Result<FullStat> {
+        self.with_context("full_stat", || {
+            self.send(&packets::FullStat::new(self.session_id, token.0))?;
 
-        FullStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        )
+            self.recv_into(buf, FullStat::RESPONSE_SIZE)?;
+
+            FullStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..)
+                    .ok_or_else(not_enough_data)?,
+            )
+        })
+    }
+
+    /// Request and wait for the full status of a Bedrock server, with its
+    /// Bedrock keys.
+    ///
+    /// If the token is no longer valid, no packet is received and an error is returned.
+    pub fn bedrock_full_stat(&self, token: Token) -> io::Result<BedrockFullStat> {
+        self.with_context("full_stat", || {
+            self.send(&packets::FullStat::new(self.session_id, token.0))?;
+
+            let mut buf = Vec::new();
+            self.recv_into(&mut buf, FullStat::RESPONSE_SIZE)?;
+
+            BedrockFullStat::from_payload(
+                buf.get(RESPONSE_HEADER_SIZE..)
+                    .ok_or_else(not_enough_data)?,
+            )
+        })
+    }
+
+    /// Get the basic status of the server with the token cached in `tokens`,
+    /// after a new handshake if the token is expected to have expired.
+    ///
+    /// If the cached token is rejected, the rotation is recorded, and the
+    /// request sent again with a new token. See [`token_cache`](crate::token_cache).
+    pub fn basic_stat_cached(&self, tokens: &mut TokenHandle) -> io::Result<BasicStat> {
+        self.stat_cached(tokens, Self::basic_stat)
+    }
+
+    /// Get the full status of the server with the token cached in `tokens`,
+    /// like [`basic_stat_cached`](Self::basic_stat_cached).
+    pub fn full_stat_cached(&self, tokens: &mut TokenHandle) -> io::Result<FullStat> {
+        self.stat_cached(tokens, Self::full_stat)
+    }
+
+    /// Send a status request with the cached token, recording the rotations
+    /// of the server tokens.
+    fn stat_cached<T>(
+        &self,
+        tokens: &mut TokenHandle,
+        stat: impl Fn(&Self, Token) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let now = tokens.now();
+        let token = match tokens.token(now) {
+            Some(token) => match stat(self, token) {
+                Ok(res) => {
+                    tokens.record_success(now);
+                    return Ok(res);
+                }
+                Err(e) if is_timeout(&e) => {
+                    // Only a rejection if the server still answers handshakes
+                    let sent = tokens.now();
+                    let token = self.handshake()?;
+                    tokens.record_rejection(now);
+                    tokens.set(token, sent);
+                    token
+                }
+                Err(e) => return Err(e),
+            },
+            None => {
+                let token = self.handshake()?;
+                tokens.set(token, now);
+                token
+            }
+        };
+
+        let sent = tokens.now();
+        let res = stat(self, token)?;
+        tokens.record_success(sent);
+        Ok(res)
+    }
+
+    /// Send a status request with arbitrary bytes after the token, built with
+    /// [`StatRequest::with_payload`](packets::StatRequest::with_payload), and
+    /// return the raw response, split into its header and its payload.
+    ///
+    /// This is a diagnostic API: the response is neither checked nor parsed,
+    /// and servers usually ignore non-standard requests, which then time out.
+    pub fn send_custom_stat(
+        &self,
+        token: Token,
+        extra: &[u8],
+    ) -> io::Result<(packets::ResponseHeader, Vec<u8>)> {
+        self.with_context("custom_stat", || {
+            self.send(&packets::StatRequest::with_payload(
+                self.session_id,
+                token.0,
+                extra,
+            ))?;
+
+            let mut buf = Vec::new();
+            self.recv_into(&mut buf, FullStat::RESPONSE_SIZE)?;
+
+            let (header, payload) =
+                packets::ResponseHeader::parse(&buf).ok_or_else(not_enough_data)?;
+            Ok((header, payload.to_vec()))
+        })
+    }
+}
+
+/// One-shot query of a server, built with chained options.
+///
+/// A client is built for each call to [`full`](Self::full), [`basic`](Self::basic)
+/// or [`ping`](Self::ping), and dropped afterwards.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::blocking::Query;
+/// # use std::time::Duration;
+/// let full_stat = Query::to("play.example.com")
+///     .port(25565)
+///     .timeout(Duration::from_secs(2))
+///     .retries(2)
+///     .full()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    host: String,
+    port: Option<u16>,
+    bind: Option<SocketAddr>,
+    timeout: Duration,
+    retries: u32,
+    resolver: Arc<dyn Resolve>,
+    rate_limiter: Option<RateLimiter>,
+    #[cfg(feature = "opentelemetry")]
+    telemetry: Option<crate::otel::Telemetry>,
+}
+
+impl Query {
+    /// Start a query of the given server. If no port is specified in the
+    /// address or with [`port`](Self::port), the [default port](DEFAULT_PORT) is used.
+    pub fn to(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: None,
+            bind: None,
+            timeout: DEFAULT_TIMEOUT,
+            retries: 0,
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+
+    /// Port of the server. The address given to [`to`](Self::to) is then used
+    /// as is, so it can be an IPv6 address.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Local address to bind the client socket to. By default, an unspecified
+    /// address of the same IP version as the resolved server address.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = Some(addr);
+        self
+    }
+
+    /// Timeout of each response, [`DEFAULT_TIMEOUT`] by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of times to start over from the handshake if a response times
+    /// out, none by default. Other errors are returned immediately.
+    ///
+    /// Queries failing after a retry return an [`AttemptsExhausted`] error,
+    /// with the error of every attempt.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Resolver of the host name of the server, the [system
+    /// resolver](SystemResolver) by default. It is not consulted when the
+    /// host is an IP address.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Limiter every attempt of the query draws a permit from, the [global
+    /// limiter](crate::rate_limit::set_global) by default, if any.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Record the duration, failures and spans of the query with the given
+    /// [instruments](crate::otel).
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+    pub fn telemetry(mut self, telemetry: crate::otel::Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Get the full status of the server.
+    pub fn full(self) -> io::Result<FullStat> {
+        self.full_timed().map(|(stat, _)| stat)
+    }
+
+    /// Get the basic status of the server.
+    pub fn basic(self) -> io::Result<BasicStat> {
+        self.basic_timed().map(|(stat, _)| stat)
+    }
+
+    /// Like [`full`](Self::full), but also return the duration of every phase of the query.
+    pub fn full_timed(self) -> io::Result<(FullStat, Timings)> {
+        self.run_timed("full_stat", |client, token, _| client.full_stat(token))
+            .map(|(stat, timings, _)| (stat, timings))
+    }
+
+    /// Like [`basic`](Self::basic), but also return the duration of every phase of the query.
+    pub fn basic_timed(self) -> io::Result<(BasicStat, Timings)> {
+        self.run_timed("basic_stat", |client, token, _| client.basic_stat(token))
+            .map(|(stat, timings, _)| (stat, timings))
+    }
+
+    /// Like [`full`](Self::full), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub fn full_attempts(self) -> io::Result<(FullStat, Vec<AttemptError>)> {
+        self.run_timed("full_stat", |client, token, _| client.full_stat(token))
+            .map(|(stat, _, attempts)| (stat, attempts))
+    }
+
+    /// Like [`basic`](Self::basic), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub fn basic_attempts(self) -> io::Result<(BasicStat, Vec<AttemptError>)> {
+        self.run_timed("basic_stat", |client, token, _| client.basic_stat(token))
+            .map(|(stat, _, attempts)| (stat, attempts))
+    }
+
+    /// Get the full status of the server, or its basic status if the full
+    /// status request still times out on the last attempt.
+    ///
+    /// Full status responses are much larger than basic ones, so on lossy
+    /// links they are lost far more often. The fallback uses the token of the
+    /// last handshake, and its result is [`AnyStat::Basic`].
+    ///
+    /// ```rust,no_run
+    /// # use minecraft_server_query::{blocking::Query, AnyStat};
+    /// match Query::to("play.example.com").retries(2).full_or_basic()? {
+    ///     AnyStat::Full(stat) => println!("{} players", stat.player_list.len()),
+    ///     AnyStat::Basic(stat) => println!("{} players (degraded)", stat.numplayers),
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn full_or_basic(self) -> io::Result<AnyStat> {
+        self.full_or_basic_attempts().map(|(stat, _)| stat)
+    }
+
+    /// Like [`full_or_basic`](Self::full_or_basic), but also return the
+    /// attempts which timed out before the query succeeded. When the query
+    /// fell back to the basic status, the last one is the full status
+    /// request which timed out.
+    pub fn full_or_basic_attempts(self) -> io::Result<(AnyStat, Vec<AttemptError>)> {
+        self.run_timed("full_or_basic", |client, token, last| {
+            let start = Instant::now();
+            match client.full_stat(token) {
+                Ok(stat) => Ok((AnyStat::Full(stat), None)),
+                Err(error) if last && is_timeout(&error) => {
+                    let stat = client.basic_stat(token)?;
+                    Ok((
+                        AnyStat::Basic(stat),
+                        Some(AttemptError::new(&client.target, error, start)),
+                    ))
+                }
+                Err(error) => Err(error),
+            }
+        })
+        .map(|((stat, fallback), _, mut attempts)| {
+            attempts.extend(fallback);
+            (stat, attempts)
+        })
+    }
+
+    /// Measure the round-trip time of a handshake with the server.
+    pub fn ping(self) -> io::Result<Duration> {
+        self.run("ping", |client| {
+            let start = Instant::now();
+            client.handshake()?;
+            Ok(start.elapsed())
+        })
+    }
+
+    /// Start recording an operation of the query, if it has telemetry.
+    fn operation(&self, name: &'static str) -> Operation {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(telemetry) = &self.telemetry {
+            let (host, port) = match self.port {
+                Some(port) => (self.host.as_str(), port),
+                None => split_address(&self.host).unwrap_or((&self.host, DEFAULT_PORT)),
+            };
+            return Operation::start(telemetry, name, host, Some(port));
+        }
+        #[cfg(not(feature = "opentelemetry"))]
+        let _ = name;
+        Operation::none()
+    }
+
+    /// Resolve the server address and build the client of the query,
+    /// recording the duration of both phases.
+    fn client(&self, timings: &mut Timings) -> io::Result<QueryClient> {
+        let (host, port) = match self.port {
+            Some(port) => (self.host.as_str(), port),
+            None => split_address(&self.host)?,
+        };
+        let target = format_target(host, port);
+
+        let start = Instant::now();
+        let server = resolve::first_address(&*self.resolver, host, port)
+            .map_err(|e| ClientError::wrap(e, "resolve", &target, None))?;
+        timings.resolve = start.elapsed();
+
+        let start = Instant::now();
+        let bind = self.bind.unwrap_or_else(|| unspecified_for(&server));
+        let client = QueryClient::connect(target, bind, server, Some(self.timeout))?;
+        timings.connect = start.elapsed();
+        Ok(client)
+    }
+
+    /// Build a client and run the request, retrying on timeouts.
+    fn run<T>(
+        &self,
+        name: &'static str,
+        request: impl Fn(&QueryClient) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let operation = self.operation(name);
+        let mut timings = Timings::default();
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let res = self.client(&mut timings).and_then(|client| {
+            let mut attempts = Attempts::new(self.retries);
+            loop {
+                if let Some(limiter) = &limiter {
+                    limiter.acquire();
+                }
+                let start = Instant::now();
+                if let Some(res) = attempts.check(&client.target, start, request(&client)) {
+                    return res;
+                }
+            }
+        });
+        operation.finish(&res, &timings);
+        res
+    }
+
+    /// Build a client, then run handshakes and status requests until one
+    /// succeeds, retrying on timeouts and recording every attempt. The status
+    /// request is told whether it is the last attempt.
+    fn run_timed<T>(
+        &self,
+        name: &'static str,
+        stat: impl Fn(&QueryClient, Token, bool) -> io::Result<T>,
+    ) -> io::Result<(T, Timings, Vec<AttemptError>)> {
+        let operation = self.operation(name);
+        let mut timings = Timings::default();
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let res = self.client(&mut timings).and_then(|client| {
+            let mut attempts = Attempts::new(self.retries);
+            loop {
+                if let Some(limiter) = &limiter {
+                    limiter.acquire();
+                }
+                let start = Instant::now();
+                let token = client.handshake();
+                timings.handshakes.push(start.elapsed());
+
+                let res = token.and_then(|token| {
+                    let start = Instant::now();
+                    let res = stat(&client, token, attempts.is_last());
+                    timings.stats.push(start.elapsed());
+                    res
+                });
+                if let Some(res) = attempts.check(&client.target, start, res) {
+                    return res.map(|stat| (stat, attempts.into_failed()));
+                }
+            }
+        });
+        operation.finish(&res, &timings);
+        res.map(|(stat, failed)| (stat, timings, failed))
+    }
+}
+
+impl From<ConnectionString> for Query {
+    /// Query the server of a connection string, with its options.
+    fn from(target: ConnectionString) -> Self {
+        Self {
+            port: Some(target.port_or_default()),
+            host: target.host,
+            bind: target.bind,
+            timeout: target.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            retries: target.retries.unwrap_or(0),
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = ConnectionStringError;
+
+    /// Parse a [connection string](crate::connection_string), or a plain
+    /// `host[:port]` target.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<ConnectionString>().map(Self::from)
     }
 }
 
@@ -129,25 +769,148 @@ impl QueryClient {
 /// Send a handshake first, and if a token is successfully received and parsed,
 /// request a full status packet.
 pub fn query(ip: &str) -> io::Result<FullStat> {
+    if let Some(limiter) = rate_limit::global() {
+        limiter.acquire();
+    }
     let client = QueryClient::new(ip)?;
     let token = client.handshake()?;
 
     client.full_stat(token)
 }
 
+/// Convenience function to get a full status packet, with the duration of
+/// every phase of the query.
+///
+/// Like [`query`], built with [`Query`]: see [`Query::full_timed`].
+pub fn query_timed(ip: &str) -> io::Result<(FullStat, Timings)> {
+    Query::to(ip).full_timed()
+}
+
+/// Measure the packet loss and the jitter of the connection to a server, by
+/// sending a series of handshakes. See [`quality`](crate::quality).
+///
+/// This takes at most `count × interval + timeout`, and returns early once
+/// every probe is answered.
+pub fn network_quality(ip: &str, options: ProbeOptions) -> io::Result<QualityReport> {
+    let client = QueryClient::new(ip)?;
+    let wrap = |e| ClientError::wrap(e, "network_quality", &client.target, Some(options.timeout));
+
+    let mut probes = Probes::new(client.session_id, options, Instant::now());
+    let mut buf = [0; Token::RESPONSE_SIZE];
+    loop {
+        while let Some(probe) = probes.poll_send(Instant::now()) {
+            client.send(&probe).map_err(wrap)?;
+        }
+        let Some(wakeup) = probes.next_wakeup(Instant::now()) else {
+            break;
+        };
+        let wait = wakeup.saturating_duration_since(Instant::now());
+        // A zero timeout is rejected by the socket
+        client
+            .socket
+            .set_read_timeout(Some(wait.max(Duration::from_millis(1))))
+            .map_err(wrap)?;
+        match client.socket.recv(&mut buf) {
+            Ok(received) => probes.receive(&buf[..received], Instant::now()),
+            // Lost probes, or ICMP errors for probes sent to a closed port
+            Err(e) if is_timeout(&e) || e.kind() == io::ErrorKind::ConnectionRefused => {}
+            Err(e) => return Err(wrap(e)),
+        }
+    }
+    Ok(probes.report())
+}
+
+/// Convenience function to get the most detailed status a server answers with.
+///
+/// Like [`query`], but if the full status request times out, request a basic
+/// status instead, with the same token. Some old modded servers answer
+/// handshakes and basic status requests, but ignore full status requests.
+///
+/// Built with [`Query`]: see [`Query::full_or_basic`].
+pub fn query_lenient(ip: &str) -> io::Result<AnyStat> {
+    Query::to(ip).full_or_basic()
+}
+
+/// Convenience function to get the full status of a Bedrock server with
+/// query enabled, with its Bedrock keys.
+///
+/// Like [`query`], but if no port is specified in the IP address, the
+/// [default Bedrock port](DEFAULT_BEDROCK_PORT) is used.
+pub fn query_bedrock(ip: &str) -> io::Result<BedrockFullStat> {
+    if let Some(limiter) = rate_limit::global() {
+        limiter.acquire();
+    }
+    let (ip, port) = split_address_or(ip, DEFAULT_BEDROCK_PORT)?;
+    let client = QueryClient::new_with_port(ip, port)?;
+    let token = client.handshake()?;
+
+    client.bedrock_full_stat(token)
+}
+
 #[cfg(test)]
 mod tests {
-    const TEST_IP: &str = "lotr.g.akliz.net:25565";
+    use std::{
+        io,
+        net::{SocketAddr, UdpSocket},
+        time::{Duration, Instant},
+    };
+
+    use crate::packets::{self, PacketType};
+    use crate::quality::ProbeOptions;
+    use crate::rate_limit::{RateLimit, RateLimiter};
+    use crate::testing::{Faults, MockQueryServer};
 
     #[test]
     fn test_handshake() {
-        let client = super::QueryClient::new(TEST_IP).unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        client.handshake().unwrap();
+    }
+
+    /// Handshake response padded after the token, longer than vanilla responses
+    const PADDED_HANDSHAKE: &[u8] =
+        b"\x09\0\0\0\x01 9513307\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0padding";
+
+    /// Answer the handshake of a client with a raw response.
+    fn raw_handshake(response: &[u8]) -> io::Result<crate::Token> {
+        let server = UdpSocket::bind("127.0.0.1:0")?;
+        let client = super::QueryClient::new(&server.local_addr()?.to_string())?;
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let mut buf = [0; 16];
+                let (_, source) = server.recv_from(&mut buf).unwrap();
+                server.send_to(response, source).unwrap();
+            });
+            client.handshake()
+        })
+    }
+
+    #[test]
+    fn test_padded_handshake() {
+        assert!(PADDED_HANDSHAKE.len() > 32);
+        assert_eq!(
+            raw_handshake(PADDED_HANDSHAKE).unwrap(),
+            crate::Token(9513307)
+        );
+
+        let err = raw_handshake(b"\x09\0\0\0\x019513307").unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("failed: Missing null terminator in handshake response."));
+        assert!(raw_handshake(b"\x09\0\0\0\x01\0").is_err());
+    }
+
+    #[test]
+    fn test_new_addr() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new_addr(server.addr()).unwrap();
         client.handshake().unwrap();
     }
 
     #[test]
     fn test_basic_stat() {
-        let client = super::QueryClient::new(TEST_IP).unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
         let token = client.handshake().unwrap();
 
         let basic_stat = client.basic_stat(token).unwrap();
@@ -156,11 +919,783 @@ mod tests {
 
     #[test]
     fn test_full_stat() {
-        let full_stat = super::query(TEST_IP).unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let full_stat = super::query(&server.addr().to_string()).unwrap();
 
         assert_eq!(full_stat.hostport, crate::DEFAULT_PORT);
         assert_eq!(full_stat.numplayers as usize, full_stat.player_list.len());
-        assert_eq!(full_stat.version, "1.7.10");
+        assert_eq!(full_stat.version, server.full_stat().version);
+        assert_eq!(full_stat.game_id, "MINECRAFT");
+    }
+
+    #[test]
+    fn test_stat_into() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        let token = client.handshake().unwrap();
+
+        let mut buf = Vec::new();
+        let full_stat = client.full_stat_into(token, &mut buf).unwrap();
+        assert_eq!(full_stat, client.full_stat(token).unwrap());
+        assert_eq!(&buf[crate::RESPONSE_HEADER_SIZE..], full_stat.to_payload());
+
+        // The buffer is reused, even if it contains a previous response
+        let capacity = buf.capacity();
+        let basic_stat = client.basic_stat_into(token, &mut buf).unwrap();
+        assert_eq!(basic_stat, client.basic_stat(token).unwrap());
+        assert_eq!(&buf[crate::RESPONSE_HEADER_SIZE..], basic_stat.to_payload());
+        assert_eq!(buf.capacity(), capacity);
+        assert_eq!(client.full_stat_into(token, &mut buf).unwrap(), full_stat);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        let token = client.handshake().unwrap();
+
+        let err = client.full_stat(token).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!("full_stat to {} timed out after 500ms", server.addr())
+        );
+        assert_eq!(server.dropped(PacketType::Stat), 1);
+        client.full_stat(token).unwrap();
+    }
+
+    #[test]
+    fn test_cancel() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let addr = server.addr();
+        let client = super::QueryClient::new_with_socket_address(
+            &addr.ip().to_string(),
+            addr.port(),
+            "127.0.0.1:0",
+            None,
+        )
+        .unwrap();
+        let token = client.handshake().unwrap();
+
+        let handle = client.cancel_handle();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        });
+        let start = std::time::Instant::now();
+        let err = client.full_stat(token).unwrap_err();
+        canceller.join().unwrap();
+
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(start.elapsed() < Duration::from_millis(100) + 2 * super::CANCEL_LATENCY);
+        assert_eq!(
+            err.to_string(),
+            format!("full_stat to {addr} failed: Operation cancelled.")
+        );
+
+        // Later operations are cancelled without sending anything
+        let received = server.received().len();
+        assert_eq!(
+            client.handshake().unwrap_err().kind(),
+            io::ErrorKind::Interrupted
+        );
+        assert_eq!(server.received().len(), received);
+    }
+
+    #[test]
+    fn test_cancel_handle_timeout() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        let handle = client.cancel_handle();
+        let token = client.handshake().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+
+        // The client timeout still applies, in slices
+        let start = std::time::Instant::now();
+        let err = client.full_stat(token).unwrap_err();
+        assert!(crate::is_timeout(&err));
+        assert!(start.elapsed() >= crate::DEFAULT_TIMEOUT);
+        assert!(start.elapsed() < crate::DEFAULT_TIMEOUT + 2 * super::CANCEL_LATENCY);
+        assert!(!handle.is_cancelled());
+        client.full_stat(token).unwrap();
+        // The timeout of the socket is restored
+        assert_eq!(
+            client.socket.read_timeout().unwrap(),
+            Some(crate::DEFAULT_TIMEOUT)
+        );
+    }
+
+    #[test]
+    fn test_query_bedrock() {
+        use crate::gs4::{tests::*, BedrockFullStat, Protocol};
+
+        let addr = spawn_stub_for(Protocol::MINECRAFT, BEDROCK_PAYLOAD);
+        let stat = super::query_bedrock(&addr.to_string()).unwrap();
+        assert_eq!(
+            stat,
+            BedrockFullStat::from_payload(BEDROCK_PAYLOAD).unwrap()
+        );
+        assert_eq!(stat.whitelist(), Some(true));
+
+        // Parsing errors carry the context of the request
+        let addr = spawn_stub_for(Protocol::MINECRAFT, OTHER_GAME_PAYLOAD);
+        let err = super::query_bedrock(&addr.to_string()).unwrap_err();
+        let context = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::ClientError>())
+            .unwrap();
+        assert_eq!(context.operation(), "full_stat");
+        assert_eq!(context.target(), addr.to_string());
+    }
+
+    #[test]
+    fn test_query_lenient() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = server.addr().to_string();
+        let stat = super::query_lenient(&addr).unwrap();
+        assert_eq!(stat, crate::AnyStat::Full(server.full_stat()));
+
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                ignore_full_stat: true,
+                ..Faults::default()
+            },
+        );
+        assert!(super::query(&addr).is_err());
+        let stat = super::query_lenient(&addr).unwrap();
+        assert!(!stat.is_full());
+        assert_eq!(stat.basic(), crate::BasicStat::from(&server.full_stat()));
+        assert_eq!(server.dropped(PacketType::Stat), 2);
+
+        // Other errors are not recovered from
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                corrupt: true,
+                ..Faults::default()
+            },
+        );
+        assert!(super::query_lenient(&addr).is_err());
+    }
+
+    #[test]
+    fn test_query_builder_rate_limiter() {
+        let server = MockQueryServer::new().unwrap();
+        let limit = RateLimit {
+            rate: 1.0 / 0.3,
+            burst: 1.0,
+        };
+        let query =
+            super::Query::to(server.addr().to_string()).rate_limiter(RateLimiter::new(limit));
+
+        // A handshake and its status request take a single permit
+        let start = Instant::now();
+        query.clone().full().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(300));
+        // Queries of other clients draw from the same budget
+        query.full().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+
+        // Retried attempts take a new permit
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let start = Instant::now();
+        super::Query::to(server.addr().to_string())
+            .timeout(Duration::from_millis(50))
+            .retries(1)
+            .rate_limiter(RateLimiter::new(limit))
+            .full()
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_global_rate_limiter() {
+        let server = MockQueryServer::new().unwrap();
+        // Generous enough not to slow down the other tests
+        let limiter = RateLimiter::new(RateLimit {
+            rate: 1e9,
+            burst: 1e9,
+        });
+        crate::rate_limit::set_global(Some(limiter));
+        assert!(crate::rate_limit::global().is_some());
+        let res = super::query(&server.addr().to_string());
+        crate::rate_limit::set_global(None);
+        assert_eq!(res.unwrap(), server.full_stat());
+        assert!(crate::rate_limit::global().is_none());
+    }
+
+    #[test]
+    fn test_query_builder_fallback() {
+        let server = MockQueryServer::new().unwrap();
+        let query = super::Query::to(server.addr().to_string())
+            .timeout(Duration::from_millis(100))
+            .retries(1);
+        let stat = query.clone().full_or_basic().unwrap();
+        assert_eq!(stat, crate::AnyStat::Full(server.full_stat()));
+
+        // A lossy link, only losing the large full status responses
+        let basic = crate::BasicStat::from(&server.full_stat());
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_larger_than: Some(crate::RESPONSE_HEADER_SIZE + basic.to_payload().len()),
+                ..Faults::default()
+            },
+        );
+        assert!(query.clone().full().is_err());
+        let (stat, attempts) = query.clone().full_or_basic_attempts().unwrap();
+        assert_eq!(stat, crate::AnyStat::Basic(basic));
+        // The full status request was retried before falling back
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts
+            .iter()
+            .all(|attempt| crate::is_timeout(&attempt.error)));
+        assert_eq!(server.dropped(PacketType::Stat), 4);
+
+        // Other errors don't fall back
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                corrupt: true,
+                ..Faults::default()
+            },
+        );
+        assert!(query.full_or_basic().is_err());
+    }
+
+    #[test]
+    fn test_query_builder() {
+        let server = MockQueryServer::new().unwrap();
+        let stat = super::Query::to(server.addr().to_string()).full().unwrap();
+        assert_eq!(stat, server.full_stat());
+        let stat = super::Query::to("127.0.0.1")
+            .port(server.addr().port())
+            .basic()
+            .unwrap();
+        assert_eq!(stat, crate::BasicStat::from(&server.full_stat()));
+        super::Query::to(server.addr().to_string()).ping().unwrap();
+        assert!(super::Query::to("127.0.0.1:invalid").full().is_err());
+    }
+
+    #[test]
+    fn test_query_builder_resolver() {
+        let server = MockQueryServer::new().unwrap();
+        let stub = crate::resolve::tests::StubResolver::new();
+        stub.set("lobby.mc.internal", &[server.addr()]);
+        let query = |host: &str| {
+            super::Query::to(host)
+                .port(25565)
+                .timeout(Duration::from_millis(50))
+                .resolver(stub.clone())
+        };
+
+        assert_eq!(
+            query("lobby.mc.internal").full().unwrap(),
+            server.full_stat()
+        );
+        assert_eq!(stub.calls(), ["lobby.mc.internal"]);
+
+        // Resolved once per query, not once per attempt
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        query("lobby.mc.internal").retries(1).full().unwrap();
+        assert_eq!(stub.calls().len(), 2);
+
+        // IP addresses are not resolved
+        super::Query::to(server.addr().to_string())
+            .resolver(stub.clone())
+            .ping()
+            .unwrap();
+        assert_eq!(stub.calls().len(), 2);
+
+        let err = query("unknown.mc.internal").ping().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(
+            err.to_string()
+                .starts_with("resolve to unknown.mc.internal:"),
+            "{err}"
+        );
+        assert_eq!(stub.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_query_builder_retries() {
+        let server = MockQueryServer::new().unwrap();
+        let query = super::Query::to(server.addr().to_string()).timeout(Duration::from_millis(50));
+        let drop_next = |drop_next| {
+            server.set_faults(
+                PacketType::Stat,
+                Faults {
+                    drop_next,
+                    ..Faults::default()
+                },
+            )
+        };
+
+        drop_next(1);
+        assert!(query.clone().full().is_err());
+        drop_next(2);
+        assert_eq!(query.clone().retries(2).full().unwrap(), server.full_stat());
+        assert_eq!(server.dropped(PacketType::Stat), 3);
+        assert_eq!(
+            server
+                .received()
+                .iter()
+                .filter(|p| p.data.len() == packets::Handshake::ENCODED_LEN)
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_query_builder_attempts() {
+        let server = MockQueryServer::new().unwrap();
+        let target = server.addr().to_string();
+        let query = super::Query::to(&target)
+            .timeout(Duration::from_millis(50))
+            .retries(2);
+        let faults = |faults| server.set_faults(PacketType::Stat, faults);
+
+        // A timeout, then a truncated response
+        faults(Faults {
+            drop_next: 1,
+            truncate: Some(40),
+            ..Faults::default()
+        });
+        let err = query.clone().basic().unwrap_err();
+        assert!(!crate::is_timeout(&err));
+        let exhausted = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::AttemptsExhausted>())
+            .unwrap();
+        let attempts = exhausted.attempts();
+        assert_eq!(attempts.len(), 2);
+        assert!(attempts.iter().all(|attempt| attempt.target == target));
+        assert!(crate::is_timeout(&attempts[0].error));
+        assert!(attempts[0].elapsed >= Duration::from_millis(50));
+        assert_eq!(attempts[1].error.kind(), err.kind());
+        let summary = err.to_string();
+        assert!(
+            summary.starts_with(&format!(
+                "2 attempts failed: #1 basic_stat to {target} timed out after 50ms ("
+            )),
+            "{summary}"
+        );
+        assert!(
+            summary.contains(&format!(
+                "ms); #2 basic_stat to {target} failed: Not enough data in UDP payload. ("
+            )),
+            "{summary}"
+        );
+
+        // Every retry timed out
+        faults(Faults {
+            drop_next: 3,
+            ..Faults::default()
+        });
+        let err = query.clone().full().unwrap_err();
+        assert!(crate::is_timeout(&err));
+        let exhausted = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::AttemptsExhausted>())
+            .unwrap();
+        assert_eq!(exhausted.attempts().len(), 3);
+        assert!(std::error::Error::source(exhausted).is_some());
+
+        // Successful queries return the failed attempts
+        faults(Faults {
+            drop_next: 1,
+            ..Faults::default()
+        });
+        let (stat, attempts) = query.clone().full_attempts().unwrap();
+        assert_eq!(stat, server.full_stat());
+        assert_eq!(attempts.len(), 1);
+        assert!(crate::is_timeout(&attempts[0].error));
+        let (_, attempts) = query.clone().basic_attempts().unwrap();
+        assert!(attempts.is_empty());
+
+        // Failures of the first attempt are returned as is
+        faults(Faults {
+            truncate: Some(40),
+            ..Faults::default()
+        });
+        let err = query.basic().unwrap_err();
+        assert!(err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::ClientError>())
+            .is_some());
+    }
+
+    #[test]
+    fn test_query_connection_string() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = server.addr();
+
+        let query: super::Query = addr.to_string().parse().unwrap();
+        assert_eq!(query.full().unwrap(), server.full_stat());
+
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let query: super::Query = format!("mc://{addr}?timeout=50ms&retries=1&bind=127.0.0.1:0")
+            .parse()
+            .unwrap();
+        let (stat, attempts) = query.full_attempts().unwrap();
+        assert_eq!(stat, server.full_stat());
+        assert!(attempts[0].elapsed >= Duration::from_millis(50));
+
+        let err = "mc://localhost?retries=many"
+            .parse::<super::Query>()
+            .unwrap_err();
+        assert_eq!(
+            io::Error::from(err).to_string(),
+            "Invalid value `many` of parameter `retries` in connection string, expected a number."
+        );
+    }
+
+    #[cfg(feature = "histogram")]
+    #[test]
+    fn test_latency_snapshot() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(20);
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                delay: Some(delay),
+                ..Faults::default()
+            },
+        );
+
+        let client = super::QueryClient::new_addr(server.addr()).unwrap();
+        for _ in 0..3 {
+            let token = client.handshake().unwrap();
+            client.full_stat(token).unwrap();
+        }
+        // Failed requests are not recorded
+        client.basic_stat(crate::Token(0)).unwrap_err();
+
+        let latency = client.latency_snapshot();
+        assert_eq!(latency.handshake.count, 3);
+        assert!(latency.handshake.p50().unwrap() >= delay);
+        assert_eq!(latency.stat.count, 3);
+        assert!(latency.stat.p99().unwrap() < latency.handshake.p50().unwrap());
+
+        client.reset_latency();
+        assert_eq!(client.latency_snapshot().handshake.count, 0);
+        assert_eq!(client.latency_snapshot().stat.p50(), None);
+    }
+
+    #[test]
+    fn test_network_quality() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(30);
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                drop_next: 2,
+                delay: Some(delay),
+                ..Faults::default()
+            },
+        );
+        let options = ProbeOptions {
+            count: 5,
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(300),
+        };
+
+        let start = Instant::now();
+        let report = super::network_quality(&server.addr().to_string(), options).unwrap();
+        assert!(start.elapsed() <= options.interval * 5 + options.timeout + delay);
+        assert_eq!(report.sent, 5);
+        assert_eq!(report.received, 3);
+        assert_eq!(report.loss_percent(), 40.0);
+        let (min, avg, max) = (
+            report.min.unwrap(),
+            report.avg.unwrap(),
+            report.max.unwrap(),
+        );
+        assert!(delay <= min && min <= avg && avg <= max && max < options.timeout);
+        assert!(report.jitter.unwrap() <= max - min);
+
+        // Returns once every probe is answered
+        server.set_faults(PacketType::Handshake, Faults::default());
+        let start = Instant::now();
+        let report = super::network_quality(&server.addr().to_string(), options).unwrap();
+        assert!(start.elapsed() < options.interval * 5 + options.timeout);
+        assert_eq!(report.received, 5);
+        assert_eq!(report.loss_percent(), 0.0);
+    }
+
+    #[test]
+    fn test_query_timed() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(100);
+        let delayed = |packet_type| {
+            server.set_faults(PacketType::Handshake, Faults::default());
+            server.set_faults(PacketType::Stat, Faults::default());
+            server.set_faults(
+                packet_type,
+                Faults {
+                    delay: Some(delay),
+                    ..Faults::default()
+                },
+            );
+        };
+
+        delayed(PacketType::Handshake);
+        let (stat, timings) = super::query_timed(&server.addr().to_string()).unwrap();
+        assert_eq!(stat, server.full_stat());
+        assert!(timings.handshakes[0] >= delay);
+        assert!(timings.stats[0] < delay);
+
+        delayed(PacketType::Stat);
+        let (_, timings) = super::Query::to(server.addr().to_string())
+            .basic_timed()
+            .unwrap();
+        assert!(timings.handshakes[0] < delay);
+        assert!(timings.stats[0] >= delay);
+        assert!(timings.total() >= timings.resolve + timings.connect + delay);
+
+        // Every attempt is recorded
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let timeout = Duration::from_millis(50);
+        let (_, timings) = super::Query::to(server.addr().to_string())
+            .timeout(timeout)
+            .retries(1)
+            .full_timed()
+            .unwrap();
+        assert_eq!(timings.handshakes.len(), 2);
+        assert_eq!(timings.stats.len(), 2);
+        assert!(timings.stats[0] >= timeout);
+        assert!(timings.stats[1] < timeout);
+    }
+
+    #[test]
+    fn test_custom_stat() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            "127.0.0.1:0",
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+        let token = client.handshake().unwrap();
+
+        // Padded like a full status request
+        let (header, payload) = client.send_custom_stat(token, &[0; 4]).unwrap();
+        assert_eq!(header.packet_type, PacketType::Stat);
+        assert_eq!(
+            crate::FullStat::from_payload(&payload).unwrap(),
+            server.full_stat()
+        );
+
+        // Requests of other lengths are ignored
+        let err = client.send_custom_stat(token, b"extra").unwrap_err();
+        assert!(crate::is_timeout(&err));
+        assert_eq!(server.received().pop().unwrap().data.len(), 16);
+    }
+
+    #[test]
+    fn test_error_context() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        let token = client.handshake().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                truncate: Some(40),
+                ..Faults::default()
+            },
+        );
+
+        let err = client.basic_stat(token).unwrap_err();
+        let context = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::ClientError>())
+            .unwrap();
+        assert_eq!(context.operation(), "basic_stat");
+        assert_eq!(context.target(), server.addr().to_string());
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "basic_stat to {} failed: Not enough data in UDP payload.",
+                server.addr()
+            )
+        );
+
+        // The local address is not available
+        let err = super::QueryClient::new_with_socket_address("::1", 25565, "192.0.2.1:0", None)
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("connect to [::1]:25565 failed: "));
+    }
+
+    #[test]
+    fn test_invalid_responses() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string()).unwrap();
+        let token = client.handshake().unwrap();
+
+        for faults in [
+            Faults {
+                truncate: Some(40),
+                ..Faults::default()
+            },
+            Faults {
+                corrupt: true,
+                ..Faults::default()
+            },
+        ] {
+            server.set_faults(PacketType::Stat, faults);
+            assert!(client.basic_stat(token).is_err());
+            assert!(client.full_stat(token).is_err());
+        }
+    }
+
+    #[test]
+    fn test_token_rotation_prediction() {
+        use crate::testing::{ManualClock, TOKEN_LIFETIME};
+        use crate::token_cache::{TokenHandle, ROTATION_MARGIN};
+
+        let server = MockQueryServer::new().unwrap();
+        let clock = ManualClock::new();
+        let start = clock.now();
+        server.set_clock(clock.clone());
+        // Rotations at 10 s, 40 s, 70 s...
+        server.set_token_rotation(start + Duration::from_secs(10), TOKEN_LIFETIME);
+        let client = super::QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            "127.0.0.1:0",
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+        let mut tokens = TokenHandle::new().with_clock(clock.clone());
+
+        // Only the token rejected at 14 s fails, then the rotations are anticipated
+        client.full_stat_cached(&mut tokens).unwrap();
+        for _ in 0..20 {
+            clock.advance(Duration::from_secs(7));
+            client.full_stat_cached(&mut tokens).unwrap();
+        }
+        assert_eq!(tokens.rejections(), 1);
+        let stats = server
+            .received()
+            .iter()
+            .filter(|p| matches!(p.request, Some(packets::Request::FullStat { .. })))
+            .count();
+        assert_eq!(stats, 22);
+
+        // Token obtained at 140 s, valid until the predicted rotation between
+        // 157 s and 164 s
+        assert_eq!(
+            tokens.estimated_expiry(),
+            Some(start + Duration::from_secs(157) - ROTATION_MARGIN)
+        );
+    }
+
+    /// Query a live server, if one is given in the `LIVE_QUERY_SERVER` environment variable
+    #[test]
+    fn test_live_server() {
+        let Ok(host) = std::env::var("LIVE_QUERY_SERVER") else {
+            return;
+        };
+        let client = super::QueryClient::new(&host).unwrap();
+        let token = client.handshake().unwrap();
+        let basic_stat = client.basic_stat(token).unwrap();
+        let full_stat = client.full_stat(token).unwrap();
         assert_eq!(full_stat.game_id, "MINECRAFT");
+        assert_eq!(basic_stat.gametype, full_stat.gametype);
+        assert_eq!(basic_stat.map, full_stat.map);
+        assert_eq!(basic_stat.maxplayers, full_stat.maxplayers);
+    }
+
+    #[test]
+    fn test_from_stat() {
+        use crate::advertised::FollowOptions;
+
+        // A front server advertising the address of another one
+        let front = MockQueryServer::new().unwrap();
+        let back = MockQueryServer::new().unwrap();
+        let mut advertisement = front.full_stat();
+        advertisement.hostip = "127.0.0.1".into();
+        advertisement.hostport = back.addr().port();
+        front.set_full_stat(advertisement);
+        let mut stat = back.full_stat();
+        stat.hostname = "Back".into();
+        back.set_full_stat(stat);
+
+        let front_stat = super::query(&front.addr().to_string()).unwrap();
+        assert_eq!(front_stat.advertised_addr(), Some(back.addr()));
+        let client = super::QueryClient::from_stat(&front_stat, FollowOptions::default()).unwrap();
+        assert_eq!(client.peer_addr().unwrap(), back.addr());
+        let back_stat = client.full_stat(client.handshake().unwrap()).unwrap();
+        assert_eq!(back_stat.hostname, "Back");
+
+        // Unusable advertisements fail, unless falling back to the queried address
+        for hostip in ["", "0.0.0.0", "play.example.com"] {
+            let mut advertisement = front_stat.clone();
+            advertisement.hostip = hostip.into();
+            let err = super::QueryClient::from_stat(&advertisement, FollowOptions::default())
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{hostip}");
+
+            let options = FollowOptions {
+                peer: Some(front.addr()),
+                ..FollowOptions::default()
+            };
+            let client = super::QueryClient::from_stat(&advertisement, options).unwrap();
+            assert_eq!(
+                client.peer_addr().unwrap(),
+                SocketAddr::new(front.addr().ip(), back.addr().port())
+            );
+        }
     }
 }
@@ -0,0 +1,38 @@
+//! Source of the current time of the time-dependent code.
+//!
+//! The [token cache](crate::token_cache) and the
+//! [mock server](crate::testing::MockQueryServer) read the time from a
+//! [`Clock`], the [`SystemClock`] by default. Tests give them a simulated
+//! clock instead, like [`ManualClock`](crate::testing::ManualClock), to run
+//! without waiting.
+//!
+//! Asynchronous code waits with the timer of its runtime instead, which
+//! `tokio` tests can pause with `tokio::time::pause`.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// A source of time, and a way to wait for it to pass.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current time of the clock.
+    fn now(&self) -> Instant;
+
+    /// Block the current thread until the clock advanced by `duration`.
+    fn sleep(&self, duration: Duration);
+}
+
+/// The monotonic clock of the system.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
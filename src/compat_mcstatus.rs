@@ -0,0 +1,337 @@
+//! Types named after the Query API of the Python [`mcstatus`](https://github.com/py-mine/mcstatus)
+//! library, to help porting code using it.
+//!
+//! This is a migration aid: the wrappers are built from a [`FullStat`], which
+//! remains the primary API of this crate, and only mirror the names and the
+//! shape of the `mcstatus` objects:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::compat_mcstatus::JavaServer;
+//! // server = JavaServer.lookup("play.example.com")
+//! let server = JavaServer::lookup("play.example.com");
+//! // query = server.query()
+//! let query = server.query()?;
+//! println!("{}/{} players", query.players.online, query.players.max);
+//! println!("{} on {}", query.software.brand, query.software.version);
+//! println!("{}", query.motd.to_plain());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Unlike `mcstatus`, [`lookup`](JavaServer::lookup) does not resolve SRV
+//! records, and queries use the port of the address, or [`DEFAULT_PORT`](crate::DEFAULT_PORT).
+
+use std::{io, time::Duration};
+
+#[cfg(feature = "rdns")]
+use crate::rdns::ReverseDns;
+use crate::{
+    blocking,
+    motd::{parse_codes, spans_to_plain, Span},
+    FullStat, DEFAULT_TIMEOUT,
+};
+
+/// A Java Edition server, like `mcstatus.JavaServer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JavaServer {
+    /// Server address, with a port if [`port`](Self::port) is `None`
+    pub address: String,
+    /// Port of the server
+    pub port: Option<u16>,
+    /// Timeout of each response
+    pub timeout: Duration,
+}
+
+impl JavaServer {
+    /// A server at the given address, optionally with a port, like `JavaServer.lookup`.
+    pub fn lookup(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            port: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// A server at the given host and port, like `JavaServer(host, port)`.
+    pub fn new(host: &str, port: u16) -> Self {
+        Self {
+            port: Some(port),
+            ..Self::lookup(host)
+        }
+    }
+
+    /// Use another timeout for each response.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Query the full status of the server, like `JavaServer.query`.
+    pub fn query(&self) -> io::Result<QueryResponse> {
+        let query = blocking::Query::to(self.address.as_str());
+        match self.port {
+            Some(port) => query.port(port),
+            None => query,
+        }
+        .timeout(self.timeout)
+        .full()
+        .map(|stat| QueryResponse::from(&stat))
+    }
+
+    /// Query the full status of the server, like `JavaServer.async_query`.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+    pub async fn async_query(&self) -> io::Result<QueryResponse> {
+        let query = crate::tokio::Query::to(self.address.as_str());
+        match self.port {
+            Some(port) => query.port(port),
+            None => query,
+        }
+        .timeout(self.timeout)
+        .full()
+        .await
+        .map(|stat| QueryResponse::from(&stat))
+    }
+
+    /// Query the full status of the server, then resolve the PTR record of
+    /// its address to fill [`QueryResponse::rdns`].
+    #[cfg(feature = "rdns")]
+    #[cfg_attr(doc, doc(cfg(feature = "rdns")))]
+    pub fn query_with_rdns(&self, reverse_dns: &ReverseDns) -> io::Result<QueryResponse> {
+        use std::net::ToSocketAddrs;
+
+        let mut query = self.query()?;
+        query.rdns = self
+            .host_port()
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .and_then(|addr| reverse_dns.lookup(addr.ip()));
+        Ok(query)
+    }
+
+    /// Query the full status of the server, then resolve the PTR record of
+    /// its address to fill [`QueryResponse::rdns`].
+    #[cfg(all(feature = "rdns", feature = "tokio"))]
+    #[cfg_attr(doc, doc(cfg(all(feature = "rdns", feature = "tokio"))))]
+    pub async fn async_query_with_rdns(
+        &self,
+        reverse_dns: &ReverseDns,
+    ) -> io::Result<QueryResponse> {
+        let mut query = self.async_query().await?;
+        if let Some(addr) = ::tokio::net::lookup_host(self.host_port())
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+        {
+            query.rdns = reverse_dns.lookup_async(addr.ip()).await;
+        }
+        Ok(query)
+    }
+
+    /// Host and port of the server.
+    #[cfg(feature = "rdns")]
+    fn host_port(&self) -> (&str, u16) {
+        if let Some(port) = self.port {
+            return (&self.address, port);
+        }
+        match self.address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().unwrap_or(crate::DEFAULT_PORT)),
+            None => (&self.address, crate::DEFAULT_PORT),
+        }
+    }
+}
+
+/// Full status of a server, like `mcstatus.QueryResponse`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryResponse {
+    /// Message of the day
+    pub motd: Motd,
+    /// Name of the default world
+    pub map: String,
+    /// Online players
+    pub players: QueryPlayers,
+    /// Server version and plugins
+    pub software: QuerySoftware,
+    /// IP that the server may receive connections on
+    pub ip: String,
+    /// Port the server is listening on
+    pub port: u16,
+    /// Game type, usually `"SMP"`
+    pub game_type: String,
+    /// Game ID, usually `"MINECRAFT"`
+    pub game_id: String,
+    /// PTR record of the address of the server, only resolved by
+    /// [`JavaServer::query_with_rdns`]
+    pub rdns: Option<String>,
+}
+
+/// Message of the day, like `mcstatus.motd.Motd`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Motd {
+    /// The MOTD as sent by the server, with formatting codes
+    pub raw: String,
+    /// The MOTD split into styled spans
+    pub parsed: Vec<Span>,
+}
+
+impl Motd {
+    /// The MOTD without formatting codes, like `Motd.to_plain`.
+    pub fn to_plain(&self) -> String {
+        spans_to_plain(&self.parsed)
+    }
+
+    /// The MOTD with formatting codes, like `Motd.to_minecraft`.
+    pub fn to_minecraft(&self) -> String {
+        self.raw.clone()
+    }
+}
+
+/// Players of a server, like `mcstatus.QueryResponse.Players`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlayers {
+    /// How many players are currently online
+    pub online: u32,
+    /// Maximum number of players
+    pub max: u32,
+    /// Names of the players currently online
+    pub list: Vec<String>,
+}
+
+/// Software of a server, like `mcstatus.QueryResponse.Software`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuerySoftware {
+    /// Game version (`"1.20.1"`...)
+    pub version: String,
+    /// Server implementation, `"vanilla"` if the server reports no plugins
+    pub brand: String,
+    /// Plugin names, with their versions if the server sends them
+    pub plugins: Vec<String>,
+}
+
+impl QuerySoftware {
+    /// Split the `plugins` field of a full stat into the brand and the plugins,
+    /// formatted as `brand: plugin 1.0; other 2.0` by Bukkit servers.
+    fn new(version: &str, plugins: &str) -> Self {
+        let (brand, plugins) = match plugins.split_once(':') {
+            Some((brand, list)) => (brand, list.split(';').map(|p| p.trim().into()).collect()),
+            None => (plugins, Vec::new()),
+        };
+        let brand = brand.trim();
+        Self {
+            version: version.to_string(),
+            brand: if brand.is_empty() { "vanilla" } else { brand }.to_string(),
+            plugins,
+        }
+    }
+}
+
+impl From<&FullStat> for QueryResponse {
+    fn from(stat: &FullStat) -> Self {
+        Self {
+            motd: Motd {
+                raw: stat.hostname.to_string(),
+                parsed: parse_codes(&stat.hostname),
+            },
+            map: stat.map.to_string(),
+            players: QueryPlayers {
+                online: stat.numplayers,
+                max: stat.maxplayers,
+                list: stat.player_list.iter().map(ToString::to_string).collect(),
+            },
+            software: QuerySoftware::new(&stat.version, &stat.plugins),
+            ip: stat.hostip.to_string(),
+            port: stat.hostport,
+            game_type: stat.gametype.to_string(),
+            game_id: stat.game_id.to_string(),
+            rdns: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockQueryServer;
+
+    fn fixture(plugins: &str) -> FullStat {
+        FullStat {
+            hostname: "§6A §lMinecraft§r Server".into(),
+            gametype: "SMP".into(),
+            game_id: "MINECRAFT".into(),
+            version: "1.20.1".into(),
+            plugins: plugins.into(),
+            map: "world".into(),
+            numplayers: 2,
+            maxplayers: 20,
+            hostport: 25565,
+            hostip: "127.0.0.1".into(),
+            player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+        }
+    }
+
+    #[test]
+    fn test_query_response() {
+        let query = QueryResponse::from(&fixture(""));
+        assert_eq!(query.motd.raw, "§6A §lMinecraft§r Server");
+        assert_eq!(query.motd.to_plain(), "A Minecraft Server");
+        assert_eq!(query.motd.to_minecraft(), "§6A §lMinecraft§r Server");
+        assert_eq!(query.map, "world");
+        assert_eq!(query.players.online, 2);
+        assert_eq!(query.players.max, 20);
+        assert_eq!(query.players.list, ["AldanTanneo", "Dinnerbone"]);
+        assert_eq!(query.software.version, "1.20.1");
+        assert_eq!(query.software.brand, "vanilla");
+        assert!(query.software.plugins.is_empty());
+        assert_eq!(query.ip, "127.0.0.1");
+        assert_eq!(query.port, 25565);
+        assert_eq!(query.game_type, "SMP");
+        assert_eq!(query.game_id, "MINECRAFT");
+    }
+
+    #[test]
+    fn test_software() {
+        let query = QueryResponse::from(&fixture(
+            "Paper on 1.20.1-R0.1-SNAPSHOT: WorldEdit 7.2.15; LuckPerms 5.4.102",
+        ));
+        assert_eq!(query.software.brand, "Paper on 1.20.1-R0.1-SNAPSHOT");
+        assert_eq!(
+            query.software.plugins,
+            ["WorldEdit 7.2.15", "LuckPerms 5.4.102"]
+        );
+
+        let query = QueryResponse::from(&fixture("CraftBukkit"));
+        assert_eq!(query.software.brand, "CraftBukkit");
+        assert!(query.software.plugins.is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        let server = MockQueryServer::with_stat(fixture("")).unwrap();
+        let query = JavaServer::lookup(&server.addr().to_string())
+            .query()
+            .unwrap();
+        assert_eq!(query, QueryResponse::from(&fixture("")));
+
+        let server = JavaServer::new("127.0.0.1", server.addr().port());
+        assert_eq!(server.query().unwrap().players.online, 2);
+    }
+
+    #[cfg(feature = "rdns")]
+    #[test]
+    fn test_query_with_rdns() {
+        use crate::rdns::tests::stub;
+
+        let server = MockQueryServer::with_stat(fixture("")).unwrap();
+        let query = JavaServer::lookup(&server.addr().to_string())
+            .query_with_rdns(&stub(Duration::ZERO))
+            .unwrap();
+        assert_eq!(query.rdns.as_deref(), Some("backend-03.hosting.example"));
+        assert_eq!(query.players.online, 2);
+
+        // A slow lookup does not fail the query
+        let query = JavaServer::new("127.0.0.1", server.addr().port())
+            .query_with_rdns(&stub(Duration::from_secs(1)))
+            .unwrap();
+        assert_eq!(query.rdns, None);
+    }
+}
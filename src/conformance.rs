@@ -0,0 +1,674 @@
+//! Protocol conformance checks, for implementations of the Query protocol.
+//!
+//! [`check_server`] runs a scripted series of requests against a server, and
+//! compares the raw responses with the packets of vanilla servers, byte for
+//! byte. Unlike the client parsers, which accept the deviations of common
+//! server implementations, every deviation is reported:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::conformance;
+//! # use std::time::Duration;
+//! let report = conformance::check_server("127.0.0.1:25565", Duration::from_secs(1))?;
+//! print!("{report}");
+//! assert!(report.violations().next().is_none());
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Each [`Check`] is a pass, a warning for behaviors which clients handle but
+//! vanilla servers do not have, or a violation. Failed checks carry the raw
+//! bytes of the offending response.
+
+use std::{
+    fmt, io,
+    net::{ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use crate::packets::{self, PacketType, QueryPacket, ResponseHeader, SESSION_MASK};
+use crate::{is_timeout, FullStat, Token};
+
+/// Keys of the full status sent by vanilla servers, in order
+pub const FULL_STAT_KEYS: [&str; 10] = [
+    "hostname",
+    "gametype",
+    "game_id",
+    "version",
+    "plugins",
+    "map",
+    "numplayers",
+    "maxplayers",
+    "hostport",
+    "hostip",
+];
+
+/// Size of the receive buffer, large enough for any UDP datagram
+const MAX_RESPONSE_SIZE: usize = 65_535;
+
+/// Session ID of the requests, with all its significant bits set
+const SESSION_ID: u32 = 0x0A0B0C0D & SESSION_MASK;
+
+/// Session ID with the bits ignored by servers set
+const OVERSIZED_SESSION_ID: u32 = 0xFFFF_FFFF;
+
+/// Outcome of a [`Check`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    /// The server behaves like vanilla servers
+    Pass,
+    /// The server deviates from vanilla servers, in a way clients handle
+    Warning,
+    /// The server breaks the protocol
+    Violation,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "PASS",
+            Self::Warning => "WARN",
+            Self::Violation => "FAIL",
+        })
+    }
+}
+
+/// Result of a single check
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Check {
+    /// Name of the check, like `"handshake_header"`
+    pub name: &'static str,
+    /// Outcome of the check
+    pub outcome: Outcome,
+    /// What was checked, or what went wrong
+    pub detail: String,
+    /// Raw response the check failed on, if any
+    pub raw: Option<Vec<u8>>,
+}
+
+/// Results of the checks of a server, in the order they ran
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Report {
+    /// Every check that ran
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// The check with the given name, if it ran.
+    pub fn get(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|check| check.name == name)
+    }
+
+    /// The checks with the given outcome.
+    pub fn with_outcome(&self, outcome: Outcome) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(move |check| check.outcome == outcome)
+    }
+
+    /// The checks with a [`Warning`](Outcome::Warning) outcome.
+    pub fn warnings(&self) -> impl Iterator<Item = &Check> {
+        self.with_outcome(Outcome::Warning)
+    }
+
+    /// The checks with a [`Violation`](Outcome::Violation) outcome.
+    pub fn violations(&self) -> impl Iterator<Item = &Check> {
+        self.with_outcome(Outcome::Violation)
+    }
+
+    /// Whether every check passed.
+    pub fn is_conformant(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome == Outcome::Pass)
+    }
+
+    fn push(&mut self, name: &'static str, outcome: Outcome, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            outcome,
+            detail: detail.into(),
+            raw: None,
+        });
+    }
+
+    fn pass(&mut self, name: &'static str, detail: impl Into<String>) {
+        self.push(name, Outcome::Pass, detail);
+    }
+
+    /// Record a failed check, with the response it failed on.
+    fn fail(
+        &mut self,
+        name: &'static str,
+        outcome: Outcome,
+        detail: impl Into<String>,
+        raw: &[u8],
+    ) {
+        self.checks.push(Check {
+            name,
+            outcome,
+            detail: detail.into(),
+            raw: Some(raw.to_vec()),
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    /// One line per check, followed by the raw bytes of failed checks.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{} {}: {}", check.outcome, check.name, check.detail)?;
+            if let Some(raw) = &check.raw {
+                write!(f, "   ")?;
+                for byte in raw {
+                    write!(f, " {byte:02X}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A connected socket sending the requests of the checks
+struct Prober {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+}
+
+impl Prober {
+    /// Send a raw request, and wait for the response. Returns `None` if no
+    /// response was received before the timeout.
+    fn request(&mut self, packet: &[u8]) -> io::Result<Option<&[u8]>> {
+        self.socket.send(packet)?;
+        self.buf.resize(MAX_RESPONSE_SIZE, 0);
+        match self.socket.recv(&mut self.buf) {
+            Ok(received) => Ok(Some(&self.buf[..received])),
+            Err(e) if is_timeout(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Check the header of a response: packet type and echoed session ID.
+/// Returns the payload if the header is valid.
+fn check_header<'a>(
+    report: &mut Report,
+    name: &'static str,
+    response: &'a [u8],
+    packet_type: PacketType,
+    session_id: u32,
+) -> Option<&'a [u8]> {
+    let Some((header, payload)) = ResponseHeader::parse(response) else {
+        report.fail(
+            name,
+            Outcome::Violation,
+            "invalid response header",
+            response,
+        );
+        return None;
+    };
+    if header.packet_type != packet_type {
+        let detail = format!(
+            "packet type {:?} instead of {packet_type:?}",
+            header.packet_type
+        );
+        report.fail(name, Outcome::Violation, detail, response);
+        None
+    } else if header.session_id != session_id {
+        let detail = format!(
+            "session ID {:#010X} echoed as {:#010X}",
+            session_id, header.session_id
+        );
+        report.fail(name, Outcome::Violation, detail, response);
+        None
+    } else {
+        report.pass(name, "packet type and session ID echoed");
+        Some(payload)
+    }
+}
+
+/// Split a null-terminated string off the start of the buffer.
+fn cstr<'a>(buf: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let end = memchr::memchr(0, buf)?;
+    let (s, rest) = buf.split_at(end);
+    *buf = &rest[1..];
+    Some(s)
+}
+
+/// Whether the bytes are the decimal representation of an integer.
+fn is_decimal(s: &[u8], signed: bool) -> bool {
+    let digits = match s {
+        [b'-', digits @ ..] if signed => digits,
+        digits => digits,
+    };
+    !digits.is_empty() && digits.len() <= 10 && digits.iter().all(u8::is_ascii_digit)
+}
+
+/// Parse the token of a handshake response, leniently.
+fn response_token(response: &[u8]) -> Option<u32> {
+    let (_, payload) = ResponseHeader::parse(response)?;
+    Token::try_from_payload(payload).ok().map(|token| token.0)
+}
+
+/// Check a handshake response, returning the token.
+fn check_handshake(report: &mut Report, response: &[u8]) -> Option<u32> {
+    let payload = check_header(
+        report,
+        "handshake_header",
+        response,
+        PacketType::Handshake,
+        SESSION_ID,
+    )?;
+    let mut rest = payload;
+    let token = cstr(&mut rest).filter(|token| is_decimal(token, true));
+    let Some(token) = token else {
+        let detail = "token is not a null-terminated decimal integer";
+        report.fail("handshake_token", Outcome::Violation, detail, response);
+        return None;
+    };
+    let parsed = std::str::from_utf8(token).ok()?.parse::<i64>().ok()?;
+    if !rest.is_empty() {
+        let detail = format!("{} bytes after the token", rest.len());
+        report.fail("handshake_token", Outcome::Warning, detail, response);
+    } else {
+        report.pass("handshake_token", "null-terminated decimal token");
+    }
+    Some(parsed as u32)
+}
+
+/// Check a basic status response, field by field.
+fn check_basic_stat(report: &mut Report, response: &[u8]) {
+    let Some(mut payload) = check_header(
+        report,
+        "basic_stat_header",
+        response,
+        PacketType::Stat,
+        SESSION_ID,
+    ) else {
+        return;
+    };
+    let mut fail =
+        |detail: &str| report.fail("basic_stat_fields", Outcome::Violation, detail, response);
+    let strings: Option<Vec<&[u8]>> = (0..5).map(|_| cstr(&mut payload)).collect();
+    let Some(strings) = strings else {
+        return fail("missing motd, gametype, map, numplayers or maxplayers");
+    };
+    if !is_decimal(strings[3], false) || !is_decimal(strings[4], false) {
+        return fail("numplayers or maxplayers is not a decimal integer");
+    }
+    if payload.len() < 2 {
+        return fail("missing hostport");
+    }
+    payload = &payload[2..];
+    if cstr(&mut payload).is_none() {
+        return fail("missing hostip");
+    }
+    if !payload.is_empty() {
+        let detail = format!("{} bytes after hostip", payload.len());
+        report.fail("basic_stat_fields", Outcome::Warning, detail, response);
+    } else {
+        report.pass(
+            "basic_stat_fields",
+            "every field present, with vanilla encodings",
+        );
+    }
+}
+
+/// Check a full status response: padding, fields, separator and players.
+fn check_full_stat(report: &mut Report, response: &[u8]) {
+    let Some(payload) = check_header(
+        report,
+        "full_stat_header",
+        response,
+        PacketType::Stat,
+        SESSION_ID,
+    ) else {
+        return;
+    };
+    let Some(mut rest) = payload.strip_prefix(FullStat::PADDING_START) else {
+        let detail = "payload does not start with the vanilla padding";
+        return report.fail("full_stat_padding", Outcome::Violation, detail, response);
+    };
+    report.pass("full_stat_padding", "vanilla padding");
+
+    // Key-value section, ended by an empty key
+    let mut keys = Vec::new();
+    let mut numplayers = None;
+    loop {
+        let Some(key) = cstr(&mut rest) else {
+            let detail = "key-value section is not terminated";
+            return report.fail("full_stat_fields", Outcome::Violation, detail, response);
+        };
+        if key.is_empty() {
+            break;
+        }
+        let Some(value) = cstr(&mut rest) else {
+            let detail = format!("no value for key {:?}", String::from_utf8_lossy(key));
+            return report.fail("full_stat_fields", Outcome::Violation, detail, response);
+        };
+        if key == b"numplayers" {
+            numplayers = std::str::from_utf8(value)
+                .ok()
+                .and_then(|v| v.parse::<usize>().ok());
+        }
+        keys.push(String::from_utf8_lossy(key).into_owned());
+    }
+    let missing: Vec<&str> = FULL_STAT_KEYS
+        .into_iter()
+        .filter(|key| !keys.iter().any(|k| k == key))
+        .collect();
+    let extra: Vec<&str> = keys
+        .iter()
+        .map(String::as_str)
+        .filter(|key| !FULL_STAT_KEYS.contains(key))
+        .collect();
+    if !missing.is_empty() {
+        let detail = format!("missing keys: {}", missing.join(", "));
+        report.fail("full_stat_fields", Outcome::Violation, detail, response);
+    } else if !extra.is_empty() {
+        let detail = format!("non-vanilla keys: {}", extra.join(", "));
+        report.fail("full_stat_fields", Outcome::Warning, detail, response);
+    } else if keys != FULL_STAT_KEYS {
+        let detail = "keys are not in the vanilla order";
+        report.fail("full_stat_fields", Outcome::Warning, detail, response);
+    } else {
+        report.pass(
+            "full_stat_fields",
+            "every key present, in the vanilla order",
+        );
+    }
+
+    // The terminators of the last value and of the key-value section are
+    // the first two bytes of the separator
+    let Some(mut rest) = rest.strip_prefix(&FullStat::SECTIONS_SEPARATOR[2..]) else {
+        let detail = "sections are not separated by the vanilla separator";
+        return report.fail("full_stat_separator", Outcome::Violation, detail, response);
+    };
+    report.pass("full_stat_separator", "vanilla separator");
+
+    let mut players = 0;
+    loop {
+        match cstr(&mut rest) {
+            Some([]) => break,
+            Some(_) => players += 1,
+            None => {
+                let detail = "player list is not terminated by an empty name";
+                return report.fail("full_stat_players", Outcome::Violation, detail, response);
+            }
+        }
+    }
+    if !rest.is_empty() {
+        let detail = format!("{} bytes after the player list", rest.len());
+        report.fail("full_stat_players", Outcome::Warning, detail, response);
+    } else {
+        report.pass("full_stat_players", format!("{players} players"));
+    }
+    if numplayers != Some(players) {
+        let detail = format!("numplayers is {numplayers:?}, but {players} players are listed");
+        report.fail("player_count", Outcome::Warning, detail, response);
+    } else {
+        report.pass("player_count", "numplayers matches the player list");
+    }
+}
+
+/// Run the conformance checks against a server, waiting at most `timeout`
+/// for each response.
+///
+/// Errors are only returned if requests cannot be sent: servers which do not
+/// answer fail the checks instead.
+pub fn check_server(addr: impl ToSocketAddrs, timeout: Duration) -> io::Result<Report> {
+    let addr = addr
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No address to check"))?;
+    let socket = UdpSocket::bind(crate::unspecified_for(&addr))?;
+    socket.connect(addr)?;
+    socket.set_read_timeout(Some(timeout))?;
+    let mut prober = Prober {
+        socket,
+        buf: Vec::new(),
+    };
+    let mut report = Report::default();
+
+    // Handshake
+    let Some(response) = prober.request(packets::Handshake::new(SESSION_ID).as_bytes())? else {
+        report.push(
+            "handshake_response",
+            Outcome::Violation,
+            "no response to a handshake",
+        );
+        return Ok(report);
+    };
+    report.pass("handshake_response", "handshake answered");
+    let Some(token) = check_handshake(&mut report, response) else {
+        return Ok(report);
+    };
+
+    // Session IDs with the ignored bits set
+    let mut oversized = vec![0xFE, 0xFD, PacketType::Handshake as u8];
+    oversized.extend_from_slice(&OVERSIZED_SESSION_ID.to_be_bytes());
+    match prober.request(&oversized)? {
+        None => report.push(
+            "oversized_session_id",
+            Outcome::Violation,
+            "handshake with an unmasked session ID not answered",
+        ),
+        Some(response) => match ResponseHeader::parse(response) {
+            Some((header, _)) if header.session_id == OVERSIZED_SESSION_ID => {
+                report.pass("oversized_session_id", "unmasked session ID echoed as is")
+            }
+            Some((header, _)) if header.session_id == OVERSIZED_SESSION_ID & SESSION_MASK => {
+                let detail = "unmasked session ID echoed masked";
+                report.fail("oversized_session_id", Outcome::Warning, detail, response)
+            }
+            _ => {
+                let detail = "unmasked session ID not echoed";
+                report.fail("oversized_session_id", Outcome::Violation, detail, response)
+            }
+        },
+    }
+    // The handshake above may have replaced the token
+    let token = match prober.request(packets::Handshake::new(SESSION_ID).as_bytes())? {
+        Some(response) => response_token(response).unwrap_or(token),
+        None => token,
+    };
+
+    // Status requests
+    match prober.request(packets::BasicStat::new(SESSION_ID, token).as_bytes())? {
+        Some(response) => check_basic_stat(&mut report, response),
+        None => report.push(
+            "basic_stat_header",
+            Outcome::Violation,
+            "basic status request not answered",
+        ),
+    }
+    match prober.request(packets::FullStat::new(SESSION_ID, token).as_bytes())? {
+        Some(response) => check_full_stat(&mut report, response),
+        None => report.push(
+            "full_stat_header",
+            Outcome::Violation,
+            "full status request not answered",
+        ),
+    }
+
+    // Tokens
+    let invalid = token.wrapping_add(1);
+    match prober.request(packets::BasicStat::new(SESSION_ID, invalid).as_bytes())? {
+        None => report.pass("invalid_token", "request with an invalid token ignored"),
+        Some(response) => {
+            let detail = "request with an invalid token answered";
+            report.fail("invalid_token", Outcome::Violation, detail, response);
+        }
+    }
+    match prober.request(packets::Handshake::new(SESSION_ID).as_bytes())? {
+        None => report.push(
+            "token_replacement",
+            Outcome::Violation,
+            "second handshake not answered",
+        ),
+        Some(response) => match response_token(response) {
+            Some(new) if new != token => {
+                match prober.request(packets::BasicStat::new(SESSION_ID, token).as_bytes())? {
+                    None => report.pass(
+                        "token_replacement",
+                        "new handshake invalidates the previous token",
+                    ),
+                    Some(response) => {
+                        let detail = "previous token still accepted after a new handshake";
+                        report.fail("token_replacement", Outcome::Warning, detail, response);
+                    }
+                }
+            }
+            Some(_) => report.pass("token_replacement", "same token sent again"),
+            None => {
+                let detail = "invalid second handshake response";
+                report.fail("token_replacement", Outcome::Violation, detail, response);
+            }
+        },
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{SocketAddr, UdpSocket},
+        time::Duration,
+    };
+
+    use super::{check_server, Outcome};
+    use crate::packets::{write_response, PacketType, Request};
+    use crate::testing::{sample_stat, Faults, MockQueryServer};
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_mock_server_conforms() {
+        let server = MockQueryServer::new().unwrap();
+        let report = check_server(server.addr(), TIMEOUT).unwrap();
+        assert!(report.is_conformant(), "{report}");
+        assert_eq!(report.checks.len(), 14, "{report}");
+    }
+
+    #[test]
+    fn test_faulty_mock_server() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                wrong_session_id: true,
+                ..Faults::default()
+            },
+        );
+        let report = check_server(server.addr(), TIMEOUT).unwrap();
+        let check = report.get("handshake_header").unwrap();
+        assert_eq!(check.outcome, Outcome::Violation);
+        assert!(check.raw.is_some());
+
+        server.set_faults(PacketType::Handshake, Faults::default());
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                corrupt: true,
+                ..Faults::default()
+            },
+        );
+        let report = check_server(server.addr(), TIMEOUT).unwrap();
+        let violations: Vec<&str> = report.violations().map(|check| check.name).collect();
+        assert_eq!(
+            violations,
+            ["basic_stat_fields", "full_stat_padding"],
+            "{report}"
+        );
+
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                wrong_packet_type: true,
+                ..Faults::default()
+            },
+        );
+        let report = check_server(server.addr(), TIMEOUT).unwrap();
+        let violations: Vec<&str> = report.violations().map(|check| check.name).collect();
+        assert_eq!(
+            violations,
+            ["basic_stat_header", "full_stat_header"],
+            "{report}"
+        );
+    }
+
+    /// Answer requests with `respond` from a background thread, until no
+    /// request is received for a second.
+    fn raw_server(respond: impl Fn(Request) -> Option<Vec<u8>> + Send + 'static) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let addr = socket.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let mut buf = [0; 64];
+            while let Ok((received, source)) = socket.recv_from(&mut buf) {
+                if let Some(response) = Request::parse(&buf[..received]).and_then(&respond) {
+                    socket.send_to(&response, source).unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    /// Replace the first occurrence of `from` in `bytes`.
+    fn replace(bytes: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+        let start = bytes.windows(from.len()).position(|w| w == from).unwrap();
+        [&bytes[..start], to, &bytes[start + from.len()..]].concat()
+    }
+
+    #[test]
+    fn test_broken_server() {
+        // Padded token, masked session IDs, a missing key, a player count
+        // mismatch, and every token accepted
+        let addr = raw_server(|request| match request {
+            Request::Handshake { session_id } => Some(write_response(
+                PacketType::Handshake,
+                session_id & crate::packets::SESSION_MASK,
+                b"9513307\0\0\0",
+            )),
+            Request::BasicStat { session_id, .. } => {
+                let stat = crate::BasicStat::from(&sample_stat());
+                Some(write_response(
+                    PacketType::Stat,
+                    session_id,
+                    &stat.to_payload(),
+                ))
+            }
+            Request::FullStat { session_id, .. } => {
+                let payload = replace(&sample_stat().to_payload(), b"game_id\0MINECRAFT\0", b"");
+                let payload = replace(&payload, b"numplayers\x002\0", b"numplayers\x003\0");
+                Some(write_response(PacketType::Stat, session_id, &payload))
+            }
+        });
+        let report = check_server(addr, TIMEOUT).unwrap();
+        let outcome = |name| report.get(name).unwrap().outcome;
+        assert_eq!(outcome("handshake_token"), Outcome::Warning, "{report}");
+        assert_eq!(outcome("oversized_session_id"), Outcome::Warning);
+        assert_eq!(outcome("basic_stat_fields"), Outcome::Pass);
+        assert_eq!(outcome("full_stat_fields"), Outcome::Violation);
+        assert_eq!(outcome("player_count"), Outcome::Warning);
+        assert_eq!(outcome("invalid_token"), Outcome::Violation);
+        assert_eq!(outcome("token_replacement"), Outcome::Pass);
+
+        let missing = report.get("full_stat_fields").unwrap();
+        assert_eq!(missing.detail, "missing keys: game_id");
+        assert!(String::from_utf8_lossy(missing.raw.as_ref().unwrap()).contains("hostname"));
+    }
+
+    #[test]
+    fn test_silent_server() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let report = check_server(socket.local_addr().unwrap(), TIMEOUT).unwrap();
+        assert_eq!(report.checks.len(), 1);
+        assert_eq!(report.violations().count(), 1);
+    }
+}
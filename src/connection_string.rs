@@ -0,0 +1,483 @@
+//! Query targets as connection strings, like `mc://host:port?timeout=2s`.
+//!
+//! A [`ConnectionString`] describes a server and the options of its queries,
+//! and converts into the `Query` builders, such as [`blocking::Query`]:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::blocking::Query;
+//! let query: Query = "mc://play.example.com:25565?timeout=2s&retries=2".parse()?;
+//! let full_stat = query.full()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The grammar is `mc://host[:port][/][?key=value[&key=value]...]`, with IPv6
+//! hosts in brackets. Their [zone identifier](crate::zone) may be
+//! percent-encoded like in URIs, as in `mc://[fe80::1%25eth0]:25565`. Strings without the `mc://` scheme are plain
+//! `host[:port]` targets, as given to [`Query::to`](crate::blocking::Query::to), or
+//! IPv6 addresses without brackets nor port.
+//!
+//! The query parameters are:
+//!
+//! | Key       | Value                                                    | Builder option                    |
+//! |-----------|----------------------------------------------------------|-----------------------------------|
+//! | `timeout` | a duration in `ms` or `s`, like `500ms` or `1.5s`        | [`timeout`](crate::blocking::Query::timeout) |
+//! | `retries` | a number                                                 | [`retries`](crate::blocking::Query::retries) |
+//! | `bind`    | a local socket address, like `0.0.0.0:0` or `[::]:25566` | [`bind`](crate::blocking::Query::bind)   |
+//! | `encoding`| `latin1`, the encoding of every Query response           | none                              |
+//! | `srv`     | `false`: SRV records are never resolved                  | none                              |
+//!
+//! Each key may only appear once. Unknown keys, and the values of `encoding`
+//! and `srv` which the crate does not support, are errors rather than being
+//! ignored.
+//!
+//! [`blocking::Query`]: crate::blocking::Query
+
+use std::{error::Error, fmt, io, net::SocketAddr, str::FromStr, time::Duration};
+
+use crate::{zone, DEFAULT_PORT};
+
+/// Scheme of the connection strings
+pub const SCHEME: &str = "mc://";
+
+/// A server and the options of its queries, parsed from a connection string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionString {
+    /// Host name or IP address of the server, without brackets
+    pub host: String,
+    /// Port of the server, if given
+    pub port: Option<u16>,
+    /// Timeout of each response
+    pub timeout: Option<Duration>,
+    /// Number of retries on timeouts
+    pub retries: Option<u32>,
+    /// Local address to bind the client socket to
+    pub bind: Option<SocketAddr>,
+}
+
+impl ConnectionString {
+    /// Port of the server, or the [default port](DEFAULT_PORT).
+    pub fn port_or_default(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+}
+
+/// Error of an invalid connection string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionStringError {
+    /// The scheme is not `mc://`
+    UnknownScheme(String),
+    /// The host is empty or malformed
+    InvalidHost(String),
+    /// The port is not a number between 0 and 65535
+    InvalidPort(String),
+    /// A query parameter is not `key=value`
+    MalformedParameter(String),
+    /// A query parameter has an unknown key
+    UnknownKey(String),
+    /// A query parameter appears twice
+    DuplicateKey(String),
+    /// A query parameter has an invalid value
+    InvalidValue {
+        /// Key of the parameter
+        key: String,
+        /// Value of the parameter
+        value: String,
+        /// Description of the valid values
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ConnectionStringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownScheme(scheme) => {
+                write!(f, "Unknown scheme `{scheme}` in connection string, expected `mc`.")
+            }
+            Self::InvalidHost(host) => write!(f, "Invalid host `{host}` in connection string."),
+            Self::InvalidPort(port) => write!(f, "Invalid port `{port}` in connection string."),
+            Self::MalformedParameter(param) => {
+                write!(f, "Malformed parameter `{param}` in connection string, expected `key=value`.")
+            }
+            Self::UnknownKey(key) => write!(f, "Unknown parameter `{key}` in connection string."),
+            Self::DuplicateKey(key) => {
+                write!(f, "Parameter `{key}` appears twice in connection string.")
+            }
+            Self::InvalidValue {
+                key,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value `{value}` of parameter `{key}` in connection string, expected {expected}."
+            ),
+        }
+    }
+}
+
+impl Error for ConnectionStringError {}
+
+impl From<ConnectionStringError> for io::Error {
+    fn from(e: ConnectionStringError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+impl FromStr for ConnectionString {
+    type Err = ConnectionStringError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(rest) = s.strip_prefix(SCHEME) else {
+            if let Some((scheme, _)) = s.split_once("://") {
+                return Err(ConnectionStringError::UnknownScheme(scheme.to_string()));
+            }
+            return plain_target(s);
+        };
+
+        let (authority, params) = match rest.split_once('?') {
+            Some((authority, params)) => (authority, Some(params)),
+            None => (rest, None),
+        };
+        let authority = authority.strip_suffix('/').unwrap_or(authority);
+        let mut res = authority_target(authority)?;
+
+        let mut seen: Vec<&str> = Vec::new();
+        for param in params
+            .filter(|params| !params.is_empty())
+            .into_iter()
+            .flat_map(|params| params.split('&'))
+        {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| ConnectionStringError::MalformedParameter(param.to_string()))?;
+            if seen.contains(&key) {
+                return Err(ConnectionStringError::DuplicateKey(key.to_string()));
+            }
+            seen.push(key);
+
+            let invalid = |expected| ConnectionStringError::InvalidValue {
+                key: key.to_string(),
+                value: value.to_string(),
+                expected,
+            };
+            match key {
+                "timeout" => {
+                    res.timeout = Some(
+                        parse_duration(value).ok_or_else(|| invalid("a duration in ms or s"))?,
+                    )
+                }
+                "retries" => res.retries = Some(value.parse().map_err(|_| invalid("a number"))?),
+                "bind" => {
+                    res.bind = Some(zoned_bind(value).ok_or_else(|| invalid("a socket address"))?)
+                }
+                "encoding" if value.eq_ignore_ascii_case("latin1") => {}
+                "encoding" => return Err(invalid("`latin1`")),
+                "srv" if value == "false" => {}
+                "srv" => return Err(invalid("`false`")),
+                _ => return Err(ConnectionStringError::UnknownKey(key.to_string())),
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// A target without scheme: `host[:port]`, or an IPv6 address.
+fn plain_target(s: &str) -> Result<ConnectionString, ConnectionStringError> {
+    if s.matches(':').count() > 1 && !s.starts_with('[') {
+        return Ok(target(s, None));
+    }
+    authority_target(s)
+}
+
+/// The `host[:port]` part of a connection string.
+fn authority_target(authority: &str) -> Result<ConnectionString, ConnectionStringError> {
+    let invalid_host = || ConnectionStringError::InvalidHost(authority.to_string());
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, rest) = bracketed.split_once(']').ok_or_else(invalid_host)?;
+            match rest {
+                "" => (host, None),
+                _ => (host, Some(rest.strip_prefix(':').ok_or_else(invalid_host)?)),
+            }
+        }
+        None => match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        },
+    };
+    if host.is_empty() || host.contains(['/', '?', '#', '@', '[', ']']) {
+        return Err(invalid_host());
+    }
+    let host = zoned_host(host).ok_or_else(invalid_host)?;
+    let port = port
+        .map(|port| {
+            port.parse()
+                .map_err(|_| ConnectionStringError::InvalidPort(port.to_string()))
+        })
+        .transpose()?;
+    Ok(target(&host, port))
+}
+
+/// Decode the zone identifier of an IPv6 address, percent-encoded as `%25` like
+/// in URIs (`fe80::1%25eth0`), or given as is (`fe80::1%eth0`).
+///
+/// Returns `None` if the host has a zone identifier but is not an IPv6 address,
+/// or if the zone identifier is empty.
+fn zoned_host(host: &str) -> Option<String> {
+    let Some((ip, zone)) = host.split_once('%') else {
+        return Some(host.to_string());
+    };
+    let zone = zone
+        .strip_prefix("25")
+        .filter(|z| !z.is_empty())
+        .unwrap_or(zone);
+    if zone.is_empty() || ip.parse::<std::net::Ipv6Addr>().is_err() {
+        return None;
+    }
+    Some(format!("{ip}%{zone}"))
+}
+
+/// Parse a local socket address, which may have a zone identifier.
+fn zoned_bind(value: &str) -> Option<SocketAddr> {
+    if !value.contains('%') {
+        return value.parse().ok();
+    }
+    let (host, port) = value.strip_prefix('[')?.split_once("]:")?;
+    zone::parse_socket_addr(&format!("[{}]:{port}", zoned_host(host)?)).ok()
+}
+
+fn target(host: &str, port: Option<u16>) -> ConnectionString {
+    ConnectionString {
+        host: host.to_string(),
+        port,
+        timeout: None,
+        retries: None,
+        bind: None,
+    }
+}
+
+/// Parse a duration in milliseconds or seconds, such as `500ms`, `2s` or `1.5s`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        return ms.parse().ok().map(Duration::from_millis);
+    }
+    let secs: f64 = s.strip_suffix('s')?.parse().ok()?;
+    // Reject signs and exponents, which `f64` accepts
+    if !s.starts_with(|c: char| c.is_ascii_digit()) || s.contains(['e', 'E']) {
+        return None;
+    }
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use super::*;
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn test_valid() {
+        let bind = |s: &str| s.parse::<SocketAddr>().ok();
+        for (s, host, port, timeout, retries, bind) in [
+            (
+                "play.example.com",
+                "play.example.com",
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "127.0.0.1:25566",
+                "127.0.0.1",
+                Some(25566),
+                None,
+                None,
+                None,
+            ),
+            ("::1", "::1", None, None, None, None),
+            ("[::1]:25566", "::1", Some(25566), None, None, None),
+            (
+                "mc://play.example.com",
+                "play.example.com",
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "mc://play.example.com/",
+                "play.example.com",
+                None,
+                None,
+                None,
+                None,
+            ),
+            (
+                "mc://localhost:25566",
+                "localhost",
+                Some(25566),
+                None,
+                None,
+                None,
+            ),
+            ("mc://[::1]", "::1", None, None, None, None),
+            ("mc://[::1]:25566/?", "::1", Some(25566), None, None, None),
+            ("mc://host?timeout=2s", "host", None, ms(2000), None, None),
+            ("mc://host?timeout=500ms", "host", None, ms(500), None, None),
+            ("mc://host?timeout=1.5s", "host", None, ms(1500), None, None),
+            ("mc://host?timeout=0ms", "host", None, ms(0), None, None),
+            (
+                "mc://host:1?timeout=2s&retries=2&encoding=latin1&srv=false",
+                "host",
+                Some(1),
+                ms(2000),
+                Some(2),
+                None,
+            ),
+            (
+                "mc://host?bind=0.0.0.0:25567&encoding=LATIN1",
+                "host",
+                None,
+                None,
+                None,
+                bind("0.0.0.0:25567"),
+            ),
+            (
+                "mc://host?bind=[::]:0",
+                "host",
+                None,
+                None,
+                None,
+                bind("[::]:0"),
+            ),
+            ("fe80::1%eth0", "fe80::1%eth0", None, None, None, None),
+            (
+                "[fe80::1%eth0]:25566",
+                "fe80::1%eth0",
+                Some(25566),
+                None,
+                None,
+                None,
+            ),
+            (
+                "mc://[fe80::1%25eth0]:25566",
+                "fe80::1%eth0",
+                Some(25566),
+                None,
+                None,
+                None,
+            ),
+            ("mc://[fe80::1%2]", "fe80::1%2", None, None, None, None),
+            (
+                "mc://host?bind=[fe80::1%252]:0",
+                "host",
+                None,
+                None,
+                None,
+                bind("[fe80::1%2]:0"),
+            ),
+        ] {
+            let expected = ConnectionString {
+                host: host.into(),
+                port,
+                timeout,
+                retries,
+                bind,
+            };
+            assert_eq!(s.parse(), Ok(expected), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        use ConnectionStringError::*;
+
+        let value = |key: &str, value: &str, expected| InvalidValue {
+            key: key.into(),
+            value: value.into(),
+            expected,
+        };
+        for (s, err) in [
+            ("http://host", UnknownScheme("http".into())),
+            ("MC://host", UnknownScheme("MC".into())),
+            ("mc://", InvalidHost("".into())),
+            ("mc://:25565", InvalidHost(":25565".into())),
+            ("mc://user@host", InvalidHost("user@host".into())),
+            ("mc://host/path", InvalidHost("host/path".into())),
+            ("mc://[::1", InvalidHost("[::1".into())),
+            ("mc://[::1]25565", InvalidHost("[::1]25565".into())),
+            ("mc://host:", InvalidPort("".into())),
+            ("mc://host:65536", InvalidPort("65536".into())),
+            ("host:port", InvalidPort("port".into())),
+            ("", InvalidHost("".into())),
+            ("mc://host?timeout", MalformedParameter("timeout".into())),
+            ("mc://host?timeout=1s&", MalformedParameter("".into())),
+            ("mc://host?tiemout=1s", UnknownKey("tiemout".into())),
+            (
+                "mc://host?retries=1&retries=2",
+                DuplicateKey("retries".into()),
+            ),
+            (
+                "mc://host?timeout=2",
+                value("timeout", "2", "a duration in ms or s"),
+            ),
+            (
+                "mc://host?timeout=-1s",
+                value("timeout", "-1s", "a duration in ms or s"),
+            ),
+            (
+                "mc://host?timeout=1e3s",
+                value("timeout", "1e3s", "a duration in ms or s"),
+            ),
+            (
+                "mc://host?timeout=1.5ms",
+                value("timeout", "1.5ms", "a duration in ms or s"),
+            ),
+            (
+                "mc://host?timeout=",
+                value("timeout", "", "a duration in ms or s"),
+            ),
+            ("mc://host?retries=-1", value("retries", "-1", "a number")),
+            (
+                "mc://host?bind=0.0.0.0",
+                value("bind", "0.0.0.0", "a socket address"),
+            ),
+            (
+                "mc://host?encoding=utf8",
+                value("encoding", "utf8", "`latin1`"),
+            ),
+            ("mc://host?srv=true", value("srv", "true", "`false`")),
+            ("mc://[host%25eth0]", InvalidHost("[host%25eth0]".into())),
+            ("mc://[fe80::1%]", InvalidHost("[fe80::1%]".into())),
+            (
+                "mc://host?bind=[fe80::1%25nonexistent0]:0",
+                value("bind", "[fe80::1%25nonexistent0]:0", "a socket address"),
+            ),
+        ] {
+            assert_eq!(s.parse::<ConnectionString>(), Err(err), "{s}");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = "mc://host?encoding=utf8"
+            .parse::<ConnectionString>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid value `utf8` of parameter `encoding` in connection string, expected `latin1`."
+        );
+        let err = io::Error::from(err);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let target = "mc://127.0.0.1?bind=127.0.0.1:0"
+            .parse::<ConnectionString>()
+            .unwrap();
+        assert_eq!(target.port_or_default(), DEFAULT_PORT);
+        assert_eq!(target.bind, Some((Ipv4Addr::LOCALHOST, 0).into()));
+    }
+}
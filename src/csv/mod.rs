@@ -0,0 +1,206 @@
+//! Export of server statuses to CSV, to open them in a spreadsheet.
+//!
+//! Every row starts with the address the server was queried at, followed by
+//! the status fields with their full stat key names, in the order vanilla
+//! servers send them, and the player names separated by `;`:
+//!
+//! ```rust
+//! # use minecraft_server_query::{csv, FullStat};
+//! let stat = FullStat {
+//!     hostname: "A Minecraft Server".into(),
+//!     gametype: "SMP".into(),
+//!     game_id: "MINECRAFT".into(),
+//!     version: "1.20.1".into(),
+//!     plugins: "".into(),
+//!     map: "world".into(),
+//!     numplayers: 2,
+//!     maxplayers: 20,
+//!     hostport: 25565,
+//!     hostip: "127.0.0.1".into(),
+//!     player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+//! };
+//!
+//! assert_eq!(
+//!     csv::full_stats_to_string([("127.0.0.1:25565", &stat)]),
+//!     "target,hostname,gametype,game_id,version,plugins,map,numplayers,maxplayers,hostport,hostip,players\r\n\
+//!      127.0.0.1:25565,A Minecraft Server,SMP,MINECRAFT,1.20.1,,world,2,20,25565,127.0.0.1,AldanTanneo;Dinnerbone\r\n",
+//! );
+//! ```
+//!
+//! The output follows [RFC 4180](https://www.rfc-editor.org/rfc/rfc4180):
+//! lines end with `\r\n`, and fields containing commas, quotes or line breaks
+//! are quoted, with their quotes doubled.
+
+use std::io::{self, Write};
+
+use crate::{AnyStat, BasicStat, FullStat, FullStatRef};
+
+/// Columns of the CSV output
+pub const HEADER: [&str; 12] = {
+    let keys = FullStatRef::KEYS;
+    let mut header = ["target"; 12];
+    let mut i = 0;
+    while i < keys.len() {
+        header[i + 1] = keys[i];
+        i += 1;
+    }
+    header[11] = "players";
+    header
+};
+
+/// Write a field, quoting it if needed.
+fn write_field(w: &mut impl Write, field: &str) -> io::Result<()> {
+    if field.contains([',', '"', '\n', '\r']) {
+        write!(w, "\"{}\"", field.replace('"', "\"\""))
+    } else {
+        w.write_all(field.as_bytes())
+    }
+}
+
+/// Write a row of fields, terminated by a line break.
+fn write_row<'a>(w: &mut impl Write, fields: impl IntoIterator<Item = &'a str>) -> io::Result<()> {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            w.write_all(b",")?;
+        }
+        write_field(w, field)?;
+    }
+    w.write_all(b"\r\n")
+}
+
+/// Write the row of a full status.
+fn write_full(w: &mut impl Write, target: &str, stat: &FullStat) -> io::Result<()> {
+    let players: Vec<&str> = stat.player_list.iter().map(|p| p.as_str()).collect();
+    write_row(
+        w,
+        [
+            target,
+            &stat.hostname,
+            &stat.gametype,
+            &stat.game_id,
+            &stat.version,
+            &stat.plugins,
+            &stat.map,
+            &stat.numplayers.to_string(),
+            &stat.maxplayers.to_string(),
+            &stat.hostport.to_string(),
+            &stat.hostip,
+            &players.join(";"),
+        ],
+    )
+}
+
+/// Write the row of a basic status, leaving the full status fields empty.
+fn write_basic(w: &mut impl Write, target: &str, stat: &BasicStat) -> io::Result<()> {
+    write_row(
+        w,
+        [
+            target,
+            &stat.motd,
+            &stat.gametype,
+            "",
+            "",
+            "",
+            &stat.map,
+            &stat.numplayers.to_string(),
+            &stat.maxplayers.to_string(),
+            &stat.hostport.to_string(),
+            &stat.hostip,
+            "",
+        ],
+    )
+}
+
+/// Write the header and a row per server, with the address it was queried at.
+///
+/// Basic statuses, without the full status fields, leave their columns empty.
+pub fn write_stats<'a>(
+    mut w: impl Write,
+    stats: impl IntoIterator<Item = (&'a str, &'a AnyStat)>,
+) -> io::Result<()> {
+    write_row(&mut w, HEADER)?;
+    for (target, stat) in stats {
+        match stat {
+            AnyStat::Full(stat) => write_full(&mut w, target, stat)?,
+            AnyStat::Basic(stat) => write_basic(&mut w, target, stat)?,
+        }
+    }
+    Ok(())
+}
+
+/// Like [`write_stats`], for full statuses.
+pub fn write_full_stats<'a>(
+    mut w: impl Write,
+    stats: impl IntoIterator<Item = (&'a str, &'a FullStat)>,
+) -> io::Result<()> {
+    write_row(&mut w, HEADER)?;
+    for (target, stat) in stats {
+        write_full(&mut w, target, stat)?;
+    }
+    Ok(())
+}
+
+/// Render statuses to a CSV string, see [`write_stats`].
+pub fn stats_to_string<'a>(stats: impl IntoIterator<Item = (&'a str, &'a AnyStat)>) -> String {
+    let mut res = Vec::new();
+    write_stats(&mut res, stats).expect("Writing to a Vec cannot fail");
+    String::from_utf8(res).expect("Statuses are valid UTF-8")
+}
+
+/// Render full statuses to a CSV string, see [`write_stats`].
+pub fn full_stats_to_string<'a>(
+    stats: impl IntoIterator<Item = (&'a str, &'a FullStat)>,
+) -> String {
+    let mut res = Vec::new();
+    write_full_stats(&mut res, stats).expect("Writing to a Vec cannot fail");
+    String::from_utf8(res).expect("Statuses are valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sample_stat;
+
+    fn pathological() -> FullStat {
+        FullStat {
+            hostname: "§6Welcome, \"friends\"!\nLine two,\r\n\"\"".into(),
+            plugins: "Paper: WorldEdit 7.2; \"Essentials\", 2.20".into(),
+            player_list: Vec::new(),
+            ..sample_stat()
+        }
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut row = Vec::new();
+        write_row(&mut row, ["plain", "a,b", "say \"hi\"", "two\nlines", ""]).unwrap();
+        assert_eq!(row, b"plain,\"a,b\",\"say \"\"hi\"\"\",\"two\nlines\",\r\n");
+    }
+
+    #[test]
+    fn test_golden_full_stats() {
+        let stats = [sample_stat(), pathological()];
+        assert_eq!(
+            full_stats_to_string([
+                ("127.0.0.1:25565", &stats[0]),
+                ("play.example.com", &stats[1]),
+            ]),
+            include_str!("golden/full_stats.csv")
+        );
+    }
+
+    #[test]
+    fn test_golden_stats() {
+        let stats = [
+            AnyStat::Full(sample_stat()),
+            AnyStat::Basic(BasicStat::from(&pathological())),
+        ];
+        let mut res = Vec::new();
+        write_stats(
+            &mut res,
+            [("127.0.0.1:25565", &stats[0]), ("[::1]:25565", &stats[1])],
+        )
+        .unwrap();
+        assert_eq!(res, include_bytes!("golden/stats.csv"));
+    }
+}
@@ -0,0 +1,404 @@
+//! Non-fatal problems found while parsing a payload.
+//!
+//! The `from_payload_with_diagnostics` parsers, such as
+//! [`FullStat::from_payload_with_diagnostics`](crate::FullStat::from_payload_with_diagnostics),
+//! recover from some malformed payloads instead of failing, and return the
+//! [`Warning`]s explaining how along with the status:
+//!
+//! ```rust
+//! # use minecraft_server_query::{diagnostics::Warning, BasicStat, ParseOptions};
+//! let payload = b"A Minecraft Server\0SMP\0world\02\0twenty\0\xDD\x63127.0.0.1\0";
+//! assert!(BasicStat::from_payload(payload).is_err());
+//!
+//! let (stat, diagnostics) =
+//!     BasicStat::from_payload_with_diagnostics(payload, &ParseOptions::default())?;
+//! assert_eq!(stat.maxplayers, 0);
+//! assert_eq!(
+//!     diagnostics.warnings(),
+//!     [Warning::DefaultedNumber { field: "maxplayers", offset: Some(31) }]
+//! );
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Limits exceeded, and payloads missing string fields, still fail.
+
+use std::{fmt, io};
+
+/// UTF-8 encoding of U+FFFD, the replacement character
+const REPLACEMENT_CHARACTER: &[u8] = "\u{FFFD}".as_bytes();
+
+/// A non-fatal problem of a payload, located by its offset in the payload
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Warning {
+    /// A number is invalid, or missing if there is no offset, and was read as
+    /// zero
+    DefaultedNumber {
+        /// Name of the field
+        field: &'static str,
+        /// Offset of the value
+        offset: Option<usize>,
+    },
+    /// The key-value section ends with a key without a value, which was
+    /// ignored
+    DanglingKey {
+        /// Offset of the key
+        offset: usize,
+    },
+    /// The separator between the key-value section and the player list is
+    /// missing or incomplete. The player list is read from the offset, which
+    /// is the end of the payload if the list was not found.
+    SeparatorFallback {
+        /// Offset of the player list
+        offset: usize,
+    },
+    /// The payload ends in the middle of a field, as if it was truncated
+    SuspectedTruncation {
+        /// Length of the payload
+        len: usize,
+    },
+    /// A string contains replacement characters, encoded in UTF-8, sent by
+    /// servers which failed to encode other characters
+    ReplacementCharacter {
+        /// Name of the field, `player_list` for player names
+        field: &'static str,
+        /// Offset of the string
+        offset: usize,
+    },
+    /// Player names were removed from the player list by a
+    /// [`PlayerFilter`](crate::player_filter::PlayerFilter)
+    FilteredPlayers {
+        /// Number of names removed
+        count: usize,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DefaultedNumber {
+                field,
+                offset: Some(offset),
+            } => write!(f, "invalid `{field}` at byte {offset}, read as 0"),
+            Self::DefaultedNumber {
+                field,
+                offset: None,
+            } => write!(f, "missing `{field}`, read as 0"),
+            Self::DanglingKey { offset } => {
+                write!(f, "key without a value at byte {offset}, ignored")
+            }
+            Self::SeparatorFallback { offset } => {
+                write!(
+                    f,
+                    "missing sections separator, player list read from byte {offset}"
+                )
+            }
+            Self::SuspectedTruncation { len } => {
+                write!(f, "payload of {len} bytes looks truncated")
+            }
+            Self::ReplacementCharacter { field, offset } => {
+                write!(f, "replacement characters in `{field}` at byte {offset}")
+            }
+            Self::FilteredPlayers { count } => {
+                write!(f, "{count} player names filtered out")
+            }
+        }
+    }
+}
+
+/// Warnings of a parsed payload, in the order they were found.
+///
+/// Empty diagnostics do not allocate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostics {
+    warnings: Vec<Warning>,
+}
+
+impl Diagnostics {
+    /// Whether the payload was parsed without warnings.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// The warnings of the payload.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Iterate over the warnings of the payload.
+    pub fn iter(&self) -> std::slice::Iter<'_, Warning> {
+        self.warnings.iter()
+    }
+
+    /// Record a warning found after parsing.
+    pub(crate) fn push(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a Warning;
+    type IntoIter = std::slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = Warning;
+    type IntoIter = std::vec::IntoIter<Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.warnings.into_iter()
+    }
+}
+
+/// Recovery from the non-fatal problems of a payload: they are warnings when
+/// diagnostics are collected, and errors or ignored otherwise.
+pub(crate) struct Collector<'p, 'd> {
+    payload: &'p [u8],
+    diagnostics: Option<&'d mut Diagnostics>,
+}
+
+impl<'p, 'd> Collector<'p, 'd> {
+    /// Collect the warnings of a payload.
+    pub(crate) fn new(payload: &'p [u8], diagnostics: &'d mut Diagnostics) -> Self {
+        Self {
+            payload,
+            diagnostics: Some(diagnostics),
+        }
+    }
+
+    /// Do not recover from any problem.
+    pub(crate) fn disabled() -> Self {
+        Self {
+            payload: &[],
+            diagnostics: None,
+        }
+    }
+
+    /// Whether the warnings are collected.
+    pub(crate) fn enabled(&self) -> bool {
+        self.diagnostics.is_some()
+    }
+
+    /// Record a warning, if the warnings are collected.
+    pub(crate) fn warn(&mut self, warning: Warning) {
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.warnings.push(warning);
+        }
+    }
+
+    /// Offset of a slice of the payload.
+    pub(crate) fn offset(&self, bytes: &[u8]) -> usize {
+        (bytes.as_ptr() as usize).saturating_sub(self.payload.as_ptr() as usize)
+    }
+
+    /// Parse a number, defaulting to zero with a warning if it is missing or
+    /// invalid. Fails instead when the warnings are not collected.
+    pub(crate) fn number<V: AsRef<[u8]>, T: Default>(
+        &mut self,
+        field: &'static str,
+        value: Option<V>,
+        parse: impl FnOnce(V) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let offset = value.as_ref().map(|value| self.offset(value.as_ref()));
+        match value.ok_or_else(crate::not_enough_data).and_then(parse) {
+            Err(_) if self.enabled() => {
+                self.warn(Warning::DefaultedNumber { field, offset });
+                Ok(T::default())
+            }
+            res => res,
+        }
+    }
+
+    /// Check a string of the payload for replacement characters.
+    pub(crate) fn check_encoding(&mut self, field: &'static str, bytes: &[u8]) {
+        if self.enabled() && memchr::memmem::find(bytes, REPLACEMENT_CHARACTER).is_some() {
+            self.warn(Warning::ReplacementCharacter {
+                field,
+                offset: self.offset(bytes),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing::sample_stat, BasicStat, FullStat, FullStatRef, ParseOptions};
+
+    fn full(payload: &[u8]) -> io::Result<(FullStat, Diagnostics)> {
+        FullStat::from_payload_with_diagnostics(payload, &ParseOptions::default())
+    }
+
+    fn basic(payload: &[u8]) -> io::Result<(BasicStat, Diagnostics)> {
+        BasicStat::from_payload_with_diagnostics(payload, &ParseOptions::default())
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let stat = sample_stat();
+        let (parsed, diagnostics) = full(&stat.to_payload()).unwrap();
+        assert_eq!(parsed, stat);
+        assert!(diagnostics.is_empty());
+        assert_eq!(diagnostics.warnings.capacity(), 0);
+
+        let (parsed, diagnostics) = basic(&BasicStat::from(&stat).to_payload()).unwrap();
+        assert_eq!(parsed, BasicStat::from(&stat));
+        assert!(diagnostics.is_empty());
+        assert_eq!(diagnostics.warnings.capacity(), 0);
+    }
+
+    #[test]
+    fn test_degraded_basic_stat() {
+        let payload = b"Caf\xEF\xBF\xBD\0SMP\0world\0-1\0\0\xDD\x63127.0";
+        assert!(BasicStat::from_payload(payload).is_err());
+        let (stat, diagnostics) = basic(payload).unwrap();
+        assert_eq!(stat.numplayers, 0);
+        assert_eq!(stat.maxplayers, 0);
+        assert_eq!(stat.hostip, "127.0");
+        assert_eq!(
+            diagnostics.warnings(),
+            [
+                Warning::ReplacementCharacter {
+                    field: "motd",
+                    offset: 0
+                },
+                Warning::DefaultedNumber {
+                    field: "numplayers",
+                    offset: Some(17)
+                },
+                Warning::SuspectedTruncation { len: 28 },
+            ]
+        );
+
+        // Missing fields are still fatal
+        assert!(basic(b"motd\0SMP\0world\x002\x0020\0").is_err());
+    }
+
+    #[test]
+    fn test_degraded_full_stat() {
+        let payload = sample_stat().to_payload();
+        let separator = FullStat::SECTIONS_SEPARATOR.as_slice();
+
+        // Missing number, dangling key and truncated player list
+        let kv = b"splitnum\0\x80\0hostname\0A\0gametype\0SMP\0game_id\0MINECRAFT\0version\0\
+            1.20\0plugins\0\0map\0world\0numplayers\0two\0hostport\x0025565\0hostip\0\
+            127.0.0.1\0motd";
+        let degraded = [&kv[..], separator, b"Alice\0B\xEF\xBF\xBDb"].concat();
+        assert!(FullStat::from_payload(&degraded).is_err());
+        let (stat, diagnostics) = full(&degraded).unwrap();
+        assert_eq!(stat.numplayers, 0);
+        assert_eq!(stat.maxplayers, 0);
+        assert_eq!(stat.player_list, ["Alice", "B\u{EF}\u{BF}\u{BD}b"]);
+        assert_eq!(
+            diagnostics.warnings(),
+            [
+                Warning::DanglingKey { offset: 132 },
+                Warning::DefaultedNumber {
+                    field: "numplayers",
+                    offset: Some(96)
+                },
+                Warning::DefaultedNumber {
+                    field: "maxplayers",
+                    offset: None
+                },
+                Warning::ReplacementCharacter {
+                    field: "player_list",
+                    offset: 154
+                },
+                Warning::SuspectedTruncation { len: 159 },
+            ]
+        );
+
+        // Incomplete separator
+        let i = memchr::memmem::find(&payload, separator).unwrap();
+        let degraded = [
+            &payload[..i],
+            b"\0\x01player_\0",
+            &payload[i + separator.len()..],
+        ]
+        .concat();
+        assert!(FullStat::from_payload(&degraded).is_err());
+        let (stat, diagnostics) = full(&degraded).unwrap();
+        assert_eq!(stat, sample_stat());
+        assert_eq!(
+            diagnostics.warnings(),
+            [Warning::SeparatorFallback { offset: i + 10 }]
+        );
+
+        // Missing player list
+        let degraded = &payload[..i + 1];
+        let (stat, diagnostics) = full(degraded).unwrap();
+        assert!(stat.player_list.is_empty());
+        assert_eq!(
+            diagnostics.warnings(),
+            [
+                Warning::SeparatorFallback { offset: i + 1 },
+                Warning::SuspectedTruncation { len: i + 1 },
+            ]
+        );
+
+        // Duplicated keys are only checked for their kept value
+        let degraded = [
+            &payload[..i],
+            b"\0hostname\0Caf\xEF\xBF\xBD\0hostname\0Cafe",
+            &payload[i..],
+        ]
+        .concat();
+        let (stat, diagnostics) = full(&degraded).unwrap();
+        assert_eq!(stat.hostname, "Cafe");
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_fatal_errors() {
+        let payload = sample_stat().to_payload();
+        // Missing string keys
+        let degraded = [&payload[..11], &payload[20..]].concat();
+        assert!(full(&degraded).is_err());
+        assert!(full(b"").is_err());
+        assert!(full(b"splitnum\0\x80\0").is_err());
+
+        let options = ParseOptions {
+            max_players: 1,
+            ..ParseOptions::default()
+        };
+        assert!(FullStatRef::from_payload_with_diagnostics(&payload, &options).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let warnings = [
+            Warning::DefaultedNumber {
+                field: "maxplayers",
+                offset: Some(31),
+            },
+            Warning::DefaultedNumber {
+                field: "hostport",
+                offset: None,
+            },
+            Warning::DanglingKey { offset: 163 },
+            Warning::SeparatorFallback { offset: 177 },
+            Warning::SuspectedTruncation { len: 191 },
+            Warning::ReplacementCharacter {
+                field: "motd",
+                offset: 0,
+            },
+        ];
+        assert_eq!(
+            warnings.map(|warning| warning.to_string()),
+            [
+                "invalid `maxplayers` at byte 31, read as 0",
+                "missing `hostport`, read as 0",
+                "key without a value at byte 163, ignored",
+                "missing sections separator, player list read from byte 177",
+                "payload of 191 bytes looks truncated",
+                "replacement characters in `motd` at byte 0",
+            ]
+        );
+    }
+}
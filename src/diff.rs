@@ -0,0 +1,380 @@
+//! Player changes between two polls of a server, and their summaries for chat
+//! notifications.
+//!
+//! A [`StatDiff`] lists the players who joined and left between two full
+//! statuses. [`format_summary`] turns it into a one-line digest, collapsing
+//! long name lists, or returns `None` when nothing changed:
+//!
+//! ```rust
+//! # use minecraft_server_query::{diff::{format_summary, StatDiff, SummaryOptions}, FullStat};
+//! # fn stat(players: &[&str]) -> FullStat {
+//! #     FullStat {
+//! #         hostname: "".into(), gametype: "".into(), game_id: "".into(), version: "".into(),
+//! #         plugins: "".into(), map: "".into(), numplayers: players.len() as u32,
+//! #         maxplayers: 50, hostport: 25565, hostip: "".into(),
+//! #         player_list: players.iter().map(|&p| p.into()).collect(),
+//! #     }
+//! # }
+//! let before = stat(&["Dave", "Erin"]);
+//! let after = stat(&["Erin", "Alice", "Bob", "Carol"]);
+//!
+//! let diff = StatDiff::new(&before, &after);
+//! let summary = format_summary(&diff, &after, &SummaryOptions::default()).unwrap();
+//! assert_eq!(
+//!     summary.plain,
+//!     "+3 joined: Alice, Bob and Carol · −1 left: Dave · 4/50 online"
+//! );
+//! assert_eq!(format_summary(&StatDiff::new(&after, &after), &after, &SummaryOptions::default()), None);
+//! ```
+
+use std::collections::HashSet;
+
+use crate::{FullStat, StatString};
+
+/// Players who joined and left a server between two full statuses
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatDiff {
+    /// Players of the new status which were not in the old one, in the order
+    /// of the new player list
+    pub joined: Vec<StatString>,
+    /// Players of the old status which are not in the new one, in the order
+    /// of the old player list
+    pub left: Vec<StatString>,
+    /// Number of players in the old status
+    pub old_numplayers: u32,
+    /// Number of players in the new status
+    pub new_numplayers: u32,
+}
+
+impl StatDiff {
+    /// The players who joined and left between two statuses. Empty names are
+    /// ignored.
+    pub fn new(old: &FullStat, new: &FullStat) -> Self {
+        let names =
+            |stat: &FullStat| -> HashSet<StatString> { stat.player_list.iter().cloned().collect() };
+        let (old_names, new_names) = (names(old), names(new));
+        let changed = |stat: &FullStat, other: &HashSet<StatString>| {
+            let mut seen = HashSet::new();
+            stat.player_list
+                .iter()
+                .filter(|name| !name.is_empty() && !other.contains(*name) && seen.insert(*name))
+                .cloned()
+                .collect()
+        };
+
+        Self {
+            joined: changed(new, &old_names),
+            left: changed(old, &new_names),
+            old_numplayers: old.numplayers,
+            new_numplayers: new.numplayers,
+        }
+    }
+
+    /// Whether no player joined or left, and the number of players did not
+    /// change either.
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty() && self.left.is_empty() && self.old_numplayers == self.new_numplayers
+    }
+}
+
+/// Texts of a summary, with `{placeholders}` replaced by their values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Templates {
+    /// Players who joined, with `{count}` and `{names}`
+    pub joined: String,
+    /// Players who left, with `{count}` and `{names}`
+    pub left: String,
+    /// Players online, with `{online}` and `{max}`
+    pub online: String,
+    /// A single name left out of a collapsed list
+    pub one_other: String,
+    /// Names left out of a collapsed list, with `{count}`
+    pub others: String,
+    /// Separator between the names of a list
+    pub comma: String,
+    /// Separator before the last item of a list
+    pub and: String,
+    /// Separator between the parts of the summary
+    pub separator: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            joined: "+{count} joined: {names}".into(),
+            left: "−{count} left: {names}".into(),
+            online: "{online}/{max} online".into(),
+            one_other: "1 other".into(),
+            others: "{count} others".into(),
+            comma: ", ".into(),
+            and: " and ".into(),
+            separator: " · ".into(),
+        }
+    }
+}
+
+/// Options of [`format_summary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryOptions {
+    /// Max number of names of a list, the others being counted. Defaults to 5.
+    pub max_names: usize,
+    /// Texts of the summary, in English by default
+    pub templates: Templates,
+}
+
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            max_names: 5,
+            templates: Templates::default(),
+        }
+    }
+}
+
+/// A summary of the changes of a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// Summary as plain text
+    pub plain: String,
+    /// Summary with the player names escaped for Markdown
+    pub markdown: String,
+}
+
+/// Summarize the changes of a server, whose new status is `stat`, on a single
+/// line. Returns `None` if nothing changed.
+pub fn format_summary(
+    diff: &StatDiff,
+    stat: &FullStat,
+    options: &SummaryOptions,
+) -> Option<Summary> {
+    if diff.is_empty() {
+        return None;
+    }
+    Some(Summary {
+        plain: summary(diff, stat, options, |name| name.to_string()),
+        markdown: summary(diff, stat, options, escape_markdown),
+    })
+}
+
+/// Build a summary, formatting player names with `escape`.
+fn summary(
+    diff: &StatDiff,
+    stat: &FullStat,
+    options: &SummaryOptions,
+    escape: impl Fn(&str) -> String + Copy,
+) -> String {
+    let templates = &options.templates;
+    let mut parts = Vec::new();
+    for (template, names) in [
+        (&templates.joined, &diff.joined),
+        (&templates.left, &diff.left),
+    ] {
+        if !names.is_empty() {
+            let list = name_list(names, options, escape);
+            parts.push(fill(
+                template,
+                &[("count", &names.len().to_string()), ("names", &list)],
+            ));
+        }
+    }
+    parts.push(fill(
+        &templates.online,
+        &[
+            ("online", &stat.numplayers.to_string()),
+            ("max", &stat.maxplayers.to_string()),
+        ],
+    ));
+    parts.join(&templates.separator)
+}
+
+/// A list of names, collapsing the names after `max_names` into a count.
+fn name_list(
+    names: &[StatString],
+    options: &SummaryOptions,
+    escape: impl Fn(&str) -> String,
+) -> String {
+    let templates = &options.templates;
+    let shown = names.len().min(options.max_names);
+    let mut items: Vec<String> = names[..shown].iter().map(|name| escape(name)).collect();
+    match names.len() - shown {
+        0 => {}
+        1 => items.push(templates.one_other.clone()),
+        others => items.push(fill(&templates.others, &[("count", &others.to_string())])),
+    }
+
+    match items.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{}{}{last}", rest.join(&templates.comma), templates.and),
+        None => String::new(),
+    }
+}
+
+/// Replace the `{placeholders}` of a template.
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (key, value)| {
+            text.replace(&format!("{{{key}}}"), value)
+        })
+}
+
+/// Escape the ASCII punctuation of a name, which Markdown could interpret.
+fn escape_markdown(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sample_stat;
+
+    fn stat(players: &[&str]) -> FullStat {
+        FullStat {
+            numplayers: players.len() as u32,
+            maxplayers: 50,
+            player_list: players.iter().map(|&name| name.into()).collect(),
+            ..sample_stat()
+        }
+    }
+
+    fn names(count: usize) -> Vec<String> {
+        (1..=count).map(|i| format!("Player{i}")).collect()
+    }
+
+    #[test]
+    fn test_diff() {
+        let diff = StatDiff::new(
+            &stat(&["Dave", "Erin", "Dave", ""]),
+            &stat(&["Erin", "Carol", "", "Alice", "Carol"]),
+        );
+        assert_eq!(diff.joined, ["Carol", "Alice"]);
+        assert_eq!(diff.left, ["Dave"]);
+        assert!(!diff.is_empty());
+
+        // The list is hidden, but the count changed
+        let mut hidden = stat(&[]);
+        hidden.numplayers = 3;
+        let diff = StatDiff::new(&stat(&[]), &hidden);
+        assert!(diff.joined.is_empty());
+        assert!(!diff.is_empty());
+        let summary = format_summary(&diff, &hidden, &SummaryOptions::default()).unwrap();
+        assert_eq!(summary.plain, "3/50 online");
+    }
+
+    #[test]
+    fn test_nothing_changed() {
+        let stat = stat(&["Alice", "Bob"]);
+        let diff = StatDiff::new(&stat, &stat);
+        assert!(diff.is_empty());
+        assert_eq!(
+            format_summary(&diff, &stat, &SummaryOptions::default()),
+            None
+        );
+        assert_eq!(
+            format_summary(&StatDiff::default(), &stat, &SummaryOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_single_names() {
+        let old = stat(&["Dave"]);
+        let new = stat(&["Alice"]);
+        let summary = format_summary(&StatDiff::new(&old, &new), &new, &SummaryOptions::default());
+        assert_eq!(
+            summary.unwrap().plain,
+            "+1 joined: Alice · −1 left: Dave · 1/50 online"
+        );
+    }
+
+    #[test]
+    fn test_truncation() {
+        let joined = names(19);
+        let new = stat(&joined.iter().map(String::as_str).collect::<Vec<_>>());
+        let options = SummaryOptions {
+            max_names: 2,
+            ..SummaryOptions::default()
+        };
+        let summary = format_summary(&StatDiff::new(&stat(&[]), &new), &new, &options).unwrap();
+        assert_eq!(
+            summary.plain,
+            "+19 joined: Player1, Player2 and 17 others · 19/50 online"
+        );
+
+        let new = stat(&["Alice", "Bob", "Carol"]);
+        let summary = format_summary(&StatDiff::new(&stat(&[]), &new), &new, &options).unwrap();
+        assert_eq!(
+            summary.plain,
+            "+3 joined: Alice, Bob and 1 other · 3/50 online"
+        );
+
+        let options = SummaryOptions {
+            max_names: 0,
+            ..SummaryOptions::default()
+        };
+        let summary = format_summary(&StatDiff::new(&stat(&[]), &new), &new, &options).unwrap();
+        assert_eq!(summary.plain, "+3 joined: 3 others · 3/50 online");
+
+        // Lists which fit are not collapsed
+        let summary = format_summary(
+            &StatDiff::new(&stat(&[]), &new),
+            &new,
+            &SummaryOptions::default(),
+        );
+        assert_eq!(
+            summary.unwrap().plain,
+            "+3 joined: Alice, Bob and Carol · 3/50 online"
+        );
+    }
+
+    #[test]
+    fn test_markdown() {
+        let new = stat(&["jeb_", "*Star*", "[Mod]Steve"]);
+        let summary = format_summary(
+            &StatDiff::new(&stat(&[]), &new),
+            &new,
+            &SummaryOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            summary.plain,
+            "+3 joined: jeb_, *Star* and [Mod]Steve · 3/50 online"
+        );
+        assert_eq!(
+            summary.markdown,
+            r"+3 joined: jeb\_, \*Star\* and \[Mod\]Steve · 3/50 online"
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let old = stat(&["Dave", "Erin"]);
+        let new = stat(&["Alice"]);
+        let options = SummaryOptions {
+            max_names: 1,
+            templates: Templates {
+                joined: "{names} a rejoint le serveur".into(),
+                left: "{count} départs : {names}".into(),
+                online: "{online} joueur(s) sur {max}".into(),
+                one_other: "un autre".into(),
+                others: "{count} autres".into(),
+                comma: ", ".into(),
+                and: " et ".into(),
+                separator: " | ".into(),
+            },
+        };
+        let summary = format_summary(&StatDiff::new(&old, &new), &new, &options).unwrap();
+        assert_eq!(
+            summary.plain,
+            "Alice a rejoint le serveur | 2 départs : Dave et un autre | 1 joueur(s) sur 50"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Query client for embedded network stacks.
+//!
+//! [`QueryClient`] runs over any connected UDP socket implementing the
+//! [`embedded-nal-async`](https://docs.rs/embedded-nal-async) traits, like the
+//! UDP sockets of `embassy-net` or `smoltcp` based stacks, with timeouts driven
+//! by an injected [`DelayNs`] implementation, such as `embassy_time::Delay`.
+//!
+//! Responses are received in a buffer provided by the caller: a buffer of
+//! [`FULL_STAT_BUFFER_SIZE`] bytes fits every vanilla response.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::embedded::{QueryClient, FULL_STAT_BUFFER_SIZE};
+//! # use embedded_hal_async::delay::DelayNs;
+//! # use embedded_nal_async::ConnectedUdp;
+//! async fn player_count(socket: impl ConnectedUdp, delay: impl DelayNs) -> std::io::Result<u32> {
+//!     let mut client = QueryClient::new(socket, delay);
+//!     let mut buf = [0; FULL_STAT_BUFFER_SIZE];
+//!     let token = client.handshake().await?;
+//!     Ok(client.basic_stat(token, &mut buf).await?.numplayers)
+//! }
+//! ```
+//!
+//! The parsed statuses own their strings: like the rest of the crate, the
+//! client still requires `std`.
+
+use std::{
+    future::{poll_fn, Future},
+    io,
+    pin::pin,
+    task::Poll,
+    time::Duration,
+};
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_nal_async::ConnectedUdp;
+
+use crate::packets::{self, QueryPacket};
+use crate::{not_enough_data, BasicStat, FullStat, Token, DEFAULT_TIMEOUT, RESPONSE_HEADER_SIZE};
+
+/// Size of a buffer fitting the full status responses of vanilla servers
+pub const FULL_STAT_BUFFER_SIZE: usize = FullStat::RESPONSE_SIZE;
+
+/// Session ID of the client requests, unless set with
+/// [`QueryClient::session_id`]
+pub const DEFAULT_SESSION_ID: u32 = 1;
+
+/// An asynchronous Query client over an embedded UDP socket, connected to the
+/// server.
+#[derive(Debug)]
+pub struct QueryClient<S, D> {
+    socket: S,
+    delay: D,
+    session_id: u32,
+    timeout: Option<Duration>,
+}
+
+impl<S: ConnectedUdp, D: DelayNs> QueryClient<S, D> {
+    /// Build a new QueryClient over a socket connected to the server, waiting
+    /// for the responses with the given delay.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new(socket: S, delay: D) -> Self {
+        Self {
+            socket,
+            delay,
+            session_id: DEFAULT_SESSION_ID,
+            timeout: Some(DEFAULT_TIMEOUT),
+        }
+    }
+
+    /// Set the timeout of the requests, or wait forever for the responses with
+    /// `None`. Timeouts are rounded up to the millisecond.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the session ID of the requests. Servers only keep the lower 4 bits
+    /// of each byte.
+    pub fn session_id(mut self, session_id: u32) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Get back the socket and the delay of the client.
+    pub fn into_inner(self) -> (S, D) {
+        (self.socket, self.delay)
+    }
+
+    /// Send a request packet to the server.
+    async fn send(&mut self, packet: &impl QueryPacket) -> io::Result<()> {
+        self.socket
+            .send(packet.as_bytes())
+            .await
+            .map_err(network_error)
+    }
+
+    /// Receive a UDP packet from the client socket, returning its payload.
+    ///
+    /// Fails if the packet does not fit in the buffer.
+    async fn recv<'b>(&mut self, buf: &'b mut [u8]) -> io::Result<&'b [u8]> {
+        let received = {
+            let mut recv = pin!(self.socket.receive_into(buf));
+            match self.timeout {
+                Some(timeout) => {
+                    let ms = timeout.as_nanos().div_ceil(1_000_000);
+                    let mut delay = pin!(self.delay.delay_ms(ms.try_into().unwrap_or(u32::MAX)));
+                    poll_fn(|cx| match recv.as_mut().poll(cx) {
+                        Poll::Ready(res) => Poll::Ready(res.map_err(network_error)),
+                        Poll::Pending => delay.as_mut().poll(cx).map(|()| {
+                            Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                "UDP async recv call timed out.",
+                            ))
+                        }),
+                    })
+                    .await?
+                }
+                None => recv.await.map_err(network_error)?,
+            }
+        };
+
+        if received > buf.len() {
+            return Err(io::Error::other(
+                "UDP payload larger than the receive buffer.",
+            ));
+        }
+        buf[..received]
+            .get(RESPONSE_HEADER_SIZE..)
+            .ok_or_else(not_enough_data)
+    }
+
+    /// Send a UDP handshake packet to the client socket.
+    ///
+    /// Receive and parse the response into a Query token, valid up to 30 seconds.
+    pub async fn handshake(&mut self) -> io::Result<Token> {
+        self.send(&packets::Handshake::new(self.session_id)).await?;
+
+        let mut buf = [0; Token::RESPONSE_SIZE];
+        Token::try_from_payload(self.recv(&mut buf).await?)
+    }
+
+    /// Request and wait for a basic status packet on the client socket,
+    /// receiving the response in the given buffer.
+    ///
+    /// If the token is no longer valid, no packet is received and an error is returned.
+    pub async fn basic_stat(&mut self, token: Token, buf: &mut [u8]) -> io::Result<BasicStat> {
+        self.send(&packets::BasicStat::new(self.session_id, token.0))
+            .await?;
+
+        BasicStat::from_payload(self.recv(buf).await?)
+    }
+
+    /// Request and wait for a full status packet on the client socket,
+    /// receiving the response in the given buffer.
+    ///
+    /// If the token is no longer valid, no packet is received and an error is returned.
+    pub async fn full_stat(&mut self, token: Token, buf: &mut [u8]) -> io::Result<FullStat> {
+        self.send(&packets::FullStat::new(self.session_id, token.0))
+            .await?;
+
+        FullStat::from_payload(self.recv(buf).await?)
+    }
+}
+
+/// Convert an error of the network stack to an IO error of the same kind.
+fn network_error(e: impl embedded_io::Error) -> io::Error {
+    io::Error::new(e.kind().into(), format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{poll_fn, Future},
+        io,
+        net::UdpSocket,
+        pin::pin,
+        task::{Context, Poll, Waker},
+        time::{Duration, Instant},
+    };
+
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_nal_async::ConnectedUdp;
+
+    use super::{QueryClient, FULL_STAT_BUFFER_SIZE};
+    use crate::packets::PacketType;
+    use crate::testing::{sample_stat, Faults, MockQueryServer};
+
+    /// In-memory stand-in for the UDP socket of an embedded stack, over a
+    /// non-blocking host socket
+    struct Socket(UdpSocket);
+
+    impl ConnectedUdp for Socket {
+        type Error = io::Error;
+
+        async fn send(&mut self, data: &[u8]) -> io::Result<()> {
+            self.0.send(data).map(drop)
+        }
+
+        async fn receive_into(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            // Peek first, to report the full size of oversized datagrams
+            let mut probe = [0; 2048];
+            let size = poll_fn(|cx| match self.0.peek(&mut probe) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+                res => Poll::Ready(res),
+            })
+            .await?;
+            self.0.recv(buffer)?;
+            Ok(size)
+        }
+    }
+
+    /// Delay polling the host clock
+    struct Delay;
+
+    impl DelayNs for Delay {
+        async fn delay_ns(&mut self, ns: u32) {
+            let deadline = Instant::now() + Duration::from_nanos(ns.into());
+            poll_fn(|cx| {
+                if Instant::now() >= deadline {
+                    Poll::Ready(())
+                } else {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    /// Busy-polling executor, like the simplest embedded ones
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    fn client(server: &MockQueryServer) -> QueryClient<Socket, Delay> {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(server.addr()).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        QueryClient::new(Socket(socket), Delay).timeout(Some(Duration::from_millis(200)))
+    }
+
+    #[test]
+    fn test_basic_stat() {
+        let server = MockQueryServer::new().unwrap();
+        let mut client = client(&server);
+        let mut buf = [0; 512];
+
+        let basic_stat = block_on(async {
+            let token = client.handshake().await?;
+            client.basic_stat(token, &mut buf).await
+        })
+        .unwrap();
+        assert_eq!(basic_stat, (&sample_stat()).into());
+    }
+
+    #[test]
+    fn test_full_stat() {
+        let server = MockQueryServer::new().unwrap();
+        let mut client = client(&server);
+        let mut buf = [0; FULL_STAT_BUFFER_SIZE];
+
+        let full_stat = block_on(async {
+            let token = client.handshake().await?;
+            client.full_stat(token, &mut buf).await
+        })
+        .unwrap();
+        assert_eq!(full_stat, sample_stat());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let mut client = client(&server);
+        let mut buf = [0; 512];
+
+        block_on(async {
+            let token = client.handshake().await.unwrap();
+            let err = client.basic_stat(token, &mut buf).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            client.basic_stat(token, &mut buf).await.unwrap();
+        });
+        assert_eq!(server.dropped(PacketType::Stat), 1);
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let server = MockQueryServer::new().unwrap();
+        let mut client = client(&server);
+        let mut buf = [0; 32];
+
+        let err = block_on(async {
+            let token = client.handshake().await?;
+            client.full_stat(token, &mut buf).await
+        })
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "UDP payload larger than the receive buffer."
+        );
+    }
+}
@@ -0,0 +1,647 @@
+//! Polling of a fleet of servers listed in a file, reloaded when it changes.
+//!
+//! The list has one server per line, as `host[:port] [label]`, with blank
+//! lines and comments starting with `#` ignored:
+//!
+//! ```text
+//! # Survival servers
+//! play.example.com          Main
+//! 10.0.0.12:25566           Creative
+//! [2001:db8::1]:25565
+//! ```
+//!
+//! With the `serde` and `serde_json` features, files with a `.json` extension
+//! are read as an array of [`Target`] objects instead.
+//!
+//! A [`FileWatcher`] polls every server of the list, and re-reads the file
+//! periodically: servers added to the file start being polled right away,
+//! and servers removed from it stop being polled.
+//!
+//! ```rust,no_run
+//! # async fn f() -> std::io::Result<()> {
+//! # use minecraft_server_query::fleet::{FileWatcher, FleetEvent};
+//! # use std::time::Duration;
+//! let (events, mut received) = tokio::sync::mpsc::channel(64);
+//! let handle = FileWatcher::new("servers.txt", Duration::from_secs(10)).spawn(events);
+//! while let Some(event) = received.recv().await {
+//!     match event {
+//!         FleetEvent::Poll { target, result, .. } => println!("{target}: {result:?}"),
+//!         FleetEvent::Reloaded(targets) => println!("Watching {} servers", targets.len()),
+//!         FleetEvent::ReloadFailed(e) => eprintln!("Invalid server list: {e}"),
+//!     }
+//! }
+//! # handle.stop().await
+//! # }
+//! ```
+
+use ::tokio::{
+    runtime::Handle,
+    sync::mpsc,
+    time::{interval, sleep, MissedTickBehavior},
+};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+#[cfg(feature = "rdns")]
+use crate::rdns::ReverseDns;
+use crate::resolve::{Resolve, SystemResolver};
+use crate::task::TaskHandle;
+use crate::{custom_io_error, tokio::Query, FullStat, DEFAULT_PORT, DEFAULT_TIMEOUT};
+
+/// Delay between two reads of a changed file, which must be identical for
+/// the change to be applied
+pub const DEFAULT_SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// A server of the list
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Target {
+    /// Host name or IP address of the server
+    pub host: String,
+    /// Query port of the server
+    #[cfg_attr(feature = "serde", serde(default = "default_port"))]
+    pub port: u16,
+    /// Name given to the server in the list
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub label: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+fn default_port() -> u16 {
+    DEFAULT_PORT
+}
+
+impl Target {
+    /// Name of the server: its label, or `host:port` if it has none.
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => Self {
+                label: None,
+                ..self.clone()
+            }
+            .to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    /// Formats the target as `host:port`, with brackets around IPv6
+    /// addresses, followed by the label if there is one.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(f, "[{}]:{}", self.host, self.port)?;
+        } else {
+            write!(f, "{}:{}", self.host, self.port)?;
+        }
+        match &self.label {
+            Some(label) => write!(f, " {label}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Target {
+    type Err = io::Error;
+
+    /// Parse a line of the list, `host[:port] [label]`. IPv6 addresses with a
+    /// port are written in brackets.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::fleet::Target;
+    /// let target: Target = "10.0.0.12:25566 Creative server".parse()?;
+    /// assert_eq!(target.host, "10.0.0.12");
+    /// assert_eq!(target.port, 25566);
+    /// assert_eq!(target.label.as_deref(), Some("Creative server"));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn from_str(line: &str) -> io::Result<Self> {
+        let line = line.trim();
+        let (addr, label) = match line.split_once(char::is_whitespace) {
+            Some((addr, label)) => (addr, Some(label.trim().to_string())),
+            None => (line, None),
+        };
+        let invalid_port = || custom_io_error(&format!("Invalid port in {addr:?}"));
+
+        let (host, port) = if let Some(bracketed) = addr.strip_prefix('[') {
+            let (host, rest) = bracketed
+                .split_once(']')
+                .ok_or_else(|| custom_io_error(&format!("Unclosed bracket in {addr:?}")))?;
+            let port = match rest.strip_prefix(':') {
+                Some(port) => port.parse().map_err(|_| invalid_port())?,
+                None if rest.is_empty() => DEFAULT_PORT,
+                None => return Err(invalid_port()),
+            };
+            (host, port)
+        } else if addr.matches(':').count() == 1 {
+            let (host, port) = addr.split_once(':').unwrap_or_default();
+            (host, port.parse().map_err(|_| invalid_port())?)
+        } else {
+            // No port, or an IPv6 address without brackets
+            (addr, DEFAULT_PORT)
+        };
+        if host.is_empty() {
+            return Err(custom_io_error(&format!("Missing host in {addr:?}")));
+        }
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            label,
+        })
+    }
+}
+
+/// Parse a server list, one `host[:port] [label]` per line. Blank lines and
+/// comments starting with `#` are ignored, and duplicates removed.
+///
+/// Errors give the number of the invalid line.
+pub fn parse_list(content: &str) -> io::Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        if line.trim().is_empty() {
+            continue;
+        }
+        let target: Target = line.parse().map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {e}", i + 1))
+        })?;
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    Ok(targets)
+}
+
+/// Parse the content of a server list file, as JSON if its extension is
+/// `.json` and the `serde_json` feature is enabled.
+fn parse_file(path: &Path, content: &str) -> io::Result<Vec<Target>> {
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let mut targets: Vec<Target> = serde_json::from_str(content)?;
+        let mut seen = std::collections::HashSet::new();
+        targets.retain(|target| seen.insert(target.clone()));
+        return Ok(targets);
+    }
+    #[cfg(not(all(feature = "serde", feature = "serde_json")))]
+    let _ = path;
+    parse_list(content)
+}
+
+/// An event of a [`FileWatcher`]
+#[derive(Debug)]
+// Polls are by far the most frequent events: boxing them would not save memory
+#[allow(clippy::large_enum_variant)]
+pub enum FleetEvent {
+    /// Result of polling a server
+    Poll {
+        /// The polled server
+        target: Target,
+        /// Full status of the server, or the error of the query
+        result: io::Result<FullStat>,
+        /// PTR record of the address of the server, resolved after its first
+        /// status when [reverse DNS](FileWatcher::rdns) is enabled
+        rdns: Option<String>,
+    },
+    /// The list was read, and the servers polled changed to these ones. Sent
+    /// after the servers removed from the list stopped being polled.
+    Reloaded(Vec<Target>),
+    /// The list could not be read or parsed. The servers polled are left
+    /// unchanged.
+    ReloadFailed(io::Error),
+}
+
+/// Polls the servers of a list file, following the changes of the file.
+///
+/// The file is read every reload interval, the poll interval by default. When
+/// its content changed, it is read again after a [settle delay](Self::settle),
+/// and the change is only applied if both reads are identical, so that a file
+/// being written is not applied half-written. Replacing the file with a
+/// rename is still the safest way to update it.
+#[derive(Debug, Clone)]
+pub struct FileWatcher {
+    path: PathBuf,
+    interval: Duration,
+    reload_interval: Duration,
+    settle: Duration,
+    timeout: Duration,
+    resolver: Arc<dyn Resolve>,
+    #[cfg(feature = "rdns")]
+    rdns: Option<ReverseDns>,
+}
+
+impl FileWatcher {
+    /// Watch the servers of the given file, polling each of them at the
+    /// given interval.
+    pub fn new(path: impl Into<PathBuf>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            reload_interval: interval,
+            settle: DEFAULT_SETTLE_DELAY,
+            timeout: DEFAULT_TIMEOUT,
+            resolver: Arc::new(SystemResolver),
+            #[cfg(feature = "rdns")]
+            rdns: None,
+        }
+    }
+
+    /// Interval between two reads of the file, the poll interval by default.
+    pub fn reload_interval(mut self, interval: Duration) -> Self {
+        self.reload_interval = interval;
+        self
+    }
+
+    /// Delay between the two reads of a changed file, [`DEFAULT_SETTLE_DELAY`]
+    /// by default.
+    pub fn settle(mut self, delay: Duration) -> Self {
+        self.settle = delay;
+        self
+    }
+
+    /// Timeout of each response, [`DEFAULT_TIMEOUT`] by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Resolver of the host names of the servers, the [system
+    /// resolver](SystemResolver) by default. Host names are resolved again
+    /// before every poll, so that servers can move to other addresses.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Resolve the PTR record of each server after its first status, to
+    /// fill the `rdns` field of its polls.
+    #[cfg(feature = "rdns")]
+    #[cfg_attr(doc, doc(cfg(feature = "rdns")))]
+    pub fn rdns(mut self, reverse_dns: ReverseDns) -> Self {
+        self.rdns = Some(reverse_dns);
+        self
+    }
+
+    /// Spawn a task watching the file and polling its servers, sending their
+    /// results to `events`, until the returned handle is stopped or dropped.
+    ///
+    /// The task also stops when the receiver of `events` is dropped.
+    pub fn spawn(self, events: mpsc::Sender<FleetEvent>) -> TaskHandle {
+        self.spawn_on(&Handle::current(), events)
+    }
+
+    /// Like [`spawn`](Self::spawn), but spawn the tasks on the given runtime.
+    pub fn spawn_on(self, runtime: &Handle, events: mpsc::Sender<FleetEvent>) -> TaskHandle {
+        let runtime = runtime.clone();
+        TaskHandle::spawn(&runtime.clone(), async move {
+            self.watch(&runtime, events).await;
+            Ok(())
+        })
+    }
+
+    /// Reload the file every reload interval, until the receiver of the
+    /// events is dropped.
+    async fn watch(&self, runtime: &Handle, events: mpsc::Sender<FleetEvent>) {
+        let mut pollers = HashMap::<Target, TaskHandle>::new();
+        // Last content read, applied or not, and whether the last read failed,
+        // to report each failure once
+        let mut seen: Option<String> = None;
+        let mut read_failed = false;
+        let mut reload = interval(self.reload_interval);
+        reload.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        while !events.is_closed() {
+            reload.tick().await;
+            let event = match self.read_changed(seen.as_deref()).await {
+                Ok(Some(content)) => {
+                    read_failed = false;
+                    let parsed = parse_file(&self.path, &content);
+                    seen = Some(content);
+                    match parsed {
+                        Ok(targets) => {
+                            self.apply(runtime, &mut pollers, &targets, &events).await;
+                            FleetEvent::Reloaded(targets)
+                        }
+                        Err(e) => FleetEvent::ReloadFailed(e),
+                    }
+                }
+                Ok(_) => {
+                    read_failed = false;
+                    continue;
+                }
+                Err(_) if read_failed => continue,
+                Err(e) => {
+                    read_failed = true;
+                    FleetEvent::ReloadFailed(e)
+                }
+            };
+            if events.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Read the file, returning its content if it is not the content last
+    /// seen, and stayed the same for the settle delay.
+    async fn read_changed(&self, seen: Option<&str>) -> io::Result<Option<String>> {
+        let content = ::tokio::fs::read_to_string(&self.path).await?;
+        if seen == Some(content.as_str()) {
+            return Ok(None);
+        }
+        sleep(self.settle).await;
+        if ::tokio::fs::read_to_string(&self.path).await? == content {
+            Ok(Some(content))
+        } else {
+            // Still being written: read again on the next reload
+            Ok(None)
+        }
+    }
+
+    /// Stop polling the servers which are not in `targets`, and start
+    /// polling the new ones.
+    async fn apply(
+        &self,
+        runtime: &Handle,
+        pollers: &mut HashMap<Target, TaskHandle>,
+        targets: &[Target],
+        events: &mpsc::Sender<FleetEvent>,
+    ) {
+        let removed: Vec<Target> = pollers
+            .keys()
+            .filter(|target| !targets.contains(target))
+            .cloned()
+            .collect();
+        for target in removed {
+            if let Some(poller) = pollers.remove(&target) {
+                let _ = poller.stop().await;
+            }
+        }
+
+        for target in targets {
+            if !pollers.contains_key(target) {
+                let poller = self.poller(runtime, target.clone(), events.clone());
+                pollers.insert(target.clone(), poller);
+            }
+        }
+    }
+
+    /// Spawn a task polling a server every interval, starting right away.
+    fn poller(
+        &self,
+        runtime: &Handle,
+        target: Target,
+        events: mpsc::Sender<FleetEvent>,
+    ) -> TaskHandle {
+        let query = Query::to(target.host.clone())
+            .port(target.port)
+            .timeout(self.timeout)
+            .resolver(self.resolver.clone());
+        let period = self.interval;
+        #[cfg(feature = "rdns")]
+        let (reverse_dns, resolver) = (self.rdns.clone(), self.resolver.clone());
+        TaskHandle::spawn(runtime, async move {
+            let mut ticks = interval(period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            // PTR record of the server, once resolved
+            #[cfg_attr(not(feature = "rdns"), allow(unused_mut))]
+            let mut rdns: Option<Option<String>> = None;
+            loop {
+                ticks.tick().await;
+                let result = query.clone().full().await;
+                #[cfg(feature = "rdns")]
+                if let (Ok(_), None, Some(reverse_dns)) = (&result, &rdns, &reverse_dns) {
+                    rdns = Some(resolve_ptr(reverse_dns, &*resolver, &target).await);
+                }
+                let event = FleetEvent::Poll {
+                    target: target.clone(),
+                    result,
+                    rdns: rdns.clone().flatten(),
+                };
+                if events.send(event).await.is_err() {
+                    return Ok(());
+                }
+            }
+        })
+    }
+}
+
+/// Resolve the PTR record of the address of a target.
+#[cfg(feature = "rdns")]
+async fn resolve_ptr(
+    reverse_dns: &ReverseDns,
+    resolver: &dyn Resolve,
+    target: &Target,
+) -> Option<String> {
+    let addr = crate::resolve::first_address_async(resolver, &target.host, target.port)
+        .await
+        .ok()?;
+    reverse_dns.lookup_async(addr.ip()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, time::Duration};
+
+    use ::tokio::sync::mpsc;
+
+    use super::{parse_list, FileWatcher, FleetEvent, Target};
+    use crate::testing::MockQueryServer;
+
+    fn target(host: &str, port: u16, label: Option<&str>) -> Target {
+        Target {
+            host: host.to_string(),
+            port,
+            label: label.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = "\
+            # Comment\n\
+            play.example.com  Main server\n\
+            \n\
+            10.0.0.12:25566 # Creative\n\
+            [2001:db8::1]:25567 IPv6\n\
+            2001:db8::2\n\
+            play.example.com Main server\n";
+        assert_eq!(
+            parse_list(list).unwrap(),
+            [
+                target("play.example.com", 25565, Some("Main server")),
+                target("10.0.0.12", 25566, None),
+                target("2001:db8::1", 25567, Some("IPv6")),
+                target("2001:db8::2", 25565, None),
+            ]
+        );
+
+        for invalid in ["host:port", "host:65536", "[::1", "[::1]25565", ":25565"] {
+            assert!(invalid.parse::<Target>().is_err(), "{invalid}");
+        }
+        let err = parse_list("a.example.com\n\nb.example.com:x\n").unwrap_err();
+        assert!(err.to_string().starts_with("Line 3:"), "{err}");
+    }
+
+    #[test]
+    fn test_display() {
+        for line in ["play.example.com:25565 Main", "[::1]:25566"] {
+            assert_eq!(line.parse::<Target>().unwrap().to_string(), line);
+        }
+    }
+
+    #[cfg(all(feature = "serde", feature = "serde_json"))]
+    #[test]
+    fn test_parse_json() {
+        let json =
+            r#"[{"host": "play.example.com", "label": "Main"}, {"host": "::1", "port": 25566}]"#;
+        assert_eq!(
+            super::parse_file("servers.json".as_ref(), json).unwrap(),
+            [
+                target("play.example.com", 25565, Some("Main")),
+                target("::1", 25566, None),
+            ]
+        );
+    }
+
+    /// Wait for the next reload, returning the targets.
+    async fn reloaded(events: &mut mpsc::Receiver<FleetEvent>) -> Vec<Target> {
+        loop {
+            match events.recv().await.unwrap() {
+                FleetEvent::Reloaded(targets) => return targets,
+                FleetEvent::ReloadFailed(e) => panic!("{e}"),
+                FleetEvent::Poll { .. } => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_watcher() {
+        let servers = [
+            MockQueryServer::new().unwrap(),
+            MockQueryServer::new().unwrap(),
+        ];
+        let line = |server: &MockQueryServer, label: &str| format!("{} {label}\n", server.addr());
+        let dir = std::env::temp_dir().join(format!("mc-query-fleet-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("servers.txt");
+        std::fs::write(&path, line(&servers[0], "first")).unwrap();
+
+        let (tx, mut events) = mpsc::channel(64);
+        let handle = FileWatcher::new(&path, Duration::from_millis(50))
+            .settle(Duration::from_millis(10))
+            .spawn(tx);
+        let first: Target = line(&servers[0], "first").parse().unwrap();
+        assert_eq!(reloaded(&mut events).await, [first]);
+
+        // Replace the first server with the second one
+        std::fs::write(&path, line(&servers[1], "second")).unwrap();
+        let second: Target = line(&servers[1], "second").parse().unwrap();
+        assert_eq!(reloaded(&mut events).await, std::slice::from_ref(&second));
+
+        // Only the second server is polled from now on
+        let mut polled = HashSet::new();
+        for _ in 0..5 {
+            match events.recv().await.unwrap() {
+                FleetEvent::Poll {
+                    target,
+                    result,
+                    rdns,
+                } => {
+                    assert_eq!(result.unwrap(), servers[1].full_stat());
+                    assert_eq!(rdns, None);
+                    polled.insert(target);
+                }
+                event => panic!("{event:?}"),
+            }
+        }
+        assert_eq!(polled, HashSet::from([second]));
+
+        // An invalid list leaves the servers unchanged
+        std::fs::write(&path, "host:port\n").unwrap();
+        loop {
+            match events.recv().await.unwrap() {
+                FleetEvent::ReloadFailed(_) => break,
+                FleetEvent::Poll { .. } => {}
+                event => panic!("{event:?}"),
+            }
+        }
+
+        handle.stop().await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_watcher_resolver() {
+        let servers = [
+            MockQueryServer::new().unwrap(),
+            MockQueryServer::with_stat(crate::FullStat {
+                hostname: "Moved".into(),
+                ..crate::testing::sample_stat()
+            })
+            .unwrap(),
+        ];
+        let dir =
+            std::env::temp_dir().join(format!("mc-query-fleet-resolver-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("servers.txt");
+        std::fs::write(&path, "lobby.mc.internal:25565").unwrap();
+
+        // The host name resolves to the first server, then to the second one
+        let stub = crate::resolve::tests::StubResolver::new();
+        stub.set("lobby.mc.internal", &[servers[0].addr()]);
+        let (tx, mut events) = mpsc::channel(64);
+        let handle = FileWatcher::new(&path, Duration::from_millis(20))
+            .resolver(stub.clone())
+            .spawn(tx);
+
+        // The host name is resolved again before every poll
+        let mut hostnames = Vec::new();
+        while hostnames.last().map(String::as_str) != Some("Moved") {
+            if let FleetEvent::Poll { result, .. } = events.recv().await.unwrap() {
+                hostnames.push(result.unwrap().hostname.to_string());
+                if hostnames.len() == 2 {
+                    stub.set("lobby.mc.internal", &[servers[1].addr()]);
+                }
+            }
+            assert!(hostnames.len() < 20, "{hostnames:?}");
+        }
+        assert_eq!(hostnames[..2], ["A Minecraft Server", "A Minecraft Server"]);
+        assert!(stub.calls().len() >= hostnames.len());
+
+        handle.stop().await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "rdns")]
+    #[tokio::test]
+    async fn test_file_watcher_rdns() {
+        let server = MockQueryServer::new().unwrap();
+        let dir = std::env::temp_dir().join(format!("mc-query-fleet-rdns-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("servers.txt");
+        std::fs::write(&path, server.addr().to_string()).unwrap();
+
+        let (tx, mut events) = mpsc::channel(64);
+        let handle = FileWatcher::new(&path, Duration::from_millis(50))
+            .rdns(crate::rdns::tests::stub(Duration::ZERO))
+            .spawn(tx);
+        for _ in 0..2 {
+            loop {
+                if let FleetEvent::Poll { result, rdns, .. } = events.recv().await.unwrap() {
+                    assert!(result.is_ok());
+                    assert_eq!(rdns.as_deref(), Some("backend-03.hosting.example"));
+                    break;
+                }
+            }
+        }
+
+        handle.stop().await.unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
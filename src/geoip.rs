@@ -0,0 +1,279 @@
+//! Geolocation of servers, from MaxMind GeoLite2 databases.
+//!
+//! An [`Enricher`] annotates the results of a scan or of a fleet with the
+//! country, city and AS number of each server, for displaying them on a map
+//! without joining them against the databases in a separate step:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{geoip::Enricher, FullStat};
+//! # use std::net::SocketAddr;
+//! # let results: Vec<(SocketAddr, FullStat)> = Vec::new();
+//! let enricher = Enricher::open("GeoLite2-City.mmdb")?.with_database("GeoLite2-ASN.mmdb")?;
+//! for (addr, stat, geo) in enricher.enrich(results) {
+//!     println!("{} ({addr}): {:?} {:?} AS{:?}", stat.hostname, geo.country, geo.city, geo.asn);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
+};
+
+use maxminddb::{geoip2, Reader};
+
+/// Geolocation of an address. Fields are `None` when no database has them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GeoInfo {
+    /// ISO 3166-1 code of the country (`"FR"`...)
+    pub country: Option<String>,
+    /// English name of the city
+    pub city: Option<String>,
+    /// Number of the autonomous system the address belongs to
+    pub asn: Option<u32>,
+}
+
+impl GeoInfo {
+    /// Fill the fields which are still missing from those of `other`.
+    fn merge(&mut self, other: GeoInfo) {
+        self.country = self.country.take().or(other.country);
+        self.city = self.city.take().or(other.city);
+        self.asn = self.asn.or(other.asn);
+    }
+}
+
+/// Looks up the geolocation of addresses in one or several GeoLite2 databases.
+///
+/// The databases are read in memory when opened, and shared between the
+/// clones of the enricher, which can be sent to other threads and tasks.
+#[derive(Debug, Clone)]
+pub struct Enricher {
+    databases: Vec<Arc<Reader<Vec<u8>>>>,
+}
+
+impl Enricher {
+    /// Open a database, such as `GeoLite2-City.mmdb` or `GeoLite2-ASN.mmdb`.
+    ///
+    /// Fails if the file cannot be read, or is not a valid database.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            databases: vec![read_database(path.as_ref())?],
+        })
+    }
+
+    /// Also look up addresses in another database, for instance an ASN
+    /// database along a city one. The fields found in the first databases
+    /// take precedence.
+    pub fn with_database(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        self.databases.push(read_database(path.as_ref())?);
+        Ok(self)
+    }
+
+    /// Look up the geolocation of an address. Addresses missing from the
+    /// databases, and records which cannot be decoded, leave the fields empty.
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        for db in self.databases.iter() {
+            if let Ok(city) = db.lookup::<geoip2::City>(ip) {
+                info.merge(GeoInfo {
+                    country: city
+                        .country
+                        .and_then(|country| country.iso_code)
+                        .map(str::to_string),
+                    city: city
+                        .city
+                        .and_then(|city| city.names)
+                        .and_then(|names| names.get("en").map(|name| name.to_string())),
+                    asn: None,
+                });
+            }
+            if let Ok(asn) = db.lookup::<geoip2::Asn>(ip) {
+                info.merge(GeoInfo {
+                    asn: asn.autonomous_system_number,
+                    ..GeoInfo::default()
+                });
+            }
+        }
+        info
+    }
+
+    /// Annotate scanner or fleet results with the geolocation of their address.
+    pub fn enrich<S>(
+        &self,
+        results: impl IntoIterator<Item = (SocketAddr, S)>,
+    ) -> Vec<(SocketAddr, S, GeoInfo)> {
+        results
+            .into_iter()
+            .map(|(addr, stat)| (addr, stat, self.lookup(addr.ip())))
+            .collect()
+    }
+}
+
+/// Read a database in memory.
+fn read_database(path: &Path) -> io::Result<Arc<Reader<Vec<u8>>>> {
+    let reader = Reader::from_source(std::fs::read(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(Arc::new(reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::testing::sample_stat;
+
+    /// Write the control byte of a field of the MaxMind DB data format.
+    fn control(out: &mut Vec<u8>, kind: u8, size: usize) {
+        assert!(size < 29);
+        if kind <= 7 {
+            out.push(kind << 5 | size as u8);
+        } else {
+            out.extend_from_slice(&[size as u8, kind - 7]);
+        }
+    }
+
+    fn string(out: &mut Vec<u8>, s: &str) {
+        control(out, 2, s.len());
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    fn uint(out: &mut Vec<u8>, kind: u8, value: u64) {
+        let bytes = value.to_be_bytes();
+        let start = bytes.iter().position(|&b| b != 0).unwrap_or(bytes.len());
+        control(out, kind, bytes.len() - start);
+        out.extend_from_slice(&bytes[start..]);
+    }
+
+    /// An IPv4 database with a single record, for 203.0.113.0/24, holding the
+    /// fields of both the City and the ASN databases.
+    fn database() -> Vec<u8> {
+        const NODES: u32 = 24;
+        let network = u32::from(std::net::Ipv4Addr::new(203, 0, 113, 0));
+
+        // Search tree, with 24 bits records: one node per bit of the prefix
+        let mut db = Vec::new();
+        for i in 0..NODES {
+            let next = if i + 1 == NODES { NODES + 16 } else { i + 1 };
+            let mut records = [NODES, NODES];
+            records[(network >> (31 - i) & 1) as usize] = next;
+            for record in records {
+                db.extend_from_slice(&record.to_be_bytes()[1..]);
+            }
+        }
+        db.extend_from_slice(&[0; 16]);
+
+        // Data section
+        control(&mut db, 7, 3);
+        string(&mut db, "city");
+        control(&mut db, 7, 1);
+        string(&mut db, "names");
+        control(&mut db, 7, 2);
+        string(&mut db, "en");
+        string(&mut db, "Paris");
+        string(&mut db, "fr");
+        string(&mut db, "Paris");
+        string(&mut db, "country");
+        control(&mut db, 7, 1);
+        string(&mut db, "iso_code");
+        string(&mut db, "FR");
+        string(&mut db, "autonomous_system_number");
+        uint(&mut db, 6, 64500);
+
+        // Metadata
+        db.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+        control(&mut db, 7, 9);
+        string(&mut db, "binary_format_major_version");
+        uint(&mut db, 5, 2);
+        string(&mut db, "binary_format_minor_version");
+        uint(&mut db, 5, 0);
+        string(&mut db, "build_epoch");
+        uint(&mut db, 9, 1_700_000_000);
+        string(&mut db, "database_type");
+        string(&mut db, "Test");
+        string(&mut db, "description");
+        control(&mut db, 7, 0);
+        string(&mut db, "ip_version");
+        uint(&mut db, 5, 4);
+        string(&mut db, "languages");
+        control(&mut db, 11, 0);
+        string(&mut db, "node_count");
+        uint(&mut db, 6, NODES.into());
+        string(&mut db, "record_size");
+        uint(&mut db, 5, 24);
+        db
+    }
+
+    /// Write the test database to a temporary file.
+    fn database_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("mc-query-geoip-{name}-{}.mmdb", std::process::id()));
+        std::fs::write(&path, database()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_lookup() {
+        let path = database_file("lookup");
+        let enricher = Enricher::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            enricher.lookup([203, 0, 113, 42].into()),
+            GeoInfo {
+                country: Some("FR".into()),
+                city: Some("Paris".into()),
+                asn: Some(64500),
+            }
+        );
+        // Lookup misses, and IPv6 addresses in an IPv4 database
+        assert_eq!(enricher.lookup([203, 0, 112, 1].into()), GeoInfo::default());
+        assert_eq!(
+            enricher.lookup("2001:db8::1".parse().unwrap()),
+            GeoInfo::default()
+        );
+    }
+
+    #[test]
+    fn test_enrich() {
+        let path = database_file("enrich");
+        let enricher = Enricher::open(&path).unwrap().with_database(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        // Shared between threads
+        let results = std::thread::spawn({
+            let enricher = enricher.clone();
+            move || {
+                enricher.enrich([
+                    (SocketAddr::from(([203, 0, 113, 7], 25565)), sample_stat()),
+                    (SocketAddr::from(([192, 0, 2, 7], 25565)), sample_stat()),
+                ])
+            }
+        })
+        .join()
+        .unwrap();
+        assert_eq!(results[0].2.country.as_deref(), Some("FR"));
+        assert_eq!(results[0].2.asn, Some(64500));
+        assert_eq!(results[1].2, GeoInfo::default());
+        assert_eq!(results[1].1.hostname, "A Minecraft Server");
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let missing = std::env::temp_dir().join("mc-query-geoip-missing.mmdb");
+        let err = Enricher::open(missing).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        let path = std::env::temp_dir().join(format!(
+            "mc-query-geoip-invalid-{}.mmdb",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"not a database").unwrap();
+        let err = Enricher::open(&path).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,151 @@
+//! Blocking implementation of the generic GameSpy4 client.
+//!
+//! Uses [std::net::UdpSocket] for sending and receiving UDP data.
+
+use std::{
+    io,
+    net::{Ipv4Addr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use super::*;
+use crate::{split_address, BasicStat, Token, DEFAULT_TIMEOUT, RESPONSE_HEADER_SIZE};
+
+/// A blocking GameSpy4 client using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct Gs4Client {
+    socket: UdpSocket,
+    session_id: u32,
+    protocol: Protocol,
+}
+
+impl Gs4Client {
+    /// Build a new Gs4Client from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new(ip: &str, protocol: Protocol) -> io::Result<Self> {
+        let (ip, port) = split_address(ip)?;
+        Self::new_with_socket_address(
+            ip,
+            port,
+            (Ipv4Addr::UNSPECIFIED, 0),
+            Some(DEFAULT_TIMEOUT),
+            protocol,
+        )
+    }
+
+    /// Builds a new Gs4Client from the given IP address, port, socket address and optional timeout.
+    ///
+    /// The IP adress must not contain a port.
+    pub fn new_with_socket_address(
+        ip: &str,
+        port: u16,
+        addr: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+        protocol: Protocol,
+    ) -> io::Result<Self> {
+        if ip.contains(':') {
+            return Err(custom_io_error(
+                "Invalid IP address: must not contain a port.",
+            ));
+        }
+
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(timeout)?;
+        socket.connect((ip, port))?;
+
+        let session_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_nanos() as u32;
+
+        Ok(Self {
+            socket,
+            session_id,
+            protocol,
+        })
+    }
+
+    /// The protocol used by this client.
+    pub fn protocol(&self) -> &Protocol {
+        &self.protocol
+    }
+
+    /// Send a UDP handshake packet to the client socket.
+    ///
+    /// Receive and parse the response into a token.
+    pub fn handshake(&self) -> io::Result<Token> {
+        self.socket
+            .send(&self.protocol.handshake(self.session_id))?;
+
+        let mut buf = [0; Token::RESPONSE_SIZE];
+        let received = self.socket.recv(&mut buf)?;
+
+        Token::try_from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+        )
+    }
+
+    /// Request and wait for a basic status packet on the client socket.
+    ///
+    /// The basic status has the same layout in every GameSpy4 game.
+    pub fn basic_stat(&self, token: Token) -> io::Result<BasicStat> {
+        self.socket
+            .send(&self.protocol.basic_stat(self.session_id, token.0))?;
+
+        let mut buf = vec![0; BasicStat::RESPONSE_SIZE];
+        let received = self.socket.recv(&mut buf)?;
+
+        BasicStat::from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+        )
+    }
+
+    /// Request and wait for a full status packet on the client socket.
+    pub fn full_stat(&self, token: Token) -> io::Result<GenericStat> {
+        self.socket
+            .send(&self.protocol.full_stat(self.session_id, token.0))?;
+
+        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
+        let received = self.socket.recv(&mut buf)?;
+
+        GenericStat::from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+            &self.protocol,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{spawn_stub, OTHER_GAME};
+    use super::*;
+    use crate::testing::MockQueryServer;
+
+    #[test]
+    fn test_other_game() {
+        let addr = spawn_stub();
+        let client = Gs4Client::new(&addr.to_string(), OTHER_GAME).unwrap();
+
+        let token = client.handshake().unwrap();
+        let stat = client.full_stat(token).unwrap();
+        assert_eq!(stat.get("hostname"), Some("Another game"));
+        assert!(stat.missing_keys(client.protocol()).is_empty());
+    }
+
+    #[test]
+    fn test_minecraft() {
+        let server = MockQueryServer::new().unwrap();
+        let client = Gs4Client::new(&server.addr().to_string(), Protocol::MINECRAFT).unwrap();
+
+        let token = client.handshake().unwrap();
+        let stat = client.full_stat(token).unwrap();
+        assert_eq!(FullStat::try_from(stat).unwrap(), server.full_stat());
+        assert_eq!(client.basic_stat(token).unwrap().numplayers, 2);
+    }
+}
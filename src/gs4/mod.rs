@@ -0,0 +1,409 @@
+//! Generic client for the [GameSpy4](https://wiki.vg/Query) query protocol.
+//!
+//! The Minecraft Query protocol is the GameSpy4 (UT3) protocol, which other
+//! games implement as well, with their own set of keys and sometimes another
+//! magic number. A [`Protocol`] describes these differences, and a full status
+//! is returned as a [`GenericStat`], with the key-value pairs and the player
+//! list as sent by the server.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::gs4::{blocking::Gs4Client, Protocol};
+//! let protocol = Protocol {
+//!     keys: &["hostname", "gamever", "numplayers", "maxplayers"],
+//!     ..Protocol::MINECRAFT
+//! };
+//! let client = Gs4Client::new("127.0.0.1:30000", protocol)?;
+//! let token = client.handshake()?;
+//! let stat = client.full_stat(token)?;
+//!
+//! println!("{:?}: {:?}", stat.get("hostname"), stat.players);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{borrow::Cow, io};
+
+use bytes::BufMut;
+
+use crate::packets::{self, PacketType};
+use crate::{
+    custom_io_error, latin1_to_string, not_enough_data, pairs, split_at_subslice, FullStat,
+    FullStatRef,
+};
+
+/// Description of the variant of the GameSpy4 protocol implemented by a game
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protocol {
+    /// Magic number at the start of server-bound packets
+    pub magic: u16,
+    /// Mask applied to session IDs, as some servers ignore the higher bits of each byte
+    pub session_mask: u32,
+    /// Padding after the token in full status requests, telling them apart from basic status requests
+    pub full_stat_padding: [u8; 4],
+    /// Size of the padding at the start of full status payloads
+    pub padding_start_size: usize,
+    /// Marker between the key-value and players sections of full status payloads
+    pub players_marker: &'static [u8],
+    /// Keys every full status must contain, see [`GenericStat::missing_keys`]
+    pub keys: &'static [&'static str],
+}
+
+impl Protocol {
+    /// The Minecraft Query protocol, used by the [`QueryClient`](crate::blocking::QueryClient)s.
+    pub const MINECRAFT: Self = Self {
+        magic: packets::MAGIC_NUMBER,
+        session_mask: packets::SESSION_MASK,
+        full_stat_padding: [0; 4],
+        padding_start_size: FullStat::PADDING_START_SIZE,
+        players_marker: b"\x01player_\0\0",
+        keys: &[
+            "hostname",
+            "gametype",
+            "game_id",
+            "version",
+            "plugins",
+            "map",
+            "numplayers",
+            "maxplayers",
+            "hostport",
+            "hostip",
+        ],
+    };
+
+    /// Build a server-bound packet.
+    fn request(&self, packet_type: PacketType, session_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut res = Vec::with_capacity(7 + payload.len());
+        res.put_u16(self.magic);
+        res.put_u8(packet_type as u8);
+        res.put_u32(session_id & self.session_mask);
+        res.put_slice(payload);
+        res
+    }
+
+    /// Build a handshake request packet.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{gs4::Protocol, packets};
+    /// assert_eq!(Protocol::MINECRAFT.handshake(1), &packets::Handshake::new(1)[..]);
+    /// ```
+    pub fn handshake(&self, session_id: u32) -> Vec<u8> {
+        self.request(PacketType::Handshake, session_id, &[])
+    }
+
+    /// Build a basic status request packet.
+    pub fn basic_stat(&self, session_id: u32, token: u32) -> Vec<u8> {
+        self.request(PacketType::Stat, session_id, &token.to_be_bytes())
+    }
+
+    /// Build a full status request packet.
+    pub fn full_stat(&self, session_id: u32, token: u32) -> Vec<u8> {
+        let mut payload = [0; 8];
+        payload[..4].copy_from_slice(&token.to_be_bytes());
+        payload[4..].copy_from_slice(&self.full_stat_padding);
+        self.request(PacketType::Stat, session_id, &payload)
+    }
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self::MINECRAFT
+    }
+}
+
+/// Full status of a GameSpy4 server, as sent by the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GenericStat {
+    /// Key-value pairs, in order
+    pub rules: Vec<(String, String)>,
+    /// Names of the players currently online
+    pub players: Vec<String>,
+}
+
+impl GenericStat {
+    /// Parse a full status payload with the given protocol.
+    ///
+    /// Unlike [`FullStat::from_payload`], no key is required: see [`missing_keys`](Self::missing_keys).
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::gs4::{GenericStat, Protocol};
+    /// let payload = b"splitnum\0\x80\0\
+    ///     hostname\0A Minetest Server\0gamever\05.8.0\
+    ///     \0\0\x01player_\0\0\
+    ///     celeron55\0\0";
+    ///
+    /// let stat = GenericStat::from_payload(payload, &Protocol::MINECRAFT)?;
+    /// assert_eq!(stat.get("gamever"), Some("5.8.0"));
+    /// assert_eq!(stat.players, ["celeron55"]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload(payload: &[u8], protocol: &Protocol) -> io::Result<Self> {
+        let mut separator = b"\0\0".to_vec();
+        separator.extend_from_slice(protocol.players_marker);
+
+        let (kv_section, players_section) = split_at_subslice(
+            payload
+                .get(protocol.padding_start_size..)
+                .ok_or_else(not_enough_data)?,
+            &separator,
+        )
+        .ok_or_else(|| custom_io_error("Failed to parse full stat payload due to missing data."))?;
+
+        Ok(Self {
+            rules: pairs(kv_section.split(|&b| b == b'\0'))
+                .map(|(key, value)| (latin1_to_string(key), latin1_to_string(value)))
+                .collect(),
+            players: players_section
+                .split(|&b| b == b'\0')
+                .filter(|name| !name.is_empty())
+                .map(latin1_to_string)
+                .collect(),
+        })
+    }
+
+    /// Value of the first pair with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The keys of the protocol missing in this status.
+    pub fn missing_keys(&self, protocol: &Protocol) -> Vec<&'static str> {
+        protocol
+            .keys
+            .iter()
+            .copied()
+            .filter(|&key| self.get(key).is_none())
+            .collect()
+    }
+}
+
+impl TryFrom<GenericStat> for FullStat {
+    type Error = io::Error;
+
+    /// Extract the Minecraft keys from a generic full status. Fails with an IO
+    /// error on missing keys.
+    fn try_from(stat: GenericStat) -> io::Result<Self> {
+        // The first value of a key is kept, like in `get`
+        let pairs = stat.rules.iter().rev().map(|(k, v)| (k, v.as_str()));
+        let mut res = FullStatRef::from_pairs(pairs, Cow::Borrowed)?.into_owned();
+        // `StatString` is only another type than `String` with the `compact_str` feature
+        #[allow(clippy::useless_conversion)]
+        {
+            res.player_list = stat.players.into_iter().map(Into::into).collect();
+        }
+        Ok(res)
+    }
+}
+
+/// Full status of a Bedrock server with query enabled, on the
+/// [Bedrock port](crate::DEFAULT_BEDROCK_PORT) by default.
+///
+/// Bedrock servers send no `game_id`, and keys unknown to Java servers, like
+/// `whitelist` or `server_engine`, which are kept in [`extra`](Self::extra).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BedrockFullStat {
+    /// The Minecraft keys and the player list of the status
+    pub stat: FullStat,
+    /// The key-value pairs of the keys unknown to Java servers, in order
+    pub extra: Vec<(String, String)>,
+}
+
+impl BedrockFullStat {
+    /// Parse the full status payload of a Bedrock server.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::gs4::BedrockFullStat;
+    /// let payload = b"splitnum\0\x80\0\
+    ///     hostname\0A Bedrock Server\0gametype\0SMP\0version\x001.20.80\0\
+    ///     server_engine\0PocketMine-MP 5.15.0\0plugins\0\0map\0world\0\
+    ///     numplayers\x000\0maxplayers\x0020\0whitelist\0off\0\
+    ///     hostip\x000.0.0.0\0hostport\x0019132\
+    ///     \0\0\x01player_\0\0\0";
+    ///
+    /// let stat = BedrockFullStat::from_payload(payload)?;
+    /// assert_eq!(stat.stat.hostport, 19132);
+    /// assert_eq!(stat.whitelist(), Some(false));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload(payload: &[u8]) -> io::Result<Self> {
+        let generic = GenericStat::from_payload(payload, &Protocol::MINECRAFT)?;
+        let extra = generic
+            .rules
+            .iter()
+            .filter(|(key, _)| !Protocol::MINECRAFT.keys.contains(&key.as_str()))
+            .cloned()
+            .collect();
+        Ok(Self {
+            stat: generic.try_into()?,
+            extra,
+        })
+    }
+
+    /// Value of the first extra pair with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.extra
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the whitelist of the server is enabled, sent as `"on"` or `"off"`.
+    pub fn whitelist(&self) -> Option<bool> {
+        match self.get("whitelist")? {
+            "on" => Some(true),
+            "off" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Software and version of the server, like `"PocketMine-MP 5.15.0"`.
+    pub fn server_engine(&self) -> Option<&str> {
+        self.get("server_engine")
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A GameSpy4 variant with another magic number and set of keys
+    pub(crate) const OTHER_GAME: Protocol = Protocol {
+        magic: 0xFEFE,
+        session_mask: 0xFFFFFFFF,
+        full_stat_padding: [0xFF, 0xFF, 0xFF, 0x01],
+        padding_start_size: 11,
+        players_marker: b"\x01player_\0\0",
+        keys: &["hostname", "gamever", "numplayers", "maxplayers", "mapname"],
+    };
+
+    /// Full status payload of a server of [`OTHER_GAME`]
+    pub(crate) const OTHER_GAME_PAYLOAD: &[u8] = b"splitnum\0\x80\0\
+        hostname\0Another game\0gamever\x001.0\0numplayers\x001\0maxplayers\x008\0mapname\0arena\
+        \0\0\x01player_\0\0\
+        somebody\0\0";
+
+    /// Spawn an in-process stub answering requests of [`OTHER_GAME`] until no
+    /// request is received for a second.
+    pub(crate) fn spawn_stub() -> std::net::SocketAddr {
+        spawn_stub_for(OTHER_GAME, OTHER_GAME_PAYLOAD)
+    }
+
+    /// Spawn an in-process stub answering requests of a protocol with a full
+    /// status payload, until no request is received for a second.
+    pub(crate) fn spawn_stub_for(
+        protocol: Protocol,
+        payload: &'static [u8],
+    ) -> std::net::SocketAddr {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let mut buf = [0; 16];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                if len < 7 || buf[..2] != protocol.magic.to_be_bytes() {
+                    continue;
+                }
+                let (packet_type, payload) = match len {
+                    7 => (PacketType::Handshake, &b"1234\0"[..]),
+                    15 if buf[7..15] == protocol.full_stat(0, 1234)[7..] => {
+                        (PacketType::Stat, payload)
+                    }
+                    _ => continue,
+                };
+                let session_id = u32::from_be_bytes([buf[3], buf[4], buf[5], buf[6]]);
+                let response = packets::write_response(packet_type, session_id, payload);
+                socket.send_to(&response, peer).unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn test_requests() {
+        assert_eq!(
+            Protocol::MINECRAFT.basic_stat(0x01020304, 5),
+            &packets::BasicStat::new(0x01020304, 5)[..]
+        );
+        assert_eq!(
+            Protocol::MINECRAFT.full_stat(0x01020304, 5),
+            &packets::FullStat::new(0x01020304, 5)[..]
+        );
+        assert_eq!(
+            OTHER_GAME.full_stat(0xF0F0F0F0, 5),
+            b"\xFE\xFE\x00\xF0\xF0\xF0\xF0\x00\x00\x00\x05\xFF\xFF\xFF\x01"
+        );
+    }
+
+    #[test]
+    fn test_other_game() {
+        let stat = GenericStat::from_payload(OTHER_GAME_PAYLOAD, &OTHER_GAME).unwrap();
+        assert_eq!(stat.get("mapname"), Some("arena"));
+        assert_eq!(stat.players, ["somebody"]);
+        assert!(stat.missing_keys(&OTHER_GAME).is_empty());
+
+        // The Minecraft keys are not there
+        assert!(stat.missing_keys(&Protocol::MINECRAFT).contains(&"game_id"));
+        assert!(FullStat::try_from(stat).is_err());
+        assert!(FullStat::from_payload(OTHER_GAME_PAYLOAD).is_err());
+    }
+
+    /// Full status payload in the layout of a Bedrock server, without
+    /// `game_id` and with the Bedrock keys
+    pub(crate) const BEDROCK_PAYLOAD: &[u8] = b"splitnum\0\x80\0\
+        hostname\0\xa7bBedrock\xa7r Survival\0gametype\0SMP\0version\x001.21.2\0\
+        server_engine\0PocketMine-MP 5.21.0\0plugins\0PocketMine-MP 5.21.0: EconomyAPI 5.7.2\0\
+        map\0world\0numplayers\x002\0maxplayers\x0030\0whitelist\0on\0\
+        hostip\x00127.0.0.1\0hostport\x0019132\
+        \0\0\x01player_\0\0\
+        Steve\0Alex\0\0";
+
+    #[test]
+    fn test_bedrock() {
+        let bedrock = BedrockFullStat::from_payload(BEDROCK_PAYLOAD).unwrap();
+        assert_eq!(bedrock.stat.game_id, "");
+        assert_eq!(bedrock.stat.version, "1.21.2");
+        assert_eq!(bedrock.stat.hostport, crate::DEFAULT_BEDROCK_PORT);
+        assert_eq!(bedrock.stat.player_list, ["Steve", "Alex"]);
+        assert_eq!(bedrock.whitelist(), Some(true));
+        assert_eq!(bedrock.server_engine(), Some("PocketMine-MP 5.21.0"));
+        assert_eq!(bedrock.get("hostname"), None);
+        assert_eq!(bedrock.extra.len(), 2);
+
+        // The Minecraft keys alone parse to the same status, and round-trip
+        let stat = FullStat::from_payload(BEDROCK_PAYLOAD).unwrap();
+        assert_eq!(stat, bedrock.stat);
+        assert_eq!(FullStat::from_payload(&stat.to_payload()).unwrap(), stat);
+    }
+
+    #[test]
+    fn test_bedrock_trusted() {
+        assert_eq!(
+            FullStat::from_payload_trusted(BEDROCK_PAYLOAD).unwrap(),
+            FullStat::from_payload(BEDROCK_PAYLOAD).unwrap()
+        );
+        assert_eq!(
+            FullStatRef::from_payload_trusted(BEDROCK_PAYLOAD).unwrap(),
+            FullStatRef::from_payload(BEDROCK_PAYLOAD).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_minecraft() {
+        let stat = crate::testing::sample_stat();
+        let generic = GenericStat::from_payload(&stat.to_payload(), &Protocol::MINECRAFT).unwrap();
+        assert!(generic.missing_keys(&Protocol::MINECRAFT).is_empty());
+        assert_eq!(FullStat::try_from(generic).unwrap(), stat);
+    }
+}
@@ -0,0 +1,156 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the generic GameSpy4 client.
+//!
+//! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
+
+use ::tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    time::timeout,
+};
+use std::{io, net::Ipv4Addr, time::Duration};
+
+use super::*;
+use crate::{split_address, BasicStat, Token, DEFAULT_TIMEOUT, RESPONSE_HEADER_SIZE};
+
+/// An asynchronous GameSpy4 client using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct Gs4Client {
+    socket: UdpSocket,
+    session_id: u32,
+    timeout: Option<Duration>,
+    protocol: Protocol,
+}
+
+impl Gs4Client {
+    /// Build a new Gs4Client from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn new(ip: &str, protocol: Protocol) -> io::Result<Self> {
+        let (ip, port) = split_address(ip)?;
+        Self::new_with_socket_address(
+            ip,
+            port,
+            (Ipv4Addr::UNSPECIFIED, 0),
+            Some(DEFAULT_TIMEOUT),
+            protocol,
+        )
+        .await
+    }
+
+    /// Builds a new Gs4Client from the given IP address, port, socket address and optional timeout.
+    ///
+    /// The IP adress must not contain a port.
+    pub async fn new_with_socket_address(
+        ip: &str,
+        port: u16,
+        addr: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+        protocol: Protocol,
+    ) -> io::Result<Self> {
+        if ip.contains(':') {
+            return Err(custom_io_error(
+                "Invalid IP address: must not contain a port.",
+            ));
+        }
+
+        let socket = UdpSocket::bind(addr).await?;
+        socket.connect((ip, port)).await?;
+
+        let session_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_nanos() as u32;
+
+        Ok(Self {
+            socket,
+            session_id,
+            timeout,
+            protocol,
+        })
+    }
+
+    /// The protocol used by this client.
+    pub fn protocol(&self) -> &Protocol {
+        &self.protocol
+    }
+
+    /// Receive a UDP packet from the client socket.
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let fut = self.socket.recv(buf);
+        if let Some(duration) = self.timeout {
+            timeout(duration, fut).await.map_err(|_| {
+                io::Error::new(io::ErrorKind::TimedOut, "UDP async recv call timed out.")
+            })?
+        } else {
+            fut.await
+        }
+    }
+
+    /// Send a UDP handshake packet to the client socket.
+    ///
+    /// Receive and parse the response into a token.
+    pub async fn handshake(&self) -> io::Result<Token> {
+        self.socket
+            .send(&self.protocol.handshake(self.session_id))
+            .await?;
+
+        let mut buf = [0; Token::RESPONSE_SIZE];
+        let received = self.recv(&mut buf).await?;
+
+        Token::try_from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+        )
+    }
+
+    /// Request and wait for a basic status packet on the client socket.
+    ///
+    /// The basic status has the same layout in every GameSpy4 game.
+    pub async fn basic_stat(&self, token: Token) -> io::Result<BasicStat> {
+        self.socket
+            .send(&self.protocol.basic_stat(self.session_id, token.0))
+            .await?;
+
+        let mut buf = vec![0; BasicStat::RESPONSE_SIZE];
+        let received = self.recv(&mut buf).await?;
+
+        BasicStat::from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+        )
+    }
+
+    /// Request and wait for a full status packet on the client socket.
+    pub async fn full_stat(&self, token: Token) -> io::Result<GenericStat> {
+        self.socket
+            .send(&self.protocol.full_stat(self.session_id, token.0))
+            .await?;
+
+        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
+        let received = self.recv(&mut buf).await?;
+
+        GenericStat::from_payload(
+            buf.get(RESPONSE_HEADER_SIZE..received)
+                .ok_or_else(not_enough_data)?,
+            &self.protocol,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{spawn_stub, OTHER_GAME};
+    use super::Gs4Client;
+
+    #[tokio::test]
+    async fn test_other_game() {
+        let addr = spawn_stub();
+        let client = Gs4Client::new(&addr.to_string(), OTHER_GAME).await.unwrap();
+
+        let token = client.handshake().await.unwrap();
+        let stat = client.full_stat(token).await.unwrap();
+        assert_eq!(stat.players, ["somebody"]);
+        assert!(stat.missing_keys(client.protocol()).is_empty());
+    }
+}
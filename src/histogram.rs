@@ -0,0 +1,304 @@
+//! Latency histograms of the Query clients.
+//!
+//! With the `histogram` feature, every client records the round trip time of
+//! its successful handshakes and status requests, from sending the request to
+//! parsing the response. Quantiles are computed over the last [`WINDOW`]
+//! requests of each kind:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::blocking::QueryClient;
+//! let client = QueryClient::new("play.example.com")?;
+//! for _ in 0..10 {
+//!     let token = client.handshake()?;
+//!     client.full_stat(token)?;
+//! }
+//! let latency = client.latency_snapshot();
+//! println!(
+//!     "handshake P50 {:?}, P99 {:?}",
+//!     latency.handshake.p50(),
+//!     latency.handshake.p99()
+//! );
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Durations are counted in log-scaled buckets, four per power of two of
+//! microseconds, so a histogram takes a fixed amount of memory and quantiles
+//! are at most 25% above the exact value.
+
+use std::{
+    io,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Number of most recent durations a histogram computes quantiles over
+pub const WINDOW: usize = 1024;
+
+/// Number of buckets, the last one holding every duration over 67 seconds
+const BUCKETS: usize = 104;
+
+/// Index of the bucket of a duration in microseconds.
+fn bucket_index(micros: u64) -> usize {
+    if micros < 4 {
+        return micros as usize;
+    }
+    let exponent = micros.ilog2() as usize;
+    let sub = (micros >> (exponent - 2)) as usize & 3;
+    ((exponent - 1) * 4 + sub).min(BUCKETS - 1)
+}
+
+/// Lower bound of a bucket in microseconds, inclusive. It is also the
+/// exclusive upper bound of the previous bucket.
+fn bucket_low(index: usize) -> u64 {
+    if index < 4 {
+        return index as u64;
+    }
+    let exponent = index / 4 + 1;
+    (4 + (index % 4) as u64) << (exponent - 2)
+}
+
+/// Durations recorded in the window, with their bucket counts
+#[derive(Debug)]
+struct Window {
+    /// Bucket of the recorded durations, in a ring buffer
+    ring: [u8; WINDOW],
+    len: usize,
+    next: usize,
+    counts: [u32; BUCKETS],
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self {
+            ring: [0; WINDOW],
+            len: 0,
+            next: 0,
+            counts: [0; BUCKETS],
+        }
+    }
+}
+
+/// A histogram of the last [`WINDOW`] durations recorded, safe to share
+/// between threads.
+#[derive(Debug, Default)]
+pub struct Histogram(Mutex<Window>);
+
+impl Histogram {
+    /// Record a duration, evicting the oldest one if the window is full.
+    pub fn record(&self, duration: Duration) {
+        let index = bucket_index(duration.as_micros().try_into().unwrap_or(u64::MAX));
+        let mut window = self.window();
+        let next = window.next;
+        if window.len == WINDOW {
+            let evicted = window.ring[next] as usize;
+            window.counts[evicted] -= 1;
+        } else {
+            window.len += 1;
+        }
+        window.ring[next] = index as u8;
+        window.counts[index] += 1;
+        window.next = (next + 1) % WINDOW;
+    }
+
+    /// Counts of the durations in the window.
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let window = self.window();
+        HistogramSnapshot {
+            buckets: (0..BUCKETS)
+                .filter(|&i| window.counts[i] > 0)
+                .map(|i| Bucket {
+                    low: Duration::from_micros(bucket_low(i)),
+                    high: Duration::from_micros(bucket_low(i + 1)),
+                    count: window.counts[i],
+                })
+                .collect(),
+            count: window.len as u32,
+        }
+    }
+
+    /// Remove every recorded duration.
+    pub fn reset(&self) {
+        *self.window() = Window::default();
+    }
+
+    fn window(&self) -> std::sync::MutexGuard<'_, Window> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A bucket of a [`HistogramSnapshot`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bucket {
+    /// Lower bound of the durations in the bucket, inclusive
+    pub low: Duration,
+    /// Upper bound of the durations in the bucket, exclusive
+    pub high: Duration,
+    /// Number of durations in the bucket
+    pub count: u32,
+}
+
+/// Counts of the durations of a [`Histogram`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistogramSnapshot {
+    /// Non-empty buckets, in increasing order
+    pub buckets: Vec<Bucket>,
+    /// Number of durations in the window
+    pub count: u32,
+}
+
+impl HistogramSnapshot {
+    /// The duration below which the fraction `q` of the durations fall,
+    /// rounded up to the upper bound of its bucket. `None` if the histogram is empty.
+    ///
+    /// `q` is clamped between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u32).max(1);
+        let mut seen = 0;
+        self.buckets.iter().find_map(|bucket| {
+            seen += bucket.count;
+            (seen >= rank).then_some(bucket.high)
+        })
+    }
+
+    /// The median duration, see [`quantile`](Self::quantile).
+    pub fn p50(&self) -> Option<Duration> {
+        self.quantile(0.5)
+    }
+
+    /// The 95th percentile, see [`quantile`](Self::quantile).
+    pub fn p95(&self) -> Option<Duration> {
+        self.quantile(0.95)
+    }
+
+    /// The 99th percentile, see [`quantile`](Self::quantile).
+    pub fn p99(&self) -> Option<Duration> {
+        self.quantile(0.99)
+    }
+}
+
+/// Latency histograms of a client, returned by `latency_snapshot`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencySnapshot {
+    /// Round trips of the handshakes
+    pub handshake: HistogramSnapshot,
+    /// Round trips of the basic and full status requests
+    pub stat: HistogramSnapshot,
+}
+
+/// Latency histograms embedded in a client
+#[derive(Debug, Default)]
+pub(crate) struct ClientLatency {
+    handshake: Histogram,
+    stat: Histogram,
+}
+
+impl ClientLatency {
+    /// Record the round trip of a client operation started at `start`, if it succeeded.
+    pub(crate) fn record<T>(&self, operation: &str, start: Instant, res: &io::Result<T>) {
+        let histogram = match operation {
+            "handshake" => &self.handshake,
+            "basic_stat" | "full_stat" => &self.stat,
+            _ => return,
+        };
+        if res.is_ok() {
+            histogram.record(start.elapsed());
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> LatencySnapshot {
+        LatencySnapshot {
+            handshake: self.handshake.snapshot(),
+            stat: self.stat.snapshot(),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.handshake.reset();
+        self.stat.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        for micros in 0..100_000 {
+            let index = bucket_index(micros);
+            assert!(bucket_low(index) <= micros && micros < bucket_low(index + 1));
+            // Buckets are at most a quarter of their lower bound wide
+            assert!(bucket_low(index + 1) - bucket_low(index) <= bucket_low(index).max(4) / 4);
+        }
+        assert_eq!(bucket_index(u64::MAX), BUCKETS - 1);
+        assert_eq!(bucket_low(BUCKETS), 1 << 27);
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let histogram = Histogram::default();
+        assert_eq!(histogram.snapshot().p50(), None);
+
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 100);
+        assert_eq!(snapshot.buckets.iter().map(|b| b.count).sum::<u32>(), 100);
+        // 50ms is in the [49.152ms, 57.344ms) bucket
+        assert_eq!(snapshot.p50(), Some(Duration::from_micros(57_344)));
+        // 95ms is in the [81.92ms, 98.304ms) bucket, along with 96ms to 98ms
+        assert_eq!(snapshot.p95(), Some(Duration::from_micros(98_304)));
+        // 99ms is in the [98.304ms, 114.688ms) bucket
+        assert_eq!(snapshot.p99(), Some(Duration::from_micros(114_688)));
+        assert_eq!(snapshot.quantile(0.0), Some(Duration::from_micros(1_024)));
+        assert_eq!(snapshot.quantile(2.0), snapshot.p99());
+
+        histogram.reset();
+        assert_eq!(histogram.snapshot().count, 0);
+        assert_eq!(histogram.snapshot().p99(), None);
+    }
+
+    #[test]
+    fn test_window() {
+        let histogram = Histogram::default();
+        for _ in 0..WINDOW {
+            histogram.record(Duration::from_millis(1));
+        }
+        for _ in 0..WINDOW / 2 {
+            histogram.record(Duration::from_millis(10));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, WINDOW as u32);
+        assert_eq!(
+            snapshot.buckets,
+            [
+                Bucket {
+                    low: Duration::from_micros(896),
+                    high: Duration::from_micros(1_024),
+                    count: WINDOW as u32 / 2,
+                },
+                Bucket {
+                    low: Duration::from_micros(8_192),
+                    high: Duration::from_micros(10_240),
+                    count: WINDOW as u32 / 2,
+                }
+            ]
+        );
+
+        for _ in 0..WINDOW / 2 {
+            histogram.record(Duration::from_millis(10));
+        }
+        assert_eq!(
+            histogram.snapshot().p50(),
+            Some(Duration::from_micros(10_240))
+        );
+        assert_eq!(
+            histogram.snapshot().quantile(0.0),
+            histogram.snapshot().p50()
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Export of server statuses in the InfluxDB [line protocol](https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/),
+//! ingested by InfluxDB and Telegraf.
+//!
+//! A status is written as a point of the [`MEASUREMENT`] measurement, with the
+//! tags given by the caller, the player counts, and the latency if known:
+//!
+//! ```rust
+//! # use minecraft_server_query::{influx, FullStat};
+//! # use std::time::{Duration, SystemTime};
+//! # let stat = FullStat {
+//! #     hostname: "A Minecraft Server".into(),
+//! #     gametype: "SMP".into(),
+//! #     game_id: "MINECRAFT".into(),
+//! #     version: "1.20.1".into(),
+//! #     plugins: "".into(),
+//! #     map: "world".into(),
+//! #     numplayers: 2,
+//! #     maxplayers: 20,
+//! #     hostport: 25565,
+//! #     hostip: "127.0.0.1".into(),
+//! #     player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+//! # };
+//! let mut line = String::new();
+//! influx::write_stat(
+//!     &mut line,
+//!     &stat,
+//!     [("server", "lobby"), ("host", "1.2.3.4")],
+//!     Some(Duration::from_micros(23_400)),
+//!     Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1712345678)),
+//! )?;
+//! assert_eq!(
+//!     line,
+//!     "minecraft_query,server=lobby,host=1.2.3.4 \
+//!      players_online=2i,players_max=20i,latency_ms=23.4 1712345678000000000\n",
+//! );
+//! # Ok::<(), std::fmt::Error>(())
+//! ```
+
+use std::{
+    fmt::{self, Write},
+    time::{Duration, SystemTime},
+};
+
+use crate::FullStat;
+
+/// Name of the measurement of the points
+pub const MEASUREMENT: &str = "minecraft_query";
+
+/// Write a tag key or value, escaping commas, equal signs, spaces and backslashes.
+///
+/// Line breaks are not allowed in tags, and are replaced by spaces.
+fn write_tag(w: &mut impl Write, tag: &str) -> fmt::Result {
+    for c in tag.chars() {
+        match c {
+            ',' | '=' | ' ' | '\\' => {
+                w.write_char('\\')?;
+                w.write_char(c)?;
+            }
+            '\n' | '\r' => w.write_str("\\ ")?,
+            _ => w.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Write a status as a line protocol point, terminated by a line break.
+///
+/// Tags are written in the given order, skipping tags with an empty key or
+/// value, which InfluxDB rejects. Without a timestamp, the server ingesting
+/// the point uses the time it received it at.
+pub fn write_stat<K: AsRef<str>, V: AsRef<str>>(
+    w: &mut impl Write,
+    stat: &FullStat,
+    tags: impl IntoIterator<Item = (K, V)>,
+    latency: Option<Duration>,
+    timestamp: Option<SystemTime>,
+) -> fmt::Result {
+    w.write_str(MEASUREMENT)?;
+    for (key, value) in tags {
+        let (key, value) = (key.as_ref(), value.as_ref());
+        if !key.is_empty() && !value.is_empty() {
+            w.write_char(',')?;
+            write_tag(w, key)?;
+            w.write_char('=')?;
+            write_tag(w, value)?;
+        }
+    }
+
+    write!(
+        w,
+        " players_online={}i,players_max={}i",
+        stat.numplayers, stat.maxplayers
+    )?;
+    if let Some(latency) = latency {
+        // Microseconds are exact in decimal, unlike the fractional seconds
+        write!(w, ",latency_ms={}", latency.as_micros() as f64 / 1000.0)?;
+    }
+
+    if let Some(timestamp) = timestamp {
+        match timestamp.duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(since) => write!(w, " {}", since.as_nanos())?,
+            Err(e) => write!(w, " -{}", e.duration().as_nanos())?,
+        }
+    }
+    w.write_char('\n')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::testing::sample_stat;
+
+    fn line<'a>(tags: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+        let mut res = String::new();
+        write_stat(&mut res, &sample_stat(), tags, None, None).unwrap();
+        res
+    }
+
+    #[test]
+    fn test_tag_escaping() {
+        assert_eq!(
+            line([("motd", "A Minecraft Server")]),
+            "minecraft_query,motd=A\\ Minecraft\\ Server players_online=2i,players_max=20i\n"
+        );
+        assert_eq!(
+            line([("my tag", "a=b,c"), ("path", "C:\\worlds\\")]),
+            "minecraft_query,my\\ tag=a\\=b\\,c,path=C:\\\\worlds\\\\ players_online=2i,players_max=20i\n"
+        );
+        assert_eq!(
+            line([("motd", "§6Line one\nLine \"two\"")]),
+            "minecraft_query,motd=§6Line\\ one\\ Line\\ \"two\" players_online=2i,players_max=20i\n"
+        );
+        // Empty tags are skipped
+        assert_eq!(
+            line([("", "value"), ("key", ""), ("host", "1.2.3.4")]),
+            "minecraft_query,host=1.2.3.4 players_online=2i,players_max=20i\n"
+        );
+    }
+
+    #[test]
+    fn test_fields_and_timestamp() {
+        let tags: BTreeMap<String, String> = [("server".into(), "lobby".into())].into();
+        let mut res = String::new();
+        write_stat(
+            &mut res,
+            &sample_stat(),
+            &tags,
+            Some(Duration::from_millis(5)),
+            Some(SystemTime::UNIX_EPOCH - Duration::from_secs(1)),
+        )
+        .unwrap();
+        assert_eq!(
+            res,
+            "minecraft_query,server=lobby players_online=2i,players_max=20i,latency_ms=5 -1000000000\n"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Logging of server statuses as [JSON Lines](https://jsonlines.org/), one
+//! record per poll, to keep a lightweight history to analyze later.
+//!
+//! A [`StatLogger`] wraps any writer, and appends a [`Record`] for every
+//! result it is given, with the time it was logged at:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking, jsonl::StatLogger};
+//! # use std::{fs::File, thread::sleep, time::Duration};
+//! let mut logger = StatLogger::new(File::create("history.jsonl")?);
+//! loop {
+//!     logger.log("play.example.com", &blocking::query("play.example.com"))?;
+//!     if logger.bytes_written() > 10_000_000 {
+//!         logger.rotate(File::create("history.1.jsonl")?)?;
+//!     }
+//!     sleep(Duration::from_secs(60));
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Successful polls are written as `{"timestamp":…,"target":…,"stat":{…}}`,
+//! and failed ones as `{"timestamp":…,"target":…,"error":"…"}`. Timestamps
+//! are in milliseconds since the Unix epoch.
+
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::FullStat;
+
+/// When a [`StatLogger`] flushes its writer
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Flush {
+    /// After every record, so that no record is lost if the program stops
+    #[default]
+    EveryRecord,
+    /// After every batch of the given number of records, for buffered writers
+    /// logging many servers. A batch of zero records never flushes.
+    Batch(u32),
+}
+
+/// Outcome of a poll, as read back from a log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// The server answered with this status
+    Stat(FullStat),
+    /// The poll failed with this error
+    Error(String),
+}
+
+/// A line of a log, to read it back with `serde_json`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// Time of the poll, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Address of the polled server
+    pub target: String,
+    /// Outcome of the poll
+    #[serde(flatten)]
+    pub outcome: Outcome,
+}
+
+/// Borrowed version of [`Outcome`], to log a status without cloning it
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OutcomeRef<'a> {
+    Stat(&'a FullStat),
+    Error(String),
+}
+
+/// Borrowed version of [`Record`]
+#[derive(Serialize)]
+struct RecordRef<'a> {
+    timestamp: u64,
+    target: &'a str,
+    #[serde(flatten)]
+    outcome: OutcomeRef<'a>,
+}
+
+/// Appends poll results to a writer, one JSON object per line.
+#[derive(Debug)]
+pub struct StatLogger<W: Write> {
+    writer: W,
+    flush: Flush,
+    /// Records written since the last flush
+    pending: u32,
+    /// Bytes written since the last rotation
+    bytes_written: u64,
+}
+
+impl<W: Write> StatLogger<W> {
+    /// Log to a writer, flushing it after every record.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            flush: Flush::EveryRecord,
+            pending: 0,
+            bytes_written: 0,
+        }
+    }
+
+    /// Set when the writer is flushed.
+    pub fn with_flush(mut self, flush: Flush) -> Self {
+        self.flush = flush;
+        self
+    }
+
+    /// Log the result of a poll of `target`, with the current time.
+    pub fn log(&mut self, target: &str, result: &io::Result<FullStat>) -> io::Result<()> {
+        self.log_at(target, result, SystemTime::now())
+    }
+
+    /// Log the result of a poll of `target`, made at the given time. Times
+    /// before the Unix epoch are logged as the epoch.
+    pub fn log_at(
+        &mut self,
+        target: &str,
+        result: &io::Result<FullStat>,
+        timestamp: SystemTime,
+    ) -> io::Result<()> {
+        let record = RecordRef {
+            timestamp: timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            target,
+            outcome: match result {
+                Ok(stat) => OutcomeRef::Stat(stat),
+                Err(e) => OutcomeRef::Error(e.to_string()),
+            },
+        };
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.bytes_written += line.len() as u64;
+
+        self.pending += 1;
+        match self.flush {
+            Flush::EveryRecord => self.flush(),
+            Flush::Batch(n) if self.pending == n => self.flush(),
+            Flush::Batch(_) => Ok(()),
+        }
+    }
+
+    /// Flush the writer, ending the current batch.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.pending = 0;
+        self.writer.flush()
+    }
+
+    /// Number of bytes written since the logger was created or last rotated,
+    /// to rotate logs by size.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Log to a new writer, returning the previous one once flushed.
+    pub fn rotate(&mut self, writer: W) -> io::Result<W> {
+        self.flush()?;
+        self.bytes_written = 0;
+        Ok(std::mem::replace(&mut self.writer, writer))
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Flush the writer and return it.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::testing::sample_stat;
+
+    fn parse(log: &[u8]) -> Vec<Record> {
+        std::str::from_utf8(log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_records() {
+        let mut logger = StatLogger::new(Vec::new());
+        let t = UNIX_EPOCH + Duration::from_millis(1_712_345_678_901);
+        logger
+            .log_at("127.0.0.1:25565", &Ok(sample_stat()), t)
+            .unwrap();
+        let error = io::Error::new(io::ErrorKind::TimedOut, "timed out");
+        logger
+            .log_at("play.example.com", &Err(error), t + Duration::from_secs(60))
+            .unwrap();
+        logger
+            .log_at(
+                "[::1]:25565",
+                &Ok(sample_stat()),
+                UNIX_EPOCH - Duration::from_secs(1),
+            )
+            .unwrap();
+
+        let log = logger.into_inner().unwrap();
+        let line = log.split(|&b| b == b'\n').nth(1).unwrap();
+        assert_eq!(
+            line,
+            br#"{"timestamp":1712345738901,"target":"play.example.com","error":"timed out"}"#
+        );
+        assert_eq!(
+            parse(&log),
+            [
+                Record {
+                    timestamp: 1_712_345_678_901,
+                    target: "127.0.0.1:25565".into(),
+                    outcome: Outcome::Stat(sample_stat()),
+                },
+                Record {
+                    timestamp: 1_712_345_738_901,
+                    target: "play.example.com".into(),
+                    outcome: Outcome::Error("timed out".into()),
+                },
+                Record {
+                    timestamp: 0,
+                    target: "[::1]:25565".into(),
+                    outcome: Outcome::Stat(sample_stat()),
+                },
+            ]
+        );
+    }
+
+    /// A writer counting its flushes
+    #[derive(Debug, Default)]
+    struct Flushes {
+        data: Vec<u8>,
+        flushes: usize,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.data.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_and_rotate() {
+        let mut logger = StatLogger::new(Flushes::default()).with_flush(Flush::Batch(2));
+        for _ in 0..5 {
+            logger.log("127.0.0.1", &Ok(sample_stat())).unwrap();
+        }
+        assert_eq!(logger.get_ref().flushes, 2);
+        let written = logger.bytes_written();
+        assert_eq!(written, logger.get_ref().data.len() as u64);
+
+        let old = logger.rotate(Flushes::default()).unwrap();
+        assert_eq!(old.flushes, 3);
+        assert_eq!(parse(&old.data).len(), 5);
+        assert_eq!(logger.bytes_written(), 0);
+
+        logger.log("127.0.0.1", &Ok(sample_stat())).unwrap();
+        assert_eq!(logger.bytes_written(), written / 5);
+        assert_eq!(logger.get_ref().flushes, 0);
+
+        let mut logger = StatLogger::new(Flushes::default());
+        logger.log("127.0.0.1", &Ok(sample_stat())).unwrap();
+        assert_eq!(logger.get_ref().flushes, 1);
+    }
+}
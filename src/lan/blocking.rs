@@ -0,0 +1,348 @@
+//! Blocking implementation of the LAN world discovery and announcement.
+//!
+//! Uses [std::net::UdpSocket] for sending and receiving UDP data.
+
+use std::{
+    io,
+    net::UdpSocket,
+    sync::mpsc,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::*;
+
+/// A blocking listener for LAN world announcements, using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct Listener {
+    socket: UdpSocket,
+}
+
+impl Listener {
+    /// Bind a new listener to the announcement port, and join the announcement multicast group.
+    pub fn bind() -> io::Result<Self> {
+        Ok(Self {
+            socket: multicast_socket()?,
+        })
+    }
+
+    /// Collect announcements for the given duration, returning every world discovered.
+    ///
+    /// Worlds are deduplicated by source address and port, and malformed
+    /// announcements are ignored.
+    pub fn discover(&self, duration: Duration) -> io::Result<Vec<LanServer>> {
+        let deadline = Instant::now() + duration;
+        let mut discovered = Discovered::default();
+        let mut buf = [0; ANNOUNCEMENT_SIZE];
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            self.socket.set_read_timeout(Some(remaining))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((received, source)) => discovered.push(&buf[..received], source),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    break
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(discovered.0)
+    }
+}
+
+/// Convenience function to collect LAN world announcements for the given duration.
+pub fn discover(duration: Duration) -> io::Result<Vec<LanServer>> {
+    Listener::bind()?.discover(duration)
+}
+
+/// Broadcast a Query handshake to the given port on the local network,
+/// returning the basic status of every server answering within the wait.
+///
+/// Only servers with `enable-query` set answer, and the broadcast does not
+/// cross routers. See [`discover_broadcast_to`] to send directed broadcasts.
+pub fn discover_broadcast(port: u16, wait: Duration) -> io::Result<Vec<(SocketAddr, BasicStat)>> {
+    discover_broadcast_to(&[SocketAddr::from((Ipv4Addr::BROADCAST, port))], wait)
+}
+
+/// Send a Query handshake to each of the given addresses, usually the
+/// directed broadcast addresses of the local interfaces, returning the basic
+/// status of every server answering within the wait.
+///
+/// Status requests are sent to servers as soon as they answer the handshake,
+/// and servers are deduplicated by address. Servers which did not send their
+/// status before the end of the wait are not returned.
+pub fn discover_broadcast_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, BasicStat)>> {
+    let servers = broadcast(targets, wait)?;
+    Ok(servers
+        .into_iter()
+        .map(|(addr, _, stat)| (addr, stat))
+        .collect())
+}
+
+/// Broadcast a Query handshake to the given port on the local network,
+/// returning the full status of every server answering within the wait,
+/// with the token it was requested with.
+///
+/// Like [`discover_broadcast`], with full status requests: servers which
+/// answer the handshake but not the full status request are left out.
+pub fn discover_broadcast_full(
+    port: u16,
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, FullStat)>> {
+    discover_broadcast_full_to(&[SocketAddr::from((Ipv4Addr::BROADCAST, port))], wait)
+}
+
+/// Send a Query handshake to each of the given addresses, returning the full
+/// status of every server answering within the wait, with the token it was
+/// requested with.
+///
+/// Like [`discover_broadcast_to`], with full status requests.
+pub fn discover_broadcast_full_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, FullStat)>> {
+    broadcast(targets, wait)
+}
+
+/// Send a Query handshake to each of the given addresses, and request a
+/// status from every server answering within the wait.
+fn broadcast<S: BroadcastStat>(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, S)>> {
+    let deadline = Instant::now() + wait;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+
+    let mut broadcast = Broadcast::<S>::new();
+    for target in targets {
+        socket.send_to(&broadcast.handshake(), target)?;
+    }
+
+    let mut buf = vec![0; S::RESPONSE_SIZE];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        socket.set_read_timeout(Some(remaining))?;
+        match socket.recv_from(&mut buf) {
+            Ok((received, source)) => {
+                if let Some(request) = broadcast.receive(&buf[..received], source) {
+                    socket.send_to(request.as_bytes(), source)?;
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                break
+            }
+            // ICMP port unreachable errors from targets without a server are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(broadcast.into_servers())
+}
+
+/// A blocking announcer advertising a world on the local network, using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct Announcer {
+    socket: UdpSocket,
+    announcement: String,
+}
+
+impl Announcer {
+    /// Build a new announcer for the world with the given MoTD and port.
+    ///
+    /// The MoTD is sanitized, see [`announcement`]. Announcements are sent
+    /// with a multicast TTL of 1, so that they do not leave the local network.
+    pub fn new(motd: &str, port: u16) -> io::Result<Self> {
+        Self::new_with_options(motd, port, 1, None)
+    }
+
+    /// Build a new announcer for the world with the given MoTD and port, sending
+    /// announcements with the given multicast TTL and from the given interface,
+    /// identified by its IPv4 address.
+    pub fn new_with_options(
+        motd: &str,
+        port: u16,
+        ttl: u32,
+        interface: Option<Ipv4Addr>,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        configure_announcer(SockRef::from(&socket), ttl, interface)?;
+
+        Ok(Self {
+            socket,
+            announcement: announcement(motd, port),
+        })
+    }
+
+    /// Send a single announcement.
+    ///
+    /// To be discovered, a world must be announced every [`ANNOUNCE_INTERVAL`].
+    pub fn announce(&self) -> io::Result<()> {
+        self.socket
+            .send_to(
+                self.announcement.as_bytes(),
+                (MULTICAST_ADDR, MULTICAST_PORT),
+            )
+            .map(|_| ())
+    }
+
+    /// Spawn a thread sending an announcement every [`ANNOUNCE_INTERVAL`], until
+    /// the returned handle is stopped or dropped.
+    ///
+    /// Errors while sending an announcement are ignored.
+    pub fn spawn(self) -> AnnouncerHandle {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || loop {
+            let _ = self.announce();
+            if stopped.recv_timeout(ANNOUNCE_INTERVAL) != Err(mpsc::RecvTimeoutError::Timeout) {
+                break;
+            }
+        });
+
+        AnnouncerHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle to an announcer thread, stopping it when dropped.
+#[derive(Debug)]
+pub struct AnnouncerHandle {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AnnouncerHandle {
+    /// Stop the announcer thread, waiting for it to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for AnnouncerHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::tests::announce;
+
+    #[test]
+    fn test_discover() {
+        let listener = super::Listener::bind().unwrap();
+
+        let handle = std::thread::spawn(|| {
+            for _ in 0..3 {
+                std::thread::sleep(Duration::from_millis(50));
+                announce(b"[MOTD]Blocking World[/MOTD][AD]40001[/AD]");
+                announce(b"[MOTD]Blocking World[/MOTD]");
+            }
+        });
+        let servers = listener.discover(Duration::from_millis(500)).unwrap();
+        handle.join().unwrap();
+
+        let servers = servers
+            .into_iter()
+            .filter(|s| s.port == 40001)
+            .collect::<Vec<_>>();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].motd, "Blocking World");
+    }
+
+    #[test]
+    fn test_announcer() {
+        let listener = super::Listener::bind().unwrap();
+
+        let announcer = super::Announcer::new("Announced [/MOTD]World", 40003).unwrap();
+        let handle = announcer.spawn();
+        let servers = listener.discover(Duration::from_millis(300)).unwrap();
+        handle.stop();
+
+        let servers = servers
+            .into_iter()
+            .filter(|s| s.port == 40003)
+            .collect::<Vec<_>>();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].motd, "Announced World");
+    }
+
+    #[test]
+    fn test_discover_broadcast() {
+        let server = crate::testing::MockQueryServer::new().unwrap();
+        let other = crate::testing::MockQueryServer::new().unwrap();
+
+        let servers = super::discover_broadcast_to(
+            &[server.addr(), other.addr(), server.addr()],
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert_eq!(servers.len(), 2);
+        assert!(servers.iter().any(|(addr, _)| *addr == server.addr()));
+        assert!(servers.iter().any(|(addr, _)| *addr == other.addr()));
+        assert_eq!(servers[0].1.motd, server.full_stat().hostname);
+    }
+
+    #[test]
+    fn test_discover_broadcast_full() {
+        use crate::{
+            packets::PacketType,
+            testing::{Faults, MockQueryServer},
+        };
+
+        let server = MockQueryServer::new().unwrap();
+        // Answers handshakes, but not full status requests
+        let silent = MockQueryServer::new().unwrap();
+        silent.set_faults(
+            PacketType::Stat,
+            Faults {
+                ignore_full_stat: true,
+                ..Faults::default()
+            },
+        );
+
+        let servers = super::discover_broadcast_full_to(
+            &[silent.addr(), server.addr()],
+            Duration::from_millis(300),
+        )
+        .unwrap();
+        assert_eq!(servers.len(), 1);
+        let (addr, _token, stat) = &servers[0];
+        assert_eq!((*addr, stat), (server.addr(), &server.full_stat()));
+        assert_eq!(silent.dropped(PacketType::Stat), 1);
+    }
+}
@@ -0,0 +1,441 @@
+//! Discovery of Java Edition worlds opened to LAN.
+//!
+//! Worlds opened to LAN announce themselves every 1.5 seconds by multicasting
+//! `[MOTD]<motd>[/MOTD][AD]<port>[/AD]` to [`MULTICAST_ADDR`]:[`MULTICAST_PORT`].
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::lan;
+//! # use std::time::Duration;
+//! for server in lan::blocking::discover(Duration::from_secs(3))? {
+//!     println!("{} on {}:{}", server.motd, server.source_ip, server.port);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! A server can be advertised the same way with an announcer:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::lan;
+//! let handle = lan::blocking::Announcer::new("A World", 25565)?.spawn();
+//! // ...
+//! handle.stop();
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Dedicated servers do not announce themselves, but the ones with the Query
+//! protocol enabled can be found by broadcasting a handshake:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::lan;
+//! # use std::time::Duration;
+//! for (addr, stat) in lan::blocking::discover_broadcast(25565, Duration::from_secs(1))? {
+//!     println!("{} on {}", stat.motd, addr);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`discover_broadcast_full`](blocking::discover_broadcast_full) requests
+//! their full status instead, leaving out the servers which don't answer it.
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, SockRef, Socket, Type};
+
+use crate::packets::{self, PacketType, QueryPacket, ResponseHeader, SESSION_MASK};
+use crate::{BasicStat, FullStat, Token};
+
+/// Multicast group LAN worlds are announced to
+pub const MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 2, 60);
+/// Port LAN worlds are announced to
+pub const MULTICAST_PORT: u16 = 4445;
+
+/// Interval between two announcements of the same world
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_millis(1500);
+
+/// Announcement max size, in bytes
+const ANNOUNCEMENT_SIZE: usize = 1024;
+/// Tags delimiting the fields of an announcement
+const TAGS: [&str; 4] = ["[MOTD]", "[/MOTD]", "[AD]", "[/AD]"];
+
+/// A world opened to LAN
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LanServer {
+    /// World MoTD, as displayed in the multiplayer menu
+    pub motd: String,
+    /// Port the world is listening on
+    pub port: u16,
+    /// IP the announcement was sent from
+    pub source_ip: IpAddr,
+}
+
+impl LanServer {
+    /// Parse a LAN world announcement. Returns `None` if the announcement is malformed.
+    ///
+    /// The address tag usually only contains the port, but some implementations
+    /// send a full `host:port` address: the host part is ignored.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::lan::LanServer;
+    /// # use std::net::Ipv4Addr;
+    /// let source_ip = Ipv4Addr::new(192, 168, 1, 10).into();
+    /// let server = LanServer::from_announcement(b"[MOTD]A World[/MOTD][AD]54321[/AD]", source_ip);
+    ///
+    /// assert_eq!(
+    ///     server,
+    ///     Some(LanServer {
+    ///         motd: "A World".to_string(),
+    ///         port: 54321,
+    ///         source_ip,
+    ///     })
+    /// );
+    /// ```
+    pub fn from_announcement(payload: &[u8], source_ip: IpAddr) -> Option<Self> {
+        let payload = String::from_utf8_lossy(payload);
+
+        let motd = tag_content(&payload, "[MOTD]", "[/MOTD]")?;
+        let address = tag_content(&payload, "[AD]", "[/AD]")?.trim();
+        let port = address
+            .rsplit_once(':')
+            .map_or(address, |(_, port)| port)
+            .parse()
+            .ok()?;
+
+        Some(Self {
+            motd: motd.to_string(),
+            port,
+            source_ip,
+        })
+    }
+}
+
+/// Return the content between the first occurence of the opening tag and the
+/// following closing tag.
+fn tag_content<'a>(payload: &'a str, open: &str, close: &str) -> Option<&'a str> {
+    let (_, rest) = payload.split_once(open)?;
+    let (content, _) = rest.split_once(close)?;
+    Some(content)
+}
+
+/// Build the announcement of a world with the given MoTD and port.
+///
+/// Tags are removed from the MoTD, so that it cannot end the MoTD field early
+/// or inject another address.
+///
+/// ```rust
+/// # use minecraft_server_query::lan::announcement;
+/// assert_eq!(
+///     announcement("A [/MO[/MOTD]TD]World", 25565),
+///     "[MOTD]A World[/MOTD][AD]25565[/AD]",
+/// );
+/// ```
+pub fn announcement(motd: &str, port: u16) -> String {
+    let mut motd = motd.to_string();
+    while let Some(tag) = TAGS.iter().find(|tag| motd.contains(*tag)) {
+        motd = motd.replace(tag, "");
+    }
+
+    format!("[MOTD]{}[/MOTD][AD]{}[/AD]", motd, port)
+}
+
+/// Set the multicast TTL and, optionally, the outgoing interface of an announcer socket.
+fn configure_announcer(
+    socket: SockRef<'_>,
+    ttl: u32,
+    interface: Option<Ipv4Addr>,
+) -> io::Result<()> {
+    socket.set_multicast_ttl_v4(ttl)?;
+    socket.set_multicast_loop_v4(true)?;
+    if let Some(interface) = interface {
+        socket.set_multicast_if_v4(&interface)?;
+    }
+    Ok(())
+}
+
+/// LAN worlds discovered during a listening window, deduplicated by source address and port
+#[derive(Debug, Default)]
+struct Discovered(Vec<LanServer>);
+
+impl Discovered {
+    /// Parse and record an announcement, ignoring it if it is malformed or
+    /// if the world was already discovered.
+    fn push(&mut self, payload: &[u8], source: SocketAddr) {
+        if let Some(server) = LanServer::from_announcement(payload, source.ip()) {
+            if !self
+                .0
+                .iter()
+                .any(|s| s.source_ip == server.source_ip && s.port == server.port)
+            {
+                self.0.push(server);
+            }
+        }
+    }
+}
+
+/// Status requested from the servers answering a broadcast handshake
+trait BroadcastStat: Sized {
+    /// Max size of a response
+    const RESPONSE_SIZE: usize;
+    /// Status request packet
+    type Request: QueryPacket;
+
+    /// Build the status request for a token.
+    fn request(session_id: u32, token: Token) -> Self::Request;
+
+    /// Parse the status from a response payload.
+    fn from_payload(payload: &[u8]) -> io::Result<Self>;
+}
+
+impl BroadcastStat for BasicStat {
+    const RESPONSE_SIZE: usize = BasicStat::RESPONSE_SIZE;
+    type Request = packets::BasicStat;
+
+    fn request(session_id: u32, token: Token) -> Self::Request {
+        packets::BasicStat::new(session_id, token.0)
+    }
+
+    fn from_payload(payload: &[u8]) -> io::Result<Self> {
+        BasicStat::from_payload(payload)
+    }
+}
+
+impl BroadcastStat for FullStat {
+    const RESPONSE_SIZE: usize = FullStat::RESPONSE_SIZE;
+    type Request = packets::FullStat;
+
+    fn request(session_id: u32, token: Token) -> Self::Request {
+        packets::FullStat::new(session_id, token.0)
+    }
+
+    fn from_payload(payload: &[u8]) -> io::Result<Self> {
+        FullStat::from_payload(payload)
+    }
+}
+
+/// A server answering a broadcast handshake
+#[derive(Debug)]
+struct Responder<S> {
+    addr: SocketAddr,
+    /// Token of the last handshake response of the server
+    token: Token,
+    stat: Option<S>,
+}
+
+/// Servers answering a broadcast handshake, deduplicated by source address
+#[derive(Debug)]
+struct Broadcast<S> {
+    session_id: u32,
+    servers: Vec<Responder<S>>,
+}
+
+impl<S: BroadcastStat> Broadcast<S> {
+    fn new() -> Self {
+        let session_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_nanos() as u32;
+
+        Self {
+            session_id: session_id & SESSION_MASK,
+            servers: Vec::new(),
+        }
+    }
+
+    /// The handshake request to broadcast.
+    fn handshake(&self) -> packets::Handshake {
+        packets::Handshake::new(self.session_id)
+    }
+
+    /// Handle a datagram received by the broadcasting socket.
+    ///
+    /// Returns the status request to send back to the source of a handshake
+    /// response, until the server sent its status. Datagrams with another
+    /// session ID, malformed responses, and repeated status responses are
+    /// ignored.
+    fn receive(&mut self, datagram: &[u8], source: SocketAddr) -> Option<S::Request> {
+        let (header, payload) = ResponseHeader::parse(datagram)?;
+        if header.session_id != self.session_id {
+            return None;
+        }
+
+        match header.packet_type {
+            PacketType::Handshake => {
+                let token = Token::try_from_payload(payload).ok()?;
+                match self.servers.iter_mut().find(|server| server.addr == source) {
+                    // A server reached twice answers with a new token, invalidating the first one
+                    Some(Responder { stat: Some(_), .. }) => return None,
+                    Some(server) => server.token = token,
+                    None => self.servers.push(Responder {
+                        addr: source,
+                        token,
+                        stat: None,
+                    }),
+                }
+                Some(S::request(self.session_id, token))
+            }
+            PacketType::Stat => {
+                let server = self
+                    .servers
+                    .iter_mut()
+                    .find(|server| server.addr == source && server.stat.is_none())?;
+                server.stat = Some(S::from_payload(payload).ok()?);
+                None
+            }
+        }
+    }
+
+    /// The servers which answered both the handshake and the status request,
+    /// with the token their status was requested with.
+    fn into_servers(self) -> Vec<(SocketAddr, Token, S)> {
+        self.servers
+            .into_iter()
+            .filter_map(|server| Some((server.addr, server.token, server.stat?)))
+            .collect()
+    }
+}
+
+/// Build a UDP socket bound to the announcement port and joined to the
+/// announcement multicast group.
+///
+/// The address is reused, so that several listeners (and the game itself)
+/// can run on the same host.
+fn multicast_socket() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    // On BSD-derived systems, SO_REUSEADDR is not enough to share a multicast port
+    #[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+    socket.set_reuse_port(true)?;
+
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MULTICAST_PORT)).into())?;
+    socket.join_multicast_v4(&MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+
+    Ok(socket.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Send an announcement to the multicast group from another socket.
+    pub(crate) fn announce(payload: &[u8]) {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        socket.set_multicast_loop_v4(true).unwrap();
+        socket
+            .send_to(payload, (MULTICAST_ADDR, MULTICAST_PORT))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_parse_announcement() {
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        let server = LanServer::from_announcement(b"[MOTD]A [World][/MOTD][AD]25565[/AD]", ip);
+        assert_eq!(server.as_ref().map(|s| s.motd.as_str()), Some("A [World]"));
+        assert_eq!(server.map(|s| s.port), Some(25565));
+
+        let server = LanServer::from_announcement(b"[MOTD][/MOTD][AD]192.168.1.2:1234[/AD]", ip);
+        assert_eq!(server.as_ref().map(|s| s.motd.as_str()), Some(""));
+        assert_eq!(server.map(|s| s.port), Some(1234));
+    }
+
+    #[test]
+    fn test_parse_malformed_announcement() {
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        for payload in [
+            &b""[..],
+            b"[MOTD]A World[/MOTD]",
+            b"[MOTD]A World[AD]25565[/AD]",
+            b"[MOTD]A World[/MOTD][AD]25565",
+            b"[MOTD]A World[/MOTD][AD]port[/AD]",
+            b"[MOTD]A World[/MOTD][AD]99999[/AD]",
+            b"\xFF\xFE[AD]",
+        ] {
+            assert_eq!(LanServer::from_announcement(payload, ip), None);
+        }
+    }
+
+    #[test]
+    fn test_announcement_round_trip() {
+        let ip = IpAddr::from(Ipv4Addr::LOCALHOST);
+
+        for motd in [
+            "A World",
+            "[/MOTD][AD]1[/AD]",
+            "[[/AD]/MOTD]",
+            "[MOTD][MOTD]",
+            "§aColored [World]",
+        ] {
+            let announcement = announcement(motd, 25565);
+            let server = LanServer::from_announcement(announcement.as_bytes(), ip).unwrap();
+            assert_eq!(server.port, 25565);
+            assert!(TAGS.iter().all(|tag| !server.motd.contains(tag)));
+        }
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let a = SocketAddr::from(([192, 168, 1, 2], 4445));
+        let b = SocketAddr::from(([192, 168, 1, 3], 4445));
+
+        let mut discovered = Discovered::default();
+        discovered.push(b"[MOTD]First[/MOTD][AD]1000[/AD]", a);
+        discovered.push(b"[MOTD]Again[/MOTD][AD]1000[/AD]", a);
+        discovered.push(b"[MOTD]Second[/MOTD][AD]2000[/AD]", a);
+        discovered.push(b"[MOTD]Other[/MOTD][AD]1000[/AD]", b);
+        discovered.push(b"garbage", b);
+
+        let motds = discovered
+            .0
+            .iter()
+            .map(|s| s.motd.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(motds, ["First", "Second", "Other"]);
+    }
+
+    #[test]
+    fn test_broadcast_responses() {
+        let a = SocketAddr::from(([192, 168, 1, 2], 25565));
+        let b = SocketAddr::from(([192, 168, 1, 3], 25565));
+        let c = SocketAddr::from(([192, 168, 1, 4], 25565));
+        let stat = BasicStat::from(&crate::testing::sample_stat());
+
+        let mut broadcast = Broadcast::<BasicStat>::new();
+        let session_id = broadcast.session_id;
+        let handshake =
+            |token: &[u8]| packets::write_response(PacketType::Handshake, session_id, token);
+        let response = packets::write_response(PacketType::Stat, session_id, &stat.to_payload());
+
+        // A status response before the handshake is ignored
+        assert!(broadcast.receive(&response, a).is_none());
+
+        let request = broadcast.receive(&handshake(b"1234\0"), a).unwrap();
+        assert_eq!(*request, *packets::BasicStat::new(session_id, 1234));
+        // Servers reached twice answer with a new token
+        let request = broadcast.receive(&handshake(b"5678\0"), a).unwrap();
+        assert_eq!(*request, *packets::BasicStat::new(session_id, 5678));
+        // Other session, malformed token, truncated header
+        let other = packets::write_response(PacketType::Handshake, session_id ^ 1, b"1\0");
+        assert!(broadcast.receive(&other, b).is_none());
+        assert!(broadcast.receive(&handshake(b"token\0"), b).is_none());
+        assert!(broadcast.receive(&handshake(b"")[..3], b).is_none());
+
+        // A server which never sends its status is not returned
+        assert!(broadcast.receive(&handshake(b"42\0"), c).is_some());
+
+        assert!(broadcast.receive(&response, a).is_none());
+        assert!(broadcast.receive(&handshake(b"1234\0"), a).is_none());
+        assert!(broadcast.receive(&response, a).is_none());
+        let servers = broadcast.into_servers();
+        assert_eq!(servers, [(a, Token(5678), stat)]);
+    }
+}
@@ -0,0 +1,299 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the LAN world discovery and announcement.
+//!
+//! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
+
+use ::tokio::{
+    net::UdpSocket,
+    runtime::Handle,
+    time::{interval, timeout_at, Instant},
+};
+use std::{io, time::Duration};
+
+use super::*;
+use crate::task::TaskHandle;
+
+/// An asynchronous listener for LAN world announcements, using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct Listener {
+    socket: UdpSocket,
+}
+
+impl Listener {
+    /// Bind a new listener to the announcement port, and join the announcement multicast group.
+    pub fn bind() -> io::Result<Self> {
+        let socket = multicast_socket()?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket: UdpSocket::from_std(socket)?,
+        })
+    }
+
+    /// Collect announcements for the given duration, returning every world discovered.
+    ///
+    /// Worlds are deduplicated by source address and port, and malformed
+    /// announcements are ignored.
+    pub async fn discover(&self, duration: Duration) -> io::Result<Vec<LanServer>> {
+        let deadline = Instant::now() + duration;
+        let mut discovered = Discovered::default();
+        let mut buf = [0; ANNOUNCEMENT_SIZE];
+
+        while let Ok(received) = timeout_at(deadline, self.socket.recv_from(&mut buf)).await {
+            let (received, source) = received?;
+            discovered.push(&buf[..received], source);
+        }
+
+        Ok(discovered.0)
+    }
+}
+
+/// Convenience function to collect LAN world announcements for the given duration.
+pub async fn discover(duration: Duration) -> io::Result<Vec<LanServer>> {
+    Listener::bind()?.discover(duration).await
+}
+
+/// Broadcast a Query handshake to the given port on the local network,
+/// returning the basic status of every server answering within the wait.
+///
+/// Only servers with `enable-query` set answer, and the broadcast does not
+/// cross routers. See [`discover_broadcast_to`] to send directed broadcasts.
+pub async fn discover_broadcast(
+    port: u16,
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, BasicStat)>> {
+    discover_broadcast_to(&[SocketAddr::from((Ipv4Addr::BROADCAST, port))], wait).await
+}
+
+/// Send a Query handshake to each of the given addresses, usually the
+/// directed broadcast addresses of the local interfaces, returning the basic
+/// status of every server answering within the wait.
+///
+/// Status requests are sent to servers as soon as they answer the handshake,
+/// and servers are deduplicated by address. Servers which did not send their
+/// status before the end of the wait are not returned.
+pub async fn discover_broadcast_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, BasicStat)>> {
+    let servers = broadcast(targets, wait).await?;
+    Ok(servers
+        .into_iter()
+        .map(|(addr, _, stat)| (addr, stat))
+        .collect())
+}
+
+/// Broadcast a Query handshake to the given port on the local network,
+/// returning the full status of every server answering within the wait,
+/// with the token it was requested with.
+///
+/// Like [`discover_broadcast`], with full status requests: servers which
+/// answer the handshake but not the full status request are left out.
+pub async fn discover_broadcast_full(
+    port: u16,
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, FullStat)>> {
+    discover_broadcast_full_to(&[SocketAddr::from((Ipv4Addr::BROADCAST, port))], wait).await
+}
+
+/// Send a Query handshake to each of the given addresses, returning the full
+/// status of every server answering within the wait, with the token it was
+/// requested with.
+///
+/// Like [`discover_broadcast_to`], with full status requests.
+pub async fn discover_broadcast_full_to(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, FullStat)>> {
+    broadcast(targets, wait).await
+}
+
+/// Send a Query handshake to each of the given addresses, and request a
+/// status from every server answering within the wait.
+async fn broadcast<S: BroadcastStat>(
+    targets: &[SocketAddr],
+    wait: Duration,
+) -> io::Result<Vec<(SocketAddr, Token, S)>> {
+    let deadline = Instant::now() + wait;
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.set_broadcast(true)?;
+
+    let mut broadcast = Broadcast::<S>::new();
+    for target in targets {
+        socket.send_to(&broadcast.handshake(), target).await?;
+    }
+
+    let mut buf = vec![0; S::RESPONSE_SIZE];
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (received, source) = match received {
+            Ok(received) => received,
+            // ICMP port unreachable errors from targets without a server are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(request) = broadcast.receive(&buf[..received], source) {
+            socket.send_to(request.as_bytes(), source).await?;
+        }
+    }
+
+    Ok(broadcast.into_servers())
+}
+
+/// An asynchronous announcer advertising a world on the local network, using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct Announcer {
+    socket: UdpSocket,
+    announcement: String,
+}
+
+impl Announcer {
+    /// Build a new announcer for the world with the given MoTD and port.
+    ///
+    /// The MoTD is sanitized, see [`announcement`]. Announcements are sent
+    /// with a multicast TTL of 1, so that they do not leave the local network.
+    pub fn new(motd: &str, port: u16) -> io::Result<Self> {
+        Self::new_with_options(motd, port, 1, None)
+    }
+
+    /// Build a new announcer for the world with the given MoTD and port, sending
+    /// announcements with the given multicast TTL and from the given interface,
+    /// identified by its IPv4 address.
+    pub fn new_with_options(
+        motd: &str,
+        port: u16,
+        ttl: u32,
+        interface: Option<Ipv4Addr>,
+    ) -> io::Result<Self> {
+        let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        configure_announcer(SockRef::from(&socket), ttl, interface)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket: UdpSocket::from_std(socket)?,
+            announcement: announcement(motd, port),
+        })
+    }
+
+    /// Send a single announcement.
+    ///
+    /// To be discovered, a world must be announced every [`ANNOUNCE_INTERVAL`].
+    pub async fn announce(&self) -> io::Result<()> {
+        self.socket
+            .send_to(
+                self.announcement.as_bytes(),
+                (MULTICAST_ADDR, MULTICAST_PORT),
+            )
+            .await
+            .map(|_| ())
+    }
+
+    /// Spawn a task sending an announcement every [`ANNOUNCE_INTERVAL`], until
+    /// the returned handle is stopped or dropped.
+    ///
+    /// Errors while sending an announcement are ignored.
+    pub fn spawn(self) -> TaskHandle {
+        self.spawn_on(&Handle::current())
+    }
+
+    /// Like [`spawn`](Self::spawn), but spawn the task on the given runtime.
+    pub fn spawn_on(self, runtime: &Handle) -> TaskHandle {
+        TaskHandle::spawn(runtime, async move {
+            let mut interval = interval(ANNOUNCE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let _ = self.announce().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::tests::announce;
+
+    #[tokio::test]
+    async fn test_discover() {
+        let listener = super::Listener::bind().unwrap();
+
+        let handle = std::thread::spawn(|| {
+            for _ in 0..3 {
+                std::thread::sleep(Duration::from_millis(50));
+                announce(b"[MOTD]Tokio World[/MOTD][AD]40002[/AD]");
+                announce(b"[AD]40002[/AD]");
+            }
+        });
+        let servers = listener.discover(Duration::from_millis(500)).await.unwrap();
+        handle.join().unwrap();
+
+        let servers = servers
+            .into_iter()
+            .filter(|s| s.port == 40002)
+            .collect::<Vec<_>>();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].motd, "Tokio World");
+    }
+
+    #[tokio::test]
+    async fn test_announcer() {
+        let listener = super::Listener::bind().unwrap();
+
+        let announcer = super::Announcer::new("Announced [AD]1[/AD]World", 40004).unwrap();
+        let handle = announcer.spawn();
+        let servers = listener.discover(Duration::from_millis(300)).await.unwrap();
+        handle.stop().await.unwrap();
+
+        let servers = servers
+            .into_iter()
+            .filter(|s| s.port == 40004)
+            .collect::<Vec<_>>();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].motd, "Announced 1World");
+    }
+
+    #[tokio::test]
+    async fn test_discover_broadcast() {
+        let server = crate::testing::MockQueryServer::new().unwrap();
+
+        let servers = super::discover_broadcast_to(
+            &[server.addr(), server.addr()],
+            Duration::from_millis(300),
+        )
+        .await
+        .unwrap();
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].0, server.addr());
+        assert_eq!(servers[0].1.motd, server.full_stat().hostname);
+    }
+
+    #[tokio::test]
+    async fn test_discover_broadcast_full() {
+        let server = crate::testing::MockQueryServer::new().unwrap();
+
+        let servers =
+            super::discover_broadcast_full_to(&[server.addr()], Duration::from_millis(300))
+                .await
+                .unwrap();
+        assert_eq!(servers.len(), 1);
+        let (addr, _token, stat) = &servers[0];
+        assert_eq!((*addr, stat), (server.addr(), &server.full_stat()));
+    }
+
+    #[tokio::test]
+    async fn test_announcer_drop() {
+        let listener = super::Listener::bind().unwrap();
+        let handle = super::Announcer::new("Dropped World", 40005)
+            .unwrap()
+            .spawn();
+        let servers = listener.discover(Duration::from_millis(300)).await.unwrap();
+        assert!(servers.iter().any(|s| s.port == 40005));
+
+        // No announcement is sent after the handle is dropped
+        drop(handle);
+        let servers = listener
+            .discover(super::ANNOUNCE_INTERVAL + Duration::from_millis(200))
+            .await
+            .unwrap();
+        assert!(servers.iter().all(|s| s.port != 40005));
+    }
+}
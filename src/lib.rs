@@ -5,7 +5,7 @@
 //! The [`blocking`] and [`async`](self::tokio) [versions](self::async_std) have
 //! the same API, adding a few `async` and `.await` here and there :
 //!
-//! ```rust
+//! ```rust,no_run
 //! # use minecraft_server_query::*;
 //! # use std::net::Ipv4Addr;
 //! # use std::time::Duration;
@@ -30,29 +30,133 @@
 //! The convenience function [`query`](blocking::query) is also available in each module,
 //! and handles the handshake for you:
 //!
-//! ```rust
+//! ```rust,no_run
 //! # use minecraft_server_query::*;
 //! # let ip_to_query = "lotr.g.akliz.net";
 //! let full_stat = blocking::query(ip_to_query)?;
 //! # Ok::<(), std::io::Error>(())
 //! ```
+//!
+//! For more options in a single expression, use the [`Query`](blocking::Query) builder:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::*;
+//! # use std::time::Duration;
+//! # let ip_to_query = "lotr.g.akliz.net";
+//! let full_stat = blocking::Query::to(ip_to_query)
+//!     .timeout(Duration::from_secs(2))
+//!     .retries(2)
+//!     .full()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
 
+pub mod advertised;
+#[cfg(feature = "arbitrary")]
+mod arbitrary_stats;
 #[cfg(feature = "async-std")]
 #[cfg_attr(doc, doc(cfg(feature = "async-std")))]
 pub mod async_std;
+#[cfg(feature = "bedrock")]
+#[cfg_attr(doc, doc(cfg(feature = "bedrock")))]
+pub mod bedrock;
 pub mod blocking;
+pub mod clock;
+#[cfg(feature = "compat-mcstatus")]
+#[cfg_attr(doc, doc(cfg(feature = "compat-mcstatus")))]
+pub mod compat_mcstatus;
+pub mod conformance;
+pub mod connection_string;
+pub mod csv;
+pub mod diagnostics;
+pub mod diff;
+#[cfg(feature = "embedded")]
+#[cfg_attr(doc, doc(cfg(feature = "embedded")))]
+pub mod embedded;
+#[cfg(feature = "fleet")]
+#[cfg_attr(doc, doc(cfg(feature = "fleet")))]
+pub mod fleet;
+#[cfg(feature = "geoip")]
+#[cfg_attr(doc, doc(cfg(feature = "geoip")))]
+pub mod geoip;
+pub mod gs4;
+#[cfg(feature = "histogram")]
+#[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+pub mod histogram;
+pub mod influx;
+#[cfg(all(feature = "serde", feature = "serde_json"))]
+#[cfg_attr(doc, doc(cfg(all(feature = "serde", feature = "serde_json"))))]
+pub mod jsonl;
+#[cfg(feature = "lan")]
+#[cfg_attr(doc, doc(cfg(feature = "lan")))]
+pub mod lan;
+pub mod monitor;
+pub mod motd;
+#[cfg(feature = "opentelemetry")]
+#[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+pub mod otel;
 pub mod packets;
+pub mod player_filter;
+pub mod player_index;
+#[cfg(feature = "probe")]
+#[cfg_attr(doc, doc(cfg(feature = "probe")))]
+pub mod probe;
+#[cfg(feature = "proxy")]
+#[cfg_attr(doc, doc(cfg(feature = "proxy")))]
+pub mod proxy;
+pub mod quality;
+pub mod rate_limit;
+#[cfg(feature = "rcon")]
+#[cfg_attr(doc, doc(cfg(feature = "rcon")))]
+pub mod rcon;
+#[cfg(feature = "rdns")]
+#[cfg_attr(doc, doc(cfg(feature = "rdns")))]
+pub mod rdns;
+#[cfg(feature = "slp")]
+#[cfg_attr(doc, doc(cfg(feature = "slp")))]
+pub mod report;
+pub mod resolve;
+#[cfg(feature = "responder")]
+#[cfg_attr(doc, doc(cfg(feature = "responder")))]
+pub mod responder;
+pub mod server_properties;
+#[cfg(feature = "slp")]
+#[cfg_attr(doc, doc(cfg(feature = "slp")))]
+pub mod slp;
+#[cfg(feature = "snapshot")]
+#[cfg_attr(doc, doc(cfg(feature = "snapshot")))]
+pub mod snapshot;
+#[cfg(any(
+    feature = "fleet",
+    all(feature = "tokio", any(feature = "lan", feature = "responder"))
+))]
+#[cfg_attr(
+    doc,
+    doc(cfg(any(
+        feature = "fleet",
+        all(feature = "tokio", any(feature = "lan", feature = "responder"))
+    )))
+)]
+pub mod task;
+#[cfg(any(test, feature = "testing"))]
+#[cfg_attr(doc, doc(cfg(feature = "testing")))]
+pub mod testing;
+pub mod token_cache;
 #[cfg(feature = "tokio")]
 #[cfg_attr(doc, doc(cfg(feature = "tokio")))]
 pub mod tokio;
+pub mod zone;
 
 use std::{
+    borrow::Cow,
     io,
-    ops::{Add, Mul},
-    time::Duration,
+    mem::MaybeUninit,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::{Duration, Instant},
 };
 
-use bytes::Buf;
+use bytes::{Buf, BufMut};
+
+use diagnostics::{Collector, Diagnostics, Warning};
 
 #[cfg(feature = "tokio")]
 #[cfg_attr(doc, doc(cfg(feature = "tokio")))]
@@ -66,16 +170,32 @@ pub use blocking::*;
 
 /// Default port for a Minecraft server.
 pub const DEFAULT_PORT: u16 = 25565;
+/// Default port for a Bedrock Edition server, on which Bedrock servers with
+/// query enabled answer queries.
+pub const DEFAULT_BEDROCK_PORT: u16 = 19132;
 /// Default timeout for the UDP sockets in [`QueryClient`](crate::blocking::QueryClient)
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// String type of the [`BasicStat`] and [`FullStat`] fields.
+///
+/// With the `compact_str` feature, this is a `CompactString`, storing strings
+/// of up to 24 bytes inline instead of on the heap.
+#[cfg(not(feature = "compact_str"))]
+pub type StatString = String;
+/// String type of the [`BasicStat`] and [`FullStat`] fields.
+///
+/// With the `compact_str` feature, this is a `CompactString`, storing strings
+/// of up to 24 bytes inline instead of on the heap.
+#[cfg(feature = "compact_str")]
+pub type StatString = compact_str::CompactString;
+
 /// Header size, in bytes
 const RESPONSE_HEADER_SIZE: usize = std::mem::size_of::<u8>() + std::mem::size_of::<u32>();
 
 /// Returns an IO error with error kind set to `Other`
 #[inline]
 fn custom_io_error(msg: &str) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, msg)
+    io::Error::other(msg)
 }
 
 /// Custom IO error for missing data in UDP payload
@@ -84,48 +204,415 @@ fn not_enough_data() -> io::Error {
     custom_io_error("Not enough data in UDP payload.")
 }
 
+/// IO error for a server address which resolved to no socket address
+fn no_address() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "No address found for the server.")
+}
+
+/// Whether an IO error is a socket timeout. Depending on the platform, blocking
+/// sockets return errors of kind `WouldBlock` or `TimedOut`.
+#[inline]
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Error of a Query client, with the operation and the server it failed on.
+///
+/// Clients return it wrapped in an [`io::Error`] of the same kind as the
+/// underlying error, which is its [`source`](std::error::Error::source).
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::{blocking::QueryClient, ClientError};
+/// let client = QueryClient::new("play.example.com")?;
+/// if let Err(e) = client.handshake() {
+///     // "handshake to play.example.com:25565 timed out after 500ms"
+///     println!("{e}");
+///     let context = e.get_ref().and_then(|e| e.downcast_ref::<ClientError>());
+///     assert_eq!(context.map(ClientError::target), Some("play.example.com:25565"));
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ClientError {
+    operation: &'static str,
+    target: String,
+    timeout: Option<Duration>,
+    source: io::Error,
+}
+
+impl ClientError {
+    /// Wrap an error of the given client operation in an IO error of the same kind.
+    fn wrap(
+        source: io::Error,
+        operation: &'static str,
+        target: &str,
+        timeout: Option<Duration>,
+    ) -> io::Error {
+        io::Error::new(
+            source.kind(),
+            Self {
+                operation,
+                target: target.to_string(),
+                timeout,
+                source,
+            },
+        )
+    }
+
+    /// Operation that failed: `"connect"`, `"handshake"`, `"basic_stat"` or `"full_stat"`
+    pub fn operation(&self) -> &str {
+        self.operation
+    }
+
+    /// Address of the server, as `host:port`
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} to {}", self.operation, self.target)?;
+        match self.timeout {
+            Some(timeout) if is_timeout(&self.source) => {
+                write!(f, " timed out after {}ms", timeout.as_millis())
+            }
+            _ if is_timeout(&self.source) => write!(f, " timed out"),
+            _ => write!(f, " failed: {}", self.source),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// A failed attempt of a query, recorded by the retries of the `Query`
+/// builders, such as [`blocking::Query`].
+#[derive(Debug)]
+pub struct AttemptError {
+    /// Address of the server, as `host:port`
+    pub target: String,
+    /// Error of the attempt
+    pub error: io::Error,
+    /// Duration of the attempt, from its handshake
+    pub elapsed: Duration,
+}
+
+impl AttemptError {
+    /// The error of an attempt on the target, started at `start`.
+    fn new(target: &str, error: io::Error, start: Instant) -> Self {
+        Self {
+            target: target.to_string(),
+            error,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+impl std::fmt::Display for AttemptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}ms)", self.error, self.elapsed.as_millis())
+    }
+}
+
+/// Error of a query whose retries were all used up, or which failed with an
+/// error other than a timeout after being retried.
+///
+/// The `Query` builders return it wrapped in an [`io::Error`] of the same kind
+/// as the error of the last attempt, which is its
+/// [`source`](std::error::Error::source). Queries failing on their first
+/// attempt return its error as is.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::{blocking::Query, AttemptsExhausted};
+/// if let Err(e) = Query::to("play.example.com").retries(2).full() {
+///     if let Some(exhausted) = e.get_ref().and_then(|e| e.downcast_ref::<AttemptsExhausted>()) {
+///         for attempt in exhausted.attempts() {
+///             eprintln!("{attempt}");
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AttemptsExhausted {
+    attempts: Vec<AttemptError>,
+}
+
+impl AttemptsExhausted {
+    /// The failed attempts, in order. The last one is the source of the error.
+    pub fn attempts(&self) -> &[AttemptError] {
+        &self.attempts
+    }
+
+    /// Take the failed attempts.
+    pub fn into_attempts(self) -> Vec<AttemptError> {
+        self.attempts
+    }
+}
+
+impl std::fmt::Display for AttemptsExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} attempts failed", self.attempts.len())?;
+        for (i, attempt) in self.attempts.iter().enumerate() {
+            let separator = if i == 0 { ": " } else { "; " };
+            write!(f, "{separator}#{} {attempt}", i + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AttemptsExhausted {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.attempts
+            .last()
+            .map(|attempt| &attempt.error as &(dyn std::error::Error + 'static))
+    }
+}
+
+/// Failed attempts of a query, retried on timeouts
+struct Attempts {
+    retries: u32,
+    failed: Vec<AttemptError>,
+}
+
+impl Attempts {
+    fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            failed: Vec::new(),
+        }
+    }
+
+    /// Record the result of an attempt started at `start`. Returns the result
+    /// of the query, or `None` if the attempt must be retried.
+    fn check<T>(
+        &mut self,
+        target: &str,
+        start: Instant,
+        res: io::Result<T>,
+    ) -> Option<io::Result<T>> {
+        let error = match res {
+            Ok(value) => return Some(Ok(value)),
+            Err(error) => error,
+        };
+        let retry = is_timeout(&error) && self.failed.len() < self.retries as usize;
+        if !retry && self.failed.is_empty() {
+            return Some(Err(error));
+        }
+
+        let kind = error.kind();
+        self.failed.push(AttemptError::new(target, error, start));
+        if retry {
+            return None;
+        }
+        let attempts = std::mem::take(&mut self.failed);
+        Some(Err(io::Error::new(kind, AttemptsExhausted { attempts })))
+    }
+
+    /// Whether the next attempt is the last one, which is not retried.
+    fn is_last(&self) -> bool {
+        self.failed.len() >= self.retries as usize
+    }
+
+    /// The attempts which failed before the query succeeded.
+    fn into_failed(self) -> Vec<AttemptError> {
+        self.failed
+    }
+}
+
+#[cfg(feature = "opentelemetry")]
+use otel::Operation;
+
+/// Recording of a client operation, which does nothing without the
+/// `opentelemetry` feature
+#[cfg(not(feature = "opentelemetry"))]
+struct Operation;
+
+#[cfg(not(feature = "opentelemetry"))]
+impl Operation {
+    fn none() -> Self {
+        Self
+    }
+
+    fn finish<T>(self, _result: &io::Result<T>, _timings: &Timings) {}
+}
+
+/// Format a host and a port as a client target, with brackets around IPv6 addresses.
+fn format_target(ip: &str, port: u16) -> String {
+    if ip.contains(':') {
+        format!("[{ip}]:{port}")
+    } else {
+        format!("{ip}:{port}")
+    }
+}
+
+/// Receive a datagram of at most `max` bytes in a cleared buffer, without
+/// initializing its memory first.
+///
+/// `recv` is given the uninitialized spare capacity of the buffer, and must
+/// return the number of bytes it initialized at its start, like
+/// [`socket2::Socket::recv`] or the filled part of a `tokio` `ReadBuf`. This is
+/// the only place the clients assume received bytes to be initialized.
+fn recv_uninit(
+    buf: &mut Vec<u8>,
+    max: usize,
+    recv: impl FnOnce(&mut [MaybeUninit<u8>]) -> io::Result<usize>,
+) -> io::Result<usize> {
+    buf.clear();
+    buf.reserve(max);
+    let received = recv(&mut buf.spare_capacity_mut()[..max])?;
+    assert!(received <= max, "Received more bytes than the buffer size");
+    // SAFETY: the first `received` bytes of the spare capacity were initialized by `recv`
+    unsafe { buf.set_len(received) };
+    Ok(received)
+}
+
+/// Unspecified local address to bind to, of the same IP version as the remote address.
+fn unspecified_for(addr: &SocketAddr) -> SocketAddr {
+    let ip = match addr {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    };
+    SocketAddr::new(ip, 0)
+}
+
+/// Unspecified address of the IP version of a host, to bind a client socket
+/// to: IPv6 for IPv6 addresses, and IPv4 for other hosts.
+fn unspecified_for_host(host: &str) -> SocketAddr {
+    match zone::split_zone(host) {
+        Some(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        None => (Ipv4Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// Splits an IP address into a host and a port.
+///
+/// IPv6 addresses with a port must be in brackets, like `[::1]:25565`. If no
+/// port is specified in the IP address, the [default port](DEFAULT_PORT) is used.
+fn split_address(ip: &str) -> io::Result<(&str, u16)> {
+    split_address_or(ip, DEFAULT_PORT)
+}
+
+/// Split an address into its host and its port, or the given default port.
+fn split_address_or(ip: &str, default_port: u16) -> io::Result<(&str, u16)> {
+    let invalid_port = || custom_io_error("Invalid port in IP address");
+    if let Some(bracketed) = ip.strip_prefix('[') {
+        return match bracketed.split_once(']') {
+            Some((ip, "")) => Ok((ip, default_port)),
+            Some((ip, port)) => Ok((
+                ip,
+                port.strip_prefix(':')
+                    .and_then(|port| port.parse::<u16>().ok())
+                    .ok_or_else(invalid_port)?,
+            )),
+            None => Err(custom_io_error("Invalid IP address: unclosed bracket.")),
+        };
+    }
+    if zone::split_zone(ip).is_some() {
+        return Ok((ip, default_port));
+    }
+    match ip.split_once(':') {
+        Some((ip, port)) => Ok((ip, port.parse::<u16>().map_err(|_| invalid_port())?)),
+        None => Ok((ip, default_port)),
+    }
+}
+
+/// Checks that a host given with a separate port does not contain a port,
+/// unless it is an IPv6 address.
+fn check_no_port(ip: &str) -> io::Result<()> {
+    if ip.contains(':') && zone::split_zone(ip).is_none() {
+        return Err(custom_io_error(
+            "Invalid IP address: must not contain a port.",
+        ));
+    }
+    Ok(())
+}
+
 /// Converts a slice of raw bytes to a string, interpreting each byte as a
 /// unicode code point
 #[inline]
 fn latin1_to_string(bytes: &[u8]) -> String {
-    bytes.iter().map(|&b| b as char).collect()
-}
-
-/// Parse a decimal number from a slice of bytes. Every byte must be a valid decimal digit.
-fn decimal_from_bytes<T>(bytes: &[u8]) -> io::Result<T>
-where
-    T: Add<T, Output = T> + Mul<T, Output = T> + From<u8>,
-{
-    bytes
-        .iter()
-        .try_fold(T::from(0), |acc, &b| {
-            if b'0' <= b && b <= b'9' {
-                Some(acc * T::from(10) + T::from(b - b'0'))
-            } else {
-                None
-            }
-        })
-        .ok_or_else(|| {
-            custom_io_error("Failed to parse decimal unsigned integer on reading non-digit byte.")
-        })
+    latin1_to_cow(bytes).into_owned()
+}
+
+/// Converts a slice of raw bytes to a string like [`latin1_to_string`],
+/// borrowing the bytes if they are all ASCII.
+#[inline]
+fn latin1_to_cow(bytes: &[u8]) -> Cow<'_, str> {
+    if bytes.is_ascii() {
+        return Cow::Borrowed(std::str::from_utf8(bytes).expect("ASCII is valid UTF-8"));
+    }
+    // Characters above U+007F take two bytes in UTF-8
+    let wide = bytes.iter().filter(|&&b| b >= 0x80).count();
+    let mut res = String::with_capacity(bytes.len() + wide);
+    res.extend(bytes.iter().map(|&b| b as char));
+    Cow::Owned(res)
+}
+
+/// Appends a string to a byte buffer, encoding each character as a single
+/// latin-1 byte. Characters outside of latin-1 are replaced with `?`, and
+/// null characters are skipped so that they cannot end the field early.
+fn put_latin1(buf: &mut Vec<u8>, s: &str) {
+    buf.extend(s.chars().filter(|&c| c != '\0').map(|c| {
+        if (c as u32) <= 0xFF {
+            c as u8
+        } else {
+            b'?'
+        }
+    }));
+}
+
+/// Appends a null-terminated latin-1 field to a byte buffer, see [`put_latin1`].
+fn put_field(buf: &mut Vec<u8>, s: &str) {
+    put_latin1(buf, s);
+    buf.push(b'\0');
+}
+
+/// Parse a decimal number from a slice of bytes. Every byte must be a valid
+/// decimal digit, and the number must fit in a `u32`.
+fn decimal_from_bytes(bytes: &[u8]) -> io::Result<u32> {
+    bytes.iter().try_fold(0u32, |acc, &b| {
+        if !b.is_ascii_digit() {
+            return Err(custom_io_error(
+                "Failed to parse decimal unsigned integer on reading non-digit byte.",
+            ));
+        }
+        acc.checked_mul(10)
+            .and_then(|acc| acc.checked_add((b - b'0') as u32))
+            .ok_or_else(|| custom_io_error("Decimal unsigned integer is too large."))
+    })
+}
+
+/// Parse a decimal number from a slice of bytes, without validation. Numbers
+/// too large for a `u32` wrap around, and non-digit bytes are read as
+/// meaningless digits.
+fn decimal_from_bytes_trusted(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |acc, &b| {
+        acc.wrapping_mul(10)
+            .wrapping_add(u32::from(b.wrapping_sub(b'0')))
+    })
+}
+
+/// Parse a decimal number from a full stat value.
+fn parse_number<T: std::str::FromStr>(value: Cow<'_, str>) -> io::Result<T> {
+    value.parse::<T>().map_err(|_| {
+        custom_io_error("Failed to parse decimal unsigned integer on reading non-digit byte.")
+    })
 }
 
 /// Split a slice of bytes at the first occurence of a subslice.
 ///
-/// The pattern is not contained in the returned slices.
-fn split_at_subslice<'a, T: PartialEq>(
-    slice: &'a [T],
-    pattern: &[T],
-) -> Option<(&'a [T], &'a [T])> {
-    if pattern.len() <= slice.len() {
-        for (i, subslice) in slice.windows(pattern.len()).enumerate() {
-            if subslice == pattern {
-                let (a, b) = slice.split_at(i);
-                return b.get(pattern.len()..).map(|b| (a, b));
-            }
-        }
-    }
-    None
+/// The pattern is not contained in the returned slices. An empty pattern is
+/// found at the start of the slice.
+fn split_at_subslice<'a>(slice: &'a [u8], pattern: &[u8]) -> Option<(&'a [u8], &'a [u8])> {
+    let i = memchr::memmem::find(slice, pattern)?;
+    Some((&slice[..i], &slice[i + pattern.len()..]))
 }
 
 /// Return an iterator on pairs of the iterator in argument. If the iterator
@@ -138,8 +625,7 @@ fn pairs<T, I: Iterator<Item = T>>(iter: I) -> impl Iterator<Item = (T, T)> {
         fn next(&mut self) -> Option<(T, T)> {
             self.0
                 .next()
-                .map(|it1| self.0.next().map(|it2| (it1, it2)))
-                .flatten()
+                .and_then(|it1| self.0.next().map(|it2| (it1, it2)))
         }
     }
 
@@ -148,14 +634,20 @@ fn pairs<T, I: Iterator<Item = T>>(iter: I) -> impl Iterator<Item = (T, T)> {
 
 /// A Query token, returned by a UDP handshake
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Token(pub u32);
 
 impl Token {
-    /// Handshake response max size, in bytes
-    const RESPONSE_SIZE: usize = 16;
+    /// Handshake response max size, in bytes. Vanilla responses take at most
+    /// 16 bytes, but some servers pad the token.
+    const RESPONSE_SIZE: usize = 64;
 
     /// Parse a token from a UDP payload, discarding the terminating null byte.
     ///
+    /// Parsing stops at the first non-digit byte. This never panics: numbers
+    /// too large for a `u32` wrap around.
+    ///
     /// ```rust
     /// # use minecraft_server_query::Token;
     /// assert_eq!(Token::from_payload(&b"123456\0"[..]), Token(123456));
@@ -165,26 +657,312 @@ impl Token {
             payload
                 .iter()
                 .map_while(|&b| {
-                    if b'0' <= b && b <= b'9' {
+                    if b.is_ascii_digit() {
                         Some((b - b'0') as u32)
                     } else {
                         None
                     }
                 })
-                .fold(0, |acc, digit| acc * 10 + digit),
+                .fold(0u32, |acc, digit| acc.wrapping_mul(10).wrapping_add(digit)),
         )
     }
+
+    /// Parse a token from a handshake response payload, up to its null terminator.
+    ///
+    /// Bytes after the terminator are ignored, and ASCII whitespace around the
+    /// digits is skipped. Negative tokens, sent by some GameSpy4 servers, are
+    /// read as the bits of an `i32`. Fails if the terminator is missing, or if
+    /// the token is not a decimal number fitting in 32 bits. This never panics.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::Token;
+    /// assert_eq!(Token::try_from_payload(b"123456\0")?, Token(123456));
+    /// assert_eq!(Token::try_from_payload(b" 123456\0\0\xFF\xFF")?, Token(123456));
+    /// assert_eq!(Token::try_from_payload(b"-1\0")?, Token(u32::MAX));
+    /// assert!(Token::try_from_payload(b"123456").is_err());
+    /// assert!(Token::try_from_payload(b"12a456\0").is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn try_from_payload(payload: &[u8]) -> io::Result<Self> {
+        let end = memchr::memchr(b'\0', payload)
+            .ok_or_else(|| custom_io_error("Missing null terminator in handshake response."))?;
+        let token = payload[..end].trim_ascii();
+        let (negative, digits) = match token.strip_prefix(b"-") {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        if digits.is_empty() {
+            return Err(custom_io_error("Empty token in handshake response."));
+        }
+        let value = decimal_from_bytes(digits)?;
+        match negative {
+            false => Ok(Self(value)),
+            true if value <= i32::MIN.unsigned_abs() => Ok(Self(value.wrapping_neg())),
+            true => Err(custom_io_error("Decimal unsigned integer is too large.")),
+        }
+    }
+
+    /// Encode a token to a UDP payload, as a null-terminated decimal number.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::Token;
+    /// assert_eq!(Token(123456).to_payload(), b"123456\0");
+    /// ```
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut res = self.0.to_string().into_bytes();
+        res.push(b'\0');
+        res
+    }
+}
+
+/// Game type reported by a server in the `gametype` field
+///
+/// Vanilla servers always send `"SMP"`, but servers derived from Classic and
+/// some plugins send `"CMP"` or custom values. Parsing is lossless: unknown
+/// values are kept verbatim, and displayed back as they were received.
+///
+/// ```rust
+/// # use minecraft_server_query::GameType;
+/// assert_eq!("SMP".parse(), Ok(GameType::Smp));
+/// assert_eq!("smp".parse(), Ok(GameType::Other("smp".into())));
+/// assert_eq!(GameType::Other("Skyblock".into()).to_string(), "Skyblock");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "StatString", into = "StatString")
+)]
+pub enum GameType {
+    /// `"SMP"`, survival multiplayer
+    Smp,
+    /// `"CMP"`, creative multiplayer
+    Cmp,
+    /// Any other value, as sent by the server
+    Other(StatString),
+}
+
+impl GameType {
+    /// The `gametype` value of this game type.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Smp => "SMP",
+            Self::Cmp => "CMP",
+            Self::Other(s) => s,
+        }
+    }
+}
+
+impl std::str::FromStr for GameType {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "SMP" => Self::Smp,
+            "CMP" => Self::Cmp,
+            _ => Self::Other(s.into()),
+        })
+    }
+}
+
+impl From<StatString> for GameType {
+    fn from(s: StatString) -> Self {
+        match s.as_str() {
+            "SMP" => Self::Smp,
+            "CMP" => Self::Cmp,
+            _ => Self::Other(s),
+        }
+    }
+}
+
+impl From<GameType> for StatString {
+    fn from(gametype: GameType) -> Self {
+        match gametype {
+            GameType::Other(s) => s,
+            known => known.as_str().into(),
+        }
+    }
+}
+
+impl std::fmt::Display for GameType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Limits on the data kept from a stat payload, to parse responses of
+/// untrusted servers with bounded memory.
+///
+/// The defaults accept any payload received by the clients, which are at most
+/// 1472 bytes long, and bound the memory taken by larger payloads given to
+/// the parsers directly.
+///
+/// ```rust
+/// # use minecraft_server_query::{FullStat, LimitExceeded, Limit, ParseOptions};
+/// let payload = b"...........\
+///     hostname\0A Minecraft Server\0\
+///     gametype\0SMP\0game_id\0MINECRAFT\0\
+///     version\01.7.10\0plugins\0\0map\0world\0\
+///     numplayers\02\0maxplayers\020\0\
+///     hostport\025565\0hostip\0127.0.0.1\
+///     \0\0\x01player_\0\0\
+///     AldanTanneo\0Dinnerbone\0\0";
+///
+/// let options = ParseOptions {
+///     max_players: 1,
+///     ..ParseOptions::default()
+/// };
+/// let err = FullStat::from_payload_with(&payload[..], &options).unwrap_err();
+/// let limit = err.get_ref().and_then(|e| e.downcast_ref::<LimitExceeded>());
+/// assert_eq!(limit.map(LimitExceeded::limit), Some(Limit::Players));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParseOptions {
+    /// Maximum number of player names in a full stat
+    pub max_players: usize,
+    /// Maximum number of key-value pairs in a full stat, including the keys
+    /// which are not kept
+    pub max_kv_pairs: usize,
+    /// Maximum length of a kept string, in bytes of the payload
+    pub max_field_len: usize,
+    /// Maximum total length of the kept strings, in bytes of the payload
+    pub max_retained: usize,
+}
+
+impl ParseOptions {
+    /// No limits, to parse payloads of trusted servers, or encoded by
+    /// `to_payload`, whatever their size.
+    pub const UNLIMITED: Self = Self {
+        max_players: usize::MAX,
+        max_kv_pairs: usize::MAX,
+        max_field_len: usize::MAX,
+        max_retained: usize::MAX,
+    };
+}
+
+impl Default for ParseOptions {
+    /// 1024 players, 64 key-value pairs, fields of 2 KiB and 16 KiB in total.
+    fn default() -> Self {
+        Self {
+            max_players: 1024,
+            max_kv_pairs: 64,
+            max_field_len: 2048,
+            max_retained: 16384,
+        }
+    }
+}
+
+/// A limit of [`ParseOptions`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Limit {
+    /// [`max_players`](ParseOptions::max_players)
+    Players,
+    /// [`max_kv_pairs`](ParseOptions::max_kv_pairs)
+    KvPairs,
+    /// [`max_field_len`](ParseOptions::max_field_len)
+    FieldLen,
+    /// [`max_retained`](ParseOptions::max_retained)
+    Retained,
+}
+
+/// Error of a payload exceeding a limit of its [`ParseOptions`].
+///
+/// Parsers return it wrapped in an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    limit: Limit,
+    max: usize,
+}
+
+impl LimitExceeded {
+    fn error(limit: Limit, max: usize) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, Self { limit, max })
+    }
+
+    /// The limit which was exceeded
+    pub fn limit(&self) -> Limit {
+        self.limit
+    }
+
+    /// Value of the limit
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.limit {
+            Limit::Players => "players",
+            Limit::KvPairs => "key-value pairs",
+            Limit::FieldLen => "bytes in a field",
+            Limit::Retained => "bytes of strings",
+        };
+        write!(f, "Payload has more than {} {what}.", self.max)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Bytes of strings kept from a payload, checked against its [`ParseOptions`]
+struct Retained<'o> {
+    options: &'o ParseOptions,
+    len: usize,
+}
+
+impl<'o> Retained<'o> {
+    fn new(options: &'o ParseOptions) -> Self {
+        Self { options, len: 0 }
+    }
+
+    /// Keep a string of the payload, decoding it as latin-1.
+    fn keep<'a>(&mut self, bytes: &'a [u8]) -> io::Result<Cow<'a, str>> {
+        self.check(bytes)?;
+        Ok(latin1_to_cow(bytes))
+    }
+
+    /// Keep a field of the payload like [`keep`](Self::keep), checking it for
+    /// replacement characters.
+    fn keep_field<'a>(
+        &mut self,
+        collector: &mut Collector<'_, '_>,
+        field: &'static str,
+        bytes: &'a [u8],
+    ) -> io::Result<Cow<'a, str>> {
+        collector.check_encoding(field, bytes);
+        self.keep(bytes)
+    }
+
+    fn check(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if bytes.len() > self.options.max_field_len {
+            return Err(LimitExceeded::error(
+                Limit::FieldLen,
+                self.options.max_field_len,
+            ));
+        }
+        self.len += bytes.len();
+        if self.len > self.options.max_retained {
+            return Err(LimitExceeded::error(
+                Limit::Retained,
+                self.options.max_retained,
+            ));
+        }
+        Ok(())
+    }
 }
 
 /// Basic status information on a minecraft server
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BasicStat {
     /// Server MoTD as displayed in the in-game server browser
-    pub motd: String,
-    /// The server's gametype, hardcoded to `"SMP"`
-    pub gametype: String,
+    pub motd: StatString,
+    /// The server's gametype, usually `"SMP"`, see [`GameType`]
+    pub gametype: StatString,
     /// Name of the default world
-    pub map: String,
+    pub map: StatString,
     /// How many players are currently online
     pub numplayers: u32,
     /// Maximum number of players this server supports
@@ -192,7 +970,7 @@ pub struct BasicStat {
     /// Port the server is listening on
     pub hostport: u16,
     /// IP that the server may receive connections on
-    pub hostip: String,
+    pub hostip: StatString,
 }
 
 impl BasicStat {
@@ -202,6 +980,8 @@ impl BasicStat {
     /// Parse a basic stat struct from a UDP payload. Fails if fields are
     /// missing, returning an IO error for missing data
     ///
+    /// This never panics: any malformed payload returns an error.
+    ///
     /// ```rust
     /// # use minecraft_server_query::BasicStat;
     /// let payload = b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x63127.0.0.1\0";
@@ -209,61 +989,122 @@ impl BasicStat {
     /// assert_eq!(
     ///     BasicStat::from_payload(&payload[..])?,
     ///     BasicStat {
-    ///         motd: "A Minecraft Server".to_string(),
-    ///         gametype: "SMP".to_string(),
-    ///         map: "world".to_string(),
+    ///         motd: "A Minecraft Server".into(),
+    ///         gametype: "SMP".into(),
+    ///         map: "world".into(),
     ///         numplayers: 2,
     ///         maxplayers: 20,
     ///         hostport: 25565,
-    ///         hostip: "127.0.0.1".to_string(),
+    ///         hostip: "127.0.0.1".into(),
     ///     }
     /// );
     /// # Ok::<(), std::io::Error>(())
     /// ```
     pub fn from_payload(payload: &[u8]) -> io::Result<Self> {
-        let mut values = payload.split(|&b| b == b'\0');
+        Self::from_payload_with(payload, &ParseOptions::default())
+    }
 
-        let motd = latin1_to_string(values.next().ok_or_else(not_enough_data)?);
-        let gametype = latin1_to_string(values.next().ok_or_else(not_enough_data)?);
-        let map = latin1_to_string(values.next().ok_or_else(not_enough_data)?);
-        let numplayers = decimal_from_bytes(values.next().ok_or_else(not_enough_data)?)?;
-        let maxplayers = decimal_from_bytes(values.next().ok_or_else(not_enough_data)?)?;
+    /// Parse a basic stat struct from a UDP payload, with the given limits.
+    ///
+    /// Fails with a [`LimitExceeded`] error if the payload exceeds them.
+    pub fn from_payload_with(payload: &[u8], options: &ParseOptions) -> io::Result<Self> {
+        BasicStatRef::from_payload_with(payload, options).map(BasicStatRef::into_owned)
+    }
 
-        let ip = values.next().ok_or_else(not_enough_data)?;
+    /// Parse a basic stat struct from a UDP payload, with the given limits,
+    /// recovering from invalid numbers, see
+    /// [`BasicStatRef::from_payload_with_diagnostics`].
+    pub fn from_payload_with_diagnostics(
+        payload: &[u8],
+        options: &ParseOptions,
+    ) -> io::Result<(Self, Diagnostics)> {
+        BasicStatRef::from_payload_with_diagnostics(payload, options)
+            .map(|(stat, diagnostics)| (stat.into_owned(), diagnostics))
+    }
 
-        let hostport = {
-            let mut buf = ip.get(..2).ok_or_else(not_enough_data)?;
-            buf.get_u16_le()
-        };
-        let hostip = latin1_to_string(ip.get(2..).ok_or_else(not_enough_data)?);
+    /// Parse a basic stat struct from a trusted UDP payload, skipping the
+    /// [limits](ParseOptions) and the validation of the numbers, see
+    /// [`BasicStatRef::from_payload_trusted`].
+    pub fn from_payload_trusted(payload: &[u8]) -> io::Result<Self> {
+        BasicStatRef::from_payload_trusted(payload).map(BasicStatRef::into_owned)
+    }
 
-        Ok(Self {
-            motd,
-            gametype,
-            map,
-            numplayers,
-            maxplayers,
-            hostport,
-            hostip,
-        })
+    /// Encode a basic stat struct to a UDP payload, the inverse of [`from_payload`](Self::from_payload).
+    ///
+    /// Strings are encoded as latin-1: other characters are replaced with `?`,
+    /// and null characters are removed.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::BasicStat;
+    /// let stat = BasicStat {
+    ///     motd: "A Minecraft Server".into(),
+    ///     gametype: "SMP".into(),
+    ///     map: "world".into(),
+    ///     numplayers: 2,
+    ///     maxplayers: 20,
+    ///     hostport: 25565,
+    ///     hostip: "127.0.0.1".into(),
+    /// };
+    ///
+    /// assert_eq!(
+    ///     stat.to_payload(),
+    ///     b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x63127.0.0.1\0",
+    /// );
+    /// assert_eq!(BasicStat::from_payload(&stat.to_payload())?, stat);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(Self::RESPONSE_SIZE);
+        put_field(&mut res, &self.motd);
+        put_field(&mut res, &self.gametype);
+        put_field(&mut res, &self.map);
+        put_field(&mut res, &self.numplayers.to_string());
+        put_field(&mut res, &self.maxplayers.to_string());
+        res.put_u16_le(self.hostport);
+        put_field(&mut res, &self.hostip);
+        res
+    }
+    /// The [`gametype`](Self::gametype) of the server, parsed.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{GameType, BasicStat};
+    /// # let payload = b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x63127.0.0.1\0";
+    /// let stat = BasicStat::from_payload(payload)?;
+    /// assert_eq!(stat.gametype_parsed(), GameType::Smp);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn gametype_parsed(&self) -> GameType {
+        self.gametype.clone().into()
     }
 }
 
-/// Full status information for a minecraft server
+impl From<&FullStat> for BasicStat {
+    /// Extract the basic status from the full status, as servers do.
+    fn from(stat: &FullStat) -> Self {
+        Self {
+            motd: stat.hostname.clone(),
+            gametype: stat.gametype.clone(),
+            map: stat.map.clone(),
+            numplayers: stat.numplayers,
+            maxplayers: stat.maxplayers,
+            hostport: stat.hostport,
+            hostip: stat.hostip.clone(),
+        }
+    }
+}
+
+/// Basic status information on a minecraft server, borrowing its strings from
+/// the payload when possible, see [`BasicStat`].
+///
+/// Strings are only copied if they contain non-ASCII latin-1 characters.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct FullStat {
+pub struct BasicStatRef<'a> {
     /// Server MoTD as displayed in the in-game server browser
-    pub hostname: String,
-    /// Game type, hardcoded to `"SMP"`
-    pub gametype: String,
-    /// Game ID, hardcoded to `"MINECRAFT"`
-    pub game_id: String,
-    /// Game version (`"1.7.10"`, `"1.16.2"`...)
-    pub version: String,
-    /// Server plugins. Format varies with server framework
-    pub plugins: String,
+    pub motd: Cow<'a, str>,
+    /// The server's gametype, usually `"SMP"`, see [`GameType`]
+    pub gametype: Cow<'a, str>,
     /// Name of the default world
-    pub map: String,
+    pub map: Cow<'a, str>,
     /// How many players are currently online
     pub numplayers: u32,
     /// Maximum number of players this server supports
@@ -271,78 +1112,223 @@ pub struct FullStat {
     /// Port the server is listening on
     pub hostport: u16,
     /// IP that the server may receive connections on
-    pub hostip: String,
-    /// Names of the players currently online
-    pub player_list: Vec<String>,
+    pub hostip: Cow<'a, str>,
 }
 
-impl FullStat {
-    /// Full stat response max size, in bytes
-    const RESPONSE_SIZE: usize = 1472;
-    /// Padding at the start of the payload
-    const PADDING_START_SIZE: usize = 11;
-    /// Padding in the middle of the payload, between the KV and players sections
-    const SECTIONS_SEPARATOR: &'static [u8; 12] = b"\0\0\x01player_\0\0";
+impl<'a> BasicStatRef<'a> {
+    /// Parse a basic stat struct from a UDP payload, borrowing from it. Fails
+    /// if fields are missing, returning an IO error for missing data
+    ///
+    /// This never panics: any malformed payload returns an error.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{BasicStat, BasicStatRef};
+    /// # use std::borrow::Cow;
+    /// let payload = b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x63127.0.0.1\0";
+    ///
+    /// let stat = BasicStatRef::from_payload(&payload[..])?;
+    /// assert!(matches!(stat.motd, Cow::Borrowed("A Minecraft Server")));
+    /// assert_eq!(stat.to_owned(), BasicStat::from_payload(&payload[..])?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload(payload: &'a [u8]) -> io::Result<Self> {
+        Self::from_payload_with(payload, &ParseOptions::default())
+    }
 
-    /// Parse the key-value section of the payload. Fails with an IO error on missing keys.
-    fn parse_kv_section(bytes: &[u8]) -> io::Result<Self> {
-        let mut values = pairs(bytes.split(|&b| b == b'\0'))
-            .map(|(key, value)| (latin1_to_string(key), latin1_to_string(value)))
-            .collect::<std::collections::HashMap<_, _>>();
-
-        let hostname = values.remove("hostname").ok_or_else(not_enough_data)?;
-        let gametype = values.remove("gametype").ok_or_else(not_enough_data)?;
-        let game_id = values.remove("game_id").ok_or_else(not_enough_data)?;
-        let version = values.remove("version").ok_or_else(not_enough_data)?;
-        let plugins = values.remove("plugins").ok_or_else(not_enough_data)?;
-        let map = values.remove("map").ok_or_else(not_enough_data)?;
-        let numplayers = values
-            .remove("numplayers")
-            .ok_or_else(not_enough_data)?
-            .parse::<u32>()
-            .map_err(|_| {
-                custom_io_error(
-                    "Failed to parse decimal unsigned integer on reading non-digit byte.",
-                )
-            })?;
-        let maxplayers = values
-            .remove("maxplayers")
-            .ok_or_else(not_enough_data)?
-            .parse::<u32>()
-            .map_err(|_| {
-                custom_io_error(
-                    "Failed to parse decimal unsigned integer on reading non-digit byte.",
-                )
-            })?;
-        let hostport = values
-            .remove("hostport")
-            .ok_or_else(not_enough_data)?
-            .parse::<u16>()
-            .map_err(|_| {
-                custom_io_error(
-                    "Failed to parse decimal unsigned integer on reading non-digit byte.",
-                )
-            })?;
-        let hostip = values.remove("hostip").ok_or_else(not_enough_data)?;
+    /// Parse a basic stat struct from a UDP payload, borrowing from it, with
+    /// the given limits.
+    ///
+    /// Fails with a [`LimitExceeded`] error if the payload exceeds them.
+    pub fn from_payload_with(payload: &'a [u8], options: &ParseOptions) -> io::Result<Self> {
+        Self::parse(payload, options, &mut Collector::disabled())
+    }
+
+    /// Parse a basic stat struct from a UDP payload, borrowing from it, with
+    /// the given limits, and collect the non-fatal problems of the payload.
+    ///
+    /// Invalid numbers are read as zero instead of failing. Fails if fields
+    /// are missing, or with a [`LimitExceeded`] error if the payload exceeds
+    /// the limits.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{diagnostics::Warning, BasicStatRef, ParseOptions};
+    /// // Truncated after the first byte of the IP
+    /// let payload = b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x631";
+    ///
+    /// let (stat, diagnostics) =
+    ///     BasicStatRef::from_payload_with_diagnostics(payload, &ParseOptions::default())?;
+    /// assert_eq!(stat.hostip, "1");
+    /// assert_eq!(diagnostics.warnings(), [Warning::SuspectedTruncation { len: 37 }]);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload_with_diagnostics(
+        payload: &'a [u8],
+        options: &ParseOptions,
+    ) -> io::Result<(Self, Diagnostics)> {
+        let mut diagnostics = Diagnostics::default();
+        let stat = Self::parse(
+            payload,
+            options,
+            &mut Collector::new(payload, &mut diagnostics),
+        )?;
+        Ok((stat, diagnostics))
+    }
+
+    fn parse(
+        payload: &'a [u8],
+        options: &ParseOptions,
+        collector: &mut Collector<'_, '_>,
+    ) -> io::Result<Self> {
+        let mut retained = Retained::new(options);
+        // The port is not null-terminated, and may contain null bytes
+        let mut values = payload.splitn(6, |&b| b == b'\0');
+        let mut next = || values.next().ok_or_else(not_enough_data);
+
+        let motd = retained.keep_field(collector, "motd", next()?)?;
+        let gametype = retained.keep_field(collector, "gametype", next()?)?;
+        let map = retained.keep_field(collector, "map", next()?)?;
+        let numplayers = collector.number("numplayers", Some(next()?), decimal_from_bytes)?;
+        let maxplayers = collector.number("maxplayers", Some(next()?), decimal_from_bytes)?;
+
+        let rest = next()?;
+
+        let hostport = {
+            let mut buf = rest.get(..2).ok_or_else(not_enough_data)?;
+            buf.get_u16_le()
+        };
+        let ip = &rest[2..];
+        let end = memchr::memchr(b'\0', ip).unwrap_or_else(|| {
+            collector.warn(Warning::SuspectedTruncation { len: payload.len() });
+            ip.len()
+        });
+        let hostip = retained.keep_field(collector, "hostip", &ip[..end])?;
 
         Ok(Self {
-            hostname,
+            motd,
             gametype,
-            game_id,
-            version,
-            plugins,
             map,
             numplayers,
             maxplayers,
             hostport,
             hostip,
-            player_list: Vec::new(),
         })
     }
 
+    /// Parse a basic stat struct from a trusted UDP payload, borrowing from it,
+    /// for servers known to answer well-formed responses.
+    ///
+    /// Garbage in, garbage out: the [limits](ParseOptions) are not enforced,
+    /// and numbers are not validated, so invalid digits give meaningless values
+    /// and large numbers wrap around. This still never panics, and fails if
+    /// fields are missing. On well-formed payloads, the result is the same as
+    /// [`from_payload`](Self::from_payload).
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::BasicStatRef;
+    /// let payload = b"A Minecraft Server\0SMP\0world\02\020\0\xDD\x63127.0.0.1\0";
+    /// assert_eq!(
+    ///     BasicStatRef::from_payload_trusted(&payload[..])?,
+    ///     BasicStatRef::from_payload(&payload[..])?,
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload_trusted(payload: &'a [u8]) -> io::Result<Self> {
+        let mut values = payload.splitn(6, |&b| b == b'\0');
+        let mut next = || values.next().ok_or_else(not_enough_data);
+
+        let motd = latin1_to_cow(next()?);
+        let gametype = latin1_to_cow(next()?);
+        let map = latin1_to_cow(next()?);
+        let numplayers = decimal_from_bytes_trusted(next()?);
+        let maxplayers = decimal_from_bytes_trusted(next()?);
+
+        let rest = next()?;
+        let hostport = {
+            let mut buf = rest.get(..2).ok_or_else(not_enough_data)?;
+            buf.get_u16_le()
+        };
+        let ip = &rest[2..];
+        let hostip = latin1_to_cow(&ip[..memchr::memchr(b'\0', ip).unwrap_or(ip.len())]);
+
+        Ok(Self {
+            motd,
+            gametype,
+            map,
+            numplayers,
+            maxplayers,
+            hostport,
+            hostip,
+        })
+    }
+
+    /// Copy the strings into an owned [`BasicStat`].
+    pub fn to_owned(&self) -> BasicStat {
+        self.clone().into_owned()
+    }
+
+    /// Convert into an owned [`BasicStat`], only copying the borrowed strings.
+    pub fn into_owned(self) -> BasicStat {
+        BasicStat {
+            motd: self.motd.into(),
+            gametype: self.gametype.into(),
+            map: self.map.into(),
+            numplayers: self.numplayers,
+            maxplayers: self.maxplayers,
+            hostport: self.hostport,
+            hostip: self.hostip.into(),
+        }
+    }
+}
+
+impl From<BasicStatRef<'_>> for BasicStat {
+    fn from(stat: BasicStatRef<'_>) -> Self {
+        stat.into_owned()
+    }
+}
+
+/// Full status information for a minecraft server
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FullStat {
+    /// Server MoTD as displayed in the in-game server browser
+    pub hostname: StatString,
+    /// Game type, usually `"SMP"`, see [`GameType`]
+    pub gametype: StatString,
+    /// Game ID, hardcoded to `"MINECRAFT"`, and empty for Bedrock servers which don't send it
+    pub game_id: StatString,
+    /// Game version (`"1.7.10"`, `"1.16.2"`...)
+    pub version: StatString,
+    /// Server plugins. Format varies with server framework
+    pub plugins: StatString,
+    /// Name of the default world
+    pub map: StatString,
+    /// How many players are currently online
+    pub numplayers: u32,
+    /// Maximum number of players this server supports
+    pub maxplayers: u32,
+    /// Port the server is listening on
+    pub hostport: u16,
+    /// IP that the server may receive connections on
+    pub hostip: StatString,
+    /// Names of the players currently online
+    pub player_list: Vec<StatString>,
+}
+
+impl FullStat {
+    /// Full stat response max size, in bytes
+    const RESPONSE_SIZE: usize = 1472;
+    /// Padding at the start of the payload
+    const PADDING_START_SIZE: usize = 11;
+    /// Padding sent by vanilla servers at the start of the payload
+    const PADDING_START: &'static [u8; 11] = b"splitnum\0\x80\0";
+    /// Padding in the middle of the payload, between the KV and players sections
+    const SECTIONS_SEPARATOR: &'static [u8; 12] = b"\0\0\x01player_\0\0";
+
     /// Parse a full stat struct from a UDP payload. Fails if fields are
     /// missing, returning an IO error for missing data
     ///
+    /// This never panics: any malformed payload returns an error.
+    ///
     /// ```rust
     /// # use minecraft_server_query::FullStat;
     /// let payload = b"...........\
@@ -357,44 +1343,1150 @@ impl FullStat {
     /// assert_eq!(
     ///     FullStat::from_payload(&payload[..])?,
     ///     FullStat {
-    ///         hostname: "A Minecraft Server".to_string(),
-    ///         gametype: "SMP".to_string(),
-    ///         game_id: "MINECRAFT".to_string(),
-    ///         version: "1.7.10".to_string(),
-    ///         plugins: "".to_string(),
-    ///         map: "world".to_string(),
+    ///         hostname: "A Minecraft Server".into(),
+    ///         gametype: "SMP".into(),
+    ///         game_id: "MINECRAFT".into(),
+    ///         version: "1.7.10".into(),
+    ///         plugins: "".into(),
+    ///         map: "world".into(),
     ///         numplayers: 2,
     ///         maxplayers: 20,
     ///         hostport: 25565,
-    ///         hostip: "127.0.0.1".to_string(),
+    ///         hostip: "127.0.0.1".into(),
     ///         player_list: vec![
-    ///             "AldanTanneo".to_string(),
-    ///             "Dinnerbone".to_string(),
+    ///             "AldanTanneo".into(),
+    ///             "Dinnerbone".into(),
     ///         ],
     ///     }
     /// );
     /// # Ok::<(), std::io::Error>(())
     /// ```
     pub fn from_payload(payload: &[u8]) -> io::Result<Self> {
+        Self::from_payload_with(payload, &ParseOptions::default())
+    }
+
+    /// Parse a full stat struct from a UDP payload, with the given limits.
+    ///
+    /// Fails with a [`LimitExceeded`] error if the payload exceeds them.
+    pub fn from_payload_with(payload: &[u8], options: &ParseOptions) -> io::Result<Self> {
+        FullStatRef::from_payload_with(payload, options).map(FullStatRef::into_owned)
+    }
+
+    /// Parse a full stat struct from a UDP payload, with the given limits,
+    /// recovering from malformed payloads, see
+    /// [`FullStatRef::from_payload_with_diagnostics`].
+    pub fn from_payload_with_diagnostics(
+        payload: &[u8],
+        options: &ParseOptions,
+    ) -> io::Result<(Self, Diagnostics)> {
+        FullStatRef::from_payload_with_diagnostics(payload, options)
+            .map(|(stat, diagnostics)| (stat.into_owned(), diagnostics))
+    }
+
+    /// Parse a full stat struct from a trusted UDP payload, skipping the
+    /// [limits](ParseOptions) and the validation of the numbers, see
+    /// [`FullStatRef::from_payload_trusted`].
+    pub fn from_payload_trusted(payload: &[u8]) -> io::Result<Self> {
+        FullStatRef::from_payload_trusted(payload).map(FullStatRef::into_owned)
+    }
+
+    /// Encode a full stat struct to a UDP payload, the inverse of [`from_payload`](Self::from_payload).
+    ///
+    /// Keys are written in the same order, and with the same padding, as vanilla servers.
+    /// Strings are encoded as latin-1: other characters are replaced with `?`,
+    /// and null characters are removed. Empty player names are skipped.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::FullStat;
+    /// let stat = FullStat {
+    ///     hostname: "A Minecraft Server".into(),
+    ///     gametype: "SMP".into(),
+    ///     game_id: "MINECRAFT".into(),
+    ///     version: "1.7.10".into(),
+    ///     plugins: "".into(),
+    ///     map: "world".into(),
+    ///     numplayers: 2,
+    ///     maxplayers: 20,
+    ///     hostport: 25565,
+    ///     hostip: "127.0.0.1".into(),
+    ///     player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+    /// };
+    ///
+    /// assert_eq!(FullStat::from_payload(&stat.to_payload())?, stat);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn to_payload(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(Self::RESPONSE_SIZE);
+        res.extend_from_slice(Self::PADDING_START);
+
+        for (key, value) in [
+            ("hostname", self.hostname.as_str()),
+            ("gametype", &self.gametype),
+            ("game_id", &self.game_id),
+            ("version", &self.version),
+            ("plugins", &self.plugins),
+            ("map", &self.map),
+            ("numplayers", &self.numplayers.to_string()),
+            ("maxplayers", &self.maxplayers.to_string()),
+            ("hostport", &self.hostport.to_string()),
+            ("hostip", &self.hostip),
+        ] {
+            put_field(&mut res, key);
+            put_field(&mut res, value);
+        }
+        // The last value terminator is part of the sections separator
+        res.pop();
+        res.extend_from_slice(Self::SECTIONS_SEPARATOR);
+
+        for player in self.player_list.iter().filter(|p| !p.is_empty()) {
+            put_field(&mut res, player);
+        }
+        res.push(b'\0');
+
+        res
+    }
+
+    /// The [`gametype`](Self::gametype) of the server, parsed.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{FullStat, GameType};
+    /// # let stat = FullStat {
+    /// #     hostname: "A Minecraft Server".into(),
+    /// #     gametype: "CMP".into(),
+    /// #     game_id: "MINECRAFT".into(),
+    /// #     version: "1.7.10".into(),
+    /// #     plugins: "".into(),
+    /// #     map: "world".into(),
+    /// #     numplayers: 0,
+    /// #     maxplayers: 20,
+    /// #     hostport: 25565,
+    /// #     hostip: "127.0.0.1".into(),
+    /// #     player_list: vec![],
+    /// # };
+    /// assert_eq!(stat.gametype_parsed(), GameType::Cmp);
+    /// ```
+    pub fn gametype_parsed(&self) -> GameType {
+        self.gametype.clone().into()
+    }
+}
+
+/// Full status information for a minecraft server, borrowing its strings from
+/// the payload when possible, see [`FullStat`].
+///
+/// Strings are only copied if they contain non-ASCII latin-1 characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullStatRef<'a> {
+    /// Server MoTD as displayed in the in-game server browser
+    pub hostname: Cow<'a, str>,
+    /// Game type, usually `"SMP"`, see [`GameType`]
+    pub gametype: Cow<'a, str>,
+    /// Game ID, hardcoded to `"MINECRAFT"`, and empty for Bedrock servers which don't send it
+    pub game_id: Cow<'a, str>,
+    /// Game version (`"1.7.10"`, `"1.16.2"`...)
+    pub version: Cow<'a, str>,
+    /// Server plugins. Format varies with server framework
+    pub plugins: Cow<'a, str>,
+    /// Name of the default world
+    pub map: Cow<'a, str>,
+    /// How many players are currently online
+    pub numplayers: u32,
+    /// Maximum number of players this server supports
+    pub maxplayers: u32,
+    /// Port the server is listening on
+    pub hostport: u16,
+    /// IP that the server may receive connections on
+    pub hostip: Cow<'a, str>,
+    /// Names of the players currently online
+    pub player_list: Vec<Cow<'a, str>>,
+}
+
+impl<'a> FullStatRef<'a> {
+    /// Keys of a full stat, in the order they are sent by vanilla servers
+    const KEYS: [&'static str; 10] = [
+        "hostname",
+        "gametype",
+        "game_id",
+        "version",
+        "plugins",
+        "map",
+        "numplayers",
+        "maxplayers",
+        "hostport",
+        "hostip",
+    ];
+
+    /// Parse the key-value section of the payload. Fails with an IO error on
+    /// missing keys, or if the section exceeds the limits.
+    fn parse_kv_section(
+        bytes: &'a [u8],
+        retained: &mut Retained<'_>,
+        collector: &mut Collector<'_, '_>,
+    ) -> io::Result<Self> {
+        let mut strings = bytes.split(|&b| b == b'\0');
+        let mut dangling = None;
+        let pairs = std::iter::from_fn(|| {
+            let key = strings.next()?;
+            // An odd number of strings ends with a key without a value
+            let value = strings.next();
+            if value.is_none() {
+                dangling = Some(key);
+            }
+            Some((key, value?))
+        });
+        let values = Self::key_values(pairs, Some(retained))?;
+        if let Some(key) = dangling {
+            collector.warn(Warning::DanglingKey {
+                offset: collector.offset(key),
+            });
+        }
+        Self::from_values(values, latin1_to_cow, collector)
+    }
+
+    /// Extract the Minecraft keys from the key-value pairs of a full stat,
+    /// without the player list. Fails with an IO error on missing keys, except
+    /// `game_id` which Bedrock servers don't send.
+    ///
+    /// If a key appears more than once, the last value is kept. Values are only
+    /// decoded for the Minecraft keys, other pairs are skipped.
+    fn from_pairs<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        pairs: impl IntoIterator<Item = (K, V)>,
+        decode: impl Fn(V) -> Cow<'a, str>,
+    ) -> io::Result<Self> {
+        let values = Self::key_values(pairs, None)?;
+        Self::from_values(values, decode, &mut Collector::disabled())
+    }
+
+    /// The values of the Minecraft keys in the key-value pairs, in the order
+    /// of [`KEYS`](Self::KEYS), keeping the last value of repeated keys.
+    ///
+    /// The pairs are checked against the limits, if any, in the same pass.
+    fn key_values<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        pairs: impl IntoIterator<Item = (K, V)>,
+        mut retained: Option<&mut Retained<'_>>,
+    ) -> io::Result<[Option<V>; 10]> {
+        let mut values: [Option<V>; 10] = Default::default();
+        for (i, (key, value)) in pairs.into_iter().enumerate() {
+            let field = Self::KEYS.iter().position(|k| k.as_bytes() == key.as_ref());
+            if let Some(retained) = retained.as_deref_mut() {
+                let max_kv_pairs = retained.options.max_kv_pairs;
+                if i >= max_kv_pairs {
+                    return Err(LimitExceeded::error(Limit::KvPairs, max_kv_pairs));
+                }
+                if field.is_some() {
+                    retained.check(value.as_ref())?;
+                }
+            }
+            if let Some(field) = field {
+                values[field] = Some(value);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Build a full stat from the values of the Minecraft keys, defaulting the
+    /// missing or invalid numbers to zero if the collector is enabled.
+    fn from_values<V: AsRef<[u8]>>(
+        values: [Option<V>; 10],
+        decode: impl Fn(V) -> Cow<'a, str>,
+        collector: &mut Collector<'_, '_>,
+    ) -> io::Result<Self> {
+        for (field, value) in Self::KEYS.iter().zip(&values) {
+            if let Some(value) = value {
+                collector.check_encoding(field, value.as_ref());
+            }
+        }
+
+        let [hostname, gametype, game_id, version, plugins, map, numplayers, maxplayers, hostport, hostip] =
+            values;
+        let string = |value: Option<V>| value.map(&decode).ok_or_else(not_enough_data);
+
+        Ok(Self {
+            hostname: string(hostname)?,
+            gametype: string(gametype)?,
+            // Bedrock servers don't send it
+            game_id: game_id.map(&decode).unwrap_or_default(),
+            version: string(version)?,
+            plugins: string(plugins)?,
+            map: string(map)?,
+            numplayers: collector.number("numplayers", numplayers, |v| parse_number(decode(v)))?,
+            maxplayers: collector.number("maxplayers", maxplayers, |v| parse_number(decode(v)))?,
+            hostport: collector.number("hostport", hostport, |v| parse_number(decode(v)))?,
+            hostip: string(hostip)?,
+            player_list: Vec::new(),
+        })
+    }
+
+    /// Parse a full stat struct from a UDP payload, borrowing from it. Fails if
+    /// fields are missing, returning an IO error for missing data
+    ///
+    /// This never panics: any malformed payload returns an error.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{FullStat, FullStatRef};
+    /// # use std::borrow::Cow;
+    /// let payload = b"...........\
+    ///     hostname\0A Minecraft Server\0\
+    ///     gametype\0SMP\0game_id\0MINECRAFT\0\
+    ///     version\01.7.10\0plugins\0\0map\0world\0\
+    ///     numplayers\02\0maxplayers\020\0\
+    ///     hostport\025565\0hostip\0127.0.0.1\
+    ///     \0\0\x01player_\0\0\
+    ///     AldanTanneo\0J\xF6rg\0\0";
+    ///
+    /// let stat = FullStatRef::from_payload(&payload[..])?;
+    /// assert!(matches!(stat.player_list[0], Cow::Borrowed("AldanTanneo")));
+    /// // Non-ASCII latin-1 characters are decoded in an owned string
+    /// assert!(matches!(&stat.player_list[1], Cow::Owned(name) if name == "Jörg"));
+    /// assert_eq!(stat.to_owned(), FullStat::from_payload(&payload[..])?);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload(payload: &'a [u8]) -> io::Result<Self> {
+        Self::from_payload_with(payload, &ParseOptions::default())
+    }
+
+    /// Parse a full stat struct from a UDP payload, borrowing from it, with
+    /// the given limits.
+    ///
+    /// Fails with a [`LimitExceeded`] error if the payload exceeds them.
+    pub fn from_payload_with(payload: &'a [u8], options: &ParseOptions) -> io::Result<Self> {
+        Self::parse(payload, options, &mut Collector::disabled())
+    }
+
+    /// Parse a full stat struct from a UDP payload, borrowing from it, with
+    /// the given limits, and collect the non-fatal problems of the payload.
+    ///
+    /// Missing or invalid numbers are read as zero, keys without a value are
+    /// ignored, and the player list is looked for after its `player_` marker if
+    /// the separator before it is incomplete, instead of failing. Fails if
+    /// string keys are missing, or with a [`LimitExceeded`] error if the
+    /// payload exceeds the limits.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{diagnostics::Warning, FullStatRef, ParseOptions};
+    /// let payload = b"...........\
+    ///     hostname\0A Minecraft Server\0\
+    ///     gametype\0SMP\0game_id\0MINECRAFT\0\
+    ///     version\01.7.10\0plugins\0\0map\0world\0\
+    ///     numplayers\02\0maxplayers\0twenty\0\
+    ///     hostport\025565\0hostip\0127.0.0.1\
+    ///     \0\x01player_\0\0\
+    ///     AldanTanneo\0Dinnerbone\0\0";
+    /// assert!(FullStatRef::from_payload(payload).is_err());
+    ///
+    /// let (stat, diagnostics) =
+    ///     FullStatRef::from_payload_with_diagnostics(payload, &ParseOptions::default())?;
+    /// assert_eq!(stat.maxplayers, 0);
+    /// assert_eq!(stat.player_list, ["AldanTanneo", "Dinnerbone"]);
+    /// assert_eq!(
+    ///     diagnostics.warnings(),
+    ///     [
+    ///         Warning::SeparatorFallback { offset: 177 },
+    ///         Warning::DefaultedNumber { field: "maxplayers", offset: Some(128) },
+    ///     ]
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload_with_diagnostics(
+        payload: &'a [u8],
+        options: &ParseOptions,
+    ) -> io::Result<(Self, Diagnostics)> {
+        let mut diagnostics = Diagnostics::default();
+        let stat = Self::parse(
+            payload,
+            options,
+            &mut Collector::new(payload, &mut diagnostics),
+        )?;
+        Ok((stat, diagnostics))
+    }
+
+    fn parse(
+        payload: &'a [u8],
+        options: &ParseOptions,
+        collector: &mut Collector<'_, '_>,
+    ) -> io::Result<Self> {
+        let sections = payload
+            .get(FullStat::PADDING_START_SIZE..)
+            .ok_or_else(not_enough_data)?;
+        let (kv_section, players_section) =
+            match split_at_subslice(sections, FullStat::SECTIONS_SEPARATOR.as_slice()) {
+                Some(sections) => sections,
+                None if collector.enabled() => Self::split_sections_fallback(sections, collector),
+                None => {
+                    return Err(custom_io_error(
+                        "Failed to parse full stat payload due to missing data.",
+                    ))
+                }
+            };
+
+        let mut retained = Retained::new(options);
+        let mut res = Self::parse_kv_section(kv_section, &mut retained, collector)?;
+
+        for name in players_section.split(|&b| b == b'\0') {
+            if name.is_empty() {
+                continue;
+            }
+            if res.player_list.len() == options.max_players {
+                return Err(LimitExceeded::error(Limit::Players, options.max_players));
+            }
+            res.player_list
+                .push(retained.keep_field(collector, "player_list", name)?);
+        }
+        // The player list is terminated by an empty name
+        if !players_section.ends_with(b"\0") {
+            collector.warn(Warning::SuspectedTruncation { len: payload.len() });
+        }
+
+        Ok(res)
+    }
+
+    /// Split the sections of a payload whose separator is incomplete, after the
+    /// `player_` marker, or before an empty player list if it is missing.
+    fn split_sections_fallback(
+        sections: &'a [u8],
+        collector: &mut Collector<'_, '_>,
+    ) -> (&'a [u8], &'a [u8]) {
+        let (kv_section, players_section) = match split_at_subslice(sections, b"\x01player_\0") {
+            Some((kv_section, players)) => {
+                (kv_section, players.strip_prefix(b"\0").unwrap_or(players))
+            }
+            None => (sections, &sections[sections.len()..]),
+        };
+        collector.warn(Warning::SeparatorFallback {
+            offset: collector.offset(players_section),
+        });
+        // Remove the terminators of the last value and of the section
+        let kv_section = kv_section
+            .strip_suffix(b"\0\0")
+            .or_else(|| kv_section.strip_suffix(b"\0"))
+            .unwrap_or(kv_section);
+        (kv_section, players_section)
+    }
+
+    /// Parse a full stat struct from a trusted UDP payload, borrowing from it,
+    /// for servers known to answer well-formed responses.
+    ///
+    /// Garbage in, garbage out: the [limits](ParseOptions) are not enforced,
+    /// and numbers are not validated, so invalid digits give meaningless values
+    /// and large numbers wrap around. This still never panics, and fails if
+    /// the sections separator or keys are missing. On well-formed payloads, the
+    /// result is the same as [`from_payload`](Self::from_payload).
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::FullStatRef;
+    /// let payload = b"...........\
+    ///     hostname\0A Minecraft Server\0\
+    ///     gametype\0SMP\0game_id\0MINECRAFT\0\
+    ///     version\01.7.10\0plugins\0\0map\0world\0\
+    ///     numplayers\02\0maxplayers\020\0\
+    ///     hostport\025565\0hostip\0127.0.0.1\
+    ///     \0\0\x01player_\0\0\
+    ///     AldanTanneo\0Dinnerbone\0\0";
+    ///
+    /// assert_eq!(
+    ///     FullStatRef::from_payload_trusted(&payload[..])?,
+    ///     FullStatRef::from_payload(&payload[..])?,
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_payload_trusted(payload: &'a [u8]) -> io::Result<Self> {
         let (kv_section, players_section) = split_at_subslice(
             payload
-                .get(Self::PADDING_START_SIZE..)
+                .get(FullStat::PADDING_START_SIZE..)
                 .ok_or_else(not_enough_data)?,
-            Self::SECTIONS_SEPARATOR.as_slice(),
+            FullStat::SECTIONS_SEPARATOR.as_slice(),
         )
         .ok_or_else(|| custom_io_error("Failed to parse full stat payload due to missing data."))?;
 
-        let mut res = Self::parse_kv_section(kv_section)?;
+        let mut values: [Option<&[u8]>; 10] = [None; 10];
+        for (key, value) in pairs(kv_section.split(|&b| b == b'\0')) {
+            if let Some(i) = Self::KEYS.iter().position(|k| k.as_bytes() == key) {
+                values[i] = Some(value);
+            }
+        }
+        let [hostname, gametype, game_id, version, plugins, map, numplayers, maxplayers, hostport, hostip] =
+            values;
+        let required = |value: Option<&'a [u8]>| value.ok_or_else(not_enough_data);
+
+        Ok(Self {
+            hostname: latin1_to_cow(required(hostname)?),
+            gametype: latin1_to_cow(required(gametype)?),
+            // Bedrock servers don't send it
+            game_id: game_id.map_or_else(Cow::default, latin1_to_cow),
+            version: latin1_to_cow(required(version)?),
+            plugins: latin1_to_cow(required(plugins)?),
+            map: latin1_to_cow(required(map)?),
+            numplayers: decimal_from_bytes_trusted(required(numplayers)?),
+            maxplayers: decimal_from_bytes_trusted(required(maxplayers)?),
+            hostport: decimal_from_bytes_trusted(required(hostport)?) as u16,
+            hostip: latin1_to_cow(required(hostip)?),
+            player_list: players_section
+                .split(|&b| b == b'\0')
+                .filter(|name| !name.is_empty())
+                .map(latin1_to_cow)
+                .collect(),
+        })
+    }
+
+    /// Copy the strings into an owned [`FullStat`].
+    pub fn to_owned(&self) -> FullStat {
+        self.clone().into_owned()
+    }
+
+    /// Convert into an owned [`FullStat`], only copying the borrowed strings.
+    pub fn into_owned(self) -> FullStat {
+        FullStat {
+            hostname: self.hostname.into(),
+            gametype: self.gametype.into(),
+            game_id: self.game_id.into(),
+            version: self.version.into(),
+            plugins: self.plugins.into(),
+            map: self.map.into(),
+            numplayers: self.numplayers,
+            maxplayers: self.maxplayers,
+            hostport: self.hostport,
+            hostip: self.hostip.into(),
+            player_list: self.player_list.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<FullStatRef<'_>> for FullStat {
+    fn from(stat: FullStatRef<'_>) -> Self {
+        stat.into_owned()
+    }
+}
+
+/// Status of a server, with the level of detail it answered with.
+///
+/// Returned by [`query_lenient`](blocking::query_lenient), for servers which
+/// ignore full status requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AnyStat {
+    /// The server answered the full status request
+    Full(FullStat),
+    /// The server only answered the basic status request, or the full status
+    /// request timed out and the query [fell back](blocking::Query::full_or_basic)
+    /// to a basic status
+    Basic(BasicStat),
+}
+
+impl AnyStat {
+    /// Whether the server answered the full status request.
+    pub fn is_full(&self) -> bool {
+        matches!(self, Self::Full(_))
+    }
+
+    /// The basic status, which is always available.
+    pub fn basic(&self) -> BasicStat {
+        match self {
+            Self::Full(stat) => BasicStat::from(stat),
+            Self::Basic(stat) => stat.clone(),
+        }
+    }
+}
+
+/// Durations of the phases of a query, measured with a monotonic clock.
+///
+/// Returned by the `_timed` methods of [`Query`](blocking::Query), and by
+/// [`query_timed`](blocking::query_timed). Retried requests are recorded
+/// individually, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timings {
+    /// Resolution of the server address
+    pub resolve: Duration,
+    /// Binding and connecting the client socket
+    pub connect: Duration,
+    /// Every handshake attempt
+    pub handshakes: Vec<Duration>,
+    /// Every status request attempt
+    pub stats: Vec<Duration>,
+}
+
+impl Timings {
+    /// Total duration of the query.
+    pub fn total(&self) -> Duration {
+        self.resolve
+            + self.connect
+            + self.handshakes.iter().sum::<Duration>()
+            + self.stats.iter().sum::<Duration>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use super::*;
 
-        res.player_list
-            .extend(players_section.split(|&b| b == b'\0').filter_map(|name| {
-                if !name.is_empty() {
-                    Some(latin1_to_string(name))
-                } else {
-                    None
+    /// Reference implementation of [`split_at_subslice`], scanning every window.
+    fn split_at_subslice_naive<'a>(
+        slice: &'a [u8],
+        pattern: &[u8],
+    ) -> Option<(&'a [u8], &'a [u8])> {
+        if pattern.len() <= slice.len() {
+            for (i, subslice) in slice.windows(pattern.len()).enumerate() {
+                if subslice == pattern {
+                    let (a, b) = slice.split_at(i);
+                    return b.get(pattern.len()..).map(|b| (a, b));
                 }
-            }));
+            }
+        }
+        None
+    }
 
-        Ok(res)
+    #[test]
+    fn test_game_type() {
+        for (s, gametype) in [
+            ("SMP", GameType::Smp),
+            ("CMP", GameType::Cmp),
+            ("smp", GameType::Other("smp".into())),
+            ("", GameType::Other("".into())),
+            (
+                " SMP\t§aSkyéblock ",
+                GameType::Other(" SMP\t§aSkyéblock ".into()),
+            ),
+        ] {
+            assert_eq!(s.parse::<GameType>(), Ok(gametype.clone()));
+            assert_eq!(GameType::from(StatString::from(s)), gametype);
+            assert_eq!(gametype.to_string(), s);
+            assert_eq!(StatString::from(gametype), s);
+        }
+
+        let mut stat =
+            BasicStat::from_payload(b"Motd\0CMP\0world\x000\x0020\0\xDD\x63127.0.0.1\0").unwrap();
+        assert_eq!(stat.gametype_parsed(), GameType::Cmp);
+        stat.gametype = "Factions".into();
+        assert_eq!(stat.gametype_parsed(), GameType::Other("Factions".into()));
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_game_type_serde() {
+        for gametype in [GameType::Smp, GameType::Other("Custom \"CMP\"".into())] {
+            let json = serde_json::to_string(&gametype).unwrap();
+            assert_eq!(json, serde_json::to_string(gametype.as_str()).unwrap());
+            assert_eq!(serde_json::from_str::<GameType>(&json).unwrap(), gametype);
+        }
+    }
+
+    #[test]
+    fn test_split_address() {
+        for (ip, split) in [
+            ("play.example.com", ("play.example.com", DEFAULT_PORT)),
+            ("127.0.0.1:25566", ("127.0.0.1", 25566)),
+            ("::1", ("::1", DEFAULT_PORT)),
+            ("fe80::1%eth0", ("fe80::1%eth0", DEFAULT_PORT)),
+            ("[::1]", ("::1", DEFAULT_PORT)),
+            ("[fe80::1%eth0]:25566", ("fe80::1%eth0", 25566)),
+        ] {
+            assert_eq!(split_address(ip).unwrap(), split, "{ip}");
+        }
+        for ip in ["host:port", "[::1", "[::1]25566", "[::1]:"] {
+            assert!(split_address(ip).is_err(), "{ip}");
+        }
+        assert_eq!(
+            split_address_or("[::1]", DEFAULT_BEDROCK_PORT).unwrap(),
+            ("::1", DEFAULT_BEDROCK_PORT)
+        );
+        assert_eq!(
+            split_address_or("127.0.0.1:19133", DEFAULT_BEDROCK_PORT).unwrap(),
+            ("127.0.0.1", 19133)
+        );
+
+        assert!(check_no_port("fe80::1%eth0").is_ok());
+        assert!(check_no_port("127.0.0.1:25565").is_err());
+    }
+
+    #[test]
+    fn test_split_at_subslice() {
+        assert_eq!(
+            split_at_subslice(b"a\0\0b\0\0c", b"\0\0"),
+            Some((&b"a"[..], &b"b\0\0c"[..]))
+        );
+        assert_eq!(
+            split_at_subslice(b"abc", b"abc"),
+            Some((&b""[..], &b""[..]))
+        );
+        assert_eq!(split_at_subslice(b"ab", b"abc"), None);
+        assert_eq!(
+            split_at_subslice(b"abc", b""),
+            Some((&b""[..], &b"abc"[..]))
+        );
+
+        // Compare with the naive implementation on random inputs, over a small
+        // alphabet so that partial and complete matches are frequent
+        let mut state = 0x2545F4914F6CDD1Du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..10_000 {
+            let slice: Vec<u8> = (0..next() % 64).map(|_| (next() % 3) as u8).collect();
+            let pattern: Vec<u8> = (1..=1 + next() % 6).map(|_| (next() % 3) as u8).collect();
+            assert_eq!(
+                split_at_subslice(&slice, &pattern),
+                split_at_subslice_naive(&slice, &pattern),
+                "{slice:?} {pattern:?}"
+            );
+        }
+    }
+
+    /// Parse a key-value section with the default limits.
+    fn parse_kv(bytes: &[u8]) -> io::Result<FullStatRef<'_>> {
+        FullStatRef::parse_kv_section(
+            bytes,
+            &mut Retained::new(&ParseOptions::default()),
+            &mut Collector::disabled(),
+        )
+    }
+
+    /// Reference implementation of the key-value section parsing, collecting
+    /// every pair in a map before extracting the Minecraft keys.
+    fn parse_kv_section_with_map(bytes: &[u8]) -> io::Result<FullStat> {
+        let mut values: HashMap<String, String> = pairs(bytes.split(|&b| b == b'\0'))
+            .map(|(key, value)| (latin1_to_string(key), latin1_to_string(value)))
+            .collect();
+        let mut take = |key: &str| values.remove(key).ok_or_else(not_enough_data);
+        let hostname = take("hostname")?;
+        let gametype = take("gametype")?;
+        let game_id = take("game_id").unwrap_or_default();
+        let version = take("version")?;
+        let plugins = take("plugins")?;
+        let map = take("map")?;
+        let numplayers = parse_number(take("numplayers")?.into())?;
+        let maxplayers = parse_number(take("maxplayers")?.into())?;
+        let hostport = parse_number(take("hostport")?.into())?;
+        let hostip = take("hostip")?;
+        Ok(FullStat {
+            hostname: hostname.as_str().into(),
+            gametype: gametype.as_str().into(),
+            game_id: game_id.as_str().into(),
+            version: version.as_str().into(),
+            plugins: plugins.as_str().into(),
+            map: map.as_str().into(),
+            numplayers,
+            maxplayers,
+            hostport,
+            hostip: hostip.as_str().into(),
+            player_list: Vec::new(),
+        })
+    }
+
+    /// Reference implementation of [`latin1_to_string`], widening every byte.
+    fn latin1_to_string_naive(bytes: &[u8]) -> String {
+        bytes.iter().map(|&b| b as char).collect()
+    }
+
+    #[test]
+    fn test_latin1() {
+        let all: Vec<u8> = (0..=255).collect();
+        for byte in 0..=255u8 {
+            for bytes in [&[byte][..], &[b'a', byte, b'z'], &[byte; 33]] {
+                let expected = latin1_to_string_naive(bytes);
+                let cow = latin1_to_cow(bytes);
+                assert_eq!(cow, expected);
+                assert_eq!(matches!(cow, Cow::Borrowed(_)), byte < 0x80);
+                assert_eq!(latin1_to_string(bytes), expected);
+            }
+        }
+
+        for window in all.windows(37) {
+            let s = latin1_to_string(window);
+            assert_eq!(s, latin1_to_string_naive(window));
+            assert_eq!(s.len(), s.capacity());
+        }
+        assert_eq!(latin1_to_cow(b""), Cow::Borrowed(""));
+    }
+
+    #[test]
+    fn test_recv_uninit() {
+        let mut buf = Vec::new();
+        let received = recv_uninit(&mut buf, 16, |spare| {
+            assert_eq!(spare.len(), 16);
+            for (dst, &src) in spare.iter_mut().zip(b"datagram") {
+                dst.write(src);
+            }
+            Ok(8)
+        })
+        .unwrap();
+        assert_eq!(received, 8);
+        assert_eq!(buf, b"datagram");
+
+        // The previous content is cleared, and the allocation reused
+        let capacity = buf.capacity();
+        assert_eq!(recv_uninit(&mut buf, 16, |_| Ok(0)).unwrap(), 0);
+        assert!(buf.is_empty());
+        assert_eq!(buf.capacity(), capacity);
+
+        buf.extend_from_slice(b"previous");
+        let err = recv_uninit(&mut buf, 16, |_| Err(io::ErrorKind::TimedOut.into())).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_kv_section_equivalence() {
+        let vanilla: &[u8] = b"hostname\0A Minecraft Server\0gametype\0SMP\0game_id\0MINECRAFT\0\
+            version\x001.7.10\0plugins\0\0map\0world\0numplayers\x002\0maxplayers\x0020\0\
+            hostport\x0025565\0hostip\x00127.0.0.1";
+        let mut duplicated = vanilla.to_vec();
+        duplicated.extend_from_slice(b"\0hostname\0Caf\xe9\0numplayers\x003");
+        let mut unknown = b"extra\0value\0".to_vec();
+        unknown.extend_from_slice(vanilla);
+        unknown.extend_from_slice(b"\0odd");
+
+        for section in [vanilla, &duplicated, &unknown] {
+            let stat = parse_kv(section).unwrap().into_owned();
+            assert_eq!(stat, parse_kv_section_with_map(section).unwrap());
+        }
+        let stat = parse_kv(&duplicated).unwrap();
+        assert_eq!(stat.hostname, "Café");
+        assert_eq!(stat.numplayers, 3);
+
+        let missing = &vanilla[..vanilla.len() - 17];
+        let invalid = [vanilla, b"\0maxplayers\0twenty"].concat();
+        let empty_number = [vanilla, b"\0hostport\0"].concat();
+        for section in [missing, &invalid, &empty_number, b"", b"\0"] {
+            let err = parse_kv(section).unwrap_err();
+            let expected = parse_kv_section_with_map(section).unwrap_err();
+            assert_eq!(err.kind(), expected.kind());
+            assert_eq!(err.to_string(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_trusted_equivalence() {
+        let stat = crate::testing::sample_stat();
+        let mut latin1 = stat.clone();
+        latin1.hostname = "§aCafé §lÜber".into();
+        latin1.player_list = vec!["Jörg".into(), "Ñandú".into()];
+        let mut no_players = stat.clone();
+        no_players.player_list.clear();
+        no_players.plugins = "CraftBukkit on Bukkit 1.20: WorldEdit 7.2; Essentials".into();
+
+        for stat in [stat, latin1, no_players] {
+            let payload = stat.to_payload();
+            let trusted = FullStatRef::from_payload_trusted(&payload).unwrap();
+            assert_eq!(trusted, FullStatRef::from_payload(&payload).unwrap());
+            assert_eq!(FullStat::from_payload_trusted(&payload).unwrap(), stat);
+
+            let basic = BasicStat::from(&stat);
+            let payload = basic.to_payload();
+            let trusted = BasicStatRef::from_payload_trusted(&payload).unwrap();
+            assert_eq!(trusted, BasicStatRef::from_payload(&payload).unwrap());
+            assert_eq!(BasicStat::from_payload_trusted(&payload).unwrap(), basic);
+        }
+
+        // Duplicated and unknown keys, and padded player lists
+        let payload = b"splitnum\0\x80\0extra\0value\0hostname\0A\0gametype\0SMP\0\
+            game_id\0MINECRAFT\0version\x001.7.10\0plugins\0\0map\0world\0numplayers\x002\0\
+            maxplayers\x0020\0hostport\x0025565\0hostip\x00127.0.0.1\0hostname\0Caf\xe9\
+            \0\0\x01player_\0\0\0Alice\0\0Bob\0\0";
+        let trusted = FullStatRef::from_payload_trusted(payload).unwrap();
+        assert_eq!(trusted, FullStatRef::from_payload(payload).unwrap());
+        assert_eq!(trusted.hostname, "Café");
+        assert_eq!(trusted.player_list, ["Alice", "Bob"]);
+    }
+
+    #[test]
+    fn test_trusted_garbage() {
+        // Limits are not enforced
+        let mut stat = crate::testing::sample_stat();
+        stat.player_list = vec!["x".repeat(3000).as_str().into(); 2000];
+        let payload = stat.to_payload();
+        assert!(FullStat::from_payload(&payload).is_err());
+        assert_eq!(FullStat::from_payload_trusted(&payload).unwrap(), stat);
+
+        // Invalid numbers give meaningless values instead of errors
+        let payload = b"motd\0SMP\0world\099999999999999999999\0twenty\0\xDD\x63\0";
+        assert!(BasicStat::from_payload(payload).is_err());
+        let stat = BasicStat::from_payload_trusted(payload).unwrap();
+        assert_eq!(stat.numplayers, 1661992959);
+        assert_eq!(stat.hostport, 25565);
+
+        // Missing structure is still an error
+        for payload in [
+            &b""[..],
+            b"\0",
+            b"splitnum\0\x80\0",
+            b"splitnum\0\x80\0\0\0\x01player_\0\0",
+            b"splitnum\0\x80\0numplayers\0\0\0\x01player_\0\0",
+        ] {
+            assert!(FullStat::from_payload_trusted(payload).is_err());
+        }
+        assert!(BasicStat::from_payload_trusted(b"motd\0SMP\0world\x002\x0020\0").is_err());
+    }
+
+    /// Crashers and edge cases found by the fuzz targets in `fuzz/`
+    #[test]
+    fn test_parser_regressions() {
+        // Overflowing tokens wrap around instead of panicking, or are rejected
+        assert!(Token::try_from_payload(b"4294967296\0").is_err());
+        assert_eq!(
+            Token::try_from_payload(b"-2147483648\0").unwrap(),
+            Token(1 << 31)
+        );
+        assert!(Token::try_from_payload(b"-2147483649\0").is_err());
+        assert!(Token::try_from_payload(b"-\0").is_err());
+        assert_eq!(Token::from_payload(b"4294967296\0"), Token(0));
+        assert_eq!(
+            Token::from_payload(b"99999999999999999999"),
+            Token(1661992959)
+        );
+
+        let basic = |numplayers: &[u8], rest: &[u8]| {
+            BasicStat::from_payload(
+                &[b"motd\0SMP\0world\0", numplayers, b"\x0020\0", rest].concat(),
+            )
+        };
+        assert_eq!(
+            basic(b"4294967295", b"\xDD\x63\0").unwrap().numplayers,
+            u32::MAX
+        );
+        assert!(basic(b"4294967296", b"\xDD\x63\0").is_err());
+        assert!(basic(b"99999999999999999999", b"\xDD\x63\0").is_err());
+        // Host ports containing null bytes, or missing
+        assert_eq!(basic(b"2", b"\0\x64127.0.0.1\0").unwrap().hostport, 25600);
+        assert_eq!(basic(b"2", b"\0\0").unwrap().hostport, 0);
+        assert!(basic(b"2", b"\0").is_err());
+        assert!(basic(b"2", b"").is_err());
+
+        for payload in [
+            &b""[..],
+            b"\0",
+            b"splitnum\0\x80",
+            b"splitnum\0\x80\0",
+            b"splitnum\0\x80\0\0\0\x01player_\0\0",
+            b"splitnum\0\x80\0numplayers\0\0\0\x01player_\0\0",
+        ] {
+            // Empty numbers are read as zero in basic stats
+            let _ = BasicStat::from_payload(payload);
+            assert!(FullStat::from_payload(payload).is_err());
+        }
+    }
+
+    /// Limit exceeded by the parsing of a payload
+    fn exceeded<T: std::fmt::Debug>(res: io::Result<T>) -> Limit {
+        let err = res.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.get_ref()
+            .and_then(|e| e.downcast_ref::<LimitExceeded>())
+            .map(LimitExceeded::limit)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let stat = crate::testing::sample_stat();
+        let long = "x".repeat(3000);
+        let options = ParseOptions::default();
+
+        // Tens of thousands of players, long or empty
+        let crowded = FullStat {
+            player_list: vec!["a".into(); 60_000],
+            ..stat.clone()
+        }
+        .to_payload();
+        assert_eq!(exceeded(FullStat::from_payload(&crowded)), Limit::Players);
+        assert_eq!(
+            exceeded(FullStatRef::from_payload(&crowded)),
+            Limit::Players
+        );
+        let parsed = FullStat::from_payload_with(
+            &crowded,
+            &ParseOptions {
+                max_players: 60_000,
+                max_retained: 1 << 20,
+                ..options
+            },
+        )
+        .unwrap();
+        assert_eq!(parsed.player_list.len(), 60_000);
+
+        let mut separators = stat.to_payload();
+        separators.extend_from_slice(&[0; 60_000]);
+        let parsed = FullStatRef::from_payload(&separators).unwrap();
+        // Empty names are skipped without being allocated
+        assert_eq!(parsed.player_list.len(), stat.player_list.len());
+        assert!(parsed.player_list.capacity() <= 4);
+
+        let long_names = FullStat {
+            player_list: vec![long[..1000].into(); 20],
+            ..stat.clone()
+        }
+        .to_payload();
+        assert_eq!(
+            exceeded(FullStat::from_payload(&long_names)),
+            Limit::Retained
+        );
+        let long_name = FullStat {
+            player_list: vec![long.as_str().into()],
+            ..stat.clone()
+        }
+        .to_payload();
+        assert_eq!(
+            exceeded(FullStatRef::from_payload(&long_name)),
+            Limit::FieldLen
+        );
+
+        // Key-value sections
+        let long_hostname = FullStat {
+            hostname: long.as_str().into(),
+            ..stat.clone()
+        }
+        .to_payload();
+        assert_eq!(
+            exceeded(FullStat::from_payload(&long_hostname)),
+            Limit::FieldLen
+        );
+        let pairs = [
+            &FullStat::PADDING_START[..],
+            &b"\0".repeat(60_000),
+            &stat.to_payload()[FullStat::PADDING_START_SIZE..],
+        ]
+        .concat();
+        assert_eq!(exceeded(FullStatRef::from_payload(&pairs)), Limit::KvPairs);
+        // Values of unknown keys are not kept
+        let unknown = [
+            &FullStat::PADDING_START[..],
+            b"extra\0",
+            long.as_bytes(),
+            b"\0",
+            &stat.to_payload()[FullStat::PADDING_START_SIZE..],
+        ]
+        .concat();
+        assert_eq!(FullStat::from_payload(&unknown).unwrap(), stat);
+
+        let long_motd = BasicStat {
+            motd: long.as_str().into(),
+            ..BasicStat::from(&stat)
+        }
+        .to_payload();
+        assert_eq!(
+            exceeded(BasicStat::from_payload(&long_motd)),
+            Limit::FieldLen
+        );
+        assert_eq!(
+            exceeded(BasicStatRef::from_payload(&long_motd)),
+            Limit::FieldLen
+        );
+        let options = ParseOptions {
+            max_field_len: long.len(),
+            max_retained: long.len(),
+            ..options
+        };
+        assert_eq!(
+            exceeded(BasicStat::from_payload_with(&long_motd, &options)),
+            Limit::Retained
+        );
+    }
+
+    /// Shrink failing cases down to short strings and small numbers
+    fn proptest_config() -> ProptestConfig {
+        ProptestConfig {
+            max_shrink_iters: 4096,
+            ..ProptestConfig::default()
+        }
+    }
+
+    /// Latin-1 strings without null characters, which are field terminators
+    fn latin1_string(max_len: usize) -> impl Strategy<Value = StatString> {
+        proptest::string::string_regex(&format!("[\\x01-\\xFF]{{0,{max_len}}}"))
+            .unwrap()
+            .prop_map(StatString::from)
+    }
+
+    fn basic_stat() -> impl Strategy<Value = BasicStat> {
+        (
+            latin1_string(64),
+            latin1_string(8),
+            latin1_string(16),
+            any::<u32>(),
+            any::<u32>(),
+            any::<u16>(),
+            latin1_string(16),
+        )
+            .prop_map(
+                |(motd, gametype, map, numplayers, maxplayers, hostport, hostip)| BasicStat {
+                    motd,
+                    gametype,
+                    map,
+                    numplayers,
+                    maxplayers,
+                    hostport,
+                    hostip,
+                },
+            )
+    }
+
+    fn full_stat() -> impl Strategy<Value = FullStat> {
+        (
+            basic_stat(),
+            latin1_string(16),
+            latin1_string(16),
+            latin1_string(64),
+            // Empty player names are skipped by the encoder
+            proptest::collection::vec(
+                latin1_string(16).prop_filter("empty player name", |p| !p.is_empty()),
+                0..32,
+            ),
+        )
+            .prop_map(|(basic, game_id, version, plugins, player_list)| FullStat {
+                hostname: basic.motd,
+                gametype: basic.gametype,
+                game_id,
+                version,
+                plugins,
+                map: basic.map,
+                numplayers: basic.numplayers,
+                maxplayers: basic.maxplayers,
+                hostport: basic.hostport,
+                hostip: basic.hostip,
+                player_list,
+            })
+    }
+
+    proptest! {
+        #![proptest_config(proptest_config())]
+
+        #[test]
+        fn prop_token_round_trip(token in any::<u32>()) {
+            prop_assert_eq!(Token::from_payload(&Token(token).to_payload()), Token(token));
+            prop_assert_eq!(Token::try_from_payload(&Token(token).to_payload())?, Token(token));
+        }
+
+        #[test]
+        fn prop_basic_stat_round_trip(stat in basic_stat()) {
+            prop_assert_eq!(BasicStat::from_payload(&stat.to_payload())?, stat);
+        }
+
+        #[test]
+        fn prop_full_stat_round_trip(stat in full_stat()) {
+            prop_assert_eq!(FullStat::from_payload(&stat.to_payload())?, stat);
+        }
+
+        #[test]
+        fn prop_parsers_never_panic(payload in proptest::collection::vec(any::<u8>(), 0..256)) {
+            Token::from_payload(&payload);
+            let _ = Token::try_from_payload(&payload);
+            let _ = BasicStat::from_payload(&payload);
+            let _ = FullStat::from_payload(&payload);
+            let _ = BasicStat::from_payload_trusted(&payload);
+            let _ = FullStat::from_payload_trusted(&payload);
+        }
+
+        #[test]
+        fn prop_trusted_equivalence(stat in full_stat()) {
+            let payload = stat.to_payload();
+            prop_assert_eq!(FullStatRef::from_payload_trusted(&payload)?, FullStatRef::from_payload(&payload)?);
+            let payload = BasicStat::from(&stat).to_payload();
+            prop_assert_eq!(BasicStatRef::from_payload_trusted(&payload)?, BasicStatRef::from_payload(&payload)?);
+        }
     }
 }
@@ -0,0 +1,200 @@
+//! Online detection of a server polled periodically.
+//!
+//! A single dropped UDP packet should not mark a server as offline. The
+//! [`StateTracker`] is fed the result of every poll, and only changes state
+//! after a number of consecutive failures or successes:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking, monitor::{ServerState, StateTracker}};
+//! # use std::{thread::sleep, time::{Duration, Instant}};
+//! let mut tracker = StateTracker::new(3, 2);
+//! loop {
+//!     let result = blocking::query("play.example.com");
+//!     if let Some(transition) = tracker.record(result.is_ok(), Instant::now()) {
+//!         if transition.to == ServerState::Down {
+//!             println!("play.example.com is down");
+//!         }
+//!     }
+//!     sleep(Duration::from_secs(10));
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+/// State of a polled server
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ServerState {
+    /// Not enough polls were recorded yet
+    Unknown,
+    /// The server answers
+    Up,
+    /// The server does not answer
+    Down,
+}
+
+/// A change of state of a server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Transition {
+    /// State before the transition
+    pub from: ServerState,
+    /// State after the transition
+    pub to: ServerState,
+    /// Time of the poll which crossed the threshold
+    pub at: Instant,
+}
+
+/// Tracks the state of a server from poll results, damping flaps.
+///
+/// The server goes down after `failures_before_down` consecutive failures, and
+/// up after `successes_before_up` consecutive successes. It starts in the
+/// [`Unknown`](ServerState::Unknown) state, leaving it when either threshold
+/// is first crossed.
+#[derive(Debug, Clone)]
+pub struct StateTracker {
+    failures_before_down: u32,
+    successes_before_up: u32,
+    state: ServerState,
+    failures: u32,
+    successes: u32,
+    last_transition: Option<Instant>,
+}
+
+impl StateTracker {
+    /// Create a tracker with the given thresholds. Thresholds of zero are
+    /// treated as one, changing state on the first poll.
+    pub fn new(failures_before_down: u32, successes_before_up: u32) -> Self {
+        Self {
+            failures_before_down: failures_before_down.max(1),
+            successes_before_up: successes_before_up.max(1),
+            state: ServerState::Unknown,
+            failures: 0,
+            successes: 0,
+            last_transition: None,
+        }
+    }
+
+    /// Record the result of a poll made at the given time, returning the
+    /// transition it caused, if any.
+    pub fn record(&mut self, success: bool, now: Instant) -> Option<Transition> {
+        let to = if success {
+            self.failures = 0;
+            self.successes = self.successes.saturating_add(1);
+            (self.successes >= self.successes_before_up).then_some(ServerState::Up)
+        } else {
+            self.successes = 0;
+            self.failures = self.failures.saturating_add(1);
+            (self.failures >= self.failures_before_down).then_some(ServerState::Down)
+        };
+
+        let to = to.filter(|&to| to != self.state)?;
+        let transition = Transition {
+            from: self.state,
+            to,
+            at: now,
+        };
+        self.state = to;
+        self.last_transition = Some(now);
+        Some(transition)
+    }
+
+    /// Current state of the server.
+    pub fn state(&self) -> ServerState {
+        self.state
+    }
+
+    /// Number of consecutive failed polls, up to now.
+    pub fn failure_streak(&self) -> u32 {
+        self.failures
+    }
+
+    /// Number of consecutive successful polls, up to now.
+    pub fn success_streak(&self) -> u32 {
+        self.successes
+    }
+
+    /// Time of the last transition, `None` while the state is unknown.
+    pub fn last_transition(&self) -> Option<Instant> {
+        self.last_transition
+    }
+
+    /// How long the server has been in its current state, at the given time.
+    /// `None` while the state is unknown.
+    pub fn time_in_state(&self, now: Instant) -> Option<Duration> {
+        self.last_transition
+            .map(|at| now.saturating_duration_since(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a sequence of `S` and `F` polls, one second apart, and return the
+    /// index and target state of every transition.
+    fn run(tracker: &mut StateTracker, start: Instant, polls: &str) -> Vec<(usize, ServerState)> {
+        polls
+            .chars()
+            .enumerate()
+            .filter_map(|(i, poll)| {
+                let now = start + Duration::from_secs(i as u64);
+                let transition = tracker.record(poll == 'S', now)?;
+                assert_eq!(transition.at, now);
+                assert_eq!(tracker.last_transition(), Some(now));
+                Some((i, transition.to))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let start = Instant::now();
+        let mut tracker = StateTracker::new(3, 2);
+        assert_eq!(
+            run(&mut tracker, start, "FSFFFSS"),
+            [(4, ServerState::Down), (6, ServerState::Up)]
+        );
+        assert_eq!(tracker.success_streak(), 2);
+        assert_eq!(tracker.failure_streak(), 0);
+
+        // Single failures and successes do not flap
+        let mut tracker = StateTracker::new(3, 2);
+        assert_eq!(
+            run(&mut tracker, start, "SSFSFFSFFFSFS"),
+            [(1, ServerState::Up), (9, ServerState::Down)]
+        );
+        assert_eq!(tracker.state(), ServerState::Down);
+        assert_eq!(tracker.success_streak(), 1);
+        assert_eq!(tracker.failure_streak(), 0);
+        assert_eq!(
+            tracker.time_in_state(start + Duration::from_secs(300)),
+            Some(Duration::from_secs(291))
+        );
+    }
+
+    #[test]
+    fn test_first_transition() {
+        let start = Instant::now();
+        let mut tracker = StateTracker::new(2, 3);
+        assert_eq!(run(&mut tracker, start, "SSF"), []);
+        assert_eq!(tracker.state(), ServerState::Unknown);
+        assert_eq!(tracker.last_transition(), None);
+        assert_eq!(tracker.time_in_state(start), None);
+        assert_eq!(tracker.failure_streak(), 1);
+
+        let transition = tracker.record(false, start).unwrap();
+        assert_eq!(transition.from, ServerState::Unknown);
+        assert_eq!(transition.to, ServerState::Down);
+
+        // Zero thresholds change state on every poll
+        let mut tracker = StateTracker::new(0, 0);
+        assert_eq!(
+            run(&mut tracker, start, "SSFS"),
+            [
+                (0, ServerState::Up),
+                (2, ServerState::Down),
+                (3, ServerState::Up)
+            ]
+        );
+    }
+}
@@ -0,0 +1,729 @@
+//! Helpers for message of the day strings.
+//!
+//! MOTDs sent by servers may contain legacy formatting codes: a section sign
+//! `§` followed by a single character selecting a color or a style.
+//!
+//! [`parse_codes`] splits such a MOTD into [`Span`]s of text sharing the same
+//! [`Style`], and [`to_legacy`] converts spans back. Chat components sent in
+//! the Server List Ping are converted to the same representation, and
+//! [`to_minimessage`] writes spans in the MiniMessage format of Adventure.
+
+/// The character starting a formatting code
+pub const SECTION_SIGN: char = '§';
+
+/// A text color: one of the 16 named colors, or an RGB color
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Color {
+    /// `§0`
+    Black,
+    /// `§1`
+    DarkBlue,
+    /// `§2`
+    DarkGreen,
+    /// `§3`
+    DarkAqua,
+    /// `§4`
+    DarkRed,
+    /// `§5`
+    DarkPurple,
+    /// `§6`
+    Gold,
+    /// `§7`
+    Gray,
+    /// `§8`
+    DarkGray,
+    /// `§9`
+    Blue,
+    /// `§a`
+    Green,
+    /// `§b`
+    Aqua,
+    /// `§c`
+    Red,
+    /// `§d`
+    LightPurple,
+    /// `§e`
+    Yellow,
+    /// `§f`
+    White,
+    /// An RGB color, from chat components or `§x§r§r§g§g§b§b` codes
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Named colors, in the order of their formatting codes, with their RGB
+    /// value in the vanilla client
+    const NAMED: [(Self, char, &'static str, u32); 16] = [
+        (Self::Black, '0', "black", 0x000000),
+        (Self::DarkBlue, '1', "dark_blue", 0x0000AA),
+        (Self::DarkGreen, '2', "dark_green", 0x00AA00),
+        (Self::DarkAqua, '3', "dark_aqua", 0x00AAAA),
+        (Self::DarkRed, '4', "dark_red", 0xAA0000),
+        (Self::DarkPurple, '5', "dark_purple", 0xAA00AA),
+        (Self::Gold, '6', "gold", 0xFFAA00),
+        (Self::Gray, '7', "gray", 0xAAAAAA),
+        (Self::DarkGray, '8', "dark_gray", 0x555555),
+        (Self::Blue, '9', "blue", 0x5555FF),
+        (Self::Green, 'a', "green", 0x55FF55),
+        (Self::Aqua, 'b', "aqua", 0x55FFFF),
+        (Self::Red, 'c', "red", 0xFF5555),
+        (Self::LightPurple, 'd', "light_purple", 0xFF55FF),
+        (Self::Yellow, 'e', "yellow", 0xFFFF55),
+        (Self::White, 'f', "white", 0xFFFFFF),
+    ];
+
+    /// Color selected by a legacy formatting code character, case insensitive.
+    pub fn from_code(code: char) -> Option<Self> {
+        let code = code.to_ascii_lowercase();
+        Self::NAMED
+            .iter()
+            .find(|(_, c, _, _)| *c == code)
+            .map(|(color, _, _, _)| *color)
+    }
+
+    /// Legacy formatting code character of a named color, or `None` for RGB colors.
+    pub fn code(self) -> Option<char> {
+        Self::NAMED
+            .iter()
+            .find(|(color, _, _, _)| *color == self)
+            .map(|(_, c, _, _)| *c)
+    }
+
+    /// RGB value of the color. Named colors have their value in the vanilla client.
+    pub fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Self::Rgb(r, g, b) => (r, g, b),
+            named => {
+                let (_, _, _, rgb) = Self::NAMED
+                    .iter()
+                    .find(|(color, _, _, _)| *color == named)
+                    .expect("every color but RGB is named");
+                ((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8)
+            }
+        }
+    }
+
+    /// The named color closest to this one, for clients without RGB colors.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::motd::Color;
+    /// assert_eq!(Color::Rgb(250, 160, 10).to_named(), Color::Gold);
+    /// assert_eq!(Color::Blue.to_named(), Color::Blue);
+    /// ```
+    pub fn to_named(self) -> Self {
+        let (r, g, b) = self.to_rgb();
+        let distance = |rgb: u32| {
+            let d = |a: u8, b: u32| (a as i32 - (b & 0xFF) as i32).pow(2);
+            d(r, rgb >> 16) + d(g, rgb >> 8) + d(b, rgb)
+        };
+        Self::NAMED
+            .iter()
+            .min_by_key(|(_, _, _, rgb)| distance(*rgb))
+            .map(|(color, _, _, _)| *color)
+            .expect("there are named colors")
+    }
+
+    /// Parse the color of a chat component: a name like `gold`, or a hex color
+    /// like `#aabbcc`.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::motd::Color;
+    /// assert_eq!(Color::from_name("gold"), Some(Color::Gold));
+    /// assert_eq!(Color::from_name("#FF8000"), Some(Color::Rgb(255, 128, 0)));
+    /// assert_eq!(Color::from_name("#FF80"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(hex) = name.strip_prefix('#') {
+            if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return None;
+            }
+            let rgb = u32::from_str_radix(hex, 16).ok()?;
+            return Some(Self::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+        }
+        Self::NAMED
+            .iter()
+            .find(|(_, _, n, _)| *n == name)
+            .map(|(color, _, _, _)| *color)
+    }
+}
+
+/// Color and formatting of a span of text
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Style {
+    /// Color of the text, or `None` for the default color
+    pub color: Option<Color>,
+    /// `§l`
+    pub bold: bool,
+    /// `§o`
+    pub italic: bool,
+    /// `§n`
+    pub underlined: bool,
+    /// `§m`
+    pub strikethrough: bool,
+    /// `§k`, with randomly changing characters
+    pub obfuscated: bool,
+}
+
+/// A span of text with a single style
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Span {
+    /// Text of the span, without formatting codes
+    pub text: String,
+    /// Style of the whole span
+    pub style: Style,
+}
+
+/// Concatenate the text of spans, without styles.
+pub fn spans_to_plain(spans: &[Span]) -> String {
+    spans.iter().map(|span| span.text.as_str()).collect()
+}
+
+/// Split a MOTD with legacy formatting codes into styled spans.
+///
+/// A color code resets the formatting, and `§r` resets both the color and the
+/// formatting. RGB colors are written `§x` followed by six hex digit codes, like
+/// `§x§f§f§8§0§0§0`. Unknown codes are removed, and empty spans are skipped.
+///
+/// ```rust
+/// # use minecraft_server_query::motd::{parse_codes, Color, Span, Style};
+/// assert_eq!(
+///     parse_codes("§6§lA §rServer"),
+///     [
+///         Span {
+///             text: "A ".to_string(),
+///             style: Style { color: Some(Color::Gold), bold: true, ..Style::default() },
+///         },
+///         Span { text: "Server".to_string(), style: Style::default() },
+///     ]
+/// );
+/// ```
+pub fn parse_codes(motd: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    push_codes(&mut spans, motd, Style::default());
+    spans
+}
+
+/// Append the spans of a MOTD with legacy formatting codes, starting from a
+/// base style which `§r` resets to.
+pub(crate) fn push_codes(spans: &mut Vec<Span>, motd: &str, base: Style) {
+    let mut style = base;
+    let mut text = String::new();
+    let mut chars = motd.chars();
+    while let Some(c) = chars.next() {
+        if c != SECTION_SIGN {
+            text.push(c);
+            continue;
+        }
+        let Some(code) = chars.next() else {
+            break;
+        };
+        push_span(spans, std::mem::take(&mut text), style);
+        match code.to_ascii_lowercase() {
+            'k' => style.obfuscated = true,
+            'l' => style.bold = true,
+            'm' => style.strikethrough = true,
+            'n' => style.underlined = true,
+            'o' => style.italic = true,
+            'r' => style = base,
+            'x' => {
+                if let Some(color) = parse_hex_color(&mut chars) {
+                    style = Style {
+                        color: Some(color),
+                        ..Style::default()
+                    };
+                }
+            }
+            code => {
+                if let Some(color) = Color::from_code(code) {
+                    style = Style {
+                        color: Some(color),
+                        ..Style::default()
+                    };
+                }
+            }
+        }
+    }
+    push_span(spans, text, style);
+}
+
+/// Parse the six hex digit codes of an RGB color following `§x`, advancing the
+/// characters only if they are valid.
+fn parse_hex_color(chars: &mut std::str::Chars<'_>) -> Option<Color> {
+    let mut lookahead = chars.clone();
+    let mut rgb = 0;
+    for _ in 0..6 {
+        if lookahead.next()? != SECTION_SIGN {
+            return None;
+        }
+        rgb = rgb << 4 | lookahead.next()?.to_digit(16)?;
+    }
+    *chars = lookahead;
+    Some(Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/// How [`to_legacy_with`] writes RGB colors
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum RgbCodes {
+    /// `§x` followed by six hex digit codes, understood by clients since 1.16
+    #[default]
+    Hex,
+    /// The code of the [closest named color](Color::to_named), for older clients
+    Nearest,
+}
+
+/// Write spans as a MOTD with legacy formatting codes, the inverse of [`parse_codes`].
+///
+/// Section signs in the text are not escaped, since legacy codes have no escape.
+///
+/// ```rust
+/// # use minecraft_server_query::motd::{parse_codes, to_legacy, Color, Span, Style};
+/// let spans = [
+///     Span {
+///         text: "A ".to_string(),
+///         style: Style { color: Some(Color::Gold), bold: true, ..Style::default() },
+///     },
+///     Span { text: "Server".to_string(), style: Style::default() },
+/// ];
+/// assert_eq!(to_legacy(&spans), "§6§lA §rServer");
+/// assert_eq!(parse_codes(&to_legacy(&spans)), spans);
+/// ```
+pub fn to_legacy(spans: &[Span]) -> String {
+    to_legacy_with(spans, RgbCodes::Hex)
+}
+
+/// Write spans as a MOTD with legacy formatting codes, like [`to_legacy`],
+/// choosing how to write RGB colors.
+///
+/// ```rust
+/// # use minecraft_server_query::motd::{to_legacy_with, Color, RgbCodes, Span, Style};
+/// let spans = [Span {
+///     text: "Orange".to_string(),
+///     style: Style { color: Some(Color::Rgb(255, 128, 0)), ..Style::default() },
+/// }];
+/// assert_eq!(to_legacy_with(&spans, RgbCodes::Hex), "§x§f§f§8§0§0§0Orange");
+/// assert_eq!(to_legacy_with(&spans, RgbCodes::Nearest), "§6Orange");
+/// ```
+pub fn to_legacy_with(spans: &[Span], rgb: RgbCodes) -> String {
+    let mut res = String::new();
+    let mut current = Style::default();
+    for span in spans.iter().filter(|span| !span.text.is_empty()) {
+        let mut style = span.style;
+        if rgb == RgbCodes::Nearest {
+            style.color = style.color.map(Color::to_named);
+        }
+
+        let flags = |style: Style| {
+            [
+                (style.obfuscated, 'k'),
+                (style.bold, 'l'),
+                (style.strikethrough, 'm'),
+                (style.underlined, 'n'),
+                (style.italic, 'o'),
+            ]
+        };
+        // Formatting can only be added: changing the color or removing a
+        // formatting code resets the formatting
+        let reset = style.color != current.color
+            || flags(current)
+                .iter()
+                .zip(flags(style))
+                .any(|(&(was, _), (is, _))| was && !is);
+        if reset {
+            match style.color {
+                Some(color) => push_color(&mut res, color),
+                None => push_code(&mut res, 'r'),
+            }
+            current = Style {
+                color: style.color,
+                ..Style::default()
+            };
+        }
+        for ((was, _), (is, code)) in flags(current).into_iter().zip(flags(style)) {
+            if is && !was {
+                push_code(&mut res, code);
+            }
+        }
+
+        current = style;
+        res.push_str(&span.text);
+    }
+    res
+}
+
+/// Append a formatting code.
+fn push_code(res: &mut String, code: char) {
+    res.push(SECTION_SIGN);
+    res.push(code);
+}
+
+/// Append the formatting codes of a color.
+fn push_color(res: &mut String, color: Color) {
+    match color.code() {
+        Some(code) => push_code(res, code),
+        None => {
+            let (r, g, b) = color.to_rgb();
+            push_code(res, 'x');
+            for digit in format!("{:06x}", (r as u32) << 16 | (g as u32) << 8 | b as u32).chars() {
+                push_code(res, digit);
+            }
+        }
+    }
+}
+
+/// Write spans as a MiniMessage string, the tag format of Adventure used in
+/// Paper configuration files.
+///
+/// Named colors become tags like `<dark_blue>`, RGB colors tags like
+/// `<#ff8000>`, and each formatting flag its own tag. Color tags enclose the
+/// formatting tags, and every tag is closed, innermost first. Literal `<` and
+/// `\` in the text are escaped with a backslash.
+///
+/// ```rust
+/// # use minecraft_server_query::motd::{parse_codes, to_minimessage};
+/// assert_eq!(
+///     to_minimessage(&parse_codes("§a§lHi §rthere")),
+///     "<green><bold>Hi </bold></green>there"
+/// );
+/// ```
+pub fn to_minimessage(spans: &[Span]) -> String {
+    let mut res = String::new();
+    let mut open: Vec<String> = Vec::new();
+    for span in spans.iter().filter(|span| !span.text.is_empty()) {
+        let tags = minimessage_tags(span.style);
+        // Keep the outermost tags shared with the previous span open
+        let kept = open.iter().zip(&tags).take_while(|(a, b)| a == b).count();
+        for tag in open.drain(kept..).rev() {
+            res.push_str(&format!("</{tag}>"));
+        }
+        for tag in &tags[kept..] {
+            res.push_str(&format!("<{tag}>"));
+        }
+        open = tags;
+
+        for c in span.text.chars() {
+            if c == '<' || c == '\\' {
+                res.push('\\');
+            }
+            res.push(c);
+        }
+    }
+    for tag in open.iter().rev() {
+        res.push_str(&format!("</{tag}>"));
+    }
+    res
+}
+
+/// MiniMessage tag names of a style, from the outermost to the innermost.
+fn minimessage_tags(style: Style) -> Vec<String> {
+    let color = style.color.map(|color| match color {
+        Color::Rgb(r, g, b) => format!("#{r:02x}{g:02x}{b:02x}"),
+        named => Color::NAMED
+            .iter()
+            .find(|(color, _, _, _)| *color == named)
+            .map(|(_, _, name, _)| name.to_string())
+            .expect("every color but RGB is named"),
+    });
+    let flags = [
+        (style.bold, "bold"),
+        (style.italic, "italic"),
+        (style.underlined, "underlined"),
+        (style.strikethrough, "strikethrough"),
+        (style.obfuscated, "obfuscated"),
+    ];
+    color
+        .into_iter()
+        .chain(
+            flags
+                .into_iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, tag)| tag.to_string()),
+        )
+        .collect()
+}
+
+/// Append a span, merging it with the last one if they have the same style.
+pub(crate) fn push_span(spans: &mut Vec<Span>, text: String, style: Style) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.style == style => last.text.push_str(&text),
+        _ => spans.push(Span { text, style }),
+    }
+}
+
+/// Remove the formatting codes from a MOTD.
+///
+/// A trailing section sign, without a code character, is removed as well.
+///
+/// ```rust
+/// # use minecraft_server_query::motd::strip_codes;
+/// assert_eq!(strip_codes("§6§lA §rMinecraft Server"), "A Minecraft Server");
+/// ```
+pub fn strip_codes(motd: &str) -> String {
+    let mut res = String::with_capacity(motd.len());
+    let mut chars = motd.chars();
+    while let Some(c) = chars.next() {
+        if c == SECTION_SIGN {
+            chars.next();
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_strip_codes() {
+        assert_eq!(strip_codes(""), "");
+        assert_eq!(strip_codes("No codes"), "No codes");
+        assert_eq!(strip_codes("§aGreen §x§r"), "Green ");
+        assert_eq!(strip_codes("Trailing §"), "Trailing ");
+        assert_eq!(strip_codes("§§Escaped"), "Escaped");
+    }
+
+    #[test]
+    fn test_parse_codes() {
+        assert_eq!(parse_codes(""), []);
+        assert_eq!(parse_codes("§a§r"), []);
+
+        let spans = parse_codes("§lBold §aGreen§o italic§§Trailing §");
+        let green = Style {
+            color: Some(Color::Green),
+            ..Style::default()
+        };
+        assert_eq!(
+            spans,
+            [
+                Span {
+                    text: "Bold ".to_string(),
+                    style: Style {
+                        bold: true,
+                        ..Style::default()
+                    },
+                },
+                Span {
+                    text: "Green".to_string(),
+                    style: green,
+                },
+                Span {
+                    text: " italicTrailing ".to_string(),
+                    style: Style {
+                        italic: true,
+                        ..green
+                    },
+                },
+            ]
+        );
+        assert_eq!(
+            spans_to_plain(&spans),
+            strip_codes("§lBold §aGreen§o italic§§Trailing §")
+        );
+
+        // Codes are case insensitive
+        assert_eq!(parse_codes("§Cred")[0].style.color, Some(Color::Red));
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        let orange = Some(Color::Rgb(255, 128, 0));
+        assert_eq!(parse_codes("§x§F§F§8§0§0§0Orange")[0].style.color, orange);
+        // Formatting is reset by RGB colors, like by named colors
+        assert_eq!(
+            parse_codes("§l§x§f§f§8§0§0§0Orange")[0].style,
+            Style {
+                color: orange,
+                ..Style::default()
+            }
+        );
+        // Incomplete sequences are skipped, but their codes are still read
+        assert_eq!(
+            parse_codes("§x§f§f§8§0§0Text")[0].style.color,
+            Some(Color::Black)
+        );
+        assert_eq!(parse_codes("§x§f§f§8§0§0§gText")[0].text, "Text");
+    }
+
+    #[test]
+    fn test_to_legacy() {
+        let span = |text: &str, style: Style| Span {
+            text: text.to_string(),
+            style,
+        };
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        let red = Style {
+            color: Some(Color::Red),
+            ..Style::default()
+        };
+
+        assert_eq!(to_legacy(&[]), "");
+        assert_eq!(to_legacy(&[span("Plain", Style::default())]), "Plain");
+        // Color codes are written before formatting codes
+        assert_eq!(
+            to_legacy(&[span("Red bold", Style { bold: true, ..red })]),
+            "§c§lRed bold"
+        );
+        // Formatting codes are only added
+        assert_eq!(
+            to_legacy(&[
+                span("Bold ", bold),
+                span(
+                    "Italic",
+                    Style {
+                        italic: true,
+                        ..bold
+                    }
+                ),
+            ]),
+            "§lBold §oItalic"
+        );
+        // Removing formatting resets with the color code, or with §r
+        assert_eq!(
+            to_legacy(&[
+                span("Red bold ", Style { bold: true, ..red }),
+                span("Red", red)
+            ]),
+            "§c§lRed bold §cRed"
+        );
+        assert_eq!(
+            to_legacy(&[span("Bold ", bold), span("Plain", Style::default())]),
+            "§lBold §rPlain"
+        );
+        // Empty spans are skipped
+        assert_eq!(
+            to_legacy(&[span("", red), span("Plain", Style::default())]),
+            "Plain"
+        );
+    }
+
+    #[test]
+    fn test_to_minimessage() {
+        assert_eq!(to_minimessage(&[]), "");
+        assert_eq!(to_minimessage(&parse_codes("Plain")), "Plain");
+
+        // A typical MOTD, with named and RGB colors
+        let motd = "§6§lA §r§x§f§f§8§0§0§0Minecraft §b§nServer§r - §7§oplay.example.com";
+        assert_eq!(
+            to_minimessage(&parse_codes(motd)),
+            "<gold><bold>A </bold></gold><#ff8000>Minecraft </#ff8000>\
+             <aqua><underlined>Server</underlined></aqua> - \
+             <gray><italic>play.example.com</italic></gray>"
+        );
+
+        // Flags are nested in a fixed order, whatever the order of the codes
+        assert_eq!(
+            to_minimessage(&parse_codes("§c§k§o§m§n§lAll")),
+            "<red><bold><italic><underlined><strikethrough><obfuscated>All\
+             </obfuscated></strikethrough></underlined></italic></bold></red>"
+        );
+        // Tags are escaped, along with the escape character
+        assert_eq!(
+            to_minimessage(&parse_codes("§a<red>\\o/")),
+            "<green>\\<red>\\\\o/</green>"
+        );
+    }
+
+    #[test]
+    fn test_to_minimessage_adjacent_changes() {
+        // Adjacent colors close the previous one
+        assert_eq!(
+            to_minimessage(&parse_codes("§aA§bB§x§0§0§0§0§f§fC")),
+            "<green>A</green><aqua>B</aqua><#0000ff>C</#0000ff>"
+        );
+        // The same color stays open when only the formatting changes
+        assert_eq!(
+            to_minimessage(&parse_codes("§a§lA§r§aB§oC")),
+            "<green><bold>A</bold>B<italic>C</italic></green>"
+        );
+        // Adding an outer flag reopens the inner ones
+        assert_eq!(
+            to_minimessage(&parse_codes("§oA§lB")),
+            "<italic>A</italic><bold><italic>B</italic></bold>"
+        );
+        // Empty spans don't open tags
+        let span = |text: &str, color| Span {
+            text: text.to_string(),
+            style: Style {
+                color: Some(color),
+                ..Style::default()
+            },
+        };
+        assert_eq!(
+            to_minimessage(&[span("", Color::Red), span("A", Color::Blue)]),
+            "<blue>A</blue>"
+        );
+    }
+
+    /// Merge adjacent spans with the same style, and skip empty spans, like the parser.
+    fn normalize(spans: &[Span], rgb: RgbCodes) -> Vec<Span> {
+        let mut res = Vec::new();
+        for span in spans {
+            let mut style = span.style;
+            if rgb == RgbCodes::Nearest {
+                style.color = style.color.map(Color::to_named);
+            }
+            push_span(&mut res, span.text.clone(), style);
+        }
+        res
+    }
+
+    fn style() -> impl Strategy<Value = Style> {
+        let color = prop_oneof![
+            (0..16usize).prop_map(|i| Color::NAMED[i].0),
+            any::<(u8, u8, u8)>().prop_map(|(r, g, b)| Color::Rgb(r, g, b)),
+        ];
+        (proptest::option::of(color), any::<[bool; 5]>()).prop_map(|(color, flags)| {
+            let [bold, italic, underlined, strikethrough, obfuscated] = flags;
+            Style {
+                color,
+                bold,
+                italic,
+                underlined,
+                strikethrough,
+                obfuscated,
+            }
+        })
+    }
+
+    fn spans() -> impl Strategy<Value = Vec<Span>> {
+        let span = ("[a-z0-9 ]{0,4}", style()).prop_map(|(text, style)| Span { text, style });
+        proptest::collection::vec(span, 0..8)
+    }
+
+    proptest! {
+        #[test]
+        fn prop_to_legacy_round_trip(spans in spans()) {
+            for rgb in [RgbCodes::Hex, RgbCodes::Nearest] {
+                let legacy = to_legacy_with(&spans, rgb);
+                prop_assert_eq!(parse_codes(&legacy), normalize(&spans, rgb), "{}", legacy);
+                prop_assert_eq!(strip_codes(&legacy), spans_to_plain(&spans));
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_named() {
+        for (color, _, _, _) in Color::NAMED {
+            assert_eq!(color.to_named(), color);
+            let (r, g, b) = color.to_rgb();
+            assert_eq!(Color::Rgb(r, g, b).to_named(), color);
+        }
+        assert_eq!(Color::Rgb(10, 10, 20).to_named(), Color::Black);
+        assert_eq!(Color::Rgb(240, 240, 240).to_named(), Color::White);
+    }
+}
@@ -0,0 +1,503 @@
+//! OpenTelemetry instrumentation of the clients.
+//!
+//! A [`Telemetry`] given to the query builders, like
+//! [`blocking::Query::telemetry`](crate::blocking::Query::telemetry), or to the
+//! [probe options](crate::probe::ProbeOptions) records every operation:
+//!
+//! - its duration, in seconds, in the `minecraft.query.duration` histogram,
+//!   with the `operation`, `outcome`, `server.address` and `server.port`
+//!   attributes,
+//! - its failures in the `minecraft.query.failures` counter, by
+//!   [class of error](error_class) in the `error.type` attribute,
+//! - the operations in progress in the `minecraft.query.in_flight` gauge,
+//! - a span, with a child span for each phase of a query (resolution,
+//!   connection, handshakes and status requests), and an event for each
+//!   protocol tried by a probe.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking::Query, otel::Telemetry};
+//! // Instruments of the global meter and tracer providers
+//! let telemetry = Telemetry::global();
+//! let full_stat = Query::to("play.example.com").telemetry(telemetry).full()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    fmt, io,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use opentelemetry::{
+    global::{self, BoxedTracer},
+    metrics::{Counter, Histogram, Meter, UpDownCounter},
+    trace::{Span, SpanKind, Status, TraceContextExt, Tracer},
+    Context, KeyValue,
+};
+
+use crate::{ClientError, Timings};
+
+/// Name of the instrumentation scope of the global meter and tracer
+pub const SCOPE: &str = "minecraft-server-query";
+
+/// Instruments recording the operations of the clients
+///
+/// Clones share the same instruments.
+#[derive(Clone)]
+pub struct Telemetry(Arc<Instruments>);
+
+struct Instruments {
+    duration: Histogram<f64>,
+    failures: Counter<u64>,
+    in_flight: UpDownCounter<i64>,
+    tracer: BoxedTracer,
+}
+
+impl Telemetry {
+    /// Record the operations with instruments of the given meter, and spans
+    /// of the given tracer.
+    pub fn new<T>(meter: &Meter, tracer: T) -> Self
+    where
+        T: Tracer + Send + Sync + 'static,
+        T::Span: Send + Sync + 'static,
+    {
+        Self(Arc::new(Instruments {
+            duration: meter
+                .f64_histogram("minecraft.query.duration")
+                .with_unit("s")
+                .with_description("Duration of the client operations")
+                .build(),
+            failures: meter
+                .u64_counter("minecraft.query.failures")
+                .with_description("Client operations which failed")
+                .build(),
+            in_flight: meter
+                .i64_up_down_counter("minecraft.query.in_flight")
+                .with_description("Client operations in progress")
+                .build(),
+            tracer: BoxedTracer::new(Box::new(tracer)),
+        }))
+    }
+
+    /// Record the operations with the meter and tracer of the global
+    /// providers, under the [`SCOPE`] name.
+    pub fn global() -> Self {
+        Self::new(&global::meter(SCOPE), global::tracer(SCOPE))
+    }
+}
+
+impl fmt::Debug for Telemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Telemetry").finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for Telemetry {
+    /// Whether both share the same instruments.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Telemetry {}
+
+/// Class of a client error, recorded in the `error.type` attribute of the
+/// failures: `"resolve"`, `"timeout"`, `"connection_refused"`,
+/// `"invalid_response"`, `"cancelled"` or `"other"`.
+pub fn error_class(e: &io::Error) -> &'static str {
+    let operation = e
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<ClientError>())
+        .map(ClientError::operation);
+    if operation == Some("resolve") {
+        return "resolve";
+    }
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => "timeout",
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => "connection_refused",
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => "invalid_response",
+        io::ErrorKind::NotFound => "resolve",
+        io::ErrorKind::Interrupted => "cancelled",
+        _ => "other",
+    }
+}
+
+/// Recording of a client operation, from its start to its end. Operations
+/// dropped before they finish, like cancelled futures, end their span
+/// without recording their duration.
+pub(crate) struct Operation(Option<Active>);
+
+struct Active {
+    telemetry: Telemetry,
+    attributes: Vec<KeyValue>,
+    cx: Context,
+    start: Instant,
+    started: SystemTime,
+}
+
+impl Operation {
+    /// An operation which is not recorded.
+    pub(crate) fn none() -> Self {
+        Self(None)
+    }
+
+    /// Start recording an operation on a server, on a given port or on
+    /// several ones.
+    pub(crate) fn start(
+        telemetry: &Telemetry,
+        name: &'static str,
+        host: &str,
+        port: Option<u16>,
+    ) -> Self {
+        let mut attributes = vec![
+            KeyValue::new("operation", name),
+            KeyValue::new("server.address", host.to_string()),
+        ];
+        if let Some(port) = port {
+            attributes.push(KeyValue::new("server.port", i64::from(port)));
+        }
+        let started = SystemTime::now();
+        let tracer = &telemetry.0.tracer;
+        let span = tracer
+            .span_builder(format!("minecraft.query {name}"))
+            .with_kind(SpanKind::Client)
+            .with_start_time(started)
+            .with_attributes(attributes.clone())
+            .start_with_context(tracer, &Context::current());
+        telemetry.0.in_flight.add(1, &attributes);
+        Self(Some(Active {
+            telemetry: telemetry.clone(),
+            attributes,
+            cx: Context::current_with_span(span),
+            start: Instant::now(),
+            started,
+        }))
+    }
+
+    /// Add an event for a protocol tried by a probe on a port, with its
+    /// latency if it answered.
+    #[cfg(feature = "probe")]
+    pub(crate) fn attempt(
+        &self,
+        protocol: crate::probe::Source,
+        port: u16,
+        result: Result<std::time::Duration, &io::Error>,
+    ) {
+        let Some(active) = &self.0 else {
+            return;
+        };
+        let mut attributes = vec![
+            KeyValue::new("minecraft.protocol", format!("{protocol:?}")),
+            KeyValue::new("server.port", i64::from(port)),
+        ];
+        match result {
+            Ok(latency) => {
+                attributes.push(KeyValue::new("outcome", "ok"));
+                attributes.push(KeyValue::new("latency", latency.as_secs_f64()));
+            }
+            Err(e) => {
+                attributes.push(KeyValue::new("outcome", "error"));
+                attributes.push(KeyValue::new("error.type", error_class(e)));
+            }
+        }
+        active.cx.span().add_event("attempt", attributes);
+    }
+
+    /// Record the end of the operation, with the duration of its phases.
+    pub(crate) fn finish<T>(mut self, result: &io::Result<T>, timings: &Timings) {
+        let Some(active) = self.0.as_mut() else {
+            return;
+        };
+        let instruments = &active.telemetry.0;
+        active.phases(timings);
+
+        let mut attributes = active.attributes.clone();
+        let span = active.cx.span();
+        match result {
+            Ok(_) => {
+                attributes.push(KeyValue::new("outcome", "ok"));
+                span.set_status(Status::Ok);
+            }
+            Err(e) => {
+                attributes.push(KeyValue::new("outcome", "error"));
+                let mut failure = active.attributes.clone();
+                failure.push(KeyValue::new("error.type", error_class(e)));
+                instruments.failures.add(1, &failure);
+                span.set_attribute(KeyValue::new("error.type", error_class(e)));
+                span.set_status(Status::error(e.to_string()));
+            }
+        }
+        instruments
+            .duration
+            .record(active.start.elapsed().as_secs_f64(), &attributes);
+    }
+}
+
+impl Active {
+    /// Child spans of the phases of a query, one after the other from the
+    /// start of the operation.
+    fn phases(&self, timings: &Timings) {
+        let mut phases = vec![("resolve", timings.resolve), ("connect", timings.connect)];
+        for (i, &handshake) in timings.handshakes.iter().enumerate() {
+            phases.push(("handshake", handshake));
+            if let Some(&stat) = timings.stats.get(i) {
+                phases.push(("stat", stat));
+            }
+        }
+        if timings.total().is_zero() {
+            return;
+        }
+
+        let tracer = &self.telemetry.0.tracer;
+        let mut time = self.started;
+        for (name, duration) in phases {
+            let mut span = tracer
+                .span_builder(name)
+                .with_kind(SpanKind::Client)
+                .with_start_time(time)
+                .start_with_context(tracer, &self.cx);
+            time += duration;
+            span.end_with_timestamp(time);
+        }
+    }
+}
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.telemetry.0.in_flight.add(-1, &self.attributes);
+        self.cx.span().end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, net::UdpSocket, time::Duration};
+
+    use opentelemetry::{metrics::MeterProvider as _, trace::TracerProvider as _};
+    use opentelemetry_sdk::{
+        metrics::{
+            data::{AggregatedMetrics, Metric, MetricData},
+            InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+        },
+        trace::{InMemorySpanExporter, SdkTracerProvider, SpanData},
+    };
+
+    use super::*;
+    use crate::{blocking::Query, testing::MockQueryServer};
+
+    /// Instruments exporting to memory
+    struct Exporters {
+        meter_provider: SdkMeterProvider,
+        metrics: InMemoryMetricExporter,
+        _tracer_provider: SdkTracerProvider,
+        spans: InMemorySpanExporter,
+    }
+
+    impl Exporters {
+        fn new() -> (Telemetry, Self) {
+            let metrics = InMemoryMetricExporter::default();
+            let meter_provider = SdkMeterProvider::builder()
+                .with_reader(PeriodicReader::builder(metrics.clone()).build())
+                .build();
+            let spans = InMemorySpanExporter::default();
+            let tracer_provider = SdkTracerProvider::builder()
+                .with_simple_exporter(spans.clone())
+                .build();
+            let telemetry =
+                Telemetry::new(&meter_provider.meter(SCOPE), tracer_provider.tracer(SCOPE));
+            let exporters = Self {
+                meter_provider,
+                metrics,
+                _tracer_provider: tracer_provider,
+                spans,
+            };
+            (telemetry, exporters)
+        }
+
+        /// Data points of a metric, as their attributes and value.
+        fn points(&self, name: &str) -> Vec<(BTreeMap<String, String>, f64)> {
+            self.meter_provider.force_flush().unwrap();
+            let metrics = self.metrics.get_finished_metrics().unwrap();
+            let metric = metrics
+                .last()
+                .unwrap()
+                .scope_metrics()
+                .flat_map(|scope| scope.metrics())
+                .find(|metric| metric.name() == name)
+                .map(Metric::data)
+                .unwrap_or_else(|| panic!("No {name} metric"));
+            let mut points = match metric {
+                AggregatedMetrics::F64(MetricData::Histogram(histogram)) => histogram
+                    .data_points()
+                    .map(|point| (attributes(point.attributes()), point.count() as f64))
+                    .collect::<Vec<_>>(),
+                AggregatedMetrics::U64(MetricData::Sum(sum)) => sum
+                    .data_points()
+                    .map(|point| (attributes(point.attributes()), point.value() as f64))
+                    .collect(),
+                AggregatedMetrics::I64(MetricData::Sum(sum)) => sum
+                    .data_points()
+                    .map(|point| (attributes(point.attributes()), point.value() as f64))
+                    .collect(),
+                data => panic!("{data:?}"),
+            };
+            points.sort_by(|a, b| a.0.cmp(&b.0));
+            points
+        }
+
+        fn spans(&self) -> Vec<SpanData> {
+            self.spans.get_finished_spans().unwrap()
+        }
+    }
+
+    fn attributes<'a>(attributes: impl Iterator<Item = &'a KeyValue>) -> BTreeMap<String, String> {
+        attributes
+            .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+            .collect()
+    }
+
+    fn expected(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_query_instruments() {
+        let (telemetry, exporters) = Exporters::new();
+        let server = MockQueryServer::new().unwrap();
+        let port = server.addr().port().to_string();
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_port = silent.local_addr().unwrap().port().to_string();
+
+        Query::to(server.addr().to_string())
+            .telemetry(telemetry.clone())
+            .full()
+            .unwrap();
+        Query::to(silent.local_addr().unwrap().to_string())
+            .timeout(Duration::from_millis(50))
+            .telemetry(telemetry.clone())
+            .basic()
+            .unwrap_err();
+
+        let ok = [
+            ("operation", "full_stat"),
+            ("server.address", "127.0.0.1"),
+            ("server.port", &port),
+        ];
+        let failed = [
+            ("operation", "basic_stat"),
+            ("server.address", "127.0.0.1"),
+            ("server.port", &silent_port),
+        ];
+        let with = |attributes: &[(&str, &str)], extra: (&str, &str)| {
+            let mut attributes = expected(attributes);
+            attributes.insert(extra.0.to_string(), extra.1.to_string());
+            attributes
+        };
+        assert_eq!(
+            exporters.points("minecraft.query.duration"),
+            [
+                (with(&failed, ("outcome", "error")), 1.0),
+                (with(&ok, ("outcome", "ok")), 1.0),
+            ]
+        );
+        assert_eq!(
+            exporters.points("minecraft.query.failures"),
+            [(with(&failed, ("error.type", "timeout")), 1.0)]
+        );
+        assert_eq!(
+            exporters.points("minecraft.query.in_flight"),
+            [(expected(&failed), 0.0), (expected(&ok), 0.0)]
+        );
+
+        // A span for each query, with a child span for each phase
+        let spans = exporters.spans();
+        let query = spans
+            .iter()
+            .find(|span| span.name == "minecraft.query full_stat")
+            .unwrap();
+        assert_eq!(query.status, Status::Ok);
+        assert_eq!(attributes(query.attributes.iter()), expected(&ok));
+        let phases = spans
+            .iter()
+            .filter(|span| span.parent_span_id == query.span_context.span_id())
+            .map(|span| span.name.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(phases, ["resolve", "connect", "handshake", "stat"]);
+
+        let query = spans
+            .iter()
+            .find(|span| span.name == "minecraft.query basic_stat")
+            .unwrap();
+        assert!(matches!(query.status, Status::Error { .. }));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_async_instruments() {
+        let (telemetry, exporters) = Exporters::new();
+        let server = MockQueryServer::new().unwrap();
+        crate::tokio::Query::to(server.addr().to_string())
+            .telemetry(telemetry)
+            .ping()
+            .await
+            .unwrap();
+
+        let points = exporters.points("minecraft.query.duration");
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].0["operation"], "ping");
+        assert_eq!(points[0].0["outcome"], "ok");
+        assert_eq!(exporters.spans().len(), 3);
+    }
+
+    #[cfg(feature = "probe")]
+    #[test]
+    fn test_probe_events() {
+        use crate::probe::{blocking::probe, ProbeOptions, Source};
+
+        let (telemetry, exporters) = Exporters::new();
+        let server = MockQueryServer::new().unwrap();
+        let options = ProbeOptions {
+            order: vec![Source::Query],
+            telemetry: Some(telemetry),
+            ..ProbeOptions::default()
+        };
+        probe(&server.addr().to_string(), &options).unwrap();
+
+        let spans = exporters.spans();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].name, "minecraft.query probe");
+        let events = spans[0].events.iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 1);
+        let event = attributes(events[0].attributes.iter());
+        assert_eq!(event["minecraft.protocol"], "Query");
+        assert_eq!(event["outcome"], "ok");
+        assert_eq!(
+            exporters.points("minecraft.query.duration")[0].0["operation"],
+            "probe"
+        );
+    }
+
+    #[test]
+    fn test_error_class() {
+        let resolve = ClientError::wrap(
+            io::ErrorKind::Other.into(),
+            "resolve",
+            "play.example.com:25565",
+            None,
+        );
+        assert_eq!(error_class(&resolve), "resolve");
+        for (kind, class) in [
+            (io::ErrorKind::TimedOut, "timeout"),
+            (io::ErrorKind::WouldBlock, "timeout"),
+            (io::ErrorKind::ConnectionRefused, "connection_refused"),
+            (io::ErrorKind::InvalidData, "invalid_response"),
+            (io::ErrorKind::Interrupted, "cancelled"),
+            (io::ErrorKind::PermissionDenied, "other"),
+        ] {
+            assert_eq!(error_class(&kind.into()), class, "{kind:?}");
+        }
+    }
+}
@@ -18,13 +18,13 @@
 //! | Session ID | [`u32`]        |                                 |
 //! | Payload    | Varies         | See per-packet documentation    |
 
-use bytes::BufMut;
+use bytes::{Buf, BufMut};
 use std::ops::Deref;
 
 /// Magic number used in server bound packets
-const MAGIC_NUMBER: u16 = 0xFEFD;
+pub(crate) const MAGIC_NUMBER: u16 = 0xFEFD;
 /// Session mask: the higher 4 bits of a byte are not taken into account
-const SESSION_MASK: u32 = 0x0F0F0F0F;
+pub(crate) const SESSION_MASK: u32 = 0x0F0F0F0F;
 
 /// Single byte constants representing the type of a packet
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -36,25 +36,238 @@ pub enum PacketType {
 }
 
 /// Write a server-bound packet to a byte array
-fn write_packet<const N: usize, const P: usize>(
+const fn write_packet<const N: usize, const P: usize>(
     packet_type: PacketType,
     session_id: u32,
     payload: [u32; P],
 ) -> [u8; N] {
     let mut res = [0; N];
-    {
-        let mut packet = &mut res[..];
-        packet.put_u16(MAGIC_NUMBER);
-        packet.put_u8(packet_type as u8);
-        packet.put_u32(session_id & SESSION_MASK);
-        for p in payload {
-            packet.put_u32(p);
+    let magic = MAGIC_NUMBER.to_be_bytes();
+    res[0] = magic[0];
+    res[1] = magic[1];
+    res[2] = packet_type as u8;
+    write_u32(&mut res, 3, session_id & SESSION_MASK);
+
+    let mut i = 0;
+    while i < P {
+        write_u32(&mut res, 7 + 4 * i, payload[i]);
+        i += 1;
+    }
+    res
+}
+
+/// Write a big-endian integer to a byte array, at the given offset
+const fn write_u32<const N: usize>(buf: &mut [u8; N], offset: usize, value: u32) {
+    let bytes = value.to_be_bytes();
+    let mut i = 0;
+    while i < 4 {
+        buf[offset + i] = bytes[i];
+        i += 1;
+    }
+}
+
+/// A server-bound packet, built by a Query client.
+///
+/// This trait is sealed: it is only implemented by [`Handshake`], [`BasicStat`],
+/// [`FullStat`] and [`StatRequest`].
+pub trait QueryPacket: private::Sealed {
+    /// Raw bytes of the packet
+    fn as_bytes(&self) -> &[u8];
+
+    /// Type of the packet
+    fn packet_type(&self) -> PacketType {
+        if self.as_bytes()[2] == PacketType::Handshake as u8 {
+            PacketType::Handshake
+        } else {
+            PacketType::Stat
         }
     }
 
+    /// Session ID of the packet. The higher 4 bits of each byte are always zero.
+    fn session_id(&self) -> u32 {
+        (&self.as_bytes()[3..]).get_u32()
+    }
+
+    /// Length of the packet, in bytes
+    fn encoded_len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Append the packet to a buffer.
+    fn write_to<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(self.as_bytes());
+    }
+}
+
+/// Append a server-bound packet to a buffer
+fn put_packet<B: BufMut>(buf: &mut B, packet_type: PacketType, session_id: u32, payload: &[u32]) {
+    buf.put_u16(MAGIC_NUMBER);
+    buf.put_u8(packet_type as u8);
+    buf.put_u32(session_id & SESSION_MASK);
+    for &p in payload {
+        buf.put_u32(p);
+    }
+}
+
+/// Append a handshake request packet to a buffer, without building a [`Handshake`].
+///
+/// ```rust
+/// # use minecraft_server_query::packets::{self, Handshake};
+/// let mut buf = Vec::with_capacity(Handshake::ENCODED_LEN);
+/// packets::write_handshake(&mut buf, 1);
+/// assert_eq!(buf, *Handshake::new(1));
+/// ```
+pub fn write_handshake<B: BufMut>(buf: &mut B, session_id: u32) {
+    put_packet(buf, PacketType::Handshake, session_id, &[]);
+}
+
+/// Append a basic status request packet to a buffer, without building a [`BasicStat`].
+pub fn write_basic_stat<B: BufMut>(buf: &mut B, session_id: u32, token: u32) {
+    put_packet(buf, PacketType::Stat, session_id, &[token]);
+}
+
+/// Append a full status request packet to a buffer, without building a [`FullStat`].
+pub fn write_full_stat<B: BufMut>(buf: &mut B, session_id: u32, token: u32) {
+    put_packet(buf, PacketType::Stat, session_id, &[token, 0]);
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Write a client-bound packet to a byte vector
+pub fn write_response(packet_type: PacketType, session_id: u32, payload: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(5 + payload.len());
+    res.put_u8(packet_type as u8);
+    res.put_u32(session_id);
+    res.put_slice(payload);
     res
 }
 
+/// Header of a client-bound packet, as received by a Query client
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResponseHeader {
+    /// Type of the response
+    pub packet_type: PacketType,
+    /// Session ID sent by the client in the request
+    pub session_id: u32,
+}
+
+impl ResponseHeader {
+    /// Split a client-bound packet into its header and its payload, the inverse
+    /// of [`write_response`]. Returns `None` if the packet is shorter than the
+    /// header or if the packet type is invalid.
+    ///
+    /// This never panics on arbitrary input.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::packets::{write_response, PacketType, ResponseHeader};
+    /// let response = write_response(PacketType::Handshake, 1, b"123456\0");
+    /// assert_eq!(
+    ///     ResponseHeader::parse(&response),
+    ///     Some((
+    ///         ResponseHeader { packet_type: PacketType::Handshake, session_id: 1 },
+    ///         &b"123456\0"[..],
+    ///     )),
+    /// );
+    /// assert_eq!(ResponseHeader::parse(b"\x09\0\0"), None);
+    /// assert_eq!(ResponseHeader::parse(b"\x01\0\0\0\x01"), None);
+    /// ```
+    pub fn parse(mut packet: &[u8]) -> Option<(Self, &[u8])> {
+        if packet.len() < crate::RESPONSE_HEADER_SIZE {
+            return None;
+        }
+        let packet_type = match packet.get_u8() {
+            t if t == PacketType::Stat as u8 => PacketType::Stat,
+            t if t == PacketType::Handshake as u8 => PacketType::Handshake,
+            _ => return None,
+        };
+        let session_id = packet.get_u32();
+        Some((
+            Self {
+                packet_type,
+                session_id,
+            },
+            packet,
+        ))
+    }
+}
+
+/// A server-bound packet, as received by a Query server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Handshake request, see [`Handshake`]
+    Handshake {
+        /// Session ID of the client
+        session_id: u32,
+    },
+    /// Basic status request, see [`BasicStat`]
+    BasicStat {
+        /// Session ID of the client
+        session_id: u32,
+        /// Token obtained from a handshake
+        token: u32,
+    },
+    /// Full status request, see [`FullStat`]
+    FullStat {
+        /// Session ID of the client
+        session_id: u32,
+        /// Token obtained from a handshake
+        token: u32,
+    },
+}
+
+impl Request {
+    /// Parse a server-bound packet. Returns `None` if the magic number, the
+    /// packet type or the packet length is invalid.
+    ///
+    /// Like vanilla servers, status requests are told apart by their length.
+    /// This never panics on arbitrary input.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::packets::{FullStat, Handshake, Request};
+    /// assert_eq!(
+    ///     Request::parse(&Handshake::new(1)),
+    ///     Some(Request::Handshake { session_id: 1 }),
+    /// );
+    /// assert_eq!(
+    ///     Request::parse(&FullStat::new(1, 123456)),
+    ///     Some(Request::FullStat { session_id: 1, token: 123456 }),
+    /// );
+    /// assert_eq!(Request::parse(b"\xFE\xFD\x09"), None);
+    /// ```
+    pub fn parse(mut packet: &[u8]) -> Option<Self> {
+        let len = packet.len();
+        if len < 7 || packet.get_u16() != MAGIC_NUMBER {
+            return None;
+        }
+        let packet_type = packet.get_u8();
+        let session_id = packet.get_u32();
+
+        match (packet_type, len) {
+            (t, 7) if t == PacketType::Handshake as u8 => Some(Self::Handshake { session_id }),
+            (t, 11) if t == PacketType::Stat as u8 => Some(Self::BasicStat {
+                session_id,
+                token: packet.get_u32(),
+            }),
+            (t, 15) if t == PacketType::Stat as u8 => Some(Self::FullStat {
+                session_id,
+                token: packet.get_u32(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Session ID of the client that sent the request
+    pub fn session_id(&self) -> u32 {
+        match *self {
+            Self::Handshake { session_id }
+            | Self::BasicStat { session_id, .. }
+            | Self::FullStat { session_id, .. } => session_id,
+        }
+    }
+}
+
 /// Handshake request packet, 7 bytes long
 ///
 /// The payload is empty.
@@ -62,12 +275,38 @@ fn write_packet<const N: usize, const P: usize>(
 pub struct Handshake([u8; 7]);
 
 impl Handshake {
+    /// Length of the packet, in bytes
+    pub const ENCODED_LEN: usize = 7;
+
     /// Build a new handshake request packet from the given session id
-    pub fn new(session_id: u32) -> Self {
+    ///
+    /// Packets can be built at compile time:
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::packets::Handshake;
+    /// static HANDSHAKE: Handshake = Handshake::new(1);
+    /// assert_eq!(*HANDSHAKE, [0xFE, 0xFD, 9, 0, 0, 0, 1]);
+    /// ```
+    pub const fn new(session_id: u32) -> Self {
         Self(write_packet(PacketType::Handshake, session_id, []))
     }
 }
 
+impl private::Sealed for Handshake {}
+
+impl QueryPacket for Handshake {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Handshake {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Kept for compatibility, prefer [`as_bytes`](QueryPacket::as_bytes).
 impl Deref for Handshake {
     type Target = [u8];
     fn deref(&self) -> &Self::Target {
@@ -82,12 +321,30 @@ impl Deref for Handshake {
 pub struct BasicStat([u8; 11]);
 
 impl BasicStat {
+    /// Length of the packet, in bytes
+    pub const ENCODED_LEN: usize = 11;
+
     /// Build a new basic status request packet from the given session ID and token
-    pub fn new(session_id: u32, token: u32) -> Self {
+    pub const fn new(session_id: u32, token: u32) -> Self {
         Self(write_packet(PacketType::Stat, session_id, [token]))
     }
 }
 
+impl private::Sealed for BasicStat {}
+
+impl QueryPacket for BasicStat {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for BasicStat {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Kept for compatibility, prefer [`as_bytes`](QueryPacket::as_bytes).
 impl Deref for BasicStat {
     type Target = [u8];
     fn deref(&self) -> &Self::Target {
@@ -98,19 +355,297 @@ impl Deref for BasicStat {
 /// Full status request packet, 15 bytes long
 ///
 /// The payload contains the token obtained from a handshake, and is padded to 8 bytes.
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct FullStat([u8; 15]);
 
 impl FullStat {
+    /// Length of the packet, in bytes
+    pub const ENCODED_LEN: usize = 15;
+
     /// Build a new full status request packet from the given session ID and token
-    pub fn new(session_id: u32, token: u32) -> Self {
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::packets::FullStat;
+    /// const FULL_STAT: FullStat = FullStat::new(0x01020304, 9513307);
+    /// assert_eq!(
+    ///     *FULL_STAT,
+    ///     [0xFE, 0xFD, 0, 0x01, 0x02, 0x03, 0x04, 0x00, 0x91, 0x29, 0x5B, 0, 0, 0, 0],
+    /// );
+    /// ```
+    pub const fn new(session_id: u32, token: u32) -> Self {
         Self(write_packet(PacketType::Stat, session_id, [token, 0]))
     }
 }
 
+impl private::Sealed for FullStat {}
+
+impl QueryPacket for FullStat {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for FullStat {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Kept for compatibility, prefer [`as_bytes`](QueryPacket::as_bytes).
 impl Deref for FullStat {
     type Target = [u8];
     fn deref(&self) -> &Self::Target {
         &self.0
     }
 }
+
+/// Status request packet with an arbitrary payload after the token.
+///
+/// This is a diagnostic API, to experiment with the way server implementations
+/// react to non-standard requests: vanilla servers tell status requests apart
+/// by their length, and ignore requests which are neither 11 nor 15 bytes
+/// long. Use [`BasicStat`] and [`FullStat`] to query servers.
+///
+/// ```rust
+/// # use minecraft_server_query::packets::{FullStat, QueryPacket, StatRequest};
+/// let request = StatRequest::with_payload(1, 123456, &[0; 4]);
+/// assert_eq!(request.as_bytes(), FullStat::new(1, 123456).as_bytes());
+///
+/// let request = StatRequest::with_payload(1, 123456, b"extra");
+/// assert_eq!(request.encoded_len(), 16);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatRequest(Vec<u8>);
+
+impl StatRequest {
+    /// Build a status request packet from the given session ID and token,
+    /// followed by the given bytes.
+    pub fn with_payload(session_id: u32, token: u32, extra: &[u8]) -> Self {
+        let mut res = Vec::with_capacity(BasicStat::ENCODED_LEN + extra.len());
+        put_packet(&mut res, PacketType::Stat, session_id, &[token]);
+        res.extend_from_slice(extra);
+        Self(res)
+    }
+
+    /// Raw bytes of the packet
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl private::Sealed for StatRequest {}
+
+impl QueryPacket for StatRequest {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for StatRequest {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Reference implementation of [`write_packet`], using [`BufMut`].
+    fn write_packet_buf<const N: usize, const P: usize>(
+        packet_type: PacketType,
+        session_id: u32,
+        payload: [u32; P],
+    ) -> [u8; N] {
+        let mut res = [0; N];
+        {
+            let mut packet = &mut res[..];
+            packet.put_u16(MAGIC_NUMBER);
+            packet.put_u8(packet_type as u8);
+            packet.put_u32(session_id & SESSION_MASK);
+            for p in payload {
+                packet.put_u32(p);
+            }
+        }
+        res
+    }
+
+    #[test]
+    fn test_packet_accessors() {
+        let handshake = Handshake::new(0x12345678);
+        assert_eq!(handshake.as_bytes(), handshake.as_ref());
+        assert_eq!(handshake.as_bytes().len(), 7);
+        assert_eq!(handshake.packet_type(), PacketType::Handshake);
+        assert_eq!(handshake.session_id(), 0x02040608);
+
+        let basic_stat = BasicStat::new(0xFFFFFFFF, 9513307);
+        assert_eq!(basic_stat.as_bytes(), &*basic_stat);
+        assert_eq!(basic_stat.as_bytes().len(), 11);
+        assert_eq!(basic_stat.packet_type(), PacketType::Stat);
+        assert_eq!(basic_stat.session_id(), SESSION_MASK);
+
+        let full_stat = FullStat::new(1, 9513307);
+        assert_eq!(full_stat.as_bytes().len(), 15);
+        assert_eq!(full_stat.packet_type(), PacketType::Stat);
+        assert_eq!(full_stat.session_id(), 1);
+        assert_eq!(
+            Request::parse(full_stat.as_bytes()),
+            Some(Request::FullStat {
+                session_id: full_stat.session_id(),
+                token: 9513307,
+            })
+        );
+    }
+
+    #[test]
+    fn test_stat_request() {
+        let request = StatRequest::with_payload(0xFFFFFFFF, 0x01020304, b"\xAB\xCD\xEF");
+        assert_eq!(
+            request.as_bytes(),
+            [0xFE, 0xFD, 0, 0x0F, 0x0F, 0x0F, 0x0F, 1, 2, 3, 4, 0xAB, 0xCD, 0xEF]
+        );
+        assert_eq!(request.packet_type(), PacketType::Stat);
+        assert_eq!(request.session_id(), SESSION_MASK);
+        assert_eq!(Request::parse(request.as_bytes()), None);
+
+        let request = StatRequest::with_payload(1, 9513307, &[]);
+        assert_eq!(request.as_bytes(), BasicStat::new(1, 9513307).as_bytes());
+        assert_eq!(request.into_vec(), BasicStat::new(1, 9513307).to_vec());
+    }
+
+    #[test]
+    fn test_write_to() {
+        let mut buf = BytesMut::new();
+        let handshake = Handshake::new(0x12345678);
+        handshake.write_to(&mut buf);
+        write_handshake(&mut buf, 0x12345678);
+        assert_eq!(buf.len(), 2 * Handshake::ENCODED_LEN);
+        assert_eq!(handshake.encoded_len(), Handshake::ENCODED_LEN);
+        assert_eq!(buf[..7], *handshake);
+        assert_eq!(buf[7..], *handshake);
+
+        let mut buf = BytesMut::new();
+        let basic_stat = BasicStat::new(1, 9513307);
+        let full_stat = FullStat::new(1, 9513307);
+        basic_stat.write_to(&mut buf);
+        full_stat.write_to(&mut buf);
+        write_basic_stat(&mut buf, 1, 9513307);
+        write_full_stat(&mut buf, 1, 9513307);
+        let expected = [
+            basic_stat.as_bytes(),
+            full_stat.as_bytes(),
+            basic_stat.as_bytes(),
+            full_stat.as_bytes(),
+        ]
+        .concat();
+        assert_eq!(buf, expected);
+        assert_eq!(
+            buf.len(),
+            2 * (BasicStat::ENCODED_LEN + FullStat::ENCODED_LEN)
+        );
+    }
+
+    #[test]
+    fn test_write_packet() {
+        let values = [
+            0,
+            1,
+            0xFF,
+            0x0F0F0F0F,
+            0xF0F0F0F0,
+            0x12345678,
+            9513307,
+            u32::MAX,
+        ];
+        for session_id in values {
+            assert_eq!(
+                *Handshake::new(session_id),
+                write_packet_buf::<7, 0>(PacketType::Handshake, session_id, [])
+            );
+            for token in values {
+                assert_eq!(
+                    *BasicStat::new(session_id, token),
+                    write_packet_buf::<11, 1>(PacketType::Stat, session_id, [token])
+                );
+                assert_eq!(
+                    *FullStat::new(session_id, token),
+                    write_packet_buf::<15, 2>(PacketType::Stat, session_id, [token, 0])
+                );
+            }
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig {
+            max_shrink_iters: 4096,
+            ..ProptestConfig::default()
+        })]
+
+        #[test]
+        fn prop_request_round_trip(session_id in any::<u32>(), token in any::<u32>()) {
+            let masked = session_id & SESSION_MASK;
+            prop_assert_eq!(
+                Request::parse(Handshake::new(session_id).as_bytes()),
+                Some(Request::Handshake { session_id: masked })
+            );
+            prop_assert_eq!(
+                Request::parse(BasicStat::new(session_id, token).as_bytes()),
+                Some(Request::BasicStat { session_id: masked, token })
+            );
+            prop_assert_eq!(
+                Request::parse(FullStat::new(session_id, token).as_bytes()),
+                Some(Request::FullStat { session_id: masked, token })
+            );
+        }
+
+        #[test]
+        fn prop_write_to_round_trip(session_id in any::<u32>(), token in any::<u32>()) {
+            let mut buf = BytesMut::new();
+            write_handshake(&mut buf, session_id);
+            write_basic_stat(&mut buf, session_id, token);
+            write_full_stat(&mut buf, session_id, token);
+
+            let masked = session_id & SESSION_MASK;
+            let (handshake, rest) = buf.split_at(Handshake::ENCODED_LEN);
+            let (basic_stat, full_stat) = rest.split_at(BasicStat::ENCODED_LEN);
+            prop_assert_eq!(
+                Request::parse(handshake),
+                Some(Request::Handshake { session_id: masked })
+            );
+            prop_assert_eq!(
+                Request::parse(basic_stat),
+                Some(Request::BasicStat { session_id: masked, token })
+            );
+            prop_assert_eq!(
+                Request::parse(full_stat),
+                Some(Request::FullStat { session_id: masked, token })
+            );
+        }
+
+        #[test]
+        fn prop_parsers_never_panic(packet in proptest::collection::vec(any::<u8>(), 0..32)) {
+            Request::parse(&packet);
+            if let Some((_, payload)) = ResponseHeader::parse(&packet) {
+                prop_assert_eq!(payload.len(), packet.len() - crate::RESPONSE_HEADER_SIZE);
+            }
+        }
+
+        #[test]
+        fn prop_response_header_round_trip(
+            session_id in any::<u32>(),
+            handshake in any::<bool>(),
+            payload in proptest::collection::vec(any::<u8>(), 0..64),
+        ) {
+            let packet_type = if handshake { PacketType::Handshake } else { PacketType::Stat };
+            let response = write_response(packet_type, session_id, &payload);
+
+            prop_assert_eq!(
+                ResponseHeader::parse(&response),
+                Some((ResponseHeader { packet_type, session_id }, &payload[..]))
+            );
+        }
+    }
+}
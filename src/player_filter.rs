@@ -0,0 +1,282 @@
+//! Filtering of the NPCs and bots in player lists.
+//!
+//! Servers with NPC or shop plugins list the names of their NPCs with the
+//! players. A [`PlayerFilter`] recognizes them by their names, and removes
+//! them from full statuses:
+//!
+//! ```rust
+//! # use minecraft_server_query::{player_filter::PlayerFilter, FullStat};
+//! # let mut stat = FullStat {
+//! #     hostname: "".into(), gametype: "".into(), game_id: "".into(), version: "".into(),
+//! #     plugins: "".into(), map: "".into(), numplayers: 4, maxplayers: 20,
+//! #     hostport: 25565, hostip: "".into(),
+//! #     player_list: vec!["AldanTanneo".into(), "CIT-1f3a".into(), "ShopKeeper".into(), "Notch".into()],
+//! # };
+//! let filter = PlayerFilter::new()
+//!     .prefix("CIT-")
+//!     .deny(["ShopKeeper"])
+//!     .adjust_numplayers(true);
+//!
+//! let removed = filter.apply(&mut stat);
+//! assert_eq!(removed, ["CIT-1f3a", "ShopKeeper"]);
+//! assert_eq!(stat.player_list, ["AldanTanneo", "Notch"]);
+//! assert_eq!(stat.numplayers, 2);
+//! ```
+//!
+//! The names removed are returned, so the raw player list can still be
+//! rebuilt, or the status can be left untouched with [`PlayerFilter::filtered`].
+//! Filtering after parsing with diagnostics records the number of names
+//! removed as a [`Warning::FilteredPlayers`].
+//!
+//! With the `regex` feature, names can also be matched by regular expressions.
+
+use std::{collections::HashSet, fmt, sync::Arc};
+
+use crate::{
+    diagnostics::{Diagnostics, Warning},
+    FullStat, StatString,
+};
+
+/// A predicate on player names
+type Predicate = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Rules recognizing the names of NPCs and bots in player lists.
+///
+/// A name is filtered if any rule matches it. By default, the number of
+/// players of filtered statuses is left as sent by the server.
+#[derive(Clone, Default)]
+pub struct PlayerFilter {
+    prefixes: Vec<String>,
+    suffixes: Vec<String>,
+    /// Lowercase denied names
+    denied: HashSet<String>,
+    predicates: Vec<Predicate>,
+    #[cfg(feature = "regex")]
+    regexes: Vec<regex::Regex>,
+    adjust_numplayers: bool,
+}
+
+impl fmt::Debug for PlayerFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("PlayerFilter");
+        debug
+            .field("prefixes", &self.prefixes)
+            .field("suffixes", &self.suffixes)
+            .field("denied", &self.denied)
+            .field("predicates", &self.predicates.len());
+        #[cfg(feature = "regex")]
+        debug.field("regexes", &self.regexes);
+        debug
+            .field("adjust_numplayers", &self.adjust_numplayers)
+            .finish()
+    }
+}
+
+impl PlayerFilter {
+    /// A filter without rules, filtering no names.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter the names starting with a prefix, like the `*` Bedrock players
+    /// are prefixed with by some proxies.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefixes.push(prefix.into());
+        self
+    }
+
+    /// Filter the names ending with a suffix.
+    pub fn suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.suffixes.push(suffix.into());
+        self
+    }
+
+    /// Filter the given names, matched case-insensitively like on Minecraft
+    /// servers.
+    pub fn deny<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.denied.extend(
+            names
+                .into_iter()
+                .map(|name| name.as_ref().to_ascii_lowercase()),
+        );
+        self
+    }
+
+    /// Filter the names matching a predicate.
+    pub fn predicate(mut self, predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Filter the names matching a regular expression.
+    #[cfg(feature = "regex")]
+    #[cfg_attr(doc, doc(cfg(feature = "regex")))]
+    pub fn regex(mut self, regex: regex::Regex) -> Self {
+        self.regexes.push(regex);
+        self
+    }
+
+    /// Set the number of players of filtered statuses to the length of their
+    /// filtered player list, instead of the number sent by the server.
+    pub fn adjust_numplayers(mut self, adjust: bool) -> Self {
+        self.adjust_numplayers = adjust;
+        self
+    }
+
+    /// Whether a name is filtered.
+    pub fn is_filtered(&self, name: &str) -> bool {
+        self.prefixes
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+            || self
+                .suffixes
+                .iter()
+                .any(|suffix| name.ends_with(suffix.as_str()))
+            || (!self.denied.is_empty() && self.denied.contains(&name.to_ascii_lowercase()))
+            || self.predicates.iter().any(|predicate| predicate(name))
+            || self.is_filtered_by_regex(name)
+    }
+
+    #[cfg(feature = "regex")]
+    fn is_filtered_by_regex(&self, name: &str) -> bool {
+        self.regexes.iter().any(|regex| regex.is_match(name))
+    }
+
+    #[cfg(not(feature = "regex"))]
+    fn is_filtered_by_regex(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Remove the filtered names from the player list of a status, and
+    /// return them in the order of the list.
+    pub fn apply(&self, stat: &mut FullStat) -> Vec<StatString> {
+        let (kept, removed) = std::mem::take(&mut stat.player_list)
+            .into_iter()
+            .partition(|name| !self.is_filtered(name));
+        stat.player_list = kept;
+        if self.adjust_numplayers {
+            stat.numplayers = stat.player_list.len().try_into().unwrap_or(u32::MAX);
+        }
+        removed
+    }
+
+    /// Like [`apply`](Self::apply), recording the number of names removed in
+    /// the diagnostics of the status, if any.
+    pub fn apply_with_diagnostics(
+        &self,
+        stat: &mut FullStat,
+        diagnostics: &mut Diagnostics,
+    ) -> Vec<StatString> {
+        let removed = self.apply(stat);
+        if !removed.is_empty() {
+            diagnostics.push(Warning::FilteredPlayers {
+                count: removed.len(),
+            });
+        }
+        removed
+    }
+
+    /// A copy of a status without the filtered names.
+    pub fn filtered(&self, stat: &FullStat) -> FullStat {
+        let mut filtered = stat.clone();
+        self.apply(&mut filtered);
+        filtered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sample_stat;
+
+    fn stat(players: &[&str]) -> FullStat {
+        FullStat {
+            numplayers: players.len() as u32,
+            player_list: players.iter().map(|&name| name.into()).collect(),
+            ..sample_stat()
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let filter = PlayerFilter::new()
+            .prefix("*")
+            .suffix("_NPC")
+            .deny(["ShopKeeper", "banker"])
+            .predicate(|name| name.len() > 16);
+
+        for name in [
+            "*BedrockPlayer",
+            "Guard_NPC",
+            "shopkeeper",
+            "Banker",
+            "AMuchTooLongPlayerName",
+        ] {
+            assert!(filter.is_filtered(name), "{name}");
+        }
+        for name in ["AldanTanneo", "Not*Bedrock", "NPC_Guard", "Banker2", ""] {
+            assert!(!filter.is_filtered(name), "{name}");
+        }
+        assert!(!PlayerFilter::new().is_filtered("*Bedrock"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let filter = PlayerFilter::new().regex(regex::Regex::new("^CIT-[0-9a-f]{4}$").unwrap());
+        assert!(filter.is_filtered("CIT-1f3a"));
+        assert!(!filter.is_filtered("CIT-1f3a2"));
+        assert!(!filter.is_filtered("Citizen"));
+    }
+
+    #[test]
+    fn test_numplayers_adjustment() {
+        let raw = stat(&["AldanTanneo", "*Steve", "Notch", "*Alex"]);
+        let filter = PlayerFilter::new().prefix("*");
+
+        let mut filtered = raw.clone();
+        let removed = filter.apply(&mut filtered);
+        assert_eq!(removed, ["*Steve", "*Alex"]);
+        assert_eq!(filtered.player_list, ["AldanTanneo", "Notch"]);
+        assert_eq!(filtered.numplayers, 4);
+        assert_eq!(filter.filtered(&raw), filtered);
+
+        let filter = filter.adjust_numplayers(true);
+        let mut filtered = raw.clone();
+        filter.apply(&mut filtered);
+        assert_eq!(filtered.numplayers, 2);
+        assert_eq!(filter.filtered(&raw), filtered);
+        // The raw status is untouched
+        assert_eq!(raw.numplayers, 4);
+        assert_eq!(raw.player_list.len(), 4);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let raw = stat(&["AldanTanneo", "Guard_NPC", "Notch"]);
+        let (mut parsed, mut diagnostics) =
+            FullStat::from_payload_with_diagnostics(&raw.to_payload(), &Default::default())
+                .unwrap();
+        assert!(diagnostics.is_empty());
+
+        let filter = PlayerFilter::new().suffix("_NPC");
+        let removed = filter.apply_with_diagnostics(&mut parsed, &mut diagnostics);
+        assert_eq!(removed, ["Guard_NPC"]);
+        assert_eq!(
+            diagnostics.warnings(),
+            [Warning::FilteredPlayers { count: 1 }]
+        );
+        assert_eq!(
+            diagnostics.warnings()[0].to_string(),
+            "1 player names filtered out"
+        );
+
+        // Nothing is recorded when no name is filtered
+        filter.apply_with_diagnostics(&mut parsed, &mut diagnostics);
+        assert_eq!(diagnostics.warnings().len(), 1);
+    }
+}
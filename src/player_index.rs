@@ -0,0 +1,319 @@
+//! Index of the players of several servers, to find which server a player is on.
+//!
+//! A [`PlayerIndex`] is built from the full statuses of the servers, by name,
+//! collected with [`FromIterator`]. It is cheap enough to rebuild on every
+//! polling cycle, or it can be kept up to date server by server:
+//!
+//! ```rust
+//! # use minecraft_server_query::player_index::PlayerIndex;
+//! let mut index = PlayerIndex::new();
+//! index.set_players("lobby", ["AldanTanneo".to_string(), "Notch".to_string()]);
+//! index.set_players("survival", ["Dinnerbone".to_string(), "Notch".to_string()]);
+//!
+//! assert_eq!(index.find("dinnerbone"), ["survival"]);
+//! // Behind a proxy, a player can show up on several backends
+//! assert_eq!(index.find("Notch"), ["lobby", "survival"]);
+//! ```
+//!
+//! Player names are matched case-insensitively, like on Minecraft servers.
+
+use std::collections::BTreeMap;
+
+use crate::FullStat;
+
+/// Name of a server in a [`PlayerIndex`]
+pub type ServerLabel = String;
+
+/// Players of each server, the serialized form of a [`PlayerIndex`]
+type Servers = BTreeMap<ServerLabel, Vec<String>>;
+
+/// Servers a player is on, with the name of the player as sent by each of them
+type Player = BTreeMap<ServerLabel, String>;
+
+/// Index of the players of several servers.
+///
+/// With the `serde` feature, it is serialized as a map from server labels to
+/// their player lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Servers", into = "Servers")
+)]
+pub struct PlayerIndex {
+    servers: Servers,
+    /// Players by lowercase name
+    players: BTreeMap<String, Player>,
+}
+
+impl PlayerIndex {
+    /// An empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the players of a server from its full status, replacing the
+    /// players it had before.
+    pub fn update(&mut self, server: &str, stat: &FullStat) {
+        self.set_players(server, stat.player_list.iter().map(|name| name.to_string()));
+    }
+
+    /// Set the players of a server, replacing the players it had before.
+    /// Empty names are ignored.
+    pub fn set_players(&mut self, server: &str, players: impl IntoIterator<Item = String>) {
+        self.remove(server);
+        let mut names: Vec<String> = Vec::new();
+        for name in players.into_iter().filter(|name| !name.is_empty()) {
+            let player = self.players.entry(name.to_lowercase()).or_default();
+            if !player.contains_key(server) {
+                player.insert(server.to_string(), name.clone());
+                names.push(name);
+            }
+        }
+        self.servers.insert(server.to_string(), names);
+    }
+
+    /// Remove a server and its players from the index.
+    pub fn remove(&mut self, server: &str) {
+        let Some(names) = self.servers.remove(server) else {
+            return;
+        };
+        for name in names {
+            let key = name.to_lowercase();
+            if let Some(player) = self.players.get_mut(&key) {
+                player.remove(server);
+                if player.is_empty() {
+                    self.players.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Keep only the servers for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let removed: Vec<ServerLabel> = self
+            .servers
+            .keys()
+            .filter(|server| !keep(server))
+            .cloned()
+            .collect();
+        for server in removed {
+            self.remove(&server);
+        }
+    }
+
+    /// The servers the given player is on, in order, matching the name
+    /// case-insensitively.
+    pub fn find(&self, name: &str) -> Vec<&ServerLabel> {
+        self.players
+            .get(&name.to_lowercase())
+            .map(|player| player.keys().collect())
+            .unwrap_or_default()
+    }
+
+    /// The players of the given server, in the order of its player list, or
+    /// `None` if the server is not in the index.
+    pub fn players_on(&self, server: &str) -> Option<&[String]> {
+        self.servers.get(server).map(Vec::as_slice)
+    }
+
+    /// Every player of the index once, sorted case-insensitively, with the
+    /// servers they are on. Names are spelled as on the first of their servers.
+    pub fn all_players(&self) -> Vec<(&str, Vec<&ServerLabel>)> {
+        self.players
+            .values()
+            .filter_map(|player| {
+                let (_, name) = player.first_key_value()?;
+                Some((name.as_str(), player.keys().collect()))
+            })
+            .collect()
+    }
+
+    /// The servers of the index, in order.
+    pub fn servers(&self) -> impl Iterator<Item = &ServerLabel> {
+        self.servers.keys()
+    }
+
+    /// Number of distinct players in the index.
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Whether no player is in the index.
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// Update the index with an event of a fleet watcher: the players of a
+    /// server are updated when it is polled, and removed if it does not
+    /// answer or leaves the list.
+    ///
+    /// Servers are named after their [label](crate::fleet::Target::name).
+    #[cfg(feature = "fleet")]
+    #[cfg_attr(doc, doc(cfg(feature = "fleet")))]
+    pub fn record(&mut self, event: &crate::fleet::FleetEvent) {
+        use crate::fleet::FleetEvent;
+
+        match event {
+            FleetEvent::Poll {
+                target,
+                result: Ok(stat),
+                ..
+            } => self.update(&target.name(), stat),
+            FleetEvent::Poll { target, .. } => self.remove(&target.name()),
+            FleetEvent::Reloaded(targets) => {
+                let names: std::collections::BTreeSet<String> =
+                    targets.iter().map(|t| t.name()).collect();
+                self.retain(|server| names.contains(server));
+            }
+            FleetEvent::ReloadFailed(_) => {}
+        }
+    }
+}
+
+impl<'a, S: AsRef<str>> FromIterator<(S, &'a FullStat)> for PlayerIndex {
+    fn from_iter<I: IntoIterator<Item = (S, &'a FullStat)>>(iter: I) -> Self {
+        let mut index = Self::new();
+        for (server, stat) in iter {
+            index.update(server.as_ref(), stat);
+        }
+        index
+    }
+}
+
+impl From<Servers> for PlayerIndex {
+    fn from(servers: Servers) -> Self {
+        let mut index = Self::new();
+        for (server, players) in servers {
+            index.set_players(&server, players);
+        }
+        index
+    }
+}
+
+impl From<PlayerIndex> for Servers {
+    fn from(index: PlayerIndex) -> Self {
+        index.servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PlayerIndex;
+    use crate::{testing::sample_stat, FullStat};
+
+    fn stat(players: &[&str]) -> FullStat {
+        FullStat {
+            numplayers: players.len() as u32,
+            player_list: players.iter().map(|&name| name.into()).collect(),
+            ..sample_stat()
+        }
+    }
+
+    fn index() -> PlayerIndex {
+        PlayerIndex::from_iter([
+            ("lobby", &stat(&["AldanTanneo", "Notch", "jeb_"])),
+            ("survival", &stat(&["Dinnerbone", "notch"])),
+            ("creative", &stat(&["Jeb_", "Grumm", ""])),
+        ])
+    }
+
+    #[test]
+    fn test_find() {
+        let index = index();
+        assert_eq!(index.find("aldantanneo"), ["lobby"]);
+        assert_eq!(index.find("NOTCH"), ["lobby", "survival"]);
+        assert_eq!(index.find("jeb_"), ["creative", "lobby"]);
+        assert!(index.find("Herobrine").is_empty());
+        assert!(index.find("").is_empty());
+    }
+
+    #[test]
+    fn test_players_on() {
+        let index = index();
+        assert_eq!(
+            index.players_on("lobby").unwrap(),
+            ["AldanTanneo", "Notch", "jeb_"]
+        );
+        assert_eq!(index.players_on("creative").unwrap(), ["Jeb_", "Grumm"]);
+        assert_eq!(index.players_on("skyblock"), None);
+    }
+
+    #[test]
+    fn test_all_players() {
+        let index = index();
+        assert_eq!(index.len(), 5);
+        let players: Vec<(&str, Vec<&str>)> = index
+            .all_players()
+            .into_iter()
+            .map(|(name, servers)| (name, servers.into_iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            players,
+            [
+                ("AldanTanneo", vec!["lobby"]),
+                ("Dinnerbone", vec!["survival"]),
+                ("Grumm", vec!["creative"]),
+                ("Jeb_", vec!["creative", "lobby"]),
+                ("Notch", vec!["lobby", "survival"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_and_remove() {
+        let mut index = index();
+        index.update("lobby", &stat(&["AldanTanneo"]));
+        assert_eq!(index.find("Notch"), ["survival"]);
+        assert!(index.find("jeb_").iter().all(|s| *s == "creative"));
+
+        index.remove("survival");
+        assert!(index.find("Notch").is_empty());
+        assert_eq!(index.servers().collect::<Vec<_>>(), ["creative", "lobby"]);
+
+        index.retain(|server| server == "lobby");
+        assert_eq!(index.all_players().len(), 1);
+        index.remove("lobby");
+        assert!(index.is_empty());
+    }
+
+    #[cfg(feature = "fleet")]
+    #[test]
+    fn test_record_fleet_events() {
+        use crate::fleet::{FleetEvent, Target};
+
+        let lobby: Target = "10.0.0.1 lobby".parse().unwrap();
+        let unlabeled: Target = "10.0.0.2:25566".parse().unwrap();
+        let mut index = PlayerIndex::new();
+        for target in [&lobby, &unlabeled] {
+            index.record(&FleetEvent::Poll {
+                target: target.clone(),
+                result: Ok(stat(&["Notch"])),
+                rdns: None,
+            });
+        }
+        assert_eq!(index.find("notch"), ["10.0.0.2:25566", "lobby"]);
+
+        index.record(&FleetEvent::Poll {
+            target: unlabeled,
+            result: Err(std::io::ErrorKind::TimedOut.into()),
+            rdns: None,
+        });
+        assert_eq!(index.find("notch"), ["lobby"]);
+
+        index.record(&FleetEvent::Reloaded(Vec::new()));
+        assert!(index.is_empty());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_serde() {
+        let index = index();
+        let json = serde_json::to_string(&index).unwrap();
+        assert_eq!(
+            json,
+            r#"{"creative":["Jeb_","Grumm"],"lobby":["AldanTanneo","Notch","jeb_"],"survival":["Dinnerbone","notch"]}"#
+        );
+        assert_eq!(serde_json::from_str::<PlayerIndex>(&json).unwrap(), index);
+    }
+}
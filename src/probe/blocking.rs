@@ -0,0 +1,268 @@
+//! Blocking implementation of the protocol auto-detection.
+//!
+//! Concurrent attempts are run on scoped threads.
+
+use std::{
+    io,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use super::*;
+use crate::bedrock::blocking::BedrockClient;
+use crate::blocking::QueryClient;
+use crate::slp::blocking::PingClient;
+
+/// Find out which protocol a server answers, and get its status.
+///
+/// If no port is specified in the IP address, the default port of each
+/// protocol is used. Fails if no protocol answered.
+pub fn probe(ip: &str, options: &ProbeOptions) -> io::Result<ServerInfo> {
+    let target = Target::parse(ip)?;
+    let operation = options.operation(&target);
+
+    let results = if options.concurrent {
+        std::thread::scope(|s| {
+            let handles = options
+                .order
+                .iter()
+                .map(|&source| {
+                    let port = target.port(source, options);
+                    (
+                        source,
+                        port,
+                        s.spawn(move || attempt(target.host, port, source, options.timeout)),
+                    )
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|(source, port, handle)| {
+                    (source, port, handle.join().expect("Attempts do not panic"))
+                })
+                .collect()
+        })
+    } else {
+        let mut results = Vec::with_capacity(options.order.len());
+        for &source in &options.order {
+            let port = target.port(source, options);
+            let result = attempt(target.host, port, source, options.timeout);
+            let answered = result.is_ok();
+            results.push((source, port, result));
+            if answered {
+                break;
+            }
+        }
+        results
+    };
+
+    resolve(operation, results)
+}
+
+/// Try every protocol on its [well-known ports](KnownPortsOptions) of a
+/// host, at the same time, and get the status of every one which answered.
+///
+/// The host must not contain a port. Answers are returned with their port, in
+/// the order of the options: Query, then Server List Ping, then Bedrock. Fails
+/// if nothing answered.
+pub fn probe_known_ports(
+    host: &str,
+    options: &KnownPortsOptions,
+) -> io::Result<Vec<(u16, ServerInfo)>> {
+    let results = std::thread::scope(|s| {
+        let handles = options
+            .matrix()
+            .into_iter()
+            .map(|(source, port)| {
+                (
+                    source,
+                    port,
+                    s.spawn(move || attempt(host, port, source, options.timeout)),
+                )
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|(source, port, handle)| {
+                (source, port, handle.join().expect("Attempts do not panic"))
+            })
+            .collect()
+    });
+
+    resolve_known_ports(options.operation(host), results)
+}
+
+/// Request the status of a server with a single protocol.
+fn attempt(
+    host: &str,
+    port: u16,
+    source: Source,
+    timeout: Duration,
+) -> io::Result<(Answer, Duration)> {
+    let timeout = Some(timeout);
+    let start = Instant::now();
+
+    let answer = match source {
+        Source::Query => {
+            let client = QueryClient::new_with_socket_address(
+                host,
+                port,
+                (Ipv4Addr::UNSPECIFIED, 0),
+                timeout,
+            )?;
+            let token = client.handshake()?;
+            Answer::Query(client.full_stat(token)?)
+        }
+        Source::Slp => Answer::Slp(PingClient::new_with_timeout(host, port, timeout)?.status()?),
+        Source::Bedrock => {
+            let client = BedrockClient::new_with_socket_address(
+                host,
+                port,
+                (Ipv4Addr::UNSPECIFIED, 0),
+                timeout,
+            )?;
+            Answer::Bedrock(client.ping()?.stat()?)
+        }
+    };
+
+    Ok((answer, start.elapsed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::bedrock::tests::{spawn_stub, BDS_PONG};
+    use crate::testing::{MockQueryServer, MockSlpServer};
+
+    fn options(query_port: u16, bedrock_port: u16) -> ProbeOptions {
+        ProbeOptions {
+            timeout: Duration::from_millis(200),
+            query_port: Some(query_port),
+            bedrock_port: Some(bedrock_port),
+            ..ProbeOptions::default()
+        }
+    }
+
+    /// A loopback UDP port on which nothing answers
+    fn silent_port() -> (std::net::UdpSocket, u16) {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        (socket, port)
+    }
+
+    #[test]
+    fn test_query_first() {
+        let query = MockQueryServer::new().unwrap();
+        let slp = MockSlpServer::new().unwrap();
+        let (_silent, bedrock_port) = silent_port();
+
+        let info = probe(
+            &slp.addr().to_string(),
+            &options(query.addr().port(), bedrock_port),
+        )
+        .unwrap();
+        assert_eq!(info.source, Source::Query);
+        assert_eq!(info.motd, "A Minecraft Server");
+        assert_eq!((info.players, info.max_players), (2, 20));
+        assert_eq!(info.attempts.len(), 1);
+    }
+
+    #[test]
+    fn test_slp_fallback() {
+        let slp = MockSlpServer::new().unwrap();
+        let (_silent, silent) = silent_port();
+
+        let info = probe(&slp.addr().to_string(), &options(silent, silent)).unwrap();
+        assert_eq!(info.source, Source::Slp);
+        assert_eq!(info.version, "1.20.1");
+        assert_eq!(info.attempts[0].source, Source::Query);
+        assert!(matches!(info.attempts[0].outcome, Outcome::Failed { .. }));
+        assert_eq!(info.attempts.len(), 2);
+    }
+
+    #[test]
+    fn test_bedrock_fallback() {
+        let bedrock = spawn_stub(BDS_PONG);
+        let (_silent, silent) = silent_port();
+
+        let info = probe(
+            &format!("127.0.0.1:{silent}"),
+            &options(silent, bedrock.port()),
+        )
+        .unwrap();
+        assert_eq!(info.source, Source::Bedrock);
+        assert_eq!(info.motd, "Dedicated Server");
+        assert_eq!(info.attempts.len(), 3);
+    }
+
+    #[test]
+    fn test_concurrent() {
+        let slp = MockSlpServer::new().unwrap();
+        let bedrock = spawn_stub(BDS_PONG);
+        let (_silent, silent) = silent_port();
+
+        let info = probe(
+            &slp.addr().to_string(),
+            &ProbeOptions {
+                order: vec![Source::Bedrock, Source::Query, Source::Slp],
+                concurrent: true,
+                ..options(silent, bedrock.port())
+            },
+        )
+        .unwrap();
+        assert_eq!(info.source, Source::Bedrock);
+        // Partial answers are kept
+        let outcomes = info
+            .attempts
+            .iter()
+            .map(|a| (a.source, matches!(a.outcome, Outcome::Answered { .. })))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                (Source::Bedrock, true),
+                (Source::Query, false),
+                (Source::Slp, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_known_ports() {
+        let query = MockQueryServer::new().unwrap();
+        let bedrock = spawn_stub(BDS_PONG);
+        let (_silent, silent) = silent_port();
+
+        let options = KnownPortsOptions {
+            query_ports: vec![query.addr().port()],
+            slp_ports: vec![silent],
+            bedrock_ports: vec![bedrock.port()],
+            timeout: Duration::from_millis(200),
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        };
+        let infos = probe_known_ports("127.0.0.1", &options).unwrap();
+        let answered = infos
+            .iter()
+            .map(|(port, info)| (*port, info.source))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            answered,
+            [
+                (query.addr().port(), Source::Query),
+                (bedrock.port(), Source::Bedrock)
+            ]
+        );
+        assert_eq!(infos[0].1.motd, "A Minecraft Server");
+        assert_eq!(infos[1].1.motd, "Dedicated Server");
+    }
+
+    #[test]
+    fn test_no_answer() {
+        let (_silent, silent) = silent_port();
+        let err = probe(&format!("127.0.0.1:{silent}"), &options(silent, silent)).unwrap_err();
+        assert!(err.to_string().contains("No protocol answered"));
+    }
+}
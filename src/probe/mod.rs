@@ -0,0 +1,504 @@
+//! Protocol auto-detection.
+//!
+//! A server address given by a user may point to a Java server with or
+//! without query enabled, or to a Bedrock server. [`probe`](blocking::probe)
+//! tries the Query protocol, the Server List Ping and the Bedrock ping, in the
+//! [configured order](ProbeOptions::order), and returns the common status
+//! fields from the first protocol which answered.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::probe::{self, ProbeOptions};
+//! let info = probe::blocking::probe("127.0.0.1", &ProbeOptions::default())?;
+//! println!(
+//!     "{} ({:?}): {}/{} players",
+//!     info.motd, info.source, info.players, info.max_players
+//! );
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! When only a host is known, [`probe_known_ports`](blocking::probe_known_ports)
+//! tries every protocol on its [well-known ports](KnownPortsOptions) at the
+//! same time, and returns every answer instead of the first one:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::probe::{self, KnownPortsOptions};
+//! for (port, info) in probe::blocking::probe_known_ports("127.0.0.1", &KnownPortsOptions::default())? {
+//!     println!("{:?} on port {}: {}", info.source, port, info.motd);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{io, time::Duration};
+
+use crate::bedrock::BedrockStat;
+use crate::report::Outcome;
+use crate::slp::SlpStatus;
+use crate::{custom_io_error, FullStat, Operation, Timings, DEFAULT_PORT, DEFAULT_TIMEOUT};
+
+/// A protocol tried by a probe
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Source {
+    /// The UDP Query protocol, with a full status request
+    Query,
+    /// The TCP Server List Ping protocol
+    Slp,
+    /// The Bedrock RakNet unconnected ping
+    Bedrock,
+}
+
+/// Configuration of a probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeOptions {
+    /// Protocols to try, in order of preference
+    pub order: Vec<Source>,
+    /// Timeout of every network operation of an attempt
+    pub timeout: Duration,
+    /// Whether all the attempts are run at the same time. Otherwise, they are
+    /// run one after the other, until a protocol answers.
+    pub concurrent: bool,
+    /// Port used for the Query protocol, instead of the port of the address
+    pub query_port: Option<u16>,
+    /// Port used for the Bedrock ping, instead of the port of the address.
+    ///
+    /// If the address has no port either, the [default Bedrock port](crate::bedrock::DEFAULT_PORT) is used.
+    pub bedrock_port: Option<u16>,
+    /// Instruments recording the probes, with an event for every attempt
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+    pub telemetry: Option<crate::otel::Telemetry>,
+}
+
+impl Default for ProbeOptions {
+    /// Try the Query protocol, then the Server List Ping, then the Bedrock
+    /// ping, one after the other, with the [default timeout](DEFAULT_TIMEOUT).
+    fn default() -> Self {
+        Self {
+            order: vec![Source::Query, Source::Slp, Source::Bedrock],
+            timeout: DEFAULT_TIMEOUT,
+            concurrent: false,
+            query_port: None,
+            bedrock_port: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+}
+
+impl ProbeOptions {
+    /// Start recording a probe of a server, if the options have telemetry.
+    fn operation(&self, target: &Target) -> Operation {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(telemetry) = &self.telemetry {
+            return Operation::start(telemetry, "probe", target.host, target.port);
+        }
+        #[cfg(not(feature = "opentelemetry"))]
+        let _ = target;
+        Operation::none()
+    }
+}
+
+/// Ports tried by [`probe_known_ports`](blocking::probe_known_ports)
+///
+/// By default, the Query protocol is tried on port 25565 and the Bedrock ping
+/// on port 19132. The Server List Ping is not tried unless a port is given,
+/// usually 25565.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPortsOptions {
+    /// Ports tried with the Query protocol, including any custom `query.port`
+    pub query_ports: Vec<u16>,
+    /// Ports tried with the Server List Ping
+    pub slp_ports: Vec<u16>,
+    /// Ports tried with the Bedrock ping
+    pub bedrock_ports: Vec<u16>,
+    /// Timeout of every network operation of an attempt
+    pub timeout: Duration,
+    /// Instruments recording the probes, with an event for every attempt
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+    pub telemetry: Option<crate::otel::Telemetry>,
+}
+
+impl Default for KnownPortsOptions {
+    fn default() -> Self {
+        Self {
+            query_ports: vec![DEFAULT_PORT],
+            slp_ports: Vec::new(),
+            bedrock_ports: vec![crate::bedrock::DEFAULT_PORT],
+            timeout: DEFAULT_TIMEOUT,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+}
+
+impl KnownPortsOptions {
+    /// Also try the Server List Ping on the default Java port.
+    pub fn with_slp(mut self) -> Self {
+        self.slp_ports.push(DEFAULT_PORT);
+        self
+    }
+
+    /// Also try the Query protocol on the given port.
+    pub fn with_query_port(mut self, port: u16) -> Self {
+        self.query_ports.push(port);
+        self
+    }
+
+    /// Start recording a probe of a host, if the options have telemetry.
+    fn operation(&self, host: &str) -> Operation {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(telemetry) = &self.telemetry {
+            return Operation::start(telemetry, "probe_known_ports", host, None);
+        }
+        #[cfg(not(feature = "opentelemetry"))]
+        let _ = host;
+        Operation::none()
+    }
+
+    /// Every protocol and port to try, without duplicates.
+    fn matrix(&self) -> Vec<(Source, u16)> {
+        let mut matrix = Vec::new();
+        for (source, ports) in [
+            (Source::Query, &self.query_ports),
+            (Source::Slp, &self.slp_ports),
+            (Source::Bedrock, &self.bedrock_ports),
+        ] {
+            for &port in ports {
+                if !matrix.contains(&(source, port)) {
+                    matrix.push((source, port));
+                }
+            }
+        }
+        matrix
+    }
+}
+
+/// Outcome of the attempt of a single protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Attempt {
+    /// The protocol tried
+    pub source: Source,
+    /// Whether the server answered, and how fast
+    pub outcome: Outcome,
+}
+
+/// Status information on a server, common to all protocols
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServerInfo {
+    /// The protocol the information comes from
+    pub source: Source,
+    /// Message of the day, which may contain formatting codes
+    pub motd: String,
+    /// Number of players online
+    pub players: u32,
+    /// Max number of players
+    pub max_players: u32,
+    /// Game version, which may contain the name of the server software
+    pub version: String,
+    /// Time taken by the request of the source protocol
+    pub latency: Duration,
+    /// Every attempt made, in the configured order. With sequential attempts,
+    /// the protocols after the source are not tried.
+    pub attempts: Vec<Attempt>,
+}
+
+/// Status answered by a protocol
+#[derive(Debug)]
+enum Answer {
+    Query(FullStat),
+    Slp(SlpStatus),
+    Bedrock(BedrockStat),
+}
+
+/// Answer of a protocol and its latency, or the error of the attempt
+type AttemptResult = io::Result<(Answer, Duration)>;
+
+/// A server address, with an optional port
+#[derive(Debug, Clone, Copy)]
+struct Target<'a> {
+    host: &'a str,
+    port: Option<u16>,
+}
+
+impl<'a> Target<'a> {
+    fn parse(ip: &'a str) -> io::Result<Self> {
+        match ip.split_once(':') {
+            Some((host, port)) => Ok(Self {
+                host,
+                port: Some(
+                    port.parse::<u16>()
+                        .map_err(|_| custom_io_error("Invalid port in IP address"))?,
+                ),
+            }),
+            None => Ok(Self {
+                host: ip,
+                port: None,
+            }),
+        }
+    }
+
+    /// Port to use for the given protocol.
+    fn port(&self, source: Source, options: &ProbeOptions) -> u16 {
+        match source {
+            Source::Query => options.query_port.or(self.port).unwrap_or(DEFAULT_PORT),
+            Source::Slp => self.port.unwrap_or(DEFAULT_PORT),
+            Source::Bedrock => options
+                .bedrock_port
+                .or(self.port)
+                .unwrap_or(crate::bedrock::DEFAULT_PORT),
+        }
+    }
+}
+
+/// Saturating conversion of the player counts of a Server List Ping.
+fn player_count(count: i64) -> u32 {
+    count.clamp(0, u32::MAX as i64) as u32
+}
+
+/// Build the server information from the results of the attempts on the
+/// given ports, in the configured order, and record the probe. Fails if no
+/// protocol answered.
+fn resolve(
+    operation: Operation,
+    results: Vec<(Source, u16, AttemptResult)>,
+) -> io::Result<ServerInfo> {
+    let res = server_info_of(&operation, results);
+    operation.finish(&res, &Timings::default());
+    res
+}
+
+/// Build the server information from the results of the attempts, in the
+/// configured order. Fails if no protocol answered.
+fn server_info_of(
+    operation: &Operation,
+    results: Vec<(Source, u16, AttemptResult)>,
+) -> io::Result<ServerInfo> {
+    let mut attempts = Vec::with_capacity(results.len());
+    let mut answer = None;
+    let mut errors = Vec::new();
+
+    for (source, port, result) in results {
+        record_attempt(operation, source, port, &result);
+        let outcome = match result {
+            Ok((res, latency)) => {
+                if answer.is_none() {
+                    answer = Some((res, latency));
+                }
+                Outcome::Answered { latency }
+            }
+            Err(e) => {
+                errors.push(format!("{source:?}: {e}"));
+                Outcome::Failed {
+                    error: e.to_string(),
+                }
+            }
+        };
+        attempts.push(Attempt { source, outcome });
+    }
+
+    let (answer, latency) = answer.ok_or_else(|| {
+        custom_io_error(&format!("No protocol answered ({}).", errors.join(", ")))
+    })?;
+
+    Ok(server_info(answer, latency, attempts))
+}
+
+/// Build the server information from the results of the attempts on every
+/// known port, keeping every answer, and record the probe. Fails if nothing
+/// answered.
+fn resolve_known_ports(
+    operation: Operation,
+    results: Vec<(Source, u16, AttemptResult)>,
+) -> io::Result<Vec<(u16, ServerInfo)>> {
+    let mut infos = Vec::new();
+    let mut errors = Vec::new();
+
+    for (source, port, result) in results {
+        record_attempt(&operation, source, port, &result);
+        match result {
+            Ok((answer, latency)) => {
+                let attempts = vec![Attempt {
+                    source,
+                    outcome: Outcome::Answered { latency },
+                }];
+                infos.push((port, server_info(answer, latency, attempts)));
+            }
+            Err(e) => errors.push(format!("{source:?} on port {port}: {e}")),
+        }
+    }
+
+    let res = if infos.is_empty() {
+        Err(custom_io_error(&format!(
+            "No protocol answered ({}).",
+            errors.join(", ")
+        )))
+    } else {
+        Ok(infos)
+    };
+    operation.finish(&res, &Timings::default());
+    res
+}
+
+/// Record the result of an attempt in the span of the probe.
+fn record_attempt(operation: &Operation, source: Source, port: u16, result: &AttemptResult) {
+    #[cfg(feature = "opentelemetry")]
+    operation.attempt(source, port, result.as_ref().map(|(_, latency)| *latency));
+    #[cfg(not(feature = "opentelemetry"))]
+    let _ = (operation, source, port, result);
+}
+
+/// Common status fields of the answer of a protocol.
+fn server_info(answer: Answer, latency: Duration, attempts: Vec<Attempt>) -> ServerInfo {
+    match answer {
+        Answer::Query(stat) => ServerInfo {
+            source: Source::Query,
+            motd: stat.hostname.to_string(),
+            players: stat.numplayers,
+            max_players: stat.maxplayers,
+            version: stat.version.to_string(),
+            latency,
+            attempts,
+        },
+        Answer::Slp(status) => ServerInfo {
+            source: Source::Slp,
+            motd: status.description.to_plain(),
+            players: player_count(status.players.online),
+            max_players: player_count(status.players.max),
+            version: status.version.name,
+            latency,
+            attempts,
+        },
+        Answer::Bedrock(stat) => ServerInfo {
+            source: Source::Bedrock,
+            motd: stat.motd,
+            players: stat.numplayers,
+            max_players: stat.maxplayers,
+            version: stat.version,
+            latency,
+            attempts,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_ports() {
+        let options = ProbeOptions {
+            query_port: Some(25575),
+            ..ProbeOptions::default()
+        };
+
+        let target = Target::parse("localhost").unwrap();
+        assert_eq!(target.port(Source::Query, &options), 25575);
+        assert_eq!(target.port(Source::Slp, &options), DEFAULT_PORT);
+        assert_eq!(
+            target.port(Source::Bedrock, &options),
+            crate::bedrock::DEFAULT_PORT
+        );
+
+        let target = Target::parse("localhost:1234").unwrap();
+        assert_eq!(target.port(Source::Slp, &options), 1234);
+        assert_eq!(target.port(Source::Bedrock, &options), 1234);
+        assert!(Target::parse("localhost:port").is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let results = vec![
+            (Source::Query, 25565, Err(io::ErrorKind::TimedOut.into())),
+            (
+                Source::Slp,
+                25565,
+                Ok((
+                    Answer::Slp(crate::testing::sample_status()),
+                    Duration::from_millis(3),
+                )),
+            ),
+        ];
+
+        let info = resolve(Operation::none(), results).unwrap();
+        assert_eq!(info.source, Source::Slp);
+        assert_eq!(info.players, 2);
+        assert_eq!(info.latency, Duration::from_millis(3));
+        assert!(matches!(info.attempts[0].outcome, Outcome::Failed { .. }));
+
+        let err = resolve(
+            Operation::none(),
+            vec![(Source::Bedrock, 19132, Err(io::ErrorKind::TimedOut.into()))],
+        );
+        assert!(err.is_err());
+        assert!(resolve(Operation::none(), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_known_ports_matrix() {
+        assert_eq!(
+            KnownPortsOptions::default().matrix(),
+            [(Source::Query, 25565), (Source::Bedrock, 19132)]
+        );
+
+        let options = KnownPortsOptions::default()
+            .with_query_port(25575)
+            .with_query_port(25565)
+            .with_slp();
+        assert_eq!(
+            options.matrix(),
+            [
+                (Source::Query, 25565),
+                (Source::Query, 25575),
+                (Source::Slp, 25565),
+                (Source::Bedrock, 19132)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_known_ports() {
+        let results = vec![
+            (Source::Query, 25565, Err(io::ErrorKind::TimedOut.into())),
+            (
+                Source::Slp,
+                25565,
+                Ok((
+                    Answer::Slp(crate::testing::sample_status()),
+                    Duration::from_millis(3),
+                )),
+            ),
+            (
+                Source::Query,
+                25575,
+                Ok((
+                    Answer::Query(crate::testing::sample_stat()),
+                    Duration::from_millis(2),
+                )),
+            ),
+        ];
+
+        let infos = resolve_known_ports(Operation::none(), results).unwrap();
+        let answered = infos
+            .iter()
+            .map(|(port, info)| (*port, info.source))
+            .collect::<Vec<_>>();
+        assert_eq!(answered, [(25565, Source::Slp), (25575, Source::Query)]);
+        assert_eq!(infos[1].1.attempts.len(), 1);
+
+        let err = resolve_known_ports(
+            Operation::none(),
+            vec![(Source::Bedrock, 19132, Err(io::ErrorKind::TimedOut.into()))],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Bedrock on port 19132"), "{err}");
+        assert!(resolve_known_ports(Operation::none(), Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the protocol auto-detection.
+//!
+//! Concurrent attempts are run on spawned tasks.
+
+use std::{
+    io,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use super::*;
+use crate::bedrock::tokio::BedrockClient;
+use crate::slp::tokio::PingClient;
+use crate::tokio::QueryClient;
+
+/// Find out which protocol a server answers, and get its status.
+///
+/// If no port is specified in the IP address, the default port of each
+/// protocol is used. Fails if no protocol answered.
+pub async fn probe(ip: &str, options: &ProbeOptions) -> io::Result<ServerInfo> {
+    let target = Target::parse(ip)?;
+    let operation = options.operation(&target);
+
+    let mut results = Vec::with_capacity(options.order.len());
+    if options.concurrent {
+        let handles = options
+            .order
+            .iter()
+            .map(|&source| {
+                let host = target.host.to_string();
+                let port = target.port(source, options);
+                let timeout = options.timeout;
+                (
+                    source,
+                    port,
+                    ::tokio::spawn(async move { attempt(&host, port, source, timeout).await }),
+                )
+            })
+            .collect::<Vec<_>>();
+        for (source, port, handle) in handles {
+            let result = handle
+                .await
+                .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Interrupted, e)));
+            results.push((source, port, result));
+        }
+    } else {
+        for &source in &options.order {
+            let port = target.port(source, options);
+            let result = attempt(target.host, port, source, options.timeout).await;
+            let answered = result.is_ok();
+            results.push((source, port, result));
+            if answered {
+                break;
+            }
+        }
+    }
+
+    resolve(operation, results)
+}
+
+/// Try every protocol on its [well-known ports](KnownPortsOptions) of a
+/// host, at the same time, and get the status of every one which answered.
+///
+/// The host must not contain a port. Answers are returned with their port, in
+/// the order of the options: Query, then Server List Ping, then Bedrock. Fails
+/// if nothing answered.
+pub async fn probe_known_ports(
+    host: &str,
+    options: &KnownPortsOptions,
+) -> io::Result<Vec<(u16, ServerInfo)>> {
+    let operation = options.operation(host);
+    let handles = options
+        .matrix()
+        .into_iter()
+        .map(|(source, port)| {
+            let host = host.to_string();
+            let timeout = options.timeout;
+            (
+                source,
+                port,
+                ::tokio::spawn(async move { attempt(&host, port, source, timeout).await }),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut results = Vec::with_capacity(handles.len());
+    for (source, port, handle) in handles {
+        let result = handle
+            .await
+            .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Interrupted, e)));
+        results.push((source, port, result));
+    }
+
+    resolve_known_ports(operation, results)
+}
+
+/// Request the status of a server with a single protocol.
+async fn attempt(
+    host: &str,
+    port: u16,
+    source: Source,
+    timeout: Duration,
+) -> io::Result<(Answer, Duration)> {
+    let timeout = Some(timeout);
+    let start = Instant::now();
+
+    let answer = match source {
+        Source::Query => {
+            let client = QueryClient::new_with_socket_address(
+                host,
+                port,
+                (Ipv4Addr::UNSPECIFIED, 0),
+                timeout,
+            )
+            .await?;
+            let token = client.handshake().await?;
+            Answer::Query(client.full_stat(token).await?)
+        }
+        Source::Slp => Answer::Slp(
+            PingClient::new_with_timeout(host, port, timeout)?
+                .status()
+                .await?,
+        ),
+        Source::Bedrock => {
+            let client = BedrockClient::new_with_socket_address(
+                host,
+                port,
+                (Ipv4Addr::UNSPECIFIED, 0),
+                timeout,
+            )
+            .await?;
+            Answer::Bedrock(client.ping().await?.stat()?)
+        }
+    };
+
+    Ok((answer, start.elapsed()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{probe, probe_known_ports};
+    use crate::bedrock::tests::{spawn_stub, BDS_PONG};
+    use crate::probe::{KnownPortsOptions, ProbeOptions, Source};
+    use crate::report::Outcome;
+    use crate::testing::{MockQueryServer, MockSlpServer};
+
+    #[tokio::test]
+    async fn test_slp_fallback() {
+        let slp = MockSlpServer::new().unwrap();
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent = silent.local_addr().unwrap().port();
+
+        let options = ProbeOptions {
+            timeout: Duration::from_millis(200),
+            query_port: Some(silent),
+            ..ProbeOptions::default()
+        };
+        let info = probe(&slp.addr().to_string(), &options).await.unwrap();
+        assert_eq!(info.source, Source::Slp);
+        assert_eq!(info.attempts.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent() {
+        let query = MockQueryServer::new().unwrap();
+        let slp = MockSlpServer::new().unwrap();
+        let bedrock = spawn_stub(BDS_PONG);
+
+        let options = ProbeOptions {
+            concurrent: true,
+            query_port: Some(query.addr().port()),
+            bedrock_port: Some(bedrock.port()),
+            ..ProbeOptions::default()
+        };
+        let info = probe(&slp.addr().to_string(), &options).await.unwrap();
+        assert_eq!(info.source, Source::Query);
+        assert!(info
+            .attempts
+            .iter()
+            .all(|a| matches!(a.outcome, Outcome::Answered { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_known_ports() {
+        let slp = MockSlpServer::new().unwrap();
+        let bedrock = spawn_stub(BDS_PONG);
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent = silent.local_addr().unwrap().port();
+
+        let options = KnownPortsOptions {
+            query_ports: vec![silent],
+            slp_ports: vec![slp.addr().port()],
+            bedrock_ports: vec![bedrock.port()],
+            timeout: Duration::from_millis(200),
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        };
+        let infos = probe_known_ports("127.0.0.1", &options).await.unwrap();
+        let sources = infos
+            .iter()
+            .map(|(_, info)| info.source)
+            .collect::<Vec<_>>();
+        assert_eq!(sources, [Source::Slp, Source::Bedrock]);
+        assert_eq!(infos[0].0, slp.addr().port());
+    }
+}
@@ -0,0 +1,469 @@
+//! Blocking implementation of the Query proxy.
+//!
+//! Uses [std::net::UdpSocket] for sending and receiving UDP data.
+
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use super::*;
+
+/// A blocking Query proxy using the [`std`] networking primitives.
+///
+/// Requests are handled one at a time: clients wait while a status request
+/// is forwarded to the backends, and while the backends are health checked.
+#[derive(Debug)]
+pub struct Proxy {
+    socket: UdpSocket,
+    upstreams: Vec<UdpSocket>,
+    clients: ClientLeg,
+    backends: Backends,
+    filter: Option<Box<dyn ResponseFilter>>,
+}
+
+impl Proxy {
+    /// Bind a new proxy to the given address, forwarding status requests to the given backend.
+    ///
+    /// The default [upstream timeout](DEFAULT_UPSTREAM_TIMEOUT) and [limits](Limits) are used.
+    pub fn bind(addr: impl ToSocketAddrs, backend: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            upstreams: vec![upstream(backend)?],
+            clients: ClientLeg::new(),
+            backends: Backends::new(1, Strategy::RoundRobin, None),
+            filter: None,
+        })
+    }
+
+    /// Bind a new proxy to the given address, balancing status requests
+    /// across the given backends with the given strategy.
+    ///
+    /// The default [upstream timeout](DEFAULT_UPSTREAM_TIMEOUT),
+    /// [health check interval](DEFAULT_HEALTH_CHECK_INTERVAL) and
+    /// [limits](Limits) are used.
+    pub fn bind_balanced(
+        addr: impl ToSocketAddrs,
+        backends: &[SocketAddr],
+        strategy: Strategy,
+    ) -> io::Result<Self> {
+        if backends.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No backend to balance requests across.",
+            ));
+        }
+
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            upstreams: backends.iter().map(upstream).collect::<io::Result<_>>()?,
+            clients: ClientLeg::new(),
+            backends: Backends::new(
+                backends.len(),
+                strategy,
+                Some(DEFAULT_HEALTH_CHECK_INTERVAL),
+            ),
+            filter: None,
+        })
+    }
+
+    /// The local address the proxy is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Set the timeout of the exchanges with the backends.
+    pub fn set_upstream_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        for upstream in self.upstreams.iter() {
+            upstream.set_read_timeout(timeout)?;
+        }
+        Ok(())
+    }
+
+    /// Set the interval between two health checks of the backends, or
+    /// disable them with `None`.
+    ///
+    /// Health checks are disabled by default on proxies with a single
+    /// backend, created with [`bind`](Self::bind).
+    pub fn set_health_check_interval(&mut self, interval: Option<Duration>) -> io::Result<()> {
+        self.backends.health_check_interval = interval;
+        if interval.is_none() {
+            self.socket.set_read_timeout(None)?;
+        }
+        Ok(())
+    }
+
+    /// Set the limits protecting the proxy against amplification abuse.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.clients.set_limits(limits);
+    }
+
+    /// Set the filter rewriting the responses relayed to clients.
+    pub fn set_filter(&mut self, filter: impl ResponseFilter + 'static) {
+        self.filter = Some(Box::new(filter));
+    }
+
+    /// Receive and answer a single request from a client, after checking the
+    /// health of the backends due for it.
+    ///
+    /// Failed exchanges with the backends are not reported: the request is
+    /// dropped, as if the backends did not answer. With health checks, this
+    /// returns without receiving any request when the next check is due.
+    pub fn serve_one(&mut self) -> io::Result<()> {
+        self.check_health();
+        if let Some(next_check) = self.backends.next_check(Instant::now()) {
+            // A zero read timeout is an error
+            let next_check = next_check.max(Duration::from_millis(1));
+            self.socket.set_read_timeout(Some(next_check))?;
+        }
+
+        let mut buf = [0; 16];
+        let (received, source) = match self.socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            // ICMP port unreachable errors from previous responses are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) && self.backends.health_check_interval.is_some() =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e),
+        };
+
+        let response = match self
+            .clients
+            .receive(&buf[..received], source, Instant::now())
+        {
+            Some(Incoming::Respond(response)) => Some(response),
+            Some(Incoming::Forward(forward)) => self.forward(forward, source),
+            None => None,
+        };
+
+        if let Some(response) = response {
+            self.socket.send_to(&response, source)?;
+        }
+        Ok(())
+    }
+
+    /// Answer requests until an IO error occurs on the client socket.
+    pub fn serve(&mut self) -> io::Result<()> {
+        loop {
+            self.serve_one()?;
+        }
+    }
+
+    /// Check the health of the backends due for it, with a handshake.
+    fn check_health(&mut self) {
+        for backend in self.backends.due(Instant::now()) {
+            let _ = self.handshake(backend, HEALTH_CHECK_SESSION);
+        }
+    }
+
+    /// Forward a status request to the backends it is routed to, returning
+    /// the response to relay.
+    fn forward(&mut self, forward: Forward, client: SocketAddr) -> Option<Vec<u8>> {
+        let session_id = self
+            .clients
+            .upstream_session(client, forward.session_id, Instant::now());
+
+        let payloads = self
+            .backends
+            .route(session_id)
+            .into_iter()
+            .filter_map(|backend| self.status(backend, forward.full, session_id))
+            .collect::<Vec<_>>();
+        self.backends
+            .relay(forward, &payloads, self.filter.as_deref())
+    }
+
+    /// Request the status of a backend, returning its payload.
+    ///
+    /// If the backend does not answer, the request is retried once with a new token.
+    fn status(&mut self, backend: usize, full: bool, session_id: u32) -> Option<Vec<u8>> {
+        for _ in 0..2 {
+            let token = match self.backends.token(backend, Instant::now()) {
+                Some(token) => token,
+                None => self.handshake(backend, session_id).ok()?,
+            };
+
+            let request = upstream_request(full, session_id, token);
+            match self.exchange(backend, &request, PacketType::Stat, session_id) {
+                Ok(payload) => return Some(payload),
+                Err(_) => self.backends.invalidate(backend),
+            }
+        }
+        None
+    }
+
+    /// Get a new token from a backend, recording whether it answered.
+    fn handshake(&mut self, backend: usize, session_id: u32) -> io::Result<Token> {
+        let token = self
+            .exchange(
+                backend,
+                &packets::Handshake::new(session_id),
+                PacketType::Handshake,
+                session_id,
+            )
+            .and_then(|payload| Token::try_from_payload(&payload));
+        self.backends
+            .handshaked(backend, token.as_ref().ok().copied(), Instant::now());
+        token
+    }
+
+    /// Send a request to a backend and wait for the matching response,
+    /// skipping late responses to previous requests.
+    fn exchange(
+        &self,
+        backend: usize,
+        request: &[u8],
+        packet_type: PacketType,
+        session_id: u32,
+    ) -> io::Result<Vec<u8>> {
+        let upstream = &self.upstreams[backend];
+        upstream.send(request)?;
+
+        let mut buf = [0; FullStat::RESPONSE_SIZE];
+        loop {
+            let received = upstream.recv(&mut buf)?;
+            if let Some(payload) = upstream_payload(&buf[..received], packet_type, session_id) {
+                return Ok(payload.to_vec());
+            }
+        }
+    }
+}
+
+/// Build a socket connected to a backend.
+fn upstream(backend: impl ToSocketAddrs) -> io::Result<UdpSocket> {
+    let upstream = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    upstream.set_read_timeout(Some(DEFAULT_UPSTREAM_TIMEOUT))?;
+    upstream.connect(backend)?;
+    Ok(upstream)
+}
+
+/// Convenience function to proxy requests from the given address to the given
+/// backend, until an IO error occurs on the client socket.
+pub fn serve(addr: impl ToSocketAddrs, backend: impl ToSocketAddrs) -> io::Result<()> {
+    Proxy::bind(addr, backend)?.serve()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, UdpSocket};
+
+    use super::{Proxy, ResponseFilter, Strategy};
+    use crate::blocking::QueryClient;
+    use crate::packets::{self, PacketType, Request};
+    use crate::testing::{sample_stat, Faults, MockQueryServer};
+    use crate::{FullStat, Token};
+
+    fn spawn_proxy(server: &MockQueryServer) -> SocketAddr {
+        let mut proxy = Proxy::bind("127.0.0.1:0", server.addr()).unwrap();
+        let addr = proxy.local_addr().unwrap();
+        std::thread::spawn(move || proxy.serve());
+        addr
+    }
+
+    #[test]
+    fn test_end_to_end() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = spawn_proxy(&server);
+        let client = QueryClient::new(&addr.to_string()).unwrap();
+
+        let token = client.handshake().unwrap();
+        assert_eq!(client.full_stat(token).unwrap(), server.full_stat());
+        assert_eq!(
+            client.basic_stat(token).unwrap().motd,
+            server.full_stat().hostname
+        );
+
+        // The backend token is reused for both requests
+        let handshakes = server
+            .received()
+            .iter()
+            .filter(|p| matches!(p.request, Some(Request::Handshake { .. })))
+            .count();
+        assert_eq!(handshakes, 1);
+    }
+
+    #[test]
+    fn test_relayed_session_id() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = spawn_proxy(&server);
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.connect(addr).unwrap();
+        let mut buf = [0; 1472];
+
+        socket.send(&packets::Handshake::new(0x01020304)).unwrap();
+        let received = socket.recv(&mut buf).unwrap();
+        assert_eq!(buf[..5], [9, 1, 2, 3, 4]);
+        let token = Token::from_payload(&buf[5..received]);
+
+        socket
+            .send(&packets::FullStat::new(0x01020304, token.0))
+            .unwrap();
+        socket.recv(&mut buf).unwrap();
+        assert_eq!(buf[..5], [0, 1, 2, 3, 4]);
+
+        // The backend never sees the token of the client leg
+        let forwarded = server.received().pop().unwrap();
+        assert!(matches!(
+            forwarded.request,
+            Some(Request::FullStat { token: t, .. }) if t != token.0
+        ));
+    }
+
+    #[test]
+    fn test_upstream_retry() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = spawn_proxy(&server);
+        let client = QueryClient::new(&addr.to_string()).unwrap();
+        let token = client.handshake().unwrap();
+        client.basic_stat(token).unwrap();
+
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        client.basic_stat(token).unwrap();
+        assert_eq!(server.dropped(PacketType::Stat), 1);
+    }
+
+    #[test]
+    fn test_invalid_client_token() {
+        let server = MockQueryServer::new().unwrap();
+        let addr = spawn_proxy(&server);
+        let client = QueryClient::new(&addr.to_string()).unwrap();
+
+        let token = client.handshake().unwrap();
+        assert!(client.full_stat(Token(token.0.wrapping_add(1))).is_err());
+        assert!(server.received().is_empty());
+    }
+
+    #[derive(Debug)]
+    struct HidePlayer(&'static str);
+
+    impl ResponseFilter for HidePlayer {
+        fn filter_full(&self, mut stat: crate::FullStat) -> crate::FullStat {
+            stat.player_list.retain(|player| player != self.0);
+            stat.numplayers = stat.player_list.len() as u32;
+            stat
+        }
+
+        fn filter_basic(&self, mut stat: crate::BasicStat) -> crate::BasicStat {
+            stat.numplayers -= 1;
+            stat
+        }
+    }
+
+    #[test]
+    fn test_response_filter() {
+        let server = MockQueryServer::new().unwrap();
+        let mut proxy = Proxy::bind("127.0.0.1:0", server.addr()).unwrap();
+        proxy.set_filter(HidePlayer("Dinnerbone"));
+        let addr = proxy.local_addr().unwrap();
+        std::thread::spawn(move || proxy.serve());
+        let client = QueryClient::new(&addr.to_string()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let stat = client.full_stat(token).unwrap();
+        assert_eq!(stat.player_list, ["AldanTanneo"]);
+        assert_eq!(stat.numplayers, 1);
+        assert_eq!(stat.hostname, server.full_stat().hostname);
+        assert_eq!(client.basic_stat(token).unwrap().numplayers, 1);
+    }
+
+    fn lobby(hostname: &str, players: &[&str]) -> MockQueryServer {
+        MockQueryServer::with_stat(FullStat {
+            hostname: hostname.into(),
+            numplayers: players.len() as u32,
+            player_list: players.iter().map(|&player| player.into()).collect(),
+            ..sample_stat()
+        })
+        .unwrap()
+    }
+
+    fn spawn_balanced(backends: &[SocketAddr], strategy: Strategy) -> SocketAddr {
+        let mut proxy = Proxy::bind_balanced("127.0.0.1:0", backends, strategy).unwrap();
+        let addr = proxy.local_addr().unwrap();
+        std::thread::spawn(move || proxy.serve());
+        addr
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let lobbies = [lobby("Lobby 1", &["Alice"]), lobby("Lobby 2", &["Bob"])];
+        let addr = spawn_balanced(
+            &[lobbies[0].addr(), lobbies[1].addr()],
+            Strategy::RoundRobin,
+        );
+
+        let mut hostnames = Vec::new();
+        for _ in 0..2 {
+            let client = QueryClient::new(&addr.to_string()).unwrap();
+            let token = client.handshake().unwrap();
+            let stat = client.full_stat(token).unwrap();
+            // The requests of a session go to the same backend
+            assert_eq!(client.basic_stat(token).unwrap().motd, stat.hostname);
+            assert_eq!(client.full_stat(token).unwrap(), stat);
+            hostnames.push(stat.hostname);
+        }
+        hostnames.sort();
+        assert_eq!(hostnames, ["Lobby 1", "Lobby 2"]);
+    }
+
+    #[test]
+    fn test_player_count_aggregate() {
+        let lobbies = [
+            lobby("Lobby 1", &["Alice"]),
+            lobby("Lobby 2", &["Bob", "Carol"]),
+        ];
+        let addr = spawn_balanced(
+            &[lobbies[0].addr(), lobbies[1].addr()],
+            Strategy::PlayerCountAggregate,
+        );
+        let client = QueryClient::new(&addr.to_string()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let stat = client.full_stat(token).unwrap();
+        assert_eq!(stat.hostname, "Lobby 1");
+        assert_eq!(stat.numplayers, 3);
+        assert_eq!(stat.maxplayers, 40);
+        assert_eq!(stat.player_list, ["Alice", "Bob", "Carol"]);
+        let basic = client.basic_stat(token).unwrap();
+        assert_eq!((basic.numplayers, basic.maxplayers), (3, 40));
+    }
+
+    #[test]
+    fn test_unhealthy_backend() {
+        let lobby = lobby("Lobby 1", &["Alice"]);
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let backends = [silent.local_addr().unwrap(), lobby.addr()];
+
+        for strategy in [Strategy::RoundRobin, Strategy::PlayerCountAggregate] {
+            let addr = spawn_balanced(&backends, strategy);
+            for _ in 0..2 {
+                let client = QueryClient::new(&addr.to_string()).unwrap();
+                let token = client.handshake().unwrap();
+                let stat = client.full_stat(token).unwrap();
+                assert_eq!(stat.hostname, "Lobby 1");
+                assert_eq!(stat.numplayers, 1);
+            }
+        }
+
+        // The silent backend was only sent health checks
+        let mut buf = [0; 16];
+        silent.set_nonblocking(true).unwrap();
+        while let Ok(received) = silent.recv(&mut buf) {
+            assert!(matches!(
+                Request::parse(&buf[..received]),
+                Some(Request::Handshake { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Forwarding proxy for the Query protocol.
+//!
+//! A proxy answers handshakes from clients itself, with the challenge tokens
+//! of a [`Responder`], and forwards the status requests carrying a valid token
+//! to a backend server, relaying its responses back to the clients.
+//!
+//! The two legs are independent: the proxy keeps its own session with the
+//! backend, refreshing its backend token every [`UPSTREAM_TOKEN_REFRESH`],
+//! while clients handshake with the proxy. Every client session is mapped to
+//! its own backend session ID, and responses are relayed with the session ID
+//! of the client.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::proxy;
+//! let mut proxy = proxy::blocking::Proxy::bind("0.0.0.0:25565", "10.0.0.2:25565")?;
+//! proxy.serve()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! A proxy can also balance requests across several backends, such as the
+//! lobbies of a network, with a [`Strategy`]. Backends are checked with a
+//! handshake every [`DEFAULT_HEALTH_CHECK_INTERVAL`], and left out while they
+//! do not answer:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::proxy::{self, Strategy};
+//! let backends = ["10.0.0.2:25565".parse().unwrap(), "10.0.0.3:25565".parse().unwrap()];
+//! let mut proxy = proxy::blocking::Proxy::bind_balanced(
+//!     "0.0.0.0:25565",
+//!     &backends,
+//!     Strategy::PlayerCountAggregate,
+//! )?;
+//! proxy.serve()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Responses can be rewritten in transit with a [`ResponseFilter`]:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{proxy::{self, ResponseFilter}, FullStat};
+//! #[derive(Debug)]
+//! struct HideBots;
+//!
+//! impl ResponseFilter for HideBots {
+//!     fn filter_full(&self, mut stat: FullStat) -> FullStat {
+//!         stat.player_list.retain(|player| !player.starts_with("bot_"));
+//!         stat.numplayers = stat.player_list.len() as u32;
+//!         stat
+//!     }
+//! }
+//!
+//! let mut proxy = proxy::blocking::Proxy::bind("0.0.0.0:25565", "10.0.0.2:25565")?;
+//! proxy.set_filter(HideBots);
+//! proxy.serve()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use crate::packets::{self, PacketType, ResponseHeader};
+use crate::responder::{basic_stat_response, full_stat_response, Accepted, Limits, Responder};
+use crate::{BasicStat, FullStat, Token, RESPONSE_HEADER_SIZE};
+
+/// Age after which the backend token is renewed with a new handshake.
+///
+/// Vanilla servers accept tokens for at least 30 seconds.
+pub const UPSTREAM_TOKEN_REFRESH: Duration = Duration::from_secs(25);
+
+/// Default timeout for the exchanges with the backend.
+///
+/// It is shorter than the [default client timeout](crate::DEFAULT_TIMEOUT), so
+/// that a failed request can be retried with a new token before clients give up.
+pub const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Default interval between two health checks of the backends of a balancing
+/// proxy.
+pub const DEFAULT_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How a balancing proxy spreads status requests across its backends.
+///
+/// Unhealthy backends, which did not answer their last handshake, are left
+/// out until they answer one again. When no backend is healthy, requests are
+/// forwarded as if they all were.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Strategy {
+    /// Forward the requests of each client session to a single backend,
+    /// picked in turn when the session starts.
+    #[default]
+    RoundRobin,
+    /// Forward every request to all backends, and answer with the sum of
+    /// their player counts and their merged player lists, truncated to fit
+    /// in a response. The other fields are those of the first backend.
+    PlayerCountAggregate,
+}
+
+/// Hook rewriting the status relayed by a proxy.
+///
+/// Filters are applied between the parsing of the backend response and the
+/// encoding of the response to the client, so they cannot produce packets
+/// breaking the wire format: strings are re-encoded like any other status,
+/// and full status responses are truncated to the client leg limits.
+///
+/// Both methods return the status unchanged by default.
+pub trait ResponseFilter: Send + Sync {
+    /// Rewrite a full status before it is relayed.
+    fn filter_full(&self, stat: FullStat) -> FullStat {
+        stat
+    }
+
+    /// Rewrite a basic status before it is relayed.
+    fn filter_basic(&self, stat: BasicStat) -> BasicStat {
+        stat
+    }
+}
+
+impl fmt::Debug for dyn ResponseFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResponseFilter")
+    }
+}
+
+/// Max number of client sessions mapped to a backend session
+const MAX_SESSIONS: usize = 4096;
+/// Session mask: the higher 4 bits of a byte are not taken into account
+const SESSION_MASK: u32 = 0x0F0F0F0F;
+/// Backend session ID of the health check handshakes
+const HEALTH_CHECK_SESSION: u32 = 0;
+
+/// A status request from a client, accepted by the client leg of a proxy
+#[derive(Debug, Copy, Clone)]
+struct Forward {
+    /// Whether the full status was requested
+    full: bool,
+    /// Session ID of the client
+    session_id: u32,
+    /// Max size of the response
+    max_size: usize,
+}
+
+/// Outcome of a client packet handled by the client leg of a proxy
+#[derive(Debug)]
+enum Incoming {
+    /// Response to send back right away
+    Respond(Vec<u8>),
+    /// Status request to forward to the backend
+    Forward(Forward),
+}
+
+/// Client leg of a proxy: token handling and session mapping
+#[derive(Debug)]
+struct ClientLeg {
+    responder: Responder,
+    sessions: HashMap<(SocketAddr, u32), (u32, Instant)>,
+    next_session: u32,
+}
+
+impl ClientLeg {
+    fn new() -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_nanos() as u32;
+
+        Self {
+            responder: Responder::new(),
+            sessions: HashMap::new(),
+            next_session: seed,
+        }
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.responder.set_limits(limits);
+    }
+
+    /// Handle a packet received from a client.
+    fn receive(&mut self, request: &[u8], source: SocketAddr, now: Instant) -> Option<Incoming> {
+        match self.responder.accept(request, source, now)? {
+            Accepted::Handshake(response) => Some(Incoming::Respond(response)),
+            Accepted::BasicStat {
+                session_id,
+                max_size,
+                ..
+            } => Some(Incoming::Forward(Forward {
+                full: false,
+                session_id,
+                max_size,
+            })),
+            Accepted::FullStat {
+                session_id,
+                max_size,
+                ..
+            } => Some(Incoming::Forward(Forward {
+                full: true,
+                session_id,
+                max_size,
+            })),
+        }
+    }
+
+    /// The backend session ID mapped to a client session.
+    fn upstream_session(&mut self, client: SocketAddr, session_id: u32, now: Instant) -> u32 {
+        if !self.sessions.contains_key(&(client, session_id)) && self.sessions.len() >= MAX_SESSIONS
+        {
+            self.sessions
+                .retain(|_, (_, used)| now.duration_since(*used) < UPSTREAM_TOKEN_REFRESH);
+            if self.sessions.len() >= MAX_SESSIONS {
+                self.sessions.clear();
+            }
+        }
+
+        let next_session = &mut self.next_session;
+        let (upstream, used) = self
+            .sessions
+            .entry((client, session_id))
+            .or_insert_with(|| {
+                *next_session = next_session.wrapping_add(1);
+                (*next_session & SESSION_MASK, now)
+            });
+        *used = now;
+        *upstream
+    }
+}
+
+/// Backend token of a proxy, renewed every [`UPSTREAM_TOKEN_REFRESH`]
+#[derive(Debug, Default)]
+struct UpstreamToken(Option<(Token, Instant)>);
+
+impl UpstreamToken {
+    /// The current token, unless it must be renewed.
+    fn get(&self, now: Instant) -> Option<Token> {
+        self.0
+            .filter(|(_, issued)| now.duration_since(*issued) < UPSTREAM_TOKEN_REFRESH)
+            .map(|(token, _)| token)
+    }
+
+    fn set(&mut self, token: Token, now: Instant) {
+        self.0 = Some((token, now));
+    }
+
+    fn invalidate(&mut self) {
+        self.0 = None;
+    }
+}
+
+/// Health and token of a backend
+#[derive(Debug)]
+struct Backend {
+    token: UpstreamToken,
+    healthy: bool,
+    checked: Option<Instant>,
+}
+
+/// Backends of a proxy: their health, their tokens, and the backend each
+/// upstream session is mapped to
+#[derive(Debug)]
+struct Backends {
+    strategy: Strategy,
+    health_check_interval: Option<Duration>,
+    backends: Vec<Backend>,
+    sessions: HashMap<u32, usize>,
+    next: usize,
+}
+
+impl Backends {
+    fn new(count: usize, strategy: Strategy, health_check_interval: Option<Duration>) -> Self {
+        Self {
+            strategy,
+            health_check_interval,
+            backends: (0..count)
+                .map(|_| Backend {
+                    token: UpstreamToken::default(),
+                    healthy: true,
+                    checked: None,
+                })
+                .collect(),
+            sessions: HashMap::new(),
+            next: 0,
+        }
+    }
+
+    /// The current token of a backend, unless it must be renewed.
+    fn token(&self, backend: usize, now: Instant) -> Option<Token> {
+        self.backends[backend].token.get(now)
+    }
+
+    fn invalidate(&mut self, backend: usize) {
+        self.backends[backend].token.invalidate();
+    }
+
+    /// Record the outcome of a handshake with a backend.
+    fn handshaked(&mut self, backend: usize, token: Option<Token>, now: Instant) {
+        let backend = &mut self.backends[backend];
+        match token {
+            Some(token) => backend.token.set(token, now),
+            None => backend.token.invalidate(),
+        }
+        backend.healthy = token.is_some();
+        backend.checked = Some(now);
+    }
+
+    /// The backends due for a health check.
+    fn due(&self, now: Instant) -> Vec<usize> {
+        let Some(interval) = self.health_check_interval else {
+            return Vec::new();
+        };
+        (0..self.backends.len())
+            .filter(|&i| match self.backends[i].checked {
+                Some(checked) => now.duration_since(checked) >= interval,
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Time until the next health check, if health checks are enabled.
+    fn next_check(&self, now: Instant) -> Option<Duration> {
+        let interval = self.health_check_interval?;
+        self.backends
+            .iter()
+            .map(|backend| match backend.checked {
+                Some(checked) => interval.saturating_sub(now.duration_since(checked)),
+                None => Duration::ZERO,
+            })
+            .min()
+    }
+
+    /// The backends a request of the upstream session is forwarded to.
+    fn route(&mut self, session_id: u32) -> Vec<usize> {
+        let mut healthy = (0..self.backends.len())
+            .filter(|&i| self.backends[i].healthy)
+            .collect::<Vec<_>>();
+        if healthy.is_empty() {
+            healthy = (0..self.backends.len()).collect();
+        }
+
+        match self.strategy {
+            Strategy::PlayerCountAggregate => healthy,
+            Strategy::RoundRobin => {
+                if let Some(&backend) = self.sessions.get(&session_id) {
+                    if healthy.contains(&backend) {
+                        return vec![backend];
+                    }
+                }
+                if self.sessions.len() >= MAX_SESSIONS {
+                    self.sessions.clear();
+                }
+                let backend = healthy[self.next % healthy.len()];
+                self.next = self.next.wrapping_add(1);
+                self.sessions.insert(session_id, backend);
+                vec![backend]
+            }
+        }
+    }
+
+    /// Build the response relayed to the client from the payloads of the
+    /// backends the request was forwarded to.
+    fn relay(
+        &self,
+        forward: Forward,
+        payloads: &[Vec<u8>],
+        filter: Option<&dyn ResponseFilter>,
+    ) -> Option<Vec<u8>> {
+        match self.strategy {
+            Strategy::RoundRobin => relay(forward, payloads.first()?, filter),
+            Strategy::PlayerCountAggregate => relay_aggregate(forward, payloads, filter),
+        }
+    }
+}
+
+/// Build a status request to the backend.
+fn upstream_request(full: bool, session_id: u32, token: Token) -> Vec<u8> {
+    if full {
+        packets::FullStat::new(session_id, token.0).to_vec()
+    } else {
+        packets::BasicStat::new(session_id, token.0).to_vec()
+    }
+}
+
+/// Payload of a backend response, if it has the expected type and session ID.
+fn upstream_payload(response: &[u8], packet_type: PacketType, session_id: u32) -> Option<&[u8]> {
+    let (header, payload) = ResponseHeader::parse(response)?;
+    (header.packet_type == packet_type && header.session_id == session_id).then_some(payload)
+}
+
+/// Build the response relayed to the client from the backend payload.
+///
+/// Without a filter, the payload is relayed as is, unless a full status is
+/// too large for the client leg limits: it is then re-encoded with a truncated
+/// player list. With a filter, the payload is always parsed and re-encoded.
+fn relay(forward: Forward, payload: &[u8], filter: Option<&dyn ResponseFilter>) -> Option<Vec<u8>> {
+    if let Some(filter) = filter {
+        return if forward.full {
+            let stat = filter.filter_full(FullStat::from_payload(payload).ok()?);
+            full_stat_response(forward.session_id, stat, forward.max_size)
+        } else {
+            let stat = filter.filter_basic(BasicStat::from_payload(payload).ok()?);
+            basic_stat_response(forward.session_id, stat, forward.max_size)
+        };
+    }
+
+    if RESPONSE_HEADER_SIZE + payload.len() > forward.max_size {
+        return if forward.full {
+            let stat = FullStat::from_payload(payload).ok()?;
+            full_stat_response(forward.session_id, stat, forward.max_size)
+        } else {
+            None
+        };
+    }
+
+    Some(packets::write_response(
+        PacketType::Stat,
+        forward.session_id,
+        payload,
+    ))
+}
+
+/// Build the response relayed to the client from the payloads of several
+/// backends, merged into a single status. Invalid payloads are skipped.
+fn relay_aggregate(
+    forward: Forward,
+    payloads: &[Vec<u8>],
+    filter: Option<&dyn ResponseFilter>,
+) -> Option<Vec<u8>> {
+    if forward.full {
+        let mut stats = payloads
+            .iter()
+            .filter_map(|payload| FullStat::from_payload(payload).ok());
+        let mut stat = stats.next()?;
+        for other in stats {
+            stat.numplayers = stat.numplayers.saturating_add(other.numplayers);
+            stat.maxplayers = stat.maxplayers.saturating_add(other.maxplayers);
+            stat.player_list.extend(other.player_list);
+        }
+        let stat = match filter {
+            Some(filter) => filter.filter_full(stat),
+            None => stat,
+        };
+        full_stat_response(forward.session_id, stat, forward.max_size)
+    } else {
+        let mut stats = payloads
+            .iter()
+            .filter_map(|payload| BasicStat::from_payload(payload).ok());
+        let mut stat = stats.next()?;
+        for other in stats {
+            stat.numplayers = stat.numplayers.saturating_add(other.numplayers);
+            stat.maxplayers = stat.maxplayers.saturating_add(other.maxplayers);
+        }
+        let stat = match filter {
+            Some(filter) => filter.filter_basic(stat),
+            None => stat,
+        };
+        basic_stat_response(forward.session_id, stat, forward.max_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upstream_sessions() {
+        let a = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let b = SocketAddr::from(([127, 0, 0, 2], 50000));
+        let now = Instant::now();
+        let mut leg = ClientLeg::new();
+
+        let session = leg.upstream_session(a, 7, now);
+        assert_eq!(session & !SESSION_MASK, 0);
+        assert_eq!(leg.upstream_session(a, 7, now), session);
+        assert_ne!(leg.upstream_session(a, 8, now), session);
+        assert_ne!(leg.upstream_session(b, 7, now), session);
+    }
+
+    #[test]
+    fn test_upstream_token_refresh() {
+        let now = Instant::now();
+        let mut token = UpstreamToken::default();
+        assert_eq!(token.get(now), None);
+
+        token.set(Token(1234), now);
+        assert_eq!(token.get(now + Duration::from_secs(1)), Some(Token(1234)));
+        assert_eq!(token.get(now + UPSTREAM_TOKEN_REFRESH), None);
+
+        token.invalidate();
+        assert_eq!(token.get(now), None);
+    }
+
+    #[test]
+    fn test_upstream_payload() {
+        let response = packets::write_response(PacketType::Stat, 7, b"payload");
+        assert_eq!(
+            upstream_payload(&response, PacketType::Stat, 7),
+            Some(&b"payload"[..])
+        );
+        assert_eq!(upstream_payload(&response, PacketType::Stat, 8), None);
+        assert_eq!(upstream_payload(&response, PacketType::Handshake, 7), None);
+        assert_eq!(upstream_payload(&response[..3], PacketType::Stat, 7), None);
+    }
+
+    #[derive(Debug)]
+    struct Identity;
+
+    impl ResponseFilter for Identity {}
+
+    #[derive(Debug)]
+    struct Inject;
+
+    impl ResponseFilter for Inject {
+        fn filter_full(&self, mut stat: FullStat) -> FullStat {
+            stat.hostname = "A\0Server".into();
+            stat.player_list.push("Evil\0\0".into());
+            stat
+        }
+    }
+
+    #[test]
+    fn test_relay_filter() {
+        let stat = crate::testing::sample_stat();
+        let payload = stat.to_payload();
+        let forward = Forward {
+            full: true,
+            session_id: 7,
+            max_size: 1472,
+        };
+
+        let response = relay(forward, &payload, Some(&Identity)).unwrap();
+        assert_eq!(response, relay(forward, &payload, None).unwrap());
+
+        // Filtered strings cannot break the wire format
+        let response = relay(forward, &payload, Some(&Inject)).unwrap();
+        let relayed = FullStat::from_payload(&response[RESPONSE_HEADER_SIZE..]).unwrap();
+        assert_eq!(relayed.hostname, "AServer");
+        assert_eq!(relayed.player_list.last().unwrap(), "Evil");
+        assert_eq!(relayed.numplayers, stat.numplayers);
+    }
+
+    #[test]
+    fn test_backend_routing() {
+        let now = Instant::now();
+        let mut backends = Backends::new(3, Strategy::RoundRobin, Some(Duration::from_secs(5)));
+        assert_eq!(backends.due(now), [0, 1, 2]);
+        assert_eq!(backends.next_check(now), Some(Duration::ZERO));
+
+        // Sessions are mapped to backends in turn, and stick to them
+        assert_eq!(backends.route(1), [0]);
+        assert_eq!(backends.route(2), [1]);
+        assert_eq!(backends.route(3), [2]);
+        assert_eq!(backends.route(1), [0]);
+
+        // Unhealthy backends are left out, and their sessions remapped
+        backends.handshaked(0, None, now);
+        backends.handshaked(1, Some(Token(1)), now);
+        backends.handshaked(2, Some(Token(2)), now);
+        assert_eq!(backends.token(1, now), Some(Token(1)));
+        assert_ne!(backends.route(1), [0]);
+        assert_eq!(backends.route(2), [1]);
+        assert!(backends.due(now).is_empty());
+        assert_eq!(
+            backends.next_check(now + Duration::from_secs(2)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(backends.due(now + Duration::from_secs(5)), [0, 1, 2]);
+
+        let mut backends = Backends::new(2, Strategy::PlayerCountAggregate, None);
+        assert_eq!(backends.route(1), [0, 1]);
+        backends.handshaked(1, None, now);
+        assert_eq!(backends.route(1), [0]);
+        // Without any healthy backend, all of them are tried
+        backends.handshaked(0, None, now);
+        assert_eq!(backends.route(1), [0, 1]);
+        assert!(backends.due(now).is_empty());
+        assert_eq!(backends.next_check(now), None);
+    }
+
+    #[test]
+    fn test_relay_aggregate() {
+        let stat = crate::testing::sample_stat();
+        let other = FullStat {
+            numplayers: 1,
+            maxplayers: 50,
+            player_list: vec!["Notch".into()],
+            hostname: "Another Server".into(),
+            ..stat.clone()
+        };
+        let payloads = [stat.to_payload(), b"invalid".to_vec(), other.to_payload()];
+        let forward = Forward {
+            full: true,
+            session_id: 7,
+            max_size: 1472,
+        };
+
+        let response = relay_aggregate(forward, &payloads, None).unwrap();
+        let merged = FullStat::from_payload(&response[RESPONSE_HEADER_SIZE..]).unwrap();
+        assert_eq!(merged.hostname, stat.hostname);
+        assert_eq!(merged.numplayers, 3);
+        assert_eq!(merged.maxplayers, 70);
+        assert_eq!(merged.player_list, ["AldanTanneo", "Dinnerbone", "Notch"]);
+
+        // The merged player list is truncated to fit
+        let max_size = response.len() - 3;
+        let response = relay_aggregate(
+            Forward {
+                max_size,
+                ..forward
+            },
+            &payloads,
+            None,
+        )
+        .unwrap();
+        let merged = FullStat::from_payload(&response[RESPONSE_HEADER_SIZE..]).unwrap();
+        assert_eq!(merged.numplayers, 3);
+        assert_eq!(merged.player_list, ["AldanTanneo", "Dinnerbone"]);
+
+        let forward = Forward {
+            full: false,
+            ..forward
+        };
+        let payloads = [
+            BasicStat::from(&stat).to_payload(),
+            BasicStat::from(&other).to_payload(),
+        ];
+        let response = relay_aggregate(forward, &payloads, None).unwrap();
+        let merged = BasicStat::from_payload(&response[RESPONSE_HEADER_SIZE..]).unwrap();
+        assert_eq!((merged.numplayers, merged.maxplayers), (3, 70));
+        assert_eq!(relay_aggregate(forward, &[], None), None);
+    }
+}
@@ -0,0 +1,345 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the Query proxy.
+//!
+//! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
+
+use ::tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    runtime::Handle,
+    time::timeout,
+};
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+};
+
+use super::*;
+use crate::task::TaskHandle;
+
+/// An asynchronous Query proxy, using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+///
+/// Requests are handled one at a time: clients wait while a status request
+/// is forwarded to the backends, and while the backends are health checked.
+#[derive(Debug)]
+pub struct Proxy {
+    socket: UdpSocket,
+    upstreams: Vec<UdpSocket>,
+    upstream_timeout: Option<Duration>,
+    clients: ClientLeg,
+    backends: Backends,
+    filter: Option<Box<dyn ResponseFilter>>,
+}
+
+impl Proxy {
+    /// Bind a new proxy to the given address, forwarding status requests to the given backend.
+    ///
+    /// The default [upstream timeout](DEFAULT_UPSTREAM_TIMEOUT) and [limits](Limits) are used.
+    pub async fn bind(addr: impl ToSocketAddrs, backend: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            upstreams: vec![upstream(backend).await?],
+            upstream_timeout: Some(DEFAULT_UPSTREAM_TIMEOUT),
+            clients: ClientLeg::new(),
+            backends: Backends::new(1, Strategy::RoundRobin, None),
+            filter: None,
+        })
+    }
+
+    /// Bind a new proxy to the given address, balancing status requests
+    /// across the given backends with the given strategy.
+    ///
+    /// The default [upstream timeout](DEFAULT_UPSTREAM_TIMEOUT),
+    /// [health check interval](DEFAULT_HEALTH_CHECK_INTERVAL) and
+    /// [limits](Limits) are used.
+    pub async fn bind_balanced(
+        addr: impl ToSocketAddrs,
+        backends: &[SocketAddr],
+        strategy: Strategy,
+    ) -> io::Result<Self> {
+        if backends.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No backend to balance requests across.",
+            ));
+        }
+
+        let mut upstreams = Vec::with_capacity(backends.len());
+        for backend in backends {
+            upstreams.push(upstream(backend).await?);
+        }
+
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            upstreams,
+            upstream_timeout: Some(DEFAULT_UPSTREAM_TIMEOUT),
+            clients: ClientLeg::new(),
+            backends: Backends::new(
+                backends.len(),
+                strategy,
+                Some(DEFAULT_HEALTH_CHECK_INTERVAL),
+            ),
+            filter: None,
+        })
+    }
+
+    /// The local address the proxy is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Set the timeout of the exchanges with the backends.
+    pub fn set_upstream_timeout(&mut self, timeout: Option<Duration>) {
+        self.upstream_timeout = timeout;
+    }
+
+    /// Set the interval between two health checks of the backends, or
+    /// disable them with `None`.
+    ///
+    /// Health checks are disabled by default on proxies with a single
+    /// backend, created with [`bind`](Self::bind).
+    pub fn set_health_check_interval(&mut self, interval: Option<Duration>) {
+        self.backends.health_check_interval = interval;
+    }
+
+    /// Set the limits protecting the proxy against amplification abuse.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.clients.set_limits(limits);
+    }
+
+    /// Set the filter rewriting the responses relayed to clients.
+    pub fn set_filter(&mut self, filter: impl ResponseFilter + 'static) {
+        self.filter = Some(Box::new(filter));
+    }
+
+    /// Receive and answer a single request from a client, after checking the
+    /// health of the backends due for it.
+    ///
+    /// Failed exchanges with the backends are not reported: the request is
+    /// dropped, as if the backends did not answer. With health checks, this
+    /// returns without receiving any request when the next check is due.
+    pub async fn serve_one(&mut self) -> io::Result<()> {
+        self.check_health().await;
+
+        let mut buf = [0; 16];
+        let recv = self.socket.recv_from(&mut buf);
+        let received = match self.backends.next_check(Instant::now()) {
+            Some(next_check) => match timeout(next_check, recv).await {
+                Ok(received) => received,
+                Err(_) => return Ok(()),
+            },
+            None => recv.await,
+        };
+        let (received, source) = match received {
+            Ok(received) => received,
+            // ICMP port unreachable errors from previous responses are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let response = match self
+            .clients
+            .receive(&buf[..received], source, Instant::now())
+        {
+            Some(Incoming::Respond(response)) => Some(response),
+            Some(Incoming::Forward(forward)) => self.forward(forward, source).await,
+            None => None,
+        };
+
+        if let Some(response) = response {
+            self.socket.send_to(&response, source).await?;
+        }
+        Ok(())
+    }
+
+    /// Answer requests until an IO error occurs on the client socket.
+    pub async fn serve(&mut self) -> io::Result<()> {
+        loop {
+            self.serve_one().await?;
+        }
+    }
+
+    /// Spawn a task answering requests until an IO error occurs on the client
+    /// socket, or until the returned handle is stopped or dropped.
+    pub fn spawn(self) -> TaskHandle {
+        self.spawn_on(&Handle::current())
+    }
+
+    /// Like [`spawn`](Self::spawn), but spawn the task on the given runtime.
+    pub fn spawn_on(mut self, runtime: &Handle) -> TaskHandle {
+        TaskHandle::spawn(runtime, async move { self.serve().await })
+    }
+
+    /// Check the health of the backends due for it, with a handshake.
+    async fn check_health(&mut self) {
+        for backend in self.backends.due(Instant::now()) {
+            let _ = self.handshake(backend, HEALTH_CHECK_SESSION).await;
+        }
+    }
+
+    /// Forward a status request to the backends it is routed to, returning
+    /// the response to relay.
+    async fn forward(&mut self, forward: Forward, client: SocketAddr) -> Option<Vec<u8>> {
+        let session_id = self
+            .clients
+            .upstream_session(client, forward.session_id, Instant::now());
+
+        let mut payloads = Vec::new();
+        for backend in self.backends.route(session_id) {
+            if let Some(payload) = self.status(backend, forward.full, session_id).await {
+                payloads.push(payload);
+            }
+        }
+        self.backends
+            .relay(forward, &payloads, self.filter.as_deref())
+    }
+
+    /// Request the status of a backend, returning its payload.
+    ///
+    /// If the backend does not answer, the request is retried once with a new token.
+    async fn status(&mut self, backend: usize, full: bool, session_id: u32) -> Option<Vec<u8>> {
+        for _ in 0..2 {
+            let token = match self.backends.token(backend, Instant::now()) {
+                Some(token) => token,
+                None => self.handshake(backend, session_id).await.ok()?,
+            };
+
+            let request = upstream_request(full, session_id, token);
+            match self
+                .exchange(backend, &request, PacketType::Stat, session_id)
+                .await
+            {
+                Ok(payload) => return Some(payload),
+                Err(_) => self.backends.invalidate(backend),
+            }
+        }
+        None
+    }
+
+    /// Get a new token from a backend, recording whether it answered.
+    async fn handshake(&mut self, backend: usize, session_id: u32) -> io::Result<Token> {
+        let token = self
+            .exchange(
+                backend,
+                &packets::Handshake::new(session_id),
+                PacketType::Handshake,
+                session_id,
+            )
+            .await
+            .and_then(|payload| Token::try_from_payload(&payload));
+        self.backends
+            .handshaked(backend, token.as_ref().ok().copied(), Instant::now());
+        token
+    }
+
+    /// Send a request to a backend and wait for the matching response,
+    /// skipping late responses to previous requests.
+    async fn exchange(
+        &self,
+        backend: usize,
+        request: &[u8],
+        packet_type: PacketType,
+        session_id: u32,
+    ) -> io::Result<Vec<u8>> {
+        let upstream = &self.upstreams[backend];
+        upstream.send(request).await?;
+
+        let mut buf = [0; FullStat::RESPONSE_SIZE];
+        let recv = async {
+            loop {
+                let received = upstream.recv(&mut buf).await?;
+                if let Some(payload) = upstream_payload(&buf[..received], packet_type, session_id) {
+                    return Ok(payload.to_vec());
+                }
+            }
+        };
+
+        match self.upstream_timeout {
+            Some(duration) => timeout(duration, recv).await.map_err(|_| {
+                io::Error::new(io::ErrorKind::TimedOut, "Upstream exchange timed out.")
+            })?,
+            None => recv.await,
+        }
+    }
+}
+
+/// Build a socket connected to a backend.
+async fn upstream(backend: impl ToSocketAddrs) -> io::Result<UdpSocket> {
+    let upstream = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    upstream.connect(backend).await?;
+    Ok(upstream)
+}
+
+/// Convenience function to proxy requests from the given address to the given
+/// backend, until an IO error occurs on the client socket.
+pub async fn serve(addr: impl ToSocketAddrs, backend: impl ToSocketAddrs) -> io::Result<()> {
+    Proxy::bind(addr, backend).await?.serve().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Proxy, Strategy};
+    use crate::testing::{sample_stat, MockQueryServer};
+    use crate::tokio::QueryClient;
+    use crate::FullStat;
+
+    #[tokio::test]
+    async fn test_end_to_end() {
+        let server = MockQueryServer::new().unwrap();
+        let proxy = Proxy::bind("127.0.0.1:0", server.addr()).await.unwrap();
+        let addr = proxy.local_addr().unwrap();
+        let handle = proxy.spawn();
+
+        let client = QueryClient::new(&addr.to_string()).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        assert_eq!(client.full_stat(token).await.unwrap(), server.full_stat());
+        assert_eq!(client.basic_stat(token).await.unwrap().numplayers, 2);
+
+        handle.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_balanced() {
+        let lobbies = [
+            MockQueryServer::new().unwrap(),
+            MockQueryServer::with_stat(FullStat {
+                hostname: "Lobby 2".into(),
+                numplayers: 1,
+                player_list: vec!["Notch".into()],
+                ..sample_stat()
+            })
+            .unwrap(),
+        ];
+        let silent = ::tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let backends = [
+            lobbies[0].addr(),
+            silent.local_addr().unwrap(),
+            lobbies[1].addr(),
+        ];
+
+        let proxy = Proxy::bind_balanced("127.0.0.1:0", &backends, Strategy::PlayerCountAggregate)
+            .await
+            .unwrap();
+        let addr = proxy.local_addr().unwrap();
+        let handle = proxy.spawn();
+        let client = QueryClient::new(&addr.to_string()).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        let stat = client.full_stat(token).await.unwrap();
+        assert_eq!(stat.numplayers, 3);
+        assert_eq!(stat.player_list, ["AldanTanneo", "Dinnerbone", "Notch"]);
+        handle.stop().await.unwrap();
+
+        let proxy = Proxy::bind_balanced("127.0.0.1:0", &backends, Strategy::RoundRobin)
+            .await
+            .unwrap();
+        let addr = proxy.local_addr().unwrap();
+        let handle = proxy.spawn();
+        let mut hostnames = Vec::new();
+        for _ in 0..2 {
+            let client = QueryClient::new(&addr.to_string()).await.unwrap();
+            let token = client.handshake().await.unwrap();
+            hostnames.push(client.full_stat(token).await.unwrap().hostname);
+        }
+        hostnames.sort();
+        assert_eq!(hostnames, ["A Minecraft Server", "Lobby 2"]);
+        handle.stop().await.unwrap();
+    }
+}
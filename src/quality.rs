@@ -0,0 +1,313 @@
+//! Packet loss and jitter measurement, with handshakes as probes.
+//!
+//! A single ping does not show an unstable connection. `network_quality`, in
+//! the [`blocking`](crate::blocking::network_quality) and
+//! [`tokio`](crate::tokio::network_quality) modules, sends a series of
+//! handshakes spaced by a fixed interval, and reports how many were answered
+//! and how their round trip times vary:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking, quality::ProbeOptions};
+//! # use std::time::Duration;
+//! let report = blocking::network_quality(
+//!     "play.example.com",
+//!     ProbeOptions {
+//!         count: 20,
+//!         interval: Duration::from_millis(200),
+//!         timeout: Duration::from_secs(1),
+//!     },
+//! )?;
+//! println!("{}% loss, jitter {:?}", report.loss_percent(), report.jitter);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Every probe is sent with its own session ID, so responses are matched to
+//! their probe even if they arrive out of order.
+
+use std::time::{Duration, Instant};
+
+use crate::packets::{self, ResponseHeader, SESSION_MASK};
+use crate::DEFAULT_TIMEOUT;
+
+/// Maximum number of probes: session IDs only have 16 significant bits
+pub const MAX_PROBES: u32 = 1 << 16;
+
+/// Options of a series of probes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeOptions {
+    /// Number of probes to send, at most [`MAX_PROBES`]
+    pub count: u32,
+    /// Time between two probes
+    pub interval: Duration,
+    /// Time after which an unanswered probe is lost
+    pub timeout: Duration,
+}
+
+impl Default for ProbeOptions {
+    /// 10 probes, 100 milliseconds apart, with the [default timeout](DEFAULT_TIMEOUT).
+    fn default() -> Self {
+        Self {
+            count: 10,
+            interval: Duration::from_millis(100),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// Statistics of a series of probes
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QualityReport {
+    /// Number of probes sent
+    pub sent: u32,
+    /// Number of probes answered before their timeout
+    pub received: u32,
+    /// Shortest round trip time, `None` if no probe was answered
+    pub min: Option<Duration>,
+    /// Average round trip time
+    pub avg: Option<Duration>,
+    /// Longest round trip time
+    pub max: Option<Duration>,
+    /// Mean deviation of the round trip times from their average
+    pub jitter: Option<Duration>,
+}
+
+impl QualityReport {
+    /// Percentage of the probes which were lost, between 0 and 100.
+    pub fn loss_percent(&self) -> f64 {
+        if self.sent == 0 {
+            return 0.0;
+        }
+        100.0 * f64::from(self.sent - self.received) / f64::from(self.sent)
+    }
+}
+
+/// Spread the 16 bits of a probe number over the significant bits of a session ID.
+fn spread(probe: u32) -> u32 {
+    (0..4).fold(0, |acc, nibble| {
+        acc | ((probe >> (4 * nibble)) & 0xF) << (8 * nibble)
+    })
+}
+
+/// Gather the significant bits of a session ID into a probe number, the inverse of [`spread`].
+fn gather(session_bits: u32) -> u32 {
+    (0..4).fold(0, |acc, nibble| {
+        acc | ((session_bits >> (8 * nibble)) & 0xF) << (4 * nibble)
+    })
+}
+
+/// Sans-IO state of a series of probes, scheduling them and matching their
+/// responses.
+#[derive(Debug)]
+pub(crate) struct Probes {
+    session_id: u32,
+    options: ProbeOptions,
+    start: Instant,
+    /// Send time of the probes sent so far
+    sent: Vec<Instant>,
+    /// Round trip time of the probes sent so far, if answered in time
+    rtts: Vec<Option<Duration>>,
+}
+
+impl Probes {
+    /// Start a series of probes at the given time. Session IDs of the probes
+    /// are derived from the session ID of the client.
+    pub(crate) fn new(session_id: u32, mut options: ProbeOptions, start: Instant) -> Self {
+        options.count = options.count.min(MAX_PROBES);
+        Self {
+            session_id: session_id & SESSION_MASK,
+            options,
+            start,
+            sent: Vec::with_capacity(options.count as usize),
+            rtts: Vec::with_capacity(options.count as usize),
+        }
+    }
+
+    fn session_id(&self, probe: usize) -> u32 {
+        self.session_id ^ spread(probe as u32)
+    }
+
+    /// The next probe to send, if it is due.
+    pub(crate) fn poll_send(&mut self, now: Instant) -> Option<packets::Handshake> {
+        let probe = self.sent.len();
+        if probe as u32 >= self.options.count || now < self.send_time(probe) {
+            return None;
+        }
+        self.sent.push(now);
+        self.rtts.push(None);
+        Some(packets::Handshake::new(self.session_id(probe)))
+    }
+
+    /// Scheduled send time of a probe.
+    fn send_time(&self, probe: usize) -> Instant {
+        self.start + self.options.interval * probe as u32
+    }
+
+    /// Time the series ends at, once the last probe has timed out.
+    fn deadline(&self) -> Instant {
+        self.send_time(self.options.count.saturating_sub(1) as usize) + self.options.timeout
+    }
+
+    /// Time of the next event: either the next probe to send, or the end of
+    /// the series. `None` once the series is over.
+    pub(crate) fn next_wakeup(&self, now: Instant) -> Option<Instant> {
+        let probe = self.sent.len();
+        if (probe as u32) < self.options.count {
+            return Some(self.send_time(probe));
+        }
+        let deadline = self.deadline();
+        let answered = self.rtts.iter().all(Option::is_some);
+        (now < deadline && !answered).then_some(deadline)
+    }
+
+    /// Match a datagram to its probe, recording its round trip time if it was
+    /// received before the probe timed out. Other datagrams are ignored.
+    pub(crate) fn receive(&mut self, datagram: &[u8], now: Instant) {
+        let Some((header, _)) = ResponseHeader::parse(datagram) else {
+            return;
+        };
+        if header.packet_type != packets::PacketType::Handshake {
+            return;
+        }
+        let bits = header.session_id ^ self.session_id;
+        let probe = gather(bits) as usize;
+        if bits & !SESSION_MASK == 0 && probe < self.sent.len() {
+            let rtt = now.saturating_duration_since(self.sent[probe]);
+            if rtt <= self.options.timeout && self.rtts[probe].is_none() {
+                self.rtts[probe] = Some(rtt);
+            }
+        }
+    }
+
+    /// Statistics of the probes sent.
+    pub(crate) fn report(&self) -> QualityReport {
+        let rtts: Vec<Duration> = self.rtts.iter().flatten().copied().collect();
+        let received = rtts.len() as u32;
+        let avg = (received > 0).then(|| rtts.iter().sum::<Duration>() / received);
+        let jitter =
+            avg.map(|avg| rtts.iter().map(|&rtt| rtt.abs_diff(avg)).sum::<Duration>() / received);
+        QualityReport {
+            sent: self.sent.len() as u32,
+            received,
+            min: rtts.iter().min().copied(),
+            avg,
+            max: rtts.iter().max().copied(),
+            jitter,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{write_response, PacketType};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_session_ids() {
+        let probes = Probes::new(0xFFFF_FFFF, ProbeOptions::default(), Instant::now());
+        let mut ids: Vec<u32> = (0..MAX_PROBES as usize)
+            .map(|i| probes.session_id(i))
+            .collect();
+        assert!(ids.iter().all(|&id| id & !SESSION_MASK == 0));
+        assert!((0..MAX_PROBES).all(|i| gather(spread(i)) == i));
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), MAX_PROBES as usize);
+    }
+
+    #[test]
+    fn test_probes() {
+        let start = Instant::now();
+        let options = ProbeOptions {
+            count: 4,
+            interval: ms(100),
+            timeout: ms(150),
+        };
+        let mut probes = Probes::new(1234, options, start);
+        let response = |probe: usize, probes: &Probes| {
+            write_response(PacketType::Handshake, probes.session_id(probe), b"1\0")
+        };
+
+        let mut sent = Vec::new();
+        for t in (0..=300).step_by(50) {
+            let now = start + ms(t);
+            if let Some(packet) = probes.poll_send(now) {
+                sent.push(packet);
+            }
+        }
+        assert_eq!(sent.len(), 4);
+        assert_eq!(probes.poll_send(start + ms(1000)), None);
+        assert_eq!(probes.next_wakeup(start + ms(300)), Some(start + ms(450)));
+
+        // Out of order responses
+        probes.receive(&response(1, &probes), start + ms(120));
+        probes.receive(&response(0, &probes), start + ms(120));
+        // Duplicated response, stat response and garbage are ignored
+        probes.receive(&response(0, &probes), start + ms(145));
+        probes.receive(
+            &write_response(PacketType::Stat, probes.session_id(2), b""),
+            start + ms(210),
+        );
+        probes.receive(b"\x09", start + ms(210));
+        // Late response, after the probe timed out
+        probes.receive(&response(2, &probes), start + ms(360));
+        probes.receive(&response(3, &probes), start + ms(340));
+
+        let report = probes.report();
+        assert_eq!(
+            report,
+            QualityReport {
+                sent: 4,
+                received: 3,
+                min: Some(ms(20)),
+                avg: Some(ms(60)),
+                max: Some(ms(120)),
+                // |120 - 60| + |20 - 60| + |40 - 60|, divided by 3
+                jitter: Some(ms(40)),
+            }
+        );
+        assert_eq!(report.loss_percent(), 25.0);
+        assert_eq!(probes.next_wakeup(start + ms(449)), Some(start + ms(450)));
+        assert_eq!(probes.next_wakeup(start + ms(450)), None);
+    }
+
+    #[test]
+    fn test_early_end() {
+        let start = Instant::now();
+        let options = ProbeOptions {
+            count: 2,
+            interval: ms(10),
+            timeout: ms(1000),
+        };
+        let mut probes = Probes::new(0, options, start);
+        assert_eq!(probes.next_wakeup(start), Some(start));
+        assert!(probes.poll_send(start).is_some());
+        assert!(probes.poll_send(start).is_none());
+        assert_eq!(probes.next_wakeup(start), Some(start + ms(10)));
+        assert!(probes.poll_send(start + ms(10)).is_some());
+
+        for probe in 0..2 {
+            let response = write_response(PacketType::Handshake, probes.session_id(probe), b"");
+            probes.receive(&response, start + ms(20));
+        }
+        // Every probe was answered
+        assert_eq!(probes.next_wakeup(start + ms(20)), None);
+
+        let empty = Probes::new(
+            0,
+            ProbeOptions {
+                count: 0,
+                ..options
+            },
+            start,
+        );
+        assert_eq!(empty.next_wakeup(start), None);
+        assert_eq!(empty.report().loss_percent(), 0.0);
+        assert_eq!(empty.report().avg, None);
+    }
+}
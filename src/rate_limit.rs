@@ -0,0 +1,199 @@
+//! Rate limiting of the queries of a whole process.
+//!
+//! A [`RateLimiter`] is a token bucket shared by its clones: every query
+//! attempt of the builders it is given to draws a permit from the same
+//! budget, whatever the number of clients created.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking::Query, rate_limit::{RateLimit, RateLimiter}};
+//! // 20 queries per second on average, across every thread
+//! let limiter = RateLimiter::new(RateLimit { rate: 20.0, burst: 5.0 });
+//! for host in ["lobby.example.com", "survival.example.com"] {
+//!     let full_stat = Query::to(host).rate_limiter(limiter.clone()).full()?;
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! A limiter can also be [installed globally](set_global): the query builders
+//! without a limiter of their own, and the free `query` functions of the
+//! clients, then use it.
+//!
+//! One permit covers an attempt, a handshake and its status request: retried
+//! attempts draw a new permit, so they are not free. Permits are handed out
+//! in the order they are requested, so callers waiting on a busy limiter are
+//! served first come, first served.
+
+use std::{
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+/// A token bucket rate limit
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Requests allowed per second, on average
+    pub rate: f64,
+    /// Requests allowed in a burst
+    pub burst: f64,
+}
+
+/// Limiter installed with [`set_global`]
+static GLOBAL: RwLock<Option<RateLimiter>> = RwLock::new(None);
+
+/// Install a limiter for the whole process, or remove it with `None`.
+///
+/// Query builders without a [limiter of their own](crate::blocking::Query::rate_limiter)
+/// and the free `query` functions of the clients draw their permits from it.
+pub fn set_global(limiter: Option<RateLimiter>) {
+    *GLOBAL.write().unwrap_or_else(PoisonError::into_inner) = limiter;
+}
+
+/// The limiter installed with [`set_global`], if any.
+pub fn global() -> Option<RateLimiter> {
+    GLOBAL
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+}
+
+/// A token bucket handing out permits at the [rate of a limit](RateLimit),
+/// waiting for them when the bucket is empty.
+///
+/// Clones share the same bucket.
+#[derive(Debug, Clone)]
+pub struct RateLimiter(Arc<Bucket>);
+
+/// Bucket of a [`RateLimiter`], tracked as the theoretical arrival time of
+/// the next permit
+#[derive(Debug)]
+struct Bucket {
+    /// Delay between two permits
+    interval: Duration,
+    /// How far ahead of now the next permit can be scheduled, for bursts
+    burst: Duration,
+    /// Time the next permit is scheduled at, if the bucket is not full
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    /// A limiter with a full bucket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rate is not positive, or if the burst is less than one
+    /// request.
+    pub fn new(limit: RateLimit) -> Self {
+        assert!(limit.rate > 0.0, "The rate of a limiter must be positive");
+        assert!(
+            limit.burst >= 1.0,
+            "The burst of a limiter must allow a request"
+        );
+        let interval = Duration::from_secs_f64(1.0 / limit.rate);
+        Self(Arc::new(Bucket {
+            interval,
+            burst: interval.mul_f64(limit.burst - 1.0),
+            next: Mutex::new(None),
+        }))
+    }
+
+    /// Wait for a permit, blocking the thread.
+    pub fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Wait for a permit, with the timer of `tokio`.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+    pub async fn acquire_async(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            ::tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take the next permit, and return the delay until it can be used.
+    ///
+    /// The permit is taken even if the caller stops waiting for it.
+    pub(crate) fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut next = self.0.next.lock().unwrap_or_else(PoisonError::into_inner);
+        let scheduled = next.map_or(now, |next| next.max(now));
+        *next = Some(scheduled + self.0.interval);
+        (scheduled - now).saturating_sub(self.0.burst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        let limiter = RateLimiter::new(RateLimit {
+            rate: 1.0,
+            burst: 4.0,
+        });
+        // A full bucket allows a burst
+        for _ in 0..4 {
+            assert_eq!(limiter.reserve(), Duration::ZERO);
+        }
+        // Then permits are spaced by the interval, in order
+        let waits: Vec<Duration> = (0..3).map(|_| limiter.clone().reserve()).collect();
+        for (i, wait) in waits.into_iter().enumerate() {
+            let expected = Duration::from_secs(i as u64 + 1);
+            assert!(wait <= expected && wait > expected - Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_acquire() {
+        let limiter = RateLimiter::new(RateLimit {
+            rate: 20.0,
+            burst: 2.0,
+        });
+        let start = Instant::now();
+        for _ in 0..4 {
+            limiter.acquire();
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(300), "{elapsed:?}");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_rate() {
+        let limiter = RateLimiter::new(RateLimit {
+            rate: 10.0,
+            burst: 10.0,
+        });
+        let start = ::tokio::time::Instant::now();
+        let tasks: Vec<_> = (0..50)
+            .map(|_| {
+                let limiter = limiter.clone();
+                ::tokio::spawn(async move {
+                    limiter.acquire_async().await;
+                    ::tokio::time::Instant::now() - start
+                })
+            })
+            .collect();
+        let mut sent = Vec::new();
+        for task in tasks {
+            sent.push(task.await.unwrap());
+        }
+        sent.sort();
+
+        // After the burst of the 10 permits of the bucket, a permit every 100ms
+        for (i, sent) in sent.iter().enumerate() {
+            let earliest = Duration::from_millis(100) * (i.saturating_sub(9) as u32);
+            assert!(
+                *sent + Duration::from_millis(10) >= earliest,
+                "#{i} at {sent:?}"
+            );
+            assert!(*sent <= earliest, "#{i} at {sent:?}");
+        }
+    }
+}
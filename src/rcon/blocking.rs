@@ -0,0 +1,202 @@
+//! Blocking implementation of the RCON protocol.
+//!
+//! Uses [std::net::TcpStream] for sending and receiving TCP data.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::*;
+use crate::DEFAULT_TIMEOUT;
+
+/// A blocking RCON client using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+}
+
+impl RconClient {
+    /// Connect to the given address and authenticate with the given password.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn connect(addr: impl ToSocketAddrs, password: &str) -> io::Result<Self> {
+        Self::connect_with_timeout(addr, password, Some(DEFAULT_TIMEOUT))
+    }
+
+    /// Connect to the given address and authenticate with the given password,
+    /// with an optional timeout applied to the connection and to every read.
+    ///
+    /// If the password is rejected by the server, an error of kind
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) is returned.
+    pub fn connect_with_timeout(
+        addr: impl ToSocketAddrs,
+        password: &str,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let stream = match timeout {
+            Some(timeout) => {
+                let mut last_err = None;
+                let mut stream = None;
+                for addr in addr.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&addr, timeout) {
+                        Ok(s) => {
+                            stream = Some(s);
+                            break;
+                        }
+                        Err(e) => last_err = Some(e),
+                    }
+                }
+                stream.ok_or_else(|| {
+                    last_err.unwrap_or_else(|| custom_io_error("Could not resolve RCON address."))
+                })?
+            }
+            None => TcpStream::connect(addr)?,
+        };
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+
+        let mut client = Self { stream, next_id: 1 };
+        client.authenticate(password)?;
+
+        Ok(client)
+    }
+
+    /// Send a packet with a new request ID, returning that ID.
+    fn send(&mut self, kind: i32, payload: &str) -> io::Result<i32> {
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+
+        self.stream
+            .write_all(&Packet::new(id, kind, payload).to_bytes())?;
+        Ok(id)
+    }
+
+    /// Receive a single packet.
+    fn recv(&mut self) -> io::Result<Packet> {
+        let mut header = [0; 4];
+        self.stream.read_exact(&mut header)?;
+
+        let mut body = vec![0; Packet::parse_length(header)?];
+        self.stream.read_exact(&mut body)?;
+
+        Packet::from_body(&body)
+    }
+
+    /// Send the login packet and wait for the server to accept or reject it.
+    fn authenticate(&mut self, password: &str) -> io::Result<()> {
+        let id = self.send(SERVERDATA_AUTH, password)?;
+
+        loop {
+            let packet = self.recv()?;
+            if packet.kind != SERVERDATA_AUTH_RESPONSE {
+                continue;
+            }
+            match packet.id {
+                AUTH_FAILURE_ID => return Err(auth_failed()),
+                response_id if response_id == id => return Ok(()),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Run a console command on the server, returning its output.
+    ///
+    /// Commands without output return an empty string. Long outputs split by
+    /// the server across several packets are reassembled.
+    ///
+    /// A dummy request is sent after the command to detect the end of the output.
+    /// If the server does not answer it, the output ends on the first read timeout
+    /// after receiving some output: without a timeout, this call never returns.
+    ///
+    /// Commands longer than [`MAX_COMMAND_PAYLOAD`] bytes fail with an error of
+    /// kind [`InvalidInput`](io::ErrorKind::InvalidInput), without being sent.
+    pub fn command(&mut self, cmd: &str) -> io::Result<String> {
+        check_command(cmd)?;
+        let id = self.send(SERVERDATA_EXECCOMMAND, cmd)?;
+        let end_id = self.send(SERVERDATA_RESPONSE_VALUE, "")?;
+
+        let mut response = Response::new(id, end_id);
+        loop {
+            match self.recv() {
+                Ok(packet) if response.push(&packet) => break,
+                Ok(_) => continue,
+                Err(e) if response.ends_on(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(response.into_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{fragmented_response, spawn_stub, spawn_stub_with};
+    use super::RconClient;
+
+    #[test]
+    fn test_auth_success() {
+        let addr = spawn_stub("password");
+        RconClient::connect(addr, "password").unwrap();
+    }
+
+    #[test]
+    fn test_auth_failure() {
+        let addr = spawn_stub("password");
+        let err = RconClient::connect(addr, "wrong").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_command() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").unwrap();
+
+        assert_eq!(client.command("list").unwrap(), "Echo: list");
+        assert_eq!(client.command("say hi").unwrap(), "Echo: say hi");
+        assert_eq!(client.command("").unwrap(), "");
+    }
+
+    #[test]
+    fn test_command_too_long() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").unwrap();
+
+        let longest = "a".repeat(super::MAX_COMMAND_PAYLOAD);
+        assert_eq!(
+            client.command(&longest).unwrap(),
+            format!("Echo: {longest}")
+        );
+        let err = client.command(&format!("{longest}a")).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        // Nothing was sent, the client is still usable
+        assert_eq!(client.command("list").unwrap(), "Echo: list");
+    }
+
+    #[test]
+    fn test_fragmented_command() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").unwrap();
+
+        assert_eq!(
+            client.command("fragmented 10000").unwrap(),
+            fragmented_response(10000)
+        );
+        assert_eq!(client.command("list").unwrap(), "Echo: list");
+    }
+
+    #[test]
+    fn test_fragmented_command_without_dummy() {
+        let addr = spawn_stub_with("password", false);
+        let mut client = RconClient::connect(addr, "password").unwrap();
+
+        assert_eq!(
+            client.command("fragmented 10000").unwrap(),
+            fragmented_response(10000)
+        );
+        assert_eq!(client.command("list").unwrap(), "Echo: list");
+    }
+}
@@ -0,0 +1,384 @@
+//! Implementation of the [RCON protocol](https://wiki.vg/RCON) used by Minecraft servers
+//! to run console commands remotely.
+//!
+//! RCON must be enabled on the server with `enable-rcon=true` and a non-empty
+//! `rcon.password` in the `server.properties` file.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::rcon;
+//! let mut client = rcon::blocking::RconClient::connect("127.0.0.1:25575", "password")?;
+//! let output = client.command("list")?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Packet format
+//!
+//! | Field name | Field type | Notes                                 |
+//! |------------|------------|---------------------------------------|
+//! | Length     | [`i32`]    | Length of the remainder of the packet |
+//! | Request ID | [`i32`]    | Echoed back by the server             |
+//! | Type       | [`i32`]    | See the `SERVERDATA_*` constants      |
+//! | Payload    | Varies     | Null-terminated string                |
+//! | Padding    | [`u8`]     | Always `0`                            |
+//!
+//! All integers are little-endian.
+
+pub mod blocking;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::io;
+
+use bytes::{Buf, BufMut};
+
+use crate::{custom_io_error, not_enough_data};
+
+/// Default RCON port for a Minecraft server.
+pub const DEFAULT_PORT: u16 = 25575;
+
+/// Type of a login packet
+pub const SERVERDATA_AUTH: i32 = 3;
+/// Type of a login response packet
+pub const SERVERDATA_AUTH_RESPONSE: i32 = 2;
+/// Type of a command packet
+pub const SERVERDATA_EXECCOMMAND: i32 = 2;
+/// Type of a command response packet
+pub const SERVERDATA_RESPONSE_VALUE: i32 = 0;
+
+/// Request ID sent back by the server when authentication fails
+const AUTH_FAILURE_ID: i32 = -1;
+
+/// Size of the request ID, type and both null bytes, in bytes
+const PACKET_OVERHEAD: usize = 4 + 4 + 2;
+/// Maximum payload length of a response packet sent by the server, in bytes
+const MAX_RESPONSE_PAYLOAD: usize = 4096;
+/// Maximum payload length of a command packet accepted by the server, in bytes
+pub const MAX_COMMAND_PAYLOAD: usize = 1446;
+
+/// A single RCON packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    /// Request ID, chosen by the client and echoed back by the server
+    pub id: i32,
+    /// Packet type, one of the `SERVERDATA_*` constants
+    pub kind: i32,
+    /// Packet payload, usually UTF-8 text
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    /// Build a new packet
+    pub fn new(id: i32, kind: i32, payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            id,
+            kind,
+            payload: payload.into(),
+        }
+    }
+
+    /// Encode this packet to its wire format, including the length prefix.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::rcon::{Packet, SERVERDATA_EXECCOMMAND};
+    /// assert_eq!(
+    ///     Packet::new(1, SERVERDATA_EXECCOMMAND, "list").to_bytes(),
+    ///     b"\x0e\0\0\0\x01\0\0\0\x02\0\0\0list\0\0",
+    /// );
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = PACKET_OVERHEAD + self.payload.len();
+        let mut res = Vec::with_capacity(4 + len);
+        res.put_i32_le(len as i32);
+        res.put_i32_le(self.id);
+        res.put_i32_le(self.kind);
+        res.put_slice(&self.payload);
+        res.put_slice(&[0, 0]);
+        res
+    }
+
+    /// Parse the length prefix of a packet, returning the length of the remainder of the packet.
+    ///
+    /// Fails if the length is too small to hold a packet, or larger than the
+    /// maximum size of a server response.
+    pub fn parse_length(header: [u8; 4]) -> io::Result<usize> {
+        let len = i32::from_le_bytes(header);
+        if len < PACKET_OVERHEAD as i32 || len as usize > PACKET_OVERHEAD + MAX_RESPONSE_PAYLOAD {
+            return Err(custom_io_error("Invalid RCON packet length."));
+        }
+        Ok(len as usize)
+    }
+
+    /// Parse a packet from its body, without the length prefix.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::rcon::{Packet, SERVERDATA_RESPONSE_VALUE};
+    /// assert_eq!(
+    ///     Packet::from_body(b"\x01\0\0\0\0\0\0\0Hello\0\0")?,
+    ///     Packet::new(1, SERVERDATA_RESPONSE_VALUE, "Hello"),
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_body(mut body: &[u8]) -> io::Result<Self> {
+        if body.len() < PACKET_OVERHEAD {
+            return Err(not_enough_data());
+        }
+
+        let id = body.get_i32_le();
+        let kind = body.get_i32_le();
+        let payload = body
+            .split(|&b| b == b'\0')
+            .next()
+            .ok_or_else(not_enough_data)?;
+
+        Ok(Self {
+            id,
+            kind,
+            payload: payload.to_vec(),
+        })
+    }
+}
+
+/// Assembles a command response split across several packets.
+///
+/// Responses longer than the maximum packet payload are split by the server.
+/// Since fragments carry no continuation marker, the client sends a dummy
+/// request right after the command: the server handles requests in order, so
+/// the response to the dummy request marks the end of the command response.
+#[derive(Debug)]
+struct Response {
+    id: i32,
+    end_id: i32,
+    payload: Vec<u8>,
+    fragments: usize,
+}
+
+impl Response {
+    /// Start assembling the response to command `id`, terminated by the
+    /// response to the dummy request `end_id`.
+    fn new(id: i32, end_id: i32) -> Self {
+        Self {
+            id,
+            end_id,
+            payload: Vec::new(),
+            fragments: 0,
+        }
+    }
+
+    /// Feed a received packet, returning `true` once the response is complete.
+    ///
+    /// Packets answering other requests are ignored.
+    fn push(&mut self, packet: &Packet) -> bool {
+        if packet.id == self.end_id {
+            return true;
+        }
+        if packet.id == self.id && packet.kind == SERVERDATA_RESPONSE_VALUE {
+            self.payload.extend_from_slice(&packet.payload);
+            self.fragments += 1;
+        }
+        false
+    }
+
+    /// Whether a receive error can end the response: servers that do not answer
+    /// the dummy request are detected by a read timeout after the first fragment.
+    fn ends_on(&self, err: &io::Error) -> bool {
+        self.fragments > 0
+            && matches!(
+                err.kind(),
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+            )
+    }
+
+    /// Decode the assembled response.
+    fn into_string(self) -> String {
+        match String::from_utf8(self.payload) {
+            Ok(s) => s,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }
+    }
+}
+
+/// Error returned when the server rejects the RCON password
+fn auth_failed() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "RCON authentication failed: wrong password.",
+    )
+}
+
+/// Check that a command fits in a single packet, as servers drop the
+/// connection on longer ones.
+fn check_command(cmd: &str) -> io::Result<()> {
+    if cmd.len() > MAX_COMMAND_PAYLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "RCON command too long: {} bytes, the maximum is {MAX_COMMAND_PAYLOAD}.",
+                cmd.len()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    /// Read a packet from a stream
+    fn read_packet(stream: &mut TcpStream) -> io::Result<Packet> {
+        let mut header = [0; 4];
+        stream.read_exact(&mut header)?;
+        let mut body = vec![0; Packet::parse_length(header)?];
+        stream.read_exact(&mut body)?;
+        Packet::from_body(&body)
+    }
+
+    /// Response of the stub to the `fragmented <len>` command
+    pub(crate) fn fragmented_response(len: usize) -> String {
+        (b'a'..=b'z').map(char::from).cycle().take(len).collect()
+    }
+
+    /// Spawn an in-process RCON stub accepting a single connection, authenticating
+    /// it with `password` and answering every command with `"Echo: <command>"`.
+    ///
+    /// The `fragmented <len>` command is answered with [`fragmented_response`],
+    /// split across as many packets as needed, with an empty packet answering
+    /// no request in the middle. Dummy requests are answered like vanilla servers
+    /// do only if `answer_dummy` is set.
+    pub(crate) fn spawn_stub_with(password: &'static str, answer_dummy: bool) -> SocketAddr {
+        spawn_stub_answering(password, answer_dummy, &[])
+    }
+
+    /// Spawn an in-process RCON stub like [`spawn_stub_with`], answering the
+    /// commands of `answers` with their output instead of echoing them.
+    pub(crate) fn spawn_stub_answering(
+        password: &'static str,
+        answer_dummy: bool,
+        answers: &'static [(&'static str, &'static str)],
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Ok(packet) = read_packet(&mut stream) {
+                let command = String::from_utf8_lossy(&packet.payload).into_owned();
+                let responses = match packet.kind {
+                    SERVERDATA_AUTH if command == password => {
+                        vec![Packet::new(packet.id, SERVERDATA_AUTH_RESPONSE, "")]
+                    }
+                    SERVERDATA_AUTH => {
+                        vec![Packet::new(AUTH_FAILURE_ID, SERVERDATA_AUTH_RESPONSE, "")]
+                    }
+                    SERVERDATA_EXECCOMMAND => match command.strip_prefix("fragmented ") {
+                        Some(len) => {
+                            let response = fragmented_response(len.parse().unwrap());
+                            let mut fragments = response
+                                .as_bytes()
+                                .chunks(MAX_RESPONSE_PAYLOAD)
+                                .map(|chunk| {
+                                    Packet::new(packet.id, SERVERDATA_RESPONSE_VALUE, chunk)
+                                })
+                                .collect::<Vec<_>>();
+                            fragments.insert(1, Packet::new(0, SERVERDATA_RESPONSE_VALUE, ""));
+                            fragments
+                        }
+                        None => vec![Packet::new(
+                            packet.id,
+                            SERVERDATA_RESPONSE_VALUE,
+                            if let Some((_, output)) =
+                                answers.iter().find(|(answered, _)| *answered == command)
+                            {
+                                output.to_string()
+                            } else if command.is_empty() {
+                                String::new()
+                            } else {
+                                format!("Echo: {}", command)
+                            },
+                        )],
+                    },
+                    kind if answer_dummy => vec![Packet::new(
+                        packet.id,
+                        SERVERDATA_RESPONSE_VALUE,
+                        format!("Unknown request {:x}", kind),
+                    )],
+                    _ => vec![],
+                };
+                for response in responses {
+                    stream.write_all(&response.to_bytes()).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    /// Spawn an in-process RCON stub behaving like a vanilla server, see [`spawn_stub_with`].
+    pub(crate) fn spawn_stub(password: &'static str) -> SocketAddr {
+        spawn_stub_with(password, true)
+    }
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = Packet::new(42, SERVERDATA_AUTH, "password");
+        let bytes = packet.to_bytes();
+
+        let len = Packet::parse_length(bytes[..4].try_into().unwrap()).unwrap();
+        assert_eq!(len, bytes.len() - 4);
+        assert_eq!(Packet::from_body(&bytes[4..]).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_invalid_length() {
+        assert!(Packet::parse_length(9i32.to_le_bytes()).is_err());
+        assert!(Packet::parse_length((-1i32).to_le_bytes()).is_err());
+        assert!(Packet::parse_length(4107i32.to_le_bytes()).is_err());
+        assert!(Packet::parse_length(4106i32.to_le_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_response_assembly() {
+        let mut response = Response::new(1, 2);
+        assert!(!response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, "Hello, ")));
+        assert!(!response.push(&Packet::new(0, SERVERDATA_RESPONSE_VALUE, "keep-alive")));
+        assert!(!response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, "")));
+        assert!(!response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, "world!")));
+        assert!(response.push(&Packet::new(
+            2,
+            SERVERDATA_RESPONSE_VALUE,
+            "Unknown request 0"
+        )));
+        assert_eq!(response.into_string(), "Hello, world!");
+    }
+
+    #[test]
+    fn test_response_split_code_point() {
+        let mut response = Response::new(1, 2);
+        let text = "\u{e9}t\u{e9}".as_bytes();
+        response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, &text[..1]));
+        response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, &text[1..]));
+        assert_eq!(response.into_string(), "\u{e9}t\u{e9}");
+    }
+
+    #[test]
+    fn test_response_timeout() {
+        let timeout = io::Error::from(io::ErrorKind::WouldBlock);
+
+        let mut response = Response::new(1, 2);
+        assert!(!response.ends_on(&timeout));
+        response.push(&Packet::new(1, SERVERDATA_RESPONSE_VALUE, "Hello"));
+        assert!(response.ends_on(&timeout));
+        assert!(!response.ends_on(&io::Error::from(io::ErrorKind::ConnectionReset)));
+    }
+
+    #[test]
+    fn test_truncated_body() {
+        assert!(Packet::from_body(b"\x01\0\0\0\0\0\0\0\0").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the RCON protocol.
+//!
+//! Uses [`tokio::net::TcpStream`](https://docs.rs/tokio/*/tokio/net/struct.TcpStream.html) for sending and receiving TCP data
+//!
+//! The client is cancellation-safe: a dropped [`command`](RconClient::command)
+//! future leaves partially sent and received packets buffered in the client,
+//! and the next command skips the packets answering earlier requests, as
+//! request IDs only increase. Once they are exhausted, after about 2^31
+//! requests, commands fail and the client must reconnect.
+
+use ::tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpStream, ToSocketAddrs},
+    time::timeout,
+};
+use bytes::BytesMut;
+use std::{future::Future, io, time::Duration};
+
+use super::*;
+use crate::DEFAULT_TIMEOUT;
+
+/// An asynchronous RCON client using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct RconClient {
+    stream: TcpStream,
+    next_id: i32,
+    timeout: Option<Duration>,
+    /// Data left to send, if a send was cancelled
+    outgoing: BytesMut,
+    /// Data received but not parsed yet, if a receive was cancelled
+    incoming: BytesMut,
+}
+
+/// Run a future with an optional timeout.
+async fn with_timeout<T>(
+    duration: Option<Duration>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    if let Some(duration) = duration {
+        timeout(duration, fut)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "RCON async call timed out."))?
+    } else {
+        fut.await
+    }
+}
+
+impl RconClient {
+    /// Connect to the given address and authenticate with the given password.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn connect(addr: impl ToSocketAddrs, password: &str) -> io::Result<Self> {
+        Self::connect_with_timeout(addr, password, Some(DEFAULT_TIMEOUT)).await
+    }
+
+    /// Connect to the given address and authenticate with the given password,
+    /// with an optional timeout applied to the connection and to every read and
+    /// write.
+    ///
+    /// If the password is rejected by the server, an error of kind
+    /// [`PermissionDenied`](io::ErrorKind::PermissionDenied) is returned.
+    pub async fn connect_with_timeout(
+        addr: impl ToSocketAddrs,
+        password: &str,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let stream = with_timeout(timeout, TcpStream::connect(addr)).await?;
+
+        let mut client = Self {
+            stream,
+            next_id: 1,
+            timeout,
+            outgoing: BytesMut::new(),
+            incoming: BytesMut::new(),
+        };
+        client.authenticate(password).await?;
+
+        Ok(client)
+    }
+
+    /// A new request ID, greater than all the previous ones. Fails once the
+    /// request IDs are exhausted.
+    fn new_id(&mut self) -> io::Result<i32> {
+        let id = self.next_id;
+        self.next_id = id.checked_add(1).ok_or_else(|| {
+            custom_io_error("RCON request IDs are exhausted, the client must reconnect.")
+        })?;
+        Ok(id)
+    }
+
+    /// Send a packet with the given request ID.
+    async fn send(&mut self, id: i32, kind: i32, payload: &str) -> io::Result<()> {
+        self.outgoing
+            .extend_from_slice(&Packet::new(id, kind, payload).to_bytes());
+        // The rest of a cancelled send goes out first
+        with_timeout(self.timeout, self.stream.write_all_buf(&mut self.outgoing)).await
+    }
+
+    /// Receive a single packet.
+    async fn recv(&mut self) -> io::Result<Packet> {
+        let duration = self.timeout;
+        with_timeout(duration, async {
+            loop {
+                if let Some(packet) = self.take_packet()? {
+                    return Ok(packet);
+                }
+                if self.stream.read_buf(&mut self.incoming).await? == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+        })
+        .await
+    }
+
+    /// Split the first packet off the received data, if it is complete.
+    fn take_packet(&mut self) -> io::Result<Option<Packet>> {
+        let Some(&header) = self.incoming.first_chunk::<4>() else {
+            return Ok(None);
+        };
+        let len = Packet::parse_length(header)?;
+        if self.incoming.len() < 4 + len {
+            return Ok(None);
+        }
+        let packet = self.incoming.split_to(4 + len);
+        Packet::from_body(&packet[4..]).map(Some)
+    }
+
+    /// Send the login packet and wait for the server to accept or reject it.
+    async fn authenticate(&mut self, password: &str) -> io::Result<()> {
+        let id = self.new_id()?;
+        self.send(id, SERVERDATA_AUTH, password).await?;
+
+        loop {
+            let packet = self.recv().await?;
+            if packet.kind != SERVERDATA_AUTH_RESPONSE {
+                continue;
+            }
+            match packet.id {
+                AUTH_FAILURE_ID => return Err(auth_failed()),
+                response_id if response_id == id => return Ok(()),
+                _ => continue,
+            }
+        }
+    }
+
+    /// Run a console command on the server, returning its output.
+    ///
+    /// Commands without output return an empty string. Long outputs split by
+    /// the server across several packets are reassembled.
+    ///
+    /// A dummy request is sent after the command to detect the end of the output.
+    /// If the server does not answer it, the output ends on the first read timeout
+    /// after receiving some output: without a timeout, this call never returns.
+    ///
+    /// Commands longer than [`MAX_COMMAND_PAYLOAD`] bytes fail with an error of
+    /// kind [`InvalidInput`](io::ErrorKind::InvalidInput), without being sent.
+    pub async fn command(&mut self, cmd: &str) -> io::Result<String> {
+        check_command(cmd)?;
+        let id = self.new_id()?;
+        let end_id = self.new_id()?;
+        self.send(id, SERVERDATA_EXECCOMMAND, cmd).await?;
+        self.send(end_id, SERVERDATA_RESPONSE_VALUE, "").await?;
+
+        let mut response = Response::new(id, end_id);
+        loop {
+            match self.recv().await {
+                Ok(packet) if response.push(&packet) => break,
+                Ok(_) => continue,
+                Err(e) if response.ends_on(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(response.into_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::oneshot,
+    };
+    use std::{io, time::Duration};
+
+    use super::super::tests::{fragmented_response, spawn_stub, spawn_stub_with};
+    use super::super::{Packet, SERVERDATA_AUTH_RESPONSE, SERVERDATA_RESPONSE_VALUE};
+    use super::RconClient;
+
+    /// Read a packet from a stream
+    async fn read_packet(stream: &mut TcpStream) -> io::Result<Packet> {
+        let mut header = [0; 4];
+        stream.read_exact(&mut header).await?;
+        let mut body = vec![0; Packet::parse_length(header)?];
+        stream.read_exact(&mut body).await?;
+        Packet::from_body(&body)
+    }
+
+    #[tokio::test]
+    async fn test_auth_success() {
+        let addr = spawn_stub("password");
+        RconClient::connect(addr, "password").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure() {
+        let addr = spawn_stub("password");
+        let err = RconClient::connect(addr, "wrong").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_command() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+        assert_eq!(client.command("say hi").await.unwrap(), "Echo: say hi");
+        assert_eq!(client.command("").await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_command_too_long() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+
+        let longest = "a".repeat(super::MAX_COMMAND_PAYLOAD);
+        assert_eq!(
+            client.command(&longest).await.unwrap(),
+            format!("Echo: {longest}")
+        );
+        let err = client.command(&format!("{longest}a")).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        // Nothing was sent, the client is still usable
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+    }
+
+    #[tokio::test]
+    async fn test_request_ids_exhausted() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+
+        // A command and its dummy packet take the last two usable IDs
+        client.next_id = i32::MAX - 2;
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+        let err = client.command("list").await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+        // Commands are not sent without an ID for their dummy packet
+        client.next_id = i32::MAX - 1;
+        assert!(client.command("list").await.is_err());
+        assert_eq!(client.next_id, i32::MAX);
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_command() {
+        let addr = spawn_stub("password");
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+
+        assert_eq!(
+            client.command("fragmented 10000").await.unwrap(),
+            fragmented_response(10000)
+        );
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+    }
+
+    #[tokio::test]
+    async fn test_fragmented_command_without_dummy() {
+        let addr = spawn_stub_with("password", false);
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+
+        assert_eq!(
+            client.command("fragmented 10000").await.unwrap(),
+            fragmented_response(10000)
+        );
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_command() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (resume, resumed) = oneshot::channel();
+
+        let server = ::tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let auth = read_packet(&mut stream).await.unwrap();
+            let response = Packet::new(auth.id, SERVERDATA_AUTH_RESPONSE, "");
+            stream.write_all(&response.to_bytes()).await.unwrap();
+
+            // Stall in the middle of the response to the first command
+            let command = read_packet(&mut stream).await.unwrap();
+            let dummy = read_packet(&mut stream).await.unwrap();
+            let stale = Packet::new(command.id, SERVERDATA_RESPONSE_VALUE, "Stale").to_bytes();
+            stream.write_all(&stale[..6]).await.unwrap();
+            resumed.await.unwrap();
+            stream.write_all(&stale[6..]).await.unwrap();
+            let end = Packet::new(dummy.id, SERVERDATA_RESPONSE_VALUE, "");
+            stream.write_all(&end.to_bytes()).await.unwrap();
+
+            let command = read_packet(&mut stream).await.unwrap();
+            let next_dummy = read_packet(&mut stream).await.unwrap();
+            assert!(auth.id < dummy.id && dummy.id < command.id && command.id < next_dummy.id);
+            let output = format!("Echo: {}", String::from_utf8_lossy(&command.payload));
+            for response in [
+                Packet::new(command.id, SERVERDATA_RESPONSE_VALUE, output),
+                Packet::new(next_dummy.id, SERVERDATA_RESPONSE_VALUE, ""),
+            ] {
+                stream.write_all(&response.to_bytes()).await.unwrap();
+            }
+        });
+
+        let mut client = RconClient::connect(addr, "password").await.unwrap();
+        let cancelled = ::tokio::time::timeout(Duration::from_millis(100), client.command("stall"));
+        assert!(cancelled.await.is_err());
+        resume.send(()).unwrap();
+
+        // The rest of the stale response is skipped
+        assert_eq!(client.command("list").await.unwrap(), "Echo: list");
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Reverse DNS lookups of server addresses.
+//!
+//! The PTR record of the address of a server (`backend-03.hosting.example`)
+//! is often more telling than the address itself in reports:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::rdns;
+//! match rdns::resolve_ptr("203.0.113.7".parse().unwrap())? {
+//!     Some(name) => println!("203.0.113.7 is {name}"),
+//!     None => println!("203.0.113.7 has no PTR record"),
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Lookups go through the resolver of the system, which may be slow to
+//! answer. A [`ReverseDns`] bounds their duration, so that they can be made
+//! after each query without stalling it.
+
+use std::{
+    fmt, io,
+    net::{IpAddr, SocketAddr},
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+/// Default timeout of a [`ReverseDns`] lookup
+pub const DEFAULT_RDNS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Resolves the PTR record of an address
+pub trait PtrResolver: fmt::Debug + Send + Sync + 'static {
+    /// The name of the PTR record of the address, or `None` if it has none.
+    fn resolve_ptr(&self, ip: IpAddr) -> io::Result<Option<String>>;
+}
+
+/// The resolver of the system, used through `getnameinfo`
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemResolver;
+
+impl PtrResolver for SystemResolver {
+    fn resolve_ptr(&self, ip: IpAddr) -> io::Result<Option<String>> {
+        let (name, _) = dns_lookup::getnameinfo(&SocketAddr::new(ip, 0), 0)?;
+        // Addresses without a PTR record are formatted numerically
+        if name.parse::<IpAddr>().is_ok() {
+            Ok(None)
+        } else {
+            Ok(Some(name))
+        }
+    }
+}
+
+/// Resolve the PTR record of an address with the resolver of the system.
+///
+/// Returns `None` if the address has no PTR record.
+pub fn resolve_ptr(ip: IpAddr) -> io::Result<Option<String>> {
+    SystemResolver.resolve_ptr(ip)
+}
+
+/// Resolve the PTR record of an address with the resolver of the system,
+/// on the blocking threads of the `tokio` runtime.
+///
+/// Returns `None` if the address has no PTR record.
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub async fn resolve_ptr_async(ip: IpAddr) -> io::Result<Option<String>> {
+    ::tokio::task::spawn_blocking(move || resolve_ptr(ip))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::new(io::ErrorKind::Interrupted, e)))
+}
+
+/// Reverse DNS lookups with a bounded duration, to enrich query results.
+///
+/// Lookups which fail or time out are treated as missing PTR records. The
+/// lookup keeps running in the background after a timeout, but its result is
+/// discarded.
+#[derive(Debug, Clone)]
+pub struct ReverseDns {
+    resolver: Arc<dyn PtrResolver>,
+    timeout: Duration,
+}
+
+impl Default for ReverseDns {
+    /// Use the [system resolver](SystemResolver), with the
+    /// [default timeout](DEFAULT_RDNS_TIMEOUT).
+    fn default() -> Self {
+        Self::with_resolver(SystemResolver)
+    }
+}
+
+impl ReverseDns {
+    /// Use the given resolver, with the [default timeout](DEFAULT_RDNS_TIMEOUT).
+    pub fn with_resolver(resolver: impl PtrResolver) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            timeout: DEFAULT_RDNS_TIMEOUT,
+        }
+    }
+
+    /// Set the max duration of a lookup.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The name of the PTR record of the address, if it has one and it was
+    /// resolved before the timeout.
+    pub fn lookup(&self, ip: IpAddr) -> Option<String> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let resolver = self.resolver.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send(resolver.resolve_ptr(ip));
+        });
+        rx.recv_timeout(self.timeout).ok()?.ok()?
+    }
+
+    /// The name of the PTR record of the address, if it has one and it was
+    /// resolved before the timeout, resolved on the blocking threads of the
+    /// `tokio` runtime.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+    pub async fn lookup_async(&self, ip: IpAddr) -> Option<String> {
+        let resolver = self.resolver.clone();
+        let lookup = ::tokio::task::spawn_blocking(move || resolver.resolve_ptr(ip));
+        ::tokio::time::timeout(self.timeout, lookup)
+            .await
+            .ok()?
+            .ok()?
+            .ok()?
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// A resolver answering from a fixed table, after a delay
+    #[derive(Debug, Default)]
+    pub(crate) struct StubResolver {
+        pub(crate) records: Vec<(IpAddr, &'static str)>,
+        pub(crate) delay: Duration,
+    }
+
+    impl PtrResolver for StubResolver {
+        fn resolve_ptr(&self, ip: IpAddr) -> io::Result<Option<String>> {
+            std::thread::sleep(self.delay);
+            if ip.is_unspecified() {
+                return Err(io::ErrorKind::Other.into());
+            }
+            Ok(self
+                .records
+                .iter()
+                .find(|(record, _)| *record == ip)
+                .map(|(_, name)| name.to_string()))
+        }
+    }
+
+    pub(crate) fn stub(delay: Duration) -> ReverseDns {
+        ReverseDns::with_resolver(StubResolver {
+            records: vec![([127, 0, 0, 1].into(), "backend-03.hosting.example")],
+            delay,
+        })
+        .timeout(Duration::from_millis(200))
+    }
+
+    #[test]
+    fn test_lookup() {
+        let rdns = stub(Duration::ZERO);
+        assert_eq!(
+            rdns.lookup([127, 0, 0, 1].into()).as_deref(),
+            Some("backend-03.hosting.example")
+        );
+        // Missing records and failed lookups
+        assert_eq!(rdns.lookup([127, 0, 0, 2].into()), None);
+        assert_eq!(rdns.lookup([0, 0, 0, 0].into()), None);
+    }
+
+    #[test]
+    fn test_lookup_timeout() {
+        let rdns = stub(Duration::from_secs(1));
+        let start = Instant::now();
+        assert_eq!(rdns.lookup([127, 0, 0, 1].into()), None);
+        assert!(start.elapsed() < Duration::from_millis(800));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_lookup_async() {
+        let rdns = stub(Duration::ZERO);
+        assert_eq!(
+            rdns.lookup_async([127, 0, 0, 1].into()).await.as_deref(),
+            Some("backend-03.hosting.example")
+        );
+        assert_eq!(rdns.lookup_async([127, 0, 0, 2].into()).await, None);
+
+        let rdns = stub(Duration::from_secs(1));
+        let start = Instant::now();
+        assert_eq!(rdns.lookup_async([127, 0, 0, 1].into()).await, None);
+        assert!(start.elapsed() < Duration::from_millis(800));
+    }
+}
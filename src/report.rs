@@ -0,0 +1,288 @@
+//! Consistency checks between the Query and Server List Ping protocols.
+//!
+//! Both protocols describe the same server, but they are configured
+//! separately: a server may send a different MOTD, or advertise a stale port,
+//! in one of them. [`compare`] queries a server with both protocols and lists
+//! the differences.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::report;
+//! let report = report::compare("127.0.0.1")?;
+//! for discrepancy in &report.discrepancies {
+//!     println!(
+//!         "{:?}: {} (query) != {} (SLP)",
+//!         discrepancy.field, discrepancy.query, discrepancy.slp
+//!     );
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::blocking::QueryClient;
+use crate::slp::{blocking::PingClient, SlpStatus};
+use crate::{motd, split_address, FullStat};
+
+/// A protocol used to get the status of a server
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Protocol {
+    /// The UDP Query protocol
+    Query,
+    /// The TCP Server List Ping protocol
+    Slp,
+}
+
+/// A status field reported by both protocols
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Field {
+    /// Message of the day, compared without formatting codes
+    Motd,
+    /// Number of players online
+    PlayersOnline,
+    /// Max number of players
+    MaxPlayers,
+    /// Game version. The Query version must be part of the SLP version name,
+    /// which often contains the name of the server software as well.
+    Version,
+    /// Port advertised in the Query status, compared to the port answering pings
+    Port,
+}
+
+/// A field with different values in the two protocols
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Discrepancy {
+    /// The field which differs
+    pub field: Field,
+    /// Value reported by the Query protocol
+    pub query: String,
+    /// Value reported by the Server List Ping protocol
+    pub slp: String,
+}
+
+/// Outcome of the status request of a single protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Outcome {
+    /// The server answered
+    Answered {
+        /// Time taken by the whole status request, including the Query handshake
+        latency: Duration,
+    },
+    /// The request failed
+    Failed {
+        /// Description of the error
+        error: String,
+    },
+}
+
+/// Differences between the statuses of a server in the two protocols
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConsistencyReport {
+    /// Outcome of the Query full status request
+    pub query: Outcome,
+    /// Outcome of the Server List Ping
+    pub slp: Outcome,
+    /// Fields with different values. Always empty if a protocol failed.
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ConsistencyReport {
+    /// Whether both protocols answered, with the same values.
+    pub fn is_consistent(&self) -> bool {
+        self.failed().is_empty() && self.discrepancies.is_empty()
+    }
+
+    /// The protocols which failed entirely.
+    pub fn failed(&self) -> Vec<Protocol> {
+        let mut res = Vec::new();
+        if matches!(self.query, Outcome::Failed { .. }) {
+            res.push(Protocol::Query);
+        }
+        if matches!(self.slp, Outcome::Failed { .. }) {
+            res.push(Protocol::Slp);
+        }
+        res
+    }
+}
+
+/// Compare the statuses of a server in the Query and Server List Ping protocols.
+///
+/// If no port is specified in the IP address, the [default port](crate::DEFAULT_PORT)
+/// is used for both protocols. Only an invalid address returns an error: failed
+/// requests are part of the report.
+pub fn compare(ip: &str) -> io::Result<ConsistencyReport> {
+    let (ip, port) = split_address(ip)?;
+    Ok(compare_ports(ip, port, port))
+}
+
+/// Compare the statuses of a server whose Query port (`query.port` in
+/// `server.properties`) differs from its game port.
+///
+/// Both requests are sent concurrently, so that the latency of one protocol
+/// does not depend on the other.
+pub fn compare_ports(ip: &str, query_port: u16, slp_port: u16) -> ConsistencyReport {
+    let (query, slp) = std::thread::scope(|s| {
+        let query = s.spawn(|| {
+            timed(|| {
+                let client = QueryClient::new_with_port(ip, query_port)?;
+                let token = client.handshake()?;
+                client.full_stat(token)
+            })
+        });
+        let slp = timed(|| PingClient::new_with_port(ip, slp_port)?.status());
+        (query.join().expect("The query thread does not panic"), slp)
+    });
+
+    let discrepancies = match (&query, &slp) {
+        (Ok((query, _)), Ok((slp, _))) => discrepancies(query, slp, slp_port),
+        _ => Vec::new(),
+    };
+
+    ConsistencyReport {
+        query: outcome(query),
+        slp: outcome(slp),
+        discrepancies,
+    }
+}
+
+/// List the fields with different values in the two statuses of a server,
+/// whose game port is `port`.
+pub fn discrepancies(query: &FullStat, slp: &SlpStatus, port: u16) -> Vec<Discrepancy> {
+    let mut res = Vec::new();
+    let mut check = |field, query: String, slp: String, same: bool| {
+        if !same {
+            res.push(Discrepancy { field, query, slp });
+        }
+    };
+
+    let query_motd = normalize_motd(&query.hostname);
+    let slp_motd = normalize_motd(&slp.description.to_plain());
+    let same = query_motd == slp_motd;
+    check(Field::Motd, query_motd, slp_motd, same);
+
+    check(
+        Field::PlayersOnline,
+        query.numplayers.to_string(),
+        slp.players.online.to_string(),
+        query.numplayers as i64 == slp.players.online,
+    );
+    check(
+        Field::MaxPlayers,
+        query.maxplayers.to_string(),
+        slp.players.max.to_string(),
+        query.maxplayers as i64 == slp.players.max,
+    );
+    check(
+        Field::Version,
+        query.version.to_string(),
+        slp.version.name.clone(),
+        motd::strip_codes(&slp.version.name).contains(query.version.as_str()),
+    );
+    check(
+        Field::Port,
+        query.hostport.to_string(),
+        port.to_string(),
+        query.hostport == port,
+    );
+
+    res
+}
+
+/// Remove formatting codes and surrounding whitespace from each line of a MOTD.
+fn normalize_motd(motd: &str) -> String {
+    motd::strip_codes(motd)
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Run a status request, measuring its duration.
+fn timed<T>(request: impl FnOnce() -> io::Result<T>) -> io::Result<(T, Duration)> {
+    let start = Instant::now();
+    let res = request()?;
+    Ok((res, start.elapsed()))
+}
+
+fn outcome<T>(result: io::Result<(T, Duration)>) -> Outcome {
+    match result {
+        Ok((_, latency)) => Outcome::Answered { latency },
+        Err(e) => Outcome::Failed {
+            error: e.to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{sample_stat, sample_status, MockQueryServer, MockSlpServer};
+
+    #[test]
+    fn test_consistent() {
+        let slp = MockSlpServer::new().unwrap();
+        let mut stat = sample_stat();
+        stat.hostname = "§6A §lMinecraft Server ".into();
+        stat.hostport = slp.addr().port();
+        let query = MockQueryServer::with_stat(stat).unwrap();
+
+        let report = compare_ports("127.0.0.1", query.addr().port(), slp.addr().port());
+        assert!(report.is_consistent(), "{report:?}");
+    }
+
+    #[test]
+    fn test_disagreeing_servers() {
+        let mut status = sample_status();
+        status.description.text = "§aAnother Server".to_string();
+        status.players.max = 100;
+        status.version.name = "Paper 1.20.1".to_string();
+        let slp = MockSlpServer::with_status(status).unwrap();
+        let query = MockQueryServer::new().unwrap();
+
+        let report = compare_ports("127.0.0.1", query.addr().port(), slp.addr().port());
+        assert!(matches!(report.query, Outcome::Answered { .. }));
+        assert!(matches!(report.slp, Outcome::Answered { .. }));
+        assert!(report.failed().is_empty());
+        assert_eq!(
+            report.discrepancies,
+            [
+                Discrepancy {
+                    field: Field::Motd,
+                    query: "A Minecraft Server".to_string(),
+                    slp: "Another Server".to_string(),
+                },
+                Discrepancy {
+                    field: Field::MaxPlayers,
+                    query: "20".to_string(),
+                    slp: "100".to_string(),
+                },
+                Discrepancy {
+                    field: Field::Port,
+                    query: "25565".to_string(),
+                    slp: slp.addr().port().to_string(),
+                },
+            ]
+        );
+        assert!(!report.is_consistent());
+    }
+
+    #[test]
+    fn test_failed_protocol() {
+        let slp = MockSlpServer::new().unwrap();
+        let query = MockQueryServer::new().unwrap();
+        let query_port = query.addr().port();
+        drop(query);
+
+        let report = compare_ports("127.0.0.1", query_port, slp.addr().port());
+        assert_eq!(report.failed(), [Protocol::Query]);
+        assert!(matches!(report.slp, Outcome::Answered { .. }));
+        assert!(report.discrepancies.is_empty());
+    }
+}
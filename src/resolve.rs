@@ -0,0 +1,361 @@
+//! Resolution of server host names.
+//!
+//! The query builders resolve host names with the [resolver of the
+//! system](SystemResolver) by default. Any other resolver, like an internal
+//! service discovery API, can be used instead by implementing [`Resolve`]:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking::Query, resolve::Resolve};
+//! # use std::{io, net::SocketAddr, sync::Arc};
+//! /// Resolves `<name>.mc.internal` hosts from a static table.
+//! #[derive(Debug)]
+//! struct Inventory(Vec<(&'static str, SocketAddr)>);
+//!
+//! impl Resolve for Inventory {
+//!     fn resolve(&self, host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+//!         let name = host.strip_suffix(".mc.internal").unwrap_or(host);
+//!         Ok(self.0.iter().filter(|(n, _)| *n == name).map(|(_, addr)| *addr).collect())
+//!     }
+//! }
+//!
+//! let inventory = Arc::new(Inventory(vec![("lobby", "10.0.3.7:25565".parse().unwrap())]));
+//! let full_stat = Query::to("lobby.mc.internal").resolver(inventory).full()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! IP addresses, including IPv6 addresses with a [zone identifier](crate::zone),
+//! are used as is: resolvers are only consulted for host names.
+
+use std::{
+    fmt,
+    future::Future,
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    pin::Pin,
+};
+
+use crate::{no_address, zone};
+
+/// The future returned by [`Resolve::resolve_async`]
+pub type ResolveFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'a>>;
+
+/// Resolves the host name of a server into its addresses
+pub trait Resolve: fmt::Debug + Send + Sync {
+    /// The addresses of the host, in order of preference.
+    ///
+    /// The port is the one requested for the server, which the returned
+    /// addresses usually keep. Service discovery may return other ports.
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>>;
+
+    /// Asynchronous counterpart of [`resolve`](Self::resolve), used by the
+    /// asynchronous clients.
+    ///
+    /// By default, it calls `resolve` directly, blocking the task until it
+    /// returns: resolvers making network requests should override it.
+    fn resolve_async<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(std::future::ready(self.resolve(host, port)))
+    }
+}
+
+/// The resolver of the system, used through `getaddrinfo`
+///
+/// Asynchronous resolutions run on the blocking threads of `async-std` when
+/// its feature is enabled, and of `tokio` otherwise.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        Ok(zone::HostPort(host, port).to_socket_addrs()?.collect())
+    }
+
+    // async-std runs its blocking threads without a runtime to enter, so
+    // it is preferred when both features are enabled
+    #[cfg(feature = "async-std")]
+    fn resolve_async<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            if let Some(addr) = zone::scoped_socket_addr(host, port)? {
+                return Ok(vec![addr]);
+            }
+            let addrs = ::async_std::net::ToSocketAddrs::to_socket_addrs(&(host, port)).await?;
+            Ok(addrs.collect())
+        })
+    }
+
+    #[cfg(all(feature = "tokio", not(feature = "async-std")))]
+    fn resolve_async<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            if let Some(addr) = zone::scoped_socket_addr(host, port)? {
+                return Ok(vec![addr]);
+            }
+            Ok(::tokio::net::lookup_host((host, port)).await?.collect())
+        })
+    }
+}
+
+/// A resolver using [`hickory-resolver`](https://docs.rs/hickory-resolver/0.24),
+/// which talks to name servers directly instead of going through the system.
+///
+/// Asynchronous resolutions must run on a `tokio` runtime. Blocking
+/// resolutions run on a runtime of their own, created on the first one, and
+/// must not be made from an asynchronous task.
+#[cfg(feature = "hickory")]
+#[cfg_attr(doc, doc(cfg(feature = "hickory")))]
+pub struct HickoryResolver {
+    config: hickory_resolver::config::ResolverConfig,
+    options: hickory_resolver::config::ResolverOpts,
+    resolver: hickory_resolver::TokioAsyncResolver,
+    blocking: std::sync::OnceLock<io::Result<hickory_resolver::Resolver>>,
+}
+
+#[cfg(feature = "hickory")]
+impl HickoryResolver {
+    /// Use the given name servers and options.
+    pub fn new(
+        config: hickory_resolver::config::ResolverConfig,
+        options: hickory_resolver::config::ResolverOpts,
+    ) -> Self {
+        Self {
+            resolver: hickory_resolver::TokioAsyncResolver::tokio(config.clone(), options.clone()),
+            config,
+            options,
+            blocking: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Use the name servers and options of the system, read from
+    /// `/etc/resolv.conf` on Unix.
+    pub fn from_system_conf() -> io::Result<Self> {
+        let (config, options) = hickory_resolver::system_conf::read_system_conf()?;
+        Ok(Self::new(config, options))
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl fmt::Debug for HickoryResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HickoryResolver")
+            .field("config", &self.config)
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "hickory")]
+impl Resolve for HickoryResolver {
+    fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        let resolver = self
+            .blocking
+            .get_or_init(|| {
+                hickory_resolver::Resolver::new(self.config.clone(), self.options.clone())
+            })
+            .as_ref()
+            .map_err(|e| io::Error::new(e.kind(), e.to_string()))?;
+        let lookup = resolver.lookup_ip(host)?;
+        Ok(lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+    }
+
+    fn resolve_async<'a>(&'a self, host: &'a str, port: u16) -> ResolveFuture<'a> {
+        Box::pin(async move {
+            let lookup = self.resolver.lookup_ip(host).await?;
+            Ok(lookup.iter().map(|ip| SocketAddr::new(ip, port)).collect())
+        })
+    }
+}
+
+/// The address of an IP address host, with its zone identifier if it has one.
+fn literal(host: &str, port: u16) -> io::Result<Option<SocketAddr>> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(Some(SocketAddr::new(ip, port)));
+    }
+    zone::scoped_socket_addr(host, port)
+}
+
+/// The preferred address of a server: its host if it is an IP address, and
+/// the first address returned by the resolver otherwise.
+pub(crate) fn first_address(
+    resolver: &dyn Resolve,
+    host: &str,
+    port: u16,
+) -> io::Result<SocketAddr> {
+    match literal(host, port)? {
+        Some(addr) => Ok(addr),
+        None => resolver
+            .resolve(host, port)?
+            .into_iter()
+            .next()
+            .ok_or_else(no_address),
+    }
+}
+
+/// Like [`first_address`], resolving host names asynchronously.
+#[cfg(any(feature = "tokio", feature = "async-std"))]
+pub(crate) async fn first_address_async(
+    resolver: &dyn Resolve,
+    host: &str,
+    port: u16,
+) -> io::Result<SocketAddr> {
+    match literal(host, port)? {
+        Some(addr) => Ok(addr),
+        None => resolver
+            .resolve_async(host, port)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(no_address),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    /// A resolver answering from a programmable table, recording the hosts it
+    /// was asked to resolve
+    #[derive(Debug, Default)]
+    pub(crate) struct StubResolver {
+        records: Mutex<HashMap<String, Vec<SocketAddr>>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl StubResolver {
+        pub(crate) fn new() -> Arc<Self> {
+            Arc::new(Self::default())
+        }
+
+        /// Answer resolutions of `host` with `addrs`, whatever the requested
+        /// port, replacing the previous answer.
+        pub(crate) fn set(&self, host: &str, addrs: &[SocketAddr]) {
+            self.records
+                .lock()
+                .unwrap()
+                .insert(host.to_string(), addrs.to_vec());
+        }
+
+        /// Hosts resolved so far, in order.
+        pub(crate) fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl Resolve for StubResolver {
+        fn resolve(&self, host: &str, _port: u16) -> io::Result<Vec<SocketAddr>> {
+            self.calls.lock().unwrap().push(host.to_string());
+            match self.records.lock().unwrap().get(host) {
+                Some(addrs) => Ok(addrs.clone()),
+                None => Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Unknown host `{host}`."),
+                )),
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_address() {
+        let stub = StubResolver::new();
+        let addr = SocketAddr::from(([10, 0, 3, 7], 25565));
+        stub.set("lobby.mc.internal", &[addr, ([10, 0, 3, 8], 25565).into()]);
+
+        assert_eq!(
+            first_address(&*stub, "lobby.mc.internal", 25565).unwrap(),
+            addr
+        );
+        assert_eq!(
+            first_address(&*stub, "unknown.mc.internal", 25565)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        stub.set("empty.mc.internal", &[]);
+        assert_eq!(
+            first_address(&*stub, "empty.mc.internal", 25565)
+                .unwrap_err()
+                .to_string(),
+            no_address().to_string()
+        );
+        assert_eq!(
+            stub.calls(),
+            [
+                "lobby.mc.internal",
+                "unknown.mc.internal",
+                "empty.mc.internal"
+            ]
+        );
+
+        // IP addresses are not resolved
+        assert_eq!(
+            first_address(&*stub, "127.0.0.1", 25565).unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 25565))
+        );
+        assert_eq!(
+            first_address(&*stub, "fe80::1%7", 25565)
+                .unwrap()
+                .to_string(),
+            "[fe80::1%7]:25565"
+        );
+        assert_eq!(stub.calls().len(), 3);
+    }
+
+    #[test]
+    fn test_system_resolver() {
+        let addrs = SystemResolver.resolve("localhost", 25565).unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs
+            .iter()
+            .all(|addr| addr.ip().is_loopback() && addr.port() == 25565));
+        assert_eq!(
+            SystemResolver.resolve("fe80::1%7", 25565).unwrap()[0].to_string(),
+            "[fe80::1%7]:25565"
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_resolve_async() {
+        let addrs = SystemResolver
+            .resolve_async("localhost", 25565)
+            .await
+            .unwrap();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+
+        // Resolvers without an asynchronous implementation
+        let stub = StubResolver::new();
+        stub.set("lobby.mc.internal", &[([10, 0, 3, 7], 25565).into()]);
+        let addr = first_address_async(&*stub, "lobby.mc.internal", 25565)
+            .await
+            .unwrap();
+        assert_eq!(addr, SocketAddr::from(([10, 0, 3, 7], 25565)));
+        first_address_async(&*stub, "::1", 25565).await.unwrap();
+        assert_eq!(stub.calls(), ["lobby.mc.internal"]);
+    }
+
+    #[cfg(feature = "hickory")]
+    #[test]
+    fn test_hickory_resolver() {
+        use hickory_resolver::config::{ResolverConfig, ResolverOpts};
+
+        // Without name servers, only the hosts file is used
+        let resolver = HickoryResolver::new(ResolverConfig::new(), ResolverOpts::default());
+        let addrs = resolver.resolve("localhost", 25565).unwrap();
+        assert!(!addrs.is_empty());
+        assert!(addrs
+            .iter()
+            .all(|addr| addr.ip().is_loopback() && addr.port() == 25565));
+        assert!(resolver.resolve("server.invalid", 25565).is_err());
+
+        let runtime = ::tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let addrs = runtime
+            .block_on(resolver.resolve_async("localhost", 25565))
+            .unwrap();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+}
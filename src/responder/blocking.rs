@@ -0,0 +1,235 @@
+//! Blocking implementation of the Query responder.
+//!
+//! Uses [std::net::UdpSocket] for sending and receiving UDP data.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::mpsc,
+};
+
+use super::*;
+
+/// A blocking Query server using the [`std`] networking primitives.
+#[derive(Debug)]
+pub struct Server<P> {
+    socket: UdpSocket,
+    responder: Responder,
+    stats: Arc<P>,
+    render_timeout: Option<Duration>,
+}
+
+impl<P: StatsProvider + Send + Sync + 'static> Server<P> {
+    /// Bind a new server to the given address, answering status requests
+    /// with the status given by the provider.
+    ///
+    /// The default [render timeout](DEFAULT_RENDER_TIMEOUT) and [limits](Limits) are used.
+    pub fn bind(addr: impl ToSocketAddrs, stats: P) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr)?,
+            responder: Responder::new(),
+            stats: Arc::new(stats),
+            render_timeout: Some(DEFAULT_RENDER_TIMEOUT),
+        })
+    }
+
+    /// The local address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The provider giving the server status.
+    pub fn stats(&self) -> &P {
+        &self.stats
+    }
+
+    /// Set the limits protecting the server against amplification abuse.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.responder.set_limits(limits);
+    }
+
+    /// Record the packets received by the server with the given observer,
+    /// or stop recording them with `None`.
+    pub fn set_observer(&mut self, observer: Option<honeypot::Observer>) {
+        self.responder.set_observer(observer);
+    }
+
+    /// Set the time allowed to the provider to return the server status.
+    /// Status requests are dropped if the provider takes longer.
+    ///
+    /// With a timeout, the provider is queried on a new thread for every
+    /// status request. Without one, it is queried on the serving thread, and
+    /// a stalled provider stalls the server.
+    pub fn set_render_timeout(&mut self, timeout: Option<Duration>) {
+        self.render_timeout = timeout;
+    }
+
+    /// Receive and answer a single request.
+    pub fn serve_one(&mut self) -> io::Result<()> {
+        let mut buf = [0; REQUEST_SIZE];
+        let (received, source) = match self.socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            // ICMP port unreachable errors from previous responses are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let accepted = match self
+            .responder
+            .accept(&buf[..received], source, Instant::now())
+        {
+            Some(accepted) => accepted,
+            None => return Ok(()),
+        };
+        let response = match (accepted, self.render_timeout) {
+            (Accepted::Handshake(response), _) => Some(response),
+            (accepted, None) => accepted.render(&self.stats),
+            (accepted, Some(timeout)) => {
+                let (tx, rx) = mpsc::channel();
+                let stats = self.stats.clone();
+                std::thread::spawn(move || tx.send(accepted.render(&stats)));
+                rx.recv_timeout(timeout).ok().flatten()
+            }
+        };
+
+        if let Some(response) = response {
+            self.socket.send_to(&response, source)?;
+        }
+        Ok(())
+    }
+
+    /// Answer requests until an IO error occurs.
+    pub fn serve(&mut self) -> io::Result<()> {
+        loop {
+            self.serve_one()?;
+        }
+    }
+}
+
+/// Convenience function to answer requests on the given address with the
+/// status given by the provider, until an IO error occurs.
+pub fn serve(
+    addr: impl ToSocketAddrs,
+    stats: impl StatsProvider + Send + Sync + 'static,
+) -> io::Result<()> {
+    Server::bind(addr, stats)?.serve()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        net::SocketAddr,
+        sync::{Arc, RwLock},
+        time::Duration,
+    };
+
+    use super::super::{
+        honeypot::{Classification, Observer},
+        tests::test_stat,
+    };
+    use super::{Server, StatsProvider};
+    use crate::blocking::QueryClient;
+    use crate::{FullStat, Token};
+
+    fn spawn_server(stats: impl StatsProvider + Send + Sync + 'static) -> SocketAddr {
+        let mut server = Server::bind("127.0.0.1:0", stats).unwrap();
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+        addr
+    }
+
+    #[test]
+    fn test_blocking_client() {
+        let addr = spawn_server(test_stat());
+        let client = QueryClient::new_with_port("127.0.0.1", addr.port()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let basic = client.basic_stat(token).unwrap();
+        assert_eq!(basic.motd, "A Responder");
+        assert_eq!(client.full_stat(token).unwrap(), test_stat());
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        let addr = spawn_server(test_stat());
+        let client = QueryClient::new_with_port("127.0.0.1", addr.port()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let err = client
+            .full_stat(Token(token.0.wrapping_add(1)))
+            .unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn test_dynamic_stats() {
+        let stats = Arc::new(RwLock::new(test_stat()));
+        let addr = spawn_server(stats.clone());
+        let client = QueryClient::new_with_port("127.0.0.1", addr.port()).unwrap();
+        let token = client.handshake().unwrap();
+
+        assert_eq!(client.full_stat(token).unwrap().player_list.len(), 2);
+        {
+            let mut stats = stats.write().unwrap();
+            stats.player_list.push("Notch".into());
+            stats.numplayers = 3;
+        }
+        let stat = client.full_stat(token).unwrap();
+        assert_eq!(stat.numplayers, 3);
+        assert_eq!(stat.player_list.last().unwrap(), "Notch");
+    }
+
+    struct Stalled;
+
+    impl StatsProvider for Stalled {
+        fn full_stat(&self) -> FullStat {
+            std::thread::sleep(Duration::from_secs(5));
+            test_stat()
+        }
+    }
+
+    #[test]
+    fn test_render_timeout() {
+        let addr = spawn_server(Stalled);
+        let client = QueryClient::new_with_port("127.0.0.1", addr.port()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let err = client.basic_stat(token).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        // The server still answers while the provider is stalled
+        client.handshake().unwrap();
+    }
+
+    #[test]
+    fn test_observer() {
+        let observer = Observer::new(16);
+        let mut server = Server::bind("127.0.0.1:0", test_stat()).unwrap();
+        server.set_observer(Some(observer.clone()));
+        let addr = server.local_addr().unwrap();
+        std::thread::spawn(move || server.serve());
+
+        let client = QueryClient::new_with_port("127.0.0.1", addr.port()).unwrap();
+        client.full_stat(client.handshake().unwrap()).unwrap();
+        let junk = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        junk.send_to(b"\xFE\xFD\x09", addr).unwrap();
+        // Answered after the junk, so the junk was recorded
+        client.handshake().unwrap();
+
+        let summary = observer.summary();
+        assert_eq!(summary.packets, 4);
+        let activity = &summary.sources[0].1;
+        assert_eq!(activity.classify(), Classification::Fuzzer);
+        assert_eq!((activity.handshakes, activity.full_stats), (2, 1));
+        assert_eq!(
+            activity.last_malformed.as_deref(),
+            Some(&b"\xFE\xFD\x09"[..])
+        );
+    }
+}
@@ -0,0 +1,560 @@
+//! Observation of the sources querying a responder, for decoy servers.
+//!
+//! An [`Observer`] attached to a [`Responder`](super::Responder) records every
+//! packet it receives, answered or not, and keeps the activity of each source
+//! IP address: the packet types it sent, when, whether its requests were well
+//! formed, and whether it completed a handshake before requesting the status.
+//! Each activity is [classified](SourceActivity::classify) from these.
+//!
+//! The observer is a shared handle: keep a clone to read a [`Summary`]
+//! periodically, or be notified of every packet with a callback:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{responder::{self, honeypot::Observer}, FullStat};
+//! # fn decoy() -> FullStat { unimplemented!() }
+//! let observer = Observer::new(10_000)
+//!     .canary(|source| format!("A Minecraft Server ({source})"))
+//!     .on_packet(|observation| println!("{observation:?}"));
+//!
+//! let mut server = responder::blocking::Server::bind("0.0.0.0:25565", decoy())?;
+//! server.set_observer(Some(observer.clone()));
+//! std::thread::spawn(move || server.serve());
+//!
+//! std::thread::sleep(std::time::Duration::from_secs(3600));
+//! for (ip, activity) in observer.summary().sources {
+//!     println!("{ip}: {:?}", activity.classify());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Memory is bounded: once the observer tracks its capacity of sources, the
+//! least recently seen source is forgotten for every new one.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// A packet received by a responder
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Packet {
+    /// Handshake request
+    Handshake,
+    /// Basic status request
+    BasicStat {
+        /// Whether the token was issued to the source
+        valid_token: bool,
+    },
+    /// Full status request
+    FullStat {
+        /// Whether the token was issued to the source
+        valid_token: bool,
+    },
+    /// Packet which is not a Query request, with its first bytes
+    Malformed(Vec<u8>),
+}
+
+/// A packet received by an observed responder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    /// Address the packet was received from
+    pub source: SocketAddr,
+    /// Time the packet was received at
+    pub time: Instant,
+    /// The packet received
+    pub packet: Packet,
+    /// Whether the packet was dropped by the rate limits of the responder
+    pub rate_limited: bool,
+}
+
+/// Behavior of a source, see [`SourceActivity::classify`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Classification {
+    /// Completed a handshake, then requested the status with its token, like
+    /// a regular client
+    Client,
+    /// Only sent handshakes, checking whether the protocol is answered
+    Prober,
+    /// Requested the status without a valid token first, like scanners
+    /// replaying captured packets
+    Blind,
+    /// Sent both Query requests and malformed packets
+    Fuzzer,
+    /// Only sent malformed packets
+    Junk,
+}
+
+/// Activity of a source IP address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceActivity {
+    /// Time of the first packet of the source
+    pub first_seen: Instant,
+    /// Time of the last packet of the source
+    pub last_seen: Instant,
+    /// Number of handshake requests
+    pub handshakes: u64,
+    /// Number of basic status requests
+    pub basic_stats: u64,
+    /// Number of full status requests
+    pub full_stats: u64,
+    /// Number of status requests with a token which was not issued to the source
+    pub invalid_tokens: u64,
+    /// Number of malformed packets
+    pub malformed: u64,
+    /// Number of packets dropped by the rate limits
+    pub rate_limited: u64,
+    /// Whether the source completed a handshake before its first status
+    /// request, or `None` if it did not request the status
+    pub handshake_first: Option<bool>,
+    /// Delay between the first handshake and the first status request with
+    /// a valid token
+    pub handshake_to_stat: Option<Duration>,
+    /// First bytes of the last malformed packet
+    pub last_malformed: Option<Vec<u8>>,
+    /// Canary MOTD served to the source
+    pub canary: Option<String>,
+    /// Time of the first handshake
+    first_handshake: Option<Instant>,
+}
+
+impl SourceActivity {
+    fn new(now: Instant) -> Self {
+        Self {
+            first_seen: now,
+            last_seen: now,
+            handshakes: 0,
+            basic_stats: 0,
+            full_stats: 0,
+            invalid_tokens: 0,
+            malformed: 0,
+            rate_limited: 0,
+            handshake_first: None,
+            handshake_to_stat: None,
+            last_malformed: None,
+            canary: None,
+            first_handshake: None,
+        }
+    }
+
+    /// Total number of packets of the source.
+    pub fn packets(&self) -> u64 {
+        self.handshakes + self.basic_stats + self.full_stats + self.malformed
+    }
+
+    /// Classify the source from its activity so far.
+    pub fn classify(&self) -> Classification {
+        let requests = self.handshakes + self.basic_stats + self.full_stats;
+        if requests == 0 {
+            Classification::Junk
+        } else if self.malformed > 0 {
+            Classification::Fuzzer
+        } else if self.handshake_first.is_none() {
+            Classification::Prober
+        } else if self.handshake_first == Some(true) {
+            Classification::Client
+        } else {
+            Classification::Blind
+        }
+    }
+
+    /// Record a packet of the source.
+    fn record(&mut self, observation: &Observation) {
+        let now = observation.time;
+        self.last_seen = now;
+        if observation.rate_limited {
+            self.rate_limited += 1;
+        }
+
+        let valid_token = match &observation.packet {
+            Packet::Handshake => {
+                self.handshakes += 1;
+                self.first_handshake.get_or_insert(now);
+                return;
+            }
+            Packet::Malformed(bytes) => {
+                self.malformed += 1;
+                self.last_malformed = Some(bytes.clone());
+                return;
+            }
+            Packet::BasicStat { valid_token } => {
+                self.basic_stats += 1;
+                *valid_token
+            }
+            Packet::FullStat { valid_token } => {
+                self.full_stats += 1;
+                *valid_token
+            }
+        };
+
+        if !valid_token {
+            self.invalid_tokens += 1;
+        }
+        self.handshake_first
+            .get_or_insert(valid_token && self.first_handshake.is_some());
+        if let (None, Some(handshake), true) =
+            (self.handshake_to_stat, self.first_handshake, valid_token)
+        {
+            self.handshake_to_stat = Some(now.saturating_duration_since(handshake));
+        }
+    }
+}
+
+/// Snapshot of the activity recorded by an [`Observer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// Activity of the tracked sources, from the least to the most recently seen
+    pub sources: Vec<(IpAddr, SourceActivity)>,
+    /// Number of sources forgotten to stay within the capacity
+    pub evicted: u64,
+    /// Number of packets observed, including those of the forgotten sources
+    pub packets: u64,
+}
+
+impl Summary {
+    /// Number of tracked sources in each class.
+    pub fn classes(&self) -> BTreeMap<Classification, usize> {
+        let mut classes = BTreeMap::new();
+        for (_, activity) in &self.sources {
+            *classes.entry(activity.classify()).or_default() += 1;
+        }
+        classes
+    }
+}
+
+/// Sources tracked by an observer, in least recently seen order
+#[derive(Debug, Default)]
+struct Sources {
+    activity: HashMap<IpAddr, (SourceActivity, u64)>,
+    /// Sources by sequence number of their last packet
+    order: BTreeMap<u64, IpAddr>,
+    next: u64,
+    evicted: u64,
+    packets: u64,
+}
+
+type Canary = Arc<dyn Fn(IpAddr) -> String + Send + Sync>;
+type Callback = Arc<dyn Fn(&Observation) + Send + Sync>;
+
+/// Shared handle recording the activity of the sources of a responder.
+///
+/// Clones share the same records.
+#[derive(Clone)]
+pub struct Observer {
+    capacity: usize,
+    sources: Arc<Mutex<Sources>>,
+    canary: Option<Canary>,
+    callback: Option<Callback>,
+}
+
+impl fmt::Debug for Observer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observer")
+            .field("capacity", &self.capacity)
+            .field("sources", &self.sources)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Observer {
+    /// Build an observer tracking at most `capacity` sources.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            sources: Default::default(),
+            canary: None,
+            callback: None,
+        }
+    }
+
+    /// Serve the MOTD given by `canary` to each source in status responses,
+    /// instead of the one of the provider. Unique MOTDs reveal the sources
+    /// behind the statuses published by scanning services.
+    pub fn canary(mut self, canary: impl Fn(IpAddr) -> String + Send + Sync + 'static) -> Self {
+        self.canary = Some(Arc::new(canary));
+        self
+    }
+
+    /// Call `callback` on every packet received, after recording it.
+    pub fn on_packet(mut self, callback: impl Fn(&Observation) + Send + Sync + 'static) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Max number of sources tracked by the observer.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Snapshot of the recorded activity.
+    pub fn summary(&self) -> Summary {
+        let sources = self.lock();
+        Summary {
+            sources: sources
+                .order
+                .values()
+                .map(|ip| (*ip, sources.activity[ip].0.clone()))
+                .collect(),
+            evicted: sources.evicted,
+            packets: sources.packets,
+        }
+    }
+
+    /// The activity of a source, if it is tracked.
+    pub fn source(&self, ip: IpAddr) -> Option<SourceActivity> {
+        self.lock()
+            .activity
+            .get(&ip)
+            .map(|(activity, _)| activity.clone())
+    }
+
+    /// Forget every record, for example after reading a summary.
+    pub fn clear(&self) {
+        *self.lock() = Sources::default();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Sources> {
+        self.sources.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Record a packet, returning the canary MOTD to serve to the source.
+    pub(crate) fn observe(&self, observation: Observation) -> Option<String> {
+        let ip = observation.source.ip();
+        let canary = {
+            let mut guard = self.lock();
+            let sources = &mut *guard;
+            sources.packets += 1;
+            let seq = sources.next;
+            sources.next += 1;
+
+            let activity = match sources.activity.get_mut(&ip) {
+                Some((activity, last)) => {
+                    sources.order.remove(last);
+                    *last = seq;
+                    activity
+                }
+                None => {
+                    if sources.activity.len() >= self.capacity {
+                        if let Some((_, oldest)) = sources.order.pop_first() {
+                            sources.activity.remove(&oldest);
+                            sources.evicted += 1;
+                        }
+                    }
+                    let activity = SourceActivity::new(observation.time);
+                    &mut sources.activity.entry(ip).or_insert((activity, seq)).0
+                }
+            };
+            sources.order.insert(seq, ip);
+            activity.record(&observation);
+
+            match (&self.canary, &observation.packet) {
+                (Some(canary), Packet::BasicStat { .. } | Packet::FullStat { .. }) => {
+                    Some(activity.canary.get_or_insert_with(|| canary(ip)).clone())
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(callback) = &self.callback {
+            callback(&observation);
+        }
+        canary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, SocketAddr},
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use super::{Classification, Observer, Packet};
+    use crate::packets;
+    use crate::responder::{tests::test_stat, Limits, Responder};
+    use crate::{BasicStat, FullStat, Token};
+
+    fn source(i: u8) -> SocketAddr {
+        SocketAddr::from(([192, 0, 2, i], 40000 + u16::from(i)))
+    }
+
+    fn respond(
+        responder: &mut Responder,
+        request: &[u8],
+        source: SocketAddr,
+        now: Instant,
+    ) -> Option<Vec<u8>> {
+        responder.accept(request, source, now)?.render(&test_stat())
+    }
+
+    #[test]
+    fn test_classification() {
+        let observer = Observer::new(16);
+        let mut responder = Responder::new();
+        responder.set_observer(Some(observer.clone()));
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+
+        // A regular client
+        let response = respond(
+            &mut responder,
+            &packets::Handshake::new(1),
+            source(1),
+            ms(0),
+        );
+        let token = Token::from_payload(&response.unwrap()[5..]).0;
+        let request = packets::FullStat::new(1, token);
+        assert!(respond(&mut responder, &request, source(1), ms(40)).is_some());
+        // A prober
+        respond(
+            &mut responder,
+            &packets::Handshake::new(2),
+            source(2),
+            ms(10),
+        );
+        // A blind scanner, replaying the token of the client
+        assert!(respond(&mut responder, &request, source(3), ms(20)).is_none());
+        let request = packets::BasicStat::new(3, 0x1234);
+        assert!(respond(&mut responder, &request, source(3), ms(30)).is_none());
+        // Junk
+        for junk in [&b"GET / HTTP/1.1\r\n"[..], b"\xFE\x01", b""] {
+            assert!(respond(&mut responder, junk, source(4), ms(50)).is_none());
+        }
+        // A fuzzer
+        respond(
+            &mut responder,
+            &packets::Handshake::new(5),
+            source(5),
+            ms(60),
+        );
+        respond(&mut responder, b"\xFE\xFD\x09\0\0", source(5), ms(70));
+
+        let client = observer.source(source(1).ip()).unwrap();
+        assert_eq!(client.classify(), Classification::Client);
+        assert_eq!((client.handshakes, client.full_stats), (1, 1));
+        assert_eq!(client.handshake_first, Some(true));
+        assert_eq!(client.handshake_to_stat, Some(Duration::from_millis(40)));
+        assert_eq!(
+            client.last_seen - client.first_seen,
+            Duration::from_millis(40)
+        );
+
+        let blind = observer.source(source(3).ip()).unwrap();
+        assert_eq!(blind.classify(), Classification::Blind);
+        assert_eq!(blind.invalid_tokens, 2);
+        assert_eq!(blind.handshake_first, Some(false));
+        assert_eq!(blind.handshake_to_stat, None);
+
+        let junk = observer.source(source(4).ip()).unwrap();
+        assert_eq!(junk.malformed, 3);
+        assert_eq!(junk.last_malformed, Some(Vec::new()));
+
+        let summary = observer.summary();
+        assert_eq!(summary.packets, 10);
+        let order: Vec<IpAddr> = summary.sources.iter().map(|(ip, _)| *ip).collect();
+        assert_eq!(order, [1, 2, 3, 4, 5].map(|i| source(i).ip()));
+        assert_eq!(
+            summary.classes().into_iter().collect::<Vec<_>>(),
+            [
+                (Classification::Client, 1),
+                (Classification::Prober, 1),
+                (Classification::Blind, 1),
+                (Classification::Fuzzer, 1),
+                (Classification::Junk, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_packets_are_observed() {
+        let observer = Observer::new(16);
+        let mut responder = Responder::new();
+        responder.set_observer(Some(observer.clone()));
+        let now = Instant::now();
+        let burst = responder.limits().per_source.unwrap().burst as u64;
+
+        for _ in 0..burst + 5 {
+            respond(&mut responder, &packets::Handshake::new(1), source(1), now);
+        }
+        let activity = observer.source(source(1).ip()).unwrap();
+        assert_eq!(activity.handshakes, burst + 5);
+        assert_eq!(activity.rate_limited, 5);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let observer = Observer::new(3);
+        let mut responder = Responder::with_limits(Limits {
+            per_source: None,
+            global: None,
+            max_response_size: None,
+        });
+        responder.set_observer(Some(observer.clone()));
+        let now = Instant::now();
+
+        for i in [1, 2, 3, 1, 4, 5] {
+            respond(&mut responder, &packets::Handshake::new(1), source(i), now);
+        }
+        let summary = observer.summary();
+        let order: Vec<IpAddr> = summary.sources.iter().map(|(ip, _)| *ip).collect();
+        assert_eq!(order, [1, 4, 5].map(|i| source(i).ip()));
+        assert_eq!(summary.evicted, 2);
+        assert_eq!(summary.packets, 6);
+        assert_eq!(observer.source(source(1).ip()).unwrap().handshakes, 2);
+
+        observer.clear();
+        assert!(observer.summary().sources.is_empty());
+    }
+
+    #[test]
+    fn test_canary_and_callback() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let observer = Observer::new(16)
+            .canary(|ip| format!("Canary {ip}"))
+            .on_packet({
+                let seen = seen.clone();
+                move |observation| seen.lock().unwrap().push(observation.packet.clone())
+            });
+        let mut responder = Responder::new();
+        responder.set_observer(Some(observer.clone()));
+        let now = Instant::now();
+
+        let response = respond(&mut responder, &packets::Handshake::new(1), source(1), now);
+        let token = Token::from_payload(&response.unwrap()[5..]).0;
+        let response = respond(
+            &mut responder,
+            &packets::BasicStat::new(1, token),
+            source(1),
+            now,
+        );
+        let basic = BasicStat::from_payload(&response.unwrap()[5..]).unwrap();
+        assert_eq!(basic.motd, "Canary 192.0.2.1");
+        let response = respond(
+            &mut responder,
+            &packets::FullStat::new(1, token),
+            source(1),
+            now,
+        );
+        let full = FullStat::from_payload(&response.unwrap()[5..]).unwrap();
+        assert_eq!(full.hostname, "Canary 192.0.2.1");
+        assert_eq!(full.player_list, test_stat().player_list);
+
+        respond(&mut responder, b"\x16\x03\x01", source(1), now);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                Packet::Handshake,
+                Packet::BasicStat { valid_token: true },
+                Packet::FullStat { valid_token: true },
+                Packet::Malformed(b"\x16\x03\x01".to_vec()),
+            ]
+        );
+        assert_eq!(
+            observer.source(source(1).ip()).unwrap().canary.as_deref(),
+            Some("Canary 192.0.2.1")
+        );
+    }
+}
@@ -0,0 +1,800 @@
+//! Server-side implementation of the Query protocol.
+//!
+//! A responder answers handshakes with challenge tokens, valid for
+//! [`TOKEN_LIFETIME`] like on vanilla servers, and answers status requests
+//! carrying a valid token with the status given by a [`StatsProvider`].
+//!
+//! Since a small spoofed request can elicit a much larger response, requests
+//! are rate-limited and responses are size-capped by default, see [`Limits`].
+//!
+//! The provider is queried on every status request, so the served status is
+//! always current. A [`FullStat`] is a provider always sending the same status,
+//! and an `Arc<RwLock<FullStat>>` can be shared with the code updating it:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{responder, FullStat};
+//! # use std::sync::{Arc, RwLock};
+//! # fn stat() -> FullStat { unimplemented!() }
+//! let stat = Arc::new(RwLock::new(stat()));
+//! let mut server = responder::blocking::Server::bind("0.0.0.0:25565", stat.clone())?;
+//! std::thread::spawn(move || server.serve());
+//!
+//! stat.write().unwrap().numplayers += 1;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! [`Responder`] itself does not do any IO, and can be used to answer query
+//! packets received by other means, for example by a proxy sharing its port.
+//!
+//! An [`Observer`](honeypot::Observer) records the activity of the sources of
+//! a responder, to run it as a decoy measuring scanning activity.
+//!
+//! With the `rcon` feature, an [`RconStats`](rcon_bridge::RconStats) provider
+//! serves the players of a server with query disabled, pulled over RCON.
+
+pub mod blocking;
+pub mod honeypot;
+#[cfg(feature = "rcon")]
+#[cfg_attr(doc, doc(cfg(all(feature = "responder", feature = "rcon"))))]
+pub mod rcon_bridge;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    future::Future,
+    hash::{Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+use self::honeypot::{Observation, Observer, Packet};
+use crate::packets::{write_response, PacketType, Request};
+pub use crate::rate_limit::RateLimit;
+use crate::{BasicStat, FullStat, Token, RESPONSE_HEADER_SIZE};
+
+/// Minimum duration a challenge token stays valid after the handshake.
+/// Tokens are valid for at most twice this duration.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Default time allowed to a [`StatsProvider`] to return the server status.
+///
+/// Status requests are dropped if the provider takes longer.
+pub const DEFAULT_RENDER_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Request max size, in bytes. Valid requests are at most 15 bytes long.
+const REQUEST_SIZE: usize = 16;
+/// Max number of source addresses tracked by the per-source rate limit
+const MAX_TRACKED_SOURCES: usize = 4096;
+
+/// Source of the server status sent by a responder, queried on every status request.
+pub trait StatsProvider {
+    /// Full status of the server.
+    fn full_stat(&self) -> FullStat;
+
+    /// Basic status of the server. Built from the full status by default.
+    fn basic_stat(&self) -> BasicStat {
+        BasicStat::from(&self.full_stat())
+    }
+}
+
+impl StatsProvider for FullStat {
+    fn full_stat(&self) -> FullStat {
+        self.clone()
+    }
+}
+
+impl StatsProvider for RwLock<FullStat> {
+    fn full_stat(&self) -> FullStat {
+        self.read().unwrap_or_else(PoisonError::into_inner).clone()
+    }
+}
+
+impl<P: StatsProvider + ?Sized> StatsProvider for Arc<P> {
+    fn full_stat(&self) -> FullStat {
+        (**self).full_stat()
+    }
+
+    fn basic_stat(&self) -> BasicStat {
+        (**self).basic_stat()
+    }
+}
+
+/// Asynchronous source of the server status sent by a responder, queried on every status request.
+///
+/// Every [`StatsProvider`] is also an asynchronous provider.
+pub trait AsyncStatsProvider: Sync {
+    /// Full status of the server.
+    fn full_stat(&self) -> impl Future<Output = FullStat> + Send;
+
+    /// Basic status of the server. Built from the full status by default.
+    fn basic_stat(&self) -> impl Future<Output = BasicStat> + Send {
+        async { BasicStat::from(&self.full_stat().await) }
+    }
+}
+
+impl<P: StatsProvider + Sync> AsyncStatsProvider for P {
+    async fn full_stat(&self) -> FullStat {
+        StatsProvider::full_stat(self)
+    }
+
+    async fn basic_stat(&self) -> BasicStat {
+        StatsProvider::basic_stat(self)
+    }
+}
+
+/// Limits protecting a responder against amplification abuse.
+///
+/// Every limit can be disabled by setting it to `None`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Limits {
+    /// Rate limit applied to every source IP address. Defaults to
+    /// 5 requests per second, with bursts of 10.
+    pub per_source: Option<RateLimit>,
+    /// Rate limit applied to all requests. Defaults to 500 requests per
+    /// second, with bursts of 1000.
+    pub global: Option<RateLimit>,
+    /// Max size of a response, in bytes. The player list of full status
+    /// responses is truncated to fit, and other responses are dropped if they
+    /// are too large. Defaults to 1472 bytes, the payload size of a UDP
+    /// packet on a typical ethernet network.
+    pub max_response_size: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            per_source: Some(RateLimit {
+                rate: 5.0,
+                burst: 10.0,
+            }),
+            global: Some(RateLimit {
+                rate: 500.0,
+                burst: 1000.0,
+            }),
+            max_response_size: Some(FullStat::RESPONSE_SIZE),
+        }
+    }
+}
+
+/// A sans-IO Query responder, managing challenge tokens and answering requests.
+///
+/// Like on vanilla servers, challenge tokens are derived from the source IP
+/// address and a secret rotated every [`TOKEN_LIFETIME`], so that status
+/// responses are only sent to addresses that completed a handshake, without
+/// storing anything per handshake. Requests are rate-limited, and responses
+/// are size-capped, according to the responder [`Limits`].
+#[derive(Debug, Clone)]
+pub struct Responder {
+    limits: Limits,
+    secrets: [u64; 2],
+    rotated: Instant,
+    rng: u64,
+    global: Bucket,
+    sources: HashMap<IpAddr, Bucket>,
+    observer: Option<Observer>,
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Responder {
+    /// Build a new responder with the default [`Limits`].
+    pub fn new() -> Self {
+        Self::with_limits(Limits::default())
+    }
+
+    /// Build a new responder with the given limits.
+    pub fn with_limits(limits: Limits) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_nanos() as u64;
+        let now = Instant::now();
+
+        let mut res = Self {
+            limits,
+            secrets: [0; 2],
+            rotated: now,
+            // xorshift state must not be zero
+            rng: seed | 1,
+            global: Bucket::new(now),
+            sources: HashMap::new(),
+            observer: None,
+        };
+        res.secrets = [res.next_secret(), res.next_secret()];
+        res
+    }
+
+    /// The limits of the responder.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Replace the limits of the responder.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// The observer recording the packets received by the responder, if any.
+    pub fn observer(&self) -> Option<&Observer> {
+        self.observer.as_ref()
+    }
+
+    /// Record the packets received by the responder with the given observer,
+    /// or stop recording them with `None`.
+    pub fn set_observer(&mut self, observer: Option<Observer>) {
+        self.observer = observer;
+    }
+
+    /// Answer a request received from the given address, with the status
+    /// given by the provider.
+    ///
+    /// Returns `None` if no response should be sent: malformed requests,
+    /// rate-limited requests and status requests with an invalid or expired
+    /// token are silently dropped, like on vanilla servers.
+    pub fn respond(
+        &mut self,
+        request: &[u8],
+        source: SocketAddr,
+        stats: &impl StatsProvider,
+    ) -> Option<Vec<u8>> {
+        self.accept(request, source, Instant::now())?.render(stats)
+    }
+
+    /// Answer a request received from the given address, with the status
+    /// given by the asynchronous provider. See [`respond`](Self::respond).
+    pub async fn respond_async(
+        &mut self,
+        request: &[u8],
+        source: SocketAddr,
+        stats: &impl AsyncStatsProvider,
+    ) -> Option<Vec<u8>> {
+        let accepted = self.accept(request, source, Instant::now())?;
+        accepted.render_async(stats).await
+    }
+
+    /// Parse, rate-limit and check the token of a request, handling handshakes right away.
+    pub(crate) fn accept(
+        &mut self,
+        request: &[u8],
+        source: SocketAddr,
+        now: Instant,
+    ) -> Option<Accepted> {
+        let Some(parsed) = Request::parse(request) else {
+            self.observe(source, now, false, || Packet::Malformed(request.to_vec()));
+            return None;
+        };
+        if self.rate_limit(source.ip(), now).is_none() {
+            self.observe(source, now, true, || self.observed(&parsed, source));
+            return None;
+        }
+        self.rotate_secrets(now);
+        let motd = self.observe(source, now, false, || self.observed(&parsed, source));
+
+        let max_size = self.limits.max_response_size.unwrap_or(usize::MAX);
+        match parsed {
+            Request::Handshake { session_id } => {
+                let token = self.token(source.ip(), self.secrets[0]);
+                let response =
+                    write_response(PacketType::Handshake, session_id, &token.to_payload());
+                Some(Accepted::Handshake(response))
+            }
+            Request::BasicStat { session_id, token } => {
+                self.check_token(source.ip(), token)?;
+                Some(Accepted::BasicStat {
+                    session_id,
+                    max_size,
+                    motd,
+                })
+            }
+            Request::FullStat { session_id, token } => {
+                self.check_token(source.ip(), token)?;
+                Some(Accepted::FullStat {
+                    session_id,
+                    max_size,
+                    motd,
+                })
+            }
+        }
+    }
+
+    /// Record a packet with the observer, returning the canary MOTD to serve.
+    fn observe(
+        &self,
+        source: SocketAddr,
+        time: Instant,
+        rate_limited: bool,
+        packet: impl FnOnce() -> Packet,
+    ) -> Option<String> {
+        self.observer.as_ref()?.observe(Observation {
+            source,
+            time,
+            packet: packet(),
+            rate_limited,
+        })
+    }
+
+    /// The observed form of a request.
+    fn observed(&self, request: &Request, source: SocketAddr) -> Packet {
+        match *request {
+            Request::Handshake { .. } => Packet::Handshake,
+            Request::BasicStat { token, .. } => Packet::BasicStat {
+                valid_token: self.check_token(source.ip(), token).is_some(),
+            },
+            Request::FullStat { token, .. } => Packet::FullStat {
+                valid_token: self.check_token(source.ip(), token).is_some(),
+            },
+        }
+    }
+
+    /// Take a request from the global bucket and from the bucket of the source.
+    fn rate_limit(&mut self, source: IpAddr, now: Instant) -> Option<()> {
+        if let Some(limit) = self.limits.per_source {
+            if !self.sources.contains_key(&source) && self.sources.len() >= MAX_TRACKED_SOURCES {
+                // Buckets that refilled are the same as new ones
+                self.sources.retain(|_, bucket| !bucket.is_full(limit, now));
+                if self.sources.len() >= MAX_TRACKED_SOURCES {
+                    return None;
+                }
+            }
+            let bucket = self
+                .sources
+                .entry(source)
+                .or_insert_with(|| Bucket::new(now));
+            bucket.refill(limit, now);
+            if bucket.tokens < 1.0 {
+                return None;
+            }
+            bucket.tokens -= 1.0;
+        }
+
+        if let Some(limit) = self.limits.global {
+            self.global.take(limit, now)?;
+        }
+        Some(())
+    }
+
+    /// Rotate the token secrets once every [`TOKEN_LIFETIME`].
+    fn rotate_secrets(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.rotated);
+        if elapsed >= TOKEN_LIFETIME * 2 {
+            self.secrets = [self.next_secret(), self.next_secret()];
+            self.rotated = now;
+        } else if elapsed >= TOKEN_LIFETIME {
+            self.secrets = [self.next_secret(), self.secrets[0]];
+            self.rotated += TOKEN_LIFETIME;
+        }
+    }
+
+    /// Check that the token was issued to the given address with the current
+    /// or the previous secret.
+    fn check_token(&self, source: IpAddr, token: u32) -> Option<()> {
+        self.secrets
+            .iter()
+            .any(|&secret| self.token(source, secret).0 == token)
+            .then_some(())
+    }
+
+    /// Derive the challenge token of an address from a secret.
+    ///
+    /// Tokens are kept in the positive `i32` range, since most clients
+    /// parse them as signed integers.
+    fn token(&self, source: IpAddr, secret: u64) -> Token {
+        let mut hasher = DefaultHasher::new();
+        secret.hash(&mut hasher);
+        source.hash(&mut hasher);
+        Token((hasher.finish() >> 33) as u32)
+    }
+
+    /// Generate a new secret with a xorshift generator.
+    fn next_secret(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+}
+
+/// Token bucket state of a rate limit
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// A new bucket, filled on its first refill
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: f64::INFINITY,
+            updated: now,
+        }
+    }
+
+    /// Add the tokens earned since the last refill.
+    fn refill(&mut self, limit: RateLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.rate).min(limit.burst);
+        self.updated = now;
+    }
+
+    /// Whether the bucket would be full after a refill.
+    fn is_full(&self, limit: RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * limit.rate >= limit.burst
+    }
+
+    /// Take a token from the bucket, returning `None` if it is empty.
+    fn take(&mut self, limit: RateLimit, now: Instant) -> Option<()> {
+        self.refill(limit, now);
+        if self.tokens < 1.0 {
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(())
+    }
+}
+
+/// A request accepted by a responder, waiting for the server status
+#[derive(Debug)]
+pub(crate) enum Accepted {
+    /// Complete handshake response
+    Handshake(Vec<u8>),
+    /// Basic status request, with the canary MOTD to serve if any
+    BasicStat {
+        session_id: u32,
+        max_size: usize,
+        motd: Option<String>,
+    },
+    /// Full status request, with the canary MOTD to serve if any
+    FullStat {
+        session_id: u32,
+        max_size: usize,
+        motd: Option<String>,
+    },
+}
+
+impl Accepted {
+    /// Build the response with the status given by the provider.
+    fn render(self, stats: &impl StatsProvider) -> Option<Vec<u8>> {
+        match self {
+            Self::Handshake(response) => Some(response),
+            Self::BasicStat {
+                session_id,
+                max_size,
+                motd,
+            } => basic_stat_response(session_id, with_motd(stats.basic_stat(), motd), max_size),
+            Self::FullStat {
+                session_id,
+                max_size,
+                motd,
+            } => full_stat_response(session_id, with_motd(stats.full_stat(), motd), max_size),
+        }
+    }
+
+    /// Build the response with the status given by the asynchronous provider.
+    async fn render_async(self, stats: &impl AsyncStatsProvider) -> Option<Vec<u8>> {
+        match self {
+            Self::Handshake(response) => Some(response),
+            Self::BasicStat {
+                session_id,
+                max_size,
+                motd,
+            } => basic_stat_response(
+                session_id,
+                with_motd(stats.basic_stat().await, motd),
+                max_size,
+            ),
+            Self::FullStat {
+                session_id,
+                max_size,
+                motd,
+            } => full_stat_response(
+                session_id,
+                with_motd(stats.full_stat().await, motd),
+                max_size,
+            ),
+        }
+    }
+}
+
+/// A status whose MOTD can be replaced by a canary
+trait Motd {
+    fn set_motd(&mut self, motd: String);
+}
+
+impl Motd for BasicStat {
+    // `StatString` is only another type than `String` with the `compact_str` feature
+    #[allow(clippy::useless_conversion)]
+    fn set_motd(&mut self, motd: String) {
+        self.motd = motd.into();
+    }
+}
+
+impl Motd for FullStat {
+    // `StatString` is only another type than `String` with the `compact_str` feature
+    #[allow(clippy::useless_conversion)]
+    fn set_motd(&mut self, motd: String) {
+        self.hostname = motd.into();
+    }
+}
+
+/// Replace the MOTD of a status with the canary, if any.
+fn with_motd<S: Motd>(mut stat: S, motd: Option<String>) -> S {
+    if let Some(motd) = motd {
+        stat.set_motd(motd);
+    }
+    stat
+}
+
+/// Build a basic status response, unless it is larger than the max size.
+pub(crate) fn basic_stat_response(
+    session_id: u32,
+    stat: BasicStat,
+    max_size: usize,
+) -> Option<Vec<u8>> {
+    let response = write_response(PacketType::Stat, session_id, &stat.to_payload());
+    (response.len() <= max_size).then_some(response)
+}
+
+/// Build a full status response, truncating the player list to fit in the
+/// max size, unless it is still too large without players.
+pub(crate) fn full_stat_response(
+    session_id: u32,
+    mut stat: FullStat,
+    max_size: usize,
+) -> Option<Vec<u8>> {
+    let mut size = RESPONSE_HEADER_SIZE + stat.to_payload().len();
+    while size > max_size {
+        let player = stat.player_list.pop()?;
+        if !player.is_empty() {
+            size -= player.chars().filter(|&c| c != '\0').count() + 1;
+        }
+    }
+
+    let response = write_response(PacketType::Stat, session_id, &stat.to_payload());
+    debug_assert_eq!(response.len(), size);
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets;
+
+    /// Server status used by the responder tests.
+    pub(crate) fn test_stat() -> FullStat {
+        FullStat {
+            hostname: "A Responder".into(),
+            gametype: "SMP".into(),
+            game_id: "MINECRAFT".into(),
+            version: "1.20.1".into(),
+            plugins: "".into(),
+            map: "world".into(),
+            numplayers: 2,
+            maxplayers: 20,
+            hostport: 25565,
+            hostip: "127.0.0.1".into(),
+            player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+        }
+    }
+
+    impl Responder {
+        fn respond_at(
+            &mut self,
+            request: &[u8],
+            source: SocketAddr,
+            now: Instant,
+        ) -> Option<Vec<u8>> {
+            self.accept(request, source, now)?.render(&test_stat())
+        }
+    }
+
+    fn handshake(responder: &mut Responder, source: SocketAddr, now: Instant) -> u32 {
+        let response = responder
+            .respond_at(&packets::Handshake::new(7), source, now)
+            .unwrap();
+        assert_eq!(response[..5], [9, 0, 0, 0, 7]);
+        Token::from_payload(&response[5..]).0
+    }
+
+    #[test]
+    fn test_stat_requests() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let now = Instant::now();
+        let mut responder = Responder::new();
+        let token = handshake(&mut responder, source, now);
+        assert!(token <= i32::MAX as u32);
+
+        let response = responder
+            .respond_at(&packets::BasicStat::new(7, token), source, now)
+            .unwrap();
+        assert_eq!(response[..5], [0, 0, 0, 0, 7]);
+        let basic = BasicStat::from_payload(&response[5..]).unwrap();
+        assert_eq!(basic, BasicStat::from(&test_stat()));
+
+        let response = responder
+            .respond_at(&packets::FullStat::new(7, token), source, now)
+            .unwrap();
+        assert_eq!(FullStat::from_payload(&response[5..]).unwrap(), test_stat());
+    }
+
+    #[test]
+    fn test_shared_provider() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let stats = Arc::new(RwLock::new(test_stat()));
+        let mut responder = Responder::new();
+
+        let response = responder
+            .respond(&packets::Handshake::new(7), source, &stats)
+            .unwrap();
+        let token = Token::from_payload(&response[5..]).0;
+        let request = packets::BasicStat::new(7, token);
+
+        let response = responder.respond(&request, source, &stats).unwrap();
+        assert_eq!(
+            BasicStat::from_payload(&response[5..]).unwrap().numplayers,
+            2
+        );
+        stats.write().unwrap().numplayers = 3;
+        let response = responder.respond(&request, source, &stats).unwrap();
+        assert_eq!(
+            BasicStat::from_payload(&response[5..]).unwrap().numplayers,
+            3
+        );
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let other = SocketAddr::from(([127, 0, 0, 2], 50000));
+        let now = Instant::now();
+        let mut responder = Responder::new();
+        let token = handshake(&mut responder, source, now);
+
+        let request = packets::FullStat::new(7, token.wrapping_add(1));
+        assert_eq!(responder.respond_at(&request, source, now), None);
+        let request = packets::FullStat::new(7, token);
+        assert_eq!(responder.respond_at(&request, other, now), None);
+    }
+
+    #[test]
+    fn test_token_expiry() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let mut responder = Responder::new();
+        let now = Instant::now();
+        let token = handshake(&mut responder, source, now);
+
+        let request = packets::BasicStat::new(7, token);
+        let later = now + TOKEN_LIFETIME - Duration::from_secs(1);
+        assert!(responder.respond_at(&request, source, later).is_some());
+        let later = now + TOKEN_LIFETIME * 2;
+        assert_eq!(responder.respond_at(&request, source, later), None);
+
+        let new_token = handshake(&mut responder, source, later);
+        assert_ne!(new_token, token);
+        let request = packets::BasicStat::new(7, new_token);
+        assert!(responder.respond_at(&request, source, later).is_some());
+    }
+
+    #[test]
+    fn test_unearned_token() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let spoofed = SocketAddr::from(([192, 0, 2, 1], 50000));
+        let now = Instant::now();
+        let mut responder = Responder::new();
+
+        // Tokens only depend on the source IP and the current secret
+        let token = handshake(&mut responder, source, now);
+        assert_eq!(handshake(&mut responder, source, now), token);
+
+        // A spoofed request with a token earned by another address is dropped
+        let request = packets::FullStat::new(7, token);
+        assert_eq!(responder.respond_at(&request, spoofed, now), None);
+        assert!(responder.respond_at(&request, source, now).is_some());
+    }
+
+    #[test]
+    fn test_per_source_rate_limit() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let other = SocketAddr::from(([127, 0, 0, 2], 50000));
+        let now = Instant::now();
+        let mut responder = Responder::new();
+        let limit = responder.limits().per_source.unwrap();
+
+        let answered = (0..100)
+            .filter_map(|_| responder.respond_at(&packets::Handshake::new(7), source, now))
+            .count();
+        assert_eq!(answered, limit.burst as usize);
+
+        // Other sources are not limited
+        assert!(responder
+            .respond_at(&packets::Handshake::new(7), other, now)
+            .is_some());
+        // The bucket refills over time
+        let later = now + Duration::from_secs(1);
+        let answered = (0..100)
+            .filter_map(|_| responder.respond_at(&packets::Handshake::new(7), source, later))
+            .count();
+        assert_eq!(answered, limit.rate as usize);
+    }
+
+    #[test]
+    fn test_global_rate_limit() {
+        let now = Instant::now();
+        let mut responder = Responder::new();
+        let limit = responder.limits().global.unwrap();
+
+        let answered = (0..2000u32)
+            .filter_map(|i| {
+                let source = SocketAddr::from((i.to_be_bytes(), 50000));
+                responder.respond_at(&packets::Handshake::new(7), source, now)
+            })
+            .count();
+        assert_eq!(answered, limit.burst as usize);
+
+        responder.set_limits(Limits {
+            global: None,
+            ..Limits::default()
+        });
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        assert!(responder
+            .respond_at(&packets::Handshake::new(7), source, now)
+            .is_some());
+    }
+
+    #[test]
+    fn test_full_stat_truncation() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let mut stat = test_stat();
+        stat.player_list = (0..200)
+            .map(|i| format!("Player{:010}", i).as_str().into())
+            .collect();
+        stat.numplayers = 200;
+        let mut responder = Responder::new();
+
+        let response = responder
+            .respond(&packets::Handshake::new(7), source, &stat)
+            .unwrap();
+        let token = Token::from_payload(&response[5..]).0;
+        let request = packets::FullStat::new(7, token);
+
+        let response = responder.respond(&request, source, &stat).unwrap();
+        assert!(response.len() <= FullStat::RESPONSE_SIZE);
+        let truncated = FullStat::from_payload(&response[5..]).unwrap();
+        assert_eq!(truncated.numplayers, 200);
+        assert!(truncated.player_list.len() < 200);
+        assert_eq!(
+            truncated.player_list,
+            stat.player_list[..truncated.player_list.len()]
+        );
+
+        // Responses still too large without players are dropped
+        stat.hostname = "A".repeat(2000).as_str().into();
+        assert_eq!(responder.respond(&request, source, &stat), None);
+        assert!(responder.respond(&request, source, &test_stat()).is_some());
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let source = SocketAddr::from(([127, 0, 0, 1], 50000));
+        let mut responder = Responder::new();
+
+        for request in [
+            &b""[..],
+            b"\xFE\xFD\x09\0\0\0",
+            b"\xFE\xFC\x09\0\0\0\x07",
+            b"\xFE\xFD\x01\0\0\0\x07",
+            b"\xFE\xFD\x00\0\0\0\x07\0\0",
+            b"\xFE\xFD\x09\0\0\0\x07\0\0\0\0",
+        ] {
+            assert_eq!(responder.respond(request, source, &test_stat()), None);
+        }
+    }
+}
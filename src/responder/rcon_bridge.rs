@@ -0,0 +1,532 @@
+//! Query responses backed by RCON, for servers with query disabled.
+//!
+//! An [`RconStats`] provider serves the players and version pulled from a
+//! server over RCON, with the `list` and `version` commands, by a
+//! [`Refresher`] thread. The other fields of the status come from a template:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{responder::{self, rcon_bridge::{RconStats, Refresher}}, FullStat};
+//! # use std::{sync::Arc, time::Duration};
+//! # fn template() -> FullStat { unimplemented!() }
+//! let stats = Arc::new(RconStats::new(template()).max_age(Duration::from_secs(120)));
+//! let _refresher = Refresher::new("10.0.0.2:25575", "password")?
+//!     .interval(Duration::from_secs(10))
+//!     .spawn(stats.clone());
+//!
+//! let mut server = responder::blocking::Server::bind("0.0.0.0:25565", stats)?;
+//! server.serve()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! When the server cannot be reached over RCON, the last players pulled from
+//! it are served until they are older than the [max age](RconStats::max_age),
+//! and the template is served afterwards.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{mpsc, Arc, PoisonError, RwLock},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::StatsProvider;
+use crate::motd::strip_codes;
+use crate::rcon::blocking::RconClient;
+use crate::{FullStat, DEFAULT_TIMEOUT};
+
+/// Default interval between two refreshes of a [`Refresher`]
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Default max age of the data served by an [`RconStats`] provider
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Players online, as listed by the `list` command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerList {
+    /// Number of players online
+    pub online: u32,
+    /// Max number of players
+    pub max: u32,
+    /// Names of the players online
+    pub players: Vec<String>,
+}
+
+/// Parse the output of the `list` command.
+///
+/// Both the vanilla format, before and after 1.13, and the format of the
+/// Essentials plugin, with players grouped by rank, are supported. Formatting
+/// codes and tags such as `[AFK]` are removed from player names.
+///
+/// ```rust
+/// # use minecraft_server_query::responder::rcon_bridge::parse_list;
+/// let list = parse_list("There are 2 of a max of 20 players online: Alice, Bob").unwrap();
+/// assert_eq!((list.online, list.max), (2, 20));
+/// assert_eq!(list.players, ["Alice", "Bob"]);
+///
+/// let list = parse_list(
+///     "§6There are §c2§6 out of maximum §c20§6 players online.\n\
+///      §6admins§r: §7[AFK]§rAlice\n§6default§r: Bob",
+/// )
+/// .unwrap();
+/// assert_eq!(list.players, ["Alice", "Bob"]);
+/// ```
+pub fn parse_list(output: &str) -> Option<PlayerList> {
+    let output = strip_codes(output);
+    let (counts, names) = output.split_once("players online")?;
+    let counts = counts.trim_start().strip_prefix("There are ")?.trim_end();
+
+    let (online, max) = match counts
+        .split_once(" of a max of ")
+        .or_else(|| counts.split_once(" out of maximum "))
+    {
+        // Essentials lists hidden players as `<online>/<hidden>`
+        Some((online, max)) => (online.split('/').next()?, max),
+        None => counts.split_once('/')?,
+    };
+
+    // Player names cannot contain colons, which end the Essentials group names
+    let players = names
+        .trim_start_matches(['.', ':'])
+        .lines()
+        .filter_map(|line| line.rsplit(':').next())
+        .flat_map(|line| line.split(','))
+        .map(player_name)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    Some(PlayerList {
+        online: online.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        players,
+    })
+}
+
+/// Remove the whitespace and leading tags, such as `[AFK]`, around a name.
+fn player_name(mut name: &str) -> &str {
+    name = name.trim();
+    while let Some(tagged) = name.strip_prefix('[') {
+        match tagged.split_once(']') {
+            Some((_, rest)) => name = rest.trim_start(),
+            None => break,
+        }
+    }
+    name
+}
+
+/// Parse the game version from the output of the `version` command of
+/// Bukkit-based servers.
+///
+/// Returns `None` for other outputs, such as the unknown command error of
+/// vanilla servers.
+///
+/// ```rust
+/// # use minecraft_server_query::responder::rcon_bridge::parse_version;
+/// let output = "This server is running Paper version git-Paper-196 (MC: 1.20.1) \
+///     (Implementing API version 1.20.1-R0.1-SNAPSHOT)";
+/// assert_eq!(parse_version(output).as_deref(), Some("1.20.1"));
+/// assert_eq!(parse_version("Unknown or incomplete command"), None);
+/// ```
+pub fn parse_version(output: &str) -> Option<String> {
+    let output = strip_codes(output);
+    let version = match output.split_once("(MC: ") {
+        Some((_, rest)) => rest.split(')').next()?,
+        None => {
+            let (_, rest) = output.split_once("Implementing API version ")?;
+            rest.split(['-', ')']).next()?
+        }
+    };
+    let version = version.trim();
+    (!version.is_empty()).then(|| version.to_string())
+}
+
+/// Data pulled over RCON, and when it was
+#[derive(Debug, Clone)]
+struct Refreshed {
+    list: PlayerList,
+    version: Option<String>,
+    at: Instant,
+}
+
+/// A [`StatsProvider`] serving the players pulled from a server over RCON.
+///
+/// The players and the version of the template are replaced by the last ones
+/// pulled from the server, while they are younger than the
+/// [max age](Self::max_age). The template is served as is before the first
+/// refresh and once the data is too old, so it should usually have no players.
+#[derive(Debug)]
+pub struct RconStats {
+    template: FullStat,
+    max_age: Duration,
+    refreshed: RwLock<Option<Refreshed>>,
+}
+
+impl RconStats {
+    /// Build a provider serving the given template until it is refreshed.
+    ///
+    /// The [default max age](DEFAULT_MAX_AGE) is used.
+    pub fn new(template: FullStat) -> Self {
+        Self {
+            template,
+            max_age: DEFAULT_MAX_AGE,
+            refreshed: RwLock::new(None),
+        }
+    }
+
+    /// Set the max age of the data pulled from the server, after which the
+    /// template is served again.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// When the data served was last pulled from the server, if it ever was.
+    pub fn last_refresh(&self) -> Option<Instant> {
+        self.read().as_ref().map(|refreshed| refreshed.at)
+    }
+
+    /// Pull the players, and the version if `with_version` is set, from the
+    /// server with the given client.
+    ///
+    /// The served data is left untouched if a command fails, or if the output
+    /// of `list` cannot be parsed, which returns an error of kind
+    /// [`InvalidData`](io::ErrorKind::InvalidData).
+    pub fn refresh(&self, client: &mut RconClient, with_version: bool) -> io::Result<()> {
+        let output = client.command("list")?;
+        let list = parse_list(&output).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected output of the list command: {output:?}"),
+            )
+        })?;
+        let version = if with_version {
+            parse_version(&client.command("version")?)
+        } else {
+            self.read().as_ref().and_then(|r| r.version.clone())
+        };
+        self.update(list, version, Instant::now());
+        Ok(())
+    }
+
+    /// Serve the given players and version, pulled from the server at `at`.
+    fn update(&self, list: PlayerList, version: Option<String>, at: Instant) {
+        *self
+            .refreshed
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Refreshed { list, version, at });
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Option<Refreshed>> {
+        self.refreshed
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The status served at `now`.
+    fn full_stat_at(&self, now: Instant) -> FullStat {
+        let mut stat = self.template.clone();
+        let refreshed = self.read();
+        let Some(refreshed) = refreshed
+            .as_ref()
+            .filter(|r| now.saturating_duration_since(r.at) <= self.max_age)
+        else {
+            return stat;
+        };
+
+        stat.numplayers = refreshed.list.online;
+        stat.maxplayers = refreshed.list.max;
+        stat.player_list = refreshed
+            .list
+            .players
+            .iter()
+            .map(|name| name.as_str().into())
+            .collect();
+        if let Some(version) = &refreshed.version {
+            stat.version = version.as_str().into();
+        }
+        stat
+    }
+}
+
+impl StatsProvider for RconStats {
+    fn full_stat(&self) -> FullStat {
+        self.full_stat_at(Instant::now())
+    }
+}
+
+/// Periodically pulls the players of a server over RCON into an [`RconStats`]
+/// provider.
+#[derive(Debug, Clone)]
+pub struct Refresher {
+    addrs: Vec<SocketAddr>,
+    password: String,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Refresher {
+    /// Build a refresher connecting to the given RCON address with the given
+    /// password. The address is resolved once.
+    ///
+    /// The [default interval](DEFAULT_REFRESH_INTERVAL) and
+    /// [timeout](DEFAULT_TIMEOUT) are used.
+    pub fn new(addr: impl ToSocketAddrs, password: &str) -> io::Result<Self> {
+        Ok(Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            password: password.to_string(),
+            interval: DEFAULT_REFRESH_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Set the interval between two refreshes.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the timeout of the connection and of every command.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Spawn a thread refreshing the provider every interval, until the
+    /// returned handle is stopped or dropped.
+    ///
+    /// The connection is kept open between refreshes, and opened again on
+    /// the next refresh after an error. The version is pulled once per
+    /// connection.
+    pub fn spawn(self, stats: Arc<RconStats>) -> RefresherHandle {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            let mut client = None;
+            loop {
+                let _ = self.refresh(&mut client, &stats);
+                if stopped.recv_timeout(self.interval) != Err(mpsc::RecvTimeoutError::Timeout) {
+                    break;
+                }
+            }
+        });
+
+        RefresherHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Refresh the provider, connecting first if needed, and dropping the
+    /// connection if it failed.
+    fn refresh(&self, client: &mut Option<RconClient>, stats: &RconStats) -> io::Result<()> {
+        let with_version = client.is_none();
+        let connected = match client {
+            Some(connected) => connected,
+            None => client.insert(RconClient::connect_with_timeout(
+                &self.addrs[..],
+                &self.password,
+                Some(self.timeout),
+            )?),
+        };
+        let res = stats.refresh(connected, with_version);
+        if res.is_err() {
+            *client = None;
+        }
+        res
+    }
+}
+
+/// Handle to a refresher thread, stopping it when dropped.
+#[derive(Debug)]
+pub struct RefresherHandle {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RefresherHandle {
+    /// Stop the refresher thread, waiting for it to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for RefresherHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::super::{blocking::Server, tests::test_stat};
+    use super::*;
+    use crate::blocking::QueryClient;
+    use crate::rcon::tests::spawn_stub_answering;
+
+    const VANILLA_LIST: &str = "There are 3 of a max of 50 players online: Alice, Bob, Carol";
+    const PAPER_VERSION: &str = "This server is running Paper version git-Paper-196 \
+        (MC: 1.20.4) (Implementing API version 1.20.4-R0.1-SNAPSHOT)";
+
+    fn template() -> FullStat {
+        FullStat {
+            numplayers: 0,
+            player_list: Vec::new(),
+            ..test_stat()
+        }
+    }
+
+    fn query(stats: Arc<RconStats>) -> FullStat {
+        let mut server = Server::bind("127.0.0.1:0", stats).unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || server.serve());
+
+        let client = QueryClient::new_with_port("127.0.0.1", port).unwrap();
+        let token = client.handshake().unwrap();
+        client.full_stat(token).unwrap()
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = |players: &[&str]| players.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+
+        // Vanilla, after and before 1.13
+        let parsed = parse_list(VANILLA_LIST).unwrap();
+        assert_eq!((parsed.online, parsed.max), (3, 50));
+        assert_eq!(parsed.players, list(&["Alice", "Bob", "Carol"]));
+        let parsed = parse_list("There are 2/20 players online:\nAlice, Bob\n").unwrap();
+        assert_eq!((parsed.online, parsed.max), (2, 20));
+        assert_eq!(parsed.players, list(&["Alice", "Bob"]));
+        assert_eq!(
+            parse_list("There are 0 of a max of 20 players online: "),
+            Some(PlayerList {
+                online: 0,
+                max: 20,
+                players: Vec::new(),
+            })
+        );
+
+        // Essentials, with hidden players and tags
+        let parsed = parse_list(
+            "§6There are §c3§6/§c1§6 out of maximum §c100§6 players online.\n\
+             §6Admins§r: §7[HIDDEN]§rAlice\n\
+             §6default§r: §7[AFK]§r Bob, Carol\n",
+        )
+        .unwrap();
+        assert_eq!((parsed.online, parsed.max), (3, 100));
+        assert_eq!(parsed.players, list(&["Alice", "Bob", "Carol"]));
+
+        assert_eq!(parse_list("Unknown or incomplete command"), None);
+        assert_eq!(parse_list("There are many players online"), None);
+        assert_eq!(
+            parse_list("There are two of a max of 20 players online: "),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version(PAPER_VERSION).as_deref(), Some("1.20.4"));
+        assert_eq!(
+            parse_version(
+                "This server is running Paper version 1.21.1-119-master@7ca3e8e (2024-10-12T20:50:50Z) \
+                 (Implementing API version 1.21.1-R0.1-SNAPSHOT)"
+            )
+            .as_deref(),
+            Some("1.21.1")
+        );
+        assert_eq!(parse_version("Unknown or incomplete command"), None);
+    }
+
+    #[test]
+    fn test_max_age() {
+        let stats = RconStats::new(template()).max_age(Duration::from_secs(60));
+        let start = Instant::now();
+        assert_eq!(stats.full_stat_at(start), template());
+        assert_eq!(stats.last_refresh(), None);
+
+        stats.update(parse_list(VANILLA_LIST).unwrap(), None, start);
+        let stat = stats.full_stat_at(start + Duration::from_secs(60));
+        assert_eq!(stat.numplayers, 3);
+        assert_eq!(stat.maxplayers, 50);
+        assert_eq!(stat.player_list, ["Alice", "Bob", "Carol"]);
+        assert_eq!(stat.version, template().version);
+        assert_eq!(stats.last_refresh(), Some(start));
+
+        assert_eq!(
+            stats.full_stat_at(start + Duration::from_secs(61)),
+            template()
+        );
+    }
+
+    #[test]
+    fn test_bridge() {
+        let addr = spawn_stub_answering(
+            "password",
+            true,
+            &[("list", VANILLA_LIST), ("version", PAPER_VERSION)],
+        );
+        let stats = Arc::new(RconStats::new(template()));
+        let refresher = Refresher::new(addr, "password")
+            .unwrap()
+            .interval(Duration::from_millis(50))
+            .spawn(stats.clone());
+        let start = Instant::now();
+        while stats.last_refresh().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        refresher.stop();
+
+        let stat = query(stats);
+        assert_eq!(stat.hostname, "A Responder");
+        assert_eq!(stat.version, "1.20.4");
+        assert_eq!(stat.numplayers, 3);
+        assert_eq!(stat.maxplayers, 50);
+        assert_eq!(stat.player_list, ["Alice", "Bob", "Carol"]);
+    }
+
+    #[test]
+    fn test_outage() {
+        let addr = spawn_stub_answering("password", true, &[("list", VANILLA_LIST)]);
+        let stats = Arc::new(RconStats::new(template()).max_age(Duration::from_millis(300)));
+        let mut client = RconClient::connect(addr, "password").unwrap();
+        stats.refresh(&mut client, true).unwrap();
+        let refreshed = stats.last_refresh();
+        // The stub only accepts a single connection
+        drop(client);
+
+        // A refresher failing to connect leaves the last-known data
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let _refresher = Refresher::new(closed, "password")
+            .unwrap()
+            .interval(Duration::from_millis(50))
+            .spawn(stats.clone());
+        let stat = query(stats.clone());
+        assert_eq!(stat.player_list, ["Alice", "Bob", "Carol"]);
+        assert_eq!(stat.version, template().version);
+
+        std::thread::sleep(Duration::from_millis(400));
+        assert_eq!(stats.last_refresh(), refreshed);
+        assert_eq!(query(stats), template());
+
+        // Unexpected outputs are errors
+        let addr = spawn_stub_answering("password", true, &[]);
+        let stats = RconStats::new(template());
+        let mut client = RconClient::connect(addr, "password").unwrap();
+        let err = stats.refresh(&mut client, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(stats.last_refresh(), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the Query responder.
+//!
+//! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
+
+use ::tokio::{
+    net::{ToSocketAddrs, UdpSocket},
+    runtime::Handle,
+    time::timeout,
+};
+use std::{io, net::SocketAddr};
+
+use super::*;
+use crate::task::TaskHandle;
+
+/// An asynchronous Query server, using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+#[derive(Debug)]
+pub struct Server<P> {
+    socket: UdpSocket,
+    responder: Responder,
+    stats: P,
+    render_timeout: Option<Duration>,
+}
+
+impl<P: AsyncStatsProvider> Server<P> {
+    /// Bind a new server to the given address, answering status requests
+    /// with the status given by the provider.
+    ///
+    /// The default [render timeout](DEFAULT_RENDER_TIMEOUT) and [limits](Limits) are used.
+    pub async fn bind(addr: impl ToSocketAddrs, stats: P) -> io::Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind(addr).await?,
+            responder: Responder::new(),
+            stats,
+            render_timeout: Some(DEFAULT_RENDER_TIMEOUT),
+        })
+    }
+
+    /// The local address the server is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The provider giving the server status.
+    pub fn stats(&self) -> &P {
+        &self.stats
+    }
+
+    /// Set the limits protecting the server against amplification abuse.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.responder.set_limits(limits);
+    }
+
+    /// Record the packets received by the server with the given observer,
+    /// or stop recording them with `None`.
+    pub fn set_observer(&mut self, observer: Option<honeypot::Observer>) {
+        self.responder.set_observer(observer);
+    }
+
+    /// Set the time allowed to the provider to return the server status.
+    /// Status requests are dropped if the provider takes longer.
+    ///
+    /// The provider is awaited on the serving task: without a timeout, a
+    /// stalled provider stalls the server.
+    pub fn set_render_timeout(&mut self, timeout: Option<Duration>) {
+        self.render_timeout = timeout;
+    }
+
+    /// Receive and answer a single request.
+    pub async fn serve_one(&mut self) -> io::Result<()> {
+        let mut buf = [0; REQUEST_SIZE];
+        let (received, source) = match self.socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            // ICMP port unreachable errors from previous responses are reported on Windows
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let accepted = match self
+            .responder
+            .accept(&buf[..received], source, Instant::now())
+        {
+            Some(accepted) => accepted,
+            None => return Ok(()),
+        };
+        let render = accepted.render_async(&self.stats);
+        let response = match self.render_timeout {
+            Some(render_timeout) => timeout(render_timeout, render).await.ok().flatten(),
+            None => render.await,
+        };
+
+        if let Some(response) = response {
+            self.socket.send_to(&response, source).await?;
+        }
+        Ok(())
+    }
+
+    /// Answer requests until an IO error occurs.
+    pub async fn serve(&mut self) -> io::Result<()> {
+        loop {
+            self.serve_one().await?;
+        }
+    }
+}
+
+impl<P: AsyncStatsProvider + Send + 'static> Server<P> {
+    /// Spawn a task answering requests until an IO error occurs, or until the
+    /// returned handle is stopped or dropped.
+    pub fn spawn(self) -> TaskHandle {
+        self.spawn_on(&Handle::current())
+    }
+
+    /// Like [`spawn`](Self::spawn), but spawn the task on the given runtime.
+    pub fn spawn_on(mut self, runtime: &Handle) -> TaskHandle {
+        TaskHandle::spawn(runtime, async move { self.serve().await })
+    }
+}
+
+/// Convenience function to answer requests on the given address with the
+/// status given by the provider, until an IO error occurs.
+pub async fn serve(addr: impl ToSocketAddrs, stats: impl AsyncStatsProvider) -> io::Result<()> {
+    Server::bind(addr, stats).await?.serve().await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{Arc, RwLock},
+        time::Duration,
+    };
+
+    use super::super::tests::test_stat;
+    use super::{AsyncStatsProvider, Server};
+    use crate::tokio::QueryClient;
+    use crate::FullStat;
+
+    #[tokio::test]
+    async fn test_tokio_client() {
+        let server = Server::bind("127.0.0.1:0", test_stat()).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = server.spawn();
+
+        let client = QueryClient::new_with_port("127.0.0.1", port).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        let basic = client.basic_stat(token).await.unwrap();
+        assert_eq!(basic.numplayers, 2);
+        assert_eq!(client.full_stat(token).await.unwrap(), test_stat());
+
+        // The server no longer answers once the task has exited
+        handle.stop().await.unwrap();
+        assert!(client.handshake().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dynamic_stats() {
+        let stats = Arc::new(RwLock::new(test_stat()));
+        let server = Server::bind("127.0.0.1:0", stats.clone()).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = server.spawn();
+
+        let client = QueryClient::new_with_port("127.0.0.1", port).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        assert_eq!(client.basic_stat(token).await.unwrap().motd, "A Responder");
+        stats.write().unwrap().hostname = "Updated".into();
+        assert_eq!(client.basic_stat(token).await.unwrap().motd, "Updated");
+
+        handle.abort();
+    }
+
+    struct Stalled;
+
+    impl AsyncStatsProvider for Stalled {
+        async fn full_stat(&self) -> FullStat {
+            ::tokio::time::sleep(Duration::from_secs(5)).await;
+            test_stat()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_render_timeout() {
+        let server = Server::bind("127.0.0.1:0", Stalled).await.unwrap();
+        let port = server.local_addr().unwrap().port();
+        let handle = server.spawn();
+
+        let client = QueryClient::new_with_port("127.0.0.1", port).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        let err = client.full_stat(token).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        client.handshake().await.unwrap();
+
+        handle.abort();
+    }
+}
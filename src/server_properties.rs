@@ -0,0 +1,320 @@
+//! Helpers for the `server.properties` file of a server.
+//!
+//! When running on the same machine as the server, its configuration is the
+//! most reliable way to know if and where the Query protocol is enabled:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking::QueryClient, server_properties};
+//! if let Some(addr) = server_properties::query_target_from_properties("server.properties")? {
+//!     let client = QueryClient::new_addr(addr)?;
+//!     let token = client.handshake()?;
+//!     println!("{:?}", client.full_stat(token)?);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The file uses the Java properties format: one `key=value` pair per line,
+//! with comments starting with `#` or `!`, and `\` escapes, including `\uXXXX`
+//! escapes for unicode characters.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    path::Path,
+};
+
+use crate::{custom_io_error, latin1_to_string, DEFAULT_PORT};
+
+/// Parse the content of a properties file into key-value pairs.
+///
+/// Keys are separated from values by the first unescaped `=`, `:` or
+/// whitespace. Lines ending with an odd number of backslashes continue on the
+/// next line. Invalid `\u` escapes are kept as is. If a key appears more than
+/// once, the last value is kept.
+///
+/// ```rust
+/// # use minecraft_server_query::server_properties::parse_properties;
+/// let properties = parse_properties("# Comment\nmotd=A \\u00A7aGreen Server\nenable-query = true");
+/// assert_eq!(properties["motd"], "A §aGreen Server");
+/// assert_eq!(properties["enable-query"], "true");
+/// ```
+pub fn parse_properties(content: &str) -> HashMap<String, String> {
+    let mut res = HashMap::new();
+    let mut lines = content.lines();
+
+    while let Some(line) = lines.next() {
+        let mut line = line.trim_start().to_string();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+        while ends_with_continuation(&line) {
+            line.pop();
+            match lines.next() {
+                Some(next) => line.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+
+        let (key, value) = split_key_value(&line);
+        res.insert(unescape(key), unescape(value));
+    }
+    res
+}
+
+/// Read and parse a properties file, see [`parse_properties`].
+///
+/// The file is decoded as UTF-8, as written by recent servers, or as latin-1
+/// if it is not valid UTF-8, as written by older servers.
+pub fn read_properties(path: impl AsRef<Path>) -> io::Result<HashMap<String, String>> {
+    let bytes = std::fs::read(path)?;
+    let content = match String::from_utf8(bytes) {
+        Ok(content) => content,
+        Err(e) => latin1_to_string(e.as_bytes()),
+    };
+    Ok(parse_properties(&content))
+}
+
+/// Address to send queries to, from the properties of a server. Returns `None`
+/// if the Query protocol is disabled.
+///
+/// The port is `query.port`, defaulting to `server-port`, then to the
+/// [default port](DEFAULT_PORT). The IP address is `server-ip`, defaulting to
+/// the loopback address if it is empty or unspecified.
+///
+/// ```rust
+/// # use minecraft_server_query::server_properties::{parse_properties, query_target};
+/// let properties = parse_properties("enable-query=true\nserver-port=25566");
+/// assert_eq!(query_target(&properties)?, Some("127.0.0.1:25566".parse().unwrap()));
+///
+/// let properties = parse_properties("enable-query=false\nquery.port=25565");
+/// assert_eq!(query_target(&properties)?, None);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn query_target(properties: &HashMap<String, String>) -> io::Result<Option<SocketAddr>> {
+    if properties.get("enable-query").map(|v| v.trim()) != Some("true") {
+        return Ok(None);
+    }
+
+    let port = match ["query.port", "server-port"]
+        .iter()
+        .find_map(|key| properties.get(*key).filter(|v| !v.trim().is_empty()))
+    {
+        Some(port) => port
+            .trim()
+            .parse::<u16>()
+            .map_err(|_| custom_io_error("Invalid port in server properties."))?,
+        None => DEFAULT_PORT,
+    };
+
+    let ip = match properties.get("server-ip").map(|v| v.trim()) {
+        None | Some("") => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        Some(ip) => match ip.parse::<IpAddr>() {
+            Ok(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Ok(ip) => ip,
+            Err(_) => {
+                return (ip, port)
+                    .to_socket_addrs()?
+                    .next()
+                    .map(Some)
+                    .ok_or_else(|| custom_io_error("Could not resolve the server IP."))
+            }
+        },
+    };
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Read the `server.properties` file at the given path, and return the address
+/// to send queries to, see [`query_target`].
+pub fn query_target_from_properties(path: impl AsRef<Path>) -> io::Result<Option<SocketAddr>> {
+    query_target(&read_properties(path)?)
+}
+
+/// Whether a line ends with an odd number of backslashes, escaping the line break.
+fn ends_with_continuation(line: &str) -> bool {
+    line.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 1
+}
+
+/// Split a line at the first unescaped separator, skipping the whitespace around it.
+fn split_key_value(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let end = line
+        .char_indices()
+        .find(|&(_, c)| {
+            let separator = !escaped && matches!(c, '=' | ':' | ' ' | '\t' | '\x0c');
+            escaped = !escaped && c == '\\';
+            separator
+        })
+        .map_or(line.len(), |(i, _)| i);
+
+    let (key, rest) = line.split_at(end);
+    let rest = rest.trim_start_matches([' ', '\t', '\x0c']);
+    let rest = rest
+        .strip_prefix(['=', ':'])
+        .unwrap_or(rest)
+        .trim_start_matches([' ', '\t', '\x0c']);
+    (key, rest)
+}
+
+/// Replace the escape sequences of a key or value.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => res.push('\t'),
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some('f') => res.push('\x0c'),
+            Some('u') => {
+                let hex: String = chars.clone().take(4).collect();
+                match u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == 4)
+                {
+                    Some(code) => {
+                        res.push(decode_utf16_unit(code, &mut chars));
+                        chars.nth(3);
+                    }
+                    None => res.push_str("\\u"),
+                }
+            }
+            Some(c) => res.push(c),
+            None => {}
+        }
+    }
+    res
+}
+
+/// Decode a UTF-16 code unit from a `\u` escape. A high surrogate is combined
+/// with the low surrogate of the following `\u` escape, if any. Unpaired
+/// surrogates are replaced with U+FFFD.
+fn decode_utf16_unit(code: u32, chars: &mut std::str::Chars) -> char {
+    if !(0xD800..0xDC00).contains(&code) {
+        return char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+    }
+    // The 4 hex digits of the high surrogate are still in the iterator
+    let rest = chars.as_str();
+    let low = rest
+        .get(4..10)
+        .and_then(|escape| escape.strip_prefix("\\u"))
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .filter(|low| (0xDC00..0xE000).contains(low));
+    match low {
+        Some(low) => {
+            chars.nth(5);
+            char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+        }
+        None => char::REPLACEMENT_CHARACTER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Properties written by a vanilla 1.20 server
+    const VANILLA: &str = "\
+#Minecraft server properties
+#Sat Jul 01 12:00:00 UTC 2023
+enable-jmx-monitoring=false
+rcon.port=25575
+level-seed=
+gamemode=survival
+enable-command-block=false
+enable-query=true
+generator-settings={}
+level-name=world
+motd=A Minecraft Server
+query.port=25585
+pvp=true
+server-ip=
+server-port=25565
+";
+
+    /// Properties with escapes, as written by older servers with latin-1 encoding
+    const ESCAPED: &str = "\
+! Edited by hand
+   motd = \\u00A76\\u00A7lCaf\\u00E9 \\ud83d\\ude00 Server\\
+          \\u00A7rOpen 24/7
+level-name:Tom\\:s\\ World
+key\\ with\\ spaces value
+broken=\\u12G4 \\ud83d
+enable-query=true
+server-port=25570
+server-ip=0.0.0.0
+trailing=\\\\
+";
+
+    #[test]
+    fn test_parse_vanilla() {
+        let properties = parse_properties(VANILLA);
+        assert_eq!(properties.len(), 13);
+        assert_eq!(properties["level-seed"], "");
+        assert_eq!(properties["generator-settings"], "{}");
+        assert_eq!(properties["motd"], "A Minecraft Server");
+        assert_eq!(
+            query_target(&properties).unwrap(),
+            Some(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 25585))
+        );
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let properties = parse_properties(ESCAPED);
+        assert_eq!(properties["motd"], "§6§lCafé 😀 Server§rOpen 24/7");
+        assert_eq!(properties["level-name"], "Tom:s World");
+        assert_eq!(properties["key with spaces"], "value");
+        assert_eq!(properties["broken"], "\\u12G4 \u{FFFD}");
+        assert_eq!(properties["trailing"], "\\");
+        // The query port defaults to the server port
+        assert_eq!(
+            query_target(&properties).unwrap(),
+            Some(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 25570))
+        );
+    }
+
+    #[test]
+    fn test_query_target() {
+        let disabled = parse_properties("enable-query=false\nquery.port=25565");
+        assert_eq!(query_target(&disabled).unwrap(), None);
+        assert_eq!(query_target(&HashMap::new()).unwrap(), None);
+
+        let defaults = parse_properties("enable-query=true\nquery.port=\nserver-ip=::1");
+        assert_eq!(
+            query_target(&defaults).unwrap(),
+            Some("[::1]:25565".parse().unwrap())
+        );
+
+        let invalid = parse_properties("enable-query=true\nquery.port=70000");
+        assert!(query_target(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_query_target_from_file() {
+        let path = std::env::temp_dir().join(format!(
+            "minecraft-server-query-{}.properties",
+            std::process::id()
+        ));
+        // Latin-1 encoded file
+        std::fs::write(
+            &path,
+            b"motd=Caf\xe9\nenable-query=true\nquery.port=25590\n",
+        )
+        .unwrap();
+        assert_eq!(read_properties(&path).unwrap()["motd"], "Café");
+        let target = query_target_from_properties(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            target.unwrap(),
+            Some(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 25590))
+        );
+
+        assert!(query_target_from_properties(&path).is_err());
+    }
+}
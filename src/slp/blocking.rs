@@ -0,0 +1,235 @@
+//! Blocking implementation of the Server List Ping protocol.
+//!
+//! Uses [std::net::TcpStream] for sending and receiving TCP data.
+
+use std::{
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use super::legacy::{self, LegacyStatus};
+use super::*;
+use crate::{check_no_port, split_address, zone::HostPort, DEFAULT_TIMEOUT};
+
+/// A blocking Server List Ping client using the [`std`] networking primitives.
+///
+/// A new TCP connection is opened for every ping, like the Minecraft client does.
+#[derive(Debug, Clone)]
+pub struct PingClient {
+    host: String,
+    port: u16,
+    timeout: Option<Duration>,
+}
+
+impl PingClient {
+    /// Build a new PingClient from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new(ip: &str) -> io::Result<Self> {
+        let (ip, port) = split_address(ip)?;
+        Self::new_with_port(ip, port)
+    }
+
+    /// Build a new PingClient from the given IP address and port.
+    ///
+    /// If the IP address already contains a port, an error is returned.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
+        Self::new_with_timeout(ip, port, Some(DEFAULT_TIMEOUT))
+    }
+
+    /// Build a new PingClient from the given IP address, port and optional
+    /// timeout, applied to the connection and to every read and write.
+    ///
+    /// The IP adress must not contain a port.
+    pub fn new_with_timeout(ip: &str, port: u16, timeout: Option<Duration>) -> io::Result<Self> {
+        check_no_port(ip)?;
+
+        Ok(Self {
+            host: ip.to_string(),
+            port,
+            timeout,
+        })
+    }
+
+    /// Request the status of the server.
+    pub fn status(&self) -> io::Result<SlpStatus> {
+        let mut stream = self.request_status()?;
+        parse_status_response(&read_packet(&mut stream)?)
+    }
+
+    /// Request the status of the server, then measure the latency with a
+    /// ping on the same connection.
+    pub fn status_with_latency(&self) -> io::Result<(SlpStatus, Duration)> {
+        let mut stream = self.request_status()?;
+        let status = parse_status_response(&read_packet(&mut stream)?)?;
+
+        let payload = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_millis() as i64;
+        let start = Instant::now();
+        stream.write_all(&ping_request(payload))?;
+        if parse_pong(&read_packet(&mut stream)?)? != payload {
+            return Err(custom_io_error("Pong payload does not match the ping."));
+        }
+
+        Ok((status, start.elapsed()))
+    }
+
+    /// Request the status of the server with a [legacy ping](legacy), for
+    /// servers before 1.7.
+    pub fn legacy_status(&self) -> io::Result<LegacyStatus> {
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+
+        stream.write_all(&legacy::LEGACY_PING)?;
+        LegacyStatus::from_response(&legacy::read_response(&mut stream)?)
+    }
+
+    /// Connect to the server and send the handshake and status request.
+    fn request_status(&self) -> io::Result<TcpStream> {
+        let mut stream = self.connect()?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+
+        let mut request = handshake(&self.host, self.port);
+        request.extend_from_slice(&status_request());
+        stream.write_all(&request)?;
+        Ok(stream)
+    }
+
+    fn connect(&self) -> io::Result<TcpStream> {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return TcpStream::connect(HostPort(&self.host, self.port)),
+        };
+
+        let mut last_err = None;
+        for addr in HostPort(&self.host, self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| custom_io_error("Could not resolve server address.")))
+    }
+}
+
+/// Convenience function to request the status of a server with a Server List Ping.
+///
+/// If no port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+pub fn ping(ip: &str) -> io::Result<SlpStatus> {
+    PingClient::new(ip)?.status()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        time::Duration,
+    };
+
+    use super::PingClient;
+    use crate::slp::legacy::{LegacyStatus, LEGACY_PING};
+    use crate::testing::MockSlpServer;
+
+    #[test]
+    fn test_status() {
+        let server = MockSlpServer::new().unwrap();
+        let client = PingClient::new(&server.addr().to_string()).unwrap();
+
+        assert_eq!(client.status().unwrap(), server.status());
+        let (status, latency) = client.status_with_latency().unwrap();
+        assert_eq!(status, server.status());
+        assert!(latency < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_legacy_status() {
+        let status = LegacyStatus {
+            motd: "A Legacy Server".to_string(),
+            numplayers: 3,
+            maxplayers: 20,
+            version: Some("1.6.4".to_string()),
+            protocol: Some(78),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let response = status.to_response();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 2];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&response).unwrap();
+            request
+        });
+
+        let client = PingClient::new(&addr.to_string()).unwrap();
+        assert_eq!(client.legacy_status().unwrap(), status);
+        assert_eq!(server.join().unwrap(), LEGACY_PING);
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        assert!(PingClient::new("127.0.0.1:notaport").is_err());
+        assert!(PingClient::new_with_port("127.0.0.1:25565", 25565).is_err());
+
+        assert!(PingClient::new("[::1]:25565").is_ok());
+        assert!(PingClient::new("fe80::1%eth0").is_ok());
+        assert!(PingClient::new_with_port("::1", 25565).is_ok());
+        assert!(PingClient::new_with_port("fe80::1%eth0", 25565).is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_zoned_address() {
+        let listener = TcpListener::bind("[::1]:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let status = LegacyStatus {
+            motd: "A Link-Local Server".to_string(),
+            numplayers: 0,
+            maxplayers: 20,
+            version: None,
+            protocol: None,
+        };
+        let response = status.to_response();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.read_exact(&mut [0; 2]).unwrap();
+            stream.write_all(&response).unwrap();
+        });
+
+        let client = PingClient::new(&format!("[::1%lo]:{port}")).unwrap();
+        assert_eq!(client.legacy_status().unwrap(), status);
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            // Accept the connection but never answer
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read_to_end(&mut Vec::new());
+        });
+
+        let client = PingClient::new_with_timeout(
+            &addr.ip().to_string(),
+            addr.port(),
+            Some(Duration::from_millis(100)),
+        )
+        .unwrap();
+        let err = client.status().unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ));
+    }
+}
@@ -0,0 +1,233 @@
+//! Decoding of the server icon sent in the status.
+
+use std::{error::Error, fmt, io};
+
+use super::SlpStatus;
+
+/// Prefix of the data URI of a server icon
+pub const FAVICON_PREFIX: &str = "data:image/png;base64,";
+/// Signature at the start of every PNG file
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Error while decoding a server icon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaviconError {
+    /// The icon does not start with the `data:image/png;base64,` prefix
+    InvalidPrefix,
+    /// The base64 data is malformed
+    InvalidBase64 {
+        /// Position of the first invalid character in the base64 data, or its
+        /// length if the data is truncated
+        position: usize,
+    },
+    /// The decoded data does not start with the PNG signature
+    NotPng,
+}
+
+impl fmt::Display for FaviconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPrefix => write!(f, "Favicon is not a base64 PNG data URI."),
+            Self::InvalidBase64 { position } => {
+                write!(f, "Invalid base64 in favicon at position {position}.")
+            }
+            Self::NotPng => write!(f, "Favicon is not a PNG image."),
+        }
+    }
+}
+
+impl Error for FaviconError {}
+
+impl From<FaviconError> for io::Error {
+    fn from(e: FaviconError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl SlpStatus {
+    /// The server icon, as the raw data URI sent by the server.
+    pub fn favicon_uri(&self) -> Option<&str> {
+        self.favicon.as_deref()
+    }
+
+    /// Decode the server icon into PNG bytes. Returns `None` if the server
+    /// sent no icon.
+    ///
+    /// Line breaks in the base64 data, sent by some older servers, are ignored.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::slp::{FaviconError, SlpStatus};
+    /// let mut status = SlpStatus::from_json(
+    ///     r#"{"version":{"name":"1.20.1","protocol":763},"players":{"max":20,"online":0}}"#,
+    /// )?;
+    /// assert!(status.favicon_png().is_none());
+    ///
+    /// status.favicon = Some("data:image/png;base64,iVBORw0KGgo=".to_string());
+    /// assert_eq!(status.favicon_png(), Some(Ok(b"\x89PNG\r\n\x1a\n".to_vec())));
+    ///
+    /// status.favicon = Some("data:image/jpeg;base64,".to_string());
+    /// assert_eq!(status.favicon_png(), Some(Err(FaviconError::InvalidPrefix)));
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn favicon_png(&self) -> Option<Result<Vec<u8>, FaviconError>> {
+        self.favicon_uri().map(decode_favicon)
+    }
+}
+
+/// Decode a server icon data URI into PNG bytes.
+fn decode_favicon(uri: &str) -> Result<Vec<u8>, FaviconError> {
+    let data = uri
+        .strip_prefix(FAVICON_PREFIX)
+        .ok_or(FaviconError::InvalidPrefix)?;
+    let png = decode_base64(data.as_bytes())?;
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(FaviconError::NotPng);
+    }
+    Ok(png)
+}
+
+/// Value of a character of the standard base64 alphabet
+fn base64_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode standard base64, with optional padding. ASCII whitespace is ignored.
+fn decode_base64(data: &[u8]) -> Result<Vec<u8>, FaviconError> {
+    let mut res = Vec::with_capacity(data.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for (position, &c) in data.iter().enumerate() {
+        if c.is_ascii_whitespace() {
+            continue;
+        }
+        if c == b'=' {
+            padding += 1;
+            continue;
+        }
+        let value = match base64_value(c) {
+            // Data after padding is invalid
+            Some(value) if padding == 0 => value,
+            _ => return Err(FaviconError::InvalidBase64 { position }),
+        };
+
+        acc = (acc << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((acc >> bits) as u8);
+        }
+    }
+
+    // A single character cannot encode a byte, and padding must complete the last quantum
+    let remainder = (res.len() % 3, bits);
+    let valid = matches!(
+        (remainder, padding),
+        ((0, 0), 0) | ((1, 4), 0 | 2) | ((2, 2), 0 | 1)
+    );
+    // Unused bits of the last character must be zero
+    if !valid || acc & ((1 << bits) - 1) != 0 {
+        return Err(FaviconError::InvalidBase64 {
+            position: data.len(),
+        });
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Server icon of a 64x64 image
+    const FAVICON: &str = "data:image/png;base64,\
+        iVBORw0KGgoAAAANSUhEUgAAAEAAAABACAIAAAAlC+aJAAAAT0lEQVR42u3PQQkAAAgEsGtkASva2Qi+hcEKLD31WgQEBAQE\
+        BAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQELgvqmoDiU+8BuwAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn test_decode_favicon() {
+        let png = decode_favicon(FAVICON).unwrap();
+        assert_eq!(png.len(), 136);
+        assert!(png.starts_with(PNG_SIGNATURE));
+        // Width and height in the IHDR chunk
+        assert_eq!(png[16..24], [0, 0, 0, 64, 0, 0, 0, 64]);
+
+        // Line breaks are ignored
+        let wrapped = FAVICON.replace("AAAA", "AAAA\n");
+        assert_eq!(decode_favicon(&wrapped).unwrap(), png);
+    }
+
+    #[test]
+    fn test_corrupted_favicon() {
+        let corrupted = FAVICON.replace("QVR4", "QV!4");
+        assert_eq!(
+            decode_favicon(&corrupted),
+            Err(FaviconError::InvalidBase64 { position: 54 })
+        );
+
+        let truncated = &FAVICON[..FAVICON.len() - 3];
+        assert!(matches!(
+            decode_favicon(truncated),
+            Err(FaviconError::InvalidBase64 { .. })
+        ));
+        // Unpadded data is accepted
+        assert_eq!(
+            decode_favicon(&FAVICON[..FAVICON.len() - 2]),
+            decode_favicon(FAVICON)
+        );
+
+        assert_eq!(
+            decode_favicon(&FAVICON[FAVICON_PREFIX.len()..]),
+            Err(FaviconError::InvalidPrefix)
+        );
+        // "Hello" is valid base64, but not a PNG
+        assert_eq!(
+            decode_favicon("data:image/png;base64,SGVsbG8="),
+            Err(FaviconError::NotPng)
+        );
+    }
+
+    #[test]
+    fn test_status_favicon() {
+        let status = |favicon: &str| {
+            SlpStatus::from_json(&format!(
+                r#"{{"version":{{"name":"1.20.1","protocol":763}},"players":{{"max":20,"online":0}},"favicon":"{favicon}"}}"#
+            ))
+            .unwrap()
+        };
+
+        let icon = status(FAVICON);
+        assert_eq!(icon.favicon_uri(), Some(FAVICON));
+        assert_eq!(icon.favicon_png().unwrap().unwrap().len(), 136);
+
+        let corrupted = status(&FAVICON.replace("QVR4", "QV!4"));
+        let err = io::Error::from(corrupted.favicon_png().unwrap().unwrap_err());
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Invalid base64 in favicon at position 54.");
+    }
+
+    #[test]
+    fn test_base64_padding() {
+        assert_eq!(decode_base64(b"").unwrap(), b"");
+        assert_eq!(decode_base64(b"TQ==").unwrap(), b"M");
+        assert_eq!(decode_base64(b"TWE=").unwrap(), b"Ma");
+        assert_eq!(decode_base64(b"TWFu").unwrap(), b"Man");
+        assert_eq!(decode_base64(b"TQ").unwrap(), b"M");
+        assert_eq!(decode_base64(b"TWE").unwrap(), b"Ma");
+
+        assert!(decode_base64(b"T").is_err());
+        assert!(decode_base64(b"TQ=").is_err());
+        assert!(decode_base64(b"TWE==").is_err());
+        assert!(decode_base64(b"TWFu=").is_err());
+        assert!(decode_base64(b"TQ==TQ==").is_err());
+        // Non-zero unused bits
+        assert!(decode_base64(b"TR==").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+//! Parsing of the mod list sent by Forge servers in the status.
+//!
+//! Forge servers before 1.13 send it in a `modinfo` field, and later versions
+//! in a `forgeData` field. Since 1.18.1 (FML network version 3), the mod list
+//! is encoded as a binary buffer packed in the `d` string of `forgeData`.
+
+use bytes::Buf;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{get_varint, SlpStatus};
+
+/// Version sent for mods which are only required on the server
+pub const IGNORE_SERVER_ONLY: &str = "OHNOES😱😱😱😱😱😱😱😱😱😱😱😱😱😱😱😱😱";
+
+/// A mod installed on a Forge server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mod {
+    /// Mod ID, like `jei`
+    pub id: String,
+    /// Mod version, or [`IGNORE_SERVER_ONLY`] for mods only required on the server
+    pub version: String,
+}
+
+/// Mod list of a Forge server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModList {
+    /// The parsed mod list
+    Mods(Vec<Mod>),
+    /// The raw `forgeData` or `modinfo` value, if its layout is unknown
+    Unknown(Value),
+}
+
+/// Mod of the `modinfo` layout, before 1.13
+#[derive(Deserialize)]
+struct LegacyMod {
+    modid: String,
+    version: String,
+}
+
+/// Mod of the `forgeData` layout, since 1.13
+#[derive(Deserialize)]
+struct ForgeDataMod {
+    #[serde(rename = "modId")]
+    mod_id: String,
+    modmarker: String,
+}
+
+impl SlpStatus {
+    /// The mod list of a Forge server, or `None` if the server did not send one.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::slp::{Mod, ModList, SlpStatus};
+    /// let status = SlpStatus::from_json(
+    ///     r#"{
+    ///         "version": {"name": "1.12.2", "protocol": 340},
+    ///         "players": {"max": 20, "online": 0},
+    ///         "modinfo": {"type": "FML", "modList": [{"modid": "forge", "version": "14.23.5.2860"}]}
+    ///     }"#,
+    /// )?;
+    /// assert_eq!(
+    ///     status.mods(),
+    ///     Some(ModList::Mods(vec![Mod {
+    ///         id: "forge".to_string(),
+    ///         version: "14.23.5.2860".to_string(),
+    ///     }]))
+    /// );
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn mods(&self) -> Option<ModList> {
+        if let Some(forge_data) = &self.forge_data {
+            Some(
+                parse_forge_data(forge_data)
+                    .map_or_else(|| ModList::Unknown(forge_data.clone()), ModList::Mods),
+            )
+        } else {
+            self.modinfo.as_ref().map(|modinfo| {
+                parse_modinfo(modinfo)
+                    .map_or_else(|| ModList::Unknown(modinfo.clone()), ModList::Mods)
+            })
+        }
+    }
+}
+
+/// Parse the `modinfo` layout, sent before 1.13.
+fn parse_modinfo(modinfo: &Value) -> Option<Vec<Mod>> {
+    let mods = Vec::<LegacyMod>::deserialize(modinfo.get("modList")?).ok()?;
+    Some(
+        mods.into_iter()
+            .map(|m| Mod {
+                id: m.modid,
+                version: m.version,
+            })
+            .collect(),
+    )
+}
+
+/// Parse the `forgeData` layout, with a `mods` array or an encoded `d` string.
+fn parse_forge_data(forge_data: &Value) -> Option<Vec<Mod>> {
+    if let Some(d) = forge_data.get("d") {
+        return parse_encoded_mods(&decode_optimized(d.as_str()?)?);
+    }
+    let mods = Vec::<ForgeDataMod>::deserialize(forge_data.get("mods")?).ok()?;
+    Some(
+        mods.into_iter()
+            .map(|m| Mod {
+                id: m.mod_id,
+                version: m.modmarker,
+            })
+            .collect(),
+    )
+}
+
+/// Decode a binary buffer packed in a string, 15 bits per UTF-16 code unit.
+///
+/// The first two code units hold the length of the buffer in bytes.
+fn decode_optimized(s: &str) -> Option<Vec<u8>> {
+    let mut units = s.chars().map(|c| (c as u32 <= 0x7FFF).then_some(c as u32));
+    let size = units.next()?? as usize | (units.next()?? as usize) << 15;
+
+    // The size is sent by the server, do not trust it for the allocation
+    let mut res = Vec::with_capacity(size.min(2 * s.len()));
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for unit in units {
+        acc |= unit? << bits;
+        bits += 15;
+        while bits >= 8 {
+            res.push(acc as u8);
+            acc >>= 8;
+            bits -= 8;
+        }
+    }
+    // The last code unit is padded with zeros
+    if bits > 0 {
+        res.push(acc as u8);
+    }
+    if res.len() < size {
+        return None;
+    }
+    res.truncate(size);
+    Some(res)
+}
+
+/// Read a boolean from a byte buffer, advancing it.
+fn get_bool(buf: &mut &[u8]) -> Option<bool> {
+    buf.has_remaining().then(|| buf.get_u8() != 0)
+}
+
+/// Read a string prefixed by its length as a VarInt, advancing the buffer.
+fn get_string(buf: &mut &[u8]) -> Option<String> {
+    let len = usize::try_from(get_varint(buf).ok()?).ok()?;
+    let s = std::str::from_utf8(buf.get(..len)?).ok()?.to_string();
+    buf.advance(len);
+    Some(s)
+}
+
+/// Parse the mod list of a decoded `d` buffer. Network channels are skipped.
+fn parse_encoded_mods(mut buf: &[u8]) -> Option<Vec<Mod>> {
+    let buf = &mut buf;
+    // Whether the list was truncated by the server
+    get_bool(buf)?;
+    if buf.remaining() < 2 {
+        return None;
+    }
+    let count = buf.get_u16();
+
+    let mut mods = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let flags = get_varint(buf).ok()?;
+        let channels = flags as u32 >> 1;
+        let id = get_string(buf)?;
+        let version = if flags & 1 == 0 {
+            get_string(buf)?
+        } else {
+            IGNORE_SERVER_ONLY.to_string()
+        };
+        for _ in 0..channels {
+            // Name, version, and whether the channel is required on the client
+            get_string(buf)?;
+            get_string(buf)?;
+            get_bool(buf)?;
+        }
+        mods.push(Mod { id, version });
+    }
+    Some(mods)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Status of a Forge 1.12.2 server
+    const FORGE_1_12: &str = r#"{
+        "description": {"text": "A Forge Server"},
+        "players": {"max": 20, "online": 0},
+        "version": {"name": "1.12.2", "protocol": 340},
+        "modinfo": {
+            "type": "FML",
+            "modList": [
+                {"modid": "minecraft", "version": "1.12.2"},
+                {"modid": "mcp", "version": "9.42"},
+                {"modid": "FML", "version": "8.0.99.99"},
+                {"modid": "forge", "version": "14.23.5.2860"},
+                {"modid": "jei", "version": "4.16.1.302"}
+            ]
+        }
+    }"#;
+
+    /// Status of a NeoForge 1.20.1 server
+    const NEOFORGE_1_20: &str = r#"{
+        "version": {"name": "1.20.1", "protocol": 763},
+        "enforcesSecureChat": true,
+        "description": {"text": "A NeoForge Server"},
+        "players": {"max": 20, "online": 0},
+        "preventsChatReports": false,
+        "forgeData": {
+            "channels": [],
+            "mods": [],
+            "truncated": false,
+            "fmlNetworkVersion": 3,
+            "d": "\u008f\u0000\u0000\u0008\u3424\u734b\u3656\u2e4c\u1998\u033a\u2e31\u6064\u44b8\u2821\u7660\u6e4d\u1959\u1a03\u2e37\u5c62\u48cc\u7b30\u7726\u4cac\u5d0e\u32b4\u5f72\u5ee6\u51c9\u734b\u3676\u4620\u0c0b\u0580\u6f66\u4ee4\u6995\u0399\u16c7\u6e8d\u0c40\u1897\u0201\u5406\u2595\u084b\u6353\u4645\u0c0b\u1917\u0b37\u4ad4\u69a5\u4319\u6616\u2dcd\u5b19\u1882\u302e\u605c\u0404\u1828\u1707\u6e4c\u005a\u3689\u6e69\u46ca\u05c9\u2333\u23a7\u6cae\u5a59\u3a39\u7265\u0c08\u3135\u019a"
+        }
+    }"#;
+
+    fn mod_list(mods: &[(&str, &str)]) -> ModList {
+        ModList::Mods(
+            mods.iter()
+                .map(|&(id, version)| Mod {
+                    id: id.to_string(),
+                    version: version.to_string(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_modinfo() {
+        let status = SlpStatus::from_json(FORGE_1_12).unwrap();
+        assert_eq!(
+            status.mods(),
+            Some(mod_list(&[
+                ("minecraft", "1.12.2"),
+                ("mcp", "9.42"),
+                ("FML", "8.0.99.99"),
+                ("forge", "14.23.5.2860"),
+                ("jei", "4.16.1.302"),
+            ]))
+        );
+
+        let status = SlpStatus::from_json(&status.to_json()).unwrap();
+        assert!(matches!(status.mods(), Some(ModList::Mods(mods)) if mods.len() == 5));
+    }
+
+    #[test]
+    fn test_forge_data() {
+        let status = SlpStatus::from_json(NEOFORGE_1_20).unwrap();
+        assert_eq!(
+            status.mods(),
+            Some(mod_list(&[
+                ("minecraft", "1.20.1"),
+                ("forge", "47.1.3"),
+                ("jei", "15.2.0.27"),
+                ("spark", IGNORE_SERVER_ONLY),
+            ]))
+        );
+
+        // Layout of FML network version 2
+        let status = SlpStatus::from_json(
+            r#"{
+                "version": {"name": "1.16.5", "protocol": 754},
+                "players": {"max": 20, "online": 0},
+                "forgeData": {
+                    "channels": [{"res": "forge:handshake", "version": "FML2", "required": true}],
+                    "mods": [{"modId": "forge", "modmarker": "36.2.39"}],
+                    "fmlNetworkVersion": 2
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(status.mods(), Some(mod_list(&[("forge", "36.2.39")])));
+    }
+
+    #[test]
+    fn test_unknown_layout() {
+        let vanilla =
+            r#"{"version":{"name":"1.20.1","protocol":763},"players":{"max":20,"online":0}}"#;
+        assert_eq!(SlpStatus::from_json(vanilla).unwrap().mods(), None);
+
+        let status = SlpStatus::from_json(
+            r#"{
+                "version": {"name": "1.21", "protocol": 767},
+                "players": {"max": 20, "online": 0},
+                "forgeData": {"fmlNetworkVersion": 4, "payload": [1, 2, 3]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            status.mods(),
+            Some(ModList::Unknown(status.forge_data.clone().unwrap()))
+        );
+
+        // Truncated encoded buffer
+        let mut status = SlpStatus::from_json(NEOFORGE_1_20).unwrap();
+        let d = status.forge_data.as_ref().unwrap()["d"].as_str().unwrap();
+        let truncated: String = d.chars().take(20).collect();
+        status.forge_data.as_mut().unwrap()["d"] = Value::String(truncated);
+        assert!(matches!(status.mods(), Some(ModList::Unknown(_))));
+    }
+
+    #[test]
+    fn test_decode_optimized() {
+        assert_eq!(decode_optimized("\u{0}\u{0}"), Some(Vec::new()));
+        // 3 bytes, 0x01 0x02 0x03, packed in 2 code units
+        assert_eq!(
+            decode_optimized("\u{3}\u{0}\u{201}\u{6}"),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(decode_optimized("\u{3}\u{0}\u{201}"), None);
+        assert_eq!(decode_optimized("\u{3}"), None);
+        // Code units above 15 bits are invalid
+        assert_eq!(decode_optimized("\u{0}\u{0}\u{8000}"), None);
+    }
+}
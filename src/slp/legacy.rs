@@ -0,0 +1,267 @@
+//! Legacy Server List Ping, answered by servers before 1.7.
+//!
+//! The client sends `0xFE 0x01`, and the server answers with a kick packet,
+//! `0xFF`, followed by the length of a string in UTF-16 code units and the
+//! string, encoded in UTF-16BE. Two layouts of the string exist:
+//!
+//! - Beta 1.8 to 1.3: `motd§online§max`
+//! - 1.4 to 1.6: `§1\0protocol\0version\0motd\0online\0max`
+//!
+//! Modern servers still answer this ping, with the 1.6 layout.
+
+use std::io::{self, Read};
+
+use crate::{custom_io_error, not_enough_data};
+
+/// Legacy ping request, sent by 1.4 to 1.6 clients
+pub const LEGACY_PING: [u8; 2] = [0xFE, 0x01];
+
+/// ID of the kick packet of the response
+const KICK_ID: u8 = 0xFF;
+/// Start of the 1.6 layout of the response string
+const V1_6_MAGIC: &str = "§1\0";
+
+/// Status of a server, as sent in response to a legacy ping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyStatus {
+    /// Message of the day
+    pub motd: String,
+    /// Number of players currently online
+    pub numplayers: u32,
+    /// Max number of players on the server
+    pub maxplayers: u32,
+    /// Game version of the server, absent in the beta layout
+    pub version: Option<String>,
+    /// Protocol version number, absent in the beta layout
+    pub protocol: Option<i32>,
+}
+
+impl LegacyStatus {
+    /// Parse a kick packet sent in response to a legacy ping, detecting its
+    /// layout.
+    ///
+    /// Responses shorter than their length prefix are parsed as far as they
+    /// go, and a trailing odd byte is decoded as a replacement character, as
+    /// are unpaired surrogates.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::slp::legacy::LegacyStatus;
+    /// let text: Vec<u16> = "A Minecraft Server§3§20".encode_utf16().collect();
+    /// let mut response = vec![0xFF, 0, text.len() as u8];
+    /// response.extend(text.iter().flat_map(|unit| unit.to_be_bytes()));
+    /// let status = LegacyStatus::from_response(&response)?;
+    /// assert_eq!(status.motd, "A Minecraft Server");
+    /// assert_eq!((status.numplayers, status.maxplayers), (3, 20));
+    /// assert_eq!(status.version, None);
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_response(response: &[u8]) -> io::Result<Self> {
+        if response.len() < 3 {
+            return Err(not_enough_data());
+        }
+        if response[0] != KICK_ID {
+            return Err(custom_io_error(
+                "Unexpected packet ID, expected a legacy ping response.",
+            ));
+        }
+        let len = 2 * u16::from_be_bytes([response[1], response[2]]) as usize;
+        let data = &response[3..];
+        let text = decode_utf16be(&data[..len.min(data.len())]);
+
+        match text.strip_prefix(V1_6_MAGIC) {
+            Some(fields) => Self::from_v1_6(fields),
+            None => Self::from_beta(&text),
+        }
+    }
+
+    /// Parse the fields of the 1.6 layout, after the `§1` magic.
+    fn from_v1_6(fields: &str) -> io::Result<Self> {
+        let mut fields = fields.split('\0');
+        let mut next = || {
+            fields
+                .next()
+                .ok_or_else(|| custom_io_error("Missing field in legacy ping response."))
+        };
+        let protocol = parse_number(next()?)?;
+        let version = next()?.to_string();
+        let motd = next()?.to_string();
+        Ok(Self {
+            numplayers: parse_number(next()?)?,
+            maxplayers: parse_number(next()?)?,
+            motd,
+            version: Some(version),
+            protocol: Some(protocol),
+        })
+    }
+
+    /// Parse the beta layout, where the MOTD may itself contain section signs.
+    fn from_beta(text: &str) -> io::Result<Self> {
+        let mut fields = text.rsplitn(3, '§');
+        let (Some(max), Some(online), Some(motd)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(custom_io_error("Missing field in legacy ping response."));
+        };
+        Ok(Self {
+            motd: motd.to_string(),
+            numplayers: parse_number(online)?,
+            maxplayers: parse_number(max)?,
+            version: None,
+            protocol: None,
+        })
+    }
+
+    /// Encode the kick packet a server sends in response to a legacy ping,
+    /// with the 1.6 layout if the version is known, and the beta layout
+    /// otherwise.
+    pub fn to_response(&self) -> Vec<u8> {
+        let text = match &self.version {
+            Some(version) => format!(
+                "{V1_6_MAGIC}{}\0{version}\0{}\0{}\0{}",
+                self.protocol.unwrap_or_default(),
+                self.motd,
+                self.numplayers,
+                self.maxplayers
+            ),
+            None => format!("{}§{}§{}", self.motd, self.numplayers, self.maxplayers),
+        };
+        let units: Vec<u16> = text.encode_utf16().take(u16::MAX as usize).collect();
+        let mut response = Vec::with_capacity(3 + 2 * units.len());
+        response.push(KICK_ID);
+        response.extend((units.len() as u16).to_be_bytes());
+        response.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        response
+    }
+}
+
+/// Parse a number field of a legacy ping response.
+fn parse_number<T: std::str::FromStr>(field: &str) -> io::Result<T> {
+    field
+        .parse()
+        .map_err(|_| custom_io_error("Invalid number in legacy ping response."))
+}
+
+/// Decode UTF-16BE text, replacing unpaired surrogates and a trailing odd
+/// byte with replacement characters.
+fn decode_utf16be(data: &[u8]) -> String {
+    let chunks = data.chunks_exact(2);
+    let odd = !chunks.remainder().is_empty();
+    let units = chunks.map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    if odd {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
+
+/// Read the kick packet sent in response to a legacy ping, until its length
+/// or the end of the stream.
+pub fn read_response(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut response = vec![0; 3];
+    reader.read_exact(&mut response)?;
+    let len = 2 * u16::from_be_bytes([response[1], response[2]]) as u64;
+    reader.take(len).read_to_end(&mut response)?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(text: &str) -> Vec<u8> {
+        let units: Vec<u16> = text.encode_utf16().collect();
+        let mut response = vec![KICK_ID];
+        response.extend((units.len() as u16).to_be_bytes());
+        response.extend(units.iter().flat_map(|unit| unit.to_be_bytes()));
+        response
+    }
+
+    #[test]
+    fn test_v1_6_layout() {
+        let status = LegacyStatus::from_response(&response(
+            "§1\x0078\x001.6.4\x00§aA §lLegacy§r Server\x005\x0020",
+        ))
+        .unwrap();
+        assert_eq!(
+            status,
+            LegacyStatus {
+                motd: "§aA §lLegacy§r Server".to_string(),
+                numplayers: 5,
+                maxplayers: 20,
+                version: Some("1.6.4".to_string()),
+                protocol: Some(78),
+            }
+        );
+        assert_eq!(
+            LegacyStatus::from_response(&status.to_response()).unwrap(),
+            status
+        );
+    }
+
+    #[test]
+    fn test_beta_layout() {
+        // The MOTD can contain section signs
+        let status = LegacyStatus::from_response(&response("§6Beta§r server§0§12")).unwrap();
+        assert_eq!(status.motd, "§6Beta§r server");
+        assert_eq!((status.numplayers, status.maxplayers), (0, 12));
+        assert_eq!((&status.version, status.protocol), (&None, None));
+        assert_eq!(
+            LegacyStatus::from_response(&status.to_response()).unwrap(),
+            status
+        );
+    }
+
+    #[test]
+    fn test_utf16_decoding() {
+        let status = LegacyStatus::from_response(&response("Sérvèr 🎮§1§10")).unwrap();
+        assert_eq!(status.motd, "Sérvèr 🎮");
+
+        // Unpaired surrogate
+        let mut unpaired = response("X§1§10");
+        unpaired.splice(3..5, [0xD8, 0x00]);
+        assert_eq!(
+            LegacyStatus::from_response(&unpaired).unwrap().motd,
+            "\u{FFFD}"
+        );
+
+        assert_eq!(decode_utf16be(&[0, b'A', 0]), "A\u{FFFD}");
+        assert_eq!(decode_utf16be(&[]), "");
+    }
+
+    #[test]
+    fn test_malformed_responses() {
+        assert!(LegacyStatus::from_response(&[]).is_err());
+        assert!(LegacyStatus::from_response(&[KICK_ID, 0]).is_err());
+        // Not a kick packet
+        let mut wrong_id = response("A§1§10");
+        wrong_id[0] = 0x00;
+        assert!(LegacyStatus::from_response(&wrong_id).is_err());
+
+        assert!(LegacyStatus::from_response(&response("No fields")).is_err());
+        assert!(LegacyStatus::from_response(&response("A§one§10")).is_err());
+        assert!(LegacyStatus::from_response(&response("§1\x0078\x001.6.4\x00motd")).is_err());
+
+        // Truncated after the counts, with an odd byte
+        let mut truncated = response("A§1§10 and more");
+        truncated.truncate(3 + 2 * 6 + 1);
+        assert!(LegacyStatus::from_response(&truncated).is_err());
+        truncated.truncate(3 + 2 * 6);
+        assert_eq!(
+            LegacyStatus::from_response(&truncated).unwrap().maxplayers,
+            10
+        );
+    }
+
+    #[test]
+    fn test_read_response() {
+        let sent = response("A§1§10");
+        let mut stream = [sent.as_slice(), b"trailing"].concat();
+        assert_eq!(read_response(&mut stream.as_slice()).unwrap(), sent);
+
+        // Shorter than its length prefix
+        stream.truncate(sent.len() - 1);
+        assert_eq!(read_response(&mut stream.as_slice()).unwrap(), stream);
+        assert!(read_response(&mut &sent[..2]).is_err());
+    }
+}
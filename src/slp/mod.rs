@@ -0,0 +1,799 @@
+//! Implementation of the [Server List Ping](https://wiki.vg/Server_List_Ping) protocol
+//! used by the Minecraft client to display servers in the multiplayer menu.
+//!
+//! Unlike the Query protocol, it does not need to be enabled on the server:
+//! it uses the TCP game port, with the same framing as the game protocol.
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::slp;
+//! let status = slp::blocking::ping("127.0.0.1")?;
+//! println!("{}/{} players online", status.players.online, status.players.max);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! # Packet format
+//!
+//! | Field name | Field type | Notes                                   |
+//! |------------|------------|-----------------------------------------|
+//! | Length     | VarInt     | Length of the packet ID and of the data |
+//! | Packet ID  | VarInt     |                                         |
+//! | Data       | Varies     | Depends on the packet ID                |
+//!
+//! A VarInt is a little-endian base 128 integer of at most 5 bytes, the highest
+//! bit of each byte being set if another byte follows.
+
+pub mod blocking;
+mod favicon;
+mod forge;
+pub mod legacy;
+#[cfg(feature = "tokio")]
+#[cfg_attr(doc, doc(cfg(feature = "tokio")))]
+pub mod tokio;
+
+pub use favicon::{FaviconError, FAVICON_PREFIX};
+pub use forge::{Mod, ModList, IGNORE_SERVER_ONLY};
+
+use std::io::{self, Read};
+
+use bytes::{Buf, BufMut};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    custom_io_error,
+    motd::{self, Color, Span, Style},
+    not_enough_data,
+};
+
+/// Protocol version sent in the handshake when the client version is unknown
+pub const STATUS_PROTOCOL: i32 = -1;
+
+/// ID of the handshake packet
+const HANDSHAKE_ID: i32 = 0x00;
+/// ID of the status request and response packets
+const STATUS_ID: i32 = 0x00;
+/// ID of the ping and pong packets
+const PING_ID: i32 = 0x01;
+/// State requested in the handshake
+const NEXT_STATE_STATUS: i32 = 1;
+
+/// Maximum length of a packet, in bytes: the largest 3-byte VarInt
+const MAX_PACKET_LENGTH: usize = (1 << 21) - 1;
+/// Maximum size of a VarInt, in bytes
+const MAX_VARINT_SIZE: usize = 5;
+
+/// Status of a server, as sent in response to a Server List Ping
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlpStatus {
+    /// Game version of the server
+    pub version: Version,
+    /// Player counts and sample
+    pub players: Players,
+    /// Message of the day
+    #[serde(default)]
+    pub description: ChatComponent,
+    /// Server icon, as a `data:image/png;base64,` URI
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    /// Mod list of Forge servers before 1.13, see [`mods`](Self::mods)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modinfo: Option<serde_json::Value>,
+    /// Mod list and network channels of Forge servers since 1.13, see [`mods`](Self::mods)
+    #[serde(default, rename = "forgeData", skip_serializing_if = "Option::is_none")]
+    pub forge_data: Option<serde_json::Value>,
+}
+
+/// Game version of a server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Version {
+    /// Version name, which may contain other information such as the server software
+    pub name: String,
+    /// [Protocol version number](https://wiki.vg/Protocol_version_numbers)
+    pub protocol: i32,
+}
+
+/// Player counts of a server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Players {
+    /// Max number of players on the server
+    pub max: i64,
+    /// Number of players currently online
+    pub online: i64,
+    /// Some of the online players, chosen by the server
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sample: Vec<SamplePlayer>,
+}
+
+/// A player in the sample of online players
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SamplePlayer {
+    /// Name of the player. Some servers send lines of text instead of players.
+    pub name: String,
+    /// UUID of the player, or `None` if the server sent an invalid or an
+    /// anonymized all-zero UUID, see [`parse_uuid`]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_uuid",
+        serialize_with = "serialize_uuid"
+    )]
+    pub id: Option<Uuid>,
+}
+
+/// A player UUID
+#[cfg(feature = "uuid")]
+pub type Uuid = uuid::Uuid;
+/// A player UUID, as a lowercase hyphenated string
+#[cfg(not(feature = "uuid"))]
+pub type Uuid = String;
+
+/// UUID sent for anonymized players, and for lines of text in the player sample
+pub const NIL_UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Parse a player UUID, hyphenated or not. Returns `None` for invalid UUIDs,
+/// and for the [all-zero UUID](NIL_UUID).
+///
+/// ```rust
+/// # use minecraft_server_query::slp::parse_uuid;
+/// let uuid = parse_uuid("61699B2E-D327-4A01-9F1E-0EA8C3F06BC6").unwrap();
+/// assert_eq!(uuid.to_string(), "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6");
+/// assert_eq!(parse_uuid("61699b2ed3274a019f1e0ea8c3f06bc6"), Some(uuid));
+///
+/// assert_eq!(parse_uuid("00000000-0000-0000-0000-000000000000"), None);
+/// assert_eq!(parse_uuid("Join our Discord!"), None);
+/// ```
+#[cfg(feature = "uuid")]
+pub fn parse_uuid(s: &str) -> Option<Uuid> {
+    if s.len() != 32 && s.len() != 36 {
+        return None;
+    }
+    Uuid::try_parse(s).ok().filter(|uuid| !uuid.is_nil())
+}
+
+/// Parse a player UUID, hyphenated or not. Returns `None` for invalid UUIDs,
+/// and for the [all-zero UUID](NIL_UUID).
+///
+/// ```rust
+/// # use minecraft_server_query::slp::parse_uuid;
+/// let uuid = parse_uuid("61699B2E-D327-4A01-9F1E-0EA8C3F06BC6").unwrap();
+/// assert_eq!(uuid.to_string(), "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6");
+/// assert_eq!(parse_uuid("61699b2ed3274a019f1e0ea8c3f06bc6"), Some(uuid));
+///
+/// assert_eq!(parse_uuid("00000000-0000-0000-0000-000000000000"), None);
+/// assert_eq!(parse_uuid("Join our Discord!"), None);
+/// ```
+#[cfg(not(feature = "uuid"))]
+pub fn parse_uuid(s: &str) -> Option<Uuid> {
+    /// Positions of the hyphens in a hyphenated UUID
+    const HYPHENS: [usize; 4] = [8, 13, 18, 23];
+
+    let hex: String = match s.len() {
+        32 => s.to_string(),
+        36 if HYPHENS.iter().all(|&i| s.as_bytes()[i] == b'-') => {
+            s.chars().filter(|&c| c != '-').collect()
+        }
+        _ => return None,
+    };
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) || hex.bytes().all(|b| b == b'0') {
+        return None;
+    }
+
+    let mut res = hex.to_ascii_lowercase();
+    for i in HYPHENS {
+        res.insert(i, '-');
+    }
+    Some(res)
+}
+
+/// Deserialize a player UUID, see [`parse_uuid`]. Values other than strings are ignored.
+fn deserialize_uuid<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Uuid>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(value.as_str().and_then(parse_uuid))
+}
+
+/// Serialize a player UUID, as the [all-zero UUID](NIL_UUID) if missing.
+fn serialize_uuid<S: serde::Serializer>(
+    id: &Option<Uuid>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match id {
+        Some(id) => serializer.collect_str(id),
+        None => serializer.serialize_str(NIL_UUID),
+    }
+}
+
+/// A text component of the chat format, used for the message of the day.
+///
+/// Plain strings are parsed as a component with text and no style.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "RawComponent")]
+pub struct ChatComponent {
+    /// Text of the component, which may contain legacy formatting codes
+    pub text: String,
+    /// Named color, like `gold`, or hex color, like `#aabbcc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    /// Bold style
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    /// Italic style
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    /// Underlined style
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    /// Strikethrough style
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    /// Obfuscated style, with randomly changing characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    /// Child components, inheriting the style of this component
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<ChatComponent>,
+}
+
+impl ChatComponent {
+    /// Text of the component and of its children, in order, without styles.
+    ///
+    /// Legacy formatting codes in the text are kept.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::slp::ChatComponent;
+    /// let motd: ChatComponent =
+    ///     serde_json::from_str(r#"{"text":"A ","extra":["Minecraft",{"text":" Server"}]}"#)?;
+    /// assert_eq!(motd.to_plain(), "A Minecraft Server");
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    pub fn to_plain(&self) -> String {
+        let mut res = String::new();
+        self.push_plain(&mut res);
+        res
+    }
+
+    fn push_plain(&self, buf: &mut String) {
+        buf.push_str(&self.text);
+        for child in &self.extra {
+            child.push_plain(buf);
+        }
+    }
+
+    /// Split the component tree into styled spans, as done for legacy MOTDs by
+    /// [`motd::parse_codes`].
+    ///
+    /// Children inherit the style of their parent, unless they override it.
+    /// Unknown colors are ignored, and legacy formatting codes in the text are
+    /// applied on top of the style of their component.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{motd::Color, slp::ChatComponent};
+    /// let motd: ChatComponent = serde_json::from_str(
+    ///     r##"{"text":"A ","color":"gold","extra":[{"text":"Server","color":"#aabbcc"}]}"##,
+    /// )?;
+    /// let spans = motd.to_spans();
+    /// assert_eq!(spans[0].style.color, Some(Color::Gold));
+    /// assert_eq!(spans[1].style.color, Some(Color::Rgb(0xaa, 0xbb, 0xcc)));
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    pub fn to_spans(&self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        self.push_spans(&mut spans, Style::default());
+        spans
+    }
+
+    fn push_spans(&self, spans: &mut Vec<Span>, parent: Style) {
+        let style = Style {
+            color: self
+                .color
+                .as_deref()
+                .and_then(Color::from_name)
+                .or(parent.color),
+            bold: self.bold.unwrap_or(parent.bold),
+            italic: self.italic.unwrap_or(parent.italic),
+            underlined: self.underlined.unwrap_or(parent.underlined),
+            strikethrough: self.strikethrough.unwrap_or(parent.strikethrough),
+            obfuscated: self.obfuscated.unwrap_or(parent.obfuscated),
+        };
+        motd::push_codes(spans, &self.text, style);
+        for child in &self.extra {
+            child.push_spans(spans, style);
+        }
+    }
+}
+
+/// Wire representation of a chat component: either a plain string or an object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawComponent {
+    Text(String),
+    Object {
+        #[serde(default)]
+        text: String,
+        #[serde(default)]
+        color: Option<String>,
+        #[serde(default)]
+        bold: Option<bool>,
+        #[serde(default)]
+        italic: Option<bool>,
+        #[serde(default)]
+        underlined: Option<bool>,
+        #[serde(default)]
+        strikethrough: Option<bool>,
+        #[serde(default)]
+        obfuscated: Option<bool>,
+        #[serde(default)]
+        extra: Vec<ChatComponent>,
+    },
+}
+
+impl From<RawComponent> for ChatComponent {
+    fn from(raw: RawComponent) -> Self {
+        match raw {
+            RawComponent::Text(text) => Self {
+                text,
+                ..Self::default()
+            },
+            RawComponent::Object {
+                text,
+                color,
+                bold,
+                italic,
+                underlined,
+                strikethrough,
+                obfuscated,
+                extra,
+            } => Self {
+                text,
+                color,
+                bold,
+                italic,
+                underlined,
+                strikethrough,
+                obfuscated,
+                extra,
+            },
+        }
+    }
+}
+
+impl SlpStatus {
+    /// Parse a status from the JSON sent by the server.
+    ///
+    /// Unknown fields are ignored. Invalid or truncated JSON returns an error
+    /// of kind [`InvalidData`](io::ErrorKind::InvalidData).
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::slp::SlpStatus;
+    /// let status = SlpStatus::from_json(
+    ///     r#"{"version":{"name":"1.20.1","protocol":763},"players":{"max":20,"online":0},"description":"A Minecraft Server"}"#,
+    /// )?;
+    /// assert_eq!(status.version.protocol, 763);
+    /// assert_eq!(status.description.text, "A Minecraft Server");
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        serde_json::from_str(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Encode this status to JSON, as sent by a server.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("A status can always be serialized to JSON")
+    }
+}
+
+/// Append a VarInt to a byte buffer.
+///
+/// ```rust
+/// # use minecraft_server_query::slp::put_varint;
+/// let mut buf = Vec::new();
+/// put_varint(&mut buf, 300);
+/// put_varint(&mut buf, -1);
+/// assert_eq!(buf, [0xac, 0x02, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+/// ```
+pub fn put_varint(buf: &mut impl BufMut, value: i32) {
+    let mut value = value as u32;
+    while value >= 0x80 {
+        buf.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+/// Read a VarInt from a byte buffer, advancing it.
+pub fn get_varint(buf: &mut impl Buf) -> io::Result<i32> {
+    let mut value = 0;
+    for i in 0..MAX_VARINT_SIZE {
+        if !buf.has_remaining() {
+            return Err(not_enough_data());
+        }
+        let byte = buf.get_u8();
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(custom_io_error("VarInt is too long."))
+}
+
+/// Read a VarInt from a reader.
+pub fn read_varint(reader: &mut impl Read) -> io::Result<i32> {
+    let mut value = 0;
+    for i in 0..MAX_VARINT_SIZE {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7F) as u32) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(custom_io_error("VarInt is too long."))
+}
+
+/// Parse the length prefix of a packet.
+///
+/// Fails if the length is negative, or larger than the maximum packet length.
+pub fn parse_length(len: i32) -> io::Result<usize> {
+    if len < 0 || len as usize > MAX_PACKET_LENGTH {
+        return Err(custom_io_error("Invalid packet length."));
+    }
+    Ok(len as usize)
+}
+
+/// Read a single packet from a reader, returning its body without the length prefix.
+pub fn read_packet(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = parse_length(read_varint(reader)?)?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Frame a packet body with its ID and length prefix.
+fn frame(id: i32, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(MAX_VARINT_SIZE + data.len());
+    put_varint(&mut body, id);
+    body.put_slice(data);
+
+    let mut res = Vec::with_capacity(MAX_VARINT_SIZE + body.len());
+    put_varint(&mut res, body.len() as i32);
+    res.put_slice(&body);
+    res
+}
+
+/// Append a string, prefixed by its length as a VarInt.
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+    put_varint(buf, s.len() as i32);
+    buf.put_slice(s.as_bytes());
+}
+
+/// Build the handshake packet, switching the connection to the status state.
+pub fn handshake(host: &str, port: u16) -> Vec<u8> {
+    let mut data = Vec::with_capacity(host.len() + 2 * MAX_VARINT_SIZE + 3);
+    put_varint(&mut data, STATUS_PROTOCOL);
+    put_string(&mut data, host);
+    data.put_u16(port);
+    put_varint(&mut data, NEXT_STATE_STATUS);
+    frame(HANDSHAKE_ID, &data)
+}
+
+/// Build the status request packet.
+///
+/// ```rust
+/// # use minecraft_server_query::slp;
+/// assert_eq!(slp::status_request(), [1, 0]);
+/// ```
+pub fn status_request() -> Vec<u8> {
+    frame(STATUS_ID, &[])
+}
+
+/// Build a ping packet, with a payload echoed back by the server in a pong
+/// packet with the same format.
+pub fn ping_request(payload: i64) -> Vec<u8> {
+    frame(PING_ID, &payload.to_be_bytes())
+}
+
+/// Build a status response packet, as sent by a server.
+pub fn status_response(status: &SlpStatus) -> Vec<u8> {
+    let mut data = Vec::new();
+    put_string(&mut data, &status.to_json());
+    frame(STATUS_ID, &data)
+}
+
+/// Parse the body of a status response packet.
+pub fn parse_status_response(mut body: &[u8]) -> io::Result<SlpStatus> {
+    if get_varint(&mut body)? != STATUS_ID {
+        return Err(custom_io_error(
+            "Unexpected packet ID, expected a status response.",
+        ));
+    }
+    let len = parse_length(get_varint(&mut body)?)?;
+    let json = body.get(..len).ok_or_else(not_enough_data)?;
+    let json =
+        std::str::from_utf8(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    SlpStatus::from_json(json)
+}
+
+/// Parse the body of a pong packet, returning its payload.
+pub fn parse_pong(mut body: &[u8]) -> io::Result<i64> {
+    if get_varint(&mut body)? != PING_ID {
+        return Err(custom_io_error("Unexpected packet ID, expected a pong."));
+    }
+    if body.remaining() < 8 {
+        return Err(not_enough_data());
+    }
+    Ok(body.get_i64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 255, 25565, 2097151, i32::MAX, -1, i32::MIN] {
+            let mut buf = Vec::new();
+            put_varint(&mut buf, value);
+            assert_eq!(get_varint(&mut &buf[..]).unwrap(), value);
+            assert_eq!(read_varint(&mut &buf[..]).unwrap(), value);
+        }
+
+        assert!(get_varint(&mut &[0x80, 0x80][..]).is_err());
+        assert!(get_varint(&mut &[0xff; 6][..]).is_err());
+        assert!(read_varint(&mut &[0xff; 6][..]).is_err());
+    }
+
+    #[test]
+    fn test_handshake() {
+        assert_eq!(
+            handshake("localhost", 25565),
+            b"\x13\x00\xff\xff\xff\xff\x0f\x09localhost\x63\xdd\x01"
+        );
+    }
+
+    #[test]
+    fn test_packet_length() {
+        let mut oversized = Vec::new();
+        put_varint(&mut oversized, MAX_PACKET_LENGTH as i32 + 1);
+        assert!(read_packet(&mut &oversized[..]).is_err());
+
+        let mut negative = Vec::new();
+        put_varint(&mut negative, -1);
+        assert!(read_packet(&mut &negative[..]).is_err());
+
+        let truncated = [10, 0, 1, 2];
+        assert!(read_packet(&mut &truncated[..]).is_err());
+    }
+
+    #[test]
+    fn test_status_response() {
+        let json = r#"{
+            "version": {"name": "Paper 1.20.1", "protocol": 763},
+            "players": {
+                "max": 100,
+                "online": 1,
+                "sample": [{"name": "Dinnerbone", "id": "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6"}]
+            },
+            "description": {"text": "", "extra": [{"text": "A ", "bold": true}, "Server"]},
+            "favicon": "data:image/png;base64,",
+            "enforcesSecureChat": true
+        }"#;
+        let mut data = Vec::new();
+        put_string(&mut data, json);
+        let packet = frame(STATUS_ID, &data);
+
+        let body = read_packet(&mut &packet[..]).unwrap();
+        let status = parse_status_response(&body).unwrap();
+        assert_eq!(status.version.name, "Paper 1.20.1");
+        assert_eq!(status.players.online, 1);
+        assert_eq!(status.players.sample[0].name, "Dinnerbone");
+        assert_eq!(
+            status.players.sample[0].id,
+            parse_uuid("61699b2e-d327-4a01-9f1e-0ea8c3f06bc6")
+        );
+        assert_eq!(status.description.to_plain(), "A Server");
+        assert_eq!(status.description.extra[0].bold, Some(true));
+        assert!(status.favicon.is_some());
+
+        let encoded = status_response(&status);
+        let body = read_packet(&mut &encoded[..]).unwrap();
+        assert_eq!(parse_status_response(&body).unwrap(), status);
+    }
+
+    #[test]
+    fn test_nested_component_spans() {
+        let motd: ChatComponent = serde_json::from_str(
+            r#"{
+                "text": "",
+                "bold": true,
+                "extra": [
+                    {"text": "Hypixel ", "color": "green"},
+                    {"text": "Network", "color": "red", "extra": [
+                        {"text": " [1.8-1.20]", "bold": false, "color": "dark_gray"},
+                        {"text": "!", "color": "not_a_color"}
+                    ]},
+                    "\n§b§lNEW §rGame"
+                ]
+            }"#,
+        )
+        .unwrap();
+        let spans = motd.to_spans();
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+
+        let styles: Vec<_> = spans.iter().map(|s| (s.text.as_str(), s.style)).collect();
+        assert_eq!(
+            styles,
+            [
+                (
+                    "Hypixel ",
+                    Style {
+                        color: Some(Color::Green),
+                        ..bold
+                    }
+                ),
+                (
+                    "Network",
+                    Style {
+                        color: Some(Color::Red),
+                        ..bold
+                    }
+                ),
+                (
+                    " [1.8-1.20]",
+                    Style {
+                        color: Some(Color::DarkGray),
+                        ..Style::default()
+                    }
+                ),
+                // An unknown color inherits the parent color
+                (
+                    "!",
+                    Style {
+                        color: Some(Color::Red),
+                        ..bold
+                    }
+                ),
+                ("\n", bold),
+                (
+                    "NEW ",
+                    Style {
+                        color: Some(Color::Aqua),
+                        ..bold
+                    }
+                ),
+                // A legacy reset goes back to the style of the component
+                ("Game", bold),
+            ]
+        );
+        assert_eq!(
+            motd::spans_to_plain(&spans),
+            motd::strip_codes(&motd.to_plain())
+        );
+    }
+
+    #[test]
+    fn test_hex_component_spans() {
+        let motd: ChatComponent = serde_json::from_str(
+            r##"{"text": "Rain", "color": "#FF5555", "extra": [
+                {"text": "bow", "color": "#55ff55", "italic": true},
+                {"text": "!", "color": "#55f"}
+            ]}"##,
+        )
+        .unwrap();
+        let spans = motd.to_spans();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(spans[0].style.color, Some(Color::Rgb(0xff, 0x55, 0x55)));
+        assert_eq!(
+            spans[1].style,
+            Style {
+                color: Some(Color::Rgb(0x55, 0xff, 0x55)),
+                italic: true,
+                ..Style::default()
+            }
+        );
+        // Short hex colors are not supported by the client
+        assert_eq!(spans[2].style.color, Some(Color::Rgb(0xff, 0x55, 0x55)));
+    }
+
+    #[test]
+    fn test_string_component_spans() {
+        let status = SlpStatus::from_json(
+            r#"{"version":{"name":"1.8.8","protocol":47},"players":{"max":20,"online":0},"description":"§6A §lMinecraft§r Server"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            status.description.to_spans(),
+            motd::parse_codes("§6A §lMinecraft§r Server")
+        );
+
+        let empty = ChatComponent::default();
+        assert!(empty.to_spans().is_empty());
+    }
+
+    #[test]
+    fn test_clean_player_sample() {
+        let players: Players = serde_json::from_str(
+            r#"{
+                "max": 100,
+                "online": 2,
+                "sample": [
+                    {"name": "Dinnerbone", "id": "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6"},
+                    {"name": "Notch", "id": "069A79F4-44E9-4726-A5BE-FCA90E38AAF5"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let ids: Vec<_> = players
+            .sample
+            .iter()
+            .map(|player| player.id.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "61699b2e-d327-4a01-9f1e-0ea8c3f06bc6",
+                "069a79f4-44e9-4726-a5be-fca90e38aaf5"
+            ]
+        );
+
+        let json = serde_json::to_string(&players).unwrap();
+        assert!(json.contains(r#""id":"069a79f4-44e9-4726-a5be-fca90e38aaf5""#));
+        assert_eq!(serde_json::from_str::<Players>(&json).unwrap(), players);
+    }
+
+    #[test]
+    fn test_junk_player_sample() {
+        let players: Players = serde_json::from_str(
+            r#"{
+                "max": 1000,
+                "online": 523,
+                "sample": [
+                    {"name": "§6§lPLAY.EXAMPLE.NET", "id": "00000000-0000-0000-0000-000000000000"},
+                    {"name": "§7Join our Discord!", "id": "discord.gg/example"},
+                    {"name": "Anonymous Player", "id": "00000000000000000000000000000000"},
+                    {"name": "§aNo id"},
+                    {"name": "Numeric id", "id": 42},
+                    {"name": "Misplaced hyphens", "id": "61699b2ed-327-4a01-9f1e-0ea8c3f06bc6"},
+                    {"name": "Dinnerbone", "id": "61699b2ed3274a019f1e0ea8c3f06bc6"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(players.sample.len(), 7);
+        assert!(players.sample[..6].iter().all(|player| player.id.is_none()));
+        assert_eq!(
+            players.sample[6].id,
+            parse_uuid("61699b2e-d327-4a01-9f1e-0ea8c3f06bc6")
+        );
+
+        // Missing UUIDs are sent as the all-zero UUID
+        let json = serde_json::to_string(&players.sample[3]).unwrap();
+        assert_eq!(json, format!(r#"{{"name":"§aNo id","id":"{NIL_UUID}"}}"#));
+    }
+
+    #[test]
+    fn test_truncated_json() {
+        let mut data = Vec::new();
+        put_string(
+            &mut data,
+            r#"{"version":{"name":"1.20.1","protocol":763},"players":{"#,
+        );
+        let body = &frame(STATUS_ID, &data)[1..];
+
+        let err = parse_status_response(body).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // The announced JSON length is longer than the packet
+        assert!(parse_status_response(&body[..body.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_pong() {
+        let packet = ping_request(0x0102030405060708);
+        let body = read_packet(&mut &packet[..]).unwrap();
+        assert_eq!(parse_pong(&body).unwrap(), 0x0102030405060708);
+        assert!(parse_pong(&body[..5]).is_err());
+        assert!(parse_pong(&status_request()[1..]).is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! [`tokio`](https://docs.rs/tokio/*/tokio) implementation of the Server List Ping protocol.
+//!
+//! Uses [`tokio::net::TcpStream`](https://docs.rs/tokio/*/tokio/net/struct.TcpStream.html) for sending and receiving TCP data
+
+use ::tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use std::{
+    future::Future,
+    io,
+    time::{Duration, Instant},
+};
+
+use super::legacy::{self, LegacyStatus};
+use super::*;
+use crate::{check_no_port, split_address, zone, DEFAULT_TIMEOUT};
+
+/// An asynchronous Server List Ping client using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+///
+/// A new TCP connection is opened for every ping, like the Minecraft client does.
+#[derive(Debug, Clone)]
+pub struct PingClient {
+    host: String,
+    port: u16,
+    timeout: Option<Duration>,
+}
+
+/// Run a future with an optional timeout.
+async fn with_timeout<T>(
+    duration: Option<Duration>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    if let Some(duration) = duration {
+        timeout(duration, fut)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "SLP async call timed out."))?
+    } else {
+        fut.await
+    }
+}
+
+impl PingClient {
+    /// Build a new PingClient from the given IP address.
+    ///
+    /// If not port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new(ip: &str) -> io::Result<Self> {
+        let (ip, port) = split_address(ip)?;
+        Self::new_with_port(ip, port)
+    }
+
+    /// Build a new PingClient from the given IP address and port.
+    ///
+    /// If the IP address already contains a port, an error is returned.
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
+        Self::new_with_timeout(ip, port, Some(DEFAULT_TIMEOUT))
+    }
+
+    /// Build a new PingClient from the given IP address, port and optional
+    /// timeout, applied to the connection and to every read and write.
+    ///
+    /// The IP adress must not contain a port.
+    pub fn new_with_timeout(ip: &str, port: u16, timeout: Option<Duration>) -> io::Result<Self> {
+        check_no_port(ip)?;
+
+        Ok(Self {
+            host: ip.to_string(),
+            port,
+            timeout,
+        })
+    }
+
+    /// Request the status of the server.
+    pub async fn status(&self) -> io::Result<SlpStatus> {
+        let mut stream = self.request_status().await?;
+        let body = with_timeout(self.timeout, read_packet_async(&mut stream)).await?;
+        parse_status_response(&body)
+    }
+
+    /// Request the status of the server, then measure the latency with a
+    /// ping on the same connection.
+    pub async fn status_with_latency(&self) -> io::Result<(SlpStatus, Duration)> {
+        let mut stream = self.request_status().await?;
+        let body = with_timeout(self.timeout, read_packet_async(&mut stream)).await?;
+        let status = parse_status_response(&body)?;
+
+        let payload = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("System time cannot be before UNIX_EPOCH")
+            .as_millis() as i64;
+        let start = Instant::now();
+        with_timeout(self.timeout, stream.write_all(&ping_request(payload))).await?;
+        let body = with_timeout(self.timeout, read_packet_async(&mut stream)).await?;
+        if parse_pong(&body)? != payload {
+            return Err(custom_io_error("Pong payload does not match the ping."));
+        }
+
+        Ok((status, start.elapsed()))
+    }
+
+    /// Request the status of the server with a [legacy ping](legacy), for
+    /// servers before 1.7.
+    pub async fn legacy_status(&self) -> io::Result<LegacyStatus> {
+        let mut stream = self.connect().await?;
+
+        with_timeout(self.timeout, stream.write_all(&legacy::LEGACY_PING)).await?;
+        let response = with_timeout(self.timeout, read_legacy_response_async(&mut stream)).await?;
+        LegacyStatus::from_response(&response)
+    }
+
+    /// Connect to the server and send the handshake and status request.
+    async fn request_status(&self) -> io::Result<TcpStream> {
+        let mut stream = self.connect().await?;
+
+        let mut request = handshake(&self.host, self.port);
+        request.extend_from_slice(&status_request());
+        with_timeout(self.timeout, stream.write_all(&request)).await?;
+        Ok(stream)
+    }
+
+    async fn connect(&self) -> io::Result<TcpStream> {
+        let connect = async {
+            match zone::scoped_socket_addr(&self.host, self.port)? {
+                Some(addr) => TcpStream::connect(addr).await,
+                None => TcpStream::connect((self.host.as_str(), self.port)).await,
+            }
+        };
+        with_timeout(self.timeout, connect).await
+    }
+}
+
+/// Convenience function to request the status of a server with a Server List Ping.
+///
+/// If no port is specified in the IP address, the [default port](crate::DEFAULT_PORT) is used.
+pub async fn ping(ip: &str) -> io::Result<SlpStatus> {
+    PingClient::new(ip)?.status().await
+}
+
+/// Read a VarInt from an asynchronous reader.
+async fn read_varint_async(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<i32> {
+    let mut value = 0;
+    for i in 0..MAX_VARINT_SIZE {
+        let byte = reader.read_u8().await?;
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(custom_io_error("VarInt is too long."))
+}
+
+/// Read a single packet from an asynchronous reader, returning its body
+/// without the length prefix.
+async fn read_packet_async(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let len = parse_length(read_varint_async(reader).await?)?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body).await?;
+    Ok(body)
+}
+
+/// Read the kick packet sent in response to a legacy ping from an
+/// asynchronous reader, see [`legacy::read_response`].
+async fn read_legacy_response_async(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let mut response = vec![0; 3];
+    reader.read_exact(&mut response).await?;
+    let len = 2 * u16::from_be_bytes([response[1], response[2]]) as u64;
+    reader.take(len).read_to_end(&mut response).await?;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PingClient;
+    use crate::testing::MockSlpServer;
+
+    #[tokio::test]
+    async fn test_status() {
+        let server = MockSlpServer::new().unwrap();
+        let client = PingClient::new(&server.addr().to_string()).unwrap();
+
+        assert_eq!(client.status().await.unwrap(), server.status());
+    }
+
+    #[tokio::test]
+    async fn test_status_with_latency() {
+        let server = MockSlpServer::new().unwrap();
+        let client = PingClient::new(&server.addr().to_string()).unwrap();
+
+        let (status, latency) = client.status_with_latency().await.unwrap();
+        assert_eq!(status, server.status());
+        assert!(latency < std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_ping() {
+        let server = MockSlpServer::new().unwrap();
+        let status = super::ping(&server.addr().to_string()).await.unwrap();
+        assert_eq!(status.players.online, server.status().players.online);
+    }
+
+    #[tokio::test]
+    async fn test_legacy_status() {
+        use tokio::io::AsyncWriteExt;
+
+        let status = crate::slp::legacy::LegacyStatus {
+            motd: "§6Beta§r server".to_string(),
+            numplayers: 0,
+            maxplayers: 12,
+            version: None,
+            protocol: None,
+        };
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let response = status.to_response();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let client = PingClient::new(&addr.to_string()).unwrap();
+        assert_eq!(client.legacy_status().await.unwrap(), status);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_zoned_address() {
+        use tokio::io::AsyncWriteExt;
+
+        let status = crate::slp::legacy::LegacyStatus {
+            motd: "A Link-Local Server".to_string(),
+            numplayers: 0,
+            maxplayers: 20,
+            version: None,
+            protocol: None,
+        };
+        let listener = tokio::net::TcpListener::bind("[::1]:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let response = status.to_response();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let client = PingClient::new_with_port("::1%lo", port).unwrap();
+        assert_eq!(client.legacy_status().await.unwrap(), status);
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Accept the connection but never answer
+        let handle = tokio::spawn(async move {
+            let connection = listener.accept().await;
+            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            drop(connection);
+        });
+
+        let client = PingClient::new_with_timeout(
+            &addr.ip().to_string(),
+            addr.port(),
+            Some(std::time::Duration::from_millis(100)),
+        )
+        .unwrap();
+        let err = client.status().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        handle.abort();
+    }
+}
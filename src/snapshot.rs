@@ -0,0 +1,509 @@
+//! Compact binary snapshots of statuses, for caches and history files.
+//!
+//! [`Snapshot::to_bytes`] encodes a status in a few bytes more than its
+//! strings, much smaller and faster to read back than JSON:
+//!
+//! ```rust
+//! # use minecraft_server_query::{snapshot::Snapshot, BasicStat};
+//! let stat = BasicStat {
+//!     motd: "A Minecraft Server".into(),
+//!     gametype: "SMP".into(),
+//!     map: "world".into(),
+//!     numplayers: 2,
+//!     maxplayers: 20,
+//!     hostport: 25565,
+//!     hostip: "127.0.0.1".into(),
+//! };
+//! let bytes = stat.to_bytes();
+//! assert_eq!(bytes.len(), 47);
+//! assert_eq!(BasicStat::from_bytes(&bytes)?, stat);
+//! # Ok::<(), minecraft_server_query::snapshot::SnapshotError>(())
+//! ```
+//!
+//! # Format
+//!
+//! A snapshot starts with the [format version](FORMAT_VERSION), the kind of
+//! status, and the number of fields which follow:
+//!
+//! | Field       | Type        | Notes                                       |
+//! |-------------|-------------|---------------------------------------------|
+//! | Version     | [`u8`]      | [`FORMAT_VERSION`]                          |
+//! | Kind        | [`u8`]      | `1` for a basic stat, `2` for a full stat, `3` for a `QueryResponse` |
+//! | Field count | [`u8`]      |                                             |
+//! | Fields      |             | In the order of the struct declaration      |
+//!
+//! Numbers are LEB128 varints, strings are their varint length followed by
+//! their UTF-8 bytes, lists are their varint length followed by their items,
+//! and optional values are a `0` or `1` byte followed by the value if present.
+//!
+//! The format is stable within minor versions of the crate. New fields are
+//! only added at the end of a struct: snapshots written before a field was
+//! added are read with its default value, and the fields of snapshots written
+//! by a newer version are ignored. Incompatible changes bump the format
+//! version, and snapshots of another version fail with
+//! [`SnapshotError::UnsupportedVersion`].
+
+use std::{error::Error, fmt, io};
+
+use crate::{BasicStat, FullStat, StatString};
+
+/// Version of the snapshot format written by this version of the crate
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Kind byte of a basic stat snapshot
+const BASIC_STAT: u8 = 1;
+/// Kind byte of a full stat snapshot
+const FULL_STAT: u8 = 2;
+/// Kind byte of a `QueryResponse` snapshot
+#[cfg(feature = "compat-mcstatus")]
+const QUERY_RESPONSE: u8 = 3;
+
+/// Error while reading a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The snapshot was written with another format version
+    UnsupportedVersion(u8),
+    /// The snapshot is of another kind of status
+    WrongKind {
+        /// Kind byte of the status read
+        expected: u8,
+        /// Kind byte of the snapshot
+        found: u8,
+    },
+    /// The snapshot ends in the middle of a field
+    Truncated,
+    /// A string is not valid UTF-8
+    InvalidUtf8,
+    /// A number is out of the range of its field
+    InvalidNumber,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported snapshot format version {version}, expected {FORMAT_VERSION}."
+            ),
+            Self::WrongKind { expected, found } => write!(
+                f,
+                "Snapshot of kind {found} cannot be read as kind {expected}."
+            ),
+            Self::Truncated => write!(f, "Truncated snapshot."),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 string in snapshot."),
+            Self::InvalidNumber => write!(f, "Out of range number in snapshot."),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<SnapshotError> for io::Error {
+    fn from(e: SnapshotError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A status with a binary snapshot representation
+pub trait Snapshot: Sized {
+    /// Encode the status.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Decode a status. Bytes after the fields of the snapshot are ignored.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError>;
+}
+
+/// Snapshot encoder
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn new(kind: u8, fields: u8) -> Self {
+        Self(vec![FORMAT_VERSION, kind, fields])
+    }
+
+    fn number(&mut self, mut n: u64) -> &mut Self {
+        while n >= 0x80 {
+            self.0.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.0.push(n as u8);
+        self
+    }
+
+    fn string(&mut self, s: &str) -> &mut Self {
+        self.number(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+        self
+    }
+
+    fn strings<S: AsRef<str>>(&mut self, list: &[S]) -> &mut Self {
+        self.number(list.len() as u64);
+        for s in list {
+            self.string(s.as_ref());
+        }
+        self
+    }
+
+    #[cfg(feature = "compat-mcstatus")]
+    fn optional_string(&mut self, s: Option<&str>) -> &mut Self {
+        match s {
+            Some(s) => {
+                self.0.push(1);
+                self.string(s)
+            }
+            None => {
+                self.0.push(0);
+                self
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+/// Snapshot decoder, reading the fields present in the snapshot and
+/// defaulting the others
+struct Reader<'a> {
+    bytes: &'a [u8],
+    fields: u8,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], kind: u8) -> Result<Self, SnapshotError> {
+        match *bytes {
+            [FORMAT_VERSION, found, fields, ref bytes @ ..] if found == kind => {
+                Ok(Self { bytes, fields })
+            }
+            [FORMAT_VERSION, found, _, ..] => Err(SnapshotError::WrongKind {
+                expected: kind,
+                found,
+            }),
+            [version, ..] if version != FORMAT_VERSION => {
+                Err(SnapshotError::UnsupportedVersion(version))
+            }
+            _ => Err(SnapshotError::Truncated),
+        }
+    }
+
+    /// Read the next field, or return its default value if the snapshot has
+    /// no more fields.
+    fn field<T: Default>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, SnapshotError>,
+    ) -> Result<T, SnapshotError> {
+        if self.fields == 0 {
+            return Ok(T::default());
+        }
+        self.fields -= 1;
+        read(self)
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        let (&byte, rest) = self.bytes.split_first().ok_or(SnapshotError::Truncated)?;
+        self.bytes = rest;
+        Ok(byte)
+    }
+
+    fn number<T: TryFrom<u64>>(&mut self) -> Result<T, SnapshotError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= u64::from(byte & 0x7F)
+                .checked_shl(shift)
+                .filter(|&bits| bits >> shift == u64::from(byte & 0x7F))
+                .ok_or(SnapshotError::InvalidNumber)?;
+            if byte & 0x80 == 0 {
+                return T::try_from(n).map_err(|_| SnapshotError::InvalidNumber);
+            }
+        }
+        Err(SnapshotError::InvalidNumber)
+    }
+
+    fn str(&mut self) -> Result<&'a str, SnapshotError> {
+        let len: usize = self.number()?;
+        if len > self.bytes.len() {
+            return Err(SnapshotError::Truncated);
+        }
+        let (s, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        std::str::from_utf8(s).map_err(|_| SnapshotError::InvalidUtf8)
+    }
+
+    fn string<S: for<'s> From<&'s str>>(&mut self) -> Result<S, SnapshotError> {
+        self.str().map(S::from)
+    }
+
+    fn strings<S: for<'s> From<&'s str>>(&mut self) -> Result<Vec<S>, SnapshotError> {
+        let len: usize = self.number()?;
+        // Every string takes at least a byte
+        let mut list = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            list.push(self.string()?);
+        }
+        Ok(list)
+    }
+
+    #[cfg(feature = "compat-mcstatus")]
+    fn optional_string(&mut self) -> Result<Option<String>, SnapshotError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.string().map(Some),
+            _ => Err(SnapshotError::InvalidNumber),
+        }
+    }
+}
+
+impl Snapshot for BasicStat {
+    fn to_bytes(&self) -> Vec<u8> {
+        Writer::new(BASIC_STAT, 7)
+            .string(&self.motd)
+            .string(&self.gametype)
+            .string(&self.map)
+            .number(self.numplayers.into())
+            .number(self.maxplayers.into())
+            .number(self.hostport.into())
+            .string(&self.hostip)
+            .finish()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader::new(bytes, BASIC_STAT)?;
+        Ok(Self {
+            motd: r.field(Reader::string::<StatString>)?,
+            gametype: r.field(Reader::string::<StatString>)?,
+            map: r.field(Reader::string::<StatString>)?,
+            numplayers: r.field(Reader::number)?,
+            maxplayers: r.field(Reader::number)?,
+            hostport: r.field(Reader::number)?,
+            hostip: r.field(Reader::string::<StatString>)?,
+        })
+    }
+}
+
+impl Snapshot for FullStat {
+    fn to_bytes(&self) -> Vec<u8> {
+        Writer::new(FULL_STAT, 11)
+            .string(&self.hostname)
+            .string(&self.gametype)
+            .string(&self.game_id)
+            .string(&self.version)
+            .string(&self.plugins)
+            .string(&self.map)
+            .number(self.numplayers.into())
+            .number(self.maxplayers.into())
+            .number(self.hostport.into())
+            .string(&self.hostip)
+            .strings(&self.player_list)
+            .finish()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader::new(bytes, FULL_STAT)?;
+        Ok(Self {
+            hostname: r.field(Reader::string::<StatString>)?,
+            gametype: r.field(Reader::string::<StatString>)?,
+            game_id: r.field(Reader::string::<StatString>)?,
+            version: r.field(Reader::string::<StatString>)?,
+            plugins: r.field(Reader::string::<StatString>)?,
+            map: r.field(Reader::string::<StatString>)?,
+            numplayers: r.field(Reader::number)?,
+            maxplayers: r.field(Reader::number)?,
+            hostport: r.field(Reader::number)?,
+            hostip: r.field(Reader::string::<StatString>)?,
+            player_list: r.field(Reader::strings)?,
+        })
+    }
+}
+
+#[cfg(feature = "compat-mcstatus")]
+#[cfg_attr(doc, doc(cfg(feature = "compat-mcstatus")))]
+impl Snapshot for crate::compat_mcstatus::QueryResponse {
+    /// Encode the response. The parsed MOTD is not stored, but parsed again
+    /// from the raw MOTD when reading the snapshot.
+    fn to_bytes(&self) -> Vec<u8> {
+        Writer::new(QUERY_RESPONSE, 13)
+            .string(&self.motd.raw)
+            .string(&self.map)
+            .number(self.players.online.into())
+            .number(self.players.max.into())
+            .strings(&self.players.list)
+            .string(&self.software.version)
+            .string(&self.software.brand)
+            .strings(&self.software.plugins)
+            .string(&self.ip)
+            .number(self.port.into())
+            .string(&self.game_type)
+            .string(&self.game_id)
+            .optional_string(self.rdns.as_deref())
+            .finish()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        use crate::compat_mcstatus::{Motd, QueryPlayers, QuerySoftware};
+
+        let mut r = Reader::new(bytes, QUERY_RESPONSE)?;
+        let raw: String = r.field(Reader::string)?;
+        Ok(Self {
+            motd: Motd {
+                parsed: crate::motd::parse_codes(&raw),
+                raw,
+            },
+            map: r.field(Reader::string)?,
+            players: QueryPlayers {
+                online: r.field(Reader::number)?,
+                max: r.field(Reader::number)?,
+                list: r.field(Reader::strings)?,
+            },
+            software: QuerySoftware {
+                version: r.field(Reader::string)?,
+                brand: r.field(Reader::string)?,
+                plugins: r.field(Reader::strings)?,
+            },
+            ip: r.field(Reader::string)?,
+            port: r.field(Reader::number)?,
+            game_type: r.field(Reader::string)?,
+            game_id: r.field(Reader::string)?,
+            rdns: r.field(Reader::optional_string)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::sample_stat;
+
+    #[test]
+    fn test_round_trip() {
+        let mut stat = sample_stat();
+        stat.hostname = "§6Ünïcode §lMOTD".into();
+        stat.player_list.push("x".repeat(200).as_str().into());
+        stat.maxplayers = u32::MAX;
+        assert_eq!(FullStat::from_bytes(&stat.to_bytes()).unwrap(), stat);
+
+        let basic = BasicStat::from(&stat);
+        assert_eq!(BasicStat::from_bytes(&basic.to_bytes()).unwrap(), basic);
+
+        // Trailing bytes are ignored
+        let mut bytes = stat.to_bytes();
+        bytes.extend_from_slice(b"garbage");
+        assert_eq!(FullStat::from_bytes(&bytes).unwrap(), stat);
+    }
+
+    #[cfg(feature = "compat-mcstatus")]
+    #[test]
+    fn test_query_response_round_trip() {
+        use crate::compat_mcstatus::QueryResponse;
+
+        let mut stat = sample_stat();
+        stat.plugins = "Paper: WorldEdit 7.2.15; LuckPerms 5.4.102".into();
+        stat.hostname = "§6A §lMinecraft§r Server".into();
+        let mut response = QueryResponse::from(&stat);
+        assert_eq!(
+            QueryResponse::from_bytes(&response.to_bytes()).unwrap(),
+            response
+        );
+
+        response.rdns = Some("backend-03.hosting.example".into());
+        assert_eq!(
+            QueryResponse::from_bytes(&response.to_bytes()).unwrap(),
+            response
+        );
+    }
+
+    #[test]
+    fn test_forward_compatibility() {
+        let stat = BasicStat::from(&sample_stat());
+
+        // A snapshot written before `hostip` was added
+        let mut old = Writer::new(BASIC_STAT, 6)
+            .string(&stat.motd)
+            .string(&stat.gametype)
+            .string(&stat.map)
+            .number(stat.numplayers.into())
+            .number(stat.maxplayers.into())
+            .number(stat.hostport.into())
+            .finish();
+        let read = BasicStat::from_bytes(&old).unwrap();
+        assert_eq!(read.hostip, "");
+        assert_eq!(read.hostport, stat.hostport);
+
+        // A snapshot written after a field was added
+        old[2] = 7;
+        let new = Writer(old)
+            .string(&stat.hostip)
+            .string("new field")
+            .finish();
+        let mut newer = new.clone();
+        newer[2] = 8;
+        assert_eq!(BasicStat::from_bytes(&newer).unwrap(), stat);
+
+        // Incompatible versions
+        let mut bytes = stat.to_bytes();
+        bytes[0] = FORMAT_VERSION + 1;
+        assert_eq!(
+            BasicStat::from_bytes(&bytes),
+            Err(SnapshotError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+        let err = io::Error::from(BasicStat::from_bytes(&bytes).unwrap_err());
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "Unsupported snapshot format version 2, expected 1."
+        );
+    }
+
+    #[test]
+    fn test_invalid_snapshots() {
+        let stat = sample_stat();
+        let bytes = stat.to_bytes();
+
+        assert_eq!(
+            BasicStat::from_bytes(&bytes),
+            Err(SnapshotError::WrongKind {
+                expected: BASIC_STAT,
+                found: FULL_STAT
+            })
+        );
+        for len in 0..bytes.len() {
+            assert_eq!(
+                FullStat::from_bytes(&bytes[..len]),
+                Err(SnapshotError::Truncated),
+                "{len}"
+            );
+        }
+
+        let invalid = Writer::new(FULL_STAT, 1).number(2).finish();
+        let invalid = [invalid, vec![0xC3, 0x28]].concat();
+        assert_eq!(
+            FullStat::from_bytes(&invalid),
+            Err(SnapshotError::InvalidUtf8)
+        );
+
+        // 2^32 players
+        let invalid = Writer::new(FULL_STAT, 7)
+            .string("")
+            .string("")
+            .string("")
+            .string("")
+            .string("")
+            .string("")
+            .number(1 << 32)
+            .finish();
+        assert_eq!(
+            FullStat::from_bytes(&invalid),
+            Err(SnapshotError::InvalidNumber)
+        );
+        let overflow = [
+            &[FORMAT_VERSION, FULL_STAT, 7, 0, 0, 0, 0, 0, 0][..],
+            &[0xFF; 11],
+        ]
+        .concat();
+        assert_eq!(
+            FullStat::from_bytes(&overflow),
+            Err(SnapshotError::InvalidNumber)
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Lifecycle of the background tasks spawned on a [`tokio`](https://docs.rs/tokio/*/tokio) runtime.
+//!
+//! Every `spawn` method of the crate returns a [`TaskHandle`], and has a
+//! `spawn_on` counterpart taking the [`Handle`] of the runtime to spawn on.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "lan")]
+//! # async fn f() -> std::io::Result<()> {
+//! # use minecraft_server_query::lan::tokio::Announcer;
+//! let handle = Announcer::new("A World", 25565)?.spawn();
+//! // ...
+//! handle.stop().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use ::tokio::{
+    runtime::Handle,
+    sync::oneshot,
+    task::{JoinError, JoinHandle},
+};
+use std::{
+    future::{poll_fn, Future},
+    io,
+    pin::pin,
+    task::Poll,
+};
+
+/// Handle to a background task, aborting it when dropped.
+///
+/// The task runs until it is stopped, or until it fails. Panics of the task
+/// do not propagate: they are reported as errors by [`stop`](Self::stop) and
+/// [`join`](Self::join).
+#[derive(Debug)]
+pub struct TaskHandle {
+    stop: Option<oneshot::Sender<()>>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl TaskHandle {
+    /// Spawn a task on the given runtime, running until it completes or until
+    /// the handle is stopped.
+    pub(crate) fn spawn(
+        runtime: &Handle,
+        task: impl Future<Output = io::Result<()>> + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = runtime.spawn(async move {
+            let mut stopped = pin!(stopped);
+            let mut task = pin!(task);
+            poll_fn(|cx| {
+                // The task is also stopped if the sender is dropped
+                if stopped.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(Ok(()));
+                }
+                task.as_mut().poll(cx)
+            })
+            .await
+        });
+
+        Self {
+            stop: Some(stop),
+            task,
+        }
+    }
+
+    /// Stop the task at its next await point, and wait for it to exit.
+    ///
+    /// Returns the error the task failed with, if it exited before being stopped.
+    pub async fn stop(mut self) -> io::Result<()> {
+        drop(self.stop.take());
+        report((&mut self.task).await)
+    }
+
+    /// Wait for the task to exit on its own, returning the error it failed with.
+    pub async fn join(mut self) -> io::Result<()> {
+        report((&mut self.task).await)
+    }
+
+    /// Abort the task, without waiting for it to exit.
+    pub fn abort(self) {
+        // The task is aborted when the handle is dropped
+    }
+
+    /// Whether the task has exited.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Turn the panic or cancellation of a task into an IO error.
+fn report(result: Result<io::Result<()>, JoinError>) -> io::Result<()> {
+    match result {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => {
+            let panic = e.into_panic();
+            let message = panic
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("Box<dyn Any>");
+            Err(io::Error::other(format!("Task panicked: {message}")))
+        }
+        Err(_) => Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "Task was aborted.",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use ::tokio::{runtime::Handle, time::sleep};
+
+    use super::TaskHandle;
+
+    /// Spawn a task incrementing a counter every 10 milliseconds.
+    fn spawn_counter() -> (TaskHandle, Arc<AtomicUsize>) {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let ticks = counter.clone();
+        let handle = TaskHandle::spawn(&Handle::current(), async move {
+            loop {
+                ticks.fetch_add(1, Ordering::Relaxed);
+                sleep(Duration::from_millis(10)).await;
+            }
+        });
+        (handle, counter)
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let (handle, counter) = spawn_counter();
+        sleep(Duration::from_millis(50)).await;
+
+        ::tokio::time::timeout(Duration::from_millis(100), handle.stop())
+            .await
+            .unwrap()
+            .unwrap();
+        let stopped = counter.load(Ordering::Relaxed);
+        assert!(stopped > 0);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(counter.load(Ordering::Relaxed), stopped);
+    }
+
+    #[tokio::test]
+    async fn test_drop_aborts() {
+        let (handle, counter) = spawn_counter();
+        sleep(Duration::from_millis(50)).await;
+
+        drop(handle);
+        // Let the runtime process the abort
+        sleep(Duration::from_millis(10)).await;
+        let stopped = counter.load(Ordering::Relaxed);
+        sleep(Duration::from_millis(50)).await;
+        assert_eq!(counter.load(Ordering::Relaxed), stopped);
+    }
+
+    #[tokio::test]
+    async fn test_failures() {
+        let handle = TaskHandle::spawn(&Handle::current(), async {
+            Err(io::Error::new(io::ErrorKind::AddrInUse, "failed"))
+        });
+        let err = handle.join().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        let handle = TaskHandle::spawn(&Handle::current(), async { panic!("oops") });
+        sleep(Duration::from_millis(10)).await;
+        assert!(handle.is_finished());
+        let err = handle.stop().await.unwrap_err();
+        assert_eq!(err.to_string(), "Task panicked: oops");
+    }
+}
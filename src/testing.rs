@@ -0,0 +1,1272 @@
+//! In-process Query server for deterministic tests.
+//!
+//! [`MockQueryServer`] answers the Query protocol on a loopback address, from
+//! a background thread, with a status set by the test. Every packet it receives
+//! is recorded, so that tests can assert on what a client sent.
+//!
+//! The server can also be told to misbehave, to test timeouts and the handling
+//! of invalid responses, see [`Faults`]. Token expiry can be tested without
+//! waiting, with a [`ManualClock`].
+//!
+//! ```rust
+//! # use minecraft_server_query::{blocking, testing::MockQueryServer};
+//! let server = MockQueryServer::new()?;
+//! let mut stat = server.full_stat();
+//! stat.numplayers = 0;
+//! stat.player_list.clear();
+//! server.set_full_stat(stat.clone());
+//!
+//! assert_eq!(blocking::query(&server.addr().to_string())?, stat);
+//! assert_eq!(server.received().len(), 2);
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "slp")]
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+};
+
+use crate::clock::{Clock, SystemClock};
+use crate::packets::{write_response, PacketType, Request};
+#[cfg(feature = "slp")]
+use crate::slp::{self, SlpStatus};
+use crate::{BasicStat, FullStat, Token, RESPONSE_HEADER_SIZE};
+
+/// Duration a challenge token stays valid after the handshake, as on vanilla servers
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(30);
+
+/// Interval at which the server thread checks for shutdown
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Faults injected by a [`MockQueryServer`] in its responses to one kind of
+/// packet, see [`MockQueryServer::set_faults`].
+///
+/// Faults are combined: a response can be both delayed and truncated, for example.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Faults {
+    /// Number of packets to drop without answering them. Decremented for
+    /// every dropped packet.
+    pub drop_next: usize,
+    /// Delay before sending responses
+    pub delay: Option<Duration>,
+    /// Length responses are truncated to, in bytes, including the header
+    pub truncate: Option<usize>,
+    /// Corrupt the payload of responses, by flipping all their bits
+    pub corrupt: bool,
+    /// Send responses with a wrong session ID
+    pub wrong_session_id: bool,
+    /// Send responses with the type of the other kind of packet
+    pub wrong_packet_type: bool,
+    /// Expire tokens immediately: on handshakes, the token sent is already
+    /// expired, and on status requests, every token is considered expired.
+    pub expire_tokens: bool,
+    /// Drop full status requests, but still answer basic status requests, like
+    /// some old modded servers. Dropped requests are counted in
+    /// [`dropped`](MockQueryServer::dropped).
+    pub ignore_full_stat: bool,
+    /// Drop responses longer than this many bytes, including the header, like
+    /// a lossy link losing large datagrams more often. Dropped responses are
+    /// counted in [`dropped`](MockQueryServer::dropped).
+    pub drop_larger_than: Option<usize>,
+}
+
+/// A value for each kind of packet
+#[derive(Debug, Clone, Default)]
+struct PerKind<T> {
+    handshake: T,
+    stat: T,
+}
+
+impl<T> PerKind<T> {
+    fn get_mut(&mut self, kind: PacketType) -> &mut T {
+        match kind {
+            PacketType::Handshake => &mut self.handshake,
+            PacketType::Stat => &mut self.stat,
+        }
+    }
+}
+
+/// A [`Clock`] only advancing when told to, to test token expiry on a
+/// [`MockQueryServer`] without waiting, see [`MockQueryServer::set_clock`].
+///
+/// Clones share the same time. Sleeping advances the clock right away.
+#[derive(Debug, Clone)]
+pub struct ManualClock(Arc<Mutex<Instant>>);
+
+impl ManualClock {
+    /// Start a clock at the current time.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Current time of the clock.
+    pub fn now(&self) -> Instant {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        ManualClock::now(self)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// A packet received by a [`MockQueryServer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceivedPacket {
+    /// Address the packet was sent from
+    pub source: SocketAddr,
+    /// Raw bytes of the packet
+    pub data: Vec<u8>,
+    /// Parsed request, or `None` if the packet is not a valid request
+    pub request: Option<Request>,
+}
+
+/// State shared with the server thread
+#[derive(Debug)]
+struct State {
+    full_stat: FullStat,
+    basic_stat: Option<BasicStat>,
+    tokens: HashMap<SocketAddr, (Token, Instant)>,
+    next_token: u32,
+    received: Vec<ReceivedPacket>,
+    faults: PerKind<Faults>,
+    dropped: PerKind<usize>,
+    clock: Arc<dyn Clock>,
+    rotation: Option<(Instant, Duration)>,
+    generator: Option<StatGenerator>,
+}
+
+impl State {
+    /// Current time, from the clock of the server.
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Record a packet and build the response to send, if any, with the
+    /// delay to wait before sending it.
+    fn respond(&mut self, data: &[u8], source: SocketAddr) -> Option<(Vec<u8>, Duration)> {
+        let request = Request::parse(data);
+        self.received.push(ReceivedPacket {
+            source,
+            data: data.to_vec(),
+            request,
+        });
+
+        let request = request?;
+        let kind = match request {
+            Request::Handshake { .. } => PacketType::Handshake,
+            Request::BasicStat { .. } | Request::FullStat { .. } => PacketType::Stat,
+        };
+        let faults = self.faults.get_mut(kind);
+        if faults.ignore_full_stat && matches!(request, Request::FullStat { .. }) {
+            *self.dropped.get_mut(kind) += 1;
+            return None;
+        }
+        if faults.drop_next > 0 {
+            faults.drop_next -= 1;
+            *self.dropped.get_mut(kind) += 1;
+            return None;
+        }
+        let faults = faults.clone();
+
+        let mut response = self.answer(request, source, &faults)?;
+        if faults.corrupt {
+            response[RESPONSE_HEADER_SIZE..]
+                .iter_mut()
+                .for_each(|b| *b = !*b);
+        }
+        if faults.wrong_session_id {
+            response[1..RESPONSE_HEADER_SIZE]
+                .iter_mut()
+                .for_each(|b| *b = !*b);
+        }
+        if faults.wrong_packet_type {
+            response[0] = match kind {
+                PacketType::Handshake => PacketType::Stat as u8,
+                PacketType::Stat => PacketType::Handshake as u8,
+            };
+        }
+        if let Some(len) = faults.truncate {
+            response.truncate(len);
+        }
+        if faults
+            .drop_larger_than
+            .is_some_and(|max| response.len() > max)
+        {
+            *self.dropped.get_mut(kind) += 1;
+            return None;
+        }
+
+        Some((response, faults.delay.unwrap_or_default()))
+    }
+
+    /// Build the response to a request, before injecting faults.
+    fn answer(&mut self, request: Request, source: SocketAddr, faults: &Faults) -> Option<Vec<u8>> {
+        match request {
+            Request::Handshake { session_id } => {
+                let token = Token(self.next_token);
+                self.next_token = self.next_token.wrapping_add(1);
+                let issued = if faults.expire_tokens {
+                    self.now().checked_sub(TOKEN_LIFETIME)?
+                } else {
+                    self.now()
+                };
+                self.tokens.insert(source, (token, issued));
+                Some(write_response(
+                    PacketType::Handshake,
+                    session_id,
+                    &token.to_payload(),
+                ))
+            }
+            Request::BasicStat { session_id, token } => {
+                self.check_token(source, token, faults)?;
+                self.generate()?;
+                let stat = self
+                    .basic_stat
+                    .clone()
+                    .unwrap_or_else(|| BasicStat::from(&self.full_stat));
+                Some(write_response(
+                    PacketType::Stat,
+                    session_id,
+                    &stat.to_payload(),
+                ))
+            }
+            Request::FullStat { session_id, token } => {
+                self.check_token(source, token, faults)?;
+                self.generate()?;
+                Some(write_response(
+                    PacketType::Stat,
+                    session_id,
+                    &self.full_stat.to_payload(),
+                ))
+            }
+        }
+    }
+
+    /// Replace the status with the next one of the generator, if any.
+    /// Returns `None` if the generated server is down, after counting the
+    /// request as dropped.
+    fn generate(&mut self) -> Option<()> {
+        let Some(generator) = &mut self.generator else {
+            return Some(());
+        };
+        match generator.advance() {
+            Some(stat) => {
+                self.full_stat = stat;
+                Some(())
+            }
+            None => {
+                *self.dropped.get_mut(PacketType::Stat) += 1;
+                None
+            }
+        }
+    }
+
+    /// Check that the token was issued to the given address less than
+    /// [`TOKEN_LIFETIME`] ago, or since the last rotation if tokens are rotated.
+    fn check_token(&self, source: SocketAddr, token: u32, faults: &Faults) -> Option<()> {
+        let now = self.now();
+        let alive = |issued: Instant| match self.rotation {
+            Some((first, period)) => {
+                let rotations = |t: Instant| {
+                    t.checked_duration_since(first)
+                        .map_or(0, |elapsed| elapsed.as_nanos() / period.as_nanos() + 1)
+                };
+                rotations(issued) == rotations(now)
+            }
+            None => now.saturating_duration_since(issued) < TOKEN_LIFETIME,
+        };
+        match self.tokens.get(&source) {
+            Some((issued_token, issued))
+                if issued_token.0 == token && alive(*issued) && !faults.expire_tokens =>
+            {
+                Some(())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A Query server running on a background thread, bound to a loopback address.
+///
+/// Handshakes are answered with tokens valid for [`TOKEN_LIFETIME`], and
+/// status requests with a valid token are answered with the status set on the
+/// server. Other packets are recorded but not answered, like on vanilla servers.
+///
+/// The server thread is stopped when the server is dropped.
+#[derive(Debug)]
+pub struct MockQueryServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockQueryServer {
+    /// Start a new server with a sample status, see [`sample_stat`].
+    pub fn new() -> io::Result<Self> {
+        Self::with_stat(sample_stat())
+    }
+
+    /// Start a new server answering full status requests with the given
+    /// status. Basic status requests are answered with the same information.
+    pub fn with_stat(full_stat: FullStat) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        socket.set_read_timeout(Some(SHUTDOWN_POLL_INTERVAL))?;
+        let addr = socket.local_addr()?;
+
+        let state = Arc::new(Mutex::new(State {
+            full_stat,
+            basic_stat: None,
+            tokens: HashMap::new(),
+            next_token: 9513307,
+            received: Vec::new(),
+            faults: PerKind::default(),
+            dropped: PerKind::default(),
+            clock: Arc::new(SystemClock),
+            rotation: None,
+            generator: None,
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || serve(socket, &state, &shutdown))
+        };
+
+        Ok(Self {
+            addr,
+            state,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server is bound to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The full status sent by the server.
+    pub fn full_stat(&self) -> FullStat {
+        self.state().full_stat.clone()
+    }
+
+    /// Set the full status sent by the server. Unless it was
+    /// [overridden](Self::set_basic_stat), the basic status is updated as well.
+    pub fn set_full_stat(&self, stat: FullStat) {
+        self.state().full_stat = stat;
+    }
+
+    /// Set the basic status sent by the server, instead of the one built from
+    /// the full status.
+    pub fn set_basic_stat(&self, stat: BasicStat) {
+        self.state().basic_stat = Some(stat);
+    }
+
+    /// Answer status requests with the next status of a generator, instead of
+    /// a fixed status. Requests are dropped while the simulated server is
+    /// down, and counted in [`dropped`](Self::dropped).
+    ///
+    /// [`full_stat`](Self::full_stat) then returns the last status sent.
+    pub fn set_generator(&self, generator: StatGenerator) {
+        self.state().generator = Some(generator);
+    }
+
+    /// Every packet received by the server so far, in order.
+    pub fn received(&self) -> Vec<ReceivedPacket> {
+        self.state().received.clone()
+    }
+
+    /// Forget the packets received so far.
+    pub fn clear_received(&self) {
+        self.state().received.clear();
+    }
+
+    /// Set the faults injected in responses to the given kind of packet.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{blocking, packets::PacketType, testing::*};
+    /// let server = MockQueryServer::new()?;
+    /// server.set_faults(
+    ///     PacketType::Stat,
+    ///     Faults {
+    ///         drop_next: 1,
+    ///         ..Faults::default()
+    ///     },
+    /// );
+    ///
+    /// assert!(blocking::query(&server.addr().to_string()).is_err());
+    /// assert_eq!(server.dropped(PacketType::Stat), 1);
+    /// assert!(blocking::query(&server.addr().to_string()).is_ok());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_faults(&self, kind: PacketType, faults: Faults) {
+        *self.state().faults.get_mut(kind) = faults;
+    }
+
+    /// The faults currently injected in responses to the given kind of packet.
+    ///
+    /// [`drop_next`](Faults::drop_next) is decremented for every dropped packet.
+    pub fn faults(&self, kind: PacketType) -> Faults {
+        self.state().faults.get_mut(kind).clone()
+    }
+
+    /// Number of packets of the given kind dropped so far.
+    pub fn dropped(&self, kind: PacketType) -> usize {
+        *self.state().dropped.get_mut(kind)
+    }
+
+    /// Use the given clock for token expiry, instead of the system clock.
+    ///
+    /// Set it before the first handshake, since tokens are timestamped when issued.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{blocking::QueryClient, testing::*};
+    /// let server = MockQueryServer::new()?;
+    /// let clock = ManualClock::new();
+    /// server.set_clock(clock.clone());
+    ///
+    /// let client = QueryClient::new(&server.addr().to_string())?;
+    /// let token = client.handshake()?;
+    /// clock.advance(TOKEN_LIFETIME);
+    /// assert!(client.basic_stat(token).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_clock(&self, clock: impl Clock + 'static) {
+        self.state().clock = Arc::new(clock);
+    }
+
+    /// Forget every token at once, at `first` and then every `period`, like
+    /// vanilla servers, instead of [`TOKEN_LIFETIME`] after each handshake.
+    ///
+    /// Times are read from the [clock](Self::set_clock) of the server.
+    ///
+    /// ```rust
+    /// # use minecraft_server_query::{blocking::QueryClient, testing::*};
+    /// # use std::time::Duration;
+    /// let server = MockQueryServer::new()?;
+    /// let clock = ManualClock::new();
+    /// server.set_clock(clock.clone());
+    /// server.set_token_rotation(clock.now() + Duration::from_secs(1), TOKEN_LIFETIME);
+    ///
+    /// let client = QueryClient::new(&server.addr().to_string())?;
+    /// let token = client.handshake()?;
+    /// clock.advance(Duration::from_secs(2));
+    /// assert!(client.basic_stat(token).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn set_token_rotation(&self, first: Instant, period: Duration) {
+        self.state().rotation = Some((first, period));
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockQueryServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The sample status sent by [`MockQueryServer::new`].
+pub fn sample_stat() -> FullStat {
+    FullStat {
+        hostname: "A Minecraft Server".into(),
+        gametype: "SMP".into(),
+        game_id: "MINECRAFT".into(),
+        version: "1.20.1".into(),
+        plugins: "".into(),
+        map: "world".into(),
+        numplayers: 2,
+        maxplayers: 20,
+        hostport: crate::DEFAULT_PORT,
+        hostip: "127.0.0.1".into(),
+        player_list: vec!["AldanTanneo".into(), "Dinnerbone".into()],
+    }
+}
+
+/// Deterministic generator of realistic statuses, for demos and load tests
+/// without real servers.
+///
+/// A generator simulates one server from its seed: a name and a MOTD with
+/// formatting codes, a version drawn from the popular ones, and a player
+/// population drifting as players join and leave, one [step](Self::step) of
+/// simulated time after the other. The server is sometimes down for a few
+/// steps.
+///
+/// As an [`Iterator`], it returns the status of every step, skipping the
+/// steps where the server is down. It can also be served by a
+/// [`MockQueryServer`], see [`set_generator`](MockQueryServer::set_generator),
+/// or by a responder, as a `Mutex<StatGenerator>` provider.
+///
+/// ```rust
+/// # use minecraft_server_query::{testing::StatGenerator, FullStat};
+/// let stats: Vec<FullStat> = StatGenerator::new(42).take(3).collect();
+/// assert_eq!(stats, StatGenerator::new(42).take(3).collect::<Vec<_>>());
+/// assert_eq!(stats[0].hostname, stats[2].hostname);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StatGenerator {
+    rng: Rng,
+    step: Duration,
+    outage_rate: f64,
+    elapsed: Duration,
+    down_for: u32,
+    stat: FullStat,
+}
+
+/// Colors of the server names, as formatting codes
+const NAME_COLORS: &[char] = &['6', '9', 'a', 'b', 'c', 'd', 'e'];
+/// Words of the server names
+const NAME_PREFIXES: &[&str] = &["Crafty", "Emerald", "Blocky", "Nether", "Sky", "Pixel"];
+const NAME_SUFFIXES: &[&str] = &["Network", "Realms", "SMP", "Craft", "Islands"];
+/// Second line of the MOTDs
+const TAGLINES: &[&str] = &[
+    "Survival & Skyblock",
+    "Now on 1.21!",
+    "Minigames and more",
+    "Join the adventure",
+];
+/// Versions, weighted by their popularity
+const VERSIONS: &[(&str, u64)] = &[
+    ("1.21.1", 35),
+    ("1.20.4", 25),
+    ("1.20.1", 15),
+    ("1.19.4", 10),
+    ("1.12.2", 8),
+    ("1.8.9", 7),
+];
+/// Player limits, small enough for full status responses to fit in a packet
+const MAX_PLAYERS: &[u32] = &[20, 32, 50, 64];
+/// Words of the player names, at most 16 characters long with their number
+const PLAYER_ADJECTIVES: &[&str] = &[
+    "Swift", "Lazy", "Crafty", "Iron", "Shadow", "Pixel", "Frost", "Ember", "Lucky", "Sneaky",
+];
+const PLAYER_NOUNS: &[&str] = &[
+    "Fox", "Creeper", "Miner", "Golem", "Wolf", "Bee", "Knight", "Builder", "Slime", "Raven",
+];
+
+impl StatGenerator {
+    /// A generator of the server simulated from the seed, with steps of 10
+    /// seconds and an outage every 200 steps on average.
+    // `StatString` is only another type than `String` with the `compact_str` feature
+    #[allow(clippy::useless_conversion)]
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng(seed);
+        let name = format!("{} {}", rng.pick(NAME_PREFIXES), rng.pick(NAME_SUFFIXES));
+        let hostname = format!(
+            "§{}§l{name}§r §7- {}",
+            rng.pick(NAME_COLORS),
+            rng.pick(TAGLINES)
+        );
+        let version = rng.weighted(VERSIONS);
+        let plugins = if rng.chance(0.5) {
+            format!("Paper on {version}: EssentialsX 2.20.1; LuckPerms 5.4.102")
+        } else {
+            String::new()
+        };
+        let maxplayers = *rng.pick(MAX_PLAYERS);
+
+        let mut generator = Self {
+            rng,
+            step: Duration::from_secs(10),
+            outage_rate: 0.005,
+            elapsed: Duration::ZERO,
+            down_for: 0,
+            stat: FullStat {
+                hostname: hostname.into(),
+                gametype: "SMP".into(),
+                game_id: "MINECRAFT".into(),
+                version: version.into(),
+                plugins: plugins.into(),
+                map: "world".into(),
+                numplayers: 0,
+                maxplayers,
+                hostport: crate::DEFAULT_PORT,
+                hostip: "127.0.0.1".into(),
+                player_list: Vec::new(),
+            },
+        };
+        let population = generator.rng.below(maxplayers as u64 / 2 + 1);
+        for _ in 0..population {
+            generator.join();
+        }
+        generator
+    }
+
+    /// Simulated time between two statuses.
+    pub fn step(mut self, step: Duration) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Chance of the server going down at each step, for 1 to 10 steps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chance is not in `0.0..1.0`.
+    pub fn outage_rate(mut self, rate: f64) -> Self {
+        assert!((0.0..1.0).contains(&rate), "Invalid outage rate {rate}");
+        self.outage_rate = rate;
+        self
+    }
+
+    /// Simulated time elapsed since the first status.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Move to the next step, and return the status of the server, or `None`
+    /// if it is down.
+    pub fn advance(&mut self) -> Option<FullStat> {
+        self.elapsed += self.step;
+        if self.down_for > 0 {
+            self.down_for -= 1;
+        } else if self.rng.chance(self.outage_rate) {
+            self.down_for = self.rng.below(10) as u32;
+        } else {
+            // Random walk of the population, within the player limit
+            match self.rng.below(5) {
+                0 => self.leave(),
+                1 => {
+                    self.leave();
+                    self.leave();
+                }
+                2 => self.join(),
+                3 => {
+                    self.join();
+                    self.join();
+                }
+                _ => {}
+            }
+            return Some(self.stat.clone());
+        }
+        None
+    }
+
+    /// Add a player with a name no online player has, unless the server is full.
+    // `StatString` is only another type than `String` with the `compact_str` feature
+    #[allow(clippy::useless_conversion)]
+    fn join(&mut self) {
+        if self.stat.numplayers >= self.stat.maxplayers {
+            return;
+        }
+        let name = loop {
+            let name = format!(
+                "{}{}{}",
+                self.rng.pick(PLAYER_ADJECTIVES),
+                self.rng.pick(PLAYER_NOUNS),
+                self.rng.below(1000)
+            )
+            .into();
+            if !self.stat.player_list.contains(&name) {
+                break name;
+            }
+        };
+        self.stat.player_list.push(name);
+        self.stat.numplayers += 1;
+    }
+
+    /// Remove a random player, if any.
+    fn leave(&mut self) {
+        if self.stat.player_list.is_empty() {
+            return;
+        }
+        let index = self.rng.below(self.stat.player_list.len() as u64) as usize;
+        self.stat.player_list.remove(index);
+        self.stat.numplayers -= 1;
+    }
+}
+
+impl Iterator for StatGenerator {
+    type Item = FullStat;
+
+    /// The status of the next step where the server is up. This never
+    /// returns `None`.
+    fn next(&mut self) -> Option<FullStat> {
+        loop {
+            if let Some(stat) = self.advance() {
+                return Some(stat);
+            }
+        }
+    }
+}
+
+/// Responders can't drop requests, so the steps where the server is down are
+/// skipped, like by the iterator.
+#[cfg(feature = "responder")]
+impl crate::responder::StatsProvider for Mutex<StatGenerator> {
+    fn full_stat(&self) -> FullStat {
+        let mut generator = self.lock().unwrap_or_else(PoisonError::into_inner);
+        generator.next().expect("the generator never ends")
+    }
+}
+
+/// SplitMix64, a small deterministic pseudo-random generator
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Whether an event of the given probability happens.
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+
+    fn weighted<T: Copy>(&mut self, items: &[(T, u64)]) -> T {
+        let mut n = self.below(items.iter().map(|(_, weight)| weight).sum());
+        for (item, weight) in items {
+            if n < *weight {
+                return *item;
+            }
+            n -= weight;
+        }
+        unreachable!("the number is below the total weight")
+    }
+}
+
+/// A Server List Ping server bound to a loopback address, answering with a
+/// status set by the test.
+///
+/// Connections are handled one at a time, from a background thread which is
+/// stopped when the server is dropped.
+#[cfg(feature = "slp")]
+#[cfg_attr(doc, doc(cfg(feature = "slp")))]
+#[derive(Debug)]
+pub struct MockSlpServer {
+    addr: SocketAddr,
+    status: Arc<Mutex<SlpStatus>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "slp")]
+impl MockSlpServer {
+    /// Start a new server with a sample status, see [`sample_status`].
+    pub fn new() -> io::Result<Self> {
+        Self::with_status(sample_status())
+    }
+
+    /// Start a new server answering pings with the given status.
+    pub fn with_status(status: SlpStatus) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let status = Arc::new(Mutex::new(status));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let status = status.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || serve_slp(listener, &status, &shutdown))
+        };
+
+        Ok(Self {
+            addr,
+            status,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server is bound to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The status sent by the server.
+    pub fn status(&self) -> SlpStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Set the status sent by the server.
+    pub fn set_status(&self, status: SlpStatus) {
+        *self.status.lock().unwrap_or_else(PoisonError::into_inner) = status;
+    }
+}
+
+#[cfg(feature = "slp")]
+impl Drop for MockSlpServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The sample status sent by [`MockSlpServer::new`], matching [`sample_stat`].
+#[cfg(feature = "slp")]
+#[cfg_attr(doc, doc(cfg(feature = "slp")))]
+pub fn sample_status() -> SlpStatus {
+    SlpStatus {
+        version: slp::Version {
+            name: "1.20.1".to_string(),
+            protocol: 763,
+        },
+        players: slp::Players {
+            max: 20,
+            online: 2,
+            sample: vec![
+                slp::SamplePlayer {
+                    name: "AldanTanneo".to_string(),
+                    id: slp::parse_uuid("2f3b1a4e-6c0d-4e8a-9b51-5d2c7e9f0a13"),
+                },
+                slp::SamplePlayer {
+                    name: "Dinnerbone".to_string(),
+                    id: slp::parse_uuid("61699b2e-d327-4a01-9f1e-0ea8c3f06bc6"),
+                },
+            ],
+        },
+        description: slp::ChatComponent {
+            text: "A Minecraft Server".to_string(),
+            ..Default::default()
+        },
+        favicon: None,
+        modinfo: None,
+        forge_data: None,
+    }
+}
+
+/// Lock the shared state, ignoring poisoning by a panicking test.
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Answer packets until the shutdown flag is set or an IO error occurs.
+fn serve(socket: UdpSocket, state: &Mutex<State>, shutdown: &AtomicBool) {
+    let mut buf = [0; FullStat::RESPONSE_SIZE];
+
+    while !shutdown.load(Ordering::Relaxed) {
+        let (received, source) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                        | io::ErrorKind::ConnectionReset
+                ) =>
+            {
+                continue
+            }
+            Err(_) => return,
+        };
+
+        let response = lock(state).respond(&buf[..received], source);
+        match response {
+            Some((response, delay)) if delay.is_zero() => {
+                let _ = socket.send_to(&response, source);
+            }
+            // Delayed responses are sent from another thread, so that other
+            // packets are still answered in the meantime
+            Some((response, delay)) => {
+                if let Ok(socket) = socket.try_clone() {
+                    std::thread::spawn(move || {
+                        std::thread::sleep(delay);
+                        let _ = socket.send_to(&response, source);
+                    });
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+/// Accept connections until the shutdown flag is set or an IO error occurs.
+#[cfg(feature = "slp")]
+fn serve_slp(listener: TcpListener, status: &Mutex<SlpStatus>, shutdown: &AtomicBool) {
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                let _ = answer_ping(stream, status);
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(SHUTDOWN_POLL_INTERVAL)
+            }
+            Err(_) => return,
+        }
+    }
+}
+
+/// Answer the status request and the optional ping of a single connection.
+#[cfg(feature = "slp")]
+fn answer_ping(mut stream: TcpStream, status: &Mutex<SlpStatus>) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    // Handshake, then status request
+    slp::read_packet(&mut stream)?;
+    slp::read_packet(&mut stream)?;
+    let response = slp::status_response(&status.lock().unwrap_or_else(PoisonError::into_inner));
+    stream.write_all(&response)?;
+
+    let payload = slp::parse_pong(&slp::read_packet(&mut stream)?)?;
+    stream.write_all(&slp::ping_request(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blocking::QueryClient, packets, packets::PacketType};
+
+    #[test]
+    fn test_token_semantics() {
+        let server = MockQueryServer::new().unwrap();
+        let client = QueryClient::new(&server.addr().to_string()).unwrap();
+
+        let token = client.handshake().unwrap();
+        let err = client.basic_stat(Token(token.0 + 1)).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        assert_eq!(client.basic_stat(token).unwrap().numplayers, 2);
+    }
+
+    #[test]
+    fn test_set_stats() {
+        let server = MockQueryServer::new().unwrap();
+        let client = QueryClient::new(&server.addr().to_string()).unwrap();
+        let token = client.handshake().unwrap();
+
+        let mut stat = sample_stat();
+        stat.hostname = "Updated".into();
+        server.set_full_stat(stat.clone());
+        assert_eq!(client.full_stat(token).unwrap(), stat);
+        assert_eq!(client.basic_stat(token).unwrap().motd, "Updated");
+
+        let mut basic = BasicStat::from(&stat);
+        basic.motd = "Overridden".into();
+        server.set_basic_stat(basic.clone());
+        assert_eq!(client.basic_stat(token).unwrap(), basic);
+        assert_eq!(client.full_stat(token).unwrap(), stat);
+    }
+
+    #[test]
+    fn test_received_packets() {
+        let server = MockQueryServer::new().unwrap();
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.connect(server.addr()).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        socket.send(b"garbage").unwrap();
+        socket.send(&packets::Handshake::new(7)).unwrap();
+        let mut buf = [0; 16];
+        socket.recv(&mut buf).unwrap();
+
+        let received = server.received();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].data, b"garbage");
+        assert_eq!(received[0].request, None);
+        assert_eq!(received[1].source, socket.local_addr().unwrap());
+        assert_eq!(
+            received[1].request,
+            Some(Request::Handshake { session_id: 7 })
+        );
+
+        server.clear_received();
+        assert!(server.received().is_empty());
+    }
+
+    /// Send a request from a new socket and wait for the response.
+    fn request(server: &MockQueryServer, request: &[u8]) -> io::Result<Vec<u8>> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+        socket.connect(server.addr())?;
+        socket.set_read_timeout(Some(Duration::from_millis(200)))?;
+        socket.send(request)?;
+        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
+        let received = socket.recv(&mut buf)?;
+        buf.truncate(received);
+        Ok(buf)
+    }
+
+    #[test]
+    fn test_drop_faults() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                drop_next: 2,
+                ..Faults::default()
+            },
+        );
+        let client = QueryClient::new(&server.addr().to_string()).unwrap();
+
+        assert!(client.handshake().is_err());
+        assert!(client.handshake().is_err());
+        let token = client.handshake().unwrap();
+        client.full_stat(token).unwrap();
+
+        assert_eq!(server.dropped(PacketType::Handshake), 2);
+        assert_eq!(server.dropped(PacketType::Stat), 0);
+        assert_eq!(server.faults(PacketType::Handshake).drop_next, 0);
+        assert_eq!(server.received().len(), 4);
+
+        // Only the full status is too large
+        let basic = BasicStat::from(&server.full_stat()).to_payload();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_larger_than: Some(RESPONSE_HEADER_SIZE + basic.len()),
+                ..Faults::default()
+            },
+        );
+        let token = client.handshake().unwrap();
+        client.basic_stat(token).unwrap();
+        assert!(client.full_stat(token).is_err());
+        assert_eq!(server.dropped(PacketType::Stat), 1);
+    }
+
+    #[test]
+    fn test_delay_faults() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                delay: Some(Duration::from_millis(100)),
+                ..Faults::default()
+            },
+        );
+
+        let start = Instant::now();
+        request(&server, &packets::Handshake::new(7)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                delay: Some(Duration::from_millis(400)),
+                ..Faults::default()
+            },
+        );
+        let err = request(&server, &packets::Handshake::new(7)).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+    }
+
+    #[test]
+    fn test_malformed_response_faults() {
+        let server = MockQueryServer::new().unwrap();
+        let clean = request(&server, &packets::Handshake::new(7)).unwrap();
+        let faulty = |faults: Faults| {
+            server.set_faults(PacketType::Handshake, faults);
+            request(&server, &packets::Handshake::new(7)).unwrap()
+        };
+
+        let truncated = faulty(Faults {
+            truncate: Some(3),
+            ..Faults::default()
+        });
+        assert_eq!(truncated, clean[..3]);
+
+        let corrupted = faulty(Faults {
+            corrupt: true,
+            ..Faults::default()
+        });
+        assert_eq!(corrupted[..5], clean[..5]);
+        assert!(corrupted[5..].iter().all(|&b| b > b'9'));
+
+        let wrong_session_id = faulty(Faults {
+            wrong_session_id: true,
+            ..Faults::default()
+        });
+        assert_eq!(wrong_session_id[0], clean[0]);
+        assert_ne!(wrong_session_id[1..5], clean[1..5]);
+
+        let wrong_packet_type = faulty(Faults {
+            wrong_packet_type: true,
+            ..Faults::default()
+        });
+        assert_eq!(wrong_packet_type[0], PacketType::Stat as u8);
+        assert_eq!(wrong_packet_type[1..5], clean[1..5]);
+    }
+
+    #[test]
+    fn test_expire_tokens_faults() {
+        let server = MockQueryServer::new().unwrap();
+        let client = QueryClient::new(&server.addr().to_string()).unwrap();
+        let expire = Faults {
+            expire_tokens: true,
+            ..Faults::default()
+        };
+
+        server.set_faults(PacketType::Handshake, expire.clone());
+        let token = client.handshake().unwrap();
+        assert!(client.basic_stat(token).is_err());
+        server.set_faults(PacketType::Handshake, Faults::default());
+
+        let token = client.handshake().unwrap();
+        server.set_faults(PacketType::Stat, expire);
+        assert!(client.basic_stat(token).is_err());
+        server.set_faults(PacketType::Stat, Faults::default());
+        client.basic_stat(token).unwrap();
+    }
+
+    #[test]
+    fn test_manual_clock() {
+        let server = MockQueryServer::new().unwrap();
+        let clock = ManualClock::new();
+        server.set_clock(clock.clone());
+        // Expired tokens are not answered, only wait a little for the response
+        let client = QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            (Ipv4Addr::LOCALHOST, 0),
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+
+        let token = client.handshake().unwrap();
+        clock.advance(TOKEN_LIFETIME - Duration::from_secs(1));
+        client.basic_stat(token).unwrap();
+        client.full_stat(token).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert!(client.basic_stat(token).is_err());
+        assert!(client.full_stat(token).is_err());
+
+        let token = client.handshake().unwrap();
+        client.full_stat(token).unwrap();
+    }
+
+    #[test]
+    fn test_stat_generator_determinism() {
+        let stats: Vec<FullStat> = StatGenerator::new(7).take(50).collect();
+        assert_eq!(stats, StatGenerator::new(7).take(50).collect::<Vec<_>>());
+        assert_ne!(stats, StatGenerator::new(8).take(50).collect::<Vec<_>>());
+        // The population changes over time
+        assert!(stats
+            .windows(2)
+            .any(|w| w[0].player_list != w[1].player_list));
+    }
+
+    #[test]
+    fn test_stat_generator_validity() {
+        for seed in 0..5 {
+            let mut generator = StatGenerator::new(seed);
+            for stat in generator.by_ref().take(500) {
+                assert_eq!(stat.numplayers as usize, stat.player_list.len());
+                assert!(stat.numplayers <= stat.maxplayers);
+                assert!(stat.player_list.iter().all(|name| name.len() <= 16));
+                let payload = stat.to_payload();
+                assert!(RESPONSE_HEADER_SIZE + payload.len() <= FullStat::RESPONSE_SIZE);
+                assert_eq!(FullStat::from_payload(&payload).unwrap(), stat);
+            }
+            assert!(generator.elapsed() >= Duration::from_secs(5000));
+        }
+    }
+
+    #[test]
+    fn test_stat_generator_outages() {
+        let mut generator = StatGenerator::new(3)
+            .step(Duration::from_secs(1))
+            .outage_rate(0.2);
+        let steps: Vec<bool> = (0..200).map(|_| generator.advance().is_some()).collect();
+        assert_eq!(generator.elapsed(), Duration::from_secs(200));
+        assert!(steps.iter().any(|up| !up));
+        assert!(steps.iter().filter(|up| **up).count() > 50);
+
+        // The iterator skips outages
+        let mut generator = StatGenerator::new(3).outage_rate(0.2);
+        generator.by_ref().take(100).for_each(drop);
+        assert!(generator.elapsed() > Duration::from_secs(1000));
+    }
+
+    #[test]
+    fn test_generated_server() {
+        let server = MockQueryServer::new().unwrap();
+        let generator = StatGenerator::new(11).outage_rate(0.3);
+        server.set_generator(generator.clone());
+        let client = QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            (Ipv4Addr::LOCALHOST, 0),
+            Some(Duration::from_millis(50)),
+        )
+        .unwrap();
+
+        let mut expected = generator;
+        let token = client.handshake().unwrap();
+        for _ in 0..20 {
+            let result = client.full_stat(token);
+            match expected.advance() {
+                Some(stat) => {
+                    assert_eq!(result.unwrap(), stat);
+                    assert_eq!(server.full_stat(), stat);
+                }
+                None => assert!(crate::is_timeout(&result.unwrap_err())),
+            }
+        }
+        assert!(server.dropped(PacketType::Stat) > 0);
+    }
+
+    #[cfg(feature = "responder")]
+    #[test]
+    fn test_generator_provider() {
+        use crate::responder::StatsProvider;
+
+        let provider = Mutex::new(StatGenerator::new(5).outage_rate(0.5));
+        let expected: Vec<FullStat> = StatGenerator::new(5).outage_rate(0.5).take(10).collect();
+        let provided: Vec<FullStat> = (0..10).map(|_| provider.full_stat()).collect();
+        assert_eq!(provided, expected);
+    }
+}
@@ -0,0 +1,351 @@
+//! Challenge token caching, learning when the server rotates its tokens.
+//!
+//! Vanilla servers do not give each token its own lifetime: they forget every
+//! token at once, at fixed [`ROTATION_PERIOD`] intervals. A token obtained
+//! just before a rotation dies almost immediately, whatever its age.
+//!
+//! A [`TokenHandle`] caches the token of a client between polls. When a
+//! cached token is rejected, which shows as a status request timing out while
+//! a new handshake succeeds, the time of the rotation is narrowed down to the
+//! interval between the last successful request and the rejected one. The
+//! token is then refreshed before each predicted rotation, and the next
+//! rotations do not fail any request:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::{blocking::QueryClient, token_cache::TokenHandle};
+//! # use std::{thread::sleep, time::Duration};
+//! let client = QueryClient::new("play.example.com")?;
+//! let mut tokens = TokenHandle::new();
+//! loop {
+//!     let stat = client.full_stat_cached(&mut tokens)?;
+//!     println!("{} players, token valid until {:?}", stat.numplayers, tokens.estimated_expiry());
+//!     sleep(Duration::from_secs(7));
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Successful requests also narrow down the rotation time, since no rotation
+//! happened between getting a token and using it.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::clock::{Clock, SystemClock};
+use crate::Token;
+
+/// Interval between two token rotations on vanilla servers
+pub const ROTATION_PERIOD: Duration = Duration::from_secs(30);
+
+/// Margin around the predicted rotations, absorbing the jitter of the network
+pub const ROTATION_MARGIN: Duration = Duration::from_millis(250);
+
+/// Estimation of the phase of the token rotations of a server, as an interval
+/// known to contain one of the rotations. The other rotations are a whole
+/// number of periods before or after it.
+#[derive(Debug, Clone)]
+struct RotationEstimator {
+    period: Duration,
+    /// Bounds of the interval, exclusive at the start and inclusive at the end
+    window: Option<(Instant, Instant)>,
+}
+
+impl RotationEstimator {
+    fn new(period: Duration) -> Self {
+        Self {
+            period,
+            window: None,
+        }
+    }
+
+    /// Occurrence of the window starting at or before `t`, less than one
+    /// period before it.
+    fn window_at(&self, t: Instant) -> Option<(Instant, Instant)> {
+        let (lo, hi) = self.window?;
+        let period = self.period.as_nanos();
+        if t >= lo {
+            let by = Duration::from_nanos(((t - lo).as_nanos() / period * period) as u64);
+            Some((lo + by, hi + by))
+        } else {
+            let by = Duration::from_nanos(((lo - t).as_nanos().div_ceil(period) * period) as u64);
+            Some((lo.checked_sub(by)?, hi.checked_sub(by)?))
+        }
+    }
+
+    /// Record a rotation between `after` and `before`.
+    fn record_rotation(&mut self, after: Instant, before: Instant) {
+        if before <= after || before - after >= self.period {
+            return;
+        }
+        self.window = match self.window_at(before) {
+            Some((lo, hi)) if lo.max(after) < hi.min(before) => {
+                Some((lo.max(after), hi.min(before)))
+            }
+            // The new rotation does not match the estimation: the server
+            // restarted, or the period is wrong
+            _ => Some((after, before)),
+        };
+    }
+
+    /// Record that no rotation happened between `after` and `before`.
+    fn record_no_rotation(&mut self, after: Instant, before: Instant) {
+        if before <= after || before - after >= self.period {
+            return;
+        }
+        let Some((lo, hi)) = self.window_at(before) else {
+            return;
+        };
+        if after <= lo && before >= hi {
+            // The server does not rotate its tokens as estimated
+            self.window = None;
+        } else if after <= lo {
+            self.window = Some((before, hi));
+        } else if after < hi && before >= hi {
+            self.window = Some((lo, after));
+        } else if let Some((prev_lo, prev_hi)) =
+            lo.checked_sub(self.period).zip(hi.checked_sub(self.period))
+        {
+            // `after` is less than a period before `before`, so after `prev_lo`
+            if after < prev_hi {
+                self.window = Some((prev_lo, after));
+            }
+        }
+    }
+
+    /// Time until which a token obtained at `acquired` is surely valid.
+    ///
+    /// Tokens obtained close to a predicted rotation may already be dead, and
+    /// expire right away.
+    fn expiry(&self, acquired: Instant) -> Instant {
+        let Some((lo, hi)) = self.window_at(acquired) else {
+            return acquired + self.period;
+        };
+        let next_lo = lo + self.period;
+        if acquired <= hi + ROTATION_MARGIN || acquired + ROTATION_MARGIN > next_lo {
+            acquired
+        } else {
+            next_lo - ROTATION_MARGIN
+        }
+    }
+}
+
+/// A cached token, with the times of the requests it was used in
+#[derive(Debug, Clone, Copy)]
+struct Cached {
+    token: Token,
+    acquired: Instant,
+    last_success: Instant,
+}
+
+/// Cache of the challenge token of a client, refreshing it before each
+/// predicted rotation of the server tokens.
+///
+/// Use it with the `_cached` methods of the clients, like
+/// [`blocking::QueryClient::full_stat_cached`](crate::blocking::QueryClient::full_stat_cached).
+/// A handle learns the rotations of a single server: use one per server.
+#[derive(Debug, Clone)]
+pub struct TokenHandle {
+    estimator: RotationEstimator,
+    cached: Option<Cached>,
+    rejections: u32,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for TokenHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenHandle {
+    /// An empty cache, for servers rotating their tokens every
+    /// [`ROTATION_PERIOD`].
+    pub fn new() -> Self {
+        Self::with_period(ROTATION_PERIOD)
+    }
+
+    /// An empty cache, for servers rotating their tokens at the given period.
+    pub fn with_period(period: Duration) -> Self {
+        Self {
+            estimator: RotationEstimator::new(period),
+            cached: None,
+            rejections: 0,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Read the time from the given clock instead of the system clock, for
+    /// example a [`ManualClock`](crate::testing::ManualClock) shared with a
+    /// mock server.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Interval between two token rotations of the server.
+    pub fn period(&self) -> Duration {
+        self.estimator.period
+    }
+
+    /// Time until which the cached token is expected to stay valid, or
+    /// `None` if no token is cached.
+    ///
+    /// Until a rotation is observed, this is one period after the handshake.
+    /// Afterwards, it is slightly before the next predicted rotation.
+    pub fn estimated_expiry(&self) -> Option<Instant> {
+        self.cached
+            .map(|cached| self.estimator.expiry(cached.acquired))
+    }
+
+    /// Number of cached tokens rejected by the server so far.
+    pub fn rejections(&self) -> u32 {
+        self.rejections
+    }
+
+    /// Forget the cached token, to get a new one for the next request.
+    pub fn invalidate(&mut self) {
+        self.cached = None;
+    }
+
+    /// Current time, from the clock of the handle.
+    pub(crate) fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// The cached token, if it is still expected to be valid at `now`.
+    pub(crate) fn token(&self, now: Instant) -> Option<Token> {
+        self.cached
+            .filter(|cached| now < self.estimator.expiry(cached.acquired))
+            .map(|cached| cached.token)
+    }
+
+    /// Cache a token obtained by a handshake sent at `sent`.
+    pub(crate) fn set(&mut self, token: Token, sent: Instant) {
+        self.cached = Some(Cached {
+            token,
+            acquired: sent,
+            last_success: sent,
+        });
+    }
+
+    /// Record a request with the cached token, sent at `sent`, answered by
+    /// the server.
+    pub(crate) fn record_success(&mut self, sent: Instant) {
+        if let Some(cached) = &mut self.cached {
+            self.estimator.record_no_rotation(cached.acquired, sent);
+            cached.last_success = sent;
+        }
+    }
+
+    /// Record the rejection of the cached token by a request sent at `sent`,
+    /// and forget the token.
+    pub(crate) fn record_rejection(&mut self, sent: Instant) {
+        if let Some(cached) = self.cached.take() {
+            self.estimator.record_rotation(cached.last_success, sent);
+            self.rejections += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{RotationEstimator, TokenHandle, ROTATION_MARGIN};
+    use crate::clock::Clock;
+    use crate::testing::ManualClock;
+    use crate::Token;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_window_intersection() {
+        let t0 = Instant::now();
+        let mut estimator = RotationEstimator::new(30 * SECOND);
+        estimator.record_rotation(t0 + 10 * SECOND, t0 + 17 * SECOND);
+        // Three periods later, overlapping the end of the window
+        estimator.record_rotation(t0 + 104 * SECOND, t0 + 110 * SECOND);
+        assert_eq!(
+            estimator.window_at(t0 + 20 * SECOND),
+            Some((t0 + 14 * SECOND, t0 + 17 * SECOND))
+        );
+        assert_eq!(
+            estimator.window_at(t0 + 5 * SECOND),
+            Some((t0 - 16 * SECOND, t0 - 13 * SECOND))
+        );
+    }
+
+    #[test]
+    fn test_mismatched_rotation_resets() {
+        let t0 = Instant::now();
+        let mut estimator = RotationEstimator::new(30 * SECOND);
+        estimator.record_rotation(t0 + 10 * SECOND, t0 + 17 * SECOND);
+        estimator.record_rotation(t0 + 50 * SECOND, t0 + 52 * SECOND);
+        assert_eq!(
+            estimator.window_at(t0 + 52 * SECOND),
+            Some((t0 + 50 * SECOND, t0 + 52 * SECOND))
+        );
+        // Wider than a period: no information
+        estimator.record_rotation(t0 + 60 * SECOND, t0 + 100 * SECOND);
+        assert_eq!(
+            estimator.window_at(t0 + 52 * SECOND),
+            Some((t0 + 50 * SECOND, t0 + 52 * SECOND))
+        );
+    }
+
+    #[test]
+    fn test_successes_narrow_the_window() {
+        let t0 = Instant::now();
+        let mut estimator = RotationEstimator::new(30 * SECOND);
+        estimator.record_rotation(t0 + 10 * SECOND, t0 + 17 * SECOND);
+        // Token obtained before the start of the next window, used inside it
+        estimator.record_no_rotation(t0 + 35 * SECOND, t0 + 42 * SECOND);
+        assert_eq!(
+            estimator.window_at(t0 + 45 * SECOND),
+            Some((t0 + 42 * SECOND, t0 + 47 * SECOND))
+        );
+        // Token obtained inside the window, used after it
+        estimator.record_no_rotation(t0 + 75 * SECOND, t0 + 80 * SECOND);
+        assert_eq!(
+            estimator.window_at(t0 + 80 * SECOND),
+            Some((t0 + 72 * SECOND, t0 + 75 * SECOND))
+        );
+        // Token valid across the whole window
+        estimator.record_no_rotation(t0 + 100 * SECOND, t0 + 110 * SECOND);
+        assert_eq!(estimator.window_at(t0 + 110 * SECOND), None);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let t0 = Instant::now();
+        let mut estimator = RotationEstimator::new(30 * SECOND);
+        assert_eq!(estimator.expiry(t0), t0 + 30 * SECOND);
+
+        estimator.record_rotation(t0 + 10 * SECOND, t0 + 17 * SECOND);
+        assert_eq!(
+            estimator.expiry(t0 + 20 * SECOND),
+            t0 + 40 * SECOND - ROTATION_MARGIN
+        );
+        assert_eq!(
+            estimator.expiry(t0 + 5 * SECOND),
+            t0 + 10 * SECOND - ROTATION_MARGIN
+        );
+        // Obtained during a possible rotation
+        assert_eq!(estimator.expiry(t0 + 44 * SECOND), t0 + 44 * SECOND);
+        assert_eq!(estimator.expiry(t0 + 40 * SECOND), t0 + 40 * SECOND);
+    }
+
+    #[test]
+    fn test_handle_clock() {
+        let clock = ManualClock::new();
+        let mut tokens = TokenHandle::new().with_clock(clock.clone());
+        tokens.set(Token(1234), tokens.now());
+        assert_eq!(tokens.estimated_expiry(), Some(clock.now() + 30 * SECOND));
+
+        clock.sleep(29 * SECOND);
+        assert_eq!(tokens.token(tokens.now()), Some(Token(1234)));
+        clock.sleep(SECOND);
+        assert_eq!(tokens.token(tokens.now()), None);
+    }
+}
@@ -3,19 +3,45 @@
 //! Uses [`tokio::net::UdpSocket`](https://docs.rs/tokio/*/tokio/net/struct.UdpSocket.html) for sending and receiving UDP data
 
 use ::tokio::{
+    io::ReadBuf,
     net::{ToSocketAddrs, UdpSocket},
-    time::timeout,
+    time::{sleep, timeout, timeout_at, Sleep},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{ready, Context, Poll, Waker},
+    time::{Duration, Instant},
 };
-use std::{io, net::Ipv4Addr, time::Duration};
 
 use super::*;
+use crate::advertised::{self, Advertised, FollowOptions};
+use crate::connection_string::{ConnectionString, ConnectionStringError};
+use crate::gs4::BedrockFullStat;
+use crate::packets::{QueryPacket, SESSION_MASK};
+use crate::quality::{ProbeOptions, Probes, QualityReport};
+use crate::rate_limit::{self, RateLimiter};
+use crate::resolve::{self, Resolve, SystemResolver};
+use crate::token_cache::TokenHandle;
 
 /// An asynchronous Query client using the [`tokio`](https://docs.rs/tokio/*/tokio) networking primitives.
+///
+/// Requests can be sent concurrently from several tasks sharing the client:
+/// each request has its own session ID, and the responses received on the
+/// socket are handed to the request with the same session ID.
 #[derive(Debug)]
 pub struct QueryClient {
     socket: UdpSocket,
     session_id: u32,
+    in_flight: Mutex<InFlight>,
     timeout: Option<Duration>,
+    target: String,
+    #[cfg(feature = "histogram")]
+    latency: crate::histogram::ClientLatency,
 }
 
 impl QueryClient {
@@ -25,16 +51,7 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub async fn new(ip: &str) -> io::Result<Self> {
-        let (ip, port) = if let Some((ip, port)) = ip.split_once(':') {
-            (
-                ip,
-                port.parse::<u16>().map_err(|_| {
-                    io::Error::new(io::ErrorKind::Other, "Invalid port in IP address")
-                })?,
-            )
-        } else {
-            (ip, DEFAULT_PORT)
-        };
+        let (ip, port) = split_address(ip)?;
 
         Self::new_with_port(ip, port).await
     }
@@ -45,17 +62,43 @@ impl QueryClient {
     ///
     /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
     pub async fn new_with_port(ip: &str, port: u16) -> io::Result<Self> {
-        if ip.contains(':') {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
-                "Invalid IP address: must not contain a port.",
-            ));
-        }
+        check_no_port(ip)?;
 
-        Self::new_with_socket_address(ip, port, (Ipv4Addr::UNSPECIFIED, 0), Some(DEFAULT_TIMEOUT))
+        Self::new_with_socket_address(ip, port, unspecified_for_host(ip), Some(DEFAULT_TIMEOUT))
             .await
     }
 
+    /// Build a new QueryClient from the given socket address, for example from
+    /// [`query_target_from_properties`](crate::server_properties::query_target_from_properties).
+    ///
+    /// The default [timeout duration](DEFAULT_TIMEOUT) is used.
+    pub async fn new_addr(addr: SocketAddr) -> io::Result<Self> {
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            Some(DEFAULT_TIMEOUT),
+        )
+        .await
+    }
+
+    /// Build a new QueryClient connected to the address advertised by a
+    /// server in its status, see [`FollowOptions`].
+    ///
+    /// Fails with an [`InvalidData`](io::ErrorKind::InvalidData) error if the
+    /// advertised IP address can't be parsed or is unspecified, unless the
+    /// options fall back to the address the status was received from.
+    pub async fn from_stat(stat: &impl Advertised, options: FollowOptions) -> io::Result<Self> {
+        let addr = advertised::follow_target(stat, &options)?;
+        Self::connect(
+            addr.to_string(),
+            unspecified_for(&addr),
+            addr,
+            options.timeout,
+        )
+        .await
+    }
+
     /// Builds a new QueryClient from the given IP address, port, socket address and optional timeout.
     ///
     /// The IP adress must not contain a port.
@@ -65,8 +108,30 @@ impl QueryClient {
         addr: impl ToSocketAddrs,
         timeout: Option<Duration>,
     ) -> io::Result<Self> {
-        let socket = UdpSocket::bind(addr).await?;
-        socket.connect((ip, port)).await?;
+        let target = format_target(ip, port);
+        match zone::scoped_socket_addr(ip, port) {
+            Ok(Some(server)) => Self::connect(target, addr, server, timeout).await,
+            Ok(None) => Self::connect(target, addr, (ip, port), timeout).await,
+            Err(e) => Err(ClientError::wrap(e, "connect", &target, None)),
+        }
+    }
+
+    /// Build a new QueryClient bound to the given address and connected to
+    /// the server, described by the target in errors.
+    async fn connect(
+        target: String,
+        addr: impl ToSocketAddrs,
+        server: impl ToSocketAddrs,
+        timeout: Option<Duration>,
+    ) -> io::Result<Self> {
+        let connect = async {
+            let socket = UdpSocket::bind(addr).await?;
+            socket.connect(server).await?;
+            Ok(socket)
+        };
+        let socket = connect
+            .await
+            .map_err(|e| ClientError::wrap(e, "connect", &target, None))?;
 
         let session_id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -76,13 +141,49 @@ impl QueryClient {
         Ok(Self {
             socket,
             session_id,
+            in_flight: Mutex::new(InFlight::new(session_id)),
             timeout,
+            target,
+            #[cfg(feature = "histogram")]
+            latency: Default::default(),
         })
     }
 
+    /// Address of the server this client is connected to, to compare with
+    /// the address it advertises, see [`verify_advertised_address`](crate::advertised::verify_advertised_address).
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Latency histograms of the successful requests of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn latency_snapshot(&self) -> crate::histogram::LatencySnapshot {
+        self.latency.snapshot()
+    }
+
+    /// Clear the latency histograms of this client.
+    #[cfg(feature = "histogram")]
+    #[cfg_attr(doc, doc(cfg(feature = "histogram")))]
+    pub fn reset_latency(&self) {
+        self.latency.reset();
+    }
+
+    /// Add a client operation and the server address to one of its errors.
+    fn context(&self, operation: &'static str, e: io::Error) -> io::Error {
+        ClientError::wrap(e, operation, &self.target, self.timeout)
+    }
+
     /// Receive a UDP packet from the client socket.
     pub async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
-        let fut = self.socket.recv(buf);
+        self.with_timeout(self.socket.recv(buf)).await
+    }
+
+    /// Wait for a receive future, failing if the client timeout elapses first.
+    async fn with_timeout(
+        &self,
+        fut: impl std::future::Future<Output = io::Result<usize>>,
+    ) -> io::Result<usize> {
         if let Some(duration) = self.timeout {
             timeout(duration, fut).await.map_err(|_| {
                 io::Error::new(io::ErrorKind::TimedOut, "UDP async recv call timed out.")
@@ -92,52 +193,898 @@ impl QueryClient {
         }
     }
 
+    /// The table of the requests awaiting their response.
+    fn in_flight(&self) -> MutexGuard<'_, InFlight> {
+        self.in_flight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Deadline of a request sent now.
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+
+    /// Send a request packet to the server.
+    async fn send(&self, packet: &impl QueryPacket) -> io::Result<usize> {
+        self.socket.send(packet.as_bytes()).await
+    }
+
     /// Send a UDP handshake packet to the client socket.
     ///
     /// Receive and parse the response into a Query token, valid up to 30 seconds.
     pub async fn handshake(&self) -> io::Result<Token> {
-        let handshake = packets::Handshake::new(self.session_id);
-        self.socket.send(&handshake).await?;
-
-        let mut buf = [0; Token::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
-
-        Ok(Token::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        ))
+        self.handshake_future().await
     }
 
     /// Request and wait for a basic status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub async fn basic_stat(&self, token: Token) -> std::io::Result<BasicStat> {
-        let request = packets::BasicStat::new(self.session_id, token.0);
-        self.socket.send(&request).await?;
-
-        let mut buf = vec![0; BasicStat::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
+        self.basic_stat_future(token).await
+    }
 
-        BasicStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
-        )
+    /// Like [`basic_stat`](Self::basic_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub async fn basic_stat_into(
+        &self,
+        token: Token,
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<BasicStat> {
+        self.basic_stat_request(token, Buffer::Borrowed(buf)).await
     }
 
     /// Request and wait for a full status packet on the client socket.
     ///
     /// If the token is no longer valid, no packet is received and an error is returned.
     pub async fn full_stat(&self, token: Token) -> std::io::Result<FullStat> {
-        let request = packets::FullStat::new(self.session_id, token.0);
-        self.socket.send(&request).await?;
+        self.full_stat_future(token).await
+    }
 
-        let mut buf = vec![0; FullStat::RESPONSE_SIZE];
-        let received = self.recv(&mut buf).await?;
+    /// Like [`full_stat`](Self::full_stat), but receive the response in
+    /// the given buffer, to reuse its allocation across calls. The buffer is
+    /// cleared first, and contains the raw response afterwards.
+    pub async fn full_stat_into(
+        &self,
+        token: Token,
+        buf: &mut Vec<u8>,
+    ) -> std::io::Result<FullStat> {
+        self.full_stat_request(token, Buffer::Borrowed(buf)).await
+    }
 
-        FullStat::from_payload(
-            buf.get(RESPONSE_HEADER_SIZE..received)
-                .ok_or_else(not_enough_data)?,
+    /// Request and wait for the full status of a Bedrock server, with its
+    /// Bedrock keys.
+    ///
+    /// If the token is no longer valid, no packet is received and an error is returned.
+    pub async fn bedrock_full_stat(&self, token: Token) -> io::Result<BedrockFullStat> {
+        QueryFuture::new(
+            self,
+            "full_stat",
+            |session_id| packets::FullStat::new(session_id, token.0),
+            Buffer::Owned(Vec::new()),
+            FullStat::RESPONSE_SIZE,
+            BedrockFullStat::from_payload,
         )
+        .await
+    }
+
+    /// A handshake with the server, driven by polling. See [`QueryFuture`].
+    pub fn handshake_future(&self) -> QueryFuture<'_, Token> {
+        QueryFuture::new(
+            self,
+            "handshake",
+            packets::Handshake::new,
+            Buffer::Owned(Vec::new()),
+            Token::RESPONSE_SIZE,
+            Token::try_from_payload,
+        )
+    }
+
+    /// A basic status request, driven by polling. See [`QueryFuture`].
+    pub fn basic_stat_future(&self, token: Token) -> QueryFuture<'_, BasicStat> {
+        self.basic_stat_request(token, Buffer::Owned(Vec::new()))
+    }
+
+    /// A full status request, driven by polling. See [`QueryFuture`].
+    pub fn full_stat_future(&self, token: Token) -> QueryFuture<'_, FullStat> {
+        self.full_stat_request(token, Buffer::Owned(Vec::new()))
+    }
+
+    fn basic_stat_request<'a>(
+        &'a self,
+        token: Token,
+        buf: Buffer<'a>,
+    ) -> QueryFuture<'a, BasicStat> {
+        QueryFuture::new(
+            self,
+            "basic_stat",
+            |session_id| packets::BasicStat::new(session_id, token.0),
+            buf,
+            BasicStat::RESPONSE_SIZE,
+            BasicStat::from_payload,
+        )
+    }
+
+    fn full_stat_request<'a>(&'a self, token: Token, buf: Buffer<'a>) -> QueryFuture<'a, FullStat> {
+        QueryFuture::new(
+            self,
+            "full_stat",
+            |session_id| packets::FullStat::new(session_id, token.0),
+            buf,
+            FullStat::RESPONSE_SIZE,
+            FullStat::from_payload,
+        )
+    }
+
+    /// Get the basic status of the server with the token cached in `tokens`,
+    /// after a new handshake if the token is expected to have expired.
+    ///
+    /// If the cached token is rejected, the rotation is recorded, and the
+    /// request sent again with a new token. See [`token_cache`](crate::token_cache).
+    pub async fn basic_stat_cached(&self, tokens: &mut TokenHandle) -> io::Result<BasicStat> {
+        self.stat_cached(tokens, Self::basic_stat_future).await
+    }
+
+    /// Get the full status of the server with the token cached in `tokens`,
+    /// like [`basic_stat_cached`](Self::basic_stat_cached).
+    pub async fn full_stat_cached(&self, tokens: &mut TokenHandle) -> io::Result<FullStat> {
+        self.stat_cached(tokens, Self::full_stat_future).await
+    }
+
+    /// Send a status request with the cached token, recording the rotations
+    /// of the server tokens.
+    async fn stat_cached<'a, T>(
+        &'a self,
+        tokens: &mut TokenHandle,
+        stat: impl Fn(&'a Self, Token) -> QueryFuture<'a, T>,
+    ) -> io::Result<T> {
+        let now = tokens.now();
+        let token = match tokens.token(now) {
+            Some(token) => match stat(self, token).await {
+                Ok(res) => {
+                    tokens.record_success(now);
+                    return Ok(res);
+                }
+                Err(e) if is_timeout(&e) => {
+                    // Only a rejection if the server still answers handshakes
+                    let sent = tokens.now();
+                    let token = self.handshake().await?;
+                    tokens.record_rejection(now);
+                    tokens.set(token, sent);
+                    token
+                }
+                Err(e) => return Err(e),
+            },
+            None => {
+                let token = self.handshake().await?;
+                tokens.set(token, now);
+                token
+            }
+        };
+
+        let sent = tokens.now();
+        let res = stat(self, token).await?;
+        tokens.record_success(sent);
+        Ok(res)
+    }
+
+    /// Send a status request with arbitrary bytes after the token, built with
+    /// [`StatRequest::with_payload`](packets::StatRequest::with_payload), and
+    /// return the raw response, split into its header and its payload.
+    ///
+    /// This is a diagnostic API: the response is neither checked nor parsed,
+    /// and servers usually ignore non-standard requests, which then time out.
+    /// Like the other requests, it has its own session ID, and can be sent
+    /// while other requests of the client are in flight.
+    pub async fn send_custom_stat(
+        &self,
+        token: Token,
+        extra: &[u8],
+    ) -> io::Result<(packets::ResponseHeader, Vec<u8>)> {
+        let mut buf = Vec::new();
+        QueryFuture::new(
+            self,
+            "custom_stat",
+            |session_id| packets::StatRequest::with_payload(session_id, token.0, extra),
+            Buffer::Borrowed(&mut buf),
+            FullStat::RESPONSE_SIZE,
+            |_| Ok(()),
+        )
+        .await?;
+
+        let (header, payload) = packets::ResponseHeader::parse(&buf)
+            .ok_or_else(not_enough_data)
+            .map_err(|e| self.context("custom_stat", e))?;
+        Ok((header, payload.to_vec()))
+    }
+}
+
+/// A request awaiting its response in the [in-flight table](InFlight)
+#[derive(Debug, Default)]
+struct InFlightEntry {
+    /// Response received by another request
+    response: Option<Vec<u8>>,
+    /// Waker of the task awaiting the response
+    waker: Option<Waker>,
+    /// Time after which responses are dropped, and the entry purged
+    deadline: Option<Instant>,
+}
+
+impl InFlightEntry {
+    /// Whether the deadline of the request has passed.
+    fn is_expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+}
+
+/// Requests of a client awaiting their response, by session ID.
+///
+/// Every request reads from the socket while it waits: a datagram for
+/// another session is stored in the entry of that session, and its task
+/// woken. Only the last task polling the socket is woken by it, so when a
+/// request leaves the table, the other requests are woken to poll it again.
+#[derive(Debug)]
+struct InFlight {
+    next_session: u32,
+    entries: HashMap<u32, InFlightEntry>,
+}
+
+impl InFlight {
+    fn new(seed: u32) -> Self {
+        Self {
+            next_session: seed & SESSION_MASK,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Add a request, with a session ID unused by the other requests.
+    fn insert(&mut self, deadline: Option<Instant>) -> u32 {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| !entry.is_expired(now));
+        loop {
+            // Count in the masked bits only, carrying over the unmasked ones
+            self.next_session = (self.next_session | !SESSION_MASK).wrapping_add(1) & SESSION_MASK;
+            if !self.entries.contains_key(&self.next_session) {
+                break;
+            }
+        }
+        self.entries.insert(
+            self.next_session,
+            InFlightEntry {
+                deadline,
+                ..Default::default()
+            },
+        );
+        self.next_session
+    }
+
+    /// Hand a response to the request of its session. Responses to unknown
+    /// or expired sessions are dropped.
+    fn dispatch(&mut self, session_id: u32, response: Vec<u8>) {
+        let now = Instant::now();
+        if let Some(entry) = self.entries.get_mut(&session_id) {
+            if !entry.is_expired(now) {
+                entry.response = Some(response);
+                if let Some(waker) = entry.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    /// Remove a request, waking the others to read from the socket in its place.
+    fn remove(&mut self, session_id: u32) {
+        if self.entries.remove(&session_id).is_some() {
+            for entry in self.entries.values_mut() {
+                if let Some(waker) = entry.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// State of a [`QueryFuture`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QueryState {
+    /// The request is being sent
+    Sending,
+    /// The request was sent, and the response is awaited
+    Receiving,
+    /// The future has completed, and must not be polled again
+    Done,
+}
+
+/// Buffer the response of a [`QueryFuture`] is received in
+#[derive(Debug)]
+enum Buffer<'a> {
+    Owned(Vec<u8>),
+    Borrowed(&'a mut Vec<u8>),
+}
+
+impl Deref for Buffer<'_> {
+    type Target = Vec<u8>;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Owned(buf) => buf,
+            Self::Borrowed(buf) => buf,
+        }
+    }
+}
+
+impl DerefMut for Buffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            Self::Owned(buf) => buf,
+            Self::Borrowed(buf) => buf,
+        }
+    }
+}
+
+/// A request of a [`QueryClient`], driven by polling.
+///
+/// The async methods of the client are built on these futures, which can
+/// also be polled by hand-written state machines: the future is [`Unpin`],
+/// and [`poll_request`](Self::poll_request) polls it without pinning.
+///
+/// The future goes through the [states](QueryState) `Sending`, while the
+/// request is written to the socket, then `Receiving`, while the response is
+/// awaited, then `Done` once it returns a result. The client timeout starts
+/// once the request is sent, and is tracked with a timer polled alongside the
+/// socket, so the future must be polled from a tokio runtime.
+///
+/// Each future has its own session ID, so several futures of the same client
+/// can be polled concurrently: a future may receive the response of another,
+/// and hand it over to it.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::tokio::{QueryClient, QueryState};
+/// # use std::{future::poll_fn, task::Poll};
+/// # async fn f() -> std::io::Result<()> {
+/// let client = QueryClient::new("play.example.com").await?;
+/// let mut handshake = client.handshake_future();
+/// let token = poll_fn(|cx| {
+///     let poll = handshake.poll_request(cx);
+///     if handshake.state() == QueryState::Receiving {
+///         // The handshake was sent
+///     }
+///     poll
+/// })
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct QueryFuture<'a, T> {
+    client: &'a QueryClient,
+    operation: &'static str,
+    session_id: u32,
+    packet: RequestBytes,
+    buf: Buffer<'a>,
+    max: usize,
+    parse: fn(&[u8]) -> io::Result<T>,
+    state: QueryState,
+    timer: Option<Pin<Box<Sleep>>>,
+    #[cfg(feature = "histogram")]
+    start: Option<std::time::Instant>,
+}
+
+impl<'a, T> QueryFuture<'a, T> {
+    fn new<P: QueryPacket>(
+        client: &'a QueryClient,
+        operation: &'static str,
+        request: impl FnOnce(u32) -> P,
+        buf: Buffer<'a>,
+        max: usize,
+        parse: fn(&[u8]) -> io::Result<T>,
+    ) -> Self {
+        let session_id = client.in_flight().insert(client.deadline());
+        let packet = RequestBytes::new(request(session_id).as_bytes());
+
+        Self {
+            client,
+            operation,
+            session_id,
+            packet,
+            buf,
+            max,
+            parse,
+            state: QueryState::Sending,
+            timer: None,
+            #[cfg(feature = "histogram")]
+            start: None,
+        }
+    }
+
+    /// The current state of the request.
+    pub fn state(&self) -> QueryState {
+        self.state
+    }
+
+    /// Poll the request. Errors carry the operation and the server address,
+    /// like the errors of the async methods of the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request is polled again after returning a result.
+    pub fn poll_request(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        #[cfg(feature = "histogram")]
+        let start = *self.start.get_or_insert_with(std::time::Instant::now);
+        let res = ready!(self.poll_inner(cx));
+        self.state = QueryState::Done;
+        self.timer = None;
+        self.client.in_flight().remove(self.session_id);
+        #[cfg(feature = "histogram")]
+        self.client.latency.record(self.operation, start, &res);
+        Poll::Ready(res.map_err(|e| self.client.context(self.operation, e)))
+    }
+
+    fn poll_inner(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<T>> {
+        match self.state {
+            QueryState::Sending => {
+                // The entry may have been purged if the future was polled late
+                self.client
+                    .in_flight()
+                    .entries
+                    .entry(self.session_id)
+                    .or_default()
+                    .deadline = self.client.deadline();
+                ready!(self.client.socket.poll_send(cx, self.packet.as_slice()))?;
+                self.state = QueryState::Receiving;
+                self.timer = self.client.timeout.map(|timeout| Box::pin(sleep(timeout)));
+            }
+            QueryState::Receiving => {}
+            QueryState::Done => panic!("QueryFuture polled after completion"),
+        }
+
+        loop {
+            {
+                let mut in_flight = self.client.in_flight();
+                let entry = in_flight.entries.entry(self.session_id).or_default();
+                if let Some(response) = entry.response.take() {
+                    *self.buf = response;
+                    break;
+                }
+                entry.waker = Some(cx.waker().clone());
+            }
+
+            // Responses to other requests may be larger than ours
+            let max = self.max.max(FullStat::RESPONSE_SIZE);
+            let mut pending = false;
+            let socket = &self.client.socket;
+            let buf = &mut *self.buf;
+            let received = recv_uninit(buf, max, |spare| {
+                let mut read_buf = ReadBuf::uninit(spare);
+                match socket.poll_recv(cx, &mut read_buf) {
+                    Poll::Ready(res) => res.map(|()| read_buf.filled().len()),
+                    Poll::Pending => {
+                        pending = true;
+                        Ok(0)
+                    }
+                }
+            });
+            if pending {
+                return match &mut self.timer {
+                    Some(timer) => {
+                        ready!(timer.as_mut().poll(cx));
+                        Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "UDP async recv call timed out.",
+                        )))
+                    }
+                    None => Poll::Pending,
+                };
+            }
+            received?;
+
+            // Datagrams too short for a header fail the request receiving them
+            let Some(session_id) = buf.get(1..RESPONSE_HEADER_SIZE) else {
+                break;
+            };
+            let session_id = u32::from_be_bytes(session_id.try_into().unwrap());
+            if session_id == self.session_id {
+                break;
+            }
+            self.client
+                .in_flight()
+                .dispatch(session_id, std::mem::take(&mut *self.buf));
+        }
+
+        let buf = &mut *self.buf;
+        buf.truncate(self.max);
+        Poll::Ready((self.parse)(
+            buf.get(RESPONSE_HEADER_SIZE..)
+                .ok_or_else(not_enough_data)?,
+        ))
+    }
+}
+
+/// Bytes of the request of a [`QueryFuture`], kept inline unless the request
+/// is larger than a full status request
+#[derive(Debug)]
+enum RequestBytes {
+    Inline([u8; packets::FullStat::ENCODED_LEN], usize),
+    Heap(Vec<u8>),
+}
+
+impl RequestBytes {
+    fn new(request: &[u8]) -> Self {
+        let mut packet = [0; packets::FullStat::ENCODED_LEN];
+        match packet.get_mut(..request.len()) {
+            Some(inline) => {
+                inline.copy_from_slice(request);
+                Self::Inline(packet, request.len())
+            }
+            None => Self::Heap(request.to_vec()),
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Inline(packet, len) => &packet[..*len],
+            Self::Heap(packet) => packet,
+        }
+    }
+}
+
+impl<T> Drop for QueryFuture<'_, T> {
+    fn drop(&mut self) {
+        self.client.in_flight().remove(self.session_id);
+    }
+}
+
+impl<T> Future for QueryFuture<'_, T> {
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().poll_request(cx)
+    }
+}
+
+/// One-shot query of a server, built with chained options.
+///
+/// A client is built for each call to [`full`](Self::full), [`basic`](Self::basic)
+/// or [`ping`](Self::ping), and dropped afterwards.
+///
+/// ```rust,no_run
+/// # use minecraft_server_query::tokio::Query;
+/// # use std::time::Duration;
+/// # async fn example() -> std::io::Result<()> {
+/// let full_stat = Query::to("play.example.com")
+///     .port(25565)
+///     .timeout(Duration::from_secs(2))
+///     .retries(2)
+///     .full()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    host: String,
+    port: Option<u16>,
+    bind: Option<SocketAddr>,
+    timeout: Duration,
+    retries: u32,
+    resolver: Arc<dyn Resolve>,
+    rate_limiter: Option<RateLimiter>,
+    #[cfg(feature = "opentelemetry")]
+    telemetry: Option<crate::otel::Telemetry>,
+}
+
+impl Query {
+    /// Start a query of the given server. If no port is specified in the
+    /// address or with [`port`](Self::port), the [default port](DEFAULT_PORT) is used.
+    pub fn to(host: impl Into<String>) -> Self {
+        Self {
+            host: host.into(),
+            port: None,
+            bind: None,
+            timeout: DEFAULT_TIMEOUT,
+            retries: 0,
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+
+    /// Port of the server. The address given to [`to`](Self::to) is then used
+    /// as is, so it can be an IPv6 address.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Local address to bind the client socket to. By default, an unspecified
+    /// address of the same IP version as the resolved server address.
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind = Some(addr);
+        self
+    }
+
+    /// Timeout of each response, [`DEFAULT_TIMEOUT`] by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of times to start over from the handshake if a response times
+    /// out, none by default. Other errors are returned immediately.
+    ///
+    /// Queries failing after a retry return an [`AttemptsExhausted`] error,
+    /// with the error of every attempt.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Resolver of the host name of the server, the [system
+    /// resolver](SystemResolver) by default. It is not consulted when the
+    /// host is an IP address.
+    pub fn resolver(mut self, resolver: Arc<dyn Resolve>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Limiter every attempt of the query draws a permit from, the [global
+    /// limiter](crate::rate_limit::set_global) by default, if any.
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Record the duration, failures and spans of the query with the given
+    /// [instruments](crate::otel).
+    #[cfg(feature = "opentelemetry")]
+    #[cfg_attr(doc, doc(cfg(feature = "opentelemetry")))]
+    pub fn telemetry(mut self, telemetry: crate::otel::Telemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Get the full status of the server.
+    pub async fn full(self) -> io::Result<FullStat> {
+        self.full_timed().await.map(|(stat, _)| stat)
+    }
+
+    /// Get the basic status of the server.
+    pub async fn basic(self) -> io::Result<BasicStat> {
+        self.basic_timed().await.map(|(stat, _)| stat)
+    }
+
+    /// Like [`full`](Self::full), but also return the duration of every phase of the query.
+    pub async fn full_timed(self) -> io::Result<(FullStat, Timings)> {
+        let (res, timings) = self.full_recorded().await;
+        res.map(|(stat, _)| (stat, timings))
+    }
+
+    /// Like [`basic`](Self::basic), but also return the duration of every phase of the query.
+    pub async fn basic_timed(self) -> io::Result<(BasicStat, Timings)> {
+        let (res, timings) = self.basic_recorded().await;
+        res.map(|(stat, _)| (stat, timings))
+    }
+
+    /// Like [`full`](Self::full), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub async fn full_attempts(self) -> io::Result<(FullStat, Vec<AttemptError>)> {
+        self.full_recorded().await.0
+    }
+
+    /// Like [`basic`](Self::basic), but also return the attempts which timed
+    /// out before the query succeeded.
+    pub async fn basic_attempts(self) -> io::Result<(BasicStat, Vec<AttemptError>)> {
+        self.basic_recorded().await.0
+    }
+
+    /// Get the full status of the server, or its basic status if the full
+    /// status request still times out on the last attempt. See
+    /// [`blocking::Query::full_or_basic`].
+    pub async fn full_or_basic(self) -> io::Result<AnyStat> {
+        self.full_or_basic_attempts().await.map(|(stat, _)| stat)
+    }
+
+    /// Like [`full_or_basic`](Self::full_or_basic), but also return the
+    /// attempts which timed out before the query succeeded. When the query
+    /// fell back to the basic status, the last one is the full status
+    /// request which timed out.
+    pub async fn full_or_basic_attempts(self) -> io::Result<(AnyStat, Vec<AttemptError>)> {
+        let operation = self.operation("full_or_basic");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, last| async move {
+                let start = Instant::now();
+                match client.full_stat(token).await {
+                    Ok(stat) => Ok((AnyStat::Full(stat), None)),
+                    Err(error) if last && is_timeout(&error) => {
+                        let stat = client.basic_stat(token).await?;
+                        let fallback = AttemptError::new(&client.target, error, start);
+                        Ok((AnyStat::Basic(stat), Some(fallback)))
+                    }
+                    Err(error) => Err(error),
+                }
+            })
+            .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        res.map(|((stat, fallback), mut attempts)| {
+            attempts.extend(fallback);
+            (stat, attempts)
+        })
+    }
+
+    /// Measure the round-trip time of a handshake with the server.
+    pub async fn ping(self) -> io::Result<Duration> {
+        let operation = self.operation("ping");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry(client, move || async move {
+                let start = Instant::now();
+                client.handshake().await?;
+                Ok(start.elapsed())
+            })
+            .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        res
+    }
+
+    /// Get the full status of the server, recording the operation. Returns
+    /// the attempts which failed before, and the duration of every phase.
+    async fn full_recorded(&self) -> (io::Result<(FullStat, Vec<AttemptError>)>, Timings) {
+        let operation = self.operation("full_stat");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, _| client.full_stat(token))
+                .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        (res, timings)
+    }
+
+    /// Like [`full_recorded`](Self::full_recorded), for the basic status.
+    async fn basic_recorded(&self) -> (io::Result<(BasicStat, Vec<AttemptError>)>, Timings) {
+        let operation = self.operation("basic_stat");
+        let mut timings = Timings::default();
+        let res = async {
+            let client = &self.client(&mut timings).await?;
+            self.retry_timed(client, &mut timings, |token, _| client.basic_stat(token))
+                .await
+        }
+        .await;
+        operation.finish(&res, &timings);
+        (res, timings)
+    }
+
+    /// Start recording an operation of the query, if it has telemetry.
+    fn operation(&self, name: &'static str) -> Operation {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(telemetry) = &self.telemetry {
+            let (host, port) = match self.port {
+                Some(port) => (self.host.as_str(), port),
+                None => split_address(&self.host).unwrap_or((&self.host, DEFAULT_PORT)),
+            };
+            return Operation::start(telemetry, name, host, Some(port));
+        }
+        #[cfg(not(feature = "opentelemetry"))]
+        let _ = name;
+        Operation::none()
+    }
+
+    /// Resolve the server address and build the client of the query,
+    /// recording the duration of both phases.
+    async fn client(&self, timings: &mut Timings) -> io::Result<QueryClient> {
+        let (host, port) = match self.port {
+            Some(port) => (self.host.as_str(), port),
+            None => split_address(&self.host)?,
+        };
+        let target = format_target(host, port);
+
+        let start = Instant::now();
+        let server = resolve::first_address_async(&*self.resolver, host, port)
+            .await
+            .map_err(|e| ClientError::wrap(e, "resolve", &target, None))?;
+        timings.resolve = start.elapsed();
+
+        let start = Instant::now();
+        let bind = self.bind.unwrap_or_else(|| unspecified_for(&server));
+        let client = QueryClient::connect(target, bind, server, Some(self.timeout)).await?;
+        timings.connect = start.elapsed();
+        Ok(client)
+    }
+
+    /// Run a request of the client, retrying on timeouts.
+    async fn retry<T, F>(
+        &self,
+        client: &QueryClient,
+        mut request: impl FnMut() -> F,
+    ) -> io::Result<T>
+    where
+        F: std::future::Future<Output = io::Result<T>>,
+    {
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let mut attempts = Attempts::new(self.retries);
+        loop {
+            if let Some(limiter) = &limiter {
+                limiter.acquire_async().await;
+            }
+            let start = Instant::now();
+            if let Some(res) = attempts.check(&client.target, start, request().await) {
+                return res;
+            }
+        }
+    }
+
+    /// Run handshakes and status requests until one succeeds, retrying on
+    /// timeouts and recording every attempt. The status request is told
+    /// whether it is the last attempt. Returns the attempts which failed
+    /// before.
+    async fn retry_timed<T, F>(
+        &self,
+        client: &QueryClient,
+        timings: &mut Timings,
+        mut stat: impl FnMut(Token, bool) -> F,
+    ) -> io::Result<(T, Vec<AttemptError>)>
+    where
+        F: std::future::Future<Output = io::Result<T>>,
+    {
+        let limiter = self.rate_limiter.clone().or_else(rate_limit::global);
+        let mut attempts = Attempts::new(self.retries);
+        loop {
+            if let Some(limiter) = &limiter {
+                limiter.acquire_async().await;
+            }
+            let start = Instant::now();
+            let token = client.handshake().await;
+            timings.handshakes.push(start.elapsed());
+
+            let res = match token {
+                Ok(token) => {
+                    let start = Instant::now();
+                    let res = stat(token, attempts.is_last()).await;
+                    timings.stats.push(start.elapsed());
+                    res
+                }
+                Err(e) => Err(e),
+            };
+            if let Some(res) = attempts.check(&client.target, start, res) {
+                return res.map(|stat| (stat, attempts.into_failed()));
+            }
+        }
+    }
+}
+
+impl From<ConnectionString> for Query {
+    /// Query the server of a connection string, with its options.
+    fn from(target: ConnectionString) -> Self {
+        Self {
+            port: Some(target.port_or_default()),
+            host: target.host,
+            bind: target.bind,
+            timeout: target.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            retries: target.retries.unwrap_or(0),
+            resolver: Arc::new(SystemResolver),
+            rate_limiter: None,
+            #[cfg(feature = "opentelemetry")]
+            telemetry: None,
+        }
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = ConnectionStringError;
+
+    /// Parse a [connection string](crate::connection_string), or a plain
+    /// `host[:port]` target.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<ConnectionString>().map(Self::from)
     }
 }
 
@@ -146,25 +1093,102 @@ impl QueryClient {
 /// Send a handshake first, and if a token is successfully received and parsed,
 /// request a full status packet.
 pub async fn query(ip: &str) -> io::Result<FullStat> {
+    if let Some(limiter) = &rate_limit::global() {
+        limiter.acquire_async().await;
+    }
     let client = QueryClient::new(ip).await?;
     let token = client.handshake().await?;
 
     client.full_stat(token).await
 }
 
+/// Convenience function to get a full status packet, with the duration of
+/// every phase of the query.
+///
+/// Like [`query`], built with [`Query`]: see [`Query::full_timed`].
+pub async fn query_timed(ip: &str) -> io::Result<(FullStat, Timings)> {
+    Query::to(ip).full_timed().await
+}
+
+/// Measure the packet loss and the jitter of the connection to a server, by
+/// sending a series of handshakes. See [`quality`](crate::quality).
+///
+/// This takes at most `count × interval + timeout`, and returns early once
+/// every probe is answered.
+pub async fn network_quality(ip: &str, options: ProbeOptions) -> io::Result<QualityReport> {
+    let client = QueryClient::new(ip).await?;
+    let wrap = |e| ClientError::wrap(e, "network_quality", &client.target, Some(options.timeout));
+
+    let mut probes = Probes::new(client.session_id, options, Instant::now());
+    let mut buf = [0; Token::RESPONSE_SIZE];
+    loop {
+        while let Some(probe) = probes.poll_send(Instant::now()) {
+            client.send(&probe).await.map_err(wrap)?;
+        }
+        let Some(wakeup) = probes.next_wakeup(Instant::now()) else {
+            break;
+        };
+        match timeout_at(wakeup.into(), client.socket.recv(&mut buf)).await {
+            Ok(Ok(received)) => probes.receive(&buf[..received], Instant::now()),
+            // Lost probes, or ICMP errors for probes sent to a closed port
+            Err(_) => {}
+            Ok(Err(e)) if e.kind() == io::ErrorKind::ConnectionRefused => {}
+            Ok(Err(e)) => return Err(wrap(e)),
+        }
+    }
+    Ok(probes.report())
+}
+
+/// Convenience function to get the most detailed status a server answers with.
+///
+/// Like [`query`], but if the full status request times out, request a basic
+/// status instead, with the same token. Some old modded servers answer
+/// handshakes and basic status requests, but ignore full status requests.
+///
+/// Built with [`Query`]: see [`Query::full_or_basic`].
+pub async fn query_lenient(ip: &str) -> io::Result<AnyStat> {
+    Query::to(ip).full_or_basic().await
+}
+
+/// Convenience function to get the full status of a Bedrock server with
+/// query enabled, with its Bedrock keys.
+///
+/// Like [`query`], but if no port is specified in the IP address, the
+/// [default Bedrock port](DEFAULT_BEDROCK_PORT) is used.
+pub async fn query_bedrock(ip: &str) -> io::Result<BedrockFullStat> {
+    if let Some(limiter) = &rate_limit::global() {
+        limiter.acquire_async().await;
+    }
+    let (ip, port) = split_address_or(ip, DEFAULT_BEDROCK_PORT)?;
+    let client = QueryClient::new_with_port(ip, port).await?;
+    let token = client.handshake().await?;
+
+    client.bedrock_full_stat(token).await
+}
+
 #[cfg(test)]
 mod tests {
-    const TEST_IP: &str = "lotr.g.akliz.net:25565";
+    use std::time::{Duration, Instant};
+
+    use crate::packets::{self, PacketType};
+    use crate::quality::ProbeOptions;
+    use crate::testing::{Faults, MockQueryServer};
 
     #[tokio::test]
     async fn test_handshake() {
-        let client = super::QueryClient::new(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
         client.handshake().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_basic_stat() {
-        let client = super::QueryClient::new(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
         let token = client.handshake().await.unwrap();
 
         let basic_stat = client.basic_stat(token).await.unwrap();
@@ -173,11 +1197,665 @@ mod tests {
 
     #[tokio::test]
     async fn test_full_stat() {
-        let full_stat = super::query(TEST_IP).await.unwrap();
+        let server = MockQueryServer::new().unwrap();
+        let full_stat = super::query(&server.addr().to_string()).await.unwrap();
 
         assert_eq!(full_stat.hostport, crate::DEFAULT_PORT);
         assert_eq!(full_stat.numplayers as usize, full_stat.player_list.len());
-        assert_eq!(full_stat.version, "1.7.10");
+        assert_eq!(full_stat.version, server.full_stat().version);
+        assert_eq!(full_stat.game_id, "MINECRAFT");
+    }
+
+    #[tokio::test]
+    async fn test_stat_into() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
+        let token = client.handshake().await.unwrap();
+
+        let mut buf = Vec::new();
+        let basic_stat = client.basic_stat_into(token, &mut buf).await.unwrap();
+        assert_eq!(basic_stat, client.basic_stat(token).await.unwrap());
+        let full_stat = client.full_stat_into(token, &mut buf).await.unwrap();
+        assert_eq!(full_stat, client.full_stat(token).await.unwrap());
+        assert_eq!(&buf[crate::RESPONSE_HEADER_SIZE..], full_stat.to_payload());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
+
+        let err = client.handshake().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(
+            err.to_string(),
+            format!("handshake to {} timed out after 500ms", server.addr())
+        );
+        client.handshake().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_builder() {
+        let server = MockQueryServer::new().unwrap();
+        let stat = super::Query::to(server.addr().to_string())
+            .full()
+            .await
+            .unwrap();
+        assert_eq!(stat, server.full_stat());
+        let stat = super::Query::to("127.0.0.1")
+            .port(server.addr().port())
+            .basic()
+            .await
+            .unwrap();
+        assert_eq!(stat, crate::BasicStat::from(&server.full_stat()));
+        super::Query::to(server.addr().to_string())
+            .ping()
+            .await
+            .unwrap();
+        assert!(super::Query::to("127.0.0.1:invalid").full().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_resolver() {
+        let server = MockQueryServer::new().unwrap();
+        let stub = crate::resolve::tests::StubResolver::new();
+        stub.set("lobby.mc.internal", &[server.addr()]);
+        let query = |host: &str| super::Query::to(host).port(25565).resolver(stub.clone());
+
+        let stat = query("lobby.mc.internal").full().await.unwrap();
+        assert_eq!(stat, server.full_stat());
+        super::Query::to(server.addr().to_string())
+            .resolver(stub.clone())
+            .ping()
+            .await
+            .unwrap();
+        let err = query("unknown.mc.internal").ping().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(stub.calls(), ["lobby.mc.internal", "unknown.mc.internal"]);
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_retries() {
+        let server = MockQueryServer::new().unwrap();
+        let query = super::Query::to(server.addr().to_string()).timeout(Duration::from_millis(50));
+        let drop_next = |drop_next| {
+            server.set_faults(
+                PacketType::Stat,
+                Faults {
+                    drop_next,
+                    ..Faults::default()
+                },
+            )
+        };
+
+        drop_next(1);
+        assert!(query.clone().full().await.is_err());
+        drop_next(2);
+        assert_eq!(
+            query.clone().retries(2).full().await.unwrap(),
+            server.full_stat()
+        );
+        assert_eq!(server.dropped(PacketType::Stat), 3);
+        assert_eq!(
+            server
+                .received()
+                .iter()
+                .filter(|p| p.data.len() == packets::Handshake::ENCODED_LEN)
+                .count(),
+            4
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_attempts() {
+        let server = MockQueryServer::new().unwrap();
+        let query = super::Query::to(server.addr().to_string())
+            .timeout(Duration::from_millis(50))
+            .retries(1);
+        let faults = |drop_next| {
+            server.set_faults(
+                PacketType::Handshake,
+                Faults {
+                    drop_next,
+                    ..Faults::default()
+                },
+            )
+        };
+
+        faults(2);
+        let err = query.clone().ping().await.unwrap_err();
+        assert!(crate::is_timeout(&err));
+        let exhausted = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::AttemptsExhausted>())
+            .unwrap();
+        assert_eq!(exhausted.attempts().len(), 2);
+        assert!(err
+            .to_string()
+            .starts_with("2 attempts failed: #1 handshake to "));
+
+        faults(1);
+        let (stat, attempts) = query.clone().basic_attempts().await.unwrap();
+        assert_eq!(stat, crate::BasicStat::from(&server.full_stat()));
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].target, server.addr().to_string());
+        assert!(crate::is_timeout(&attempts[0].error));
+        let (_, attempts) = query.full_attempts().await.unwrap();
+        assert!(attempts.is_empty());
+    }
+
+    #[cfg(feature = "histogram")]
+    #[tokio::test]
+    async fn test_latency_snapshot() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(20);
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                delay: Some(delay),
+                ..Faults::default()
+            },
+        );
+
+        let client = super::QueryClient::new_addr(server.addr()).await.unwrap();
+        for _ in 0..3 {
+            let token = client.handshake().await.unwrap();
+            client.full_stat(token).await.unwrap();
+        }
+        // Failed requests are not recorded
+        client.basic_stat(crate::Token(0)).await.unwrap_err();
+
+        let latency = client.latency_snapshot();
+        assert_eq!(latency.handshake.count, 3);
+        assert!(latency.handshake.p50().unwrap() >= delay);
+        assert_eq!(latency.stat.count, 3);
+        assert!(latency.stat.p99().unwrap() < latency.handshake.p50().unwrap());
+
+        client.reset_latency();
+        assert_eq!(client.latency_snapshot().handshake.count, 0);
+        assert_eq!(client.latency_snapshot().stat.p50(), None);
+    }
+
+    #[tokio::test]
+    async fn test_network_quality() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(30);
+        server.set_faults(
+            PacketType::Handshake,
+            Faults {
+                drop_next: 2,
+                delay: Some(delay),
+                ..Faults::default()
+            },
+        );
+        let options = ProbeOptions {
+            count: 5,
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_millis(300),
+        };
+
+        let start = Instant::now();
+        let report = super::network_quality(&server.addr().to_string(), options)
+            .await
+            .unwrap();
+        assert!(start.elapsed() <= options.interval * 5 + options.timeout + delay);
+        assert_eq!(report.sent, 5);
+        assert_eq!(report.received, 3);
+        assert_eq!(report.loss_percent(), 40.0);
+        let (min, avg, max) = (
+            report.min.unwrap(),
+            report.avg.unwrap(),
+            report.max.unwrap(),
+        );
+        assert!(delay <= min && min <= avg && avg <= max && max < options.timeout);
+        assert!(report.jitter.unwrap() <= max - min);
+
+        // Returns once every probe is answered
+        server.set_faults(PacketType::Handshake, Faults::default());
+        let start = Instant::now();
+        let report = super::network_quality(&server.addr().to_string(), options)
+            .await
+            .unwrap();
+        assert!(start.elapsed() < options.interval * 5 + options.timeout);
+        assert_eq!(report.received, 5);
+        assert_eq!(report.loss_percent(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_query_timed() {
+        let server = MockQueryServer::new().unwrap();
+        let delay = Duration::from_millis(100);
+        let delayed = |packet_type| {
+            server.set_faults(PacketType::Handshake, Faults::default());
+            server.set_faults(PacketType::Stat, Faults::default());
+            server.set_faults(
+                packet_type,
+                Faults {
+                    delay: Some(delay),
+                    ..Faults::default()
+                },
+            );
+        };
+
+        delayed(PacketType::Handshake);
+        let (stat, timings) = super::query_timed(&server.addr().to_string())
+            .await
+            .unwrap();
+        assert_eq!(stat, server.full_stat());
+        assert!(timings.handshakes[0] >= delay);
+        assert!(timings.stats[0] < delay);
+
+        delayed(PacketType::Stat);
+        let (_, timings) = super::Query::to(server.addr().to_string())
+            .basic_timed()
+            .await
+            .unwrap();
+        assert!(timings.handshakes[0] < delay);
+        assert!(timings.stats[0] >= delay);
+        assert!(timings.total() >= timings.resolve + timings.connect + delay);
+
+        // Every attempt is recorded
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let timeout = Duration::from_millis(50);
+        let (_, timings) = super::Query::to(server.addr().to_string())
+            .timeout(timeout)
+            .retries(1)
+            .full_timed()
+            .await
+            .unwrap();
+        assert_eq!(timings.handshakes.len(), 2);
+        assert_eq!(timings.stats.len(), 2);
+        assert!(timings.stats[0] >= timeout);
+        assert!(timings.stats[1] < timeout);
+    }
+
+    #[tokio::test]
+    async fn test_custom_stat() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            "127.0.0.1:0",
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+        let token = client.handshake().await.unwrap();
+
+        // Padded like a full status request
+        let (header, payload) = client.send_custom_stat(token, &[0; 4]).await.unwrap();
+        assert_eq!(header.packet_type, PacketType::Stat);
+        assert_eq!(
+            crate::FullStat::from_payload(&payload).unwrap(),
+            server.full_stat()
+        );
+
+        // Requests of other lengths are ignored
+        let err = client.send_custom_stat(token, b"extra").await.unwrap_err();
+        assert!(crate::is_timeout(&err));
+        assert_eq!(server.received().pop().unwrap().data.len(), 16);
+    }
+
+    /// Poll a request with a waker which does nothing, until it completes.
+    async fn poll_manually<T>(mut request: super::QueryFuture<'_, T>) -> std::io::Result<T> {
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        loop {
+            if let std::task::Poll::Ready(res) = request.poll_request(&mut cx) {
+                assert_eq!(request.state(), super::QueryState::Done);
+                return res;
+            }
+            assert_ne!(request.state(), super::QueryState::Done);
+            ::tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_poll_requests() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            "127.0.0.1:0",
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+
+        let request = client.handshake_future();
+        assert_eq!(request.state(), super::QueryState::Sending);
+        let token = poll_manually(request).await.unwrap();
+        let stat = poll_manually(client.basic_stat_future(token))
+            .await
+            .unwrap();
+        assert_eq!(stat, crate::BasicStat::from(&server.full_stat()));
+        let stat = poll_manually(client.full_stat_future(token)).await.unwrap();
+        assert_eq!(stat, server.full_stat());
+
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_next: 1,
+                ..Faults::default()
+            },
+        );
+        let err = poll_manually(client.full_stat_future(token))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(
+            err.to_string(),
+            format!("full_stat to {} timed out after 50ms", server.addr())
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_requests() {
+        let server = MockQueryServer::new().unwrap();
+        let client = std::sync::Arc::new(
+            super::QueryClient::new(&server.addr().to_string())
+                .await
+                .unwrap(),
+        );
+        let token = client.handshake().await.unwrap();
+        server.clear_received();
+
+        let tasks: Vec<_> = (0..20)
+            .map(|_| {
+                let client = client.clone();
+                tokio::spawn(async move { client.full_stat(token).await })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap(), server.full_stat());
+        }
+
+        // Every request had its own session ID
+        let sessions: std::collections::HashSet<_> = server
+            .received()
+            .iter()
+            .map(|packet| packet.request.unwrap().session_id())
+            .collect();
+        assert_eq!(sessions.len(), 20);
+        assert!(client.in_flight().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_custom_stat() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
+        let token = client.handshake().await.unwrap();
+
+        // Custom requests go through the in-flight table like the others
+        let (custom, full, basic) = tokio::join!(
+            client.send_custom_stat(token, &[0; 4]),
+            client.full_stat_future(token),
+            client.basic_stat(token)
+        );
+        let (header, payload) = custom.unwrap();
+        assert_eq!(header.packet_type, PacketType::Stat);
+        assert_eq!(
+            crate::FullStat::from_payload(&payload).unwrap(),
+            server.full_stat()
+        );
+        assert_eq!(full.unwrap(), server.full_stat());
+        assert_eq!(basic.unwrap(), crate::BasicStat::from(&server.full_stat()));
+        assert!(client.in_flight().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_responses() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
+        let token = client.handshake().await.unwrap();
+
+        // The second request reads the response of the first, and hands it over
+        let (first, second, handshake) = tokio::join!(
+            client.full_stat(token),
+            client.basic_stat(token),
+            client.handshake()
+        );
+        assert_eq!(first.unwrap(), server.full_stat());
+        assert_eq!(second.unwrap(), crate::BasicStat::from(&server.full_stat()));
+        // Handshakes renew the token of the client address
+        let token = handshake.unwrap();
+
+        // A dropped request leaves the table, and its late response is ignored
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                delay: Some(Duration::from_millis(50)),
+                ..Faults::default()
+            },
+        );
+        let mut dropped = client.full_stat_future(token);
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        assert!(dropped.poll_request(&mut cx).is_pending());
+        drop(dropped);
+        server.set_faults(PacketType::Stat, Faults::default());
+        assert!(client.in_flight().entries.is_empty());
+        assert_eq!(
+            client.basic_stat(token).await.unwrap().hostport,
+            crate::DEFAULT_PORT
+        );
+    }
+
+    #[test]
+    fn test_in_flight_sessions() {
+        let mut in_flight = super::InFlight::new(0xFFFF_FFFE);
+        let sessions: Vec<u32> = (0..4).map(|_| in_flight.insert(None)).collect();
+        assert_eq!(sessions, [0x0F0F_0F0F, 0, 1, 2]);
+
+        // Session IDs in use are skipped
+        in_flight.next_session = 0x0F0F_0F0F;
+        assert_eq!(in_flight.insert(None), 3);
+
+        // Expired requests are purged, and their responses dropped
+        let expired = Instant::now() - Duration::from_secs(1);
+        in_flight.entries.get_mut(&1).unwrap().deadline = Some(expired);
+        in_flight.dispatch(1, vec![0]);
+        assert_eq!(in_flight.entries[&1].response, None);
+        in_flight.dispatch(2, vec![0]);
+        assert_eq!(in_flight.entries[&2].response, Some(vec![0]));
+        in_flight.dispatch(0x0101_0101, vec![0]);
+        in_flight.insert(None);
+        assert!(!in_flight.entries.contains_key(&1));
+    }
+
+    #[tokio::test]
+    async fn test_error_context() {
+        let server = MockQueryServer::new().unwrap();
+        let client = super::QueryClient::new(&server.addr().to_string())
+            .await
+            .unwrap();
+        let token = client.handshake().await.unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                truncate: Some(40),
+                ..Faults::default()
+            },
+        );
+
+        let err = client.full_stat(token).await.unwrap_err();
+        let context = err
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::ClientError>())
+            .unwrap();
+        assert_eq!(context.operation(), "full_stat");
+        assert_eq!(context.target(), server.addr().to_string());
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "full_stat to {} failed: Failed to parse full stat payload due to missing data.",
+                server.addr()
+            )
+        );
+
+        // The local address is not available
+        let err = super::QueryClient::new_with_socket_address("::1", 25565, "192.0.2.1:0", None)
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("connect to [::1]:25565 failed: "));
+    }
+
+    #[tokio::test]
+    async fn test_query_bedrock() {
+        use crate::gs4::{tests::*, BedrockFullStat, Protocol};
+
+        let addr = spawn_stub_for(Protocol::MINECRAFT, BEDROCK_PAYLOAD);
+        let stat = super::query_bedrock(&addr.to_string()).await.unwrap();
+        assert_eq!(
+            stat,
+            BedrockFullStat::from_payload(BEDROCK_PAYLOAD).unwrap()
+        );
+        assert_eq!(stat.whitelist(), Some(true));
+    }
+
+    #[tokio::test]
+    async fn test_query_lenient() {
+        let server = MockQueryServer::new().unwrap();
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                ignore_full_stat: true,
+                ..Faults::default()
+            },
+        );
+
+        let stat = super::query_lenient(&server.addr().to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            stat,
+            crate::AnyStat::Basic(crate::BasicStat::from(&server.full_stat()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_rate_limiter() {
+        let server = MockQueryServer::new().unwrap();
+        let limiter = crate::rate_limit::RateLimiter::new(crate::rate_limit::RateLimit {
+            rate: 5.0,
+            burst: 2.0,
+        });
+        let query = super::Query::to(server.addr().to_string()).rate_limiter(limiter);
+
+        // Four concurrent queries, two of which wait for the bucket to refill
+        let start = Instant::now();
+        let (a, b, c, d) = tokio::join!(
+            query.clone().full(),
+            query.clone().basic(),
+            query.clone().ping(),
+            query.full(),
+        );
+        assert!(start.elapsed() >= Duration::from_millis(350));
+        assert!(a.is_ok() && b.is_ok() && c.is_ok() && d.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_query_builder_fallback() {
+        let server = MockQueryServer::new().unwrap();
+        let basic = crate::BasicStat::from(&server.full_stat());
+        server.set_faults(
+            PacketType::Stat,
+            Faults {
+                drop_larger_than: Some(crate::RESPONSE_HEADER_SIZE + basic.to_payload().len()),
+                ..Faults::default()
+            },
+        );
+
+        let (stat, attempts) = super::Query::to(server.addr().to_string())
+            .timeout(Duration::from_millis(100))
+            .full_or_basic_attempts()
+            .await
+            .unwrap();
+        assert_eq!(stat, crate::AnyStat::Basic(basic));
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].error.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(server.dropped(PacketType::Stat), 1);
+    }
+
+    #[tokio::test]
+    async fn test_token_rotation_prediction() {
+        use crate::testing::{ManualClock, TOKEN_LIFETIME};
+        use crate::token_cache::TokenHandle;
+
+        let server = MockQueryServer::new().unwrap();
+        let clock = ManualClock::new();
+        server.set_clock(clock.clone());
+        server.set_token_rotation(clock.now() + Duration::from_secs(10), TOKEN_LIFETIME);
+        let client = super::QueryClient::new_with_socket_address(
+            "127.0.0.1",
+            server.addr().port(),
+            "127.0.0.1:0",
+            Some(Duration::from_millis(50)),
+        )
+        .await
+        .unwrap();
+        let mut tokens = TokenHandle::new().with_clock(clock.clone());
+
+        client.basic_stat_cached(&mut tokens).await.unwrap();
+        for _ in 0..15 {
+            clock.advance(Duration::from_secs(7));
+            client.basic_stat_cached(&mut tokens).await.unwrap();
+        }
+        assert_eq!(tokens.rejections(), 1);
+    }
+
+    /// Query a live server, if one is given in the `LIVE_QUERY_SERVER` environment variable
+    #[tokio::test]
+    async fn test_live_server() {
+        let Ok(host) = std::env::var("LIVE_QUERY_SERVER") else {
+            return;
+        };
+        let client = super::QueryClient::new(&host).await.unwrap();
+        let token = client.handshake().await.unwrap();
+        let basic_stat = client.basic_stat(token).await.unwrap();
+        let full_stat = client.full_stat(token).await.unwrap();
         assert_eq!(full_stat.game_id, "MINECRAFT");
+        assert_eq!(basic_stat.gametype, full_stat.gametype);
+        assert_eq!(basic_stat.map, full_stat.map);
+        assert_eq!(basic_stat.maxplayers, full_stat.maxplayers);
+    }
+
+    #[tokio::test]
+    async fn test_from_stat() {
+        let front = MockQueryServer::new().unwrap();
+        let back = MockQueryServer::new().unwrap();
+        let mut advertisement = front.full_stat();
+        advertisement.hostip = "127.0.0.1".into();
+        advertisement.hostport = back.addr().port();
+        front.set_full_stat(advertisement);
+
+        let front_stat = super::query(&front.addr().to_string()).await.unwrap();
+        let client = super::QueryClient::from_stat(&front_stat, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(client.peer_addr().unwrap(), back.addr());
+        let token = client.handshake().await.unwrap();
+        assert_eq!(client.full_stat(token).await.unwrap(), back.full_stat());
     }
 }
@@ -0,0 +1,217 @@
+//! IPv6 zone identifiers, to query servers over link-local addresses.
+//!
+//! A link-local address, like `fe80::1`, is only meaningful on a given network
+//! interface, named by the zone identifier of the address: `fe80::1%eth0`, or
+//! `fe80::1%2` with the index of the interface. The clients and the query
+//! builders accept such addresses, and connect to them on their interface:
+//!
+//! ```rust,no_run
+//! # use minecraft_server_query::blocking::{Query, QueryClient};
+//! let client = QueryClient::new("[fe80::1%eth0]:25565")?;
+//! let full_stat = "mc://[fe80::1%25eth0]:25565".parse::<Query>()?.full()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! Interface names are only supported on Unix. Elsewhere, the numeric index
+//! of the interface must be given.
+
+use std::{
+    io,
+    net::{Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs},
+};
+
+/// Split a host into an IPv6 address and its zone identifier, if it has one.
+///
+/// Returns `None` for hosts which are not IPv6 addresses.
+///
+/// ```rust
+/// # use minecraft_server_query::zone::split_zone;
+/// assert_eq!(split_zone("fe80::1%eth0"), Some(("fe80::1".parse().unwrap(), Some("eth0"))));
+/// assert_eq!(split_zone("::1"), Some(("::1".parse().unwrap(), None)));
+/// assert_eq!(split_zone("play.example.com"), None);
+/// ```
+pub fn split_zone(host: &str) -> Option<(Ipv6Addr, Option<&str>)> {
+    let (ip, zone) = match host.split_once('%') {
+        Some((ip, zone)) => (ip, Some(zone)),
+        None => (host, None),
+    };
+    Some((ip.parse().ok()?, zone))
+}
+
+/// Index of the network interface of a zone identifier, which is either the
+/// index itself or the name of the interface.
+///
+/// An error of kind [`NotFound`](io::ErrorKind::NotFound) is returned for
+/// unknown interface names.
+pub fn interface_index(zone: &str) -> io::Result<u32> {
+    if let Ok(index) = zone.parse() {
+        return Ok(index);
+    }
+
+    #[cfg(unix)]
+    let index = std::ffi::CString::new(zone).map_or(0, |name| {
+        // SAFETY: the name is a valid null-terminated string
+        unsafe { libc::if_nametoindex(name.as_ptr()) }
+    });
+    #[cfg(not(unix))]
+    let index = 0;
+
+    if index == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Unknown network interface `{zone}` in IPv6 zone identifier."),
+        ));
+    }
+    Ok(index)
+}
+
+/// The socket address of an IPv6 address with a zone identifier, scoped to
+/// its interface.
+///
+/// Returns `Ok(None)` for other hosts, which are resolved as usual.
+///
+/// ```rust
+/// # use minecraft_server_query::zone::scoped_socket_addr;
+/// let addr = scoped_socket_addr("fe80::1%2", 25565)?.unwrap();
+/// assert_eq!(addr.to_string(), "[fe80::1%2]:25565");
+/// assert_eq!(scoped_socket_addr("fe80::1", 25565)?, None);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn scoped_socket_addr(host: &str, port: u16) -> io::Result<Option<SocketAddr>> {
+    match split_zone(host) {
+        Some((ip, Some(zone))) => Ok(Some(
+            SocketAddrV6::new(ip, port, 0, interface_index(zone)?).into(),
+        )),
+        _ => Ok(None),
+    }
+}
+
+/// Parse a socket address, which may have an IPv6 zone identifier with an
+/// interface name, like `[fe80::1%eth0]:25565`.
+///
+/// ```rust
+/// # use minecraft_server_query::zone::parse_socket_addr;
+/// assert_eq!(parse_socket_addr("[fe80::1%2]:0")?.to_string(), "[fe80::1%2]:0");
+/// assert_eq!(parse_socket_addr("0.0.0.0:0")?.to_string(), "0.0.0.0:0");
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn parse_socket_addr(s: &str) -> io::Result<SocketAddr> {
+    if let Ok(addr) = s.parse() {
+        return Ok(addr);
+    }
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid socket address `{s}`."),
+        )
+    };
+    let (host, port) = s
+        .strip_prefix('[')
+        .and_then(|s| s.split_once("]:"))
+        .ok_or_else(invalid)?;
+    let port = port.parse().map_err(|_| invalid())?;
+    scoped_socket_addr(host, port)?.ok_or_else(invalid)
+}
+
+/// A host and a port, resolved to a scoped address when the host is an IPv6
+/// address with a zone identifier, and with the system resolver otherwise
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct HostPort<'a>(pub(crate) &'a str, pub(crate) u16);
+
+impl ToSocketAddrs for HostPort<'_> {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        match scoped_socket_addr(self.0, self.1)? {
+            Some(addr) => Ok(vec![addr].into_iter()),
+            None => Ok((self.0, self.1)
+                .to_socket_addrs()?
+                .collect::<Vec<_>>()
+                .into_iter()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv6Addr, UdpSocket};
+
+    use super::*;
+    use crate::packets::{self, PacketType, Request};
+
+    #[test]
+    fn test_split_zone() {
+        let ip = "fe80::1".parse::<Ipv6Addr>().unwrap();
+        assert_eq!(split_zone("fe80::1%eth0"), Some((ip, Some("eth0"))));
+        assert_eq!(split_zone("fe80::1%2"), Some((ip, Some("2"))));
+        assert_eq!(split_zone("fe80::1%"), Some((ip, Some(""))));
+        assert_eq!(split_zone("fe80::1"), Some((ip, None)));
+        assert_eq!(split_zone("127.0.0.1%eth0"), None);
+        assert_eq!(split_zone("[fe80::1%eth0]"), None);
+    }
+
+    #[test]
+    fn test_scoped_socket_addr() {
+        let addr = scoped_socket_addr("fe80::1%7", 25565).unwrap().unwrap();
+        assert!(matches!(addr, SocketAddr::V6(v6) if v6.scope_id() == 7));
+        assert_eq!(scoped_socket_addr("::1", 25565).unwrap(), None);
+        assert_eq!(scoped_socket_addr("localhost", 25565).unwrap(), None);
+
+        let err = scoped_socket_addr("fe80::1%nonexistent0", 25565).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("`nonexistent0`"), "{err}");
+        assert!(scoped_socket_addr("fe80::1%", 25565).is_err());
+
+        assert!(parse_socket_addr("[fe80::1%7]:0").is_ok());
+        assert_eq!(
+            parse_socket_addr("[fe80::1%nonexistent0]:0")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+        for invalid in ["fe80::1%7", "[fe80::1%7]", "[fe80::1%7]:port", "[host%7]:0"] {
+            assert_eq!(
+                parse_socket_addr(invalid).unwrap_err().kind(),
+                io::ErrorKind::InvalidInput,
+                "{invalid}"
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_loopback_interface() {
+        let lo = interface_index("lo").unwrap();
+        assert_ne!(lo, 0);
+
+        // A server on the IPv6 loopback, answering handshakes
+        let server = UdpSocket::bind("[::1]:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let mut buf = [0; 16];
+            while let Ok((received, source)) = server.recv_from(&mut buf) {
+                if let Some(Request::Handshake { session_id }) = Request::parse(&buf[..received]) {
+                    let response =
+                        packets::write_response(PacketType::Handshake, session_id, b"1234\0");
+                    server.send_to(&response, source).unwrap();
+                }
+            }
+        });
+
+        let addr = HostPort("::1%lo", port)
+            .to_socket_addrs()
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(matches!(addr, SocketAddr::V6(v6) if v6.scope_id() == lo));
+
+        let client = crate::blocking::QueryClient::new(&format!("[::1%lo]:{port}")).unwrap();
+        assert_eq!(client.handshake().unwrap().0, 1234);
+        let query = crate::blocking::Query::to(format!("::1%{lo}")).port(port);
+        assert!(query.ping().is_ok());
+        let query = format!("mc://[::1%25lo]:{port}?bind=[::1%25lo]:0")
+            .parse::<crate::blocking::Query>()
+            .unwrap();
+        assert!(query.ping().is_ok());
+    }
+}